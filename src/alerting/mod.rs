@@ -0,0 +1,481 @@
+//! Outbound alerts (generic JSON, Slack, Discord webhooks) for operational events
+//! such as a provider going down, repeated auth failures or failed token refreshes.
+//!
+//! There are no budget alerts: the mux tracks cost but enforces no spending
+//! budgets, so there is no threshold to cross.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::auth::{OAuthClient, OAuthConfig, TokenStore};
+use crate::providers::error::ProviderError;
+use crate::storage::kv::KvStore;
+
+/// Alerting configuration
+///
+/// Example:
+/// ```toml
+/// [alerting]
+/// enabled = true
+/// failure_threshold = 5
+///
+/// [[alerting.webhooks]]
+/// url = "https://hooks.slack.com/services/..."
+/// format = "slack"
+/// events = ["provider_down", "token_refresh_failed"]   # optional, defaults to all
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertingConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    /// Consecutive failures before a provider is reported as down
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// Consecutive auth failures before alerting
+    #[serde(default = "default_auth_failure_threshold")]
+    pub auth_failure_threshold: u32,
+    /// Minimum seconds between two alerts for the same event/subject
+    #[serde(default = "default_cooldown_secs")]
+    pub cooldown_secs: u64,
+    /// Try refreshing OAuth tokens this many minutes before expiry (alert on failure)
+    #[serde(default = "default_token_expiry_warning_mins")]
+    pub token_expiry_warning_mins: i64,
+}
+
+impl Default for AlertingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            webhooks: Vec::new(),
+            failure_threshold: default_failure_threshold(),
+            auth_failure_threshold: default_auth_failure_threshold(),
+            cooldown_secs: default_cooldown_secs(),
+            token_expiry_warning_mins: default_token_expiry_warning_mins(),
+        }
+    }
+}
+
+fn default_failure_threshold() -> u32 {
+    5
+}
+
+fn default_auth_failure_threshold() -> u32 {
+    3
+}
+
+fn default_cooldown_secs() -> u64 {
+    300
+}
+
+fn default_token_expiry_warning_mins() -> i64 {
+    30
+}
+
+/// A single webhook target
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    /// Event kinds to send (empty = all)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    #[default]
+    Json,
+    Slack,
+    Discord,
+}
+
+/// Events that can trigger an alert
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlertEvent {
+    /// A provider failed `failure_threshold` times in a row
+    ProviderDown {
+        provider: String,
+        consecutive_failures: u32,
+        last_error: String,
+    },
+    /// A provider rejected our credentials repeatedly
+    AuthFailures {
+        provider: String,
+        consecutive_failures: u32,
+        last_error: String,
+    },
+    /// An OAuth token is about to expire and could not be refreshed
+    TokenRefreshFailed {
+        provider_id: String,
+        expires_at: DateTime<Utc>,
+        error: String,
+    },
+    /// A provider's primary API key was rejected and the secondary key took over
+    CredentialFailover {
+        provider: String,
+        error: String,
+    },
+}
+
+impl AlertEvent {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AlertEvent::ProviderDown { .. } => "provider_down",
+            AlertEvent::AuthFailures { .. } => "auth_failures",
+            AlertEvent::TokenRefreshFailed { .. } => "token_refresh_failed",
+            AlertEvent::CredentialFailover { .. } => "credential_failover",
+        }
+    }
+
+    /// Key used for cooldown (one alert per kind and subject)
+    fn dedup_key(&self) -> String {
+        let subject = match self {
+            AlertEvent::ProviderDown { provider, .. }
+            | AlertEvent::AuthFailures { provider, .. }
+            | AlertEvent::CredentialFailover { provider, .. } => provider,
+            AlertEvent::TokenRefreshFailed { provider_id, .. } => provider_id,
+        };
+        format!("{}:{}", self.kind(), subject)
+    }
+
+    pub fn title(&self) -> String {
+        match self {
+            AlertEvent::ProviderDown { provider, .. } => format!("Provider '{}' is failing", provider),
+            AlertEvent::AuthFailures { provider, .. } => {
+                format!("Authentication failing for provider '{}'", provider)
+            }
+            AlertEvent::TokenRefreshFailed { provider_id, .. } => {
+                format!("OAuth token refresh failed for '{}'", provider_id)
+            }
+            AlertEvent::CredentialFailover { provider, .. } => {
+                format!("Provider '{}' switched to its secondary API key", provider)
+            }
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            AlertEvent::ProviderDown { consecutive_failures, last_error, .. }
+            | AlertEvent::AuthFailures { consecutive_failures, last_error, .. } => {
+                format!("{} consecutive failures. Last error: {}", consecutive_failures, last_error)
+            }
+            AlertEvent::TokenRefreshFailed { expires_at, error, .. } => {
+                format!("Token expires at {}. Error: {}", expires_at.to_rfc3339(), error)
+            }
+            AlertEvent::CredentialFailover { error, .. } => {
+                format!("The primary key was rejected: {}", error)
+            }
+        }
+    }
+
+    /// Webhook body for the given format
+    pub fn payload(&self, format: WebhookFormat) -> serde_json::Value {
+        match format {
+            WebhookFormat::Json => serde_json::json!({
+                "service": "claude-code-mux",
+                "event": self.kind(),
+                "title": self.title(),
+                "message": self.message(),
+                "details": self,
+                "timestamp": Utc::now().to_rfc3339(),
+            }),
+            WebhookFormat::Slack => serde_json::json!({
+                "text": format!("🚨 *{}*\n{}", self.title(), self.message()),
+            }),
+            WebhookFormat::Discord => serde_json::json!({
+                "content": format!("🚨 **{}**\n{}", self.title(), self.message()),
+            }),
+        }
+    }
+}
+
+/// Events raised where no `Alerter` is at hand (see [`notify`])
+static EVENTS: OnceLock<mpsc::UnboundedSender<AlertEvent>> = OnceLock::new();
+
+/// Raise an alert from code without access to the server's `Alerter` (e.g. providers)
+pub fn notify(event: AlertEvent) {
+    match EVENTS.get() {
+        Some(events) => {
+            let _ = events.send(event);
+        }
+        None => warn!("🚨 {} - {}", event.title(), event.message()),
+    }
+}
+
+/// Failure counters expire if a provider sees no traffic for this long
+const FAILURE_COUNTER_TTL: Duration = Duration::from_secs(3600);
+
+/// Tracks provider health and dispatches alerts to configured webhooks
+///
+/// Failure counters and alert cooldowns live in the shared KV store, so
+/// replicas sharing a Redis backend count failures and de-duplicate alerts together.
+#[derive(Clone)]
+pub struct Alerter {
+    config: Arc<AlertingConfig>,
+    client: reqwest::Client,
+    kv: Arc<dyn KvStore>,
+}
+
+impl Alerter {
+    pub fn new(config: AlertingConfig, kv: Arc<dyn KvStore>) -> Self {
+        Self {
+            config: Arc::new(config),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+            kv,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled && !self.config.webhooks.is_empty()
+    }
+
+    /// Reset failure counters after a successful request
+    pub async fn record_success(&self, provider: &str) {
+        if !self.is_enabled() {
+            return;
+        }
+        if let Err(e) = self.kv.del(&failures_key(provider)).await {
+            warn!("Failed to reset failure counters: {}", e);
+        }
+    }
+
+    /// Count a failed request and alert when thresholds are crossed
+    pub async fn record_failure(&self, provider: &str, error: &ProviderError) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let is_auth = matches!(
+            error,
+            ProviderError::AuthError(_) | ProviderError::ApiError { status: 401 | 403, .. }
+        );
+
+        let key = failures_key(provider);
+        let counters = async {
+            let consecutive = self.kv.hincr(&key, "consecutive", 1, Some(FAILURE_COUNTER_TTL)).await?;
+            let auth = if is_auth {
+                self.kv.hincr(&key, "auth", 1, Some(FAILURE_COUNTER_TTL)).await?
+            } else {
+                0
+            };
+            anyhow::Ok((consecutive, auth))
+        };
+        let (consecutive, auth) = match counters.await {
+            Ok(counters) => counters,
+            Err(e) => {
+                warn!("Failed to update failure counters: {}", e);
+                return;
+            }
+        };
+
+        // Both can be crossed by the same failure; neither alert hides the other
+        if is_auth && auth == self.config.auth_failure_threshold as i64 {
+            self.fire(AlertEvent::AuthFailures {
+                provider: provider.to_string(),
+                consecutive_failures: auth as u32,
+                last_error: error.to_string(),
+            })
+            .await;
+        }
+        if consecutive == self.config.failure_threshold as i64 {
+            self.fire(AlertEvent::ProviderDown {
+                provider: provider.to_string(),
+                consecutive_failures: consecutive as u32,
+                last_error: error.to_string(),
+            })
+            .await;
+        }
+    }
+
+    /// Whether an event is outside its cooldown window (marks it as fired)
+    async fn should_fire(&self, event: &AlertEvent) -> bool {
+        if self.config.cooldown_secs == 0 {
+            return true;
+        }
+        let key = format!("alert-cooldown:{}", event.dedup_key());
+        self.kv
+            .set_nx(&key, "1", Duration::from_secs(self.config.cooldown_secs))
+            .await
+            // If the store is unreachable, prefer a duplicate alert over a lost one
+            .unwrap_or(true)
+    }
+
+    /// Send an event to all matching webhooks (in the background)
+    pub async fn fire(&self, event: AlertEvent) {
+        if !self.is_enabled() || !self.should_fire(&event).await {
+            return;
+        }
+
+        warn!("🚨 Alert: {} - {}", event.title(), event.message());
+
+        for webhook in &self.config.webhooks {
+            if !webhook.events.is_empty() && !webhook.events.iter().any(|e| e == event.kind()) {
+                continue;
+            }
+            let client = self.client.clone();
+            let url = webhook.url.clone();
+            let payload = event.payload(webhook.format);
+            tokio::spawn(async move {
+                match client.post(&url).json(&payload).send().await {
+                    Ok(resp) if !resp.status().is_success() => {
+                        warn!("Alert webhook returned {}", resp.status());
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("Failed to deliver alert webhook: {}", e),
+                }
+            });
+        }
+    }
+
+    /// Deliver the events raised through [`notify`]
+    pub fn spawn_listener(&self) {
+        let (events, mut received) = mpsc::unbounded_channel();
+        if EVENTS.set(events).is_err() {
+            return;
+        }
+        let alerter = self.clone();
+        tokio::spawn(async move {
+            while let Some(event) = received.recv().await {
+                alerter.fire(event).await;
+            }
+        });
+    }
+
+    /// Periodically refresh OAuth tokens nearing expiry, alerting when refresh fails
+    pub fn spawn_token_watcher(&self, token_store: TokenStore) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let alerter = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(300));
+            loop {
+                interval.tick().await;
+                let window = chrono::Duration::minutes(alerter.config.token_expiry_warning_mins);
+                for (provider_id, token) in token_store.all() {
+                    if Utc::now() + window < token.expires_at {
+                        continue;
+                    }
+                    let client = OAuthClient::new(
+                        OAuthConfig::for_provider_id(&provider_id),
+                        token_store.clone(),
+                    );
+                    match client.refresh_token(&provider_id).await {
+                        Ok(_) => info!("🔄 Refreshed OAuth token for '{}' ahead of expiry", provider_id),
+                        Err(e) => {
+                            alerter
+                                .fire(AlertEvent::TokenRefreshFailed {
+                                    provider_id,
+                                    expires_at: token.expires_at,
+                                    error: e.to_string(),
+                                })
+                                .await
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+fn failures_key(provider: &str) -> String {
+    format!("failures:{}", provider)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::kv::MemoryKv;
+
+    fn alerter(threshold: u32) -> Alerter {
+        Alerter::new(AlertingConfig {
+            enabled: true,
+            webhooks: vec![WebhookConfig {
+                url: "http://127.0.0.1:9/unused".to_string(),
+                format: WebhookFormat::Json,
+                events: vec![],
+            }],
+            failure_threshold: threshold,
+            ..Default::default()
+        }, Arc::new(MemoryKv::default()))
+    }
+
+    #[test]
+    fn test_payload_formats() {
+        let event = AlertEvent::ProviderDown {
+            provider: "openrouter".to_string(),
+            consecutive_failures: 5,
+            last_error: "timeout".to_string(),
+        };
+
+        let json = event.payload(WebhookFormat::Json);
+        assert_eq!(json["event"], "provider_down");
+        assert_eq!(json["details"]["provider"], "openrouter");
+
+        let slack = event.payload(WebhookFormat::Slack);
+        assert!(slack["text"].as_str().unwrap().contains("openrouter"));
+
+        let discord = event.payload(WebhookFormat::Discord);
+        assert!(discord["content"].as_str().unwrap().contains("timeout"));
+    }
+
+    #[tokio::test]
+    async fn test_cooldown_suppresses_repeats() {
+        let alerter = alerter(3);
+        let event = AlertEvent::AuthFailures {
+            provider: "zai".to_string(),
+            consecutive_failures: 3,
+            last_error: "401".to_string(),
+        };
+        assert!(alerter.should_fire(&event).await);
+        assert!(!alerter.should_fire(&event).await);
+    }
+
+    #[tokio::test]
+    async fn test_coinciding_thresholds_fire_both() {
+        let alerter = alerter(3);
+        let error = ProviderError::ApiError { status: 401, message: "invalid key".to_string() };
+        for _ in 0..3 {
+            alerter.record_failure("zai", &error).await;
+        }
+
+        // Both alerts fired, so both are in their cooldown
+        let auth = AlertEvent::AuthFailures {
+            provider: "zai".to_string(),
+            consecutive_failures: 3,
+            last_error: error.to_string(),
+        };
+        let down = AlertEvent::ProviderDown {
+            provider: "zai".to_string(),
+            consecutive_failures: 3,
+            last_error: error.to_string(),
+        };
+        assert!(!alerter.should_fire(&auth).await);
+        assert!(!alerter.should_fire(&down).await);
+    }
+
+    #[tokio::test]
+    async fn test_success_resets_failures() {
+        let alerter = alerter(3);
+        let error = ProviderError::ApiError { status: 500, message: "boom".to_string() };
+        alerter.record_failure("zai", &error).await;
+        alerter.record_failure("zai", &error).await;
+        alerter.record_success("zai").await;
+
+        let counters = alerter.kv.hgetall(&failures_key("zai")).await.unwrap();
+        assert!(counters.is_empty());
+    }
+}
@@ -0,0 +1,311 @@
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+/// Client key defined in the config (`[[server.api_keys]]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientKey {
+    /// Who uses the key (shown in the admin API)
+    pub client: String,
+    /// The key itself (supports `$ENV_VAR`)
+    pub key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub privacy: PrivacySettings,
+}
+
+/// What may be kept about a client's requests
+///
+/// Routing and failover work the same in every mode; these settings only
+/// limit what the logging, caching and metrics subsystems retain.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrivacySettings {
+    /// Capture request/response bodies (live log, trace export, archive, debug logs)
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub log_bodies: bool,
+    /// Keep conversation content beyond the request (semantic cache)
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub store_sessions: bool,
+    #[serde(default, skip_serializing_if = "MetricsMode::is_full")]
+    pub metrics: MetricsMode,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricsMode {
+    #[default]
+    Full,
+    /// Only timing, status and token counts; no models, providers or error text
+    HeadersOnly,
+}
+
+impl MetricsMode {
+    fn is_full(&self) -> bool {
+        *self == MetricsMode::Full
+    }
+}
+
+impl Default for PrivacySettings {
+    fn default() -> Self {
+        Self {
+            log_bodies: true,
+            store_sessions: true,
+            metrics: MetricsMode::Full,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+/// Client identified by a valid key
+#[derive(Debug, Clone, PartialEq)]
+pub struct Client {
+    pub name: String,
+    pub privacy: PrivacySettings,
+}
+
+/// Key minted through the admin API; only a hash of the key is stored
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MintedKey {
+    pub id: String,
+    pub client: String,
+    /// First characters of the key, to tell keys apart
+    pub prefix: String,
+    key_hash: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub privacy: PrivacySettings,
+}
+
+impl MintedKey {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_none_or(|t| now < t)
+    }
+}
+
+/// Outcome of checking a client key
+#[derive(Debug, PartialEq)]
+pub enum KeyCheck {
+    /// Valid key of this client
+    Valid(Client),
+    Expired,
+    Unknown,
+}
+
+/// Minted client keys, persisted to a JSON file
+#[derive(Debug, Clone)]
+pub struct ApiKeyStore {
+    file_path: PathBuf,
+    keys: Arc<RwLock<Vec<MintedKey>>>,
+}
+
+impl ApiKeyStore {
+    pub fn new(file_path: PathBuf) -> Result<Self> {
+        let keys = if file_path.exists() {
+            let content = fs::read_to_string(&file_path).context("Failed to read API key file")?;
+            serde_json::from_str(&content).context("Failed to parse API key file")?
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            file_path,
+            keys: Arc::new(RwLock::new(keys)),
+        })
+    }
+
+    /// ~/.claude-code-mux/api_keys.json
+    pub fn default_path() -> Result<PathBuf> {
+        let home = dirs::home_dir().context("Failed to get home directory")?;
+        let config_dir = home.join(".claude-code-mux");
+        fs::create_dir_all(&config_dir).context("Failed to create config directory")?;
+        Ok(config_dir.join("api_keys.json"))
+    }
+
+    pub fn default() -> Result<Self> {
+        Self::new(Self::default_path()?)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.read().unwrap().is_empty()
+    }
+
+    pub fn list(&self) -> Vec<MintedKey> {
+        self.keys.read().unwrap().clone()
+    }
+
+    /// Create a key for `client`; returns the key (shown once) and its record
+    ///
+    /// With `rotate_grace`, the client's other keys stay valid for that long and
+    /// then expire, so clients can switch over without downtime.
+    pub fn mint(
+        &self,
+        client: &str,
+        privacy: PrivacySettings,
+        ttl: Option<Duration>,
+        rotate_grace: Option<Duration>,
+    ) -> Result<(String, MintedKey)> {
+        let now = Utc::now();
+        let secret: [u8; 24] = rand::thread_rng().gen();
+        let key = format!("ccm_{}", URL_SAFE_NO_PAD.encode(secret));
+        let id_bytes: [u8; 6] = rand::thread_rng().gen();
+        let record = MintedKey {
+            id: id_bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+            client: client.to_string(),
+            prefix: key[..10].to_string(),
+            key_hash: hash_key(&key),
+            created_at: now,
+            expires_at: ttl.map(|ttl| now + ttl),
+            privacy,
+        };
+
+        {
+            let mut keys = self.keys.write().unwrap();
+            if let Some(grace) = rotate_grace {
+                let deadline = now + grace;
+                for old in keys.iter_mut().filter(|k| k.client == client && k.is_active(now)) {
+                    old.expires_at = Some(old.expires_at.map_or(deadline, |t| t.min(deadline)));
+                }
+            }
+            keys.push(record.clone());
+        }
+        self.persist()?;
+        Ok((key, record))
+    }
+
+    /// Expire a key after `grace` (immediately for zero); returns false for unknown ids
+    pub fn revoke(&self, id: &str, grace: Duration) -> Result<bool> {
+        let deadline = Utc::now() + grace;
+        let found = {
+            let mut keys = self.keys.write().unwrap();
+            match keys.iter_mut().find(|k| k.id == id) {
+                Some(key) => {
+                    key.expires_at = Some(key.expires_at.map_or(deadline, |t| t.min(deadline)));
+                    true
+                }
+                None => false,
+            }
+        };
+        if found {
+            self.persist()?;
+        }
+        Ok(found)
+    }
+
+    pub fn check(&self, key: &str) -> KeyCheck {
+        let hash = hash_key(key);
+        let keys = self.keys.read().unwrap();
+        match keys.iter().find(|k| k.key_hash == hash) {
+            Some(k) if k.is_active(Utc::now()) => KeyCheck::Valid(Client {
+                name: k.client.clone(),
+                privacy: k.privacy.clone(),
+            }),
+            Some(_) => KeyCheck::Expired,
+            None => KeyCheck::Unknown,
+        }
+    }
+
+    fn persist(&self) -> Result<()> {
+        let content = {
+            let keys = self.keys.read().unwrap();
+            serde_json::to_string_pretty(&*keys)?
+        };
+        fs::write(&self.file_path, content).context("Failed to write API key file")?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&self.file_path, fs::Permissions::from_mode(0o600))
+                .context("Failed to set API key file permissions")?;
+        }
+        Ok(())
+    }
+}
+
+/// Check a key against config keys
+pub fn check_config_keys(keys: &[ClientKey], key: &str) -> KeyCheck {
+    match keys.iter().find(|k| k.key == key) {
+        Some(k) if k.expires_at.is_none_or(|t| Utc::now() < t) => KeyCheck::Valid(Client {
+            name: k.client.clone(),
+            privacy: k.privacy.clone(),
+        }),
+        Some(_) => KeyCheck::Expired,
+        None => KeyCheck::Unknown,
+    }
+}
+
+/// Hex SHA-256 of a key
+pub(crate) fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ci() -> KeyCheck {
+        KeyCheck::Valid(Client {
+            name: "ci".to_string(),
+            privacy: PrivacySettings::default(),
+        })
+    }
+
+    #[test]
+    fn test_rotation_grace() {
+        let path = std::env::temp_dir().join(format!("ccm-api-keys-{}.json", std::process::id()));
+        let store = ApiKeyStore::new(path.clone()).unwrap();
+
+        let (old_key, _) = store.mint("ci", PrivacySettings::default(), None, None).unwrap();
+        let (new_key, _) = store.mint("ci", PrivacySettings::default(), None, Some(Duration::hours(1))).unwrap();
+        assert_eq!(store.check(&old_key), ci());
+        assert_eq!(store.check(&new_key), ci());
+
+        let old = store.list().into_iter().find(|k| old_key.starts_with(&k.prefix)).unwrap();
+        assert!(old.expires_at.is_some());
+        store.revoke(&old.id, Duration::zero()).unwrap();
+        assert_eq!(store.check(&old_key), KeyCheck::Expired);
+
+        // Reloads from disk
+        let reloaded = ApiKeyStore::new(path.clone()).unwrap();
+        assert_eq!(reloaded.check(&new_key), ci());
+        assert_eq!(reloaded.check("ccm_nope"), KeyCheck::Unknown);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_privacy_settings() {
+        let key: ClientKey = toml::from_str(
+            r#"
+client = "acme"
+key = "k"
+log_bodies = false
+metrics = "headers_only"
+"#,
+        )
+        .unwrap();
+        let KeyCheck::Valid(client) = check_config_keys(std::slice::from_ref(&key), "k") else {
+            panic!("key should be valid");
+        };
+        assert!(!client.privacy.log_bodies);
+        assert!(client.privacy.store_sessions);
+        assert_eq!(client.privacy.metrics, MetricsMode::HeadersOnly);
+
+        // Defaults are omitted when serialized
+        let json = serde_json::to_value(ClientKey { privacy: PrivacySettings::default(), ..key }).unwrap();
+        assert_eq!(json, serde_json::json!({ "client": "acme", "key": "k" }));
+    }
+}
@@ -1,5 +1,9 @@
+pub mod api_keys;
 pub mod oauth;
+pub mod oidc;
 pub mod token_store;
 
 pub use oauth::{OAuthClient, OAuthConfig, AuthorizationUrl, PKCEVerifier};
+pub use api_keys::ApiKeyStore;
+pub use oidc::{OidcConfig, OidcVerifier};
 pub use token_store::{TokenStore, OAuthToken};
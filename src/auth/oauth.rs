@@ -4,6 +4,7 @@ use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use anyhow::{Context, Result, anyhow};
 use chrono::{DateTime, Utc};
+use std::time::Duration;
 
 use super::token_store::{OAuthToken, TokenStore};
 
@@ -97,6 +98,18 @@ impl OAuthConfig {
         }
     }
 
+    /// Guess the OAuth configuration from a stored token's provider ID
+    pub fn for_provider_id(provider_id: &str) -> Self {
+        let id = provider_id.to_lowercase();
+        if id.contains("openai") || id.contains("codex") || id.contains("chatgpt") {
+            Self::openai_codex()
+        } else if id.contains("gemini") || id.contains("google") {
+            Self::gemini()
+        } else {
+            Self::anthropic()
+        }
+    }
+
     /// Google Gemini (AI Pro/Ultra) OAuth configuration
     ///
     /// Note: This uses Google's official Gemini CLI OAuth app credentials.
@@ -327,12 +340,42 @@ impl OAuthClient {
 
         // Save token
         self.token_store.save(token.clone())?;
+        self.token_store.publish_overwrite(&token).await?;
 
         Ok(token)
     }
 
     /// Refresh an access token
+    ///
+    /// With a shared token store only one instance refreshes at a time; the others
+    /// wait for it and pick up the refreshed token.
     pub async fn refresh_token(&self, provider_id: &str) -> Result<OAuthToken> {
+        if !self.token_store.is_shared() {
+            return self.refresh_token_upstream(provider_id).await;
+        }
+
+        let before = self.token_store.get(provider_id).map(|t| t.access_token);
+        for _ in 0..40 {
+            self.token_store.sync_shared().await?;
+            if let Some(token) = self.token_store.get(provider_id) {
+                // Another instance refreshed it in the meantime
+                if Some(&token.access_token) != before.as_ref() && !token.needs_refresh() {
+                    return Ok(token);
+                }
+            }
+
+            if self.token_store.try_lock_refresh(provider_id, Duration::from_secs(30)).await? {
+                let result = self.refresh_token_upstream(provider_id).await;
+                self.token_store.unlock_refresh(provider_id).await;
+                return result;
+            }
+            tokio::time::sleep(Duration::from_millis(250)).await;
+        }
+
+        Err(anyhow!("Timed out waiting for another instance to refresh the '{}' token", provider_id))
+    }
+
+    async fn refresh_token_upstream(&self, provider_id: &str) -> Result<OAuthToken> {
         let existing_token = self.token_store.get(provider_id)
             .context("No token found for provider")?;
 
@@ -430,6 +473,10 @@ impl OAuthClient {
 
         // Save refreshed token
         self.token_store.save(token.clone())?;
+        if !self.token_store.publish(&token).await? {
+            // Lost the race (e.g. our lock expired); use whatever is shared now
+            return self.token_store.get(provider_id).context("Shared token disappeared");
+        }
 
         Ok(token)
     }
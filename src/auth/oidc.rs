@@ -0,0 +1,323 @@
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+/// How long fetched signing keys are trusted before they are fetched again
+const JWKS_TTL: Duration = Duration::from_secs(3600);
+/// Minimum time between refetches triggered by an unknown key id
+const JWKS_REFETCH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// External OIDC provider for admin API and dashboard access
+///
+/// When set, admin routes require a bearer JWT signed by the issuer
+/// (RS256 or ES256) whose `aud` contains `audience`.
+///
+/// Example:
+/// ```toml
+/// [server.oidc]
+/// issuer = "https://accounts.google.com"
+/// audience = "ccm-admin"
+/// allowed_subjects = ["alice@example.com"]
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OidcConfig {
+    /// Issuer URL; must match the token's `iss`
+    pub issuer: String,
+    /// Expected `aud`
+    pub audience: String,
+    /// Signing keys URL (default: discovered from `{issuer}/.well-known/openid-configuration`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jwks_uri: Option<String>,
+    /// Subjects or emails allowed in (default: anyone the issuer vouches for)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_subjects: Vec<String>,
+    /// Clock skew tolerated for `exp`/`nbf`
+    #[serde(default = "default_leeway_secs")]
+    pub leeway_secs: i64,
+}
+
+fn default_leeway_secs() -> i64 {
+    60
+}
+
+/// Verified identity from an admin token
+#[derive(Debug, Clone, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    iss: String,
+    aud: Audience,
+    exp: i64,
+    #[serde(default)]
+    nbf: Option<i64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, audience: &str) -> bool {
+        match self {
+            Audience::One(a) => a == audience,
+            Audience::Many(all) => all.iter().any(|a| a == audience),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Header {
+    alg: String,
+    #[serde(default)]
+    kid: Option<String>,
+}
+
+/// Public key from a JWKS document
+#[derive(Debug, Clone, Deserialize)]
+pub struct Jwk {
+    #[serde(default)]
+    pub kid: Option<String>,
+    pub kty: String,
+    #[serde(default)]
+    pub n: Option<String>,
+    #[serde(default)]
+    pub e: Option<String>,
+    #[serde(default)]
+    pub crv: Option<String>,
+    #[serde(default)]
+    pub x: Option<String>,
+    #[serde(default)]
+    pub y: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+/// Validates admin JWTs against the issuer's published keys
+pub struct OidcVerifier {
+    config: OidcConfig,
+    client: reqwest::Client,
+    /// Keys and when they were fetched
+    keys: RwLock<Option<(Instant, Vec<Jwk>)>>,
+}
+
+impl OidcVerifier {
+    pub fn new(config: OidcConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+            keys: RwLock::new(None),
+        }
+    }
+
+    /// Verify signature, issuer, audience, expiry and the subject allow-list
+    pub async fn verify(&self, token: &str) -> Result<Claims> {
+        let mut parts = token.split('.');
+        let (Some(header), Some(payload), Some(sig), None) = (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            bail!("Malformed JWT");
+        };
+        let header: Header = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header)?).context("Invalid JWT header")?;
+        let signature = URL_SAFE_NO_PAD.decode(sig).context("Invalid JWT signature encoding")?;
+        let signed = &token[..header_len(token)];
+
+        let key = self.key(header.kid.as_deref(), &header.alg).await?;
+        verify_signature(&key, &header.alg, signed.as_bytes(), &signature)?;
+
+        let claims: Claims =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload)?).context("Invalid JWT claims")?;
+        self.check_claims(&claims, chrono::Utc::now().timestamp())?;
+        Ok(claims)
+    }
+
+    fn check_claims(&self, claims: &Claims, now: i64) -> Result<()> {
+        if claims.iss.trim_end_matches('/') != self.config.issuer.trim_end_matches('/') {
+            bail!("Token issuer '{}' is not trusted", claims.iss);
+        }
+        if !claims.aud.contains(&self.config.audience) {
+            bail!("Token audience does not include '{}'", self.config.audience);
+        }
+        if now > claims.exp + self.config.leeway_secs {
+            bail!("Token expired");
+        }
+        if claims.nbf.is_some_and(|nbf| now + self.config.leeway_secs < nbf) {
+            bail!("Token not yet valid");
+        }
+        let allowed = &self.config.allowed_subjects;
+        if !allowed.is_empty()
+            && !allowed
+                .iter()
+                .any(|s| *s == claims.sub || claims.email.as_deref() == Some(s.as_str()))
+        {
+            bail!("Subject '{}' is not allowed", claims.sub);
+        }
+        Ok(())
+    }
+
+    /// Signing key for `kid`, refetching the key set when it is stale or the key is unknown
+    async fn key(&self, kid: Option<&str>, alg: &str) -> Result<Jwk> {
+        let refetch = match &*self.keys.read().unwrap_or_else(|e| e.into_inner()) {
+            Some((fetched, keys)) => {
+                if let Some(key) = find_key(keys, kid, alg).filter(|_| fetched.elapsed() < JWKS_TTL) {
+                    return Ok(key);
+                }
+                // Unknown keys may have been rotated in, but don't hammer the issuer
+                fetched.elapsed() >= JWKS_REFETCH_INTERVAL
+            }
+            None => true,
+        };
+        if !refetch {
+            bail!("Unknown signing key");
+        }
+
+        let keys = self.fetch_keys().await?;
+        let key = find_key(&keys, kid, alg).ok_or_else(|| anyhow!("Unknown signing key"));
+        *self.keys.write().unwrap_or_else(|e| e.into_inner()) = Some((Instant::now(), keys));
+        key
+    }
+
+    async fn fetch_keys(&self) -> Result<Vec<Jwk>> {
+        let jwks_uri = match &self.config.jwks_uri {
+            Some(uri) => uri.clone(),
+            None => {
+                let url = format!(
+                    "{}/.well-known/openid-configuration",
+                    self.config.issuer.trim_end_matches('/')
+                );
+                let discovery: serde_json::Value = self
+                    .client
+                    .get(&url)
+                    .send()
+                    .await
+                    .with_context(|| format!("OIDC discovery at {} failed", url))?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                discovery["jwks_uri"]
+                    .as_str()
+                    .context("OIDC discovery document has no jwks_uri")?
+                    .to_string()
+            }
+        };
+        let set: JwkSet = self
+            .client
+            .get(&jwks_uri)
+            .send()
+            .await
+            .with_context(|| format!("Fetching signing keys from {} failed", jwks_uri))?
+            .error_for_status()?
+            .json()
+            .await?;
+        tracing::debug!("🔐 Fetched {} OIDC signing keys", set.keys.len());
+        Ok(set.keys)
+    }
+}
+
+/// Length of `header.payload`, the signed part of a JWT
+fn header_len(token: &str) -> usize {
+    token.rfind('.').unwrap_or(token.len())
+}
+
+fn find_key(keys: &[Jwk], kid: Option<&str>, alg: &str) -> Option<Jwk> {
+    let kty = match alg {
+        "RS256" => "RSA",
+        "ES256" => "EC",
+        _ => return None,
+    };
+    keys.iter()
+        .filter(|k| k.kty == kty)
+        .find(|k| kid.is_none() || k.kid.as_deref() == kid)
+        .cloned()
+}
+
+fn verify_signature(key: &Jwk, alg: &str, message: &[u8], sig: &[u8]) -> Result<()> {
+    let field = |v: &Option<String>, name: &str| -> Result<Vec<u8>> {
+        let v = v.as_deref().ok_or_else(|| anyhow!("Signing key has no '{}'", name))?;
+        Ok(URL_SAFE_NO_PAD.decode(v)?)
+    };
+    let verified = match alg {
+        "RS256" => RsaPublicKeyComponents {
+            n: field(&key.n, "n")?,
+            e: field(&key.e, "e")?,
+        }
+        .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, sig),
+        "ES256" => {
+            if key.crv.as_deref() != Some("P-256") {
+                bail!("Unsupported EC curve");
+            }
+            let mut point = vec![0x04];
+            point.extend(field(&key.x, "x")?);
+            point.extend(field(&key.y, "y")?);
+            UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point).verify(message, sig)
+        }
+        other => bail!("Unsupported JWT algorithm '{}'", other),
+    };
+    verified.map_err(|_| anyhow!("Invalid JWT signature"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use serde_json::json;
+
+    fn sign(key_pair: &EcdsaKeyPair, claims: serde_json::Value) -> String {
+        let encode = |v: serde_json::Value| URL_SAFE_NO_PAD.encode(v.to_string());
+        let signed = format!("{}.{}", encode(json!({ "alg": "ES256", "kid": "k1" })), encode(claims));
+        let sig = key_pair.sign(&SystemRandom::new(), signed.as_bytes()).unwrap();
+        format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(sig.as_ref()))
+    }
+
+    #[tokio::test]
+    async fn test_verify_es256() {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        let point = key_pair.public_key().as_ref();
+
+        let verifier = OidcVerifier::new(OidcConfig {
+            issuer: "https://idp.example.com/".to_string(),
+            audience: "ccm-admin".to_string(),
+            jwks_uri: None,
+            allowed_subjects: vec!["alice@example.com".to_string()],
+            leeway_secs: 0,
+        });
+        *verifier.keys.write().unwrap() = Some((
+            Instant::now(),
+            vec![Jwk {
+                kid: Some("k1".to_string()),
+                kty: "EC".to_string(),
+                n: None,
+                e: None,
+                crv: Some("P-256".to_string()),
+                x: Some(URL_SAFE_NO_PAD.encode(&point[1..33])),
+                y: Some(URL_SAFE_NO_PAD.encode(&point[33..])),
+            }],
+        ));
+
+        let exp = chrono::Utc::now().timestamp() + 300;
+        let claims = |sub: &str, aud: serde_json::Value| {
+            json!({ "iss": "https://idp.example.com", "sub": sub, "email": "alice@example.com", "aud": aud, "exp": exp })
+        };
+        let token = sign(&key_pair, claims("u-1", json!(["other", "ccm-admin"])));
+        assert_eq!(verifier.verify(&token).await.unwrap().sub, "u-1");
+
+        assert!(verifier.verify(&sign(&key_pair, claims("u-1", json!("other")))).await.is_err());
+        let tampered = token.replacen('.', ".e", 1);
+        assert!(verifier.verify(&tampered).await.is_err());
+
+        let mut expired = claims("u-1", json!("ccm-admin"));
+        expired["exp"] = json!(exp - 600);
+        assert!(verifier.verify(&sign(&key_pair, expired)).await.is_err());
+    }
+}
@@ -3,8 +3,19 @@ use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
+use crate::storage::KvStore;
+
+/// Hash holding all shared tokens (field = provider ID, value = token or tombstone JSON)
+const SHARED_TOKENS_KEY: &str = "oauth-tokens";
+
+/// Marks a shared token as deleted, so instances drop their copy instead of republishing it
+#[derive(Debug, Serialize, Deserialize)]
+struct Tombstone {
+    deleted_at: DateTime<Utc>,
+}
 
 /// OAuth token information
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,6 +57,22 @@ pub struct TokenStore {
     file_path: PathBuf,
     /// In-memory cache of tokens
     tokens: Arc<RwLock<HashMap<String, OAuthToken>>>,
+    /// Optional shared backend for clustered deployments
+    shared: Option<SharedTokens>,
+}
+
+/// Tokens mirrored in a shared KV store, updated with optimistic locking
+#[derive(Clone)]
+struct SharedTokens {
+    kv: Arc<dyn KvStore>,
+    /// Last raw value seen per provider (the expected value for compare-and-set)
+    seen: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl std::fmt::Debug for SharedTokens {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedTokens").finish_non_exhaustive()
+    }
 }
 
 impl TokenStore {
@@ -64,9 +91,23 @@ impl TokenStore {
         Ok(Self {
             file_path,
             tokens: Arc::new(RwLock::new(tokens)),
+            shared: None,
         })
     }
 
+    /// Mirror tokens in a shared KV store so several instances use (and refresh) the same tokens
+    pub fn with_shared(mut self, kv: Arc<dyn KvStore>) -> Self {
+        self.shared = Some(SharedTokens {
+            kv,
+            seen: Arc::new(RwLock::new(HashMap::new())),
+        });
+        self
+    }
+
+    pub fn is_shared(&self) -> bool {
+        self.shared.is_some()
+    }
+
     /// Get default token store path
     /// ~/.claude-code-mux/oauth_tokens.json
     pub fn default_path() -> Result<PathBuf> {
@@ -131,6 +172,155 @@ impl TokenStore {
         tokens.clone()
     }
 
+    /// Pull the latest tokens from the shared store (local-only tokens are published,
+    /// tokens deleted elsewhere are dropped)
+    pub async fn sync_shared(&self) -> Result<()> {
+        let Some(shared) = &self.shared else {
+            return Ok(());
+        };
+
+        let remote = shared.kv.hgetall_raw(SHARED_TOKENS_KEY).await?;
+        let mut changed = false;
+        for (provider_id, raw) in &remote {
+            if serde_json::from_str::<Tombstone>(raw).is_ok() {
+                shared.seen.write().unwrap().insert(provider_id.clone(), raw.clone());
+                changed |= self.tokens.write().unwrap().remove(provider_id).is_some();
+                continue;
+            }
+            let token: OAuthToken = match serde_json::from_str(raw) {
+                Ok(token) => token,
+                Err(e) => {
+                    tracing::warn!("Ignoring malformed shared token for '{}': {}", provider_id, e);
+                    continue;
+                }
+            };
+            shared.seen.write().unwrap().insert(provider_id.clone(), raw.clone());
+            let mut tokens = self.tokens.write().unwrap();
+            let differs = tokens
+                .get(provider_id)
+                .is_none_or(|local| local.access_token != token.access_token);
+            if differs {
+                tokens.insert(provider_id.clone(), token);
+                changed = true;
+            }
+        }
+
+        // Seed the shared store with tokens only this instance knows about
+        for token in self.all().into_values() {
+            if !remote.contains_key(&token.provider_id) {
+                Self::compare_and_publish(shared, &token).await?;
+            }
+        }
+
+        if changed {
+            self.persist()?;
+        }
+        Ok(())
+    }
+
+    /// Write a token to the shared store; returns false if another instance changed it first
+    /// (the newer shared token is then loaded instead)
+    pub async fn publish(&self, token: &OAuthToken) -> Result<bool> {
+        let Some(shared) = &self.shared else {
+            return Ok(true);
+        };
+
+        let swapped = Self::compare_and_publish(shared, token).await?;
+        if !swapped {
+            tracing::warn!("Shared token for '{}' changed concurrently, reloading", token.provider_id);
+            self.sync_shared().await?;
+        }
+        Ok(swapped)
+    }
+
+    /// CAS against the last shared version this instance has seen
+    async fn compare_and_publish(shared: &SharedTokens, token: &OAuthToken) -> Result<bool> {
+        let raw = serde_json::to_string(token).context("Failed to serialize token")?;
+        let expected = shared.seen.read().unwrap().get(&token.provider_id).cloned();
+        // Only a new login (publish_overwrite) may bring back a deleted token
+        if expected.as_deref().is_some_and(|e| serde_json::from_str::<Tombstone>(e).is_ok()) {
+            return Ok(false);
+        }
+        let swapped = shared
+            .kv
+            .hcas(SHARED_TOKENS_KEY, &token.provider_id, expected.as_deref(), Some(&raw))
+            .await?;
+        if swapped {
+            shared.seen.write().unwrap().insert(token.provider_id.clone(), raw);
+        }
+        Ok(swapped)
+    }
+
+    /// Write a token to the shared store regardless of concurrent changes (new logins)
+    pub async fn publish_overwrite(&self, token: &OAuthToken) -> Result<()> {
+        let Some(shared) = &self.shared else {
+            return Ok(());
+        };
+
+        let raw = serde_json::to_string(token).context("Failed to serialize token")?;
+        Self::overwrite(shared, &token.provider_id, raw).await
+    }
+
+    /// Replace a token in the shared store with a tombstone; deletes win over
+    /// concurrent refreshes and over instances that still hold the token locally
+    pub async fn unpublish(&self, provider_id: &str) -> Result<()> {
+        let Some(shared) = &self.shared else {
+            return Ok(());
+        };
+        let raw = serde_json::to_string(&Tombstone { deleted_at: Utc::now() })
+            .context("Failed to serialize tombstone")?;
+        Self::overwrite(shared, provider_id, raw).await
+    }
+
+    /// Set a shared value whatever its current version is
+    async fn overwrite(shared: &SharedTokens, provider_id: &str, raw: String) -> Result<()> {
+        for _ in 0..5 {
+            let current = shared.kv.hgetall_raw(SHARED_TOKENS_KEY).await?.remove(provider_id);
+            if shared
+                .kv
+                .hcas(SHARED_TOKENS_KEY, provider_id, current.as_deref(), Some(&raw))
+                .await?
+            {
+                shared.seen.write().unwrap().insert(provider_id.to_string(), raw);
+                return Ok(());
+            }
+        }
+        anyhow::bail!("Failed to publish token for '{}': too much contention", provider_id)
+    }
+
+    /// Acquire the cluster-wide refresh lock for a provider (always succeeds when not shared)
+    pub async fn try_lock_refresh(&self, provider_id: &str, ttl: Duration) -> Result<bool> {
+        match &self.shared {
+            Some(shared) => shared.kv.set_nx(&refresh_lock_key(provider_id), "1", ttl).await,
+            None => Ok(true),
+        }
+    }
+
+    pub async fn unlock_refresh(&self, provider_id: &str) {
+        if let Some(shared) = &self.shared {
+            if let Err(e) = shared.kv.del(&refresh_lock_key(provider_id)).await {
+                tracing::warn!("Failed to release refresh lock for '{}': {}", provider_id, e);
+            }
+        }
+    }
+
+    /// Periodically pull tokens refreshed by other instances
+    pub fn spawn_shared_sync(&self, interval: Duration) {
+        if !self.is_shared() {
+            return;
+        }
+        let store = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = store.sync_shared().await {
+                    tracing::warn!("Failed to sync shared OAuth tokens: {}", e);
+                }
+            }
+        });
+    }
+
     /// Persist tokens to file
     fn persist(&self) -> Result<()> {
         let tokens = self.tokens.read().unwrap();
@@ -153,6 +343,10 @@ impl TokenStore {
     }
 }
 
+fn refresh_lock_key(provider_id: &str) -> String {
+    format!("oauth-refresh:{}", provider_id)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -209,4 +403,70 @@ mod tests {
         assert!(!valid_token.is_expired());
         assert!(!valid_token.needs_refresh());
     }
+
+    #[tokio::test]
+    async fn test_shared_tokens_optimistic_locking() {
+        let kv: Arc<dyn KvStore> = Arc::new(crate::storage::kv::MemoryKv::default());
+        let temp_dir = TempDir::new().unwrap();
+        let a = TokenStore::new(temp_dir.path().join("a.json")).unwrap().with_shared(kv.clone());
+        let b = TokenStore::new(temp_dir.path().join("b.json")).unwrap().with_shared(kv);
+
+        let mut token = OAuthToken {
+            provider_id: "claude-max".to_string(),
+            access_token: "v1".to_string(),
+            refresh_token: "refresh".to_string(),
+            expires_at: Utc::now() + chrono::Duration::hours(1),
+            enterprise_url: None,
+            project_id: None,
+        };
+        a.save(token.clone()).unwrap();
+        a.sync_shared().await.unwrap();
+
+        b.sync_shared().await.unwrap();
+        assert_eq!(b.get("claude-max").unwrap().access_token, "v1");
+
+        // A refreshes first; B's update is based on a stale version and is rejected
+        token.access_token = "v2".to_string();
+        assert!(a.publish(&token).await.unwrap());
+        token.access_token = "v2-stale".to_string();
+        assert!(!b.publish(&token).await.unwrap());
+        assert_eq!(b.get("claude-max").unwrap().access_token, "v2");
+    }
+
+    #[tokio::test]
+    async fn test_shared_delete_is_not_resurrected() {
+        let kv: Arc<dyn KvStore> = Arc::new(crate::storage::kv::MemoryKv::default());
+        let temp_dir = TempDir::new().unwrap();
+        let a = TokenStore::new(temp_dir.path().join("a.json")).unwrap().with_shared(kv.clone());
+        let b = TokenStore::new(temp_dir.path().join("b.json")).unwrap().with_shared(kv);
+
+        let token = OAuthToken {
+            provider_id: "claude-max".to_string(),
+            access_token: "v1".to_string(),
+            refresh_token: "refresh".to_string(),
+            expires_at: Utc::now() + chrono::Duration::hours(1),
+            enterprise_url: None,
+            project_id: None,
+        };
+        a.save(token.clone()).unwrap();
+        a.sync_shared().await.unwrap();
+        b.sync_shared().await.unwrap();
+
+        // A revokes the token; B still has it locally and must not publish it again
+        a.remove("claude-max").unwrap();
+        a.unpublish("claude-max").await.unwrap();
+        b.sync_shared().await.unwrap();
+        a.sync_shared().await.unwrap();
+        assert!(a.get("claude-max").is_none());
+        assert!(b.get("claude-max").is_none());
+
+        // A stale refresh from before the delete loses too
+        assert!(!b.publish(&token).await.unwrap());
+
+        // A new login replaces the tombstone
+        b.save(token.clone()).unwrap();
+        b.publish_overwrite(&token).await.unwrap();
+        a.sync_shared().await.unwrap();
+        assert_eq!(a.get("claude-max").unwrap().access_token, "v1");
+    }
 }
@@ -0,0 +1,295 @@
+//! Usage events for external billing and chargeback systems
+//!
+//! Every completed request becomes a normalized usage event. Events are
+//! queued, sent in batches to a webhook, and retried with backoff when the
+//! endpoint is unavailable.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use crate::storage::UsageRecord;
+
+/// Longest wait between two delivery attempts of a batch
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Usage webhook configuration
+///
+/// Example:
+/// ```toml
+/// [billing]
+/// enabled = true
+/// url = "https://billing.internal/ingest/llm-usage"
+/// headers = { Authorization = "Bearer $BILLING_TOKEN" }
+/// batch_size = 50
+/// flush_interval_secs = 10
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BillingConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub url: String,
+    /// Extra request headers; `$ENV_VAR` references in values are resolved
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Events per POST
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// Send a partial batch after this long
+    #[serde(default = "default_flush_interval_secs")]
+    pub flush_interval_secs: u64,
+    /// Attempts per batch before it is dropped
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Events held in memory while the endpoint is down; newer events are dropped beyond this
+    #[serde(default = "default_queue_capacity")]
+    pub queue_capacity: usize,
+}
+
+impl Default for BillingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: String::new(),
+            headers: BTreeMap::new(),
+            batch_size: default_batch_size(),
+            flush_interval_secs: default_flush_interval_secs(),
+            max_attempts: default_max_attempts(),
+            queue_capacity: default_queue_capacity(),
+        }
+    }
+}
+
+impl BillingConfig {
+    /// Replace `$ENV_VAR` references in header values (unset variables become empty)
+    pub fn resolve_env_vars(&mut self) {
+        let var = regex::Regex::new(r"\$([A-Za-z_][A-Za-z0-9_]*)").expect("valid regex");
+        for value in self.headers.values_mut() {
+            *value = var
+                .replace_all(value, |caps: &regex::Captures| std::env::var(&caps[1]).unwrap_or_default())
+                .into_owned();
+        }
+    }
+}
+
+fn default_batch_size() -> usize {
+    50
+}
+
+fn default_flush_interval_secs() -> u64 {
+    10
+}
+
+fn default_max_attempts() -> u32 {
+    5
+}
+
+fn default_queue_capacity() -> usize {
+    10_000
+}
+
+/// Normalized usage record sent to the billing endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageEvent {
+    /// Request id, stable across retries so receivers can de-duplicate
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    /// Client that sent the request (from its API key), if known
+    pub tenant: Option<String>,
+    pub endpoint: String,
+    /// Model requested by the client
+    pub model: String,
+    pub provider: Option<String>,
+    /// Upstream model that served the request
+    pub provider_model: Option<String>,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    pub cost_usd: Option<f64>,
+    pub latency_ms: u64,
+    pub success: bool,
+}
+
+impl UsageEvent {
+    pub fn new(record: &UsageRecord, tenant: Option<String>) -> Self {
+        Self {
+            id: record.id.clone(),
+            timestamp: record.timestamp,
+            tenant,
+            endpoint: record.endpoint.clone(),
+            model: record.model.clone(),
+            provider: record.provider.clone(),
+            provider_model: record.actual_model.clone(),
+            input_tokens: record.input_tokens,
+            output_tokens: record.output_tokens,
+            cost_usd: record.cost_usd,
+            latency_ms: record.latency_ms,
+            success: record.success,
+        }
+    }
+}
+
+/// Queue of usage events drained by a background sender
+#[derive(Clone)]
+pub struct BillingHook {
+    tx: mpsc::Sender<UsageEvent>,
+}
+
+impl BillingHook {
+    /// Start the sender; None when billing is disabled
+    pub fn spawn(config: &BillingConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        if config.url.is_empty() {
+            warn!("billing is enabled but billing.url is empty; usage events disabled");
+            return None;
+        }
+        let (tx, rx) = mpsc::channel(config.queue_capacity.max(1));
+        tokio::spawn(run(config.clone(), rx));
+        Some(Self { tx })
+    }
+
+    /// Queue an event; drops it (with a warning) when the queue is full
+    pub fn record(&self, event: UsageEvent) {
+        if let Err(mpsc::error::TrySendError::Full(event)) = self.tx.try_send(event) {
+            warn!("Billing queue full; dropped usage event {}", event.id);
+        }
+    }
+}
+
+async fn run(config: BillingConfig, mut rx: mpsc::Receiver<UsageEvent>) {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .unwrap_or_default();
+    let batch_size = config.batch_size.max(1);
+    let mut interval = tokio::time::interval(Duration::from_secs(config.flush_interval_secs.max(1)));
+    let mut batch = Vec::with_capacity(batch_size);
+
+    loop {
+        let closed = tokio::select! {
+            event = rx.recv() => match event {
+                Some(event) => {
+                    batch.push(event);
+                    if batch.len() < batch_size {
+                        continue;
+                    }
+                    false
+                }
+                None => true,
+            },
+            _ = interval.tick() => false,
+        };
+        if !batch.is_empty() {
+            deliver(&client, &config, std::mem::take(&mut batch)).await;
+        }
+        if closed {
+            return;
+        }
+    }
+}
+
+/// POST a batch, retrying network errors, 429 and 5xx with exponential backoff
+async fn deliver(client: &reqwest::Client, config: &BillingConfig, events: Vec<UsageEvent>) {
+    let body = serde_json::json!({ "events": events });
+    let mut backoff = Duration::from_secs(1);
+
+    for attempt in 1..=config.max_attempts.max(1) {
+        let mut request = client.post(&config.url).json(&body);
+        for (name, value) in &config.headers {
+            request = request.header(name, value);
+        }
+        let retry = match request.send().await {
+            Ok(resp) if resp.status().is_success() => {
+                debug!("💳 Delivered {} usage events", events.len());
+                return;
+            }
+            Ok(resp) => {
+                let status = resp.status();
+                warn!("Billing webhook returned {} (attempt {})", status, attempt);
+                status.as_u16() == 429 || status.is_server_error()
+            }
+            Err(e) => {
+                warn!("Failed to deliver usage events (attempt {}): {}", attempt, e);
+                true
+            }
+        };
+        if !retry {
+            break;
+        }
+        if attempt < config.max_attempts {
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+    warn!("Dropped {} usage events after failed delivery", events.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_batches_and_retries() {
+        use axum::{extract::State, http::StatusCode, routing::post, Json};
+        use std::sync::{Arc, Mutex};
+
+        // Fails the first POST, then accepts
+        #[derive(Default)]
+        struct Received {
+            attempts: usize,
+            batches: Vec<serde_json::Value>,
+        }
+        async fn ingest(
+            State(received): State<Arc<Mutex<Received>>>,
+            Json(body): Json<serde_json::Value>,
+        ) -> StatusCode {
+            let mut received = received.lock().unwrap();
+            received.attempts += 1;
+            if received.attempts == 1 {
+                return StatusCode::SERVICE_UNAVAILABLE;
+            }
+            received.batches.push(body);
+            StatusCode::OK
+        }
+
+        let received = Arc::new(Mutex::new(Received::default()));
+        let app = axum::Router::new().route("/", post(ingest)).with_state(received.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let hook = BillingHook::spawn(&BillingConfig {
+            enabled: true,
+            url,
+            batch_size: 2,
+            flush_interval_secs: 3600,
+            ..Default::default()
+        })
+        .unwrap();
+        let record: UsageRecord = serde_json::from_value(serde_json::json!({
+            "id": "req_1", "timestamp": "2026-01-01T00:00:00Z", "model": "claude-sonnet",
+            "success": true, "latency_ms": 120, "input_tokens": 10, "output_tokens": 5,
+        }))
+        .unwrap();
+        hook.record(UsageEvent::new(&record, Some("ci".to_string())));
+        hook.record(UsageEvent::new(&record, None));
+
+        for _ in 0..50 {
+            if !received.lock().unwrap().batches.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        let received = received.lock().unwrap();
+        assert_eq!(received.attempts, 2);
+        let events = received.batches[0]["events"].as_array().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["tenant"], "ci");
+        assert_eq!(events[0]["input_tokens"], 10);
+    }
+}
@@ -0,0 +1,289 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use futures::stream::StreamExt;
+use std::time::{Duration, Instant};
+
+use super::{AppConfig, ModelMapping};
+use crate::auth::TokenStore;
+use crate::models::AnthropicRequest;
+use crate::providers::error::ProviderError;
+use crate::providers::streaming::LineBuffer;
+use crate::providers::{AnthropicProvider, ProviderRegistry};
+use crate::storage::{Database, UsageRecord, UsageStore, BENCH_ENDPOINT};
+
+/// Per-request limit for benchmark requests
+const RUN_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Standardized prompts, cycled through by the runs
+const PROMPTS: [&str; 3] = [
+    "Write a Rust function that returns the n-th Fibonacci number iteratively. Reply with code only.",
+    "Explain in three sentences what a mutex is and when to use one.",
+    "Rewrite this shell command to also include hidden files: `ls -l | grep '.rs'`. Answer briefly.",
+];
+
+const MAX_TOKENS: u32 = 256;
+
+/// Timing and usage of one streamed response
+#[derive(Debug, Default, Clone, PartialEq)]
+struct StreamStats {
+    /// Time until the first content delta (or the first chunk for non-Anthropic streams)
+    ttft: Option<Duration>,
+    input_tokens: u32,
+    cache_read_tokens: u32,
+    output_tokens: u32,
+}
+
+impl StreamStats {
+    /// Update from one chunk of Anthropic SSE
+    fn observe(&mut self, chunk: &str, elapsed: Duration) {
+        for data in chunk.lines().filter_map(|l| l.strip_prefix("data:")) {
+            let Ok(event) = serde_json::from_str::<serde_json::Value>(data.trim()) else {
+                continue;
+            };
+            let usage = match event["type"].as_str() {
+                Some("content_block_delta") => {
+                    self.ttft.get_or_insert(elapsed);
+                    continue;
+                }
+                Some("message_start") => &event["message"]["usage"],
+                Some("message_delta") => &event["usage"],
+                _ => continue,
+            };
+            if let Some(input) = usage["input_tokens"].as_u64() {
+                self.input_tokens = input as u32;
+            }
+            if let Some(cached) = usage["cache_read_input_tokens"].as_u64() {
+                self.cache_read_tokens = cached as u32;
+            }
+            if let Some(output) = usage["output_tokens"].as_u64() {
+                self.output_tokens = output as u32;
+            }
+        }
+    }
+}
+
+/// One benchmark request against one mapping
+struct Run {
+    total: Duration,
+    stats: StreamStats,
+    error: Option<String>,
+}
+
+/// Aggregated results for one mapping
+struct MappingResult<'a> {
+    mapping: &'a ModelMapping,
+    runs: Vec<Run>,
+}
+
+impl MappingResult<'_> {
+    fn ok_runs(&self) -> impl Iterator<Item = &Run> {
+        self.runs.iter().filter(|r| r.error.is_none())
+    }
+
+    fn median_ttft(&self) -> Option<Duration> {
+        let mut ttfts: Vec<Duration> = self.ok_runs().filter_map(|r| r.stats.ttft).collect();
+        ttfts.sort();
+        ttfts.get(ttfts.len() / 2).copied()
+    }
+
+    /// Output tokens per second of generation (after the first token)
+    fn tokens_per_sec(&self) -> Option<f64> {
+        let (tokens, secs) = self.ok_runs().fold((0u32, 0f64), |(tokens, secs), r| {
+            let generation = r.total.saturating_sub(r.stats.ttft.unwrap_or_default());
+            (tokens + r.stats.output_tokens, secs + generation.as_secs_f64())
+        });
+        (tokens > 0 && secs > 0.0).then(|| tokens as f64 / secs)
+    }
+
+    fn cost_usd(&self) -> Option<f64> {
+        self.ok_runs()
+            .map(|r| self.mapping.cost_usd(r.stats.input_tokens, r.stats.cache_read_tokens, r.stats.output_tokens))
+            .sum()
+    }
+
+    fn error_rate(&self) -> f64 {
+        let failed = self.runs.iter().filter(|r| r.error.is_some()).count();
+        failed as f64 / self.runs.len().max(1) as f64
+    }
+}
+
+/// `ccm bench <model> [--runs N]`: run standardized prompts against every provider
+/// mapped to a model and compare latency, throughput, cost and errors
+///
+/// Results are also written to the usage database (endpoint `bench`) when it is enabled;
+/// usage, cost and daily reports leave them out.
+pub async fn run(config: &AppConfig, model: &str, runs: usize) -> Result<()> {
+    let model_config = config
+        .models
+        .iter()
+        .find(|m| m.name.eq_ignore_ascii_case(model))
+        .with_context(|| {
+            let names: Vec<&str> = config.models.iter().map(|m| m.name.as_str()).collect();
+            format!("Unknown model '{}' (configured: {})", model, names.join(", "))
+        })?;
+
+    let token_store = TokenStore::default().context("Failed to open token store")?;
+    let registry = ProviderRegistry::from_configs(&config.providers, &config.server.timeouts, Some(token_store))
+        .context("Failed to initialize providers")?;
+    let usage_store = Database::from_config(&config.database)?.map(UsageStore::new);
+
+    let mut mappings: Vec<&ModelMapping> = model_config.mappings.iter().collect();
+    mappings.sort_by_key(|m| m.priority);
+
+    println!(
+        "⏱️  Benchmarking '{}' across {} providers, {} runs each...",
+        model_config.name,
+        mappings.len(),
+        runs
+    );
+
+    let mut results = Vec::new();
+    for mapping in mappings {
+        let Some(provider) = registry.get_provider(&mapping.provider) else {
+            println!("  ⏭️  {}: provider not enabled", mapping.provider);
+            continue;
+        };
+
+        let mut result = MappingResult { mapping, runs: Vec::new() };
+        for i in 0..runs {
+            let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+                "model": mapping.actual_model,
+                "max_tokens": MAX_TOKENS,
+                "stream": true,
+                "messages": [{ "role": "user", "content": PROMPTS[i % PROMPTS.len()] }],
+            }))?;
+
+            let run = run_once(provider.as_ref().as_ref(), request).await;
+            if let Some(store) = &usage_store {
+                let record = usage_record(&model_config.name, mapping, &run);
+                if let Err(e) = store.append(&record).await {
+                    eprintln!("Failed to record benchmark run: {}", e);
+                }
+            }
+            result.runs.push(run);
+        }
+        results.push(result);
+    }
+
+    print_table(&results);
+    Ok(())
+}
+
+async fn run_once(provider: &dyn AnthropicProvider, request: AnthropicRequest) -> Run {
+    let start = Instant::now();
+    let mut stats = StreamStats::default();
+    let mut first_chunk = None;
+    // Events can be split across chunks; only complete lines are parsed
+    let mut pending = LineBuffer::default();
+
+    let streamed = tokio::time::timeout(RUN_TIMEOUT, async {
+        let mut stream = provider.send_message_stream(request).await?;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            let elapsed = start.elapsed();
+            first_chunk.get_or_insert(elapsed);
+            pending.push(&chunk);
+            if let Some(lines) = pending.lines() {
+                stats.observe(&lines, elapsed);
+            }
+        }
+        Ok::<_, ProviderError>(())
+    })
+    .await;
+    // Streams in other formats still get a TTFT from their first chunk
+    stats.ttft = stats.ttft.or(first_chunk);
+
+    let error = match streamed {
+        Err(_) => Some(format!("No response within {}s", RUN_TIMEOUT.as_secs())),
+        Ok(Err(e)) => Some(e.to_string()),
+        Ok(Ok(())) => None,
+    };
+    Run {
+        total: start.elapsed(),
+        stats,
+        error,
+    }
+}
+
+fn usage_record(model: &str, mapping: &ModelMapping, run: &Run) -> UsageRecord {
+    let now = Utc::now();
+    UsageRecord {
+        id: format!("bench_{}", now.timestamp_nanos_opt().unwrap_or_default()),
+        timestamp: now,
+        endpoint: BENCH_ENDPOINT.to_string(),
+        model: model.to_string(),
+        routed_model: Some(model.to_string()),
+        route_type: None,
+        provider: Some(mapping.provider.clone()),
+        actual_model: Some(mapping.actual_model.clone()),
+        stream: true,
+        success: run.error.is_none(),
+        latency_ms: run.total.as_millis() as u64,
+        input_tokens: run.stats.input_tokens,
+        output_tokens: run.stats.output_tokens,
+        cost_usd: mapping.cost_usd(run.stats.input_tokens, run.stats.cache_read_tokens, run.stats.output_tokens),
+        error: run.error.clone(),
+    }
+}
+
+fn print_table(results: &[MappingResult]) {
+    println!(
+        "\n{:<20} {:<32} {:>10} {:>10} {:>12} {:>8}",
+        "PROVIDER", "MODEL", "TTFT", "TOK/S", "COST", "ERRORS"
+    );
+    for result in results {
+        let ttft = result
+            .median_ttft()
+            .map_or("-".to_string(), |t| format!("{}ms", t.as_millis()));
+        let tps = result.tokens_per_sec().map_or("-".to_string(), |t| format!("{:.1}", t));
+        let cost = result.cost_usd().map_or("-".to_string(), |c| format!("${:.5}", c));
+        println!(
+            "{:<20} {:<32} {:>10} {:>10} {:>12} {:>7.0}%",
+            result.mapping.provider,
+            result.mapping.actual_model,
+            ttft,
+            tps,
+            cost,
+            result.error_rate() * 100.0
+        );
+    }
+
+    for result in results {
+        if let Some(error) = result.runs.iter().find_map(|r| r.error.as_deref()) {
+            println!("\n❌ {}: {}", result.mapping.provider, error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_stats() {
+        let mut stats = StreamStats::default();
+        stats.observe(
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":12,\"output_tokens\":1}}}\n\n",
+            Duration::from_millis(100),
+        );
+        assert_eq!(stats.ttft, None);
+
+        stats.observe(
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n",
+            Duration::from_millis(250),
+        );
+        stats.observe(
+            "event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{},\"usage\":{\"output_tokens\":40}}\n\n",
+            Duration::from_millis(900),
+        );
+        assert_eq!(
+            stats,
+            StreamStats {
+                ttft: Some(Duration::from_millis(250)),
+                input_tokens: 12,
+                cache_read_tokens: 0,
+                output_tokens: 40,
+            }
+        );
+    }
+}
@@ -0,0 +1,246 @@
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+use super::logs::server_base_url;
+use super::AppConfig;
+
+/// Placeholder credential when the mux doesn't require an API key
+const NO_AUTH_TOKEN: &str = "ccm";
+
+/// Provider id used for the mux in Codex CLI config
+const CODEX_PROVIDER_ID: &str = "ccm";
+
+/// Environment variable Codex CLI reads the mux API key from
+const CODEX_KEY_ENV: &str = "CCM_API_KEY";
+
+/// Options for the `ccm init-*` commands
+#[derive(Debug, Default)]
+pub struct ClientSetup {
+    /// Write a named profile instead of changing the client's defaults
+    pub profile: Option<String>,
+    /// Model the client requests by default
+    pub model: Option<String>,
+    /// Model for background tasks (Claude Code only)
+    pub small_model: Option<String>,
+    /// Print the result instead of writing it
+    pub dry_run: bool,
+}
+
+fn claude_dir() -> Result<PathBuf> {
+    Ok(dirs::home_dir().context("Failed to get home directory")?.join(".claude"))
+}
+
+/// Environment Claude Code needs to talk to the mux
+fn claude_env(config: &AppConfig, setup: &ClientSetup) -> Vec<(&'static str, String)> {
+    let token = config
+        .server
+        .api_key
+        .clone()
+        .unwrap_or_else(|| NO_AUTH_TOKEN.to_string());
+    let mut env = vec![
+        ("ANTHROPIC_BASE_URL", server_base_url(config)),
+        ("ANTHROPIC_AUTH_TOKEN", token),
+    ];
+    if let Some(model) = &setup.model {
+        env.push(("ANTHROPIC_MODEL", model.clone()));
+    }
+    if let Some(model) = &setup.small_model {
+        env.push(("ANTHROPIC_SMALL_FAST_MODEL", model.clone()));
+    }
+    env
+}
+
+/// Set `env` entries in Claude Code settings, keeping everything else as is
+fn merge_env(mut settings: Value, env: &[(&str, String)]) -> Value {
+    if !settings.is_object() {
+        settings = Value::Object(Map::new());
+    }
+    let root = settings.as_object_mut().expect("object");
+    let env_map = root
+        .entry("env")
+        .or_insert_with(|| Value::Object(Map::new()));
+    if !env_map.is_object() {
+        *env_map = Value::Object(Map::new());
+    }
+    let env_map = env_map.as_object_mut().expect("object");
+    // An API key would take precedence over the auth token
+    env_map.remove("ANTHROPIC_API_KEY");
+    for (key, value) in env {
+        env_map.insert(key.to_string(), Value::String(value.clone()));
+    }
+    settings
+}
+
+/// Register the mux as a Codex model provider (chat completions wire API) and select it
+fn merge_codex_config(
+    mut config: toml::Value,
+    base_url: &str,
+    requires_key: bool,
+    model: &str,
+    profile: Option<&str>,
+) -> toml::Value {
+    use toml::value::Table;
+
+    if !config.is_table() {
+        config = toml::Value::Table(Table::new());
+    }
+    let root = config.as_table_mut().expect("table");
+
+    let mut provider = Table::new();
+    provider.insert("name".into(), "Claude Code Mux".into());
+    provider.insert("base_url".into(), format!("{}/v1", base_url).into());
+    provider.insert("wire_api".into(), "chat".into());
+    if requires_key {
+        provider.insert("env_key".into(), CODEX_KEY_ENV.into());
+    }
+    table_entry(root, "model_providers").insert(CODEX_PROVIDER_ID.into(), provider.into());
+
+    let target = match profile {
+        Some(profile) => table_entry(table_entry(root, "profiles"), profile),
+        None => root,
+    };
+    target.insert("model_provider".into(), CODEX_PROVIDER_ID.into());
+    target.insert("model".into(), model.into());
+    config
+}
+
+/// Get or create a sub-table (replacing a non-table value)
+fn table_entry<'a>(table: &'a mut toml::value::Table, key: &str) -> &'a mut toml::value::Table {
+    let entry = table
+        .entry(key)
+        .or_insert_with(|| toml::Value::Table(Default::default()));
+    if !entry.is_table() {
+        *entry = toml::Value::Table(Default::default());
+    }
+    entry.as_table_mut().expect("table")
+}
+
+fn read_json(path: &Path) -> Result<Value> {
+    if !path.exists() {
+        return Ok(Value::Object(Map::new()));
+    }
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+fn write_file(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    std::fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// `ccm init-claude`: point Claude Code at the mux via its settings file
+pub fn init_claude(config: &AppConfig, setup: &ClientSetup) -> Result<()> {
+    let file_name = match &setup.profile {
+        Some(profile) => format!("settings.ccm-{}.json", profile),
+        None => "settings.json".to_string(),
+    };
+    let path = claude_dir()?.join(file_name);
+
+    let settings = merge_env(read_json(&path)?, &claude_env(config, setup));
+    let content = serde_json::to_string_pretty(&settings)?;
+
+    if setup.dry_run {
+        println!("# {}\n{}", path.display(), content);
+        return Ok(());
+    }
+
+    write_file(&path, &content)?;
+    println!("✅ Wrote {}", path.display());
+    if setup.profile.is_some() {
+        println!("   Use it with: claude --settings {}", path.display());
+    }
+    Ok(())
+}
+
+/// `ccm init-codex`: register the mux's OpenAI-compatible endpoint in ~/.codex/config.toml
+pub fn init_codex(config: &AppConfig, setup: &ClientSetup) -> Result<()> {
+    let path = dirs::home_dir()
+        .context("Failed to get home directory")?
+        .join(".codex")
+        .join("config.toml");
+
+    let existing = if path.exists() {
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))?
+    } else {
+        toml::Value::Table(Default::default())
+    };
+
+    let model = setup.model.as_deref().unwrap_or(&config.router.default);
+    let requires_key = config.server.api_key.is_some();
+    let merged = merge_codex_config(
+        existing,
+        &server_base_url(config),
+        requires_key,
+        model,
+        setup.profile.as_deref(),
+    );
+    let content = toml::to_string_pretty(&merged)?;
+
+    if setup.dry_run {
+        println!("# {}\n{}", path.display(), content);
+        return Ok(());
+    }
+
+    write_file(&path, &content)?;
+    println!("✅ Wrote {}", path.display());
+    if let Some(profile) = &setup.profile {
+        println!("   Use it with: codex --profile {}", profile);
+    }
+    if requires_key {
+        println!("   Export {} with the server API key before running codex", CODEX_KEY_ENV);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_env_keeps_other_settings() {
+        let existing = json!({
+            "permissions": { "allow": ["Bash(ls)"] },
+            "env": { "FOO": "bar", "ANTHROPIC_API_KEY": "sk-old" },
+        });
+        let merged = merge_env(
+            existing,
+            &[("ANTHROPIC_BASE_URL", "http://127.0.0.1:13456".to_string())],
+        );
+
+        assert_eq!(merged["permissions"]["allow"][0], "Bash(ls)");
+        assert_eq!(merged["env"]["FOO"], "bar");
+        assert_eq!(merged["env"]["ANTHROPIC_BASE_URL"], "http://127.0.0.1:13456");
+        assert!(merged["env"].get("ANTHROPIC_API_KEY").is_none());
+    }
+
+    #[test]
+    fn test_codex_profile_uses_mux_provider() {
+        let existing: toml::Value = toml::from_str(
+            r#"
+model = "gpt-5"
+approval_policy = "on-request"
+"#,
+        )
+        .unwrap();
+        let merged = merge_codex_config(existing, "http://127.0.0.1:13456", false, "sonnet", Some("mux"));
+
+        // Defaults are untouched when writing a profile
+        assert_eq!(merged["model"].as_str(), Some("gpt-5"));
+        assert_eq!(merged["approval_policy"].as_str(), Some("on-request"));
+        assert_eq!(merged["profiles"]["mux"]["model_provider"].as_str(), Some("ccm"));
+        assert_eq!(merged["profiles"]["mux"]["model"].as_str(), Some("sonnet"));
+
+        let provider = &merged["model_providers"]["ccm"];
+        assert_eq!(provider["base_url"].as_str(), Some("http://127.0.0.1:13456/v1"));
+        assert_eq!(provider["wire_api"].as_str(), Some("chat"));
+        assert!(provider.get("env_key").is_none());
+    }
+}
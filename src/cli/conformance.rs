@@ -0,0 +1,299 @@
+//! Conformance suite run against real providers (`live-tests` feature)
+//!
+//! Sends the same small set of requests (text, usage, stop sequences, tools,
+//! images, streaming) to every enabled provider and reports which features
+//! made it through the translation end to end:
+//!
+//! ```sh
+//! CCM_CONFIG=~/.claude-code-mux/config.toml cargo test --features live-tests live_conformance -- --nocapture
+//! ```
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+use super::doctor::test_model;
+use super::AppConfig;
+use crate::auth::TokenStore;
+use crate::models::AnthropicRequest;
+use crate::providers::streaming::collect_response;
+use crate::providers::{AnthropicProvider, ProviderRegistry, ProviderResponse};
+
+/// Per-request limit
+const CHECK_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// 16x16 solid red PNG
+const RED_PNG: &str = "iVBORw0KGgoAAAANSUhEUgAAABAAAAAQCAIAAACQkWg2AAAAFklEQVR42mP4z8BAEmIY1TCqYfhqAACQ+f8B8u7oVwAAAABJRU5ErkJggg==";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capability {
+    Text,
+    Usage,
+    StopSequences,
+    Tools,
+    Images,
+    Streaming,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 6] = [
+        Capability::Text,
+        Capability::Usage,
+        Capability::StopSequences,
+        Capability::Tools,
+        Capability::Images,
+        Capability::Streaming,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Capability::Text => "text",
+            Capability::Usage => "usage",
+            Capability::StopSequences => "stop_sequences",
+            Capability::Tools => "tools",
+            Capability::Images => "images",
+            Capability::Streaming => "streaming",
+        }
+    }
+
+    /// Request exercising the capability
+    fn request(self, model: &str) -> Value {
+        let mut request = json!({
+            "model": model,
+            "max_tokens": 256,
+            "messages": [{ "role": "user", "content": "Reply with the single word: pong" }],
+        });
+        match self {
+            Capability::Text | Capability::Usage | Capability::Streaming => {}
+            Capability::StopSequences => {
+                request["messages"][0]["content"] =
+                    "Count from 1 to 10, separated by spaces, with nothing else.".into();
+                request["stop_sequences"] = json!(["5"]);
+            }
+            Capability::Tools => {
+                request["messages"][0]["content"] = "What is the weather in Paris? Use the tool.".into();
+                request["tools"] = json!([{
+                    "name": "get_weather",
+                    "description": "Current weather for a city",
+                    "input_schema": {
+                        "type": "object",
+                        "properties": { "city": { "type": "string" } },
+                        "required": ["city"],
+                    },
+                }]);
+            }
+            Capability::Images => {
+                request["messages"][0]["content"] = json!([
+                    { "type": "image", "source": { "type": "base64", "media_type": "image/png", "data": RED_PNG } },
+                    { "type": "text", "text": "What color is this image? Answer with one word." },
+                ]);
+            }
+        }
+        request
+    }
+
+    /// Whether a response shows the capability working, with a short note
+    fn verify(self, response: &Value) -> Result<String, String> {
+        let text = response["content"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|block| block["text"].as_str())
+            .collect::<String>();
+        match self {
+            Capability::Text | Capability::Streaming if text.trim().is_empty() => Err("empty reply".to_string()),
+            Capability::Text | Capability::Streaming => match response["stop_reason"].as_str() {
+                Some(reason) => Ok(format!("{:?} ({})", text.trim(), reason)),
+                None => Err("no stop_reason".to_string()),
+            },
+            Capability::Usage => {
+                let (input, output) = (
+                    response["usage"]["input_tokens"].as_u64().unwrap_or(0),
+                    response["usage"]["output_tokens"].as_u64().unwrap_or(0),
+                );
+                if input > 0 && output > 0 {
+                    Ok(format!("{} in / {} out", input, output))
+                } else {
+                    Err(format!("usage missing ({} in / {} out)", input, output))
+                }
+            }
+            Capability::StopSequences => {
+                if response["stop_reason"] == "stop_sequence" {
+                    Ok(format!("stopped at {}", response["stop_sequence"]))
+                } else if !text.contains('5') && !text.contains('6') {
+                    Ok("stopped (stop_reason not reported)".to_string())
+                } else {
+                    Err(format!("ran past the stop sequence: {:?}", text.trim()))
+                }
+            }
+            Capability::Tools => {
+                let call = response["content"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .find(|block| block["type"] == "tool_use" && block["name"] == "get_weather");
+                match call {
+                    Some(call) if call["input"]["city"].is_string() => Ok(format!("called with {}", call["input"])),
+                    Some(call) => Err(format!("called without a city: {}", call["input"])),
+                    None => Err("no tool call".to_string()),
+                }
+            }
+            Capability::Images if text.to_lowercase().contains("red") => Ok(format!("{:?}", text.trim())),
+            Capability::Images => Err(format!("didn't see red: {:?}", text.trim())),
+        }
+    }
+}
+
+/// Results of the suite for one provider
+#[derive(Debug)]
+pub struct ProviderReport {
+    pub provider: String,
+    pub model: Option<String>,
+    pub results: Vec<(Capability, Result<String, String>)>,
+}
+
+impl ProviderReport {
+    pub fn passed(&self, capability: Capability) -> bool {
+        self.results.iter().any(|(c, result)| *c == capability && result.is_ok())
+    }
+}
+
+/// Run the suite against every enabled provider
+pub async fn run(config: &AppConfig) -> Result<Vec<ProviderReport>> {
+    let token_store = TokenStore::default().context("Failed to open token store")?;
+    let registry = ProviderRegistry::from_configs(&config.providers, &config.server.timeouts, Some(token_store))
+        .context("Failed to initialize providers")?;
+
+    let mut reports = Vec::new();
+    for provider_config in config.providers.iter().filter(|p| p.is_enabled()) {
+        let name = provider_config.name.clone();
+        let model = test_model(config, &name);
+        let (Some(model), Some(provider)) = (model.clone(), registry.get_provider(&name)) else {
+            reports.push(ProviderReport {
+                provider: name,
+                model,
+                results: Vec::new(),
+            });
+            continue;
+        };
+
+        let mut results = Vec::new();
+        for capability in Capability::ALL {
+            let result = match send(provider.as_ref().as_ref(), capability, &model).await {
+                Ok(response) => capability.verify(&serde_json::to_value(&response)?),
+                Err(e) => Err(e),
+            };
+            results.push((capability, result));
+        }
+        reports.push(ProviderReport {
+            provider: name,
+            model: Some(model),
+            results,
+        });
+    }
+    Ok(reports)
+}
+
+async fn send(provider: &dyn AnthropicProvider, capability: Capability, model: &str) -> Result<ProviderResponse, String> {
+    let request: AnthropicRequest =
+        serde_json::from_value(capability.request(model)).map_err(|e| e.to_string())?;
+    let response = async {
+        if capability == Capability::Streaming {
+            collect_response(provider.send_message_stream(request).await?).await
+        } else {
+            provider.send_message(request).await
+        }
+    };
+    match tokio::time::timeout(CHECK_TIMEOUT, response).await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(_) => Err(format!("no response within {}s", CHECK_TIMEOUT.as_secs())),
+    }
+}
+
+/// Capability report as a table followed by the failure details
+pub fn format_report(reports: &[ProviderReport]) -> String {
+    let mut out = format!("{:<20} {:<32}", "provider", "model");
+    for capability in Capability::ALL {
+        out.push_str(&format!(" {:<14}", capability.as_str()));
+    }
+    out.push('\n');
+
+    let mut failures = Vec::new();
+    for report in reports {
+        out.push_str(&format!(
+            "{:<20} {:<32}",
+            report.provider,
+            report.model.as_deref().unwrap_or("(no model)")
+        ));
+        for capability in Capability::ALL {
+            let mark = match report.results.iter().find(|(c, _)| *c == capability) {
+                Some((_, Ok(_))) => "✅",
+                Some((_, Err(e))) => {
+                    failures.push(format!("{} {}: {}", report.provider, capability.as_str(), e));
+                    "❌"
+                }
+                None => "⏭️",
+            };
+            out.push_str(&format!(" {:<14}", mark));
+        }
+        out.push('\n');
+    }
+    for failure in failures {
+        out.push_str(&format!("\n{}", failure));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let response = json!({
+            "content": [
+                { "type": "text", "text": "1 2 3 4 " },
+                { "type": "tool_use", "id": "t1", "name": "get_weather", "input": { "city": "Paris" } },
+            ],
+            "stop_reason": "stop_sequence",
+            "stop_sequence": "5",
+            "usage": { "input_tokens": 20, "output_tokens": 0 },
+        });
+        assert!(Capability::Text.verify(&response).is_ok());
+        assert!(Capability::StopSequences.verify(&response).is_ok());
+        assert!(Capability::Tools.verify(&response).is_ok());
+        assert!(Capability::Usage.verify(&response).is_err());
+        assert!(Capability::Images.verify(&response).is_err());
+
+        let report = ProviderReport {
+            provider: "zai".to_string(),
+            model: Some("glm-4.6".to_string()),
+            results: vec![(Capability::Text, Ok("pong".to_string())), (Capability::Images, Err("no vision".to_string()))],
+        };
+        assert!(report.passed(Capability::Text));
+        assert!(format_report(&[report]).ends_with("\nzai images: no vision"));
+    }
+
+    /// Runs against the providers in `$CCM_CONFIG` (default: the user's config)
+    #[tokio::test]
+    async fn live_conformance() {
+        let path = match std::env::var_os("CCM_CONFIG") {
+            Some(path) => path.into(),
+            None => AppConfig::default_path().unwrap(),
+        };
+        if !path.exists() {
+            eprintln!("No config at {}, skipping live conformance tests", path.display());
+            return;
+        }
+        let config = AppConfig::from_file(&path).unwrap();
+        let reports = run(&config).await.unwrap();
+        println!("{}", format_report(&reports));
+
+        let broken: Vec<&str> = reports
+            .iter()
+            .filter(|r| r.model.is_some() && !r.passed(Capability::Text))
+            .map(|r| r.provider.as_str())
+            .collect();
+        assert!(broken.is_empty(), "providers failing basic requests: {:?}", broken);
+    }
+}
@@ -0,0 +1,252 @@
+use anyhow::{Context, Result};
+use std::time::{Duration, Instant};
+
+use super::AppConfig;
+use crate::auth::TokenStore;
+use crate::models::AnthropicRequest;
+use crate::providers::error::ProviderError;
+use crate::providers::ProviderRegistry;
+
+/// Responses slower than this are reported as a latency problem
+const SLOW_RESPONSE: Duration = Duration::from_secs(10);
+
+/// Per-request limit for smoke test requests
+const CHECK_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckStatus {
+    Ok,
+    /// Reachable but degraded (slow, rate limited)
+    Warning,
+    Failed,
+    /// Nothing to test with (no model configured)
+    Skipped,
+}
+
+/// Smoke test result for one provider
+#[derive(Debug)]
+pub struct ProviderCheck {
+    pub provider: String,
+    pub model: Option<String>,
+    pub status: CheckStatus,
+    pub latency: Option<Duration>,
+    pub message: String,
+}
+
+impl ProviderCheck {
+    fn is_usable(&self) -> bool {
+        matches!(self.status, CheckStatus::Ok | CheckStatus::Warning)
+    }
+}
+
+/// Send a one-token request to every enabled provider
+pub async fn check_providers(config: &AppConfig) -> Result<Vec<ProviderCheck>> {
+    let token_store = TokenStore::default().context("Failed to open token store")?;
+    let registry = ProviderRegistry::from_configs(&config.providers, &config.server.timeouts, Some(token_store))
+        .context("Failed to initialize providers")?;
+
+    let mut checks = Vec::new();
+    for provider_config in config.providers.iter().filter(|p| p.is_enabled()) {
+        let name = provider_config.name.clone();
+        let Some(model) = test_model(config, &name) else {
+            checks.push(ProviderCheck {
+                provider: name,
+                model: None,
+                status: CheckStatus::Skipped,
+                latency: None,
+                message: "No model to test with: add a model mapping or list models on the provider".to_string(),
+            });
+            continue;
+        };
+        let Some(provider) = registry.get_provider(&name) else {
+            continue;
+        };
+
+        let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": model,
+            "max_tokens": 1,
+            "messages": [{ "role": "user", "content": "ping" }],
+        }))?;
+
+        let start = Instant::now();
+        let result = tokio::time::timeout(CHECK_TIMEOUT, provider.send_message(request)).await;
+        let latency = start.elapsed();
+
+        let (status, message) = match result {
+            Err(_) => (
+                CheckStatus::Failed,
+                format!("No response within {}s: check base_url and network access", CHECK_TIMEOUT.as_secs()),
+            ),
+            Ok(Ok(_)) if latency > SLOW_RESPONSE => (
+                CheckStatus::Warning,
+                format!("Slow response ({}ms)", latency.as_millis()),
+            ),
+            Ok(Ok(_)) => (CheckStatus::Ok, "OK".to_string()),
+            Ok(Err(e)) => diagnose(&e),
+        };
+
+        checks.push(ProviderCheck {
+            provider: name,
+            model: Some(model),
+            status,
+            latency: Some(latency),
+            message,
+        });
+    }
+    Ok(checks)
+}
+
+/// Model used to test a provider: its highest-priority mapping, else its first listed model
+pub(super) fn test_model(config: &AppConfig, provider: &str) -> Option<String> {
+    config
+        .models
+        .iter()
+        .flat_map(|m| &m.mappings)
+        .filter(|m| m.provider == provider)
+        .min_by_key(|m| m.priority)
+        .map(|m| m.actual_model.clone())
+        .or_else(|| {
+            config
+                .providers
+                .iter()
+                .find(|p| p.name == provider)
+                .and_then(|p| p.models.first().cloned())
+        })
+}
+
+/// Map a provider error to an actionable message
+fn diagnose(error: &ProviderError) -> (CheckStatus, String) {
+    let message = match error {
+        ProviderError::AuthError(e) => format!("Authentication failed ({}): log in again via the admin UI OAuth flow", e),
+        ProviderError::ApiError { status: 401 | 403, message } => {
+            format!("Authentication rejected ({}): check api_key or OAuth token", message)
+        }
+        ProviderError::ApiError { status: 404, message } => {
+            format!("Model or endpoint not found ({}): check actual_model and base_url", message)
+        }
+        ProviderError::ApiError { status: 400 | 422, message } => {
+            format!("Request rejected ({}): check provider_type matches the API format", message)
+        }
+        ProviderError::ApiError { status: 429, message } => {
+            return (CheckStatus::Warning, format!("Rate limited ({}), but credentials work", message));
+        }
+        ProviderError::ApiError { status, message } if *status >= 500 => {
+            format!("Provider error {} ({}): the service may be down", status, message)
+        }
+        ProviderError::HttpError(e) => format!("Connection failed ({}): check base_url and network access", e),
+        ProviderError::SerializationError(e) => {
+            format!("Unexpected response format ({}): check provider_type", e)
+        }
+        ProviderError::ModelNotSupported(model) => {
+            format!("Model '{}' not supported: add it to the provider's models list", model)
+        }
+        other => other.to_string(),
+    };
+    (CheckStatus::Failed, message)
+}
+
+/// Configured models left without any working provider
+pub fn unavailable_models(config: &AppConfig, checks: &[ProviderCheck]) -> Vec<String> {
+    config
+        .models
+        .iter()
+        .filter(|model| {
+            !model.mappings.iter().any(|mapping| {
+                checks
+                    .iter()
+                    .any(|c| c.provider == mapping.provider && c.is_usable())
+            })
+        })
+        .map(|model| model.name.clone())
+        .collect()
+}
+
+pub fn print_report(checks: &[ProviderCheck]) {
+    for check in checks {
+        let icon = match check.status {
+            CheckStatus::Ok => "✅",
+            CheckStatus::Warning => "⚠️ ",
+            CheckStatus::Failed => "❌",
+            CheckStatus::Skipped => "⏭️ ",
+        };
+        let latency = check
+            .latency
+            .map(|l| format!(" [{}ms]", l.as_millis()))
+            .unwrap_or_default();
+        let model = check.model.as_deref().map(|m| format!(" ({})", m)).unwrap_or_default();
+        println!("{} {}{}{}: {}", icon, check.provider, model, latency, check.message);
+    }
+}
+
+/// `ccm doctor`: smoke test every provider; returns false if a configured model has no working provider
+pub async fn run(config: &AppConfig) -> Result<bool> {
+    println!("🩺 Checking {} providers...", config.providers.iter().filter(|p| p.is_enabled()).count());
+    let checks = check_providers(config).await?;
+    print_report(&checks);
+
+    let unavailable = unavailable_models(config, &checks);
+    if unavailable.is_empty() {
+        println!("\nAll configured models have a working provider");
+        Ok(true)
+    } else {
+        println!("\nModels without a working provider: {}", unavailable.join(", "));
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(provider: &str, status: CheckStatus) -> ProviderCheck {
+        ProviderCheck {
+            provider: provider.to_string(),
+            model: None,
+            status,
+            latency: None,
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_models_with_fallback_stay_available() {
+        let config = AppConfig::from_toml_str(
+            r#"
+[router]
+default = "sonnet"
+
+[[models]]
+name = "sonnet"
+mappings = [
+    { priority = 1, provider = "a", actual_model = "x" },
+    { priority = 2, provider = "b", actual_model = "y" },
+]
+
+[[models]]
+name = "opus"
+mappings = [{ priority = 1, provider = "a", actual_model = "z" }]
+"#,
+        )
+        .unwrap();
+
+        let checks = [check("a", CheckStatus::Failed), check("b", CheckStatus::Warning)];
+        assert_eq!(unavailable_models(&config, &checks), vec!["opus"]);
+        assert_eq!(test_model(&config, "a").as_deref(), Some("x"));
+    }
+
+    #[test]
+    fn test_diagnose_auth_errors() {
+        let (status, message) = diagnose(&ProviderError::ApiError {
+            status: 401,
+            message: "invalid x-api-key".to_string(),
+        });
+        assert_eq!(status, CheckStatus::Failed);
+        assert!(message.contains("api_key"));
+
+        let (status, _) = diagnose(&ProviderError::ApiError {
+            status: 429,
+            message: "slow down".to_string(),
+        });
+        assert_eq!(status, CheckStatus::Warning);
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::{Context, Result};
+use futures::stream::StreamExt;
+
+use super::AppConfig;
+use crate::providers::streaming::LineBuffer;
+
+/// Base URL of the running server (0.0.0.0 is reached via loopback)
+pub fn server_base_url(config: &AppConfig) -> String {
+    let host = match config.server.host.as_str() {
+        "0.0.0.0" | "::" => "127.0.0.1",
+        host => host,
+    };
+    format!("http://{}:{}", host, config.server.port)
+}
+
+/// `ccm logs [-f] [--bodies]`: print recent requests, optionally following the live feed
+pub async fn run(config: &AppConfig, follow: bool, bodies: bool) -> Result<()> {
+    let base_url = server_base_url(config);
+    let client = reqwest::Client::new();
+
+    if !follow {
+        let entries: Vec<serde_json::Value> = client
+            .get(format!("{}/admin/logs", base_url))
+            .send()
+            .await
+            .with_context(|| format!("Failed to connect to {} (is the service running?)", base_url))?
+            .error_for_status()?
+            .json()
+            .await?;
+        for entry in &entries {
+            print_entry(entry, bodies);
+        }
+        return Ok(());
+    }
+
+    let response = client
+        .get(format!("{}/admin/logs/stream?bodies={}", base_url, bodies))
+        .send()
+        .await
+        .with_context(|| format!("Failed to connect to {} (is the service running?)", base_url))?
+        .error_for_status()?;
+
+    println!("📡 Following requests on {} (Ctrl+C to stop)", base_url);
+
+    let mut stream = response.bytes_stream();
+    let mut buffer = LineBuffer::default();
+    while let Some(chunk) = stream.next().await {
+        buffer.push(&chunk?);
+        let lines = buffer.lines().unwrap_or_default();
+        for line in lines.lines() {
+            let Some(data) = line.strip_prefix("data:") else {
+                continue;
+            };
+            match serde_json::from_str::<serde_json::Value>(data.trim()) {
+                Ok(entry) if entry.get("skipped").is_some() => {
+                    println!("⚠️  {} entries dropped (client too slow)", entry["skipped"]);
+                }
+                Ok(entry) => print_entry(&entry, bodies),
+                Err(_) => continue,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_entry(entry: &serde_json::Value, bodies: bool) {
+    let str_field = |key: &str| entry.get(key).and_then(|v| v.as_str()).unwrap_or("-");
+    let status = if entry["success"].as_bool().unwrap_or(false) { "✅" } else { "❌" };
+    let tokens = match (entry["input_tokens"].as_u64(), entry["output_tokens"].as_u64()) {
+        (Some(input), Some(output)) => format!(" {}→{} tok", input, output),
+        _ => String::new(),
+    };
+
+    println!(
+        "{} {} {} → {} [{}] {}/{} {}ms{}{}",
+        status,
+        str_field("timestamp"),
+        str_field("model"),
+        str_field("routed_model"),
+        str_field("route_type"),
+        str_field("provider"),
+        str_field("actual_model"),
+        entry["latency_ms"].as_u64().unwrap_or(0),
+        tokens,
+        if entry["stream"].as_bool().unwrap_or(false) { " (stream)" } else { "" },
+    );
+    if let Some(error) = entry.get("error").and_then(|e| e.as_str()) {
+        println!("   error: {}", error);
+    }
+
+    if bodies {
+        for key in ["request_body", "response_body"] {
+            if let Some(body) = entry.get(key) {
+                if let Ok(pretty) = serde_json::to_string_pretty(body) {
+                    println!("   {}:\n{}", key, pretty);
+                }
+            }
+        }
+    }
+}
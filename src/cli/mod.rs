@@ -1,7 +1,29 @@
+pub mod bench;
+pub mod clients;
+#[cfg(all(test, feature = "live-tests"))]
+pub mod conformance;
+pub mod doctor;
+pub mod logs;
+pub mod transform;
+
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use anyhow::{Context, Result};
+use crate::auth::api_keys::ClientKey;
+use crate::auth::OidcConfig;
+use crate::models::{Role, Verbosity};
 use crate::providers::ProviderConfig;
+use crate::transform::fim::FimTemplate;
+use crate::transform::guardrails::GuardrailsConfig;
+use crate::transform::sampling::Sampling;
+use crate::transform::templates::PromptTemplate;
+use crate::transform::tools::ToolPolicy;
+use crate::storage::{ArchiveConfig, BlobStoreConfig, DatabaseConfig, SemanticCacheConfig, SharedStateConfig};
+use crate::transform::truncation::TruncationConfig;
+use crate::alerting::AlertingConfig;
+use crate::billing::BillingConfig;
+use crate::traces::TraceExportConfig;
+use crate::reports::UsageReportConfig;
 
 /// Application configuration
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -13,6 +35,34 @@ pub struct AppConfig {
     pub providers: Vec<ProviderConfig>,
     #[serde(default)]
     pub models: Vec<ModelConfig>,
+    #[serde(default)]
+    pub tool_result_truncation: TruncationConfig,
+    #[serde(default)]
+    pub blob_store: BlobStoreConfig,
+    #[serde(default)]
+    pub alerting: AlertingConfig,
+    #[serde(default)]
+    pub usage_report: UsageReportConfig,
+    #[serde(default)]
+    pub database: DatabaseConfig,
+    #[serde(default)]
+    pub shared_state: SharedStateConfig,
+    #[serde(default)]
+    pub semantic_cache: SemanticCacheConfig,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub templates: Vec<PromptTemplate>,
+    #[serde(default)]
+    pub guardrails: GuardrailsConfig,
+    #[serde(default)]
+    pub billing: BillingConfig,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trace_export: Vec<TraceExportConfig>,
+    #[serde(default)]
+    pub archive: ArchiveConfig,
+    #[serde(default)]
+    pub organizations: OrganizationsConfig,
+    #[serde(default)]
+    pub judge: JudgeConfig,
 }
 
 /// Server configuration
@@ -23,10 +73,42 @@ pub struct ServerConfig {
     #[serde(default = "default_host")]
     pub host: String,
     pub api_key: Option<String>,
+    /// Additional client keys, with optional expiry for rotation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub api_keys: Vec<ClientKey>,
     #[serde(default = "default_log_level")]
     pub log_level: String,
     #[serde(default)]
     pub timeouts: TimeoutConfig,
+    /// Chunks read ahead from upstream per streaming response before pausing
+    #[serde(default = "default_stream_buffer_chunks")]
+    pub stream_buffer_chunks: usize,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    /// Log requests and responses larger than these sizes
+    #[serde(default)]
+    pub payload_warnings: PayloadWarnings,
+    /// Share of requests whose bodies are logged, and what is redacted from them
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    /// Compress responses for clients that send Accept-Encoding (streams are never compressed)
+    #[serde(default = "default_compression")]
+    pub compression: bool,
+    /// Require JWTs from this OIDC issuer on admin routes and the dashboard
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oidc: Option<OidcConfig>,
+    /// Report lossy request transformations in an `x-ccm-transform-warnings` response header
+    #[serde(default)]
+    pub transform_warnings_header: bool,
+    /// Port of the gRPC ingress (requires the `grpc` build feature)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grpc_port: Option<u16>,
+    /// Recent requests kept in memory with full bodies, for replay and comparison
+    #[serde(default)]
+    pub replay_buffer: usize,
+    /// Answers to Claude Code's event logging and feature flag calls
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
 }
 
 impl Default for ServerConfig {
@@ -35,12 +117,27 @@ impl Default for ServerConfig {
             port: default_port(),
             host: default_host(),
             api_key: None,
+            api_keys: Vec::new(),
             log_level: default_log_level(),
             timeouts: TimeoutConfig::default(),
+            stream_buffer_chunks: default_stream_buffer_chunks(),
+            limits: LimitsConfig::default(),
+            payload_warnings: PayloadWarnings::default(),
+            logging: LoggingConfig::default(),
+            compression: default_compression(),
+            transform_warnings_header: false,
+            grpc_port: None,
+            replay_buffer: 0,
+            oidc: None,
+            telemetry: TelemetryConfig::default(),
         }
     }
 }
 
+fn default_compression() -> bool {
+    true
+}
+
 fn default_port() -> u16 {
     3456
 }
@@ -53,11 +150,17 @@ fn default_log_level() -> String {
     "info".to_string()
 }
 
-/// Timeout configuration
+fn default_stream_buffer_chunks() -> usize {
+    32
+}
+
+/// Timeout configuration, the defaults of each provider's `[providers.timeouts]`
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TimeoutConfig {
+    /// Default `total_ms` (non-streaming requests)
     #[serde(default = "default_api_timeout")]
     pub api_timeout_ms: u64,
+    /// Default `connect_ms`
     #[serde(default = "default_connect_timeout")]
     pub connect_timeout_ms: u64,
 }
@@ -79,6 +182,141 @@ fn default_connect_timeout() -> u64 {
     10_000 // 10 seconds
 }
 
+/// Claude Code's auxiliary endpoints (event logging, metrics, feature flags)
+///
+/// ```toml
+/// [server.telemetry]
+/// mode = "proxy"                 # stub (default), proxy or off
+/// paths = ["/api/claude_code/settings"]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct TelemetryConfig {
+    #[serde(default)]
+    pub mode: TelemetryMode,
+    /// More paths to answer besides the built-in ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TelemetryMode {
+    /// Answer with an empty success
+    #[default]
+    Stub,
+    /// Forward to Anthropic with an Anthropic OAuth login, stubbing when there is none
+    Proxy,
+    /// Leave the paths unrouted (404)
+    Off,
+}
+
+/// Request size limits (protects small deployments from huge pastes)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LimitsConfig {
+    /// Largest accepted request body
+    #[serde(default = "default_max_request_bytes")]
+    pub max_request_bytes: usize,
+    /// Largest decoded size of a single image
+    #[serde(default = "default_max_image_bytes")]
+    pub max_image_bytes: usize,
+    /// Total size of request bodies held in memory at once
+    #[serde(default = "default_max_inflight_bytes")]
+    pub max_inflight_bytes: usize,
+    /// Requests of one conversation (`metadata.user_id` session) running at
+    /// once; more wait their turn
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_requests_per_session: Option<usize>,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_request_bytes: default_max_request_bytes(),
+            max_image_bytes: default_max_image_bytes(),
+            max_inflight_bytes: default_max_inflight_bytes(),
+            max_requests_per_session: None,
+        }
+    }
+}
+
+/// Sizes above which a single payload is logged as a warning
+///
+/// ```toml
+/// [server.payload_warnings]
+/// request_bytes = 1048576    # as sent to the provider
+/// response_bytes = 262144
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct PayloadWarnings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_bytes: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_bytes: Option<usize>,
+}
+
+/// Body sampling and redaction for the request log, trace export, archive and debug logs
+///
+/// Requests outside the sample are logged like those of a `log_bodies = false`
+/// key: timing, status and token counts only.
+///
+/// ```toml
+/// [server.logging]
+/// body_sample_rate = 0.1              # full bodies for 10% of requests
+/// redact = ["images", "system"]       # also "tool_results"
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LoggingConfig {
+    /// Share of requests logged with bodies (0.0 - 1.0)
+    #[serde(default = "default_body_sample_rate")]
+    pub body_sample_rate: f64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redact: Vec<Redaction>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            body_sample_rate: default_body_sample_rate(),
+            redact: Vec::new(),
+        }
+    }
+}
+
+fn default_body_sample_rate() -> f64 {
+    1.0
+}
+
+impl LoggingConfig {
+    /// Whether to log the bodies of the next request
+    pub fn sample(&self) -> bool {
+        self.body_sample_rate >= 1.0 || rand::random::<f64>() < self.body_sample_rate
+    }
+}
+
+/// Part of a logged body replaced by a size note
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Redaction {
+    /// Base64 data of images and documents
+    Images,
+    /// The system prompt
+    System,
+    /// Tool result content
+    ToolResults,
+}
+
+fn default_max_request_bytes() -> usize {
+    32 * 1024 * 1024
+}
+
+fn default_max_image_bytes() -> usize {
+    10 * 1024 * 1024
+}
+
+fn default_max_inflight_bytes() -> usize {
+    256 * 1024 * 1024
+}
+
 /// Router configuration
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct RouterConfig {
@@ -92,6 +330,89 @@ pub struct RouterConfig {
     /// Regex pattern for detecting background tasks (e.g., "(?i)claude.*haiku").
     /// If empty/null, defaults to claude-haiku pattern.
     pub background_regex: Option<String>,
+    /// Model for /v1/completions (inline autocomplete), regardless of the requested model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion: Option<String>,
+    /// Built-in detection of Claude Code's utility calls
+    #[serde(default)]
+    pub background_detection: BackgroundDetection,
+    /// Models per Claude Code role
+    #[serde(default)]
+    pub roles: RoleModels,
+}
+
+impl RouterConfig {
+    /// Model for a role; plan and background fall back to `think` and `background`
+    pub fn role_model(&self, role: Role) -> Option<&String> {
+        match role {
+            Role::Main => self.roles.main.as_ref(),
+            Role::Plan => self.roles.plan.as_ref().or(self.think.as_ref()),
+            Role::Subagent => self.roles.subagent.as_ref(),
+            Role::Background => self.roles.background.as_ref().or(self.background.as_ref()),
+        }
+    }
+}
+
+/// Models per Claude Code role, picked by an `x-ccm-role` header or detected
+/// from the request
+///
+/// ```toml
+/// [router.roles]
+/// main = "claude-sonnet"
+/// plan = "claude-opus"          # default: router.think
+/// subagent = "glm-4.6"
+/// background = "glm-4.5-air"    # default: router.background
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RoleModels {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub main: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plan: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subagent: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background: Option<String>,
+}
+
+/// Recognize Claude Code's utility calls (titles, topic checks, command
+/// prefixes) by their shape rather than only the model name
+///
+/// ```toml
+/// [router.background_detection]
+/// enabled = true
+/// model = "glm-4.5-air"       # default: router.background
+/// max_tokens = 1024
+/// system_markers = ["Write a commit message"]
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BackgroundDetection {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Model for detected calls (default: router.background)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Largest `max_tokens` a utility call asks for
+    #[serde(default = "default_background_max_tokens")]
+    pub max_tokens: u32,
+    /// Prompt snippets marking a utility call, besides the built-in ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub system_markers: Vec<String>,
+}
+
+impl Default for BackgroundDetection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: None,
+            max_tokens: default_background_max_tokens(),
+            system_markers: Vec::new(),
+        }
+    }
+}
+
+fn default_background_max_tokens() -> u32 {
+    1024
 }
 
 /// Model configuration with 1:N provider mappings
@@ -101,6 +422,25 @@ pub struct ModelConfig {
     pub name: String,
     /// List of provider mappings with priorities (fallback support)
     pub mappings: Vec<ModelMapping>,
+    /// Streaming requests that see no token within this time move to the next
+    /// mapping (at most once per request)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttft_slo_ms: Option<u64>,
+    /// Default verbosity for requests that don't set one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verbosity: Option<Verbosity>,
+    /// Upper bound on the client's `max_tokens`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Retry refused requests on the next mapping (at most once per request)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refusal_retry: Option<RefusalRetry>,
+    /// Stream output to the client at no more than this rate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens_per_sec: Option<f64>,
+    /// Default, forced and stripped sampling parameters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampling: Option<Sampling>,
 }
 
 /// Model mapping to a specific provider
@@ -112,10 +452,161 @@ pub struct ModelMapping {
     pub provider: String,
     /// Actual model name to use with the provider
     pub actual_model: String,
+    /// Tool policy for this mapping (overrides the provider's tool_policy)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_policy: Option<ToolPolicy>,
+    /// Price per million input tokens (USD), used for usage reports
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_cost_per_mtok: Option<f64>,
+    /// Price per million output tokens (USD), used for usage reports
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_cost_per_mtok: Option<f64>,
+    /// Price per million input tokens served from the provider's prompt cache
+    /// (default: a tenth of input_cost_per_mtok)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached_input_cost_per_mtok: Option<f64>,
+    /// Send /v1/completions requests to the provider's completions endpoint
+    /// using this fill-in-the-middle format (instead of chat with prefill)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fim: Option<FimTemplate>,
+    /// Continue past the model's output limit when clients ask for more
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continuation: Option<Continuation>,
+}
+
+/// Auto-continuation for models whose output limit is below what clients request
+///
+/// `max_tokens` is capped at `max_output_tokens`; when the model stops there,
+/// the partial answer is sent back as an assistant prefill and the rounds are
+/// stitched into one response (or stream).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Continuation {
+    /// The model's output limit per request
+    pub max_output_tokens: u32,
+    /// Continuation requests per client request
+    #[serde(default = "default_max_rounds")]
+    pub max_rounds: u32,
+}
+
+fn default_max_rounds() -> u32 {
+    3
+}
+
+/// Detection of refusals that should be retried on another provider
+///
+/// A response counts as refused when its stop reason is `refusal` or the start
+/// of its text matches one of the patterns (case-insensitive regexes).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefusalRetry {
+    #[serde(default = "default_refusal_patterns")]
+    pub patterns: Vec<String>,
+    /// How much of the answer's text is checked (refusals come first)
+    #[serde(default = "default_refusal_scan_chars")]
+    pub scan_chars: usize,
+}
+
+fn default_refusal_patterns() -> Vec<String> {
+    [
+        r"^(I'm sorry|I am sorry|Sorry)[,.]? (but )?I (can't|cannot|won't|am unable to|'m unable to|am not able to)",
+        r"^I (can't|cannot|won't) (help|assist|comply|provide|do that)",
+        r"^I'm (not able|unable) to (help|assist)",
+    ]
+    .iter()
+    .map(|p| p.to_string())
+    .collect()
+}
+
+fn default_refusal_scan_chars() -> usize {
+    300
+}
+
+/// Passthrough of the Anthropic Admin API (`/v1/organizations/...`)
+///
+/// Example:
+/// ```toml
+/// [organizations]
+/// provider = "anthropic"               # default: first enabled "anthropic" provider with an API key
+/// admin_key = "$ANTHROPIC_ADMIN_KEY"   # default: the client's x-api-key
+/// merge_usage = true                   # add mux traffic to usage and cost reports
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrganizationsConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_key: Option<String>,
+    #[serde(default = "default_merge_usage")]
+    pub merge_usage: bool,
+}
+
+fn default_merge_usage() -> bool {
+    true
+}
+
+impl Default for OrganizationsConfig {
+    fn default() -> Self {
+        Self {
+            provider: None,
+            admin_key: None,
+            merge_usage: true,
+        }
+    }
+}
+
+/// Quality scoring of sampled responses by a judge model; enables the database
+///
+/// Example:
+/// ```toml
+/// [judge]
+/// enabled = true
+/// model = "judge"        # a [[models]] alias
+/// sample_rate = 0.05     # grade 5% of requests
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JudgeConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Model alias that grades responses
+    #[serde(default)]
+    pub model: String,
+    /// Share of requests to grade (0.0 - 1.0)
+    #[serde(default = "default_judge_sample_rate")]
+    pub sample_rate: f64,
+}
+
+fn default_judge_sample_rate() -> f64 {
+    0.05
+}
+
+impl Default for JudgeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: String::new(),
+            sample_rate: default_judge_sample_rate(),
+        }
+    }
 }
 
 impl ModelConfig {}
 
+impl ModelMapping {
+    /// Estimated cost in USD (None when no pricing is configured)
+    ///
+    /// `cached_input_tokens` are prompt tokens read from the provider's cache,
+    /// not included in `input_tokens`.
+    pub fn cost_usd(&self, input_tokens: u32, cached_input_tokens: u32, output_tokens: u32) -> Option<f64> {
+        if self.input_cost_per_mtok.is_none() && self.output_cost_per_mtok.is_none() {
+            return None;
+        }
+        let input_price = self.input_cost_per_mtok.unwrap_or(0.0);
+        let cached_price = self.cached_input_cost_per_mtok.unwrap_or(input_price / 10.0);
+        let input = input_price * input_tokens as f64 + cached_price * cached_input_tokens as f64;
+        let output = self.output_cost_per_mtok.unwrap_or(0.0) * output_tokens as f64;
+        Some((input + output) / 1_000_000.0)
+    }
+}
+
 impl AppConfig {
     /// Get default config file path
     /// Returns ~/.claude-code-mux/config.toml (cross-platform)
@@ -138,8 +629,13 @@ impl AppConfig {
         let content = std::fs::read_to_string(path)
             .with_context(|| format!("Failed to read config file: {}", path.display()))?;
 
-        let mut config: AppConfig = toml::from_str(&content)
-            .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+        Self::from_toml_str(&content)
+            .with_context(|| format!("Failed to parse config file: {}", path.display()))
+    }
+
+    /// Parse configuration from TOML text, resolving environment variables
+    pub fn from_toml_str(content: &str) -> Result<Self> {
+        let mut config: AppConfig = toml::from_str(content)?;
 
         // Resolve environment variables
         config.resolve_env_vars()?;
@@ -200,10 +696,68 @@ impl AppConfig {
 host = "127.0.0.1"
 port = 13456
 log_level = "info"
+# Streamed chunks buffered per response while the client catches up
+# stream_buffer_chunks = 32
+# gzip/brotli response compression (default: true)
+# compression = false
+# List lossy transformations (dropped thinking, filtered tools, ...) in an
+# x-ccm-transform-warnings response header (they are always in the request log)
+# transform_warnings_header = true
+# gRPC ingress on a second port (builds with `--features grpc`, see proto/ccm.proto)
+# grpc_port = 13457
+# Keep the last N requests with full bodies so they can be replayed
+# (POST /v1/logs/<id>/replay) and compared
+# replay_buffer = 50
+# Client key required on /v1 routes (clients send it as x-api-key or Bearer token)
+# api_key = "$CCM_API_KEY"
+
+# Optional: more client keys with expiry, for rotation; keys can also be
+# minted and revoked at runtime via POST /admin/keys and /admin/keys/revoke
+# [[server.api_keys]]
+# client = "ci"
+# key = "$CI_CCM_KEY"
+# expires_at = "2026-01-01T00:00:00Z"
+# log_bodies = false            # never capture request/response bodies for this key
+# store_sessions = false        # skip the semantic cache
+# metrics = "headers_only"      # keep only timing, status and token counts
+
+# Optional: require a bearer JWT from your identity provider on the admin API
+# and dashboard (e.g. behind oauth2-proxy with --pass-authorization-header)
+# [server.oidc]
+# issuer = "https://accounts.google.com"
+# audience = "ccm-admin"
+# allowed_subjects = ["alice@example.com"]
 
+# Defaults for providers without their own [providers.timeouts]
 [server.timeouts]
-api_timeout_ms = 600000      # 10 minutes
-connect_timeout_ms = 10000   # 10 seconds
+api_timeout_ms = 600000      # non-streaming requests: 10 minutes
+connect_timeout_ms = 10000   # connecting: 10 seconds
+
+# Optional: Request size limits (oversized requests get a 413)
+# [server.limits]
+# max_request_bytes = 33554432    # 32 MiB
+# max_image_bytes = 10485760      # 10 MiB
+# max_inflight_bytes = 268435456  # 256 MiB across concurrent requests
+# max_requests_per_session = 4    # per conversation; extra requests queue
+
+# Optional: log bodies for a share of requests only, and redact parts of them
+# (request log, trace export, archive and debug logs)
+# [server.logging]
+# body_sample_rate = 0.1          # the rest are logged without bodies
+# redact = ["images", "system"]   # also "tool_results"
+
+# Optional: log a warning for single requests/responses over these sizes
+# (totals per provider are at GET /v1/stats/payloads and /metrics)
+# [server.payload_warnings]
+# request_bytes = 1048576     # request as sent to the provider
+# response_bytes = 262144
+
+# Optional: Claude Code's event logging and feature flag calls get an empty
+# success by default; "proxy" forwards them to Anthropic with an Anthropic
+# OAuth login, "off" leaves them unrouted
+# [server.telemetry]
+# mode = "stub"
+# paths = ["/api/claude_code/settings"]   # more paths to answer
 
 [router]
 # Default model to use when no routing conditions are met
@@ -219,12 +773,137 @@ default = "placeholder-model"
 # Optional: Model for web search tasks (e.g., "glm-4.6")
 # websearch = ""
 
+# Optional: Model for /v1/completions code completion (e.g., "qwen2.5-coder")
+# completion = ""
+
 # Optional: Regex pattern for auto-mapping models (e.g., "^claude-")
 # auto_map_regex = ""
 
 # Optional: Regex pattern for detecting background tasks (e.g., "(?i)claude.*haiku")
 # background_regex = ""
 
+# Optional: Also recognize Claude Code's utility calls (titles, topic checks,
+# command prefixes) by their prompt and small max_tokens, whatever the model
+# [router.background_detection]
+# enabled = true
+# model = "glm-4.5-air"     # default: router.background
+# max_tokens = 1024
+
+# Optional: A model per Claude Code role. Clients can name the role in an
+# x-ccm-role header; otherwise subagents and the main conversation are told
+# apart by their system prompt
+# [router.roles]
+# main = "claude-sonnet"
+# plan = "claude-opus"        # default: think
+# subagent = "glm-4.6"
+# background = "glm-4.5-air"  # default: background
+
+# Optional: Truncate huge tool results (e.g. grep output) before sending upstream
+# [tool_result_truncation]
+# enabled = true
+# max_bytes = 50000    # Results larger than this are truncated
+# head_bytes = 20000   # Bytes kept from the start
+# tail_bytes = 10000   # Bytes kept from the end
+
+# Optional: Persist usage, request history and provider health (~/.claude-code-mux/ccm.db)
+# [database]
+# enabled = true
+
+# Optional: Write hourly request log files and upload them to S3 (or GCS with HMAC keys)
+# [archive]
+# enabled = true
+# provider = "s3"               # or "gcs"
+# bucket = "ccm-logs"
+# region = "eu-west-1"
+# # endpoint = "https://minio.internal:9000"   # other S3-compatible stores
+# access_key_id = "$AWS_ACCESS_KEY_ID"
+# secret_access_key = "$AWS_SECRET_ACCESS_KEY"
+# include_bodies = false        # true archives full request/response transcripts
+# retention_days = 90           # delete archived objects after this long
+
+# Optional: Anthropic Admin API passthrough (/v1/organizations/...) for usage dashboards
+# [organizations]
+# admin_key = "$ANTHROPIC_ADMIN_KEY"   # default: the client's x-api-key
+# merge_usage = true                   # add traffic to other providers to usage/cost reports
+
+# Optional: Grade a sample of responses with a judge model (GET /admin/scores); enables the database
+# [judge]
+# enabled = true
+# model = "judge"               # a [[models]] alias
+# sample_rate = 0.05            # share of requests to grade
+
+# Optional: Share failure counters, alert cooldowns and usage counters between replicas
+# [shared_state]
+# backend = "redis"             # or "memory" (default)
+# url = "redis://127.0.0.1:6379/0"
+# share_oauth_tokens = true     # share OAuth tokens and coordinate refreshes
+
+# Optional: Write a daily usage report (~/.claude-code-mux/reports); enables the database
+# [usage_report]
+# enabled = true
+# hour_utc = 0
+# webhook_url = "https://hooks.slack.com/services/..."
+# webhook_format = "slack"
+
+# Optional: Alert webhooks (json, slack or discord)
+# [alerting]
+# enabled = true
+# failure_threshold = 5          # Consecutive failures before a provider is reported down
+# auth_failure_threshold = 3
+# cooldown_secs = 300
+# [[alerting.webhooks]]
+# url = "https://hooks.slack.com/services/..."
+# format = "slack"
+
+# Optional: POST usage events (tenant, model, tokens, cost, latency) to a billing system
+# [billing]
+# enabled = true
+# url = "https://billing.internal/ingest/llm-usage"
+# headers = { Authorization = "Bearer $BILLING_TOKEN" }
+# batch_size = 50               # Events per request
+# flush_interval_secs = 10      # Send partial batches after this long
+# max_attempts = 5              # Retries use exponential backoff
+
+# Optional: Export request/response traces to Langfuse or LangSmith
+# [[trace_export]]
+# backend = "langfuse"
+# public_key = "$LANGFUSE_PUBLIC_KEY"
+# secret_key = "$LANGFUSE_SECRET_KEY"
+# [[trace_export]]
+# backend = "langsmith"
+# api_key = "$LANGSMITH_API_KEY"
+# project = "ci-agents"
+# tenants = ["ci"]              # Client names from [[server.api_keys]] / minted keys
+
+# Optional: Stop runaway agent loops (warn the model, or refuse with action = "refuse")
+# [guardrails]
+# max_repeated_tool_calls = 5   # Identical tool calls in a row
+# max_turns = 200               # Assistant turns per conversation
+# action = "warn"
+# [[guardrails.keys]]           # Overrides for one client key (x-api-key / Bearer)
+# api_key = "$CI_AGENT_KEY"
+# action = "refuse"
+
+# Optional: Serve near-duplicate requests from a cache (opt-in per model, non-streaming only)
+# [semantic_cache]
+# enabled = true
+# models = ["background"]       # Names from [[models]]
+# similarity_threshold = 0.97
+# ttl_secs = 3600
+# [semantic_cache.embeddings]
+# base_url = "http://localhost:11434/v1"   # Any OpenAI-compatible /embeddings endpoint
+# model = "nomic-embed-text"
+# api_key = "$OPENAI_API_KEY"
+
+# Optional: Prompt templates, served at POST /v1/templates/<name>/messages
+# with a body like {"variables": {"commits": "..."}}
+# [[templates]]
+# name = "release-notes"
+# model = "default"
+# system = "You write release notes for {{product}}."
+# prompt = "Summarize these commits:\n{{commits}}"
+# defaults = { product = "my-app" }
+
 # Providers configuration
 # Add providers via the web UI or edit this section
 # Example:
@@ -233,19 +912,97 @@ default = "placeholder-model"
 # provider_type = "anthropic"  # or "openai", "openrouter", etc.
 # auth_type = "api_key"        # or "oauth"
 # api_key = "your-api-key-here"
+# secondary_api_key = "$MY_PROVIDER_KEY_2"  # Optional: used once the primary key is rejected
 # enabled = true
 # models = []
+# stream_only = true           # Optional: serve non-streaming requests from the streaming API
+# synthetic_stream = true      # Optional: the reverse, for backends without streaming
+# repair_code_fences = true    # Optional: fix broken code fences from weaker models
+# idempotency_keys = true      # Optional: send Idempotency-Key, the same on every retry
+# user_id = { source = "tenant", hash = true }   # Optional: metadata.user_id from the mux client (Anthropic)
+# organization = "org-..."     # Optional: OpenAI-Organization header (multi-org keys)
+# project = "proj_..."         # Optional: OpenAI-Project header (default: the key's project)
+#
+# Optional: ping local/serverless backends so the model stays loaded
+# [providers.keep_warm]
+# interval_secs = 240
+# model = "qwen2.5-coder:7b"   # default: first entry of models
+#
+# Optional: separate timeouts per phase (connect and total default to [server.timeouts])
+# [providers.timeouts]
+# connect_ms = 5000
+# first_token_ms = 120000                  # streaming: until the first content arrives
+# total_ms = 900000                        # non-streaming: the whole request
+#
+# Optional: HTTP version and keep-alive of upstream connections
+# [providers.http]
+# version = "http2"                        # "auto" (default), "http1" or "http2" (prior knowledge)
+# keep_alive_secs = 20                     # ping idle connections so they stay warm
+# pool_idle_secs = 300                     # close unused connections after (default: 90)
+# max_concurrent = 16                      # requests in flight to the host; more wait in line
+# reserved_interactive = 4                 # slots background requests can't take (default: a quarter)
+#
+# Optional: sign requests for an enterprise gateway ("hmac" or "aws_sigv4")
+# [providers.signing]
+# type = "hmac"
+# secret = "$GATEWAY_SECRET"
+#
+# Optional: streaming workarounds for OpenAI-compatible backends behind proxies
+# [providers.stream_quirks]
+# stream_options = true                    # send stream_options.include_usage
+# accept = "text/event-stream"
+# disable_compression = true               # compressing proxies buffer SSE
+# ping_interval_secs = 10                  # ping the client while upstream is idle
+# headers = { "Cache-Control" = "no-cache" }
+#
+# Optional: User-Agent and attribution headers on every request to the provider
+# [providers.attribution]
+# user_agent = "my-gateway/1.0"            # default: claude-code-mux/<version>
+# headers = { "X-Title" = "My Team" }
+#
+# Optional: anthropic-beta flags for Anthropic-compatible providers
+# [providers.betas]
+# add = ["prompt-caching-2024-07-31"]        # always sent
+# forward = ["context-1m"]                   # client flags passed through ("*" = all)
+# strip = ["interleaved-thinking"]           # never sent
+# models = { "claude-3-7-sonnet-20250219" = ["output-128k-2025-02-19"] }
+#
+# Optional: convert images the provider doesn't accept (WebP, GIF, BMP) and
+# downscale large ones
+# [providers.images]
+# formats = ["png", "jpeg"]
+# max_dimension = 2048
+# max_bytes = 5242880
+#
+# Optional: documents (PDFs) are only taken by Anthropic's API; elsewhere they
+# become a placeholder like "[attachment omitted: report.pdf, 14 pages]"
+# [providers.unsupported_content]
+# action = "placeholder"   # skip, placeholder or error (try the next mapping)
+# blocks = ["image"]       # for text-only models
+
+# Optional: cache large system prompts and tools upstream (Gemini API key / Vertex AI, Moonshot)
+# [providers.context_cache]
+# min_tokens = 4096   # smaller prefixes are sent as-is
+# ttl_secs = 3600
+# max_caches = 8      # older caches are deleted (Gemini)
 
 # Models configuration
 # Add models via the web UI or edit this section
 # Example:
 # [[models]]
 # name = "my-model"
+# ttft_slo_ms = 8000   # Optional: streams with no token by then move to the next mapping
+# verbosity = "low"    # Optional: default verbosity (low, medium, high)
+# max_tokens = 4096    # Optional: cap on the client's max_tokens
+# refusal_retry = {}   # Optional: retry refusals once on the next mapping
+# max_output_tokens_per_sec = 60   # Optional: pace streamed output to the client
+# sampling = { force = { temperature = 1.0 }, strip = ["top_p"] }  # Optional: sampling overrides
 #
 # [[models.mappings]]
 # provider = "my-provider"
 # actual_model = "claude-sonnet-4-5"
 # priority = 1
+# continuation = { max_output_tokens = 8192 }  # Optional: continue past the model's output limit
 "#.to_string()
     }
 
@@ -259,6 +1016,27 @@ default = "placeholder-model"
             }
         }
 
+        // Resolve client keys (unset variables disable the key)
+        self.server.api_keys.retain_mut(|client_key| match client_key.key.strip_prefix('$') {
+            Some(env_var) => match std::env::var(env_var) {
+                Ok(value) => {
+                    client_key.key = value;
+                    true
+                }
+                Err(_) => false,
+            },
+            None => true,
+        });
+
+        self.billing.resolve_env_vars();
+        self.archive.resolve_env_vars();
+        if let Some(env_var) = self.organizations.admin_key.as_deref().and_then(|k| k.strip_prefix('$')) {
+            self.organizations.admin_key = std::env::var(env_var).ok();
+        }
+        for export in &mut self.trace_export {
+            export.resolve_env_vars();
+        }
+
         // Resolve provider API keys (only for enabled providers)
         for provider in &mut self.providers {
             // Skip disabled providers
@@ -277,6 +1055,33 @@ default = "placeholder-model"
                     }
                 }
             }
+            if let Some(signing) = &mut provider.signing {
+                if let Err(env_var) = signing.resolve_env_vars() {
+                    anyhow::bail!("Environment variable {} not found for provider {}", env_var, provider.name);
+                }
+            }
+            if let Some(env_var) = provider.secondary_api_key.as_deref().and_then(|k| k.strip_prefix('$')) {
+                match std::env::var(env_var) {
+                    Ok(value) => provider.secondary_api_key = Some(value),
+                    Err(_) => {
+                        anyhow::bail!("Environment variable {} not found for provider {}", env_var, provider.name)
+                    }
+                }
+            }
+        }
+
+        // Resolve per-key guardrail overrides (unset variables never match a request)
+        for key in &mut self.guardrails.keys {
+            if let Some(env_var) = key.api_key.strip_prefix('$') {
+                key.api_key = std::env::var(env_var).unwrap_or_default();
+            }
+        }
+
+        // Resolve the embeddings key of the semantic cache
+        if let Some(embeddings) = &mut self.semantic_cache.embeddings {
+            if let Some(env_var) = embeddings.api_key.as_deref().and_then(|k| k.strip_prefix('$')) {
+                embeddings.api_key = std::env::var(env_var).ok();
+            }
         }
 
         Ok(())
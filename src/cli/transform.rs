@@ -0,0 +1,70 @@
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+use super::{AppConfig, ModelMapping};
+use crate::auth::TokenStore;
+use crate::models::AnthropicRequest;
+use crate::providers::http::Attribution;
+use crate::providers::ProviderRegistry;
+use crate::router::Router;
+use crate::transform;
+
+/// Print the request a provider would receive for an Anthropic request file
+///
+/// Runs the router and the configured transformations (tool policy, history
+/// normalization, truncation, output defaults, beta flags) without sending anything.
+pub fn test(config: &AppConfig, request_path: &Path, provider_name: &str, model: Option<&str>, betas: &[String]) -> Result<()> {
+    let content = std::fs::read_to_string(request_path)
+        .with_context(|| format!("Failed to read {}", request_path.display()))?;
+    let mut request: AnthropicRequest = serde_json::from_str(&content).context("Invalid Anthropic request")?;
+
+    let decision = Router::new(config.clone()).route(&mut request)?;
+    let model_config = config.models.iter().find(|m| m.name == decision.model_name);
+    let mut mapping = match model_config.and_then(|m| m.mappings.iter().find(|m| m.provider == provider_name)) {
+        Some(mapping) => mapping.clone(),
+        None => serde_json::from_value::<ModelMapping>(serde_json::json!({
+            "priority": 1,
+            "provider": provider_name,
+            "actual_model": decision.model_name,
+        }))?,
+    };
+    if let Some(model) = model {
+        mapping.actual_model = model.to_string();
+    }
+
+    let token_store = TokenStore::default().context("Failed to open token store")?;
+    let registry = ProviderRegistry::from_configs(&config.providers, &config.server.timeouts, Some(token_store))
+        .context("Failed to initialize providers")?;
+    let Some(provider) = registry.get_provider(provider_name) else {
+        let names: Vec<&str> = config.providers.iter().map(|p| p.name.as_str()).collect();
+        bail!("Unknown or disabled provider '{}' (configured: {})", provider_name, names.join(", "));
+    };
+
+    request.model = mapping.actual_model.clone();
+    request.betas = betas.to_vec();
+    if let Err(reason) = transform::prepare(config, model_config, &mapping, provider.as_ref().as_ref(), &mut request) {
+        bail!("{} can't take this request: {}", provider_name, reason);
+    }
+    // The first round of auto-continuation asks for at most the model's limit
+    if let Some(continuation) = &mapping.continuation {
+        request.max_tokens = request.max_tokens.min(continuation.max_output_tokens);
+    }
+
+    let mut outbound = provider.preview_request(&request)?;
+    // Attribution headers are added by the provider's HTTP client
+    if let Some(provider_config) = config.providers.iter().find(|p| p.name == provider_name) {
+        for (name, value) in Attribution::for_provider(provider_config).headers() {
+            if !outbound.headers.iter().any(|(n, _)| n.eq_ignore_ascii_case(&name)) {
+                outbound.headers.push((name, value));
+            }
+        }
+    }
+    println!("# {} ({}) → {} / {}", decision.model_name, decision.route_type, provider_name, mapping.actual_model);
+    println!("POST {}", outbound.url);
+    for (name, value) in &outbound.headers {
+        println!("{}: {}", name, value);
+    }
+    println!();
+    println!("{}", serde_json::to_string_pretty(&outbound.body)?);
+    Ok(())
+}
@@ -1,13 +1,19 @@
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
+mod alerting;
 mod auth;
+mod billing;
 mod cli;
 mod models;
 mod pid;
 mod providers;
+mod reports;
 mod router;
 mod server;
+mod storage;
+mod traces;
+mod transform;
 
 #[derive(Parser)]
 #[command(name = "ccm")]
@@ -29,6 +35,12 @@ enum Commands {
         /// Port to listen on
         #[arg(short, long)]
         port: Option<u16>,
+        /// Smoke test all providers before starting; refuse to start if a model has no working provider
+        #[arg(long)]
+        verify_providers: bool,
+        /// Speak newline-delimited JSON on stdin/stdout instead of listening on a port
+        #[arg(long, conflicts_with_all = ["port", "verify_providers"])]
+        stdio: bool,
     },
     /// Stop the router service
     Stop,
@@ -40,15 +52,90 @@ enum Commands {
     Init,
     /// Manage models and providers
     Model,
+    /// Send a test request to each provider and report problems
+    Doctor,
+    /// Point Claude Code at this service (~/.claude/settings.json)
+    InitClaude {
+        /// Write ~/.claude/settings.ccm-<PROFILE>.json for `claude --settings` instead
+        #[arg(long)]
+        profile: Option<String>,
+        /// Model Claude Code requests by default (ANTHROPIC_MODEL)
+        #[arg(long)]
+        model: Option<String>,
+        /// Model for background tasks (ANTHROPIC_SMALL_FAST_MODEL)
+        #[arg(long)]
+        small_model: Option<String>,
+        /// Print the settings instead of writing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Point Codex CLI at this service's OpenAI-compatible endpoint (~/.codex/config.toml)
+    InitCodex {
+        /// Write a [profiles.<PROFILE>] entry for `codex --profile` instead
+        #[arg(long)]
+        profile: Option<String>,
+        /// Model Codex requests (defaults to the router's default model)
+        #[arg(long)]
+        model: Option<String>,
+        /// Print the config instead of writing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Compare every provider mapped to a model: TTFT, tokens/sec, cost and error rate
+    Bench {
+        /// Model name from the [[models]] section
+        model: String,
+        /// Requests per provider
+        #[arg(short = 'n', long, default_value_t = 5)]
+        runs: usize,
+    },
+    /// Show recent requests handled by the running service
+    Logs {
+        /// Follow the live request feed
+        #[arg(short, long)]
+        follow: bool,
+        /// Include full request/response bodies
+        #[arg(long)]
+        bodies: bool,
+    },
+    /// Inspect the configured request transformations
+    Transform {
+        #[command(subcommand)]
+        command: TransformCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum TransformCommand {
+    /// Print the exact request a provider would receive for an Anthropic request file
+    Test {
+        /// Anthropic Messages API request (JSON)
+        request: PathBuf,
+        /// Provider name from the [[providers]] section
+        provider: String,
+        /// Upstream model (defaults to the mapping's actual_model)
+        #[arg(long)]
+        model: Option<String>,
+        /// Client anthropic-beta flag (repeatable)
+        #[arg(long = "beta")]
+        betas: Vec<String>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    // Initialize tracing
-    tracing_subscriber::fmt::init();
-
     let cli = Cli::parse();
 
+    // Initialize tracing (stdout carries the protocol in stdio mode)
+    if matches!(cli.command, Commands::Start { stdio: true, .. }) {
+        tracing_subscriber::fmt()
+            .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+            .with_writer(std::io::stderr)
+            .init();
+    } else {
+        tracing_subscriber::fmt::init();
+    }
+
     // Get config path (use default if not specified)
     let config_path = match &cli.config {
         Some(path) => path.clone(),
@@ -60,7 +147,10 @@ async fn main() -> anyhow::Result<()> {
     let config = cli::AppConfig::from_file(&config_path)?;
 
     match cli.command {
-        Commands::Start { port } => {
+        Commands::Start { stdio: true, .. } => {
+            server::start_server(config, config_path, true).await?;
+        }
+        Commands::Start { port, verify_providers, .. } => {
             let mut config = config;
 
             // Override port if specified
@@ -68,6 +158,10 @@ async fn main() -> anyhow::Result<()> {
                 config.server.port = port;
             }
 
+            if verify_providers && !cli::doctor::run(&config).await? {
+                anyhow::bail!("Provider verification failed, not starting (run `ccm doctor` for details)");
+            }
+
             // Write PID file
             if let Err(e) = pid::write_pid() {
                 eprintln!("Warning: Failed to write PID file: {}", e);
@@ -97,7 +191,7 @@ async fn main() -> anyhow::Result<()> {
             println!("Press Ctrl+C to stop");
 
             // Cleanup PID file on exit
-            let result = server::start_server(config, config_path).await;
+            let result = server::start_server(config, config_path, false).await;
             let _ = pid::cleanup_pid();
             result?;
         }
@@ -254,6 +348,32 @@ async fn main() -> anyhow::Result<()> {
                 }
             }
         }
+        Commands::Doctor => {
+            if !cli::doctor::run(&config).await? {
+                std::process::exit(1);
+            }
+        }
+        Commands::InitClaude { profile, model, small_model, dry_run } => {
+            cli::clients::init_claude(
+                &config,
+                &cli::clients::ClientSetup { profile, model, small_model, dry_run },
+            )?;
+        }
+        Commands::InitCodex { profile, model, dry_run } => {
+            cli::clients::init_codex(
+                &config,
+                &cli::clients::ClientSetup { profile, model, small_model: None, dry_run },
+            )?;
+        }
+        Commands::Bench { model, runs } => {
+            cli::bench::run(&config, &model, runs).await?;
+        }
+        Commands::Logs { follow, bodies } => {
+            cli::logs::run(&config, follow, bodies).await?;
+        }
+        Commands::Transform { command: TransformCommand::Test { request, provider, model, betas } } => {
+            cli::transform::test(&config, &request, &provider, model.as_deref(), &betas)?;
+        }
     }
 
     Ok(())
@@ -25,6 +25,80 @@ pub struct AnthropicRequest {
     pub system: Option<SystemPrompt>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<Tool>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+    /// Beta flags from the client's `anthropic-beta` header (not part of the body)
+    #[serde(skip)]
+    pub betas: Vec<String>,
+    /// API version the client asked for (`anthropic-version`), as a date
+    #[serde(skip)]
+    pub anthropic_version: Option<String>,
+    /// Idempotency key of the client request, the same on every failover attempt
+    /// (set only for providers with `idempotency_keys`; not part of the body)
+    #[serde(skip)]
+    pub idempotency_key: Option<String>,
+    /// Requested answer length (a mux extension; sent natively only where supported)
+    #[serde(default, skip_serializing)]
+    pub verbosity: Option<Verbosity>,
+}
+
+/// How long answers should be (OpenAI's `verbosity`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verbosity {
+    Low,
+    Medium,
+    High,
+}
+
+/// Beta that streams tool input without server-side JSON buffering
+pub const FINE_GRAINED_TOOL_STREAMING: &str = "fine-grained-tool-streaming-2025-05-14";
+
+impl AnthropicRequest {
+    /// Whether the client enabled a beta (matched by feature name, ignoring the date suffix)
+    pub fn has_beta(&self, beta: &str) -> bool {
+        let feature = beta_feature(beta);
+        self.betas.iter().any(|b| beta_feature(b) == feature)
+    }
+}
+
+/// Whether two beta flags name the same feature (either may omit the date suffix)
+pub fn same_beta(a: &str, b: &str) -> bool {
+    beta_feature(a) == beta_feature(b)
+}
+
+/// `fine-grained-tool-streaming-2025-05-14` -> `fine-grained-tool-streaming`
+fn beta_feature(beta: &str) -> &str {
+    let is_date = |s: &str| s.len() == 11 && s.starts_with('-') && s[1..].split('-').all(|p| p.parse::<u16>().is_ok());
+    match beta.len().checked_sub(11).and_then(|i| Some((i, beta.get(i..)?))) {
+        Some((i, date)) if is_date(date) => &beta[..i],
+        _ => beta,
+    }
+}
+
+/// Parse `anthropic-beta` header values (comma-separated, possibly repeated)
+pub fn parse_betas<'a>(values: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut betas: Vec<String> = Vec::new();
+    for beta in values.flat_map(|v| v.split(',')).map(str::trim).filter(|b| !b.is_empty()) {
+        if !betas.iter().any(|b| b == beta) {
+            betas.push(beta.to_string());
+        }
+    }
+    betas
+}
+
+/// Date of an `anthropic-version` value
+///
+/// Platform forms (`vertex-2023-10-16`, `bedrock-2023-05-31`) are reduced to the
+/// date; values that aren't a date give `None` rather than an error.
+pub fn parse_anthropic_version(value: &str) -> Option<String> {
+    let value = value.trim();
+    let split = value.len().checked_sub(10)?;
+    let (prefix, date) = (value.get(..split)?, value.get(split..)?);
+    let parts: Vec<&str> = date.split('-').collect();
+    let is_date = parts.len() == 3
+        && parts.iter().zip([4, 2, 2]).all(|(p, len)| p.len() == len && p.bytes().all(|b| b.is_ascii_digit()));
+    (is_date && (prefix.is_empty() || prefix.ends_with('-'))).then(|| date.to_string())
 }
 
 /// Message in the conversation
@@ -120,8 +194,37 @@ pub enum ContentBlock {
     #[serde(rename = "thinking")]
     Thinking {
         thinking: String,
+        /// Must be echoed back verbatim to Anthropic; empty for other backends
+        #[serde(default, skip_serializing_if = "String::is_empty")]
         signature: String,
     },
+    /// Encrypted thinking (Anthropic safety redaction), echoed back as is
+    #[serde(rename = "redacted_thinking")]
+    RedactedThinking { data: String },
+    /// PDF or text attachment; only Anthropic's API takes these natively
+    #[serde(rename = "document")]
+    Document {
+        source: serde_json::Value,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        context: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        citations: Option<serde_json::Value>,
+    },
+    /// Call of a tool the API runs itself (Anthropic's `web_search`)
+    #[serde(rename = "server_tool_use")]
+    ServerToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    /// Results of a server-side web search (`web_search_result` items or an error)
+    #[serde(rename = "web_search_tool_result")]
+    WebSearchToolResult {
+        tool_use_id: String,
+        content: serde_json::Value,
+    },
 }
 
 /// Image source for vision API
@@ -136,6 +239,30 @@ pub struct ImageSource {
     pub url: Option<String>,
 }
 
+/// How the model may use tools
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolChoice {
+    /// The model decides
+    Auto {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        disable_parallel_tool_use: Option<bool>,
+    },
+    /// Some tool must be called
+    Any {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        disable_parallel_tool_use: Option<bool>,
+    },
+    /// This tool must be called
+    Tool {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        disable_parallel_tool_use: Option<bool>,
+    },
+    /// No tool may be called
+    None,
+}
+
 /// Tool definition for function calling
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Tool {
@@ -147,6 +274,9 @@ pub struct Tool {
     pub description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub input_schema: Option<serde_json::Value>,
+    /// Guarantee tool input matches the schema (OpenAI strict mode)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
 }
 
 /// Thinking/reasoning configuration for Plan Mode
@@ -192,19 +322,68 @@ pub struct RouteDecision {
 /// Type of routing decision
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RouteType {
+    Subagent,
     WebSearch,
     Think,
     Background,
+    Completion,
     Default,
 }
 
+/// Part a request plays in a Claude Code session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// The conversation with the user
+    Main,
+    Plan,
+    /// Work delegated through the Task tool
+    Subagent,
+    Background,
+}
+
+impl std::str::FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "main" => Ok(Role::Main),
+            "plan" => Ok(Role::Plan),
+            "subagent" => Ok(Role::Subagent),
+            "background" => Ok(Role::Background),
+            other => Err(format!("unknown role '{}'", other)),
+        }
+    }
+}
+
 impl std::fmt::Display for RouteType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            RouteType::Subagent => write!(f, "subagent"),
             RouteType::WebSearch => write!(f, "web-search"),
             RouteType::Think => write!(f, "think"),
             RouteType::Background => write!(f, "background"),
+            RouteType::Completion => write!(f, "completion"),
             RouteType::Default => write!(f, "default"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_betas_and_version() {
+        assert_eq!(
+            parse_betas(["a-2025-01-01, b", "a-2025-01-01", " "].into_iter()),
+            vec!["a-2025-01-01".to_string(), "b".to_string()]
+        );
+        assert_eq!(parse_anthropic_version("2023-06-01"), Some("2023-06-01".to_string()));
+        assert_eq!(parse_anthropic_version(" vertex-2023-10-16"), Some("2023-10-16".to_string()));
+        assert_eq!(parse_anthropic_version("bedrock-2023-05-31"), Some("2023-05-31".to_string()));
+        assert_eq!(parse_anthropic_version("latest"), None);
+        assert_eq!(parse_anthropic_version("x2023-06-01"), None);
+        assert_eq!(parse_anthropic_version("2023-6-011"), None);
+    }
+}
@@ -1,9 +1,11 @@
-use super::{AnthropicProvider, ProviderResponse, error::ProviderError};
+use super::{AnthropicProvider, OutboundRequest, ProviderResponse, REDACTED, betas::BetaConfig, error::ProviderError, http};
+use super::signing::{RequestSigning, SendSigned};
 use crate::models::{AnthropicRequest, CountTokensRequest, CountTokensResponse};
 use crate::auth::{TokenStore, OAuthClient, OAuthConfig};
 use async_trait::async_trait;
 use reqwest::Client;
 use std::pin::Pin;
+use std::sync::Arc;
 use futures::stream::Stream;
 use bytes::Bytes;
 
@@ -22,8 +24,17 @@ pub struct AnthropicCompatibleProvider {
     oauth_provider: Option<String>,
     /// Token store for OAuth authentication
     token_store: Option<TokenStore>,
+    /// Anthropic's own API: client beta flags are forwarded by default
+    native: bool,
+    /// Configured `anthropic-beta` policy
+    betas: BetaConfig,
+    /// Signature required by a gateway in front of the provider
+    signing: Option<Arc<RequestSigning>>,
 }
 
+/// Betas required for Claude subscription (OAuth) access
+const OAUTH_BETAS: &str = "oauth-2025-04-20,claude-code-20250219,interleaved-thinking-2025-05-14,fine-grained-tool-streaming-2025-05-14";
+
 impl AnthropicCompatibleProvider {
     pub fn new(
         name: String,
@@ -42,9 +53,42 @@ impl AnthropicCompatibleProvider {
             custom_headers: Vec::new(),
             oauth_provider,
             token_store,
+            native: false,
+            betas: BetaConfig::default(),
+            signing: None,
         }
     }
 
+    /// Mark as Anthropic's own API, which accepts the client's beta flags
+    pub fn native(mut self) -> Self {
+        self.native = true;
+        self
+    }
+
+    /// Use a preconfigured HTTP client (see `http::client`)
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Sign every request (`[providers.signing]`)
+    pub fn with_signing(mut self, signing: Option<RequestSigning>) -> Self {
+        self.signing = signing.map(Arc::new);
+        self
+    }
+
+    /// Apply the `[providers.betas]` policy
+    pub fn with_betas(mut self, betas: Option<BetaConfig>) -> Self {
+        self.betas = betas.unwrap_or_default();
+        self
+    }
+
+    /// `anthropic-beta` header value: OAuth betas, configured betas and the forwarded client betas
+    fn beta_header(&self, model: &str, client_betas: &[String]) -> Option<String> {
+        let builtin: Vec<&str> = if self.is_oauth() { OAUTH_BETAS.split(',').collect() } else { Vec::new() };
+        self.betas.header(&builtin, model, client_betas, self.native)
+    }
+
     /// Create with custom headers
     pub fn with_headers(
         name: String,
@@ -64,6 +108,9 @@ impl AnthropicCompatibleProvider {
             custom_headers,
             oauth_provider,
             token_store,
+            native: false,
+            betas: BetaConfig::default(),
+            signing: None,
         }
     }
 
@@ -134,15 +181,11 @@ impl AnthropicCompatibleProvider {
 
     /// Create OpenRouter provider
     pub fn openrouter(api_key: String, models: Vec<String>) -> Self {
-        Self::with_headers(
+        Self::new(
             "openrouter".to_string(),
             api_key,
             "https://openrouter.ai/api".to_string(),
             models,
-            vec![
-                ("HTTP-Referer".to_string(), "https://github.com/bahkchanhee/claude-code-mux".to_string()),
-                ("X-Title".to_string(), "Claude Code Mux".to_string()),
-            ],
             None,
             None,
         )
@@ -214,14 +257,15 @@ impl AnthropicProvider for AnthropicCompatibleProvider {
         // Set auth header based on OAuth vs API key
         if self.is_oauth() {
             // OAuth: Use Authorization Bearer token
-            req_builder = req_builder
-                .header("Authorization", format!("Bearer {}", auth_value))
-                .header("anthropic-beta", "oauth-2025-04-20,claude-code-20250219,interleaved-thinking-2025-05-14,fine-grained-tool-streaming-2025-05-14");
+            req_builder = req_builder.header("Authorization", format!("Bearer {}", auth_value));
             tracing::debug!("🔐 Using OAuth Bearer token for {}", self.name);
         } else {
             // API Key: Use x-api-key
             req_builder = req_builder.header("x-api-key", auth_value);
         }
+        if let Some(betas) = self.beta_header(&request.model, &request.betas) {
+            req_builder = req_builder.header("anthropic-beta", betas);
+        }
 
         // Add custom headers (for OpenRouter, etc.)
         for (key, value) in &self.custom_headers {
@@ -229,9 +273,9 @@ impl AnthropicProvider for AnthropicCompatibleProvider {
         }
 
         // Send request (pass-through, no transformation needed!)
-        let response = req_builder
+        let response = http::idempotency_key(req_builder, &request)
             .json(&request)
-            .send()
+            .send_signed(self.signing.as_ref())
             .await?;
 
         // Check for errors
@@ -280,16 +324,17 @@ impl AnthropicProvider for AnthropicCompatibleProvider {
 
             // Set auth header
             if self.is_oauth() {
-                req_builder = req_builder
-                    .header("Authorization", format!("Bearer {}", auth_value))
-                    .header("anthropic-beta", "oauth-2025-04-20,claude-code-20250219,interleaved-thinking-2025-05-14,fine-grained-tool-streaming-2025-05-14");
+                req_builder = req_builder.header("Authorization", format!("Bearer {}", auth_value));
             } else {
                 req_builder = req_builder.header("x-api-key", auth_value);
             }
+            if let Some(betas) = self.beta_header(&request.model, &[]) {
+                req_builder = req_builder.header("anthropic-beta", betas);
+            }
 
             let response = req_builder
                 .json(&request)
-                .send()
+                .send_signed(self.signing.as_ref())
                 .await?;
 
             if !response.status().is_success() {
@@ -343,7 +388,8 @@ impl AnthropicProvider for AnthropicCompatibleProvider {
             total_chars += content.len();
         }
 
-        let estimated_tokens = (total_chars / 4) as u32;
+        let image_tokens = super::vision::VisionFormula::Anthropic.message_image_tokens(&request.messages);
+        let estimated_tokens = (total_chars / 4) as u32 + image_tokens;
 
         Ok(CountTokensResponse {
             input_tokens: estimated_tokens,
@@ -369,13 +415,14 @@ impl AnthropicProvider for AnthropicCompatibleProvider {
 
         // Set auth header based on OAuth vs API key
         if self.is_oauth() {
-            req_builder = req_builder
-                .header("Authorization", format!("Bearer {}", auth_value))
-                .header("anthropic-beta", "oauth-2025-04-20,claude-code-20250219,interleaved-thinking-2025-05-14,fine-grained-tool-streaming-2025-05-14");
+            req_builder = req_builder.header("Authorization", format!("Bearer {}", auth_value));
             tracing::debug!("🔐 Using OAuth Bearer token for streaming on {}", self.name);
         } else {
             req_builder = req_builder.header("x-api-key", auth_value);
         }
+        if let Some(betas) = self.beta_header(&request.model, &request.betas) {
+            req_builder = req_builder.header("anthropic-beta", betas);
+        }
 
         // Add custom headers
         for (key, value) in &self.custom_headers {
@@ -383,9 +430,9 @@ impl AnthropicProvider for AnthropicCompatibleProvider {
         }
 
         // Send request with stream=true
-        let response = req_builder
+        let response = http::idempotency_key(req_builder, &request)
             .json(&request)
-            .send()
+            .send_signed(self.signing.as_ref())
             .await?;
 
         // Check for errors
@@ -412,4 +459,33 @@ impl AnthropicProvider for AnthropicCompatibleProvider {
     fn supports_model(&self, model: &str) -> bool {
         self.models.iter().any(|m| m == model)
     }
+
+    fn preview_request(&self, request: &AnthropicRequest) -> Result<OutboundRequest, ProviderError> {
+        let mut headers = vec![
+            ("anthropic-version".to_string(), "2023-06-01".to_string()),
+            ("Content-Type".to_string(), "application/json".to_string()),
+        ];
+        if self.is_oauth() {
+            headers.push(("Authorization".to_string(), format!("Bearer {}", REDACTED)));
+        } else {
+            headers.push(("x-api-key".to_string(), REDACTED.to_string()));
+        }
+        if let Some(betas) = self.beta_header(&request.model, &request.betas) {
+            headers.push(("anthropic-beta".to_string(), betas));
+        }
+        headers.extend(self.custom_headers.iter().cloned());
+        Ok(OutboundRequest {
+            url: format!("{}/v1/messages", self.base_url),
+            headers,
+            body: serde_json::to_value(request)?,
+        })
+    }
+
+    fn supports_beta(&self, beta: &str) -> bool {
+        self.betas.forwards(beta, self.native)
+    }
+
+    fn supports_documents(&self) -> bool {
+        self.native
+    }
 }
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::models::same_beta;
+
+/// Which `anthropic-beta` flags an Anthropic-compatible provider receives
+///
+/// Flags are matched by feature name, so `context-1m` matches
+/// `context-1m-2025-08-07`. Client flags the provider doesn't forward are
+/// stripped, and the mux emulates what it can (e.g. fine-grained tool streaming).
+///
+/// Example:
+/// ```toml
+/// [providers.betas]
+/// add = ["prompt-caching-2024-07-31"]
+/// forward = ["context-1m", "interleaved-thinking"]
+/// strip = ["oauth"]
+/// models = { "claude-3-7-sonnet-20250219" = ["output-128k-2025-02-19"] }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BetaConfig {
+    /// Flags attached to every request
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub add: Vec<String>,
+    /// Client flags passed through; `["*"]` for all
+    /// (default: all for Anthropic's API, none for other providers)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forward: Option<Vec<String>>,
+    /// Flags never sent, including built-in ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub strip: Vec<String>,
+    /// Extra flags per upstream model
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub models: BTreeMap<String, Vec<String>>,
+}
+
+impl BetaConfig {
+    /// Whether a client flag reaches the provider
+    pub fn forwards(&self, beta: &str, forward_by_default: bool) -> bool {
+        let forwarded = match &self.forward {
+            Some(list) => list.iter().any(|f| f == "*" || same_beta(f, beta)),
+            None => forward_by_default,
+        };
+        forwarded && !self.strip.iter().any(|s| same_beta(s, beta))
+    }
+
+    /// `anthropic-beta` value for a request to `model`
+    ///
+    /// `builtin` are flags the provider needs regardless of the client (OAuth access).
+    pub fn header(&self, builtin: &[&str], model: &str, client: &[String], forward_by_default: bool) -> Option<String> {
+        let mut betas: Vec<&str> = Vec::new();
+        let configured = self.add.iter().chain(self.models.get(model).into_iter().flatten());
+        for beta in builtin.iter().copied().chain(configured.map(String::as_str)) {
+            if !self.strip.iter().any(|s| same_beta(s, beta)) && !betas.iter().any(|b| same_beta(b, beta)) {
+                betas.push(beta);
+            }
+        }
+        for beta in client {
+            if self.forwards(beta, forward_by_default) && !betas.iter().any(|b| same_beta(b, beta)) {
+                betas.push(beta);
+            }
+        }
+        (!betas.is_empty()).then(|| betas.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header() {
+        let config: BetaConfig = toml::from_str(
+            r#"
+add = ["prompt-caching-2024-07-31"]
+forward = ["context-1m"]
+strip = ["oauth"]
+models = { "claude-3-7-sonnet" = ["output-128k-2025-02-19"] }
+"#,
+        )
+        .unwrap();
+        let client = vec![
+            "context-1m-2025-08-07".to_string(),
+            "fine-grained-tool-streaming-2025-05-14".to_string(),
+        ];
+
+        assert_eq!(
+            config.header(&["oauth-2025-04-20"], "claude-3-7-sonnet", &client, false).as_deref(),
+            Some("prompt-caching-2024-07-31,output-128k-2025-02-19,context-1m-2025-08-07")
+        );
+        assert!(!config.forwards("fine-grained-tool-streaming-2025-05-14", true));
+
+        // Defaults: Anthropic's API forwards everything, others nothing
+        let default = BetaConfig::default();
+        assert_eq!(default.header(&[], "m", &client, false), None);
+        assert_eq!(default.header(&[], "m", &client[..1], true).as_deref(), Some("context-1m-2025-08-07"));
+    }
+}
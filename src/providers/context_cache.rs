@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Explicit context caching of the stable request prefix (system prompt + tools)
+///
+/// Providers that support it upload the prefix once and reference the cache in
+/// later requests, which are then billed at the cached-token rate.
+///
+/// Example:
+/// ```toml
+/// [providers.context_cache]
+/// min_tokens = 4096
+/// ttl_secs = 3600
+/// max_caches = 8
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContextCacheConfig {
+    /// Smallest prefix (estimated tokens) worth caching; providers reject tiny caches
+    #[serde(default = "default_min_tokens")]
+    pub min_tokens: u32,
+    /// Lifetime of a created cache
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+    /// Live caches kept per provider; the least recently used is deleted
+    /// beyond this, so caches of outdated prompts stop accruing storage (Gemini)
+    #[serde(default = "default_max_caches")]
+    pub max_caches: usize,
+}
+
+fn default_min_tokens() -> u32 {
+    4096
+}
+
+fn default_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_max_caches() -> usize {
+    8
+}
+
+impl Default for ContextCacheConfig {
+    fn default() -> Self {
+        Self {
+            min_tokens: default_min_tokens(),
+            ttl_secs: default_ttl_secs(),
+            max_caches: default_max_caches(),
+        }
+    }
+}
+
+/// Cache resources a provider created upstream, keyed by prefix hash
+#[derive(Debug, Default)]
+pub struct CacheIndex {
+    entries: Mutex<HashMap<u64, CachedPrefix>>,
+}
+
+#[derive(Debug)]
+struct CachedPrefix {
+    name: String,
+    expires_at: Instant,
+    last_used: Instant,
+}
+
+/// Stop using a cache this long before the provider expires it
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+impl CacheIndex {
+    /// Name of a live cache for the prefix
+    pub fn get(&self, key: u64) -> Option<String> {
+        self.lookup(key).map(|(name, _)| name)
+    }
+
+    /// Name of a live cache for the prefix and how long it remains usable
+    pub fn lookup(&self, key: u64) -> Option<(String, Duration)> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, entry| entry.expires_at > now);
+        entries.get_mut(&key).map(|entry| {
+            entry.last_used = now;
+            (entry.name.clone(), entry.expires_at - now)
+        })
+    }
+
+    /// Remember a cache the provider created (or extended) with the given lifetime
+    pub fn insert(&self, key: u64, name: String, ttl: Duration) {
+        let now = Instant::now();
+        let expires_at = now + ttl.saturating_sub(EXPIRY_MARGIN);
+        self.entries.lock().unwrap().insert(
+            key,
+            CachedPrefix {
+                name,
+                expires_at,
+                last_used: now,
+            },
+        );
+    }
+
+    /// Forget a cache the provider no longer has
+    pub fn remove(&self, name: &str) {
+        self.entries.lock().unwrap().retain(|_, entry| entry.name != name);
+    }
+
+    /// Drop the least recently used caches beyond `max`, returning their names
+    pub fn evict(&self, max: usize) -> Vec<String> {
+        let mut entries = self.entries.lock().unwrap();
+        let mut by_use: Vec<(u64, Instant)> = entries.iter().map(|(key, entry)| (*key, entry.last_used)).collect();
+        by_use.sort_by_key(|(_, last_used)| *last_used);
+        let excess = by_use.len().saturating_sub(max);
+        by_use
+            .into_iter()
+            .take(excess)
+            .filter_map(|(key, _)| entries.remove(&key).map(|entry| entry.name))
+            .collect()
+    }
+}
+
+/// Hash identifying a prefix (model plus serialized system prompt and tools)
+pub fn prefix_key(parts: &[&str]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    parts.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Rough token count of serialized content (~4 characters per token)
+pub fn estimate_tokens(serialized: &str) -> u32 {
+    (serialized.len() / 4) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_index() {
+        let index = CacheIndex::default();
+        let key = prefix_key(&["gemini-2.5-pro", "system", "tools"]);
+        assert_ne!(key, prefix_key(&["gemini-2.5-pro", "system", "other tools"]));
+
+        index.insert(key, "cachedContents/abc".to_string(), Duration::from_secs(3600));
+        assert_eq!(index.get(key).as_deref(), Some("cachedContents/abc"));
+
+        // Entries within the expiry margin are no longer handed out
+        index.insert(key, "cachedContents/old".to_string(), Duration::from_secs(30));
+        assert_eq!(index.get(key), None);
+
+        // Outdated prompts are evicted least recently used first
+        index.insert(1, "cachedContents/a".to_string(), Duration::from_secs(3600));
+        index.insert(2, "cachedContents/b".to_string(), Duration::from_secs(3600));
+        std::thread::sleep(Duration::from_millis(2));
+        index.insert(3, "cachedContents/c".to_string(), Duration::from_secs(3600));
+        let (_, remaining) = index.lookup(1).unwrap();
+        assert!(remaining > Duration::from_secs(3500));
+        assert_eq!(index.evict(2), ["cachedContents/b"]);
+        assert_eq!(index.get(2), None);
+
+        index.remove("cachedContents/a");
+        assert_eq!(index.get(1), None);
+        assert!(index.get(3).is_some());
+    }
+}
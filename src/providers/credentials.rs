@@ -0,0 +1,191 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::Stream;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::warn;
+
+use super::error::ProviderError;
+use super::{AnthropicProvider, FimRequest, FimResponse, OutboundRequest, ProviderResponse};
+use crate::alerting::{self, AlertEvent};
+use crate::models::{AnthropicRequest, CountTokensRequest, CountTokensResponse};
+
+/// A provider with a primary and a secondary API key
+///
+/// Requests use the primary key until it is rejected (revoked, expired or out
+/// of quota); from then on the secondary key is used until the config is
+/// reloaded. The switch raises a `credential_failover` alert.
+pub struct BlueGreen {
+    name: String,
+    primary: Box<dyn AnthropicProvider>,
+    secondary: Box<dyn AnthropicProvider>,
+    on_secondary: AtomicBool,
+}
+
+impl BlueGreen {
+    pub fn new(name: String, primary: Box<dyn AnthropicProvider>, secondary: Box<dyn AnthropicProvider>) -> Self {
+        Self {
+            name,
+            primary,
+            secondary,
+            on_secondary: AtomicBool::new(false),
+        }
+    }
+
+    fn active(&self) -> &dyn AnthropicProvider {
+        if self.on_secondary.load(Ordering::Relaxed) {
+            self.secondary.as_ref()
+        } else {
+            self.primary.as_ref()
+        }
+    }
+
+    /// Switch to the secondary key if the primary's error calls for it
+    fn switches_on(&self, error: &ProviderError) -> bool {
+        if !is_credential_error(error) {
+            return false;
+        }
+        if !self.on_secondary.swap(true, Ordering::Relaxed) {
+            warn!("🔑 Primary API key of provider '{}' rejected, switching to the secondary key: {}", self.name, error);
+            alerting::notify(AlertEvent::CredentialFailover {
+                provider: self.name.clone(),
+                error: error.to_string(),
+            });
+        }
+        true
+    }
+}
+
+/// Errors meaning the key itself is unusable, rather than the request
+fn is_credential_error(error: &ProviderError) -> bool {
+    match error {
+        ProviderError::AuthError(_) => true,
+        ProviderError::ApiError { status: 401..=403, .. } => true,
+        ProviderError::ApiError { status: 429, message } => message.contains("insufficient_quota"),
+        _ => false,
+    }
+}
+
+#[async_trait]
+impl AnthropicProvider for BlueGreen {
+    async fn send_message(&self, request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
+        if !self.on_secondary.load(Ordering::Relaxed) {
+            match self.primary.send_message(request.clone()).await {
+                Err(e) if self.switches_on(&e) => {}
+                result => return result,
+            }
+        }
+        self.secondary.send_message(request).await
+    }
+
+    async fn send_message_stream(
+        &self,
+        request: AnthropicRequest,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<Bytes, ProviderError>> + Send>>, ProviderError> {
+        if !self.on_secondary.load(Ordering::Relaxed) {
+            match self.primary.send_message_stream(request.clone()).await {
+                Err(e) if self.switches_on(&e) => {}
+                result => return result,
+            }
+        }
+        self.secondary.send_message_stream(request).await
+    }
+
+    async fn count_tokens(&self, request: CountTokensRequest) -> Result<CountTokensResponse, ProviderError> {
+        if !self.on_secondary.load(Ordering::Relaxed) {
+            match self.primary.count_tokens(request.clone()).await {
+                Err(e) if self.switches_on(&e) => {}
+                result => return result,
+            }
+        }
+        self.secondary.count_tokens(request).await
+    }
+
+    fn supports_model(&self, model: &str) -> bool {
+        self.primary.supports_model(model)
+    }
+
+    fn supports_beta(&self, beta: &str) -> bool {
+        self.primary.supports_beta(beta)
+    }
+
+    fn supports_verbosity(&self) -> bool {
+        self.primary.supports_verbosity()
+    }
+
+    fn supports_documents(&self) -> bool {
+        self.primary.supports_documents()
+    }
+
+    fn preview_request(&self, request: &AnthropicRequest) -> Result<OutboundRequest, ProviderError> {
+        self.active().preview_request(request)
+    }
+
+    async fn complete_fim(&self, request: FimRequest) -> Result<FimResponse, ProviderError> {
+        if !self.on_secondary.load(Ordering::Relaxed) {
+            match self.primary.complete_fim(request.clone()).await {
+                Err(e) if self.switches_on(&e) => {}
+                result => return result,
+            }
+        }
+        self.secondary.complete_fim(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fails with the given status, or answers when there is none
+    struct Key(Option<u16>);
+
+    #[async_trait]
+    impl AnthropicProvider for Key {
+        async fn send_message(&self, request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
+            match self.0 {
+                Some(status) => Err(ProviderError::ApiError {
+                    status,
+                    message: "rejected".to_string(),
+                }),
+                None => Ok(serde_json::from_value(serde_json::json!({
+                    "id": "msg_1", "type": "message", "role": "assistant", "content": [],
+                    "model": request.model, "stop_reason": "end_turn", "stop_sequence": null,
+                    "usage": { "input_tokens": 1, "output_tokens": 1 },
+                }))?),
+            }
+        }
+
+        async fn send_message_stream(
+            &self,
+            _request: AnthropicRequest,
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<Bytes, ProviderError>> + Send>>, ProviderError> {
+            unimplemented!()
+        }
+
+        async fn count_tokens(&self, _request: CountTokensRequest) -> Result<CountTokensResponse, ProviderError> {
+            unimplemented!()
+        }
+
+        fn supports_model(&self, _model: &str) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn test_switches_to_secondary() {
+        let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "m", "max_tokens": 10,
+            "messages": [{ "role": "user", "content": "hi" }],
+        }))
+        .unwrap();
+
+        // Server errors are not the key's fault
+        let provider = BlueGreen::new("p".to_string(), Box::new(Key(Some(500))), Box::new(Key(None)));
+        assert!(provider.send_message(request.clone()).await.is_err());
+        assert!(!provider.on_secondary.load(Ordering::Relaxed));
+
+        let provider = BlueGreen::new("p".to_string(), Box::new(Key(Some(401))), Box::new(Key(None)));
+        assert!(provider.send_message(request.clone()).await.is_ok());
+        assert!(provider.on_secondary.load(Ordering::Relaxed));
+    }
+}
@@ -21,3 +21,95 @@ pub enum ProviderError {
     #[error("Authentication error: {0}")]
     AuthError(String),
 }
+
+/// Broad cause of a failed provider request, telling a bad config or request
+/// apart from a provider problem
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FailureKind {
+    /// Missing, invalid or revoked credentials
+    Auth,
+    /// Rate limits, exhausted quota or billing
+    Quota,
+    /// Blocked by the provider's safety or moderation filter
+    ContentFilter,
+    /// Request the provider rejected as malformed or unsupported
+    Schema,
+    /// Connection failures
+    Network,
+    /// No response (or no first token) in time
+    Timeout,
+    /// Other provider-side errors (5xx)
+    Upstream,
+}
+
+impl FailureKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FailureKind::Auth => "auth",
+            FailureKind::Quota => "quota",
+            FailureKind::ContentFilter => "content_filter",
+            FailureKind::Schema => "schema",
+            FailureKind::Network => "network",
+            FailureKind::Timeout => "timeout",
+            FailureKind::Upstream => "upstream",
+        }
+    }
+}
+
+const CONTENT_FILTER_MARKERS: &[&str] = &["content_filter", "content filter", "content policy", "safety", "moderation"];
+
+const QUOTA_MARKERS: &[&str] = &["quota", "rate limit", "rate_limit", "billing", "credit"];
+
+impl ProviderError {
+    pub fn failure_kind(&self) -> FailureKind {
+        match self {
+            ProviderError::AuthError(_) => FailureKind::Auth,
+            ProviderError::HttpError(e) if e.is_timeout() => FailureKind::Timeout,
+            ProviderError::HttpError(_) => FailureKind::Network,
+            ProviderError::SerializationError(_)
+            | ProviderError::ModelNotSupported(_)
+            | ProviderError::ConfigError(_) => FailureKind::Schema,
+            ProviderError::ApiError { status, message } => {
+                let message = message.to_lowercase();
+                let mentions = |markers: &[&str]| markers.iter().any(|m| message.contains(m));
+                match status {
+                    401 | 403 => FailureKind::Auth,
+                    402 | 429 => FailureKind::Quota,
+                    408 | 504 => FailureKind::Timeout,
+                    _ if mentions(CONTENT_FILTER_MARKERS) => FailureKind::ContentFilter,
+                    _ if mentions(QUOTA_MARKERS) => FailureKind::Quota,
+                    400..=499 => FailureKind::Schema,
+                    _ => FailureKind::Upstream,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_error(status: u16, message: &str) -> ProviderError {
+        ProviderError::ApiError {
+            status,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_failure_kind() {
+        assert_eq!(ProviderError::AuthError("no token".into()).failure_kind(), FailureKind::Auth);
+        assert_eq!(api_error(403, "forbidden").failure_kind(), FailureKind::Auth);
+        assert_eq!(api_error(429, "slow down").failure_kind(), FailureKind::Quota);
+        assert_eq!(api_error(400, "You exceeded your current quota").failure_kind(), FailureKind::Quota);
+        assert_eq!(
+            api_error(400, "Response blocked by SAFETY settings").failure_kind(),
+            FailureKind::ContentFilter
+        );
+        assert_eq!(api_error(422, "messages.0.content: invalid type").failure_kind(), FailureKind::Schema);
+        assert_eq!(api_error(504, "No first token within 100ms").failure_kind(), FailureKind::Timeout);
+        assert_eq!(api_error(529, "overloaded").failure_kind(), FailureKind::Upstream);
+        assert_eq!(FailureKind::ContentFilter.as_str(), "content_filter");
+    }
+}
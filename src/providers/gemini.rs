@@ -1,10 +1,15 @@
-use super::{AnthropicProvider, ProviderError, ProviderResponse, Usage};
+use super::context_cache::{estimate_tokens, prefix_key, CacheIndex, ContextCacheConfig};
+use super::signing::{RequestSigning, SendSigned};
+use super::streaming::LineBuffer;
+use super::{AnthropicProvider, OutboundRequest, ProviderError, ProviderResponse, Usage, REDACTED};
 use crate::auth::{OAuthClient, OAuthConfig, TokenStore};
-use crate::models::{AnthropicRequest, ContentBlock, MessageContent, SystemPrompt};
+use crate::models::{AnthropicRequest, ContentBlock, MessageContent, SystemPrompt, Tool, ToolChoice};
+use crate::transform::losses::{self, LossKind};
 use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
 
 /// Google Gemini provider supporting three authentication methods:
 /// 1. OAuth 2.0 (Google AI Pro/Ultra) - Uses Code Assist API
@@ -23,6 +28,21 @@ pub struct GeminiProvider {
     // OAuth fields
     pub oauth_provider_id: Option<String>,
     pub token_store: Option<TokenStore>,
+    /// Explicit caching of the system prompt and tools (not available via Code Assist)
+    context_cache: Option<ContextCacheConfig>,
+    caches: CacheIndex,
+    /// Signature required by a gateway in front of the provider
+    signing: Option<Arc<RequestSigning>>,
+}
+
+/// Anthropic's server-side search tool (`type: web_search_20250305`)
+fn is_web_search_tool(tool: &Tool) -> bool {
+    tool.r#type.as_deref().is_some_and(|t| t.starts_with("web_search"))
+}
+
+/// Whether grounding results go back to the client as web search blocks
+fn wants_web_search(request: &AnthropicRequest) -> bool {
+    request.tools.iter().flatten().any(is_web_search_tool)
 }
 
 /// Remove JSON Schema metadata fields that Gemini API doesn't support
@@ -93,9 +113,30 @@ impl GeminiProvider {
             location,
             oauth_provider_id,
             token_store,
+            context_cache: None,
+            caches: CacheIndex::default(),
+            signing: None,
         }
     }
 
+    /// Use a preconfigured HTTP client (see `http::client`)
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Sign every request (`[providers.signing]`)
+    pub fn with_signing(mut self, signing: Option<RequestSigning>) -> Self {
+        self.signing = signing.map(Arc::new);
+        self
+    }
+
+    /// Cache large system prompts and tool definitions as `cachedContents`
+    pub fn with_context_cache(mut self, config: Option<ContextCacheConfig>) -> Self {
+        self.context_cache = config;
+        self
+    }
+
     /// Check if this provider uses OAuth (Code Assist API)
     fn is_oauth(&self) -> bool {
         self.oauth_provider_id.is_some() && self.token_store.is_some()
@@ -172,8 +213,24 @@ impl GeminiProvider {
             }
         });
 
+        // functionResponse parts are matched to calls by name, not id
+        let tool_names: HashMap<&str, &str> = request
+            .messages
+            .iter()
+            .filter_map(|msg| match &msg.content {
+                MessageContent::Blocks(blocks) => Some(blocks),
+                MessageContent::Text(_) => None,
+            })
+            .flatten()
+            .filter_map(|block| match block {
+                ContentBlock::ToolUse { id, name, .. } => Some((id.as_str(), name.as_str())),
+                _ => None,
+            })
+            .collect();
+
         // Transform messages
         let mut contents = Vec::new();
+        let (mut flattened_thinking, mut dropped_blocks) = (0, 0);
         for msg in &request.messages {
             let role = match msg.role.as_str() {
                 "user" => "user",
@@ -207,6 +264,8 @@ impl GeminiProvider {
                                             data: data.clone(),
                                         },
                                     });
+                                } else {
+                                    dropped_blocks += 1;
                                 }
                             }
                             ContentBlock::Thinking { thinking, .. } => {
@@ -214,9 +273,31 @@ impl GeminiProvider {
                                 parts.push(GeminiPart::Text {
                                     text: thinking.clone(),
                                 });
+                                flattened_thinking += 1;
+                            }
+                            ContentBlock::ToolUse { name, input, .. } => {
+                                parts.push(GeminiPart::FunctionCall {
+                                    function_call: GeminiFunctionCall {
+                                        name: name.clone(),
+                                        args: input.clone(),
+                                        id: None,
+                                    },
+                                });
+                            }
+                            ContentBlock::ToolResult { tool_use_id, content } => {
+                                let name = tool_names
+                                    .get(tool_use_id.as_str())
+                                    .copied()
+                                    .unwrap_or(tool_use_id.as_str());
+                                parts.push(GeminiPart::FunctionResponse {
+                                    function_response: GeminiFunctionResponse {
+                                        name: name.to_string(),
+                                        response: serde_json::json!({ "content": content.to_string() }),
+                                    },
+                                });
                             }
                             _ => {
-                                // Skip tool use/result for now
+                                dropped_blocks += 1;
                             }
                         }
                     }
@@ -229,6 +310,12 @@ impl GeminiProvider {
                 parts,
             });
         }
+        if flattened_thinking > 0 {
+            losses::record(LossKind::ThinkingFlattened, format!("{} thinking blocks sent as text", flattened_thinking));
+        }
+        if dropped_blocks > 0 {
+            losses::record(LossKind::ContentDropped, format!("{} image or redacted thinking blocks not sent to Gemini", dropped_blocks));
+        }
 
         // Transform generation config
         let generation_config = GeminiGenerationConfig {
@@ -237,6 +324,7 @@ impl GeminiProvider {
             top_k: Some(40), // Gemini default
             max_output_tokens: Some(request.max_tokens as i32),
             stop_sequences: request.stop_sequences.clone(),
+            thinking_config: GeminiThinkingConfig::from_request(request),
         };
 
         // Transform tools if present
@@ -249,11 +337,13 @@ impl GeminiProvider {
                     let tool_name = tool.name.as_ref().map(|s| s.as_str()).unwrap_or("");
 
                     match tool_name {
-                        "WebSearch" => {
-                            // Convert to Gemini's native Google Search tool
-                            gemini_tools.push(GeminiTool::GoogleSearch {
-                                google_search: GoogleSearchTool {},
-                            });
+                        _ if is_web_search_tool(tool) || tool_name == "WebSearch" => {
+                            // Convert to Gemini's native Google Search tool (once for both)
+                            if !gemini_tools.iter().any(|t| matches!(t, GeminiTool::GoogleSearch { .. })) {
+                                gemini_tools.push(GeminiTool::GoogleSearch {
+                                    google_search: GoogleSearchTool {},
+                                });
+                            }
                         }
                         "WebFetch" => {
                             // Convert to Gemini's native URL Context tool
@@ -290,11 +380,30 @@ impl GeminiProvider {
             None // lite/flash-lite models don't support tools
         };
 
+        // Forced, required or forbidden function calls
+        let declared: Vec<&str> = tools
+            .iter()
+            .flatten()
+            .flat_map(|tool| match tool {
+                GeminiTool::FunctionDeclarations { function_declarations } => {
+                    function_declarations.iter().map(|f| f.name.as_str()).collect()
+                }
+                _ => Vec::new(),
+            })
+            .collect();
+        let tool_config = request
+            .tool_choice
+            .as_ref()
+            .filter(|_| !declared.is_empty())
+            .and_then(|choice| GeminiToolConfig::from_choice(choice, &declared));
+
         Ok(GeminiRequest {
             contents,
             system_instruction,
             generation_config: Some(generation_config),
             tools,
+            tool_config,
+            cached_content: None,
         })
     }
 
@@ -304,45 +413,68 @@ impl GeminiProvider {
         response: GeminiResponse,
         model: String,
     ) -> Result<ProviderResponse, ProviderError> {
-        let candidate = response
-            .candidates
-            .first()
-            .ok_or_else(|| ProviderError::ApiError {
-                status: 500,
-                message: "No candidates in response".to_string(),
-            })?;
+        let Some(candidate) = response.candidates.first() else {
+            let block_reason = response.prompt_feedback.as_ref().and_then(|f| f.block_reason.as_deref());
+            return Err(ProviderError::ApiError {
+                status: 502,
+                message: no_content_message(block_reason, None),
+            });
+        };
 
-        let content = candidate
+        // Empty parts or a function call Gemini couldn't produce: surface it as an
+        // error (which also lets the router fall back) instead of an empty message
+        let finish = candidate.finish_reason.as_deref();
+        let empty = candidate
             .content
             .parts
             .iter()
-            .map(|part| match part {
-                GeminiPart::Text { text } => ContentBlock::Text {
+            .all(|part| matches!(part, GeminiPart::Text { text } if text.is_empty()));
+        if finish == Some("MALFORMED_FUNCTION_CALL") || (empty && finish != Some("MAX_TOKENS")) {
+            tracing::warn!("⚠️ Gemini response without content (finishReason: {:?})", finish);
+            return Err(ProviderError::ApiError {
+                status: 502,
+                message: no_content_message(None, finish),
+            });
+        }
+
+        let mut content: Vec<ContentBlock> = candidate
+            .content
+            .parts
+            .iter()
+            .enumerate()
+            .map(|(index, part)| match part {
+                GeminiPart::Text { text } | GeminiPart::Thought { text, thought: false } => ContentBlock::Text {
                     text: text.clone(),
                 },
+                GeminiPart::Thought { text, thought: true } => ContentBlock::Thinking {
+                    thinking: text.clone(),
+                    signature: String::new(),
+                },
+                GeminiPart::FunctionCall { function_call } => function_call.to_tool_use(index),
                 _ => ContentBlock::Text {
                     text: String::new(),
                 },
             })
             .collect();
 
-        let stop_reason = match candidate.finish_reason.as_deref() {
-            Some("STOP") => Some("end_turn".to_string()),
-            Some("MAX_TOKENS") => Some("max_tokens".to_string()),
-            _ => None,
+        if let Some(note) = finish.and_then(stopped_note) {
+            tracing::warn!("⚠️ Gemini cut the response short (finishReason: {:?})", finish);
+            content.push(ContentBlock::Text { text: note });
+        }
+
+        let stop_reason = if content.iter().any(|block| matches!(block, ContentBlock::ToolUse { .. })) {
+            Some("tool_use".to_string())
+        } else {
+            finish.map(|reason| stop_reason(reason).to_string())
         };
 
+        let (input_tokens, cache_read_input_tokens) =
+            response.usage_metadata.as_ref().map_or((0, None), GeminiUsageMetadata::input_tokens);
         let usage = Usage {
-            input_tokens: response
-                .usage_metadata
-                .as_ref()
-                .and_then(|u| u.prompt_token_count)
-                .unwrap_or(0) as u32,
-            output_tokens: response
-                .usage_metadata
-                .as_ref()
-                .and_then(|u| u.candidates_token_count)
-                .unwrap_or(0) as u32,
+            input_tokens,
+            output_tokens: response.usage_metadata.as_ref().map_or(0, GeminiUsageMetadata::output_tokens),
+            cache_read_input_tokens,
+            ..Default::default()
         };
 
         Ok(ProviderResponse {
@@ -404,20 +536,272 @@ impl GeminiProvider {
             return Ok(response);
         }
     }
-}
 
-#[async_trait]
-impl AnthropicProvider for GeminiProvider {
-    async fn send_message(
+    /// Move the system instruction and tools into a `cachedContents` resource
+    ///
+    /// Only large prefixes are cached. A failure to create the cache costs the
+    /// discount, never the request. Gemini rejects `toolConfig` alongside a cache,
+    /// so requests that set one are sent as-is. Caches in use are extended once
+    /// half their TTL has passed; when the prompt changes, the new prefix gets its
+    /// own cache and the least recently used ones beyond `max_caches` are deleted.
+    async fn use_context_cache(&self, model: &str, request: &mut GeminiRequest) {
+        let Some(config) = &self.context_cache else {
+            return;
+        };
+        if self.is_oauth() || request.tool_config.is_some() {
+            return;
+        }
+        let system = serde_json::to_string(&request.system_instruction).unwrap_or_default();
+        let tools = serde_json::to_string(&request.tools).unwrap_or_default();
+        if estimate_tokens(&system) + estimate_tokens(&tools) < config.min_tokens {
+            return;
+        }
+
+        let key = prefix_key(&[model, &system, &tools]);
+        let ttl = std::time::Duration::from_secs(config.ttl_secs);
+        let name = match self.caches.lookup(key) {
+            Some((name, remaining)) => {
+                if remaining < ttl / 2 {
+                    match self.extend_cached_content(&name, config.ttl_secs).await {
+                        Ok(()) => self.caches.insert(key, name.clone(), ttl),
+                        Err(e) => tracing::warn!("⚠️ Failed to extend Gemini context cache {}: {}", name, e),
+                    }
+                }
+                name
+            }
+            None => match self.create_cached_content(model, request, config.ttl_secs).await {
+                Ok(name) => {
+                    tracing::info!("💾 Created Gemini context cache {} for {}", name, model);
+                    self.caches.insert(key, name.clone(), ttl);
+                    for evicted in self.caches.evict(config.max_caches) {
+                        self.delete_cached_content(evicted);
+                    }
+                    name
+                }
+                Err(e) => {
+                    tracing::warn!("⚠️ Failed to create Gemini context cache, sending uncached: {}", e);
+                    return;
+                }
+            },
+        };
+        request.system_instruction = None;
+        request.tools = None;
+        request.cached_content = Some(name);
+    }
+
+    /// Create a `cachedContents` resource for the request's system instruction and tools
+    async fn create_cached_content(
         &self,
-        request: AnthropicRequest,
-    ) -> Result<ProviderResponse, ProviderError> {
-        let model = request.model.clone();
+        model: &str,
+        request: &GeminiRequest,
+        ttl_secs: u64,
+    ) -> Result<String, ProviderError> {
+        let (url, model_path) = match (&self.project_id, &self.location) {
+            (Some(project_id), Some(location)) => {
+                let parent = format!("projects/{}/locations/{}", project_id, location);
+                (
+                    format!("{}/{}/cachedContents", self.base_url, parent),
+                    format!("{}/publishers/google/models/{}", parent, model),
+                )
+            }
+            _ => (
+                format!("{}/cachedContents?key={}", self.base_url, self.api_key.as_deref().unwrap_or_default()),
+                format!("models/{}", model),
+            ),
+        };
+
+        let mut body = serde_json::json!({ "model": model_path, "ttl": format!("{}s", ttl_secs) });
+        if let Some(system_instruction) = &request.system_instruction {
+            body["systemInstruction"] = serde_json::to_value(system_instruction)?;
+        }
+        if let Some(tools) = &request.tools {
+            body["tools"] = serde_json::to_value(tools)?;
+        }
+
+        let mut req_builder = self.client.post(&url).header("Content-Type", "application/json");
+        for (key, value) in &self.custom_headers {
+            req_builder = req_builder.header(key, value);
+        }
+        let response = req_builder.json(&body).send_signed(self.signing.as_ref()).await?;
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let message = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(ProviderError::ApiError { status, message });
+        }
+
+        let created: serde_json::Value = response.json().await?;
+        created["name"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| ProviderError::ApiError {
+                status: 502,
+                message: "cachedContents response without a name".to_string(),
+            })
+    }
+
+    /// URL of an existing `cachedContents` resource
+    fn cached_content_url(&self, name: &str, query: &[&str]) -> String {
+        let key = (!self.is_vertex_ai()).then(|| format!("key={}", self.api_key.as_deref().unwrap_or_default()));
+        let query: Vec<&str> = query.iter().copied().chain(key.as_deref()).collect();
+        if query.is_empty() {
+            format!("{}/{}", self.base_url, name)
+        } else {
+            format!("{}/{}?{}", self.base_url, name, query.join("&"))
+        }
+    }
+
+    /// Push a cache's expiry `ttl_secs` into the future
+    async fn extend_cached_content(&self, name: &str, ttl_secs: u64) -> Result<(), ProviderError> {
+        let mut req_builder = self
+            .client
+            .patch(self.cached_content_url(name, &["updateMask=ttl"]))
+            .header("Content-Type", "application/json");
+        for (key, value) in &self.custom_headers {
+            req_builder = req_builder.header(key, value);
+        }
+        let body = serde_json::json!({ "ttl": format!("{}s", ttl_secs) });
+        let response = req_builder.json(&body).send_signed(self.signing.as_ref()).await?;
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let message = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(ProviderError::ApiError { status, message });
+        }
+        Ok(())
+    }
+
+    /// Delete a cache in the background; it would expire on its own, but keeps
+    /// accruing storage until then
+    fn delete_cached_content(&self, name: String) {
+        let mut req_builder = self.client.delete(self.cached_content_url(&name, &[]));
+        for (key, value) in &self.custom_headers {
+            req_builder = req_builder.header(key, value);
+        }
+        let signing = self.signing.clone();
+        tokio::spawn(async move {
+            match req_builder.send_signed(signing.as_ref()).await {
+                Ok(response) if response.status().is_success() => {
+                    tracing::info!("🗑️ Deleted Gemini context cache {}", name);
+                }
+                Ok(response) => {
+                    tracing::debug!("Gemini context cache {} not deleted: {}", name, response.status());
+                }
+                Err(e) => tracing::debug!("Gemini context cache {} not deleted: {}", name, e),
+            }
+        });
+    }
+
+    /// Forget the request's cache when the error says it's gone, so the next
+    /// request creates a new one
+    fn check_cache_error(&self, cached_content: Option<&str>, error_text: &str) {
+        let Some(name) = cached_content else {
+            return;
+        };
+        let lower = error_text.to_ascii_lowercase();
+        if lower.contains("cachedcontent") || lower.contains("cached content") {
+            tracing::warn!("⚠️ Gemini context cache {} rejected, recreating on the next request", name);
+            self.caches.remove(name);
+        }
+    }
+
+    /// URL and body of a countTokens call
+    ///
+    /// The Gemini API wraps the request as `generateContentRequest` to count the
+    /// system instruction and tools too; Vertex AI takes them at the top level.
+    /// Code Assist only counts `contents`.
+    fn count_tokens_call(
+        &self,
+        model: &str,
+        gemini_request: GeminiRequest,
+    ) -> Result<(String, serde_json::Value), ProviderError> {
+        if self.is_oauth() {
+            let body = serde_json::json!({
+                "request": {
+                    "model": format!("models/{}", model),
+                    "contents": gemini_request.contents,
+                },
+            });
+            Ok((format!("{}:countTokens", self.base_url), body))
+        } else if self.is_vertex_ai() {
+            let url = format!(
+                "{}/projects/{}/locations/{}/publishers/google/models/{}:countTokens",
+                self.base_url,
+                self.project_id.as_ref().unwrap(),
+                self.location.as_ref().unwrap(),
+                model
+            );
+            let body = serde_json::json!({
+                "contents": gemini_request.contents,
+                "systemInstruction": gemini_request.system_instruction,
+                "tools": gemini_request.tools,
+            });
+            Ok((url, body))
+        } else if let Some(api_key) = &self.api_key {
+            let url = format!("{}/models/{}:countTokens?key={}", self.base_url, model, api_key);
+            let mut inner = serde_json::to_value(&gemini_request)?;
+            inner["model"] = format!("models/{}", model).into();
+            // Sampling settings don't change the count
+            if let Some(inner) = inner.as_object_mut() {
+                inner.remove("generationConfig");
+                inner.remove("toolConfig");
+            }
+            Ok((url, serde_json::json!({ "generateContentRequest": inner })))
+        } else {
+            Err(ProviderError::ConfigError(
+                "Gemini provider requires either api_key, OAuth, or Vertex AI configuration".to_string()
+            ))
+        }
+    }
+
+    /// Prompt tokens of a request, as counted by the countTokens endpoint
+    async fn count_tokens_upstream(&self, request: &AnthropicRequest) -> Result<u32, ProviderError> {
+        let mut gemini_request = self.transform_request(request)?;
+        let (url, body) = self.count_tokens_call(&request.model, gemini_request.clone())?;
+
+        let mut req_builder = self.client.post(&url).header("Content-Type", "application/json");
+        if self.is_oauth() {
+            let bearer_token = self.get_auth_header().await?.ok_or_else(|| {
+                ProviderError::AuthError("OAuth configured but no token available".to_string())
+            })?;
+            req_builder = req_builder.header("Authorization", bearer_token);
+        }
+        for (key, value) in &self.custom_headers {
+            req_builder = req_builder.header(key, value);
+        }
+
+        let response = req_builder.json(&body).send_signed(self.signing.as_ref()).await?;
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(ProviderError::ApiError {
+                status,
+                message: error_text,
+            });
+        }
+        let counted: GeminiCountTokensResponse = response.json().await?;
+
+        let mut tokens = counted.total_tokens;
+        if self.is_oauth() {
+            // Code Assist didn't see the system instruction and tools
+            gemini_request.contents.clear();
+            tokens += estimate_tokens(&serde_json::to_string(&gemini_request)?);
+        }
+        Ok(tokens)
+    }
+
+    /// Send one (non-streaming) generateContent request
+    async fn generate_content(
+        &self,
+        model: &str,
+        gemini_request: &GeminiRequest,
+    ) -> Result<GeminiResponse, ProviderError> {
+        let mut gemini_request = gemini_request.clone();
 
         // Check if using OAuth (Code Assist API)
         if self.is_oauth() {
             // Use Code Assist API endpoint
-            let gemini_request = self.transform_request(&request)?;
 
             // Get OAuth bearer token
             let auth_header = self.get_auth_header().await?;
@@ -444,7 +828,7 @@ impl AnthropicProvider for GeminiProvider {
 
             // Wrap in Code Assist API format
             let code_assist_request = CodeAssistRequest {
-                model: model.clone(),
+                model: model.to_string(),
                 project: project_id,
                 user_prompt_id: Some(user_prompt_id),
                 request: CodeAssistInnerRequest {
@@ -452,6 +836,7 @@ impl AnthropicProvider for GeminiProvider {
                     system_instruction: gemini_request.system_instruction,
                     generation_config: gemini_request.generation_config,
                     tools: gemini_request.tools,
+                    tool_config: gemini_request.tool_config,
                     session_id: None, // Optional
                 },
             };
@@ -468,6 +853,7 @@ impl AnthropicProvider for GeminiProvider {
 
             // Clone necessary data for the retry closure
             let client = self.client.clone();
+            let signing = self.signing.clone();
             let custom_headers = self.custom_headers.clone();
             let bearer_token = bearer_token.clone();
             let code_assist_request = code_assist_request.clone();
@@ -487,7 +873,7 @@ impl AnthropicProvider for GeminiProvider {
                     }
 
                     // Send request
-                    req_builder.json(&code_assist_request).send()
+                    req_builder.json(&code_assist_request).send_signed(signing.as_ref())
                 },
                 3, // max_retries
             ).await?;
@@ -526,10 +912,10 @@ impl AnthropicProvider for GeminiProvider {
 
             // Parse Code Assist response
             let code_assist_response: CodeAssistResponse = response.json().await?;
-            self.transform_response(code_assist_response.response, model)
+            Ok(code_assist_response.response)
         } else {
             // Use public Gemini API or Vertex AI
-            let gemini_request = self.transform_request(&request)?;
+            self.use_context_cache(model, &mut gemini_request).await;
 
             // Build URL
             let url = if self.is_vertex_ai() {
@@ -557,9 +943,10 @@ impl AnthropicProvider for GeminiProvider {
 
             // Clone necessary data for the retry closure
             let client = self.client.clone();
+            let signing = self.signing.clone();
             let custom_headers = self.custom_headers.clone();
-            let gemini_request = gemini_request.clone();
             let url = url.clone();
+            let cached_content = gemini_request.cached_content.clone();
 
             // Use retry handler for 429 errors
             let response = self.handle_rate_limit_retry(
@@ -572,7 +959,7 @@ impl AnthropicProvider for GeminiProvider {
                     }
 
                     // Send request
-                    req_builder.json(&gemini_request).send()
+                    req_builder.json(&gemini_request).send_signed(signing.as_ref())
                 },
                 3, // max_retries
             ).await?;
@@ -584,16 +971,43 @@ impl AnthropicProvider for GeminiProvider {
                     .await
                     .unwrap_or_else(|_| "Unknown error".to_string());
                 tracing::error!("Gemini API error ({}): {}", status, error_text);
+                self.check_cache_error(cached_content.as_deref(), &error_text);
                 return Err(ProviderError::ApiError {
                     status,
                     message: error_text,
                 });
             }
 
-            let gemini_response: GeminiResponse = response.json().await?;
-            self.transform_response(gemini_response, model)
+            Ok(response.json().await?)
         }
     }
+}
+
+#[async_trait]
+impl AnthropicProvider for GeminiProvider {
+    async fn send_message(
+        &self,
+        request: AnthropicRequest,
+    ) -> Result<ProviderResponse, ProviderError> {
+        let model = request.model.clone();
+        let mut gemini_request = self.transform_request(&request)?;
+        let mut response = self.generate_content(&model, &gemini_request).await?;
+
+        // Gemini sometimes produces a function call it can't parse; ask once more without function calling
+        if finish_reason(&response) == Some("MALFORMED_FUNCTION_CALL") && gemini_request.tools.is_some() {
+            tracing::warn!("⚠️ Gemini returned MALFORMED_FUNCTION_CALL, retrying with function calling disabled");
+            gemini_request.tool_config = Some(GeminiToolConfig::mode("NONE"));
+            response = self.generate_content(&model, &gemini_request).await?;
+        }
+
+        // Searches for Anthropic's web_search tool are reported ahead of the answer
+        let grounding = response.candidates.first_mut().and_then(|c| c.grounding_metadata.take());
+        let mut response = self.transform_response(response, model)?;
+        if let Some(blocks) = grounding.filter(|_| wants_web_search(&request)).and_then(|g| g.web_search_blocks()) {
+            response.content.splice(0..0, blocks);
+        }
+        Ok(response)
+    }
 
     async fn send_message_stream(
         &self,
@@ -602,6 +1016,7 @@ impl AnthropicProvider for GeminiProvider {
         use futures::TryStreamExt;
 
         let model = request.model.clone();
+        let web_search = wants_web_search(&request);
 
         // Check if using OAuth (Code Assist API)
         if self.is_oauth() {
@@ -641,6 +1056,7 @@ impl AnthropicProvider for GeminiProvider {
                     system_instruction: gemini_request.system_instruction,
                     generation_config: gemini_request.generation_config,
                     tools: gemini_request.tools,
+                    tool_config: gemini_request.tool_config,
                     session_id: None, // Optional
                 },
             };
@@ -662,7 +1078,7 @@ impl AnthropicProvider for GeminiProvider {
             }
 
             // Send request
-            let response = req_builder.json(&code_assist_request).send().await?;
+            let response = req_builder.json(&code_assist_request).send_signed(self.signing.as_ref()).await?;
 
             if !response.status().is_success() {
                 let status = response.status().as_u16();
@@ -677,13 +1093,14 @@ impl AnthropicProvider for GeminiProvider {
                 });
             }
 
-            // Return the streaming response
-            // The Gemini API returns SSE format, just pass through the stream
-            let stream = response.bytes_stream().map_err(|e| ProviderError::HttpError(e));
-            Ok(Box::pin(stream))
+            // Code Assist wraps each chunk as {"response": {...}, "traceId": ...};
+            // unwrap it and re-emit Anthropic SSE events
+            let stream = response.bytes_stream().map_err(ProviderError::HttpError);
+            Ok(Box::pin(transcode_stream(stream, model, StreamEnvelope::CodeAssist, web_search)))
         } else {
             // Use public Gemini API or Vertex AI streaming
-            let gemini_request = self.transform_request(&request)?;
+            let mut gemini_request = self.transform_request(&request)?;
+            self.use_context_cache(&model, &mut gemini_request).await;
 
             // Build URL
             let url = if self.is_vertex_ai() {
@@ -720,7 +1137,7 @@ impl AnthropicProvider for GeminiProvider {
             }
 
             // Send request
-            let response = req_builder.json(&gemini_request).send().await?;
+            let response = req_builder.json(&gemini_request).send_signed(self.signing.as_ref()).await?;
 
             if !response.status().is_success() {
                 let status = response.status().as_u16();
@@ -729,31 +1146,99 @@ impl AnthropicProvider for GeminiProvider {
                     .await
                     .unwrap_or_else(|_| "Unknown error".to_string());
                 tracing::error!("Gemini API streaming error ({}): {}", status, error_text);
+                self.check_cache_error(gemini_request.cached_content.as_deref(), &error_text);
                 return Err(ProviderError::ApiError {
                     status,
                     message: error_text,
                 });
             }
 
-            // Return the streaming response
-            let stream = response.bytes_stream().map_err(|e| ProviderError::HttpError(e));
-            Ok(Box::pin(stream))
+            // Re-emit the GenerateContentResponse chunks as Anthropic SSE events
+            let stream = response.bytes_stream().map_err(ProviderError::HttpError);
+            Ok(Box::pin(transcode_stream(stream, model, StreamEnvelope::Plain, web_search)))
         }
     }
 
     async fn count_tokens(
         &self,
-        _request: crate::models::CountTokensRequest,
+        request: crate::models::CountTokensRequest,
     ) -> Result<crate::models::CountTokensResponse, ProviderError> {
-        // TODO: Implement token counting for Gemini
-        Err(ProviderError::ConfigError(
-            "Token counting not yet implemented for Gemini".to_string(),
-        ))
+        let request = AnthropicRequest {
+            model: request.model,
+            messages: request.messages,
+            max_tokens: 1,
+            system: request.system,
+            tools: request.tools,
+            betas: Vec::new(),
+            tool_choice: None,
+            anthropic_version: None,
+            idempotency_key: None,
+            verbosity: None,
+            thinking: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
+            stop_sequences: None,
+            stream: None,
+            metadata: None,
+        };
+        let input_tokens = match self.count_tokens_upstream(&request).await {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                // Context tracking still works with an estimate:
+                // text at ~4 characters per token, images at Gemini's flat rate
+                tracing::warn!("⚠️ Gemini countTokens failed ({}), estimating locally", e);
+                super::vision::VisionFormula::Gemini.estimate_input_tokens(&request)
+            }
+        };
+        Ok(crate::models::CountTokensResponse { input_tokens })
     }
 
     fn supports_model(&self, model: &str) -> bool {
         self.models.contains(&model.to_string())
     }
+
+    fn preview_request(&self, request: &AnthropicRequest) -> Result<OutboundRequest, ProviderError> {
+        let gemini_request = self.transform_request(request)?;
+        let mut headers = vec![("Content-Type".to_string(), "application/json".to_string())];
+        let (url, body) = if self.is_oauth() {
+            headers.push(("Authorization".to_string(), format!("Bearer {}", REDACTED)));
+            let project = self
+                .oauth_provider_id
+                .as_ref()
+                .zip(self.token_store.as_ref())
+                .and_then(|(id, store)| store.get(id))
+                .and_then(|token| token.project_id);
+            let code_assist_request = CodeAssistRequest {
+                model: request.model.clone(),
+                project,
+                user_prompt_id: Some("gemini-<timestamp>".to_string()),
+                request: CodeAssistInnerRequest {
+                    contents: gemini_request.contents,
+                    system_instruction: gemini_request.system_instruction,
+                    generation_config: gemini_request.generation_config,
+                    tools: gemini_request.tools,
+                    tool_config: gemini_request.tool_config,
+                    session_id: None,
+                },
+            };
+            (format!("{}:generateContent", self.base_url), serde_json::to_value(&code_assist_request)?)
+        } else if self.is_vertex_ai() {
+            let url = format!(
+                "{}/projects/{}/locations/{}/publishers/google/models/{}:generateContent",
+                self.base_url,
+                self.project_id.as_deref().unwrap_or_default(),
+                self.location.as_deref().unwrap_or_default(),
+                request.model
+            );
+            (url, serde_json::to_value(&gemini_request)?)
+        } else {
+            let url = format!("{}/models/{}:generateContent?key={}", self.base_url, request.model, REDACTED);
+            (url, serde_json::to_value(&gemini_request)?)
+        };
+        headers.extend(self.custom_headers.iter().map(|(k, v)| (k.clone(), v.clone())));
+        Ok(OutboundRequest { url, headers, body })
+    }
 }
 
 // Gemini API structures
@@ -768,9 +1253,21 @@ struct GeminiRequest {
     generation_config: Option<GeminiGenerationConfig>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<Vec<GeminiTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_config: Option<GeminiToolConfig>,
+    /// `cachedContents/...` holding the system instruction and tools
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cached_content: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiCountTokensResponse {
+    #[serde(default)]
+    total_tokens: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 struct GeminiContent {
     role: String,
     parts: Vec<GeminiPart>,
@@ -779,8 +1276,54 @@ struct GeminiContent {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 enum GeminiPart {
+    /// Thought summary (`includeThoughts`); listed first so it isn't read as plain text
+    Thought { text: String, thought: bool },
     Text { text: String },
     InlineData { inline_data: GeminiInlineData },
+    FunctionCall {
+        #[serde(rename = "functionCall")]
+        function_call: GeminiFunctionCall,
+    },
+    FunctionResponse {
+        #[serde(rename = "functionResponse")]
+        function_response: GeminiFunctionResponse,
+    },
+    /// Part types we don't translate yet (kept so a response still parses)
+    Other(serde_json::Value),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GeminiFunctionCall {
+    name: String,
+    #[serde(default)]
+    args: serde_json::Value,
+    /// Only set by some models; Anthropic ids are generated otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+}
+
+impl GeminiFunctionCall {
+    /// Anthropic `tool_use` block for the call (`index`: part position, for a unique id)
+    fn to_tool_use(&self, index: usize) -> ContentBlock {
+        let id = self.id.clone().filter(|id| !id.is_empty()).unwrap_or_else(|| {
+            format!("toolu_gemini_{}_{}", chrono::Utc::now().timestamp_millis(), index)
+        });
+        let input = match &self.args {
+            serde_json::Value::Null => serde_json::json!({}),
+            args => args.clone(),
+        };
+        ContentBlock::ToolUse {
+            id,
+            name: self.name.clone(),
+            input,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GeminiFunctionResponse {
+    name: String,
+    response: serde_json::Value,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -808,6 +1351,37 @@ struct GeminiGenerationConfig {
     max_output_tokens: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking_config: Option<GeminiThinkingConfig>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiThinkingConfig {
+    thinking_budget: u32,
+    /// Return thought summaries as `thought: true` parts
+    include_thoughts: bool,
+}
+
+/// Largest thinking budget Gemini 2.5 Pro accepts
+const MAX_THINKING_BUDGET: u32 = 32768;
+/// Largest thinking budget of the Flash models
+const MAX_FLASH_THINKING_BUDGET: u32 = 24576;
+
+impl GeminiThinkingConfig {
+    /// Anthropic's `thinking: {type: "enabled", budget_tokens}` for a model
+    fn from_request(request: &AnthropicRequest) -> Option<Self> {
+        let thinking = request.thinking.as_ref().filter(|t| t.r#type == "enabled")?;
+        let max = if request.model.contains("flash") {
+            MAX_FLASH_THINKING_BUDGET
+        } else {
+            MAX_THINKING_BUDGET
+        };
+        Some(Self {
+            thinking_budget: thinking.budget_tokens.unwrap_or(max).min(max),
+            include_thoughts: true,
+        })
+    }
 }
 
 /// Gemini Tool supports multiple tool types via protobuf oneof
@@ -838,6 +1412,56 @@ struct GeminiFunctionDeclaration {
     parameters: serde_json::Value,
 }
 
+/// Controls whether and how the model calls functions
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiToolConfig {
+    function_calling_config: GeminiFunctionCallingConfig,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiFunctionCallingConfig {
+    /// AUTO, ANY or NONE
+    mode: String,
+    /// With ANY: the functions the model may call
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_function_names: Option<Vec<String>>,
+}
+
+impl GeminiToolConfig {
+    fn mode(mode: &str) -> Self {
+        Self {
+            function_calling_config: GeminiFunctionCallingConfig {
+                mode: mode.to_string(),
+                allowed_function_names: None,
+            },
+        }
+    }
+
+    /// Translate Anthropic's `tool_choice` (`declared`: function declarations sent)
+    fn from_choice(choice: &ToolChoice, declared: &[&str]) -> Option<Self> {
+        match choice {
+            ToolChoice::Auto { .. } => Some(Self::mode("AUTO")),
+            ToolChoice::Any { .. } => Some(Self::mode("ANY")),
+            ToolChoice::None => Some(Self::mode("NONE")),
+            ToolChoice::Tool { name, .. } if declared.contains(&name.as_str()) => {
+                let mut config = Self::mode("ANY");
+                config.function_calling_config.allowed_function_names = Some(vec![name.clone()]);
+                Some(config)
+            }
+            ToolChoice::Tool { name, .. } => {
+                // WebSearch/WebFetch become built-in tools, which can't be forced
+                losses::record(
+                    LossKind::FieldDropped,
+                    format!("tool_choice {} (not a function declaration on Gemini)", name),
+                );
+                None
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 struct GoogleSearchTool {}
 
@@ -847,17 +1471,141 @@ struct UrlContextTool {}
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct GeminiResponse {
+    #[serde(default)]
     candidates: Vec<GeminiCandidate>,
     #[serde(skip_serializing_if = "Option::is_none")]
     usage_metadata: Option<GeminiUsageMetadata>,
+    /// Set when the prompt itself was blocked (no candidates)
+    #[serde(default)]
+    prompt_feedback: Option<GeminiPromptFeedback>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiPromptFeedback {
+    block_reason: Option<String>,
+}
+
+/// Finish reason of the first candidate
+fn finish_reason(response: &GeminiResponse) -> Option<&str> {
+    response.candidates.first()?.finish_reason.as_deref()
+}
+
+/// Explanation for a response without usable content
+///
+/// Gemini answers a blocked prompt with no candidates at all, and
+/// MALFORMED_FUNCTION_CALL, SAFETY, RECITATION and friends with an empty candidate.
+fn no_content_message(block_reason: Option<&str>, finish_reason: Option<&str>) -> String {
+    match (block_reason, finish_reason) {
+        (Some(block), _) => format!("Gemini blocked the prompt ({})", block),
+        (None, Some(reason)) => match finish_description(reason) {
+            Some(description) => format!("Gemini returned no content: {} (finishReason: {})", description, reason),
+            None => format!("Gemini returned no content (finishReason: {})", reason),
+        },
+        (None, None) => "Gemini returned no content".to_string(),
+    }
+}
+
+/// Why Gemini stopped early, for finish reasons other than STOP and MAX_TOKENS
+fn finish_description(reason: &str) -> Option<&'static str> {
+    Some(match reason {
+        "SAFETY" => "safety filters",
+        "IMAGE_SAFETY" => "image safety filters",
+        "RECITATION" => "recitation of training data",
+        "BLOCKLIST" => "a blocked term",
+        "PROHIBITED_CONTENT" => "prohibited content",
+        "SPII" => "sensitive personal information",
+        "LANGUAGE" => "an unsupported language",
+        "MALFORMED_FUNCTION_CALL" => "a function call that couldn't be parsed",
+        "OTHER" => "an unspecified reason",
+        _ => return None,
+    })
+}
+
+/// Anthropic stop reason for a Gemini finish reason; content filters are refusals
+fn stop_reason(reason: &str) -> &'static str {
+    match reason {
+        "MAX_TOKENS" => "max_tokens",
+        "SAFETY" | "IMAGE_SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" => "refusal",
+        _ => "end_turn",
+    }
+}
+
+/// Note appended to partial content Gemini cut short, so the cut isn't silent
+fn stopped_note(reason: &str) -> Option<String> {
+    finish_description(reason).map(|description| format!("[Gemini stopped the response: {} ({})]", description, reason))
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct GeminiCandidate {
+    /// Absent on chunks that only carry the finish reason
+    #[serde(default)]
     content: GeminiContent,
     #[serde(skip_serializing_if = "Option::is_none")]
     finish_reason: Option<String>,
+    /// Searches and sources behind a `googleSearch` answer
+    #[serde(default)]
+    grounding_metadata: Option<GeminiGroundingMetadata>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiGroundingMetadata {
+    #[serde(default)]
+    web_search_queries: Vec<String>,
+    #[serde(default)]
+    grounding_chunks: Vec<GeminiGroundingChunk>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiGroundingChunk {
+    web: Option<GeminiWebSource>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiWebSource {
+    uri: Option<String>,
+    title: Option<String>,
+}
+
+impl GeminiGroundingMetadata {
+    /// `server_tool_use` and `web_search_tool_result` blocks for the searches run
+    ///
+    /// Gemini reports all queries and sources of a response together, so they
+    /// become one search call. The results carry no `encrypted_content`; they
+    /// are turned into text before going to any backend (see `transform::handoff`).
+    fn web_search_blocks(&self) -> Option<[ContentBlock; 2]> {
+        if self.web_search_queries.is_empty() && self.grounding_chunks.is_empty() {
+            return None;
+        }
+        let id = format!("srvtoolu_gemini_{}", chrono::Utc::now().timestamp_millis());
+        let results: Vec<serde_json::Value> = self
+            .grounding_chunks
+            .iter()
+            .filter_map(|chunk| chunk.web.as_ref())
+            .map(|web| {
+                serde_json::json!({
+                    "type": "web_search_result",
+                    "url": web.uri.as_deref().unwrap_or_default(),
+                    "title": web.title.as_deref().unwrap_or_default(),
+                    "encrypted_content": "",
+                    "page_age": null,
+                })
+            })
+            .collect();
+        Some([
+            ContentBlock::ServerToolUse {
+                id: id.clone(),
+                name: "web_search".to_string(),
+                input: serde_json::json!({ "query": self.web_search_queries.join(" | ") }),
+            },
+            ContentBlock::WebSearchToolResult {
+                tool_use_id: id,
+                content: results.into(),
+            },
+        ])
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -865,7 +1613,25 @@ struct GeminiCandidate {
 struct GeminiUsageMetadata {
     prompt_token_count: Option<i32>,
     candidates_token_count: Option<i32>,
+    /// Thinking tokens, billed as output but not part of candidatesTokenCount
+    thoughts_token_count: Option<i32>,
     total_token_count: Option<i32>,
+    /// Part of the prompt served from an implicit or explicit cache
+    cached_content_token_count: Option<i32>,
+}
+
+impl GeminiUsageMetadata {
+    /// Uncached prompt tokens, cached prompt tokens
+    fn input_tokens(&self) -> (u32, Option<u32>) {
+        let cached = self.cached_content_token_count.unwrap_or(0).max(0) as u32;
+        let prompt = self.prompt_token_count.unwrap_or(0).max(0) as u32;
+        (prompt.saturating_sub(cached), (cached > 0).then_some(cached))
+    }
+
+    /// Answer plus thinking tokens
+    fn output_tokens(&self) -> u32 {
+        (self.candidates_token_count.unwrap_or(0).max(0) + self.thoughts_token_count.unwrap_or(0).max(0)) as u32
+    }
 }
 
 // Code Assist API structures (for OAuth)
@@ -891,6 +1657,8 @@ struct CodeAssistInnerRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<Vec<GeminiTool>>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    tool_config: Option<GeminiToolConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     session_id: Option<String>,
 }
 
@@ -937,6 +1705,363 @@ enum GeminiErrorDetail {
     Unknown,
 }
 
+/// How each streamed chunk is wrapped
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StreamEnvelope {
+    /// Code Assist API: `{"response": <GenerateContentResponse>, "traceId": ...}`
+    CodeAssist,
+    /// Gemini API and Vertex AI: a bare `GenerateContentResponse`
+    Plain,
+}
+
+/// Incremental Gemini -> Anthropic SSE translation for one response
+struct StreamTranscoder {
+    model: String,
+    started: bool,
+    /// Index of the open text or thinking block, if any
+    open_block: Option<usize>,
+    /// Whether the open block is a thinking block
+    open_thinking: bool,
+    next_index: usize,
+    input_tokens: u32,
+    cache_read_tokens: Option<u32>,
+    output_tokens: u32,
+    stop_reason: Option<String>,
+    /// Whether a function call was emitted (stop reason `tool_use`)
+    tool_use: bool,
+    /// Upstream finishReason / blockReason, reported when the stream produced no content
+    finish_reason: Option<String>,
+    block_reason: Option<String>,
+    /// Whether the client sent Anthropic's web_search tool
+    web_search: bool,
+    /// Latest grounding metadata, emitted as web search blocks at the end
+    grounding: Option<GeminiGroundingMetadata>,
+    /// Parts held back until the end of a web_search stream, so the search
+    /// comes before the answer as in non-streaming responses
+    held: Vec<GeminiPart>,
+}
+
+impl StreamTranscoder {
+    fn new(model: String, web_search: bool) -> Self {
+        Self {
+            model,
+            started: false,
+            open_block: None,
+            open_thinking: false,
+            next_index: 0,
+            input_tokens: 0,
+            cache_read_tokens: None,
+            output_tokens: 0,
+            stop_reason: None,
+            tool_use: false,
+            finish_reason: None,
+            block_reason: None,
+            web_search,
+            grounding: None,
+            held: Vec::new(),
+        }
+    }
+
+    fn event(out: &mut String, event: &str, data: serde_json::Value) {
+        out.push_str(&format!("event: {}\ndata: {}\n\n", event, data));
+    }
+
+    /// Translate one SSE line from upstream
+    fn process_line(&mut self, line: &str, envelope: StreamEnvelope, out: &mut String) {
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+            return;
+        };
+        let parsed = match envelope {
+            StreamEnvelope::CodeAssist => {
+                serde_json::from_str::<CodeAssistResponse>(data).map(|r| r.response)
+            }
+            StreamEnvelope::Plain => serde_json::from_str::<GeminiResponse>(data),
+        };
+        match parsed {
+            Ok(chunk) => self.process_chunk(chunk, out),
+            Err(e) => tracing::warn!("Skipping unparseable Gemini stream chunk: {}", e),
+        }
+    }
+
+    fn process_chunk(&mut self, chunk: GeminiResponse, out: &mut String) {
+        if let Some(usage) = &chunk.usage_metadata {
+            (self.input_tokens, self.cache_read_tokens) = usage.input_tokens();
+            self.output_tokens = usage.output_tokens();
+        }
+
+        if !self.started {
+            self.started = true;
+            let mut usage = serde_json::json!({ "input_tokens": self.input_tokens, "output_tokens": 0 });
+            if let Some(cached) = self.cache_read_tokens {
+                usage["cache_read_input_tokens"] = cached.into();
+            }
+            Self::event(out, "message_start", serde_json::json!({
+                "type": "message_start",
+                "message": {
+                    "id": format!("gemini-{}", chrono::Utc::now().timestamp_millis()),
+                    "type": "message",
+                    "role": "assistant",
+                    "content": [],
+                    "model": self.model,
+                    "stop_reason": null,
+                    "stop_sequence": null,
+                    "usage": usage,
+                },
+            }));
+        }
+
+        if let Some(block_reason) = chunk.prompt_feedback.and_then(|f| f.block_reason) {
+            self.block_reason = Some(block_reason);
+        }
+        let Some(candidate) = chunk.candidates.into_iter().next() else {
+            return;
+        };
+        if self.web_search {
+            // Gemini reports its sources only at the end of the stream
+            if candidate.grounding_metadata.is_some() {
+                self.grounding = candidate.grounding_metadata;
+            }
+            self.held.extend(candidate.content.parts);
+        } else {
+            for part in candidate.content.parts {
+                self.part(part, out);
+            }
+        }
+
+        if let Some(reason) = candidate.finish_reason.as_deref() {
+            self.finish_reason = Some(reason.to_string());
+            self.stop_reason = Some(stop_reason(reason).to_string());
+        }
+    }
+
+    /// Translate one content part (other part kinds are skipped)
+    fn part(&mut self, part: GeminiPart, out: &mut String) {
+        match part {
+            GeminiPart::Text { text } => self.text(text, false, out),
+            GeminiPart::Thought { text, thought } => self.text(text, thought, out),
+            GeminiPart::FunctionCall { function_call } => self.function_call(&function_call, out),
+            _ => {}
+        }
+    }
+
+    /// Text or thought, appended to the open block of its kind
+    fn text(&mut self, text: String, thinking: bool, out: &mut String) {
+        if text.is_empty() {
+            return;
+        }
+        // Thoughts come before the answer; switching kinds closes the open block
+        if self.open_thinking != thinking {
+            if let Some(index) = self.open_block.take() {
+                Self::event(out, "content_block_stop", serde_json::json!({
+                    "type": "content_block_stop",
+                    "index": index,
+                }));
+            }
+        }
+        let index = match self.open_block {
+            Some(index) => index,
+            None => {
+                let index = self.next_index;
+                self.next_index += 1;
+                self.open_block = Some(index);
+                self.open_thinking = thinking;
+                let block = if thinking {
+                    serde_json::json!({ "type": "thinking", "thinking": "" })
+                } else {
+                    serde_json::json!({ "type": "text", "text": "" })
+                };
+                Self::event(out, "content_block_start", serde_json::json!({
+                    "type": "content_block_start",
+                    "index": index,
+                    "content_block": block,
+                }));
+                index
+            }
+        };
+        let delta = if thinking {
+            serde_json::json!({ "type": "thinking_delta", "thinking": text })
+        } else {
+            serde_json::json!({ "type": "text_delta", "text": text })
+        };
+        Self::event(out, "content_block_delta", serde_json::json!({
+            "type": "content_block_delta",
+            "index": index,
+            "delta": delta,
+        }));
+    }
+
+    /// A function call arrives whole: one `tool_use` block with its input in one delta
+    fn function_call(&mut self, call: &GeminiFunctionCall, out: &mut String) {
+        if let Some(index) = self.open_block.take() {
+            Self::event(out, "content_block_stop", serde_json::json!({
+                "type": "content_block_stop",
+                "index": index,
+            }));
+        }
+        let index = self.next_index;
+        self.next_index += 1;
+        self.tool_use = true;
+        let ContentBlock::ToolUse { id, name, input } = call.to_tool_use(index) else {
+            return;
+        };
+        Self::event(out, "content_block_start", serde_json::json!({
+            "type": "content_block_start",
+            "index": index,
+            "content_block": { "type": "tool_use", "id": id, "name": name, "input": {} },
+        }));
+        Self::event(out, "content_block_delta", serde_json::json!({
+            "type": "content_block_delta",
+            "index": index,
+            "delta": { "type": "input_json_delta", "partial_json": input.to_string() },
+        }));
+        Self::event(out, "content_block_stop", serde_json::json!({
+            "type": "content_block_stop",
+            "index": index,
+        }));
+    }
+
+    /// Searches Gemini ran, as `server_tool_use` and `web_search_tool_result` blocks
+    fn web_search(&mut self, blocks: [ContentBlock; 2], out: &mut String) {
+        if let Some(index) = self.open_block.take() {
+            Self::event(out, "content_block_stop", serde_json::json!({
+                "type": "content_block_stop",
+                "index": index,
+            }));
+        }
+        for block in blocks {
+            let index = self.next_index;
+            self.next_index += 1;
+            let content_block = match &block {
+                ContentBlock::ServerToolUse { id, name, .. } => {
+                    serde_json::json!({ "type": "server_tool_use", "id": id, "name": name, "input": {} })
+                }
+                block => serde_json::to_value(block).unwrap_or_default(),
+            };
+            Self::event(out, "content_block_start", serde_json::json!({
+                "type": "content_block_start",
+                "index": index,
+                "content_block": content_block,
+            }));
+            if let ContentBlock::ServerToolUse { input, .. } = &block {
+                Self::event(out, "content_block_delta", serde_json::json!({
+                    "type": "content_block_delta",
+                    "index": index,
+                    "delta": { "type": "input_json_delta", "partial_json": input.to_string() },
+                }));
+            }
+            Self::event(out, "content_block_stop", serde_json::json!({
+                "type": "content_block_stop",
+                "index": index,
+            }));
+        }
+    }
+
+    /// Close any open block and end the message
+    ///
+    /// A stream that produced no content (blocked prompt, malformed function
+    /// call, empty parts) ends with an `error` event rather than an empty message.
+    fn finish(&mut self, out: &mut String) {
+        if let Some(blocks) = self.grounding.take().and_then(|g| g.web_search_blocks()) {
+            self.web_search(blocks, out);
+        }
+        for part in std::mem::take(&mut self.held) {
+            self.part(part, out);
+        }
+        let finish = self.finish_reason.as_deref();
+        if (self.next_index == 0 && finish != Some("MAX_TOKENS")) || finish == Some("MALFORMED_FUNCTION_CALL") {
+            tracing::warn!("⚠️ Gemini stream without content (finishReason: {:?})", finish);
+            if let Some(index) = self.open_block.take() {
+                Self::event(out, "content_block_stop", serde_json::json!({
+                    "type": "content_block_stop",
+                    "index": index,
+                }));
+            }
+            Self::event(out, "error", serde_json::json!({
+                "type": "error",
+                "error": { "type": "api_error", "message": no_content_message(self.block_reason.as_deref(), finish) },
+            }));
+            return;
+        }
+        let finish = self.finish_reason.as_deref();
+        if let Some(note) = finish.and_then(stopped_note) {
+            tracing::warn!("⚠️ Gemini cut the stream short (finishReason: {:?})", finish);
+            // Continues an open text block, else gets its own
+            let note = if self.open_block.is_some() && !self.open_thinking {
+                format!("\n\n{}", note)
+            } else {
+                note
+            };
+            self.text(note, false, out);
+        }
+        if let Some(index) = self.open_block.take() {
+            Self::event(out, "content_block_stop", serde_json::json!({
+                "type": "content_block_stop",
+                "index": index,
+            }));
+        }
+        Self::event(out, "message_delta", serde_json::json!({
+            "type": "message_delta",
+            "delta": {
+                "stop_reason": if self.tool_use { "tool_use" } else { self.stop_reason.as_deref().unwrap_or("end_turn") },
+                "stop_sequence": null,
+            },
+            "usage": { "output_tokens": self.output_tokens },
+        }));
+        Self::event(out, "message_stop", serde_json::json!({ "type": "message_stop" }));
+    }
+}
+
+/// Convert a Gemini SSE byte stream into Anthropic SSE events
+fn transcode_stream<S>(
+    stream: S,
+    model: String,
+    envelope: StreamEnvelope,
+    web_search: bool,
+) -> impl futures::stream::Stream<Item = Result<bytes::Bytes, ProviderError>> + Send
+where
+    S: futures::stream::Stream<Item = Result<bytes::Bytes, ProviderError>> + Send + Unpin + 'static,
+{
+    use futures::StreamExt;
+
+    futures::stream::unfold(
+        (stream, LineBuffer::default(), StreamTranscoder::new(model, web_search), false),
+        move |(mut stream, mut buffer, mut transcoder, done)| async move {
+            if done {
+                return None;
+            }
+            loop {
+                let mut out = String::new();
+                match stream.next().await {
+                    Some(Ok(bytes)) => {
+                        buffer.push(&bytes);
+                        let Some(lines) = buffer.lines() else {
+                            continue;
+                        };
+                        for line in lines.lines() {
+                            transcoder.process_line(line, envelope, &mut out);
+                        }
+                        if out.is_empty() {
+                            continue;
+                        }
+                        return Some((Ok(bytes::Bytes::from(out)), (stream, buffer, transcoder, false)));
+                    }
+                    Some(Err(e)) => return Some((Err(e), (stream, buffer, transcoder, true))),
+                    None => {
+                        for line in buffer.rest().lines() {
+                            transcoder.process_line(line, envelope, &mut out);
+                        }
+                        transcoder.finish(&mut out);
+                        if out.is_empty() {
+                            return None;
+                        }
+                        return Some((Ok(bytes::Bytes::from(out)), (stream, buffer, transcoder, true)));
+                    }
+                }
+            }
+        },
+    )
+}
+
 /// Parse retry delay from Google's duration format (e.g., "3.020317815s", "60s", "900ms")
 fn parse_retry_delay(duration: &str) -> Option<std::time::Duration> {
     if let Some(ms_str) = duration.strip_suffix("ms") {
@@ -982,3 +2107,465 @@ fn extract_retry_delay(error_text: &str) -> Option<std::time::Duration> {
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::streaming::parse_sse_events;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_code_assist_stream_is_unwrapped() {
+        let chunks: Vec<Result<bytes::Bytes, ProviderError>> = vec![
+            Ok(bytes::Bytes::from(
+                "data: {\"response\":{\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Hel\"}]}}],",
+            )),
+            Ok(bytes::Bytes::from(
+                "\"usageMetadata\":{\"promptTokenCount\":7,\"cachedContentTokenCount\":5}},\"traceId\":\"abc\"}\n\n\
+                 data: {\"response\":{\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"lo\"}]},\"finishReason\":\"MAX_TOKENS\"}],\
+                 \"usageMetadata\":{\"promptTokenCount\":7,\"candidatesTokenCount\":2}}}\n\n",
+            )),
+        ];
+
+        let output: String = transcode_stream(
+            futures::stream::iter(chunks),
+            "gemini-2.5-pro".to_string(),
+            StreamEnvelope::CodeAssist,
+            false,
+        )
+        .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+        .collect::<Vec<_>>()
+        .await
+        .concat();
+
+        let events = parse_sse_events(&output);
+        let types: Vec<_> = events.iter().map(|e| e.event.as_deref().unwrap()).collect();
+        assert_eq!(
+            types,
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+
+        let start: serde_json::Value = serde_json::from_str(&events[0].data).unwrap();
+        assert_eq!(start["message"]["usage"]["input_tokens"], 2);
+        assert_eq!(start["message"]["usage"]["cache_read_input_tokens"], 5);
+        let delta: serde_json::Value = serde_json::from_str(&events[5].data).unwrap();
+        assert_eq!(delta["delta"]["stop_reason"], "max_tokens");
+        assert_eq!(delta["usage"]["output_tokens"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_plain_stream_is_transcoded() {
+        let chunks: Vec<Result<bytes::Bytes, ProviderError>> = vec![Ok(bytes::Bytes::from(
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Hi\"}]},\"finishReason\":\"STOP\"}],\
+             \"usageMetadata\":{\"promptTokenCount\":3,\"candidatesTokenCount\":1}}\r\n\r\n",
+        ))];
+        let output: String = transcode_stream(futures::stream::iter(chunks), "gemini-2.5-flash".to_string(), StreamEnvelope::Plain, false)
+            .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+            .collect::<Vec<_>>()
+            .await
+            .concat();
+
+        let events = parse_sse_events(&output);
+        let types: Vec<_> = events.iter().map(|e| e.event.as_deref().unwrap()).collect();
+        assert_eq!(
+            types,
+            ["message_start", "content_block_start", "content_block_delta", "content_block_stop", "message_delta", "message_stop"]
+        );
+        let delta: serde_json::Value = serde_json::from_str(&events[2].data).unwrap();
+        assert_eq!(delta["delta"]["text"], "Hi");
+        let end: serde_json::Value = serde_json::from_str(&events[4].data).unwrap();
+        assert_eq!(end["delta"]["stop_reason"], "end_turn");
+        assert_eq!(end["usage"]["output_tokens"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_function_call_round_trip() {
+        let provider = GeminiProvider::new(
+            "gemini".to_string(),
+            Some("k".to_string()),
+            None,
+            vec![],
+            HashMap::new(),
+            None,
+            None,
+            None,
+            None,
+        );
+        let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "gemini-2.5-pro",
+            "max_tokens": 64,
+            "messages": [
+                { "role": "user", "content": "List files" },
+                { "role": "assistant", "content": [
+                    { "type": "tool_use", "id": "toolu_1", "name": "Bash", "input": { "command": "ls" } },
+                ] },
+                { "role": "user", "content": [
+                    { "type": "tool_result", "tool_use_id": "toolu_1", "content": "a.rs" },
+                ] },
+            ],
+        }))
+        .unwrap();
+        let body = serde_json::to_value(provider.transform_request(&request).unwrap()).unwrap();
+        assert_eq!(
+            body["contents"][1]["parts"][0],
+            serde_json::json!({ "functionCall": { "name": "Bash", "args": { "command": "ls" } } })
+        );
+        assert_eq!(
+            body["contents"][2]["parts"][0],
+            serde_json::json!({ "functionResponse": { "name": "Bash", "response": { "content": "a.rs" } } })
+        );
+
+        let response: GeminiResponse = serde_json::from_str(
+            r#"{"candidates":[{"content":{"role":"model","parts":[{"text":"Reading"},{"functionCall":{"name":"Read","args":{"path":"a.rs"}}}]},"finishReason":"STOP"}]}"#,
+        )
+        .unwrap();
+        let response = provider.transform_response(response, "m".to_string()).unwrap();
+        assert_eq!(response.stop_reason.as_deref(), Some("tool_use"));
+        let ContentBlock::ToolUse { id, name, input } = &response.content[1] else {
+            panic!("{:?}", response.content);
+        };
+        assert!(id.starts_with("toolu_"));
+        assert_eq!((name.as_str(), &input["path"]), ("Read", &serde_json::json!("a.rs")));
+
+        // Streamed: text block closed, tool_use block with the whole input
+        let chunks: Vec<Result<bytes::Bytes, ProviderError>> = vec![Ok(bytes::Bytes::from(
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Reading\"},\
+             {\"functionCall\":{\"name\":\"Read\",\"args\":{\"path\":\"a.rs\"}}}]},\"finishReason\":\"STOP\"}]}\n\n",
+        ))];
+        let output: String = transcode_stream(futures::stream::iter(chunks), "m".to_string(), StreamEnvelope::Plain, false)
+            .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+            .collect::<Vec<_>>()
+            .await
+            .concat();
+        let events = parse_sse_events(&output);
+        let types: Vec<_> = events.iter().map(|e| e.event.as_deref().unwrap()).collect();
+        assert_eq!(
+            types,
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        let tool: serde_json::Value = serde_json::from_str(&events[5].data).unwrap();
+        assert_eq!(tool["delta"]["partial_json"], r#"{"path":"a.rs"}"#);
+        let end: serde_json::Value = serde_json::from_str(&events[7].data).unwrap();
+        assert_eq!(end["delta"]["stop_reason"], "tool_use");
+    }
+
+    #[tokio::test]
+    async fn test_thinking() {
+        let provider = GeminiProvider::new(
+            "gemini".to_string(),
+            Some("k".to_string()),
+            None,
+            vec![],
+            HashMap::new(),
+            None,
+            None,
+            None,
+            None,
+        );
+        let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "gemini-2.5-flash",
+            "max_tokens": 64,
+            "thinking": { "type": "enabled", "budget_tokens": 50000 },
+            "messages": [{ "role": "user", "content": "hi" }],
+        }))
+        .unwrap();
+        let body = serde_json::to_value(provider.transform_request(&request).unwrap()).unwrap();
+        // Clamped to the flash maximum
+        assert_eq!(
+            body["generationConfig"]["thinkingConfig"],
+            serde_json::json!({ "thinkingBudget": 24576, "includeThoughts": true })
+        );
+
+        let response: GeminiResponse = serde_json::from_str(
+            r#"{"candidates":[{"content":{"role":"model","parts":[{"text":"Plan","thought":true},{"text":"Hello"}]},"finishReason":"STOP"}],
+                "usageMetadata":{"promptTokenCount":3,"candidatesTokenCount":1,"thoughtsTokenCount":4}}"#,
+        )
+        .unwrap();
+        let response = provider.transform_response(response, "m".to_string()).unwrap();
+        assert!(matches!(&response.content[0], ContentBlock::Thinking { thinking, .. } if thinking == "Plan"));
+        assert!(matches!(&response.content[1], ContentBlock::Text { text } if text == "Hello"));
+        assert_eq!(response.usage.output_tokens, 5);
+
+        // Streamed: a thinking block, then a text block
+        let chunks: Vec<Result<bytes::Bytes, ProviderError>> = vec![Ok(bytes::Bytes::from(
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Plan\",\"thought\":true}]}}]}\n\n\
+             data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Hello\"}]},\"finishReason\":\"STOP\"}]}\n\n",
+        ))];
+        let output: String = transcode_stream(futures::stream::iter(chunks), "m".to_string(), StreamEnvelope::Plain, false)
+            .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+            .collect::<Vec<_>>()
+            .await
+            .concat();
+        let events: Vec<serde_json::Value> = parse_sse_events(&output)
+            .iter()
+            .map(|e| serde_json::from_str(&e.data).unwrap())
+            .collect();
+        assert_eq!(events[1]["content_block"]["type"], "thinking");
+        assert_eq!(events[2]["delta"], serde_json::json!({ "type": "thinking_delta", "thinking": "Plan" }));
+        assert_eq!(events[3]["type"], "content_block_stop");
+        assert_eq!((events[4]["index"].clone(), events[4]["content_block"]["type"].clone()), (serde_json::json!(1), serde_json::json!("text")));
+        assert_eq!(events[5]["delta"]["text"], "Hello");
+    }
+
+    #[tokio::test]
+    async fn test_web_search() {
+        let provider = GeminiProvider::new(
+            "gemini".to_string(),
+            Some("k".to_string()),
+            None,
+            vec![],
+            HashMap::new(),
+            None,
+            None,
+            None,
+            None,
+        );
+        let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "gemini-2.5-flash",
+            "max_tokens": 64,
+            "tools": [
+                { "type": "web_search_20250305", "name": "web_search", "max_uses": 5 },
+                { "name": "WebSearch", "input_schema": { "type": "object" } },
+            ],
+            "messages": [{ "role": "user", "content": "Rust news?" }],
+        }))
+        .unwrap();
+        assert!(wants_web_search(&request));
+        let body = serde_json::to_value(provider.transform_request(&request).unwrap()).unwrap();
+        assert_eq!(body["tools"], serde_json::json!([{ "googleSearch": {} }]));
+
+        let grounding = r#""groundingMetadata":{"webSearchQueries":["rust news"],
+            "groundingChunks":[{"web":{"uri":"https://blog.rust-lang.org","title":"rust-lang.org"}}]}"#;
+        let response: GeminiResponse = serde_json::from_str(&format!(
+            r#"{{"candidates":[{{"content":{{"role":"model","parts":[{{"text":"Rust 2.0 is out"}}]}},"finishReason":"STOP",{}}}]}}"#,
+            grounding
+        ))
+        .unwrap();
+        let [call, result] = response.candidates[0].grounding_metadata.as_ref().unwrap().web_search_blocks().unwrap();
+        let ContentBlock::ServerToolUse { id, input, .. } = call else { panic!() };
+        assert_eq!(input["query"], "rust news");
+        let ContentBlock::WebSearchToolResult { tool_use_id, content } = result else { panic!() };
+        assert_eq!(tool_use_id, id);
+        assert_eq!(content[0]["url"], "https://blog.rust-lang.org");
+
+        // Streamed: the answer is held back so the search still comes first
+        let chunks: Vec<Result<bytes::Bytes, ProviderError>> = vec![
+            Ok(bytes::Bytes::from(
+                "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Rust \"}]}}]}\n\n",
+            )),
+            Ok(bytes::Bytes::from(format!(
+                "data: {{\"candidates\":[{{\"content\":{{\"role\":\"model\",\"parts\":[{{\"text\":\"2.0\"}}]}},\"finishReason\":\"STOP\",{}}}]}}\n\n",
+                grounding.replace('\n', "")
+            ))),
+        ];
+        let output: String = transcode_stream(futures::stream::iter(chunks), "m".to_string(), StreamEnvelope::Plain, true)
+            .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+            .collect::<Vec<_>>()
+            .await
+            .concat();
+        let events: Vec<serde_json::Value> = parse_sse_events(&output)
+            .iter()
+            .map(|e| serde_json::from_str(&e.data).unwrap())
+            .collect();
+        let starts: Vec<_> = events
+            .iter()
+            .filter(|e| e["type"] == "content_block_start")
+            .map(|e| e["content_block"]["type"].as_str().unwrap())
+            .collect();
+        assert_eq!(starts, ["server_tool_use", "web_search_tool_result", "text"]);
+        let text: String = events
+            .iter()
+            .filter(|e| e["delta"]["type"] == "text_delta")
+            .map(|e| e["delta"]["text"].as_str().unwrap())
+            .collect();
+        assert_eq!(text, "Rust 2.0");
+        assert!(output.contains(r#""partial_json":"{\"query\":\"rust news\"}""#));
+    }
+
+    #[test]
+    fn test_tool_choice() {
+        let provider = GeminiProvider::new(
+            "gemini".to_string(),
+            Some("k".to_string()),
+            None,
+            vec![],
+            HashMap::new(),
+            None,
+            None,
+            None,
+            None,
+        );
+        let tool_config = |choice: serde_json::Value| {
+            let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+                "model": "gemini-2.5-pro",
+                "max_tokens": 64,
+                "messages": [{ "role": "user", "content": "hi" }],
+                "tools": [
+                    { "name": "Bash", "input_schema": { "type": "object" } },
+                    { "name": "WebSearch", "input_schema": { "type": "object" } },
+                ],
+                "tool_choice": choice,
+            }))
+            .unwrap();
+            serde_json::to_value(provider.transform_request(&request).unwrap()).unwrap()["toolConfig"]
+                ["functionCallingConfig"]
+                .clone()
+        };
+
+        assert_eq!(
+            tool_config(serde_json::json!({ "type": "tool", "name": "Bash" })),
+            serde_json::json!({ "mode": "ANY", "allowedFunctionNames": ["Bash"] })
+        );
+        assert_eq!(tool_config(serde_json::json!({ "type": "any" }))["mode"], "ANY");
+        assert_eq!(tool_config(serde_json::json!({ "type": "auto" }))["mode"], "AUTO");
+        assert_eq!(tool_config(serde_json::json!({ "type": "none" }))["mode"], "NONE");
+        // Built-in Google Search can't be forced
+        assert!(tool_config(serde_json::json!({ "type": "tool", "name": "WebSearch" })).is_null());
+    }
+
+    #[test]
+    fn test_count_tokens_call() {
+        let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "gemini-2.5-pro",
+            "max_tokens": 1,
+            "system": "Be brief",
+            "messages": [{ "role": "user", "content": "Hi" }],
+        }))
+        .unwrap();
+        let provider = |api_key: Option<&str>, project: Option<&str>| {
+            GeminiProvider::new(
+                "gemini".to_string(),
+                api_key.map(str::to_string),
+                None,
+                vec![],
+                HashMap::new(),
+                None,
+                None,
+                project.map(str::to_string),
+                project.map(|_| "us-central1".to_string()),
+            )
+        };
+
+        let api_key = provider(Some("k"), None);
+        let (url, body) = api_key
+            .count_tokens_call("gemini-2.5-pro", api_key.transform_request(&request).unwrap())
+            .unwrap();
+        assert_eq!(
+            url,
+            "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-pro:countTokens?key=k"
+        );
+        assert_eq!(body["generateContentRequest"]["model"], "models/gemini-2.5-pro");
+        assert_eq!(body["generateContentRequest"]["contents"][0]["parts"][0]["text"], "Hi");
+        assert_eq!(body["generateContentRequest"]["systemInstruction"]["parts"][0]["text"], "Be brief");
+        assert!(body["generateContentRequest"].get("generationConfig").is_none());
+
+        let vertex = provider(None, Some("proj"));
+        let (url, body) = vertex
+            .count_tokens_call("gemini-2.5-pro", vertex.transform_request(&request).unwrap())
+            .unwrap();
+        assert!(url.ends_with("/projects/proj/locations/us-central1/publishers/google/models/gemini-2.5-pro:countTokens"));
+        assert_eq!(body["contents"][0]["parts"][0]["text"], "Hi");
+        assert_eq!(body["systemInstruction"]["parts"][0]["text"], "Be brief");
+
+        // Cache resources are addressed by name
+        assert_eq!(
+            api_key.cached_content_url("cachedContents/abc", &["updateMask=ttl"]),
+            "https://generativelanguage.googleapis.com/v1beta/cachedContents/abc?updateMask=ttl&key=k"
+        );
+        let name = "projects/proj/locations/us-central1/cachedContents/abc";
+        assert!(vertex.cached_content_url(name, &[]).ends_with(&format!("/{}", name)));
+
+        let counted: GeminiCountTokensResponse =
+            serde_json::from_str(r#"{"totalTokens": 31, "totalBillableCharacters": 96}"#).unwrap();
+        assert_eq!(counted.total_tokens, 31);
+    }
+
+    #[tokio::test]
+    async fn test_responses_without_content() {
+        let provider = GeminiProvider::new(
+            "gemini".to_string(),
+            Some("k".to_string()),
+            None,
+            vec![],
+            HashMap::new(),
+            None,
+            None,
+            None,
+            None,
+        );
+        let parse = |json: &str| serde_json::from_str::<GeminiResponse>(json).unwrap();
+
+        let blocked = parse(r#"{"promptFeedback":{"blockReason":"SAFETY"}}"#);
+        let err = provider.transform_response(blocked, "m".to_string()).unwrap_err();
+        assert!(err.to_string().contains("blocked the prompt (SAFETY)"));
+
+        let malformed = parse(r#"{"candidates":[{"finishReason":"MALFORMED_FUNCTION_CALL"}]}"#);
+        assert_eq!(finish_reason(&malformed), Some("MALFORMED_FUNCTION_CALL"));
+        let err = provider.transform_response(malformed, "m".to_string()).unwrap_err();
+        assert!(err.to_string().contains("MALFORMED_FUNCTION_CALL"));
+
+        let empty = parse(r#"{"candidates":[{"content":{"role":"model","parts":[]},"finishReason":"STOP"}]}"#);
+        assert!(provider.transform_response(empty, "m".to_string()).is_err());
+
+        let recitation = parse(r#"{"candidates":[{"content":{"role":"model","parts":[]},"finishReason":"RECITATION"}]}"#);
+        let err = provider.transform_response(recitation, "m".to_string()).unwrap_err();
+        assert!(err.to_string().contains("recitation of training data"));
+
+        // Partial content cut short: kept, with a note and a refusal stop reason
+        let cut = parse(r#"{"candidates":[{"content":{"role":"model","parts":[{"text":"Sure, "}]},"finishReason":"SAFETY"}]}"#);
+        let response = provider.transform_response(cut, "m".to_string()).unwrap();
+        assert_eq!(response.stop_reason.as_deref(), Some("refusal"));
+        assert!(matches!(&response.content[1], ContentBlock::Text { text } if text.contains("safety filters (SAFETY)")));
+        let other = parse(r#"{"candidates":[{"content":{"role":"model","parts":[{"text":"Sure"}]},"finishReason":"OTHER"}]}"#);
+        let response = provider.transform_response(other, "m".to_string()).unwrap();
+        assert_eq!(response.stop_reason.as_deref(), Some("end_turn"));
+        assert_eq!(response.content.len(), 2);
+
+        // Streams end with an error event instead of an empty message
+        let chunks: Vec<Result<bytes::Bytes, ProviderError>> = vec![Ok(bytes::Bytes::from(
+            "data: {\"response\":{\"candidates\":[{\"finishReason\":\"MALFORMED_FUNCTION_CALL\"}]}}\n\n",
+        ))];
+        let output: String = transcode_stream(futures::stream::iter(chunks), "m".to_string(), StreamEnvelope::CodeAssist, false)
+            .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+            .collect::<Vec<_>>()
+            .await
+            .concat();
+        let types: Vec<_> = parse_sse_events(&output).into_iter().filter_map(|e| e.event).collect();
+        assert_eq!(types, ["message_start", "error"]);
+
+        // A stream cut short gets the note in its text block
+        let chunks: Vec<Result<bytes::Bytes, ProviderError>> = vec![Ok(bytes::Bytes::from(
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Sure, \"}]},\"finishReason\":\"SAFETY\"}]}\n\n",
+        ))];
+        let output: String = transcode_stream(futures::stream::iter(chunks), "m".to_string(), StreamEnvelope::Plain, false)
+            .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+            .collect::<Vec<_>>()
+            .await
+            .concat();
+        let events: Vec<serde_json::Value> = parse_sse_events(&output)
+            .iter()
+            .map(|e| serde_json::from_str(&e.data).unwrap())
+            .collect();
+        assert_eq!(events.len(), 7);
+        assert!(events[3]["delta"]["text"].as_str().unwrap().starts_with("\n\n[Gemini stopped the response"));
+        assert_eq!(events[5]["delta"]["stop_reason"], "refusal");
+    }
+}
@@ -0,0 +1,261 @@
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use super::ProviderConfig;
+use crate::cli::TimeoutConfig;
+use crate::models::AnthropicRequest;
+
+/// `User-Agent` sent to providers unless configured otherwise
+pub const DEFAULT_USER_AGENT: &str = concat!("claude-code-mux/", env!("CARGO_PKG_VERSION"));
+
+/// Identification headers sent with every request to a provider
+///
+/// Built-in attribution for the provider type (e.g. OpenRouter's app headers)
+/// is merged with `[providers.attribution]`, which wins on conflicts.
+///
+/// Example:
+/// ```toml
+/// [providers.attribution]
+/// user_agent = "my-gateway/1.0"
+/// headers = { "X-Title" = "My Team" }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Attribution {
+    /// `User-Agent` (default: `claude-code-mux/<version>`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Attribution {
+    /// Headers a provider type asks integrations to send
+    fn builtin(provider_type: &str) -> Self {
+        let headers: &[(&str, &str)] = match provider_type {
+            // App attribution for OpenRouter's rankings
+            "openrouter" => &[
+                ("HTTP-Referer", "https://github.com/bahkchanhee/claude-code-mux"),
+                ("X-Title", "Claude Code Mux"),
+            ],
+            "novita" => &[("X-Novita-Source", "claude-code-mux")],
+            _ => &[],
+        };
+        Self {
+            user_agent: None,
+            headers: headers.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+        }
+    }
+
+    /// Effective attribution of a provider
+    pub fn for_provider(config: &ProviderConfig) -> Self {
+        let mut attribution = Self::builtin(&config.provider_type);
+        if let Some(configured) = &config.attribution {
+            if configured.user_agent.is_some() {
+                attribution.user_agent = configured.user_agent.clone();
+            }
+            attribution.headers.extend(configured.headers.clone());
+        }
+        attribution
+    }
+
+    /// All headers, `User-Agent` first
+    pub fn headers(&self) -> Vec<(String, String)> {
+        let user_agent = self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT);
+        std::iter::once(("User-Agent".to_string(), user_agent.to_string()))
+            .chain(self.headers.iter().map(|(name, value)| (name.clone(), value.clone())))
+            .collect()
+    }
+}
+
+/// HTTP protocol version used with a provider
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpVersion {
+    /// HTTP/2 when the server offers it during the TLS handshake, else HTTP/1.1
+    #[default]
+    Auto,
+    /// HTTP/1.1 only, for gateways that mishandle HTTP/2
+    Http1,
+    /// HTTP/2 without negotiation (prior knowledge)
+    Http2,
+}
+
+/// Connection settings of a provider's HTTP client
+///
+/// Example:
+/// ```toml
+/// [providers.http]
+/// version = "http2"       # "auto" (default), "http1" or "http2"
+/// keep_alive_secs = 20    # ping idle HTTP/2 connections so they stay open
+/// pool_idle_secs = 300    # how long unused connections are kept
+/// max_concurrent = 16     # requests in flight to the host at once
+/// reserved_interactive = 4
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HttpSettings {
+    #[serde(default)]
+    pub version: HttpVersion,
+    /// HTTP/2 PING (and TCP keep-alive) interval, also while idle
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_alive_secs: Option<u64>,
+    /// Idle connections are closed after this long (default 90s)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool_idle_secs: Option<u64>,
+    /// Requests in flight to the provider's host at once (default: unlimited);
+    /// more wait in line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent: Option<usize>,
+    /// Slots of `max_concurrent` that background requests can't take
+    /// (default: a quarter)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserved_interactive: Option<usize>,
+}
+
+impl HttpSettings {
+    fn apply(&self, mut builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        builder = match self.version {
+            HttpVersion::Auto => builder,
+            HttpVersion::Http1 => builder.http1_only(),
+            HttpVersion::Http2 => builder.http2_prior_knowledge(),
+        };
+        if let Some(secs) = self.keep_alive_secs {
+            let interval = Duration::from_secs(secs);
+            builder = builder
+                .tcp_keepalive(interval)
+                .http2_keep_alive_interval(interval)
+                .http2_keep_alive_timeout(interval)
+                .http2_keep_alive_while_idle(true);
+        }
+        if let Some(secs) = self.pool_idle_secs {
+            builder = builder.pool_idle_timeout(Duration::from_secs(secs));
+        }
+        builder
+    }
+
+    /// Slots kept for interactive requests
+    pub fn reserved(&self, max_concurrent: usize) -> usize {
+        self.reserved_interactive
+            .unwrap_or_else(|| max_concurrent.div_ceil(4))
+            .min(max_concurrent.saturating_sub(1))
+    }
+}
+
+/// Header carrying a request's idempotency key
+pub const IDEMPOTENCY_HEADER: &str = "Idempotency-Key";
+
+/// Attach the request's idempotency key, if it has one
+pub fn idempotency_key(builder: RequestBuilder, request: &AnthropicRequest) -> RequestBuilder {
+    match &request.idempotency_key {
+        Some(key) => builder.header(IDEMPOTENCY_HEADER, key),
+        None => builder,
+    }
+}
+
+/// `OpenAI-Organization` and `OpenAI-Project` headers of the configured account
+pub fn account_headers(config: &ProviderConfig) -> Vec<(String, String)> {
+    [("OpenAI-Organization", &config.organization), ("OpenAI-Project", &config.project)]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.clone()?)))
+        .collect()
+}
+
+/// HTTP client for one provider
+///
+/// Every request it sends carries the provider's attribution headers and OpenAI
+/// organization/project (request code can still override them), compression follows its stream quirks and
+/// connections follow its `[providers.http]` settings.
+pub fn client(config: &ProviderConfig, defaults: &TimeoutConfig) -> Client {
+    let mut default_headers = HeaderMap::new();
+    for (name, value) in Attribution::for_provider(config).headers().into_iter().chain(account_headers(config)) {
+        match (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(&value)) {
+            (Ok(name), Ok(value)) => {
+                default_headers.insert(name, value);
+            }
+            _ => tracing::warn!("Ignoring invalid header '{}' for provider '{}'", name, config.name),
+        }
+    }
+
+    let connect_timeout = config.timeouts.clone().unwrap_or_default().with_defaults(defaults).connect();
+    let mut builder = Client::builder()
+        .default_headers(default_headers)
+        .connect_timeout(connect_timeout);
+    if config.stream_quirks.as_ref().is_some_and(|q| q.disable_compression) {
+        builder = builder.no_gzip().no_brotli();
+    }
+    if let Some(http) = &config.http {
+        builder = http.apply(builder);
+    }
+    builder.build().unwrap_or_else(|e| {
+        tracing::warn!("Failed to build HTTP client for provider '{}': {}", config.name, e);
+        Client::new()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attribution() {
+        let config: ProviderConfig = toml::from_str(
+            r#"
+name = "openrouter"
+provider_type = "openrouter"
+api_key = "k"
+models = []
+
+[attribution]
+user_agent = "my-gateway/1.0"
+headers = { "X-Title" = "My Team" }
+"#,
+        )
+        .unwrap();
+        let headers = Attribution::for_provider(&config).headers();
+        assert_eq!(
+            headers,
+            [
+                ("User-Agent".to_string(), "my-gateway/1.0".to_string()),
+                ("HTTP-Referer".to_string(), "https://github.com/bahkchanhee/claude-code-mux".to_string()),
+                ("X-Title".to_string(), "My Team".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_http_settings() {
+        let settings: HttpSettings = toml::from_str("version = \"http1\"\nkeep_alive_secs = 20").unwrap();
+        assert_eq!(settings.version, HttpVersion::Http1);
+        assert_eq!(settings.pool_idle_secs, None);
+        assert!(toml::from_str::<HttpSettings>("version = \"h3\"").is_err());
+
+        let builder = HttpSettings {
+            version: HttpVersion::Http2,
+            ..settings
+        }
+        .apply(Client::builder());
+        assert!(builder.build().is_ok());
+    }
+
+    #[test]
+    fn test_idempotency_key() {
+        let mut request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "m", "max_tokens": 1,
+            "messages": [{ "role": "user", "content": "hi" }],
+        }))
+        .unwrap();
+        let sent = |request: &AnthropicRequest| {
+            idempotency_key(Client::new().post("http://localhost/v1/messages"), request)
+                .build()
+                .unwrap()
+        };
+        assert!(sent(&request).headers().get(IDEMPOTENCY_HEADER).is_none());
+
+        request.idempotency_key = Some("req_1".to_string());
+        assert_eq!(sent(&request).headers()[IDEMPOTENCY_HEADER], "req_1");
+        // Never part of the body
+        assert!(serde_json::to_value(&request).unwrap().get("idempotency_key").is_none());
+    }
+}
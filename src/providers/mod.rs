@@ -1,12 +1,35 @@
 pub mod error;
 pub mod openai;
+pub mod pool;
 pub mod anthropic_compatible;
+pub mod betas;
+pub mod context_cache;
+pub mod credentials;
+pub mod quirks;
 pub mod gemini;
+pub mod http;
 pub mod registry;
+pub mod signing;
 pub mod streaming;
+pub mod stream_only;
+pub mod synthetic_stream;
+pub mod timeouts;
+pub mod tool_ids;
+pub mod vision;
 
 use async_trait::async_trait;
 use crate::models::{AnthropicRequest, CountTokensRequest, CountTokensResponse, ContentBlock};
+use crate::transform::images::ImageSettings;
+use crate::transform::unsupported::UnsupportedContent;
+use crate::transform::user_id::UserIdTagging;
+use crate::transform::thinking::ThinkingHistory;
+use betas::BetaConfig;
+use context_cache::ContextCacheConfig;
+use quirks::StreamQuirks;
+use http::{Attribution, HttpSettings};
+use timeouts::Timeouts;
+use signing::RequestSigning;
+use crate::transform::tools::ToolPolicy;
 use error::ProviderError;
 use serde::{Deserialize, Serialize};
 use bytes::Bytes;
@@ -26,10 +49,16 @@ pub struct ProviderResponse {
     pub usage: Usage,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Usage {
     pub input_tokens: u32,
     pub output_tokens: u32,
+    /// Prompt tokens written to the provider's cache
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_creation_input_tokens: Option<u32>,
+    /// Prompt tokens served from the provider's cache (not included in `input_tokens`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read_input_tokens: Option<u32>,
 }
 
 /// Main provider trait - all providers must implement this
@@ -53,6 +82,77 @@ pub trait AnthropicProvider: Send + Sync {
 
     /// Check if provider supports a specific model
     fn supports_model(&self, model: &str) -> bool;
+
+    /// Whether the backend itself implements an Anthropic beta feature
+    /// (otherwise the mux strips or emulates it)
+    fn supports_beta(&self, _beta: &str) -> bool {
+        false
+    }
+
+    /// Whether the API takes a `verbosity` parameter
+    /// (otherwise it is expressed in the system prompt)
+    fn supports_verbosity(&self) -> bool {
+        false
+    }
+
+    /// Whether the API takes `document` content blocks
+    /// (otherwise they are replaced per `unsupported_content`)
+    fn supports_documents(&self) -> bool {
+        false
+    }
+
+    /// The HTTP request `send_message` would make, with credentials redacted
+    /// (used by `ccm transform test`)
+    fn preview_request(&self, _request: &AnthropicRequest) -> Result<OutboundRequest, ProviderError> {
+        Err(ProviderError::ModelNotSupported(
+            "Request previews are not supported by this provider".to_string(),
+        ))
+    }
+
+    /// Raw text completion for fill-in-the-middle (OpenAI-style /completions)
+    async fn complete_fim(&self, _request: FimRequest) -> Result<FimResponse, ProviderError> {
+        Err(ProviderError::ModelNotSupported(
+            "FIM completions are not supported by this provider".to_string(),
+        ))
+    }
+}
+
+/// Outbound provider request, as shown by `ccm transform test`
+#[derive(Debug, Serialize)]
+pub struct OutboundRequest {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: serde_json::Value,
+}
+
+/// Placeholder for credentials in request previews
+pub const REDACTED: &str = "[redacted]";
+
+/// Fill-in-the-middle request, already rendered for the target model
+#[derive(Debug, Clone, Serialize)]
+pub struct FimRequest {
+    pub model: String,
+    pub prompt: String,
+    /// Only sent to APIs with native FIM support
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,
+    pub max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+}
+
+/// Fill-in-the-middle result
+#[derive(Debug, Clone)]
+pub struct FimResponse {
+    pub id: String,
+    pub text: String,
+    /// OpenAI finish reason ("stop", "length")
+    pub finish_reason: Option<String>,
+    pub usage: Usage,
 }
 
 /// Authentication type for providers
@@ -85,6 +185,10 @@ pub struct ProviderConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub api_key: Option<String>,
 
+    /// Secondary API key, used once the primary is rejected (revoked, expired or out of quota)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secondary_api_key: Option<String>,
+
     /// OAuth provider ID (required for auth_type = "oauth")
     /// References a token stored in TokenStore
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -98,9 +202,112 @@ pub struct ProviderConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub location: Option<String>,
 
+    /// OpenAI organization (`OpenAI-Organization` header), for keys in several
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub organization: Option<String>,
+
+    /// OpenAI project (`OpenAI-Project` header); the key's default project otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+
     pub base_url: Option<String>,
     pub models: Vec<String>,
     pub enabled: Option<bool>,
+
+    /// Tool filtering/renaming applied to requests sent to this provider
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_policy: Option<ToolPolicy>,
+
+    /// Thinking blocks in history: keep, redact or drop (default depends on provider_type)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thinking_history: Option<ThinkingHistory>,
+
+    /// Set to false for backends that only handle one tool call per turn:
+    /// parallel calls in history are split into sequential turns, and
+    /// OpenAI-compatible requests ask for one call at a time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parallel_tool_calls: Option<bool>,
+
+    /// Send tools in strict mode, adjusting schemas to its requirements (OpenAI)
+    #[serde(default)]
+    pub strict_tools: bool,
+
+    /// Always call the streaming API, aggregating the stream when the client
+    /// didn't ask for one (for backends whose non-streaming endpoint is broken)
+    #[serde(default)]
+    pub stream_only: bool,
+
+    /// Call the non-streaming API and emit a synthetic stream with pings to
+    /// clients that asked for one (for backends without streaming)
+    #[serde(default)]
+    pub synthetic_stream: bool,
+
+    /// Repair broken markdown code fences in responses (for weaker models)
+    #[serde(default)]
+    pub repair_code_fences: bool,
+
+    /// Send an `Idempotency-Key` header, the same on every attempt of a request
+    #[serde(default)]
+    pub idempotency_keys: bool,
+
+    /// Set `metadata.user_id` from the mux tenant or key (Anthropic providers)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<UserIdTagging>,
+
+    /// Periodically ping the backend so its model stays loaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_warm: Option<KeepWarm>,
+
+    /// `anthropic-beta` flags to add, forward or strip (Anthropic-compatible providers)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub betas: Option<BetaConfig>,
+
+    /// Explicit context caching of the system prompt and tools (Gemini API key / Vertex AI, Moonshot)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_cache: Option<ContextCacheConfig>,
+
+    /// Streaming workarounds for proxied backends (OpenAI-compatible providers)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_quirks: Option<StreamQuirks>,
+
+    /// User-Agent and attribution headers on every request to this provider
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attribution: Option<Attribution>,
+
+    /// HTTP version and connection keep-alive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<HttpSettings>,
+
+    /// Image formats and size limits; other images are converted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub images: Option<ImageSettings>,
+
+    /// Documents (and blocks listed as unsupported): skip, placeholder or error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unsupported_content: Option<UnsupportedContent>,
+
+    /// Connect, first-token and total timeouts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeouts: Option<Timeouts>,
+
+    /// Request signing for a gateway in front of the provider
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing: Option<RequestSigning>,
+}
+
+/// Keep-warm pings for local or serverless backends with slow cold starts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeepWarm {
+    /// Seconds between pings (Ollama unloads idle models after 5 minutes by default)
+    #[serde(default = "default_keep_warm_interval")]
+    pub interval_secs: u64,
+    /// Model to ping (default: the provider's first model)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+fn default_keep_warm_interval() -> u64 {
+    240
 }
 
 impl ProviderConfig {
@@ -108,6 +315,12 @@ impl ProviderConfig {
         self.enabled.unwrap_or(true)
     }
 
+    /// Thinking history policy for requests sent to this provider
+    pub fn thinking_history(&self) -> ThinkingHistory {
+        self.thinking_history
+            .unwrap_or_else(|| ThinkingHistory::default_for(&self.provider_type))
+    }
+
     /// Get the API key or OAuth provider ID
     pub fn get_auth_credential(&self) -> Option<String> {
         match self.auth_type {
@@ -1,10 +1,17 @@
-use super::{AnthropicProvider, ProviderResponse, ContentBlock, Usage, error::ProviderError};
-use crate::models::{AnthropicRequest, CountTokensRequest, CountTokensResponse, MessageContent};
+use super::{AnthropicProvider, FimRequest, FimResponse, OutboundRequest, ProviderResponse, ContentBlock, Usage, REDACTED, error::ProviderError, http};
+use super::context_cache::{estimate_tokens, prefix_key, CacheIndex, ContextCacheConfig};
+use super::quirks::{ModelQuirks, StreamQuirks};
+use super::signing::{RequestSigning, SendSigned};
+use super::streaming::{with_heartbeat, LineBuffer};
+use super::tool_ids::{ToolIdFormat, ToolIdMap};
+use crate::models::{AnthropicRequest, CountTokensRequest, CountTokensResponse, MessageContent, Verbosity};
 use crate::auth::{OAuthClient, OAuthConfig, TokenStore};
+use crate::transform::losses::{self, LossKind};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use reqwest::Client;
 use std::pin::Pin;
+use std::sync::Arc;
 use futures::stream::Stream;
 use bytes::Bytes;
 use base64::{Engine as _, engine::general_purpose};
@@ -20,6 +27,11 @@ struct OpenAIRequest {
     messages: Vec<OpenAIMessage>,
     #[serde(skip_serializing_if = "Option::is_none")]
     max_tokens: Option<u32>,
+    /// Replaces `max_tokens` on OpenAI's API (required by reasoning models)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verbosity: Option<Verbosity>,
     #[serde(skip_serializing_if = "Option::is_none")]
     temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -32,6 +44,8 @@ struct OpenAIRequest {
     tools: Option<Vec<OpenAITool>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tool_choice: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parallel_tool_calls: Option<bool>,
 }
 
 /// OpenAI Responses API request format (for Codex models)
@@ -118,6 +132,8 @@ struct OpenAIFunctionDef {
     description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     parameters: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    strict: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -156,6 +172,48 @@ struct OpenAIUsage {
     completion_tokens: u32,
     #[serde(default)]
     total_tokens: u32,
+    #[serde(default)]
+    prompt_tokens_details: Option<TokensDetails>,
+    /// Moonshot reports context cache hits here
+    #[serde(default)]
+    cached_tokens: Option<u32>,
+}
+
+/// `prompt_tokens_details` / `input_tokens_details`
+#[derive(Debug, Default, Deserialize)]
+struct TokensDetails {
+    #[serde(default)]
+    cached_tokens: u32,
+}
+
+/// Split an OpenAI prompt count (which includes cached tokens) into
+/// Anthropic's uncached `input_tokens` and `cache_read_input_tokens`
+fn split_cached(prompt_tokens: u32, cached_tokens: u32) -> (u32, Option<u32>) {
+    (prompt_tokens.saturating_sub(cached_tokens), (cached_tokens > 0).then_some(cached_tokens))
+}
+
+/// Error codes of quota and organization/project mismatches
+const ACCOUNT_ERROR_CODES: [&str; 3] = ["insufficient_quota", "mismatched_project", "mismatched_organization"];
+
+/// An OpenAI quota or project error, with the organization and project the
+/// request was billed to; None for other errors
+///
+/// On multi-project accounts these errors usually mean the key's default
+/// project was used, or the configured one lacks quota or model access.
+fn account_error_message(message: &str, organization: Option<&str>, project: Option<&str>) -> Option<String> {
+    let body: serde_json::Value = serde_json::from_str(message).unwrap_or_default();
+    let error = &body["error"];
+    let code = error["code"].as_str().or(error["type"].as_str()).unwrap_or_default();
+    let text = error["message"].as_str().unwrap_or(message);
+    if !ACCOUNT_ERROR_CODES.contains(&code) && !text.to_lowercase().contains("project") {
+        return None;
+    }
+    Some(format!(
+        "{} (sent with project {} and organization {}; set `project` and `organization` on the provider to use others)",
+        text,
+        project.map_or("<key default>".to_string(), |p| format!("`{}`", p)),
+        organization.map_or("<key default>".to_string(), |o| format!("`{}`", o)),
+    ))
 }
 
 /// OpenAI Responses API response format (for Codex models)
@@ -187,6 +245,8 @@ struct ResponsesContentBlock {
 struct ResponsesUsage {
     input_tokens: u32,
     output_tokens: u32,
+    #[serde(default)]
+    input_tokens_details: Option<TokensDetails>,
 }
 
 /// OpenAI provider implementation
@@ -196,11 +256,22 @@ pub struct OpenAIProvider {
     base_url: String,
     client: Client,
     models: Vec<String>,
-    custom_headers: Vec<(String, String)>,
     /// OAuth provider ID (if using OAuth instead of API key)
     oauth_provider: Option<String>,
     /// Token store for OAuth authentication
     token_store: Option<TokenStore>,
+    /// Moonshot context caching of the system prompt and tools
+    context_cache: Option<ContextCacheConfig>,
+    caches: CacheIndex,
+    /// Streaming workarounds for proxied backends
+    stream_quirks: StreamQuirks,
+    /// Signature required by a gateway in front of the provider
+    signing: Option<Arc<RequestSigning>>,
+    /// Configured OpenAI organization and project (sent by the HTTP client), for error messages
+    organization: Option<String>,
+    project: Option<String>,
+    /// Configured `parallel_tool_calls`, sent with requests that have tools
+    parallel_tool_calls: Option<bool>,
 }
 
 impl OpenAIProvider {
@@ -218,9 +289,15 @@ impl OpenAIProvider {
             base_url,
             client: Client::new(),
             models,
-            custom_headers: Vec::new(),
             oauth_provider,
             token_store,
+            context_cache: None,
+            caches: CacheIndex::default(),
+            stream_quirks: StreamQuirks::default(),
+            signing: None,
+            organization: None,
+            project: None,
+            parallel_tool_calls: None,
         }
     }
 
@@ -356,38 +433,13 @@ impl OpenAIProvider {
         })
     }
 
-    pub fn with_headers(
-        name: String,
-        api_key: String,
-        base_url: String,
-        models: Vec<String>,
-        custom_headers: Vec<(String, String)>,
-        oauth_provider: Option<String>,
-        token_store: Option<TokenStore>,
-    ) -> Self {
-        Self {
-            name,
-            api_key,
-            base_url,
-            client: Client::new(),
-            models,
-            custom_headers,
-            oauth_provider,
-            token_store,
-        }
-    }
-
-    /// OpenRouter - OpenAI-compatible with optional referer headers
+    /// OpenRouter - OpenAI-compatible (attribution headers come from `http::Attribution`)
     pub fn openrouter(name: String, api_key: String, models: Vec<String>) -> Self {
-        Self::with_headers(
+        Self::new(
             name,
             api_key,
             "https://openrouter.ai/api/v1".to_string(),
             models,
-            vec![
-                ("HTTP-Referer".to_string(), "https://github.com/bahkchanhee/claude-code-mux".to_string()),
-                ("X-Title".to_string(), "Claude Code Mux".to_string()),
-            ],
             None,
             None,
         )
@@ -405,14 +457,13 @@ impl OpenAIProvider {
         )
     }
 
-    /// NovitaAI - OpenAI-compatible with source header
+    /// NovitaAI - OpenAI-compatible (source header comes from `http::Attribution`)
     pub fn novita(name: String, api_key: String, models: Vec<String>) -> Self {
-        Self::with_headers(
+        Self::new(
             name,
             api_key,
             "https://api.novita.ai/v3/openai".to_string(),
             models,
-            vec![("X-Novita-Source".to_string(), "claude-code-mux".to_string())],
             None,
             None,
         )
@@ -501,6 +552,126 @@ impl OpenAIProvider {
         )
     }
 
+    /// Use a preconfigured HTTP client (see `http::client`)
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Sign every request (`[providers.signing]`)
+    pub fn with_signing(mut self, signing: Option<RequestSigning>) -> Self {
+        self.signing = signing.map(Arc::new);
+        self
+    }
+
+    /// Apply streaming workarounds (headers, stream_options, heartbeats)
+    pub fn with_stream_quirks(mut self, quirks: Option<StreamQuirks>) -> Self {
+        self.stream_quirks = quirks.unwrap_or_default();
+        self
+    }
+
+    /// OpenAI organization and project the HTTP client sends, named in quota and project errors
+    pub fn with_account(mut self, organization: Option<String>, project: Option<String>) -> Self {
+        self.organization = organization;
+        self.project = project;
+        self
+    }
+
+    /// Allow or forbid several tool calls in one response (`parallel_tool_calls`)
+    pub fn with_parallel_tool_calls(mut self, parallel: Option<bool>) -> Self {
+        self.parallel_tool_calls = parallel;
+        self
+    }
+
+    /// API error, pointing at the organization/project settings when OpenAI
+    /// rejects the project or its quota
+    fn api_error(&self, status: u16, message: String) -> ProviderError {
+        let message = account_error_message(&message, self.organization.as_deref(), self.project.as_deref())
+            .unwrap_or(message);
+        ProviderError::ApiError { status, message }
+    }
+
+    /// Cache large system prompts and tool definitions (Moonshot only)
+    pub fn with_context_cache(mut self, config: Option<ContextCacheConfig>) -> Self {
+        self.context_cache = config;
+        self
+    }
+
+    /// Moonshot context cache headers for a chat completions request
+    ///
+    /// The leading system messages and the tools are uploaded once per TTL; later
+    /// requests name the cache in `X-Msh-Context-Cache` and Moonshot serves the
+    /// matching prefix from it. Failing to create a cache only costs the discount.
+    async fn context_cache_headers(&self, request: &OpenAIRequest, auth_value: &str) -> Vec<(String, String)> {
+        let Some(config) = &self.context_cache else {
+            return Vec::new();
+        };
+        let system: Vec<&OpenAIMessage> = request.messages.iter().take_while(|m| m.role == "system").collect();
+        let messages = serde_json::to_string(&system).unwrap_or_default();
+        let tools = serde_json::to_string(&request.tools).unwrap_or_default();
+        if estimate_tokens(&messages) + estimate_tokens(&tools) < config.min_tokens {
+            return Vec::new();
+        }
+
+        let key = prefix_key(&[&request.model, &messages, &tools]);
+        let cache_id = match self.caches.get(key) {
+            Some(cache_id) => cache_id,
+            None => match self.create_context_cache(request, &system, auth_value, config.ttl_secs).await {
+                Ok(cache_id) => {
+                    tracing::info!("💾 Created Moonshot context cache {} for {}", cache_id, request.model);
+                    self.caches.insert(key, cache_id.clone(), std::time::Duration::from_secs(config.ttl_secs));
+                    cache_id
+                }
+                Err(e) => {
+                    tracing::warn!("⚠️ Failed to create Moonshot context cache, sending uncached: {}", e);
+                    return Vec::new();
+                }
+            },
+        };
+        vec![
+            ("X-Msh-Context-Cache".to_string(), cache_id),
+            ("X-Msh-Context-Cache-Reset-TTL".to_string(), config.ttl_secs.to_string()),
+        ]
+    }
+
+    /// Create a Moonshot cache (`POST /caching`) and return its id
+    async fn create_context_cache(
+        &self,
+        request: &OpenAIRequest,
+        system: &[&OpenAIMessage],
+        auth_value: &str,
+        ttl_secs: u64,
+    ) -> Result<String, ProviderError> {
+        // Caches belong to a model family (moonshot-v1-8k/32k/128k share one)
+        let model = if request.model.starts_with("moonshot-v1") { "moonshot-v1" } else { request.model.as_str() };
+        let mut body = serde_json::json!({ "model": model, "messages": system, "ttl": ttl_secs });
+        if let Some(tools) = &request.tools {
+            body["tools"] = serde_json::to_value(tools)?;
+        }
+
+        let response = self
+            .client
+            .post(format!("{}/caching", self.base_url))
+            .header("Authorization", format!("Bearer {}", auth_value))
+            .json(&body)
+            .send_signed(self.signing.as_ref())
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let message = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(ProviderError::ApiError { status, message });
+        }
+
+        let created: serde_json::Value = response.json().await?;
+        created["id"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| ProviderError::ApiError {
+                status: 502,
+                message: "caching response without an id".to_string(),
+            })
+    }
+
     /// Get authentication header value (API key or OAuth Bearer token)
     async fn get_auth_header(&self) -> Result<String, ProviderError> {
         // If OAuth provider is configured, use Bearer token
@@ -554,6 +725,11 @@ impl OpenAIProvider {
         self.oauth_provider.is_some() && self.token_store.is_some()
     }
 
+    /// OpenAI's own API (as opposed to a compatible backend)
+    fn is_openai_api(&self) -> bool {
+        self.base_url.contains("api.openai.com")
+    }
+
     /// Extract ChatGPT account ID from JWT access token
     fn extract_account_id(access_token: &str) -> Option<String> {
         // JWT format: header.payload.signature
@@ -576,8 +752,11 @@ impl OpenAIProvider {
     }
 
     /// Transform Anthropic request to OpenAI format
-    fn transform_request(&self, request: &AnthropicRequest) -> Result<OpenAIRequest, ProviderError> {
+    fn transform_request(&self, request: &AnthropicRequest, ids: &mut ToolIdMap) -> Result<OpenAIRequest, ProviderError> {
         let mut openai_messages = Vec::new();
+        let mut dropped_thinking = 0;
+        let mut dropped_images = 0;
+        let quirks = ModelQuirks::for_model(&request.model);
 
         // Add system message if present
         if let Some(ref system) = request.system {
@@ -591,7 +770,7 @@ impl OpenAIProvider {
                 }
             };
             openai_messages.push(OpenAIMessage {
-                role: "system".to_string(),
+                role: quirks.map_or("system", |q| q.system_role).to_string(),
                 content: Some(OpenAIContent::String(system_text)),
                 reasoning: None,
                 tool_calls: None,
@@ -617,7 +796,7 @@ impl OpenAIProvider {
                     let tool_results: Vec<_> = blocks.iter()
                         .filter_map(|block| {
                             if let crate::models::ContentBlock::ToolResult { tool_use_id, content } = block {
-                                Some((tool_use_id.clone(), content.to_string()))
+                                Some((ids.upstream_id(tool_use_id), content.to_string()))
                             } else {
                                 None
                             }
@@ -629,7 +808,7 @@ impl OpenAIProvider {
                         .filter_map(|block| {
                             if let crate::models::ContentBlock::ToolUse { id, name, input } = block {
                                 Some(OpenAIToolCall {
-                                    id: id.clone(),
+                                    id: ids.upstream_id(id),
                                     r#type: "function".to_string(),
                                     function: OpenAIFunctionCall {
                                         name: name.clone(),
@@ -665,6 +844,7 @@ impl OpenAIProvider {
                                 } else if let Some(url) = &source.url {
                                     url.clone()
                                 } else {
+                                    dropped_images += 1;
                                     continue; // Skip invalid image sources
                                 };
 
@@ -678,8 +858,17 @@ impl OpenAIProvider {
                             crate::models::ContentBlock::ToolResult { .. } => {
                                 // Will be handled as separate messages below
                             }
-                            crate::models::ContentBlock::Thinking { .. } => {
+                            crate::models::ContentBlock::Thinking { .. }
+                            | crate::models::ContentBlock::RedactedThinking { .. } => {
                                 // OpenAI doesn't have thinking blocks, skip
+                                dropped_thinking += 1;
+                            }
+                            crate::models::ContentBlock::Document { .. } => {
+                                // Replaced by a placeholder before dispatch (transform::unsupported)
+                            }
+                            crate::models::ContentBlock::ServerToolUse { .. }
+                            | crate::models::ContentBlock::WebSearchToolResult { .. } => {
+                                // Turned into text before dispatch (transform::handoff)
                             }
                         }
                     }
@@ -733,27 +922,50 @@ impl OpenAIProvider {
                             name: tool.name.as_ref()?.clone(),
                             description: tool.description.clone(),
                             parameters: tool.input_schema.clone(),
+                            strict: tool.strict,
                         },
                     })
                 })
                 .collect()
         });
 
+        if dropped_thinking > 0 {
+            losses::record(LossKind::ThinkingDropped, format!("{} thinking blocks not sent to {}", dropped_thinking, self.name));
+        }
+        if dropped_images > 0 {
+            losses::record(LossKind::ContentDropped, format!("{} images without data or URL", dropped_images));
+        }
+        if request.top_k.is_some() {
+            losses::record(LossKind::FieldDropped, "top_k (not supported by the OpenAI API)");
+        }
+        let sampling = quirks.is_none_or(|q| q.sampling);
+        if !sampling && (request.temperature.is_some() || request.top_p.is_some()) {
+            losses::record(
+                LossKind::FieldDropped,
+                format!("temperature/top_p (not supported by {})", request.model),
+            );
+        }
+        let completion_tokens = self.is_openai_api() || quirks.is_some_and(|q| q.max_completion_tokens);
+
         Ok(OpenAIRequest {
             model: request.model.clone(),
             messages: openai_messages,
-            max_tokens: Some(request.max_tokens),
-            temperature: request.temperature,
-            top_p: request.top_p,
+            max_tokens: (!completion_tokens).then_some(request.max_tokens),
+            max_completion_tokens: completion_tokens.then_some(request.max_tokens),
+            verbosity: request.verbosity.filter(|_| self.supports_verbosity()),
+            temperature: request.temperature.filter(|_| sampling),
+            top_p: request.top_p.filter(|_| sampling),
             stop: request.stop_sequences.clone(),
             stream: request.stream,
+            // Only accepted alongside tools
+            parallel_tool_calls: self.parallel_tool_calls.filter(|_| tools.is_some()),
             tools,
             tool_choice: None, // TODO: Add tool_choice support if needed
         })
     }
 
     /// Transform OpenAI response to Anthropic format
-    fn transform_response(&self, response: OpenAIResponse) -> ProviderResponse {
+    fn transform_response(&self, response: OpenAIResponse, ids: &mut ToolIdMap) -> ProviderResponse {
         let choice = response.choices.into_iter().next()
             .expect("OpenAI response must have at least one choice");
 
@@ -781,19 +993,45 @@ impl OpenAIProvider {
             String::new()
         };
 
+        let tool_calls = choice.message.tool_calls.unwrap_or_default();
+        let mut content = Vec::new();
+        if !text.is_empty() || tool_calls.is_empty() {
+            content.push(ContentBlock::Text { text });
+        }
+        for call in tool_calls {
+            content.push(ContentBlock::ToolUse {
+                id: ids.anthropic_id(&call.id),
+                name: call.function.name,
+                input: serde_json::from_str(&call.function.arguments).unwrap_or_default(),
+            });
+        }
+
+        let stop_reason = match choice.finish_reason.as_deref() {
+            Some("tool_calls") => Some("tool_use".to_string()),
+            _ => choice.finish_reason,
+        };
+
+        let cached = response
+            .usage
+            .prompt_tokens_details
+            .as_ref()
+            .map(|d| d.cached_tokens)
+            .or(response.usage.cached_tokens)
+            .unwrap_or(0);
+        let (input_tokens, cache_read_input_tokens) = split_cached(response.usage.prompt_tokens, cached);
         ProviderResponse {
             id: response.id,
             r#type: "message".to_string(),
             role: "assistant".to_string(),
-            content: vec![ContentBlock::Text {
-                text,
-            }],
+            content,
             model: response.model,
-            stop_reason: choice.finish_reason,
+            stop_reason,
             stop_sequence: None,
             usage: Usage {
-                input_tokens: response.usage.prompt_tokens,
+                input_tokens,
                 output_tokens: response.usage.completion_tokens,
+                cache_read_input_tokens,
+                ..Default::default()
             },
         }
     }
@@ -822,9 +1060,15 @@ impl OpenAIProvider {
             model: response.model,
             stop_reason: Some("end_turn".to_string()),
             stop_sequence: None,
-            usage: Usage {
-                input_tokens: response.usage.input_tokens,
-                output_tokens: response.usage.output_tokens,
+            usage: {
+                let cached = response.usage.input_tokens_details.as_ref().map_or(0, |d| d.cached_tokens);
+                let (input_tokens, cache_read_input_tokens) = split_cached(response.usage.input_tokens, cached);
+                Usage {
+                    input_tokens,
+                    output_tokens: response.usage.output_tokens,
+                    cache_read_input_tokens,
+                    ..Default::default()
+                }
             },
         }
     }
@@ -893,24 +1137,16 @@ impl AnthropicProvider for OpenAIProvider {
                 }
             }
 
-            // Add custom headers
-            for (key, value) in &self.custom_headers {
-                req_builder = req_builder.header(key, value);
-            }
-
-            let response = req_builder
+            let response = http::idempotency_key(req_builder, &request)
                 .json(&responses_request)
-                .send()
+                .send_signed(self.signing.as_ref())
                 .await?;
 
             if !response.status().is_success() {
                 let status = response.status().as_u16();
                 let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
                 tracing::error!("Responses API error ({}): {}", status, error_text);
-                return Err(ProviderError::ApiError {
-                    status,
-                    message: error_text,
-                });
+                return Err(self.api_error(status, error_text));
             }
 
             let response_text = response.text().await?;
@@ -933,11 +1169,13 @@ impl AnthropicProvider for OpenAIProvider {
                 usage: Usage {
                     input_tokens: 0,  // SSE doesn't provide token counts
                     output_tokens: 0,
+                    ..Default::default()
                 },
             })
         } else {
             // Use standard /v1/chat/completions endpoint for non-Codex models
-            let openai_request = self.transform_request(&request)?;
+            let mut ids = ToolIdMap::for_request(&request, ToolIdFormat::OpenAI);
+            let openai_request = self.transform_request(&request, &mut ids)?;
             let url = format!("{}/chat/completions", base_url);
 
             let mut req_builder = self.client
@@ -964,23 +1202,19 @@ impl AnthropicProvider for OpenAIProvider {
                 }
             }
 
-            // Add custom headers (for OpenRouter, NovitaAI, etc.)
-            for (key, value) in &self.custom_headers {
+            for (key, value) in self.context_cache_headers(&openai_request, &auth_value).await {
                 req_builder = req_builder.header(key, value);
             }
 
-            let response = req_builder
+            let response = http::idempotency_key(req_builder, &request)
                 .json(&openai_request)
-                .send()
+                .send_signed(self.signing.as_ref())
                 .await?;
 
             if !response.status().is_success() {
                 let status = response.status().as_u16();
                 let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                return Err(ProviderError::ApiError {
-                    status,
-                    message: error_text,
-                });
+                return Err(self.api_error(status, error_text));
             }
 
             // Get response body as text for debugging
@@ -995,7 +1229,7 @@ impl AnthropicProvider for OpenAIProvider {
                     e
                 })?;
 
-            Ok(self.transform_response(openai_response))
+            Ok(self.transform_response(openai_response, &mut ids))
         }
     }
 
@@ -1040,7 +1274,8 @@ impl AnthropicProvider for OpenAIProvider {
             total_chars += content.len();
         }
 
-        let estimated_tokens = (total_chars / 4) as u32;
+        let image_tokens = super::vision::VisionFormula::OpenAI.message_image_tokens(&request.messages);
+        let estimated_tokens = (total_chars / 4) as u32 + image_tokens;
 
         Ok(CountTokensResponse {
             input_tokens: estimated_tokens,
@@ -1065,6 +1300,8 @@ impl AnthropicProvider for OpenAIProvider {
 
         // Check if this is a Codex model
         let is_codex = Self::is_codex_model(&request.model);
+        let mut ids = ToolIdMap::for_request(&request, ToolIdFormat::OpenAI);
+        let mut cache_headers = Vec::new();
 
         let (url, request_body) = if is_codex {
             // Use /v1/responses endpoint for Codex models
@@ -1075,9 +1312,13 @@ impl AnthropicProvider for OpenAIProvider {
             (format!("{}/responses", base_url), body)
         } else {
             // Use standard /v1/chat/completions endpoint
-            let openai_request = self.transform_request(&request)?;
-            let body = serde_json::to_value(&openai_request)
+            let openai_request = self.transform_request(&request, &mut ids)?;
+            cache_headers = self.context_cache_headers(&openai_request, &auth_value).await;
+            let mut body = serde_json::to_value(&openai_request)
                 .map_err(|e| ProviderError::SerializationError(e))?;
+            if self.stream_quirks.stream_options {
+                body["stream_options"] = serde_json::json!({ "include_usage": true });
+            }
             (format!("{}/chat/completions", base_url), body)
         };
 
@@ -1085,8 +1326,11 @@ impl AnthropicProvider for OpenAIProvider {
         let mut req_builder = self.client
             .post(&url)
             .header("Authorization", format!("Bearer {}", auth_value))
-            .header("Content-Type", "application/json")
-            .header("accept", "text/event-stream");
+            .header("Content-Type", "application/json");
+        req_builder = self.stream_quirks.apply(req_builder);
+        for (key, value) in cache_headers {
+            req_builder = req_builder.header(key, value);
+        }
 
         // For OAuth (ChatGPT Codex), add Codex-specific headers
         if self.is_oauth() && is_codex {
@@ -1105,29 +1349,560 @@ impl AnthropicProvider for OpenAIProvider {
             }
         }
 
-        let response = req_builder
+        let response = http::idempotency_key(req_builder, &request)
             .json(&request_body)
-            .send()
+            .send_signed(self.signing.as_ref())
             .await?;
 
         // Check for errors
         if !response.status().is_success() {
             let status = response.status().as_u16();
             let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(ProviderError::ApiError {
-                status,
-                message: error_text,
-            });
+            return Err(self.api_error(status, error_text));
         }
 
-        // TODO: Transform OpenAI SSE format to Anthropic SSE format
-        // For now, just pass through the stream
         let stream = response.bytes_stream().map_err(|e| ProviderError::HttpError(e));
 
-        Ok(Box::pin(stream))
+        // TODO: Transform Responses API events for Codex models; passed through for now
+        if is_codex {
+            return Ok(Box::pin(stream));
+        }
+
+        let stream = transcode_chat_stream(stream, request.model, ids);
+        match self.stream_quirks.ping_interval_secs {
+            Some(secs) => Ok(Box::pin(with_heartbeat(Box::pin(stream), std::time::Duration::from_secs(secs)))),
+            None => Ok(Box::pin(stream)),
+        }
     }
 
     fn supports_model(&self, model: &str) -> bool {
         self.models.iter().any(|m| m == model)
     }
+
+    fn preview_request(&self, request: &AnthropicRequest) -> Result<OutboundRequest, ProviderError> {
+        if self.is_oauth() || Self::is_codex_model(&request.model) {
+            return Err(ProviderError::ModelNotSupported(
+                "Previews cover the Chat Completions API, not the Responses API".to_string(),
+            ));
+        }
+        let mut ids = ToolIdMap::for_request(request, ToolIdFormat::OpenAI);
+        let body = serde_json::to_value(self.transform_request(request, &mut ids)?)?;
+        let headers = vec![
+            ("Authorization".to_string(), format!("Bearer {}", REDACTED)),
+            ("Content-Type".to_string(), "application/json".to_string()),
+        ];
+        Ok(OutboundRequest {
+            url: format!("{}/chat/completions", self.base_url),
+            headers,
+            body,
+        })
+    }
+
+    fn supports_verbosity(&self) -> bool {
+        !self.is_oauth() && (self.is_openai_api() || self.base_url.contains("openrouter.ai"))
+    }
+
+    async fn complete_fim(&self, request: FimRequest) -> Result<FimResponse, ProviderError> {
+        if self.is_oauth() {
+            return Err(ProviderError::ModelNotSupported(
+                "FIM completions are not available with ChatGPT OAuth".to_string(),
+            ));
+        }
+
+        let auth_value = self.get_auth_header().await?;
+        let url = format!("{}/completions", self.base_url);
+
+        let response = self.client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", auth_value))
+            .header("Content-Type", "application/json")
+            .json(&request)
+            .send_signed(self.signing.as_ref())
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(self.api_error(status, error_text));
+        }
+
+        let body: serde_json::Value = response.json().await?;
+        let choice = &body["choices"][0];
+        // Codestral's /fim/completions answers in chat format
+        let text = choice["text"]
+            .as_str()
+            .or_else(|| choice["message"]["content"].as_str())
+            .unwrap_or_default()
+            .to_string();
+
+        Ok(FimResponse {
+            id: body["id"].as_str().unwrap_or("fim").to_string(),
+            text,
+            finish_reason: choice["finish_reason"].as_str().map(str::to_string),
+            usage: {
+                let (input_tokens, cache_read_input_tokens) = split_cached(
+                    body["usage"]["prompt_tokens"].as_u64().unwrap_or(0) as u32,
+                    body["usage"]["prompt_tokens_details"]["cached_tokens"].as_u64().unwrap_or(0) as u32,
+                );
+                Usage {
+                    input_tokens,
+                    output_tokens: body["usage"]["completion_tokens"].as_u64().unwrap_or(0) as u32,
+                    cache_read_input_tokens,
+                    ..Default::default()
+                }
+            },
+        })
+    }
+}
+
+/// Incremental chat completions -> Anthropic SSE translation for one response
+///
+/// Text is streamed as it arrives. Tool calls are assembled per `tool_calls[].index`
+/// (parallel calls may interleave) and emitted as complete tool_use blocks at the end.
+struct ChatStreamTranscoder {
+    model: String,
+    ids: ToolIdMap,
+    started: bool,
+    /// Index of the open text block, if any
+    text_block: Option<usize>,
+    next_index: usize,
+    /// (upstream id, name, arguments) by OpenAI tool call index
+    tool_calls: std::collections::BTreeMap<u64, (String, String, String)>,
+    input_tokens: u32,
+    cache_read_tokens: Option<u32>,
+    output_tokens: u32,
+    stop_reason: Option<String>,
+}
+
+impl ChatStreamTranscoder {
+    fn new(model: String, ids: ToolIdMap) -> Self {
+        Self {
+            model,
+            ids,
+            started: false,
+            text_block: None,
+            next_index: 0,
+            tool_calls: Default::default(),
+            input_tokens: 0,
+            cache_read_tokens: None,
+            output_tokens: 0,
+            stop_reason: None,
+        }
+    }
+
+    fn event(out: &mut String, event: &str, data: serde_json::Value) {
+        out.push_str(&format!("event: {}\ndata: {}\n\n", event, data));
+    }
+
+    /// Translate one SSE line from upstream
+    fn process_line(&mut self, line: &str, out: &mut String) {
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+            return;
+        };
+        if data == "[DONE]" {
+            return;
+        }
+        match serde_json::from_str::<serde_json::Value>(data) {
+            Ok(chunk) => self.process_chunk(&chunk, out),
+            Err(e) => tracing::warn!("Skipping unparseable OpenAI stream chunk: {}", e),
+        }
+    }
+
+    fn process_chunk(&mut self, chunk: &serde_json::Value, out: &mut String) {
+        if let Some(usage) = chunk.get("usage").filter(|u| u.is_object()) {
+            (self.input_tokens, self.cache_read_tokens) = split_cached(
+                usage["prompt_tokens"].as_u64().unwrap_or(0) as u32,
+                usage["prompt_tokens_details"]["cached_tokens"]
+                    .as_u64()
+                    .or(usage["cached_tokens"].as_u64())
+                    .unwrap_or(0) as u32,
+            );
+            self.output_tokens = usage["completion_tokens"].as_u64().unwrap_or(0) as u32;
+        }
+
+        if !self.started {
+            self.started = true;
+            Self::event(out, "message_start", serde_json::json!({
+                "type": "message_start",
+                "message": {
+                    "id": chunk["id"].as_str().unwrap_or("chatcmpl"),
+                    "type": "message",
+                    "role": "assistant",
+                    "content": [],
+                    "model": self.model,
+                    "stop_reason": null,
+                    "stop_sequence": null,
+                    "usage": { "input_tokens": self.input_tokens, "output_tokens": 0 },
+                },
+            }));
+        }
+
+        let Some(choice) = chunk["choices"].get(0) else {
+            return;
+        };
+        let delta = &choice["delta"];
+
+        if let Some(text) = delta["content"].as_str().filter(|t| !t.is_empty()) {
+            let index = match self.text_block {
+                Some(index) => index,
+                None => {
+                    let index = self.next_index;
+                    self.next_index += 1;
+                    self.text_block = Some(index);
+                    Self::event(out, "content_block_start", serde_json::json!({
+                        "type": "content_block_start",
+                        "index": index,
+                        "content_block": { "type": "text", "text": "" },
+                    }));
+                    index
+                }
+            };
+            Self::event(out, "content_block_delta", serde_json::json!({
+                "type": "content_block_delta",
+                "index": index,
+                "delta": { "type": "text_delta", "text": text },
+            }));
+        }
+
+        for call in delta["tool_calls"].as_array().into_iter().flatten() {
+            let index = call["index"].as_u64().unwrap_or(0);
+            let entry = self.tool_calls.entry(index).or_default();
+            if let Some(id) = call["id"].as_str() {
+                entry.0 = id.to_string();
+            }
+            if let Some(name) = call["function"]["name"].as_str() {
+                entry.1.push_str(name);
+            }
+            if let Some(arguments) = call["function"]["arguments"].as_str() {
+                entry.2.push_str(arguments);
+            }
+        }
+
+        if let Some(reason) = choice["finish_reason"].as_str() {
+            self.stop_reason = Some(
+                match reason {
+                    "length" => "max_tokens",
+                    "tool_calls" => "tool_use",
+                    _ => "end_turn",
+                }
+                .to_string(),
+            );
+        }
+    }
+
+    /// Close the text block, emit assembled tool calls and end the message
+    fn finish(&mut self, out: &mut String) {
+        if !self.started {
+            return;
+        }
+        if let Some(index) = self.text_block.take() {
+            Self::event(out, "content_block_stop", serde_json::json!({
+                "type": "content_block_stop",
+                "index": index,
+            }));
+        }
+
+        let has_tool_calls = !self.tool_calls.is_empty();
+        for (upstream_id, name, arguments) in std::mem::take(&mut self.tool_calls).into_values() {
+            let index = self.next_index;
+            self.next_index += 1;
+            Self::event(out, "content_block_start", serde_json::json!({
+                "type": "content_block_start",
+                "index": index,
+                "content_block": {
+                    "type": "tool_use",
+                    "id": self.ids.anthropic_id(&upstream_id),
+                    "name": name,
+                    "input": {},
+                },
+            }));
+            if !arguments.is_empty() {
+                Self::event(out, "content_block_delta", serde_json::json!({
+                    "type": "content_block_delta",
+                    "index": index,
+                    "delta": { "type": "input_json_delta", "partial_json": arguments },
+                }));
+            }
+            Self::event(out, "content_block_stop", serde_json::json!({
+                "type": "content_block_stop",
+                "index": index,
+            }));
+        }
+
+        let stop_reason = match self.stop_reason.as_deref() {
+            _ if has_tool_calls => "tool_use",
+            Some(reason) => reason,
+            None => "end_turn",
+        };
+        // Usage arrives with the last chunk, after message_start went out
+        let mut usage = serde_json::json!({ "output_tokens": self.output_tokens });
+        if let Some(cached) = self.cache_read_tokens {
+            usage["input_tokens"] = self.input_tokens.into();
+            usage["cache_read_input_tokens"] = cached.into();
+        }
+        Self::event(out, "message_delta", serde_json::json!({
+            "type": "message_delta",
+            "delta": { "stop_reason": stop_reason, "stop_sequence": null },
+            "usage": usage,
+        }));
+        Self::event(out, "message_stop", serde_json::json!({ "type": "message_stop" }));
+    }
+}
+
+/// Convert a chat completions SSE byte stream into Anthropic SSE events
+fn transcode_chat_stream<S>(
+    stream: S,
+    model: String,
+    ids: ToolIdMap,
+) -> impl Stream<Item = Result<Bytes, ProviderError>> + Send
+where
+    S: Stream<Item = Result<Bytes, ProviderError>> + Send + Unpin + 'static,
+{
+    use futures::StreamExt;
+
+    futures::stream::unfold(
+        (stream, LineBuffer::default(), ChatStreamTranscoder::new(model, ids), false),
+        |(mut stream, mut buffer, mut transcoder, done)| async move {
+            if done {
+                return None;
+            }
+            loop {
+                let mut out = String::new();
+                match stream.next().await {
+                    Some(Ok(bytes)) => {
+                        buffer.push(&bytes);
+                        let Some(lines) = buffer.lines() else {
+                            continue;
+                        };
+                        for line in lines.lines() {
+                            transcoder.process_line(line, &mut out);
+                        }
+                        if out.is_empty() {
+                            continue;
+                        }
+                        return Some((Ok(Bytes::from(out)), (stream, buffer, transcoder, false)));
+                    }
+                    Some(Err(e)) => return Some((Err(e), (stream, buffer, transcoder, true))),
+                    None => {
+                        for line in buffer.rest().lines() {
+                            transcoder.process_line(line, &mut out);
+                        }
+                        transcoder.finish(&mut out);
+                        if out.is_empty() {
+                            return None;
+                        }
+                        return Some((Ok(Bytes::from(out)), (stream, buffer, transcoder, true)));
+                    }
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::streaming::parse_sse_events;
+
+    #[test]
+    fn test_account_errors() {
+        let quota = r#"{"error":{"message":"You exceeded your current quota.","type":"insufficient_quota","code":"insufficient_quota"}}"#;
+        let message = account_error_message(quota, None, Some("proj_abc")).unwrap();
+        assert!(message.starts_with("You exceeded your current quota."), "{}", message);
+        assert!(message.contains("project `proj_abc` and organization <key default>"), "{}", message);
+
+        let access = r#"{"error":{"message":"Project `proj_abc` does not have access to model `gpt-5`","code":"model_not_found"}}"#;
+        assert!(account_error_message(access, Some("org_1"), None).unwrap().contains("organization `org_1`"));
+
+        let other = r#"{"error":{"message":"Invalid value for 'temperature'","code":"invalid_value"}}"#;
+        assert_eq!(account_error_message(other, None, None), None);
+
+        // The HTTP client sends the configured account
+        let config: crate::providers::ProviderConfig = toml::from_str(
+            "name = \"openai\"\nprovider_type = \"openai\"\nmodels = []\nproject = \"proj_abc\"",
+        )
+        .unwrap();
+        assert_eq!(http::account_headers(&config), [("OpenAI-Project".to_string(), "proj_abc".to_string())]);
+    }
+
+    #[test]
+    fn test_reasoning_model_quirks() {
+        let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "openai/o3-mini",
+            "max_tokens": 1000,
+            "temperature": 0.2,
+            "system": "Be brief",
+            "messages": [{ "role": "user", "content": "hi" }],
+        }))
+        .unwrap();
+        let provider = OpenAIProvider::new(
+            "azure".to_string(),
+            "k".to_string(),
+            "https://example.openai.azure.com/openai/v1".to_string(),
+            vec![],
+            None,
+            None,
+        );
+        let body = provider.preview_request(&request).unwrap().body;
+        assert_eq!(body["max_completion_tokens"], 1000);
+        assert!(body.get("max_tokens").is_none());
+        assert!(body.get("temperature").is_none());
+        assert_eq!(body["messages"][0]["role"], "developer");
+    }
+
+    #[test]
+    fn test_preview_request() {
+        let mut request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-5",
+            "max_tokens": 1000,
+            "messages": [{ "role": "user", "content": "hi" }],
+        }))
+        .unwrap();
+        request.verbosity = Some(Verbosity::Low);
+
+        let openai = OpenAIProvider::new(
+            "openai".to_string(),
+            "sk-secret".to_string(),
+            "https://api.openai.com/v1".to_string(),
+            vec![],
+            None,
+            None,
+        );
+        let preview = openai.preview_request(&request).unwrap();
+        assert_eq!(preview.url, "https://api.openai.com/v1/chat/completions");
+        assert_eq!(preview.body["max_completion_tokens"], 1000);
+        assert_eq!(preview.body["verbosity"], "low");
+        assert!(preview.body.get("max_tokens").is_none());
+        assert!(!serde_json::to_string(&preview).unwrap().contains("sk-secret"));
+
+        let local = OpenAIProvider::new(
+            "ollama".to_string(),
+            String::new(),
+            "http://localhost:11434/v1".to_string(),
+            vec![],
+            None,
+            None,
+        );
+        // (reasoning model families take max_completion_tokens everywhere)
+        request.model = "llama3.3".to_string();
+        let preview = local.preview_request(&request).unwrap();
+        assert_eq!(preview.body["max_tokens"], 1000);
+        assert!(preview.body.get("verbosity").is_none());
+    }
+
+    #[test]
+    fn test_parallel_tool_calls_disabled() {
+        let mut request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o",
+            "max_tokens": 100,
+            "messages": [{ "role": "user", "content": "hi" }],
+            "tools": [{ "name": "Read", "input_schema": { "type": "object" } }],
+        }))
+        .unwrap();
+        let provider = OpenAIProvider::new(
+            "openai".to_string(),
+            "k".to_string(),
+            "https://api.openai.com/v1".to_string(),
+            vec![],
+            None,
+            None,
+        )
+        .with_parallel_tool_calls(Some(false));
+
+        let preview = provider.preview_request(&request).unwrap();
+        assert_eq!(preview.body["parallel_tool_calls"], false);
+
+        // Rejected by OpenAI without tools
+        request.tools = None;
+        let preview = provider.preview_request(&request).unwrap();
+        assert!(preview.body.get("parallel_tool_calls").is_none());
+    }
+
+    #[test]
+    fn test_cached_tokens() {
+        let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o",
+            "max_tokens": 100,
+            "messages": [{ "role": "user", "content": "hi" }],
+        }))
+        .unwrap();
+        let provider = OpenAIProvider::new(
+            "openai".to_string(),
+            "k".to_string(),
+            "https://api.openai.com/v1".to_string(),
+            vec![],
+            None,
+            None,
+        );
+        let response: OpenAIResponse = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-1",
+            "model": "gpt-4o",
+            "choices": [{ "message": { "role": "assistant", "content": "hello" }, "finish_reason": "stop" }],
+            "usage": {
+                "prompt_tokens": 2006,
+                "completion_tokens": 300,
+                "total_tokens": 2306,
+                "prompt_tokens_details": { "cached_tokens": 1920 },
+            },
+        }))
+        .unwrap();
+
+        let mut ids = ToolIdMap::for_request(&request, ToolIdFormat::OpenAI);
+        let usage = provider.transform_response(response, &mut ids).usage;
+        assert_eq!(usage.input_tokens, 86);
+        assert_eq!(usage.cache_read_input_tokens, Some(1920));
+        assert_eq!(usage.output_tokens, 300);
+
+        // Moonshot reports context cache hits at the top level
+        let response: OpenAIResponse = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-2",
+            "model": "moonshot-v1-128k",
+            "choices": [{ "message": { "role": "assistant", "content": "hello" }, "finish_reason": "stop" }],
+            "usage": { "prompt_tokens": 5000, "completion_tokens": 10, "cached_tokens": 4800 },
+        }))
+        .unwrap();
+        let usage = provider.transform_response(response, &mut ids).usage;
+        assert_eq!((usage.input_tokens, usage.cache_read_input_tokens), (200, Some(4800)));
+    }
+
+    #[test]
+    fn test_stream_assembles_parallel_tool_calls() {
+        let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o",
+            "max_tokens": 16,
+            "messages": [{ "role": "user", "content": "hi" }],
+        }))
+        .unwrap();
+        let mut transcoder =
+            ChatStreamTranscoder::new("gpt-4o".to_string(), ToolIdMap::for_request(&request, ToolIdFormat::OpenAI));
+
+        let chunks = [
+            r#"data: {"id":"c1","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_a","function":{"name":"Read","arguments":"{\"pa"}}]}}]}"#,
+            r#"data: {"id":"c1","choices":[{"index":0,"delta":{"tool_calls":[{"index":1,"id":"call_b","function":{"name":"Bash","arguments":"{}"}}]}}]}"#,
+            r#"data: {"id":"c1","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"th\":1}"}}]},"finish_reason":"tool_calls"}]}"#,
+            "data: [DONE]",
+        ];
+        let mut out = String::new();
+        for chunk in chunks {
+            transcoder.process_line(chunk, &mut out);
+        }
+        transcoder.finish(&mut out);
+
+        let events: Vec<serde_json::Value> = parse_sse_events(&out)
+            .iter()
+            .map(|e| serde_json::from_str(&e.data).unwrap())
+            .collect();
+        let starts: Vec<_> = events
+            .iter()
+            .filter(|e| e["type"] == "content_block_start")
+            .map(|e| (e["index"].as_u64().unwrap(), e["content_block"]["id"].as_str().unwrap()))
+            .collect();
+        assert_eq!(starts, vec![(0, "call_a"), (1, "call_b")]);
+
+        let first_args = events
+            .iter()
+            .find(|e| e["type"] == "content_block_delta" && e["index"] == 0)
+            .unwrap();
+        assert_eq!(first_args["delta"]["partial_json"], "{\"path\":1}");
+        assert!(events.iter().any(|e| e["delta"]["stop_reason"] == "tool_use"));
+    }
 }
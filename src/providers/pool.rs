@@ -0,0 +1,192 @@
+//! Concurrency limits per upstream host
+//!
+//! Providers pointing at the same host share one gate. Requests wait their
+//! turn in FIFO order, and background traffic (subagent bursts, utility
+//! calls) can never take the slots reserved for the interactive session.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::{Stream, StreamExt};
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::error::ProviderError;
+use super::{AnthropicProvider, FimRequest, FimResponse, OutboundRequest, ProviderConfig, ProviderResponse};
+use crate::models::{AnthropicRequest, CountTokensRequest, CountTokensResponse};
+
+tokio::task_local! {
+    static LANE: Cell<Lane>;
+}
+
+/// Scheduling class of a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lane {
+    /// The conversation the user is watching
+    Interactive,
+    /// Work that can wait: background routes, requests marked as background,
+    /// and the router's own calls (judge, keep-warm)
+    Background,
+}
+
+/// Run a client request; its lane starts out interactive
+pub async fn scope<F: Future>(future: F) -> F::Output {
+    LANE.scope(Cell::new(Lane::Interactive), future).await
+}
+
+/// Set the lane of the current request
+///
+/// Does nothing outside of [`scope`].
+pub fn set_lane(lane: Lane) {
+    let _ = LANE.try_with(|current| current.set(lane));
+}
+
+fn current_lane() -> Lane {
+    LANE.try_with(Cell::get).unwrap_or(Lane::Background)
+}
+
+/// Host a provider's requests go to, for sharing gates
+pub fn host_key(config: &ProviderConfig) -> String {
+    config
+        .base_url
+        .as_deref()
+        .and_then(|url| reqwest::Url::parse(url).ok())
+        .and_then(|url| url.host_str().map(|host| format!("{}:{}", host, url.port_or_known_default().unwrap_or(0))))
+        .unwrap_or_else(|| format!("provider:{}", config.name))
+}
+
+/// Concurrent request slots of one host
+#[derive(Debug)]
+pub struct HostGate {
+    all: Arc<Semaphore>,
+    /// Background requests hold one of these as well as a slot
+    background: Arc<Semaphore>,
+}
+
+/// A held slot, released on drop
+pub struct Slot {
+    _all: OwnedSemaphorePermit,
+    _background: Option<OwnedSemaphorePermit>,
+}
+
+impl HostGate {
+    /// `reserved` of the `max_concurrent` slots are kept for interactive requests
+    pub fn new(max_concurrent: usize, reserved: usize) -> Self {
+        let max_concurrent = max_concurrent.max(1);
+        Self {
+            all: Arc::new(Semaphore::new(max_concurrent)),
+            background: Arc::new(Semaphore::new(max_concurrent.saturating_sub(reserved).max(1))),
+        }
+    }
+
+    /// Wait for a slot in the given lane
+    pub async fn acquire(&self, lane: Lane) -> Slot {
+        // The semaphores are never closed
+        let background = match lane {
+            Lane::Interactive => None,
+            Lane::Background => Some(self.background.clone().acquire_owned().await.expect("gate closed")),
+        };
+        Slot {
+            _all: self.all.clone().acquire_owned().await.expect("gate closed"),
+            _background: background,
+        }
+    }
+}
+
+/// Holds a host slot for each upstream request, for the whole stream
+pub struct HostLimited {
+    inner: Box<dyn AnthropicProvider>,
+    gate: Arc<HostGate>,
+}
+
+impl HostLimited {
+    pub fn new(inner: Box<dyn AnthropicProvider>, gate: Arc<HostGate>) -> Self {
+        Self { inner, gate }
+    }
+}
+
+#[async_trait]
+impl AnthropicProvider for HostLimited {
+    async fn send_message(&self, request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
+        let _slot = self.gate.acquire(current_lane()).await;
+        self.inner.send_message(request).await
+    }
+
+    async fn send_message_stream(
+        &self,
+        request: AnthropicRequest,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<Bytes, ProviderError>> + Send>>, ProviderError> {
+        let slot = self.gate.acquire(current_lane()).await;
+        let stream = self.inner.send_message_stream(request).await?;
+        Ok(Box::pin(stream.map(move |chunk| {
+            let _ = &slot;
+            chunk
+        })))
+    }
+
+    async fn count_tokens(&self, request: CountTokensRequest) -> Result<CountTokensResponse, ProviderError> {
+        self.inner.count_tokens(request).await
+    }
+
+    fn supports_model(&self, model: &str) -> bool {
+        self.inner.supports_model(model)
+    }
+
+    fn supports_beta(&self, beta: &str) -> bool {
+        self.inner.supports_beta(beta)
+    }
+
+    fn supports_verbosity(&self) -> bool {
+        self.inner.supports_verbosity()
+    }
+
+    fn supports_documents(&self) -> bool {
+        self.inner.supports_documents()
+    }
+
+    fn preview_request(&self, request: &AnthropicRequest) -> Result<OutboundRequest, ProviderError> {
+        self.inner.preview_request(request)
+    }
+
+    async fn complete_fim(&self, request: FimRequest) -> Result<FimResponse, ProviderError> {
+        let _slot = self.gate.acquire(current_lane()).await;
+        self.inner.complete_fim(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_reserved_slots() {
+        let gate = Arc::new(HostGate::new(3, 1));
+        let _a = gate.acquire(Lane::Background).await;
+        let _b = gate.acquire(Lane::Background).await;
+
+        // Background traffic is capped below the limit...
+        let blocked = tokio::time::timeout(Duration::from_millis(20), gate.acquire(Lane::Background)).await;
+        assert!(blocked.is_err());
+        // ...so the interactive session still gets through
+        assert_eq!(current_lane(), Lane::Background);
+        let c = scope(async {
+            assert_eq!(current_lane(), Lane::Interactive);
+            gate.acquire(current_lane()).await
+        })
+        .await;
+
+        let blocked = tokio::time::timeout(Duration::from_millis(20), gate.acquire(Lane::Interactive)).await;
+        assert!(blocked.is_err());
+        drop(c);
+        assert!(tokio::time::timeout(Duration::from_millis(20), gate.acquire(Lane::Interactive)).await.is_ok());
+
+        let config: ProviderConfig = toml::from_str(
+            "name = \"a\"\nprovider_type = \"anthropic\"\nbase_url = \"https://api.anthropic.com/v1\"\nmodels = []",
+        )
+        .unwrap();
+        assert_eq!(host_key(&config), "api.anthropic.com:443");
+    }
+}
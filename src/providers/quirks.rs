@@ -0,0 +1,141 @@
+use reqwest::RequestBuilder;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Streaming workarounds for backends behind proxies (e.g. Cloudflare) that
+/// buffer or drop idle SSE connections
+///
+/// Example:
+/// ```toml
+/// [providers.stream_quirks]
+/// stream_options = true
+/// accept = "text/event-stream, application/json"
+/// disable_compression = true
+/// ping_interval_secs = 10
+/// headers = { "Cache-Control" = "no-cache" }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StreamQuirks {
+    /// Send `stream_options: {"include_usage": true}`; some backends only flush
+    /// the stream (and report usage) with it
+    #[serde(default)]
+    pub stream_options: bool,
+    /// `Accept` header of streaming requests (default: `text/event-stream`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accept: Option<String>,
+    /// Ask for uncompressed responses; compressing proxies hold SSE chunks back
+    #[serde(default)]
+    pub disable_compression: bool,
+    /// Emit an Anthropic `ping` event to the client after this many idle seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ping_interval_secs: Option<u64>,
+    /// Extra headers on streaming requests
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl StreamQuirks {
+    /// Accept and extra headers for a streaming request
+    pub fn apply(&self, mut builder: RequestBuilder) -> RequestBuilder {
+        builder = builder.header("accept", self.accept.as_deref().unwrap_or("text/event-stream"));
+        if self.disable_compression {
+            builder = builder.header("accept-encoding", "identity");
+        }
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        builder
+    }
+}
+
+/// Parameter rules of a model family on OpenAI-style chat completions
+#[derive(Debug, PartialEq)]
+pub struct ModelQuirks {
+    /// Model name prefixes (after any `vendor/` prefix); the first matching entry wins
+    pub prefixes: &'static [&'static str],
+    /// Send `max_completion_tokens` instead of `max_tokens`
+    pub max_completion_tokens: bool,
+    /// Whether `temperature` and `top_p` are accepted
+    pub sampling: bool,
+    /// Role the system prompt is sent with
+    pub system_role: &'static str,
+}
+
+/// Reasoning model families and what they reject
+pub const MODEL_QUIRKS: &[ModelQuirks] = &[
+    // The first o1 releases take no system or developer message at all
+    ModelQuirks {
+        prefixes: &["o1-mini", "o1-preview"],
+        max_completion_tokens: true,
+        sampling: false,
+        system_role: "user",
+    },
+    ModelQuirks {
+        prefixes: &["o1", "o3", "o4"],
+        max_completion_tokens: true,
+        sampling: false,
+        system_role: "developer",
+    },
+    // The chat variant is a regular chat model
+    ModelQuirks {
+        prefixes: &["gpt-5-chat"],
+        max_completion_tokens: true,
+        sampling: true,
+        system_role: "system",
+    },
+    ModelQuirks {
+        prefixes: &["gpt-5"],
+        max_completion_tokens: true,
+        sampling: false,
+        system_role: "developer",
+    },
+];
+
+impl ModelQuirks {
+    /// Rules for a model, if it belongs to a family in [`MODEL_QUIRKS`]
+    pub fn for_model(model: &str) -> Option<&'static ModelQuirks> {
+        let model = model.rsplit('/').next().unwrap_or(model).to_ascii_lowercase();
+        MODEL_QUIRKS
+            .iter()
+            .find(|quirks| quirks.prefixes.iter().any(|prefix| model.starts_with(prefix)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Client;
+
+    #[test]
+    fn test_apply() {
+        let quirks: StreamQuirks = toml::from_str(
+            r#"
+accept = "text/event-stream, application/json"
+disable_compression = true
+headers = { "Cache-Control" = "no-cache" }
+"#,
+        )
+        .unwrap();
+        let request = quirks.apply(Client::new().post("http://localhost/v1/chat/completions")).build().unwrap();
+        assert_eq!(request.headers()["accept"], "text/event-stream, application/json");
+        assert_eq!(request.headers()["accept-encoding"], "identity");
+        assert_eq!(request.headers()["cache-control"], "no-cache");
+
+        let default = StreamQuirks::default();
+        let request = default.apply(Client::new().post("http://localhost/")).build().unwrap();
+        assert_eq!(request.headers()["accept"], "text/event-stream");
+        assert!(request.headers().get("cache-control").is_none());
+    }
+
+    #[test]
+    fn test_model_quirks() {
+        let quirks = |model| ModelQuirks::for_model(model).map(|q| (q.sampling, q.system_role));
+        assert_eq!(quirks("o3-mini"), Some((false, "developer")));
+        assert_eq!(quirks("openai/o4-mini"), Some((false, "developer")));
+        assert_eq!(quirks("o1-mini-2024-09-12"), Some((false, "user")));
+        assert_eq!(quirks("gpt-5-chat-latest"), Some((true, "system")));
+        assert_eq!(quirks("GPT-5-codex"), Some((false, "developer")));
+        assert_eq!(quirks("gpt-4o"), None);
+        assert_eq!(quirks("llama-3.3-70b"), None);
+    }
+}
@@ -1,6 +1,13 @@
 use super::{AnthropicProvider, ProviderConfig, OpenAIProvider, AnthropicCompatibleProvider, error::ProviderError};
 use super::gemini::GeminiProvider;
+use super::credentials::BlueGreen;
+use super::http;
+use super::pool::{self, HostGate, HostLimited};
+use super::stream_only::StreamOnly;
+use super::synthetic_stream::SyntheticStream;
+use super::timeouts::TimeLimited;
 use crate::auth::TokenStore;
+use crate::cli::TimeoutConfig;
 use std::collections::HashMap;
 use std::sync::Arc;
 
@@ -22,8 +29,16 @@ impl ProviderRegistry {
     }
 
     /// Load providers from configuration
-    pub fn from_configs(configs: &[ProviderConfig], token_store: Option<TokenStore>) -> Result<Self, ProviderError> {
+    ///
+    /// `defaults` are the `[server.timeouts]` for providers without their own.
+    pub fn from_configs(
+        configs: &[ProviderConfig],
+        defaults: &TimeoutConfig,
+        token_store: Option<TokenStore>,
+    ) -> Result<Self, ProviderError> {
         let mut registry = Self::new();
+        // Providers on the same host share its concurrency limit
+        let mut gates: HashMap<String, Arc<HostGate>> = HashMap::new();
 
         for config in configs {
             // Skip disabled providers
@@ -47,143 +62,44 @@ impl ProviderRegistry {
                 }
             };
 
-            // Create provider instance based on type
-            let provider: Box<dyn AnthropicProvider> = match config.provider_type.as_str() {
-                // OpenAI
-                "openai" => Box::new(OpenAIProvider::new(
-                    config.name.clone(),
-                    api_key,
-                    config.base_url.clone().unwrap_or_else(|| "https://api.openai.com/v1".to_string()),
-                    config.models.clone(),
-                    config.oauth_provider.clone(),
-                    token_store.clone(),
-                )),
+            let client = http::client(config, defaults);
+            let provider = build(config, api_key, &token_store, &client)?;
 
-                // Anthropic-compatible providers
-                "anthropic" => Box::new(AnthropicCompatibleProvider::new(
-                    config.name.clone(),
-                    api_key,
-                    config.base_url.clone().unwrap_or_else(|| "https://api.anthropic.com".to_string()),
-                    config.models.clone(),
-                    config.oauth_provider.clone(),
-                    token_store.clone(),
-                )),
-                "z.ai" => Box::new(AnthropicCompatibleProvider::zai(
-                    api_key,
-                    config.models.clone(),
-                    token_store.clone(),
-                )),
-                "minimax" => Box::new(AnthropicCompatibleProvider::minimax(
-                    api_key,
-                    config.models.clone(),
-                    token_store.clone(),
-                )),
-                "zenmux" => Box::new(AnthropicCompatibleProvider::zenmux(
-                    api_key,
-                    config.models.clone(),
-                    token_store.clone(),
-                )),
-                "kimi-coding" => Box::new(AnthropicCompatibleProvider::kimi_coding(
-                    api_key,
-                    config.models.clone(),
-                    token_store.clone(),
-                )),
-
-                // OpenAI-compatible providers
-                "openrouter" => Box::new(OpenAIProvider::openrouter(
-                    config.name.clone(),
-                    api_key,
-                    config.models.clone(),
-                )),
-                "deepinfra" => Box::new(OpenAIProvider::deepinfra(
-                    config.name.clone(),
-                    api_key,
-                    config.models.clone(),
-                )),
-                "novita" => Box::new(OpenAIProvider::novita(
-                    config.name.clone(),
-                    api_key,
-                    config.models.clone(),
-                )),
-                "baseten" => Box::new(OpenAIProvider::baseten(
-                    config.name.clone(),
-                    api_key,
-                    config.models.clone(),
-                )),
-                "together" => Box::new(OpenAIProvider::together(
-                    config.name.clone(),
-                    api_key,
-                    config.models.clone(),
-                )),
-                "fireworks" => Box::new(OpenAIProvider::fireworks(
-                    config.name.clone(),
-                    api_key,
-                    config.models.clone(),
-                )),
-                "groq" => Box::new(OpenAIProvider::groq(
-                    config.name.clone(),
-                    api_key,
-                    config.models.clone(),
-                )),
-                "nebius" => Box::new(OpenAIProvider::nebius(
-                    config.name.clone(),
-                    api_key,
-                    config.models.clone(),
-                )),
-                "cerebras" => Box::new(OpenAIProvider::cerebras(
+            // A rejected primary key fails over to the secondary
+            let provider: Box<dyn AnthropicProvider> = match &config.secondary_api_key {
+                Some(secondary) if config.auth_type == super::AuthType::ApiKey => Box::new(BlueGreen::new(
                     config.name.clone(),
-                    api_key,
-                    config.models.clone(),
+                    provider,
+                    build(config, secondary.clone(), &token_store, &client)?,
                 )),
-                "moonshot" => Box::new(OpenAIProvider::moonshot(
-                    config.name.clone(),
-                    api_key,
-                    config.models.clone(),
-                )),
-
-                // Google Gemini (supports OAuth, API Key, Vertex AI)
-                "gemini" => {
-                    let api_key_opt = if config.auth_type == super::AuthType::ApiKey {
-                        Some(api_key.clone())
-                    } else {
-                        None
-                    };
-
-                    Box::new(GeminiProvider::new(
-                        config.name.clone(),
-                        api_key_opt,
-                        config.base_url.clone(),
-                        config.models.clone(),
-                        HashMap::new(), // custom headers
-                        config.oauth_provider.clone(),
-                        token_store.clone(),
-                        None, // No project_id/location for Gemini (AI Studio/OAuth only)
-                        None,
-                    ))
-                }
-
-                "vertex-ai" => {
-                    // Vertex AI provider (separate from Gemini)
-                    // Uses Google Cloud Vertex AI with ADC authentication
-                    Box::new(GeminiProvider::new(
-                        config.name.clone(),
-                        None, // No API key for Vertex AI (uses ADC)
-                        config.base_url.clone(),
-                        config.models.clone(),
-                        HashMap::new(), // custom headers
-                        None, // No OAuth for Vertex AI
-                        token_store.clone(),
-                        config.project_id.clone(), // GCP project ID
-                        config.location.clone(),   // GCP location
-                    ))
-                }
+                _ => provider,
+            };
 
-                other => {
-                    return Err(ProviderError::ConfigError(
-                        format!("Unknown provider type: {}", other)
-                    ));
+            // Limits apply to the upstream calls, whichever API the client used
+            let timeouts = config.timeouts.clone().unwrap_or_default().with_defaults(defaults);
+            let provider: Box<dyn AnthropicProvider> = Box::new(TimeLimited::new(provider, &timeouts));
+            let provider: Box<dyn AnthropicProvider> = match (config.stream_only, config.synthetic_stream) {
+                (true, true) => {
+                    return Err(ProviderError::ConfigError(format!(
+                        "Provider '{}' can't set both stream_only and synthetic_stream",
+                        config.name
+                    )));
                 }
+                (true, false) => Box::new(StreamOnly::new(provider)),
+                (false, true) => Box::new(SyntheticStream::new(provider)),
+                (false, false) => provider,
             };
+            // Outermost, so waiting for a slot doesn't count against the timeouts
+            let provider: Box<dyn AnthropicProvider> =
+                match config.http.as_ref().and_then(|h| Some((h, h.max_concurrent?))) {
+                    Some((settings, max_concurrent)) => {
+                        let gate = gates
+                            .entry(pool::host_key(config))
+                            .or_insert_with(|| Arc::new(HostGate::new(max_concurrent, settings.reserved(max_concurrent))));
+                        Box::new(HostLimited::new(provider, gate.clone()))
+                    }
+                    None => provider,
+                };
 
             // NOTE: models field in provider config is deprecated
             // Model mappings are now defined in [[models]] section
@@ -196,6 +112,12 @@ impl ProviderRegistry {
         Ok(registry)
     }
 
+    /// Register a provider built outside of the config
+    #[cfg(test)]
+    pub fn insert(&mut self, name: &str, provider: Box<dyn AnthropicProvider>) {
+        self.providers.insert(name.to_string(), Arc::new(provider));
+    }
+
     /// Get a provider by name
     pub fn get_provider(&self, name: &str) -> Option<Arc<Box<dyn AnthropicProvider>>> {
         self.providers.get(name).cloned()
@@ -237,6 +159,138 @@ impl Default for ProviderRegistry {
     }
 }
 
+/// Create the provider instance of a config entry with the given key
+///
+/// The HTTP client is per provider entry and carries its attribution headers.
+fn build(
+    config: &ProviderConfig,
+    api_key: String,
+    token_store: &Option<TokenStore>,
+    client: &reqwest::Client,
+) -> Result<Box<dyn AnthropicProvider>, ProviderError> {
+    let openai = |provider: OpenAIProvider| -> Box<dyn AnthropicProvider> {
+        Box::new(
+            provider
+                .with_client(client.clone())
+                .with_signing(config.signing.clone())
+                .with_stream_quirks(config.stream_quirks.clone())
+                .with_account(config.organization.clone(), config.project.clone())
+                .with_parallel_tool_calls(config.parallel_tool_calls),
+        )
+    };
+    let anthropic = |provider: AnthropicCompatibleProvider| -> Box<dyn AnthropicProvider> {
+        Box::new(
+            provider
+                .with_client(client.clone())
+                .with_signing(config.signing.clone())
+                .with_betas(config.betas.clone()),
+        )
+    };
+
+    // Create provider instance based on type
+    let provider: Box<dyn AnthropicProvider> = match config.provider_type.as_str() {
+        // OpenAI
+        "openai" => openai(OpenAIProvider::new(
+            config.name.clone(),
+            api_key,
+            config.base_url.clone().unwrap_or_else(|| "https://api.openai.com/v1".to_string()),
+            config.models.clone(),
+            config.oauth_provider.clone(),
+            token_store.clone(),
+        )),
+
+        // Anthropic-compatible providers
+        "anthropic" => anthropic(AnthropicCompatibleProvider::new(
+            config.name.clone(),
+            api_key,
+            config.base_url.clone().unwrap_or_else(|| "https://api.anthropic.com".to_string()),
+            config.models.clone(),
+            config.oauth_provider.clone(),
+            token_store.clone(),
+        ).native()),
+        "z.ai" => anthropic(AnthropicCompatibleProvider::zai(
+            api_key,
+            config.models.clone(),
+            token_store.clone(),
+        )),
+        "minimax" => anthropic(AnthropicCompatibleProvider::minimax(
+            api_key,
+            config.models.clone(),
+            token_store.clone(),
+        )),
+        "zenmux" => anthropic(AnthropicCompatibleProvider::zenmux(
+            api_key,
+            config.models.clone(),
+            token_store.clone(),
+        )),
+        "kimi-coding" => anthropic(AnthropicCompatibleProvider::kimi_coding(
+            api_key,
+            config.models.clone(),
+            token_store.clone(),
+        )),
+
+        // OpenAI-compatible providers
+        "openrouter" => openai(OpenAIProvider::openrouter(config.name.clone(), api_key, config.models.clone())),
+        "deepinfra" => openai(OpenAIProvider::deepinfra(config.name.clone(), api_key, config.models.clone())),
+        "novita" => openai(OpenAIProvider::novita(config.name.clone(), api_key, config.models.clone())),
+        "baseten" => openai(OpenAIProvider::baseten(config.name.clone(), api_key, config.models.clone())),
+        "together" => openai(OpenAIProvider::together(config.name.clone(), api_key, config.models.clone())),
+        "fireworks" => openai(OpenAIProvider::fireworks(config.name.clone(), api_key, config.models.clone())),
+        "groq" => openai(OpenAIProvider::groq(config.name.clone(), api_key, config.models.clone())),
+        "nebius" => openai(OpenAIProvider::nebius(config.name.clone(), api_key, config.models.clone())),
+        "cerebras" => openai(OpenAIProvider::cerebras(config.name.clone(), api_key, config.models.clone())),
+        "moonshot" => openai(
+            OpenAIProvider::moonshot(config.name.clone(), api_key, config.models.clone())
+                .with_context_cache(config.context_cache.clone()),
+        ),
+
+        // Google Gemini (supports OAuth, API Key, Vertex AI)
+        "gemini" => {
+            let api_key_opt = if config.auth_type == super::AuthType::ApiKey {
+                Some(api_key.clone())
+            } else {
+                None
+            };
+
+            Box::new(GeminiProvider::new(
+                config.name.clone(),
+                api_key_opt,
+                config.base_url.clone(),
+                config.models.clone(),
+                HashMap::new(), // custom headers
+                config.oauth_provider.clone(),
+                token_store.clone(),
+                None, // No project_id/location for Gemini (AI Studio/OAuth only)
+                None,
+            ).with_client(client.clone()).with_signing(config.signing.clone()).with_context_cache(config.context_cache.clone()))
+        }
+
+        "vertex-ai" => {
+            // Vertex AI provider (separate from Gemini)
+            // Uses Google Cloud Vertex AI with ADC authentication
+            Box::new(GeminiProvider::new(
+                config.name.clone(),
+                None, // No API key for Vertex AI (uses ADC)
+                config.base_url.clone(),
+                config.models.clone(),
+                HashMap::new(), // custom headers
+                None, // No OAuth for Vertex AI
+                token_store.clone(),
+                config.project_id.clone(), // GCP project ID
+                config.location.clone(),   // GCP location
+            ).with_client(client.clone()).with_signing(config.signing.clone()).with_context_cache(config.context_cache.clone()))
+        }
+
+        other => {
+            return Err(ProviderError::ConfigError(
+                format!("Unknown provider type: {}", other)
+            ));
+        }
+    };
+
+    Ok(provider)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,300 @@
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::{Method, Request, RequestBuilder, Response, Url};
+use ring::{digest, hmac};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Signs outbound requests for API gateways that require it
+///
+/// Example:
+/// ```toml
+/// [providers.signing]
+/// type = "hmac"
+/// secret = "$GATEWAY_SECRET"
+/// key_id = "ccm"                # optional, sent as X-Signature-Key-Id
+///
+/// [providers.signing]
+/// type = "aws_sigv4"
+/// access_key_id = "$AWS_ACCESS_KEY_ID"
+/// secret_access_key = "$AWS_SECRET_ACCESS_KEY"
+/// region = "us-east-1"
+/// service = "execute-api"
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RequestSigning {
+    /// HMAC-SHA256 over the timestamp, method, path and body hash
+    Hmac {
+        secret: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key_id: Option<String>,
+        /// Header carrying the hex signature
+        #[serde(default = "default_signature_header")]
+        header: String,
+    },
+    /// AWS Signature Version 4 (API Gateway, Bedrock-style gateways)
+    AwsSigv4 {
+        access_key_id: String,
+        secret_access_key: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        session_token: Option<String>,
+        region: String,
+        service: String,
+    },
+}
+
+fn default_signature_header() -> String {
+    "X-Signature".to_string()
+}
+
+impl RequestSigning {
+    /// Resolve `$VAR` secrets from the environment
+    ///
+    /// Returns the name of the first variable that is not set.
+    pub fn resolve_env_vars(&mut self) -> Result<(), String> {
+        let fields = match self {
+            Self::Hmac { secret, key_id, .. } => vec![Some(secret), key_id.as_mut()],
+            Self::AwsSigv4 {
+                access_key_id,
+                secret_access_key,
+                session_token,
+                ..
+            } => vec![Some(access_key_id), Some(secret_access_key), session_token.as_mut()],
+        };
+        for field in fields.into_iter().flatten() {
+            if let Some(var) = field.strip_prefix('$') {
+                let value = std::env::var(var).map_err(|_| var.to_string())?;
+                *field = value;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the signature headers to a built request
+    pub fn sign(&self, request: &mut Request) {
+        let body = request.body().and_then(|body| body.as_bytes()).unwrap_or_default().to_vec();
+        let headers = self.headers(request.method(), request.url(), &body, Utc::now());
+        for (name, value) in headers {
+            match (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(&value)) {
+                (Ok(name), Ok(value)) => {
+                    request.headers_mut().insert(name, value);
+                }
+                _ => tracing::warn!("Skipping invalid signature header '{}'", name),
+            }
+        }
+    }
+
+    /// Signature headers of a request sent at `now`
+    fn headers(&self, method: &Method, url: &Url, body: &[u8], now: DateTime<Utc>) -> Vec<(String, String)> {
+        let body_hash = hex(digest::digest(&digest::SHA256, body).as_ref());
+        match self {
+            Self::Hmac { secret, key_id, header } => {
+                let timestamp = now.timestamp().to_string();
+                let payload = format!("{}\n{}\n{}\n{}", timestamp, method, url.path(), body_hash);
+                let signature = hex(hmac_sha256(secret.as_bytes(), &payload).as_ref());
+                let mut headers = vec![
+                    ("X-Signature-Timestamp".to_string(), timestamp),
+                    (header.clone(), signature),
+                ];
+                if let Some(key_id) = key_id {
+                    headers.push(("X-Signature-Key-Id".to_string(), key_id.clone()));
+                }
+                headers
+            }
+            Self::AwsSigv4 {
+                access_key_id,
+                secret_access_key,
+                session_token,
+                region,
+                service,
+            } => {
+                let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+                let date = now.format("%Y%m%d").to_string();
+                let host = match url.port() {
+                    Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+                    None => url.host_str().unwrap_or_default().to_string(),
+                };
+
+                let mut signed = vec![("host", host), ("x-amz-date", amz_date.clone())];
+                if let Some(token) = session_token {
+                    signed.push(("x-amz-security-token", token.clone()));
+                }
+                let canonical_headers: String = signed.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+                let signed_headers = signed.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+                let canonical_request = format!(
+                    "{}\n{}\n{}\n{}\n{}\n{}",
+                    method,
+                    canonical_path(url),
+                    canonical_query(url),
+                    canonical_headers,
+                    signed_headers,
+                    body_hash
+                );
+
+                let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+                let string_to_sign = format!(
+                    "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+                    amz_date,
+                    scope,
+                    hex(digest::digest(&digest::SHA256, canonical_request.as_bytes()).as_ref())
+                );
+                let key = [date.as_str(), region, service, "aws4_request"]
+                    .iter()
+                    .fold(format!("AWS4{}", secret_access_key).into_bytes(), |key, part| {
+                        hmac_sha256(&key, part).as_ref().to_vec()
+                    });
+                let signature = hex(hmac_sha256(&key, &string_to_sign).as_ref());
+
+                let mut headers = vec![
+                    (
+                        "Authorization".to_string(),
+                        format!(
+                            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                            access_key_id, scope, signed_headers, signature
+                        ),
+                    ),
+                    ("X-Amz-Date".to_string(), amz_date),
+                ];
+                if let Some(token) = session_token {
+                    headers.push(("X-Amz-Security-Token".to_string(), token.clone()));
+                }
+                headers
+            }
+        }
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> hmac::Tag {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// SigV4 URI encoding: everything but unreserved characters
+fn uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Path with each segment encoded twice, as SigV4 requires outside of S3
+fn canonical_path(url: &Url) -> String {
+    let path = url.path();
+    if path.is_empty() || path == "/" {
+        return "/".to_string();
+    }
+    path.split('/')
+        .map(|segment| uri_encode(&uri_encode(&percent_decode(segment))))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn canonical_query(url: &Url) -> String {
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(key, value)| (uri_encode(&key), uri_encode(&value)))
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Sending with an optional request signature
+pub trait SendSigned {
+    fn send_signed(self, signing: Option<&Arc<RequestSigning>>) -> Pending;
+}
+
+type Pending = Pin<Box<dyn Future<Output = reqwest::Result<Response>> + Send>>;
+
+impl SendSigned for RequestBuilder {
+    fn send_signed(self, signing: Option<&Arc<RequestSigning>>) -> Pending {
+        let signing = signing.cloned();
+        Box::pin(async move {
+            let Some(signing) = signing else {
+                return self.send().await;
+            };
+            let (client, request) = self.build_split();
+            let mut request = request?;
+            signing.sign(&mut request);
+            client.execute(request).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unset_secret_variable_is_an_error() {
+        let mut signing = RequestSigning::Hmac {
+            secret: "$CCM_TEST_UNSET_SIGNING_SECRET".to_string(),
+            key_id: Some("ccm".to_string()),
+            header: default_signature_header(),
+        };
+        assert_eq!(signing.resolve_env_vars(), Err("CCM_TEST_UNSET_SIGNING_SECRET".to_string()));
+    }
+
+    #[test]
+    fn test_sigv4_vanilla() {
+        // "get-vanilla" from the AWS Signature Version 4 test suite
+        let signing = RequestSigning::AwsSigv4 {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+            region: "us-east-1".to_string(),
+            service: "service".to_string(),
+        };
+        let now = DateTime::parse_from_rfc3339("2015-08-30T12:36:00Z").unwrap().with_timezone(&Utc);
+        let url = Url::parse("https://example.amazonaws.com/").unwrap();
+        let headers = signing.headers(&Method::GET, &url, b"", now);
+        assert_eq!(
+            headers[0].1,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+        assert_eq!(headers[1].1, "20150830T123600Z");
+
+        let hmac: RequestSigning = toml::from_str("type = \"hmac\"\nsecret = \"s\"").unwrap();
+        let headers = hmac.headers(&Method::POST, &url, b"{}", now);
+        assert_eq!(headers[0], ("X-Signature-Timestamp".to_string(), "1440938160".to_string()));
+        assert_eq!(headers[1].0, "X-Signature");
+        assert_eq!(headers[1].1.len(), 64);
+    }
+}
@@ -0,0 +1,67 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::Stream;
+use std::pin::Pin;
+
+use super::error::ProviderError;
+use super::streaming::collect_response;
+use super::{AnthropicProvider, FimRequest, FimResponse, OutboundRequest, ProviderResponse};
+use crate::models::{AnthropicRequest, CountTokensRequest, CountTokensResponse};
+
+/// Serves non-streaming requests from the provider's streaming API
+///
+/// For backends whose non-streaming endpoint is missing or unreliable: the
+/// stream is aggregated into a single Anthropic response.
+pub struct StreamOnly {
+    inner: Box<dyn AnthropicProvider>,
+}
+
+impl StreamOnly {
+    pub fn new(inner: Box<dyn AnthropicProvider>) -> Self {
+        Self { inner }
+    }
+}
+
+#[async_trait]
+impl AnthropicProvider for StreamOnly {
+    async fn send_message(&self, mut request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
+        request.stream = Some(true);
+        let stream = self.inner.send_message_stream(request).await?;
+        collect_response(stream).await
+    }
+
+    async fn send_message_stream(
+        &self,
+        request: AnthropicRequest,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<Bytes, ProviderError>> + Send>>, ProviderError> {
+        self.inner.send_message_stream(request).await
+    }
+
+    async fn count_tokens(&self, request: CountTokensRequest) -> Result<CountTokensResponse, ProviderError> {
+        self.inner.count_tokens(request).await
+    }
+
+    fn supports_model(&self, model: &str) -> bool {
+        self.inner.supports_model(model)
+    }
+
+    fn supports_beta(&self, beta: &str) -> bool {
+        self.inner.supports_beta(beta)
+    }
+
+    fn supports_verbosity(&self) -> bool {
+        self.inner.supports_verbosity()
+    }
+
+    fn supports_documents(&self) -> bool {
+        self.inner.supports_documents()
+    }
+
+    fn preview_request(&self, request: &AnthropicRequest) -> Result<OutboundRequest, ProviderError> {
+        self.inner.preview_request(request)
+    }
+
+    async fn complete_fim(&self, request: FimRequest) -> Result<FimResponse, ProviderError> {
+        self.inner.complete_fim(request).await
+    }
+}
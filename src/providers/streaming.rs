@@ -1,9 +1,13 @@
 use bytes::Bytes;
 use futures::stream::Stream;
 use pin_project::pin_project;
+use std::collections::HashMap;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
+use super::{ProviderError, ProviderResponse, Usage};
+use crate::models::ContentBlock;
+
 /// SSE event from provider
 #[derive(Debug, Clone)]
 pub struct SseEvent {
@@ -25,6 +29,46 @@ impl SseEvent {
     }
 }
 
+/// Buffer for line-based streams (SSE, NDJSON) that only decodes complete lines
+///
+/// A chunk can end in the middle of a multibyte character, so decoding chunks one
+/// at a time would turn both halves into U+FFFD. Delimiters are ASCII, so anything
+/// up to one is whole UTF-8.
+#[derive(Debug, Default)]
+pub struct LineBuffer(Vec<u8>);
+
+impl LineBuffer {
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    /// Take all complete lines (up to and including the last `\n`)
+    pub fn lines(&mut self) -> Option<String> {
+        let end = self.0.iter().rposition(|b| *b == b'\n')? + 1;
+        Some(self.drain(end))
+    }
+
+    /// Take the next complete SSE event (up to and including its blank line)
+    pub fn event(&mut self) -> Option<String> {
+        let end = self.0.windows(2).position(|w| w == b"\n\n")? + 2;
+        Some(self.drain(end))
+    }
+
+    /// Take whatever is left (an unterminated line or event)
+    pub fn rest(&mut self) -> String {
+        self.drain(self.0.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn drain(&mut self, end: usize) -> String {
+        let bytes: Vec<u8> = self.0.drain(..end).collect();
+        String::from_utf8(bytes).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+    }
+}
+
 /// Parse SSE events from a byte stream
 pub fn parse_sse_events(input: &str) -> Vec<SseEvent> {
     let mut events = Vec::new();
@@ -63,6 +107,339 @@ pub fn parse_sse_events(input: &str) -> Vec<SseEvent> {
     events
 }
 
+/// Rewrite an SSE byte stream line by line
+/// Partial lines are buffered across chunks so `f` always sees complete lines
+pub fn map_sse_lines<S, E, F>(stream: S, f: F) -> impl Stream<Item = Result<Bytes, E>> + Send
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + Unpin + 'static,
+    E: Send + 'static,
+    F: Fn(&str) -> String + Send + 'static,
+{
+    use futures::StreamExt;
+
+    futures::stream::unfold(
+        (stream, LineBuffer::default(), f, false),
+        |(mut stream, mut buffer, f, done)| async move {
+            if done {
+                return None;
+            }
+            loop {
+                match stream.next().await {
+                    Some(Ok(bytes)) => {
+                        buffer.push(&bytes);
+                        let Some(lines) = buffer.lines() else {
+                            continue;
+                        };
+                        let output: String = lines
+                            .split_inclusive('\n')
+                            .map(|line| match line.strip_suffix('\n') {
+                                Some(content) => format!("{}\n", f(content)),
+                                None => f(line),
+                            })
+                            .collect();
+                        return Some((Ok(Bytes::from(output)), (stream, buffer, f, false)));
+                    }
+                    Some(Err(e)) => return Some((Err(e), (stream, buffer, f, false))),
+                    None => {
+                        if buffer.is_empty() {
+                            return None;
+                        }
+                        let output = f(&buffer.rest());
+                        return Some((Ok(Bytes::from(output)), (stream, buffer, f, true)));
+                    }
+                }
+            }
+        },
+    )
+}
+
+/// Insert Anthropic `ping` events while the stream is idle
+///
+/// Pings only go in at event boundaries, so a partially received event is never split.
+pub fn with_heartbeat<S, E>(stream: S, interval: std::time::Duration) -> impl Stream<Item = Result<Bytes, E>> + Send
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + Unpin + 'static,
+    E: Send + 'static,
+{
+    use futures::StreamExt;
+
+    futures::stream::unfold((stream, true), move |(mut stream, at_boundary)| async move {
+        loop {
+            match tokio::time::timeout(interval, stream.next()).await {
+                Ok(Some(Ok(bytes))) => {
+                    let at_boundary = bytes.ends_with(b"\n\n") || (at_boundary && bytes.is_empty());
+                    return Some((Ok(bytes), (stream, at_boundary)));
+                }
+                Ok(Some(Err(e))) => return Some((Err(e), (stream, at_boundary))),
+                Ok(None) => return None,
+                Err(_) if at_boundary => {
+                    let ping = Bytes::from_static(b"event: ping\ndata: {\"type\": \"ping\"}\n\n");
+                    return Some((Ok(ping), (stream, true)));
+                }
+                Err(_) => continue,
+            }
+        }
+    })
+}
+
+/// Aggregate an Anthropic SSE stream into the equivalent non-streaming response
+pub async fn collect_response<S>(mut stream: S) -> Result<ProviderResponse, ProviderError>
+where
+    S: Stream<Item = Result<Bytes, ProviderError>> + Unpin,
+{
+    use futures::StreamExt;
+
+    let mut collector = ResponseCollector::default();
+    let mut buffer = LineBuffer::default();
+    while let Some(chunk) = stream.next().await {
+        buffer.push(&chunk?);
+        while let Some(event) = buffer.event() {
+            for event in parse_sse_events(&event) {
+                if let Ok(data) = serde_json::from_str(&event.data) {
+                    collector.observe(data)?;
+                }
+            }
+        }
+    }
+    collector.finish()
+}
+
+/// Stream events of a complete response, following its `message_start`
+///
+/// Each content block is sent as a single delta.
+pub fn response_events(response: &ProviderResponse) -> String {
+    use serde_json::json;
+
+    let mut events = Vec::new();
+    for (index, block) in response.content.iter().enumerate() {
+        let mut block = serde_json::to_value(block).unwrap_or_default();
+        let deltas = match block["type"].as_str().unwrap_or_default() {
+            "text" => vec![json!({ "type": "text_delta", "text": block["text"].take() })],
+            "tool_use" => {
+                let input = std::mem::replace(&mut block["input"], json!({}));
+                vec![json!({ "type": "input_json_delta", "partial_json": input.to_string() })]
+            }
+            "thinking" => vec![
+                json!({ "type": "thinking_delta", "thinking": block["thinking"].take() }),
+                json!({ "type": "signature_delta", "signature": block["signature"].take() }),
+            ],
+            _ => Vec::new(),
+        };
+        // The start event carries the block without its streamed content
+        for field in ["text", "thinking", "signature"] {
+            if block[field].is_null() && block.get(field).is_some() {
+                block[field] = "".into();
+            }
+        }
+        events.push(("content_block_start", json!({ "type": "content_block_start", "index": index, "content_block": block })));
+        for delta in deltas {
+            events.push(("content_block_delta", json!({ "type": "content_block_delta", "index": index, "delta": delta })));
+        }
+        events.push(("content_block_stop", json!({ "type": "content_block_stop", "index": index })));
+    }
+    events.push((
+        "message_delta",
+        json!({
+            "type": "message_delta",
+            "delta": { "stop_reason": response.stop_reason, "stop_sequence": response.stop_sequence },
+            "usage": response.usage,
+        }),
+    ));
+    events.push(("message_stop", json!({ "type": "message_stop" })));
+
+    events
+        .into_iter()
+        .map(|(event, data)| SseEvent { event: Some(event.to_string()), data: data.to_string() }.to_sse_string())
+        .collect()
+}
+
+/// Response state built up from stream events
+#[derive(Default)]
+struct ResponseCollector {
+    message: Option<serde_json::Value>,
+    /// Content blocks by index, with the tool input JSON received so far
+    blocks: std::collections::BTreeMap<u64, (serde_json::Value, String)>,
+    stop_reason: Option<String>,
+    stop_sequence: Option<String>,
+    /// Usage reported by `message_delta` (output tokens, and input tokens when
+    /// only known at the end)
+    final_usage: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ResponseCollector {
+    fn observe(&mut self, event: serde_json::Value) -> Result<(), ProviderError> {
+        match event["type"].as_str().unwrap_or_default() {
+            "message_start" => self.message = Some(event["message"].clone()),
+            "content_block_start" => {
+                let index = event["index"].as_u64().unwrap_or(0);
+                self.blocks.insert(index, (event["content_block"].clone(), String::new()));
+            }
+            "content_block_delta" => {
+                let index = event["index"].as_u64().unwrap_or(0);
+                let Some((block, input_json)) = self.blocks.get_mut(&index) else {
+                    return Ok(());
+                };
+                let delta = &event["delta"];
+                let (field, value) = match delta["type"].as_str().unwrap_or_default() {
+                    "text_delta" => ("text", &delta["text"]),
+                    "thinking_delta" => ("thinking", &delta["thinking"]),
+                    "signature_delta" => ("signature", &delta["signature"]),
+                    "input_json_delta" => {
+                        input_json.push_str(delta["partial_json"].as_str().unwrap_or_default());
+                        return Ok(());
+                    }
+                    _ => return Ok(()),
+                };
+                let text = format!("{}{}", block[field].as_str().unwrap_or_default(), value.as_str().unwrap_or_default());
+                block[field] = text.into();
+            }
+            "message_delta" => {
+                self.stop_reason = event["delta"]["stop_reason"].as_str().map(str::to_string);
+                self.stop_sequence = event["delta"]["stop_sequence"].as_str().map(str::to_string);
+                if let Some(usage) = event["usage"].as_object() {
+                    self.final_usage.extend(usage.clone());
+                }
+            }
+            "error" => {
+                return Err(ProviderError::ApiError {
+                    status: 502,
+                    message: event["error"]["message"].as_str().unwrap_or("stream error").to_string(),
+                });
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<ProviderResponse, ProviderError> {
+        let message = self.message.ok_or_else(|| ProviderError::ApiError {
+            status: 502,
+            message: "Stream ended without a message".to_string(),
+        })?;
+        let mut content = Vec::new();
+        for (mut block, input_json) in self.blocks.into_values() {
+            if !input_json.is_empty() {
+                block["input"] = serde_json::from_str(&input_json)?;
+            }
+            content.push(serde_json::from_value::<ContentBlock>(block)?);
+        }
+        let mut usage = message["usage"].as_object().cloned().unwrap_or_default();
+        usage.extend(self.final_usage);
+        let usage: Usage = serde_json::from_value(usage.into()).unwrap_or_default();
+        Ok(ProviderResponse {
+            id: message["id"].as_str().unwrap_or_default().to_string(),
+            r#type: "message".to_string(),
+            role: "assistant".to_string(),
+            content,
+            model: message["model"].as_str().unwrap_or_default().to_string(),
+            stop_reason: self.stop_reason,
+            stop_sequence: self.stop_sequence,
+            usage,
+        })
+    }
+}
+
+/// Hold back Anthropic `input_json_delta` fragments and emit each tool's input as a
+/// single delta just before its `content_block_stop`
+pub fn coalesce_tool_input<S, E>(stream: S) -> impl Stream<Item = Result<Bytes, E>> + Send
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + Unpin + 'static,
+    E: Send + 'static,
+{
+    use futures::StreamExt;
+
+    futures::stream::unfold(
+        (stream, LineBuffer::default(), HashMap::new(), false),
+        |(mut stream, mut buffer, mut pending, done)| async move {
+            if done {
+                return None;
+            }
+            loop {
+                match stream.next().await {
+                    Some(Ok(bytes)) => {
+                        buffer.push(&bytes);
+                        let mut output = String::new();
+                        while let Some(event) = buffer.event() {
+                            output.push_str(&coalesce_event(&event, &mut pending));
+                        }
+                        if output.is_empty() {
+                            continue;
+                        }
+                        return Some((Ok(Bytes::from(output)), (stream, buffer, pending, false)));
+                    }
+                    Some(Err(e)) => return Some((Err(e), (stream, buffer, pending, false))),
+                    None => {
+                        if buffer.is_empty() {
+                            return None;
+                        }
+                        return Some((Ok(Bytes::from(buffer.rest())), (stream, buffer, pending, true)));
+                    }
+                }
+            }
+        },
+    )
+}
+
+/// Buffer or release one SSE event for `coalesce_tool_input`
+fn coalesce_event(event: &str, pending: &mut HashMap<u64, String>) -> String {
+    let Some(data) = parse_sse_events(event)
+        .into_iter()
+        .next()
+        .and_then(|e| serde_json::from_str::<serde_json::Value>(&e.data).ok())
+    else {
+        return event.to_string();
+    };
+    let index = data["index"].as_u64().unwrap_or(0);
+
+    match data["type"].as_str() {
+        Some("content_block_delta") if data["delta"]["type"] == "input_json_delta" => {
+            let fragment = data["delta"]["partial_json"].as_str().unwrap_or_default();
+            pending.entry(index).or_default().push_str(fragment);
+            String::new()
+        }
+        Some("content_block_stop") => match pending.remove(&index) {
+            Some(input) => {
+                let delta = SseEvent {
+                    event: Some("content_block_delta".to_string()),
+                    data: serde_json::json!({
+                        "type": "content_block_delta",
+                        "index": index,
+                        "delta": { "type": "input_json_delta", "partial_json": input },
+                    })
+                    .to_string(),
+                };
+                format!("{}{}", delta.to_sse_string(), event)
+            }
+            None => event.to_string(),
+        },
+        _ => event.to_string(),
+    }
+}
+
+/// Read up to the first content delta (or the end of the stream) and return a
+/// stream that replays what was read, so the wait can be bounded by a timeout
+pub async fn first_token<E>(
+    stream: Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>,
+) -> Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>
+where
+    E: Send + 'static,
+{
+    use futures::StreamExt;
+
+    let mut stream = stream.fuse();
+    let mut head = Vec::new();
+    while let Some(item) = stream.next().await {
+        let is_token = item
+            .as_ref()
+            .map_or(true, |b| b.windows(19).any(|w| w == b"content_block_delta"));
+        head.push(item);
+        if is_token {
+            break;
+        }
+    }
+    Box::pin(futures::stream::iter(head).chain(stream))
+}
+
 /// Stream adapter that converts a reqwest Response stream into SSE events
 #[pin_project]
 pub struct SseStream<S> {
@@ -151,6 +528,161 @@ mod tests {
         assert_eq!(events[1].event.as_deref(), Some("delta"));
     }
 
+    #[tokio::test]
+    async fn test_collect_response() {
+        let events = [
+            r#"{"type":"message_start","message":{"id":"msg_1","model":"m","usage":{"input_tokens":12,"output_tokens":1}}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Let me "}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"check."}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_1","name":"read","input":{}}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"path\":"}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"\"a.rs\"}"}}"#,
+            r#"{"type":"content_block_stop","index":1}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use"},"usage":{"output_tokens":20}}"#,
+            r#"{"type":"message_stop"}"#,
+        ];
+        let text: String = events.iter().map(|data| format!("data: {}\n\n", data)).collect();
+        // Split mid-event to exercise buffering
+        let (a, b) = text.split_at(100);
+        let chunks = vec![Ok(Bytes::from(a.to_string())), Ok(Bytes::from(b.to_string()))];
+
+        let response = collect_response(futures::stream::iter(chunks)).await.unwrap();
+        assert_eq!(response.id, "msg_1");
+        assert_eq!(response.stop_reason.as_deref(), Some("tool_use"));
+        assert_eq!((response.usage.input_tokens, response.usage.output_tokens), (12, 20));
+        assert!(matches!(&response.content[0], ContentBlock::Text { text } if text == "Let me check."));
+        assert!(matches!(&response.content[1], ContentBlock::ToolUse { input, .. } if input["path"] == "a.rs"));
+
+        let error = r#"data: {"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        let chunks = vec![Ok(Bytes::from(format!("{}\n\n", error)))];
+        assert!(collect_response(futures::stream::iter(chunks)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_response_events_round_trip() {
+        let response: ProviderResponse = serde_json::from_value(serde_json::json!({
+            "id": "msg_1", "type": "message", "role": "assistant", "model": "m",
+            "content": [
+                { "type": "thinking", "thinking": "Look first.", "signature": "sig" },
+                { "type": "tool_use", "id": "toolu_1", "name": "read", "input": { "path": "a.rs" } },
+            ],
+            "stop_reason": "tool_use", "stop_sequence": null,
+            "usage": { "input_tokens": 12, "output_tokens": 20 },
+        }))
+        .unwrap();
+        let start = r#"data: {"type":"message_start","message":{"id":"msg_1","model":"m","usage":{"input_tokens":0,"output_tokens":0}}}"#;
+        let text = format!("{}\n\n{}", start, response_events(&response));
+
+        let collected = collect_response(futures::stream::iter(vec![Ok(Bytes::from(text))])).await.unwrap();
+        assert_eq!(serde_json::to_value(&collected.content).unwrap(), serde_json::to_value(&response.content).unwrap());
+        assert_eq!(collected.stop_reason.as_deref(), Some("tool_use"));
+        assert_eq!((collected.usage.input_tokens, collected.usage.output_tokens), (12, 20));
+    }
+
+    #[tokio::test]
+    async fn test_map_sse_lines_buffers_partial_lines() {
+        use futures::StreamExt;
+
+        let chunks: Vec<Result<Bytes, ()>> = vec![
+            Ok(Bytes::from("data: {\"na")),
+            Ok(Bytes::from("me\":\"a\"}\n\ndata: x")),
+            Ok(Bytes::from("\n\n")),
+        ];
+        let mapped = map_sse_lines(futures::stream::iter(chunks), |line| line.replace("\"a\"", "\"b\""));
+        let output: Vec<_> = mapped.collect().await;
+        let text: String = output
+            .into_iter()
+            .map(|r| String::from_utf8(r.unwrap().to_vec()).unwrap())
+            .collect();
+
+        assert_eq!(text, "data: {\"name\":\"b\"}\n\ndata: x\n\n");
+    }
+
+    #[tokio::test]
+    async fn test_multibyte_characters_split_across_chunks() {
+        use futures::StreamExt;
+
+        let input = "data: {\"text\":\"héllo 世界\"}\n\n".as_bytes();
+        // Cut inside 'é' and inside '世'
+        let cuts = [17, input.len() - 9];
+        let chunks = || -> Vec<Result<Bytes, ()>> {
+            vec![
+                Ok(Bytes::copy_from_slice(&input[..cuts[0]])),
+                Ok(Bytes::copy_from_slice(&input[cuts[0]..cuts[1]])),
+                Ok(Bytes::copy_from_slice(&input[cuts[1]..])),
+            ]
+        };
+        let text = |output: Vec<Result<Bytes, ()>>| -> String {
+            output.into_iter().map(|r| String::from_utf8(r.unwrap().to_vec()).unwrap()).collect()
+        };
+
+        let lines = map_sse_lines(futures::stream::iter(chunks()), str::to_string).collect().await;
+        assert_eq!(text(lines).as_bytes(), input);
+        let events = coalesce_tool_input(futures::stream::iter(chunks())).collect().await;
+        assert_eq!(text(events).as_bytes(), input);
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_waits_for_event_boundary() {
+        use futures::StreamExt;
+
+        let interval = std::time::Duration::from_millis(10);
+        let complete: Result<Bytes, ()> = Ok(Bytes::from("event: message_start\ndata: {}\n\n"));
+        let partial: Result<Bytes, ()> = Ok(Bytes::from("event: content_block_delta\n"));
+
+        // Idle after a complete event: ping
+        let upstream = futures::stream::iter(vec![complete.clone()]).chain(futures::stream::pending());
+        let mut stream = Box::pin(with_heartbeat(Box::pin(upstream), interval));
+        stream.next().await;
+        let ping = stream.next().await.unwrap().unwrap();
+        assert_eq!(&ping[..], b"event: ping\ndata: {\"type\": \"ping\"}\n\n");
+
+        // Idle in the middle of an event: wait
+        let upstream = futures::stream::iter(vec![complete, partial]).chain(futures::stream::pending());
+        let mut stream = Box::pin(with_heartbeat(Box::pin(upstream), interval));
+        stream.next().await;
+        stream.next().await;
+        assert!(tokio::time::timeout(interval * 5, stream.next()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_coalesce_tool_input() {
+        use futures::StreamExt;
+
+        let chunks: Vec<Result<Bytes, ()>> = vec![
+            Ok(Bytes::from("event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":1,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{\\\"a\"}}\n\n")),
+            Ok(Bytes::from("event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":1,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"\\\":1}\"}}\n\nevent: content_block_stop\n")),
+            Ok(Bytes::from("data: {\"type\":\"content_block_stop\",\"index\":1}\n\n")),
+        ];
+        let output: Vec<_> = coalesce_tool_input(futures::stream::iter(chunks)).collect().await;
+        let text: String = output
+            .into_iter()
+            .map(|r| String::from_utf8(r.unwrap().to_vec()).unwrap())
+            .collect();
+        let events = parse_sse_events(&text);
+
+        assert_eq!(events.len(), 2);
+        let delta: serde_json::Value = serde_json::from_str(&events[0].data).unwrap();
+        assert_eq!(delta["delta"]["partial_json"], "{\"a\":1}");
+        assert_eq!(events[1].event.as_deref(), Some("content_block_stop"));
+    }
+
+    #[tokio::test]
+    async fn test_first_token_replays_head() {
+        use futures::StreamExt;
+
+        let chunks: Vec<Result<Bytes, ()>> = vec![
+            Ok(Bytes::from("event: message_start\n\n")),
+            Ok(Bytes::from("event: content_block_delta\n\n")),
+            Ok(Bytes::from("event: message_stop\n\n")),
+        ];
+        let stream = first_token(Box::pin(futures::stream::iter(chunks))).await;
+        let output: Vec<_> = stream.map(|r| r.unwrap()).collect().await;
+        assert_eq!(output.len(), 3);
+    }
+
     #[test]
     fn test_parse_sse_no_event_type() {
         let input = "data: plain data\n\n";
@@ -0,0 +1,114 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::{Stream, StreamExt};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::error::ProviderError;
+use super::streaming::{response_events, with_heartbeat, SseEvent};
+use super::{AnthropicProvider, FimRequest, FimResponse, OutboundRequest, ProviderResponse};
+use crate::models::{AnthropicRequest, CountTokensRequest, CountTokensResponse};
+
+/// Idle time between pings while the upstream response is pending
+const PING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Serves streaming requests from the provider's non-streaming API
+///
+/// For backends without streaming: the client gets `message_start` right away,
+/// pings while the upstream request runs, then the whole response at once,
+/// so clients with first-byte timeouts don't give up on long generations.
+pub struct SyntheticStream {
+    inner: Arc<dyn AnthropicProvider>,
+}
+
+impl SyntheticStream {
+    pub fn new(inner: Box<dyn AnthropicProvider>) -> Self {
+        Self { inner: Arc::from(inner) }
+    }
+}
+
+fn event(event: &str, data: serde_json::Value) -> Bytes {
+    Bytes::from(
+        SseEvent {
+            event: Some(event.to_string()),
+            data: data.to_string(),
+        }
+        .to_sse_string(),
+    )
+}
+
+#[async_trait]
+impl AnthropicProvider for SyntheticStream {
+    async fn send_message(&self, request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
+        self.inner.send_message(request).await
+    }
+
+    async fn send_message_stream(
+        &self,
+        mut request: AnthropicRequest,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<Bytes, ProviderError>> + Send>>, ProviderError> {
+        request.stream = Some(false);
+        let start = event(
+            "message_start",
+            serde_json::json!({
+                "type": "message_start",
+                "message": {
+                    "id": format!("msg_{:016x}", rand::random::<u64>()),
+                    "type": "message",
+                    "role": "assistant",
+                    "content": [],
+                    "model": request.model,
+                    "stop_reason": null,
+                    "stop_sequence": null,
+                    "usage": { "input_tokens": 0, "output_tokens": 0 },
+                },
+            }),
+        );
+
+        let inner = self.inner.clone();
+        let body = futures::stream::once(async move {
+            // The stream has started, so failures can only be reported in-band
+            Ok(match inner.send_message(request).await {
+                Ok(response) => Bytes::from(response_events(&response)),
+                Err(e) => event(
+                    "error",
+                    serde_json::json!({
+                        "type": "error",
+                        "error": { "type": "api_error", "message": e.to_string() },
+                    }),
+                ),
+            })
+        });
+        let stream = futures::stream::once(async move { Ok(start) }).chain(body);
+        Ok(Box::pin(with_heartbeat(Box::pin(stream), PING_INTERVAL)))
+    }
+
+    async fn count_tokens(&self, request: CountTokensRequest) -> Result<CountTokensResponse, ProviderError> {
+        self.inner.count_tokens(request).await
+    }
+
+    fn supports_model(&self, model: &str) -> bool {
+        self.inner.supports_model(model)
+    }
+
+    fn supports_beta(&self, beta: &str) -> bool {
+        self.inner.supports_beta(beta)
+    }
+
+    fn supports_verbosity(&self) -> bool {
+        self.inner.supports_verbosity()
+    }
+
+    fn supports_documents(&self) -> bool {
+        self.inner.supports_documents()
+    }
+
+    fn preview_request(&self, request: &AnthropicRequest) -> Result<OutboundRequest, ProviderError> {
+        self.inner.preview_request(request)
+    }
+
+    async fn complete_fim(&self, request: FimRequest) -> Result<FimResponse, ProviderError> {
+        self.inner.complete_fim(request).await
+    }
+}
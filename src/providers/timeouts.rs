@@ -0,0 +1,187 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::Stream;
+use serde::{Deserialize, Serialize};
+use std::pin::Pin;
+use std::time::Duration;
+
+use super::error::ProviderError;
+use super::streaming::first_token;
+use super::{AnthropicProvider, FimRequest, FimResponse, OutboundRequest, ProviderResponse};
+use crate::cli::TimeoutConfig;
+use crate::models::{AnthropicRequest, CountTokensRequest, CountTokensResponse};
+
+/// Per-provider timeouts
+///
+/// Slow reasoning models need minutes for a long answer but should still open
+/// a connection within seconds, so each phase has its own limit. Connect and
+/// total limits that aren't set come from `[server.timeouts]`.
+///
+/// Example:
+/// ```toml
+/// [providers.timeouts]
+/// connect_ms = 5000         # establishing the connection
+/// first_token_ms = 120000   # streaming: until the first content arrives
+/// total_ms = 900000         # non-streaming: the whole request
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Timeouts {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_token_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_ms: Option<u64>,
+}
+
+impl Timeouts {
+    /// Fill in the limits left unset from the server-wide ones
+    pub fn with_defaults(mut self, defaults: &TimeoutConfig) -> Self {
+        self.connect_ms.get_or_insert(defaults.connect_timeout_ms);
+        self.total_ms.get_or_insert(defaults.api_timeout_ms);
+        self
+    }
+
+    pub fn connect(&self) -> Duration {
+        Duration::from_millis(self.connect_ms.unwrap_or(TimeoutConfig::default().connect_timeout_ms))
+    }
+}
+
+fn timed_out(phase: &str, limit: Duration) -> ProviderError {
+    ProviderError::ApiError {
+        status: 504,
+        message: format!("No {} within {}ms", phase, limit.as_millis()),
+    }
+}
+
+/// Applies the first-token and total timeouts to a provider's requests
+/// (the connect timeout is set on its HTTP client)
+pub struct TimeLimited {
+    inner: Box<dyn AnthropicProvider>,
+    first_token: Option<Duration>,
+    total: Option<Duration>,
+}
+
+impl TimeLimited {
+    pub fn new(inner: Box<dyn AnthropicProvider>, timeouts: &Timeouts) -> Self {
+        Self {
+            inner,
+            first_token: timeouts.first_token_ms.map(Duration::from_millis),
+            total: timeouts.total_ms.map(Duration::from_millis),
+        }
+    }
+}
+
+#[async_trait]
+impl AnthropicProvider for TimeLimited {
+    async fn send_message(&self, request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
+        match self.total {
+            Some(total) => tokio::time::timeout(total, self.inner.send_message(request))
+                .await
+                .map_err(|_| timed_out("response", total))?,
+            None => self.inner.send_message(request).await,
+        }
+    }
+
+    async fn send_message_stream(
+        &self,
+        request: AnthropicRequest,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<Bytes, ProviderError>> + Send>>, ProviderError> {
+        let Some(limit) = self.first_token else {
+            return self.inner.send_message_stream(request).await;
+        };
+        let started = async { Ok(first_token(self.inner.send_message_stream(request).await?).await) };
+        tokio::time::timeout(limit, started)
+            .await
+            .map_err(|_| timed_out("first token", limit))?
+    }
+
+    async fn count_tokens(&self, request: CountTokensRequest) -> Result<CountTokensResponse, ProviderError> {
+        self.inner.count_tokens(request).await
+    }
+
+    fn supports_model(&self, model: &str) -> bool {
+        self.inner.supports_model(model)
+    }
+
+    fn supports_beta(&self, beta: &str) -> bool {
+        self.inner.supports_beta(beta)
+    }
+
+    fn supports_verbosity(&self) -> bool {
+        self.inner.supports_verbosity()
+    }
+
+    fn supports_documents(&self) -> bool {
+        self.inner.supports_documents()
+    }
+
+    fn preview_request(&self, request: &AnthropicRequest) -> Result<OutboundRequest, ProviderError> {
+        self.inner.preview_request(request)
+    }
+
+    async fn complete_fim(&self, request: FimRequest) -> Result<FimResponse, ProviderError> {
+        match self.total {
+            Some(total) => tokio::time::timeout(total, self.inner.complete_fim(request))
+                .await
+                .map_err(|_| timed_out("response", total))?,
+            None => self.inner.complete_fim(request).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers after a second
+    struct Slow;
+
+    #[async_trait]
+    impl AnthropicProvider for Slow {
+        async fn send_message(&self, _request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            unreachable!("the total timeout is shorter")
+        }
+
+        async fn send_message_stream(
+            &self,
+            _request: AnthropicRequest,
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<Bytes, ProviderError>> + Send>>, ProviderError> {
+            // Headers arrive right away, content never does
+            let start = Bytes::from("event: message_start\ndata: {}\n\n");
+            Ok(Box::pin(futures::StreamExt::chain(
+                futures::stream::iter(vec![Ok(start)]),
+                futures::stream::pending(),
+            )))
+        }
+
+        async fn count_tokens(&self, _request: CountTokensRequest) -> Result<CountTokensResponse, ProviderError> {
+            unimplemented!()
+        }
+
+        fn supports_model(&self, _model: &str) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timeouts() {
+        let timeouts: Timeouts = toml::from_str("first_token_ms = 20\ntotal_ms = 20").unwrap();
+        let defaults = TimeoutConfig { api_timeout_ms: 600_000, connect_timeout_ms: 5_000 };
+        let timeouts = timeouts.with_defaults(&defaults);
+        assert_eq!(timeouts.connect(), Duration::from_secs(5));
+        assert_eq!(timeouts.total_ms, Some(20));
+        let provider = TimeLimited::new(Box::new(Slow), &timeouts);
+        let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "m", "max_tokens": 10,
+            "messages": [{ "role": "user", "content": "hi" }],
+        }))
+        .unwrap();
+
+        let err = provider.send_message(request.clone()).await.unwrap_err();
+        assert!(matches!(err, ProviderError::ApiError { status: 504, .. }));
+        let err = provider.send_message_stream(request).await.err().unwrap();
+        assert!(err.to_string().contains("No first token within 20ms"));
+    }
+}
@@ -0,0 +1,157 @@
+use crate::models::{AnthropicRequest, ContentBlock, MessageContent};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// Tool call id rules of an upstream wire format
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToolIdFormat {
+    /// OpenAI chat completions: `tool_call_id` of at most 40 characters
+    OpenAI,
+}
+
+impl ToolIdFormat {
+    fn accepts(self, id: &str) -> bool {
+        match self {
+            ToolIdFormat::OpenAI => !id.is_empty() && id.len() <= 40 && is_anthropic_id(id),
+        }
+    }
+
+    /// Deterministic replacement for an id the format doesn't accept
+    fn convert(self, id: &str) -> String {
+        match self {
+            ToolIdFormat::OpenAI => {
+                let mut hasher = DefaultHasher::new();
+                id.hash(&mut hasher);
+                format!("call_{:016x}", hasher.finish())
+            }
+        }
+    }
+}
+
+/// Anthropic tool_use ids: `^[a-zA-Z0-9_-]+$`
+fn is_anthropic_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Bidirectional tool call id table for one conversation
+///
+/// Built from the request history, so tool_use ids and the tool_result blocks that
+/// refer to them translate to the same upstream id, and ids the upstream returns
+/// translate back to the ids the client already knows.
+#[derive(Debug)]
+pub struct ToolIdMap {
+    format: ToolIdFormat,
+    /// Anthropic id -> upstream id
+    upstream: HashMap<String, String>,
+    /// Upstream id -> Anthropic id
+    anthropic: HashMap<String, String>,
+    generated: usize,
+}
+
+impl ToolIdMap {
+    pub fn for_request(request: &AnthropicRequest, format: ToolIdFormat) -> Self {
+        let mut map = Self {
+            format,
+            upstream: HashMap::new(),
+            anthropic: HashMap::new(),
+            generated: 0,
+        };
+        for message in &request.messages {
+            if let MessageContent::Blocks(blocks) = &message.content {
+                for block in blocks {
+                    if let ContentBlock::ToolUse { id, .. } = block {
+                        map.upstream_id(id);
+                    }
+                }
+            }
+        }
+        map
+    }
+
+    /// Id to send upstream for an Anthropic tool_use id
+    pub fn upstream_id(&mut self, id: &str) -> String {
+        if let Some(upstream) = self.upstream.get(id) {
+            return upstream.clone();
+        }
+        let mut upstream = if self.format.accepts(id) {
+            id.to_string()
+        } else {
+            self.format.convert(id)
+        };
+        // Keep the mapping one-to-one
+        while self.anthropic.contains_key(&upstream) {
+            upstream = self.format.convert(&format!("{}#", upstream));
+        }
+        self.link(id, &upstream);
+        upstream
+    }
+
+    /// Anthropic tool_use id for an id the upstream returned
+    ///
+    /// Every missing (empty) upstream id gets a new id of its own.
+    pub fn anthropic_id(&mut self, upstream: &str) -> String {
+        if upstream.is_empty() {
+            return self.generate();
+        }
+        if let Some(id) = self.anthropic.get(upstream) {
+            return id.clone();
+        }
+        let id = if is_anthropic_id(upstream) && !self.upstream.contains_key(upstream) {
+            upstream.to_string()
+        } else {
+            self.generate()
+        };
+        self.link(&id, upstream);
+        id
+    }
+
+    /// A `toolu_ccm_N` id not used in this conversation
+    fn generate(&mut self) -> String {
+        loop {
+            self.generated += 1;
+            let id = format!("toolu_ccm_{}", self.generated);
+            if !self.upstream.contains_key(&id) {
+                return id;
+            }
+        }
+    }
+
+    fn link(&mut self, id: &str, upstream: &str) {
+        self.upstream.insert(id.to_string(), upstream.to_string());
+        self.anthropic.insert(upstream.to_string(), id.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_round_trip() {
+        let long_id = format!("toolu_{}", "x".repeat(60));
+        let request: AnthropicRequest = serde_json::from_value(json!({
+            "model": "m",
+            "max_tokens": 16,
+            "messages": [
+                { "role": "assistant", "content": [
+                    { "type": "tool_use", "id": "toolu_01A", "name": "Read", "input": {} },
+                    { "type": "tool_use", "id": long_id, "name": "Bash", "input": {} },
+                ]},
+            ],
+        }))
+        .unwrap();
+        let mut ids = ToolIdMap::for_request(&request, ToolIdFormat::OpenAI);
+
+        assert_eq!(ids.upstream_id("toolu_01A"), "toolu_01A");
+        let upstream = ids.upstream_id(&long_id);
+        assert!(upstream.len() <= 40);
+        assert_eq!(ids.anthropic_id(&upstream), long_id);
+
+        // Fresh upstream ids come back as valid Anthropic ids
+        assert_eq!(ids.anthropic_id("call_9"), "call_9");
+        assert_eq!(ids.anthropic_id(""), "toolu_ccm_1");
+        assert_eq!(ids.anthropic_id(""), "toolu_ccm_2");
+    }
+}
@@ -0,0 +1,194 @@
+//! Token estimates for images
+//!
+//! Local token counts only looked at text, so a screenshot-heavy request
+//! counted as a handful of tokens. Each provider family bills images by its
+//! own rule; these follow the published formulas closely enough for
+//! `count_tokens` and cost estimates.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::io::Cursor;
+
+use crate::models::{
+    AnthropicRequest, ContentBlock, ImageSource, Message, MessageContent, SystemPrompt,
+    ToolResultBlock, ToolResultContent,
+};
+
+/// How a provider family turns an image into prompt tokens
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VisionFormula {
+    /// Long side capped at 1568 px, then `width * height / 750`
+    Anthropic,
+    /// 85 base tokens plus 170 per 512 px tile after resizing (`detail: high`)
+    OpenAI,
+    /// A flat 258 tokens per image
+    Gemini,
+}
+
+const ANTHROPIC_MAX_EDGE: f64 = 1568.0;
+const ANTHROPIC_MAX_TOKENS: u32 = 1600;
+const OPENAI_BASE_TOKENS: u32 = 85;
+const OPENAI_TILE_TOKENS: u32 = 170;
+const GEMINI_IMAGE_TOKENS: u32 = 258;
+
+/// Rough characters per token for text
+const CHARS_PER_TOKEN: usize = 4;
+
+impl VisionFormula {
+    /// Formula for a `provider_type` from the config
+    pub fn for_provider(provider_type: &str) -> Self {
+        match provider_type {
+            "gemini" | "vertex-ai" => Self::Gemini,
+            "anthropic" | "z.ai" | "minimax" | "zenmux" | "kimi-coding" => Self::Anthropic,
+            _ => Self::OpenAI,
+        }
+    }
+
+    /// Tokens for an image of the given size
+    pub fn tokens(self, width: u32, height: u32) -> u32 {
+        let (width, height) = (width.max(1) as f64, height.max(1) as f64);
+        match self {
+            Self::Anthropic => {
+                let scale = (ANTHROPIC_MAX_EDGE / width.max(height)).min(1.0);
+                let tokens = (width * scale) * (height * scale) / 750.0;
+                (tokens.ceil() as u32).min(ANTHROPIC_MAX_TOKENS)
+            }
+            Self::OpenAI => {
+                // Fit in 2048x2048, then shortest side down to 768
+                let fit = (2048.0 / width.max(height)).min(1.0);
+                let (width, height) = (width * fit, height * fit);
+                let shrink = (768.0 / width.min(height)).min(1.0);
+                let tiles = ((width * shrink) / 512.0).ceil() * ((height * shrink) / 512.0).ceil();
+                OPENAI_BASE_TOKENS + OPENAI_TILE_TOKENS * tiles as u32
+            }
+            Self::Gemini => GEMINI_IMAGE_TOKENS,
+        }
+    }
+
+    /// Tokens for an image whose size isn't known (URL sources, unreadable data)
+    fn unknown(self) -> u32 {
+        match self {
+            Self::Anthropic => ANTHROPIC_MAX_TOKENS,
+            Self::OpenAI => self.tokens(1024, 1024),
+            Self::Gemini => GEMINI_IMAGE_TOKENS,
+        }
+    }
+
+    /// Tokens for one image source
+    pub fn image_tokens(self, source: &ImageSource) -> u32 {
+        match dimensions(source) {
+            Some((width, height)) => self.tokens(width, height),
+            None => self.unknown(),
+        }
+    }
+
+    /// Tokens for every image in the messages, tool results included
+    pub fn message_image_tokens(self, messages: &[Message]) -> u32 {
+        messages
+            .iter()
+            .filter_map(|message| match &message.content {
+                MessageContent::Blocks(blocks) => Some(blocks),
+                MessageContent::Text(_) => None,
+            })
+            .flatten()
+            .map(|block| match block {
+                ContentBlock::Image { source } => self.image_tokens(source),
+                ContentBlock::ToolResult {
+                    content: ToolResultContent::Blocks(blocks),
+                    ..
+                } => blocks
+                    .iter()
+                    .map(|block| match block {
+                        ToolResultBlock::Image { source } => self.image_tokens(source),
+                        _ => 0,
+                    })
+                    .sum(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Estimated prompt tokens of a request: text at ~4 characters per token plus images
+    pub fn estimate_input_tokens(self, request: &AnthropicRequest) -> u32 {
+        let mut chars = match &request.system {
+            Some(SystemPrompt::Text(text)) => text.len(),
+            Some(SystemPrompt::Blocks(blocks)) => blocks.iter().map(|b| b.text.len()).sum(),
+            None => 0,
+        };
+        for message in &request.messages {
+            chars += match &message.content {
+                MessageContent::Text(text) => text.len(),
+                MessageContent::Blocks(blocks) => blocks
+                    .iter()
+                    .map(|block| match block {
+                        ContentBlock::Text { text } => text.len(),
+                        ContentBlock::Thinking { thinking, .. } => thinking.len(),
+                        ContentBlock::ToolUse { input, .. } => input.to_string().len(),
+                        ContentBlock::ToolResult { content, .. } => content.to_string().len(),
+                        _ => 0,
+                    })
+                    .sum(),
+            };
+        }
+        if let Some(tools) = &request.tools {
+            chars += serde_json::to_string(tools).map_or(0, |t| t.len());
+        }
+        (chars / CHARS_PER_TOKEN) as u32 + self.message_image_tokens(&request.messages)
+    }
+}
+
+/// Pixel size of a base64 image, read from its header
+fn dimensions(source: &ImageSource) -> Option<(u32, u32)> {
+    let bytes = STANDARD.decode(source.data.as_ref()?).ok()?;
+    image::ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_token_formulas() {
+        // 1000x1000: under the edge cap
+        assert_eq!(VisionFormula::Anthropic.tokens(1000, 1000), 1334);
+        // 4000x3000 scales to 1568x1176, capped
+        assert_eq!(VisionFormula::Anthropic.tokens(4000, 3000), 1600);
+        assert_eq!(VisionFormula::Anthropic.tokens(200, 200), 54);
+
+        // OpenAI's documented examples
+        assert_eq!(VisionFormula::OpenAI.tokens(1024, 1024), 765);
+        assert_eq!(VisionFormula::OpenAI.tokens(2048, 4096), 1105);
+        assert_eq!(VisionFormula::OpenAI.tokens(100, 100), 255);
+
+        assert_eq!(VisionFormula::Gemini.tokens(4000, 3000), 258);
+        assert_eq!(VisionFormula::for_provider("vertex-ai"), VisionFormula::Gemini);
+        assert_eq!(VisionFormula::for_provider("groq"), VisionFormula::OpenAI);
+
+        // Size read from the image itself
+        let mut png = Cursor::new(Vec::new());
+        image::DynamicImage::new_rgb8(800, 400)
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "m",
+            "max_tokens": 16,
+            "messages": [{ "role": "user", "content": [
+                { "type": "text", "text": "x".repeat(400) },
+                { "type": "image", "source": { "type": "base64", "media_type": "image/png", "data": STANDARD.encode(png.into_inner()) } },
+                { "type": "image", "source": { "type": "url", "url": "https://example.com/a.png" } },
+            ] }],
+        }))
+        .unwrap();
+        assert_eq!(
+            VisionFormula::Anthropic.message_image_tokens(&request.messages),
+            427 + 1600
+        );
+        assert_eq!(
+            VisionFormula::Gemini.estimate_input_tokens(&request),
+            100 + 2 * 258
+        );
+    }
+}
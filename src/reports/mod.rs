@@ -0,0 +1,300 @@
+//! Daily usage reports generated from the usage store.
+
+use anyhow::{Context, Result};
+use chrono::{Duration as ChronoDuration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use tracing::{error, info};
+
+use crate::alerting::WebhookFormat;
+use crate::storage::{UsageRecord, UsageStore};
+
+/// Daily usage report configuration (also enables usage recording)
+///
+/// Example:
+/// ```toml
+/// [usage_report]
+/// enabled = true
+/// hour_utc = 0                 # generate yesterday's report at 00:xx UTC
+/// # path = "/var/lib/ccm/reports"   # defaults to ~/.claude-code-mux/reports
+/// webhook_url = "https://hooks.slack.com/services/..."
+/// webhook_format = "slack"
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageReportConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub hour_utc: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+    #[serde(default)]
+    pub webhook_format: WebhookFormat,
+}
+
+/// Per-provider statistics
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProviderStats {
+    pub requests: u64,
+    pub errors: u64,
+    pub error_rate: f64,
+    pub avg_latency_ms: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+/// Aggregated usage for a single day
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageReport {
+    pub date: NaiveDate,
+    pub requests: u64,
+    pub errors: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+    /// (model, requests), most used first
+    pub top_models: Vec<(String, u64)>,
+    pub providers: BTreeMap<String, ProviderStats>,
+}
+
+impl UsageReport {
+    pub fn from_records(date: NaiveDate, records: &[UsageRecord]) -> Self {
+        let mut report = Self {
+            date,
+            requests: 0,
+            errors: 0,
+            input_tokens: 0,
+            output_tokens: 0,
+            cost_usd: 0.0,
+            top_models: Vec::new(),
+            providers: BTreeMap::new(),
+        };
+
+        let mut models: BTreeMap<String, u64> = BTreeMap::new();
+        let mut latency_totals: BTreeMap<String, u64> = BTreeMap::new();
+
+        for record in records {
+            report.requests += 1;
+            if !record.success {
+                report.errors += 1;
+            }
+            report.input_tokens += record.input_tokens as u64;
+            report.output_tokens += record.output_tokens as u64;
+            report.cost_usd += record.cost_usd.unwrap_or(0.0);
+
+            let model = record.actual_model.clone().unwrap_or_else(|| record.model.clone());
+            *models.entry(model).or_default() += 1;
+
+            let provider = record.provider.clone().unwrap_or_else(|| "unknown".to_string());
+            *latency_totals.entry(provider.clone()).or_default() += record.latency_ms;
+            let stats = report.providers.entry(provider).or_default();
+            stats.requests += 1;
+            if !record.success {
+                stats.errors += 1;
+            }
+            stats.input_tokens += record.input_tokens as u64;
+            stats.output_tokens += record.output_tokens as u64;
+        }
+
+        for (provider, stats) in report.providers.iter_mut() {
+            stats.error_rate = stats.errors as f64 / stats.requests as f64;
+            stats.avg_latency_ms = latency_totals[provider] / stats.requests;
+        }
+
+        let mut top_models: Vec<(String, u64)> = models.into_iter().collect();
+        top_models.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top_models.truncate(5);
+        report.top_models = top_models;
+
+        report
+    }
+
+    pub fn error_rate(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.requests as f64
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# Claude Code Mux usage report - {}", self.date);
+        let _ = writeln!(out);
+        let _ = writeln!(
+            out,
+            "- Requests: {} ({} errors, {:.1}%)",
+            self.requests,
+            self.errors,
+            self.error_rate() * 100.0
+        );
+        let _ = writeln!(out, "- Tokens: {} in / {} out", self.input_tokens, self.output_tokens);
+        let _ = writeln!(out, "- Estimated cost: ${:.2}", self.cost_usd);
+
+        if !self.top_models.is_empty() {
+            let _ = writeln!(out, "\n## Top models\n");
+            for (model, count) in &self.top_models {
+                let _ = writeln!(out, "- {}: {}", model, count);
+            }
+        }
+
+        if !self.providers.is_empty() {
+            let _ = writeln!(out, "\n## Providers\n");
+            let _ = writeln!(out, "| Provider | Requests | Error rate | Avg latency | Tokens in/out |");
+            let _ = writeln!(out, "|---|---|---|---|---|");
+            for (name, stats) in &self.providers {
+                let _ = writeln!(
+                    out,
+                    "| {} | {} | {:.1}% | {}ms | {}/{} |",
+                    name,
+                    stats.requests,
+                    stats.error_rate * 100.0,
+                    stats.avg_latency_ms,
+                    stats.input_tokens,
+                    stats.output_tokens
+                );
+            }
+        }
+
+        out
+    }
+
+    fn webhook_payload(&self, format: WebhookFormat) -> serde_json::Value {
+        match format {
+            WebhookFormat::Json => serde_json::json!({
+                "service": "claude-code-mux",
+                "event": "daily_usage_report",
+                "report": self,
+            }),
+            WebhookFormat::Slack => serde_json::json!({ "text": self.to_markdown() }),
+            // Discord rejects messages over 2000 characters
+            WebhookFormat::Discord => {
+                let mut content = self.to_markdown();
+                if content.len() > 1900 {
+                    let mut end = 1900;
+                    while !content.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    content.truncate(end);
+                    content.push_str("\n…");
+                }
+                serde_json::json!({ "content": content })
+            }
+        }
+    }
+}
+
+/// ~/.claude-code-mux/reports
+fn default_reports_path() -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Failed to get home directory")?;
+    Ok(home.join(".claude-code-mux").join("reports"))
+}
+
+/// Build the report for `date`, write it to disk and post it to the webhook
+pub async fn generate_report(
+    config: &UsageReportConfig,
+    store: &UsageStore,
+    date: NaiveDate,
+) -> Result<UsageReport> {
+    let report = UsageReport::from_records(date, &store.load_day(date).await?);
+
+    let dir = match &config.path {
+        Some(path) => path.clone(),
+        None => default_reports_path()?,
+    };
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create reports directory: {}", dir.display()))?;
+    let path = dir.join(format!("{}.md", date.format("%Y-%m-%d")));
+    std::fs::write(&path, report.to_markdown())
+        .with_context(|| format!("Failed to write report: {}", path.display()))?;
+    info!("📊 Wrote usage report for {} to {}", date, path.display());
+
+    if let Some(url) = &config.webhook_url {
+        reqwest::Client::new()
+            .post(url)
+            .json(&report.webhook_payload(config.webhook_format))
+            .send()
+            .await
+            .context("Failed to post usage report")?
+            .error_for_status()
+            .context("Usage report webhook rejected the report")?;
+    }
+
+    Ok(report)
+}
+
+/// Generate the previous day's report every day at `hour_utc`
+pub fn spawn_daily_report(config: UsageReportConfig, store: UsageStore) {
+    tokio::spawn(async move {
+        loop {
+            let now = Utc::now();
+            let hour = config.hour_utc.min(23);
+            let mut next = now
+                .date_naive()
+                .and_hms_opt(hour, 0, 0)
+                .expect("valid hour")
+                .and_utc();
+            if next <= now {
+                next += ChronoDuration::days(1);
+            }
+            let wait = (next - now).to_std().unwrap_or_default();
+            tokio::time::sleep(wait).await;
+
+            let yesterday = next.date_naive() - ChronoDuration::days(1);
+            if let Err(e) = generate_report(&config, &store, yesterday).await {
+                error!("Failed to generate usage report for {}: {}", yesterday, e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(provider: &str, model: &str, success: bool, latency_ms: u64) -> UsageRecord {
+        UsageRecord {
+            id: String::new(),
+            timestamp: Utc::now(),
+            endpoint: "/v1/messages".to_string(),
+            model: "claude-sonnet".to_string(),
+            routed_model: None,
+            route_type: None,
+            provider: Some(provider.to_string()),
+            actual_model: Some(model.to_string()),
+            stream: false,
+            success,
+            latency_ms,
+            input_tokens: 100,
+            output_tokens: 50,
+            cost_usd: Some(0.01),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_aggregates_records() {
+        let records = vec![
+            record("zai", "glm-4.6", true, 100),
+            record("zai", "glm-4.6", false, 300),
+            record("openrouter", "kimi-k2", true, 200),
+        ];
+        let report = UsageReport::from_records(Utc::now().date_naive(), &records);
+
+        assert_eq!(report.requests, 3);
+        assert_eq!(report.errors, 1);
+        assert_eq!(report.input_tokens, 300);
+        assert!((report.cost_usd - 0.03).abs() < 1e-9);
+        assert_eq!(report.top_models[0], ("glm-4.6".to_string(), 2));
+
+        let zai = &report.providers["zai"];
+        assert_eq!(zai.avg_latency_ms, 200);
+        assert!((zai.error_rate - 0.5).abs() < 1e-9);
+
+        assert!(report.to_markdown().contains("| zai | 2 | 50.0% | 200ms | 200/100 |"));
+    }
+}
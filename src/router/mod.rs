@@ -1,9 +1,42 @@
 use crate::cli::AppConfig;
-use crate::models::{AnthropicRequest, RouteDecision, RouteType, SystemPrompt};
+use crate::models::{AnthropicRequest, ContentBlock, MessageContent, Role, RouteDecision, RouteType, SystemPrompt};
 use anyhow::Result;
 use regex::Regex;
+use serde::Serialize;
 use tracing::{debug, info};
 
+/// One routing rule as evaluated for a request (see `Router::explain`)
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleCheck {
+    pub rule: &'static str,
+    pub matched: bool,
+    pub detail: String,
+}
+
+impl RuleCheck {
+    fn new(rule: &'static str, matched: bool, detail: impl Into<String>) -> Self {
+        Self {
+            rule,
+            matched,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Prompt snippets of Claude Code's utility calls
+const UTILITY_MARKERS: &[&str] = &[
+    "Analyze if this message indicates a new conversation topic",
+    "write a 5-10 word title",
+    "Your task is to process Bash commands",
+    "Extract any file paths that this command reads or modifies",
+    "Summarize this coding conversation",
+];
+
+/// System prompt of subagents spawned by the Task tool
+const SUBAGENT_MARKER: &str = "You are an agent for Claude Code";
+/// System prompt of the main interactive session
+const MAIN_MARKER: &str = "You are an interactive CLI tool";
+
 /// Router for intelligently selecting models based on request characteristics
 #[derive(Clone)]
 pub struct Router {
@@ -88,6 +121,27 @@ impl Router {
     /// Route an incoming request to the appropriate model
     /// Priority: websearch > subagent > think > background > auto-map > default
     pub fn route(&self, request: &mut AnthropicRequest) -> Result<RouteDecision> {
+        self.route_traced(request, None, &mut Vec::new())
+    }
+
+    /// Route a request whose client named its role (`x-ccm-role`)
+    pub fn route_as(&self, request: &mut AnthropicRequest, role: Option<Role>) -> Result<RouteDecision> {
+        self.route_traced(request, role, &mut Vec::new())
+    }
+
+    /// Route a request and report how each rule was evaluated
+    pub fn explain(&self, request: &mut AnthropicRequest, role: Option<Role>) -> (Result<RouteDecision>, Vec<RuleCheck>) {
+        let mut checks = Vec::new();
+        let decision = self.route_traced(request, role, &mut checks);
+        (decision, checks)
+    }
+
+    fn route_traced(
+        &self,
+        request: &mut AnthropicRequest,
+        role: Option<Role>,
+        checks: &mut Vec<RuleCheck>,
+    ) -> Result<RouteDecision> {
         // Save original model for background task detection
         let original_model = request.model.clone();
 
@@ -98,18 +152,24 @@ impl Router {
                 let old = request.model.clone();
                 request.model = self.config.router.default.clone();
                 debug!("🔀 Auto-mapped model '{}' → '{}'", old, request.model);
+                checks.push(RuleCheck::new("auto_map", true, format!("'{}' matches /{}/, mapped to '{}'", old, regex, request.model)));
+            } else {
+                checks.push(RuleCheck::new("auto_map", false, format!("'{}' doesn't match /{}/", request.model, regex)));
             }
         }
 
         // 1. WebSearch (HIGHEST PRIORITY - tool-based detection)
-        if let Some(ref websearch_model) = self.config.router.websearch {
-            if self.has_web_search_tool(request) {
+        match self.config.router.websearch {
+            Some(ref websearch_model) if self.has_web_search_tool(request) => {
                 info!("🔍 Routing to websearch model (web_search tool detected)");
+                checks.push(RuleCheck::new("websearch", true, "web_search tool present"));
                 return Ok(RouteDecision {
                     model_name: websearch_model.clone(),
                     route_type: RouteType::WebSearch,
                 });
             }
+            Some(_) => checks.push(RuleCheck::new("websearch", false, "no web_search tool")),
+            None => checks.push(RuleCheck::new("websearch", false, "router.websearch not set")),
         }
 
         // 2. Subagent Model (system prompt tag)
@@ -118,43 +178,119 @@ impl Router {
                 "🤖 Routing to subagent model (CCM-SUBAGENT-MODEL tag): {}",
                 model
             );
+            checks.push(RuleCheck::new("subagent", true, format!("CCM-SUBAGENT-MODEL tag names '{}'", model)));
             return Ok(RouteDecision {
                 model_name: model,
                 route_type: RouteType::Default, // Using Default route type
             });
         }
+        checks.push(RuleCheck::new("subagent", false, "no CCM-SUBAGENT-MODEL tag"));
+
+        // 2b. Role named by the client
+        if let Some(role) = role {
+            match self.config.router.role_model(role) {
+                Some(model) => {
+                    debug!("🎭 Routing {:?} role to {}", role, model);
+                    checks.push(RuleCheck::new("role", true, format!("x-ccm-role is {:?}", role)));
+                    return Ok(RouteDecision {
+                        model_name: model.clone(),
+                        route_type: role_route_type(role),
+                    });
+                }
+                None => checks.push(RuleCheck::new("role", false, format!("no model for the {:?} role", role))),
+            }
+        }
 
         // 3. Think mode (Plan Mode / Reasoning)
-        if let Some(ref think_model) = self.config.router.think {
-            if self.is_plan_mode(request) {
+        match self.config.router.role_model(Role::Plan) {
+            Some(think_model) if self.is_plan_mode(request) => {
                 info!("🧠 Routing to think model (Plan Mode detected)");
+                checks.push(RuleCheck::new("think", true, "thinking enabled"));
                 return Ok(RouteDecision {
                     model_name: think_model.clone(),
                     route_type: RouteType::Think,
                 });
             }
+            Some(_) => checks.push(RuleCheck::new("think", false, "thinking not enabled")),
+            None => checks.push(RuleCheck::new("think", false, "router.think not set")),
         }
 
         // 4. Background tasks (check against ORIGINAL model name, before auto-mapping)
-        if let Some(ref background_model) = self.config.router.background {
-            if self.is_background_task(&original_model) {
+        match self.config.router.role_model(Role::Background) {
+            Some(background_model) if self.is_background_task(&original_model) => {
                 debug!("🔄 Routing to background model");
+                checks.push(RuleCheck::new("background", true, format!("'{}' matches the background pattern", original_model)));
                 return Ok(RouteDecision {
                     model_name: background_model.clone(),
                     route_type: RouteType::Background,
                 });
             }
+            Some(_) => checks.push(RuleCheck::new(
+                "background",
+                false,
+                format!("'{}' doesn't match the background pattern", original_model),
+            )),
+            None => checks.push(RuleCheck::new("background", false, "router.background not set")),
+        }
+
+        // 4b. Utility calls recognized by shape (any model name)
+        let detection = &self.config.router.background_detection;
+        match detection.model.as_ref().or(self.config.router.role_model(Role::Background)) {
+            Some(model) if detection.enabled => match self.utility_call(request, &original_model) {
+                Some(reason) => {
+                    debug!("🔄 Routing utility call to background model ({})", reason);
+                    checks.push(RuleCheck::new("background_detection", true, reason));
+                    return Ok(RouteDecision {
+                        model_name: model.clone(),
+                        route_type: RouteType::Background,
+                    });
+                }
+                None => checks.push(RuleCheck::new("background_detection", false, "not a utility call")),
+            },
+            _ if !detection.enabled => {
+                checks.push(RuleCheck::new("background_detection", false, "router.background_detection disabled"))
+            }
+            _ => checks.push(RuleCheck::new("background_detection", false, "no background model set")),
+        }
+
+        // 4c. Subagent or main conversation, told apart by the system prompt
+        if let Some(role) = self.detect_role(request) {
+            match self.config.router.role_model(role) {
+                Some(model) => {
+                    debug!("🎭 Routing {:?} role to {}", role, model);
+                    checks.push(RuleCheck::new("role", true, format!("system prompt of the {:?} role", role)));
+                    return Ok(RouteDecision {
+                        model_name: model.clone(),
+                        route_type: role_route_type(role),
+                    });
+                }
+                None => checks.push(RuleCheck::new("role", false, format!("no model for the {:?} role", role))),
+            }
         }
 
         // 5. Default fallback
         // Use the transformed model name (from auto-mapping) or original if no mapping
         debug!("✅ Using model: {}", request.model);
+        checks.push(RuleCheck::new("default", true, format!("using model '{}'", request.model)));
         Ok(RouteDecision {
             model_name: request.model.clone(),
             route_type: RouteType::Default,
         })
     }
 
+    /// Route a legacy /v1/completions (autocomplete) request
+    /// Uses the completion model when configured, otherwise the regular rules
+    pub fn route_completion(&self, request: &mut AnthropicRequest) -> Result<RouteDecision> {
+        if let Some(ref completion_model) = self.config.router.completion {
+            debug!("⌨️ Routing to completion model");
+            return Ok(RouteDecision {
+                model_name: completion_model.clone(),
+                route_type: RouteType::Completion,
+            });
+        }
+        self.route(request)
+    }
+
     /// Check if request has web_search tool (tool-based detection)
     /// Following claude-code-router pattern: checks if tools array contains web_search type
     fn has_web_search_tool(&self, request: &AnthropicRequest) -> bool {
@@ -189,6 +325,61 @@ impl Router {
         }
     }
 
+    /// Subagent or main-session role from Claude Code's system prompt
+    fn detect_role(&self, request: &AnthropicRequest) -> Option<Role> {
+        let system = system_text(request);
+        if system.contains(SUBAGENT_MARKER) {
+            Some(Role::Subagent)
+        } else if system.contains(MAIN_MARKER) {
+            Some(Role::Main)
+        } else {
+            None
+        }
+    }
+
+    /// Why a request looks like one of Claude Code's utility calls, if it does
+    ///
+    /// Those calls carry no tools and ask for little output; they're recognized
+    /// by a known prompt, or by a Haiku model name.
+    fn utility_call(&self, request: &AnthropicRequest, original_model: &str) -> Option<String> {
+        let detection = &self.config.router.background_detection;
+        let has_tools = request.tools.as_ref().is_some_and(|tools| !tools.is_empty());
+        if has_tools || request.max_tokens > detection.max_tokens || self.is_plan_mode(request) {
+            return None;
+        }
+
+        let system = system_text(request);
+        // Some utility prompts are sent as the user turn instead
+        let first_user = request
+            .messages
+            .first()
+            .filter(|m| m.role == "user")
+            .map(|m| match &m.content {
+                MessageContent::Text(text) => text.clone(),
+                MessageContent::Blocks(blocks) => blocks
+                    .iter()
+                    .filter_map(|b| match b {
+                        ContentBlock::Text { text } => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            })
+            .unwrap_or_default();
+        let marker = UTILITY_MARKERS
+            .iter()
+            .copied()
+            .chain(detection.system_markers.iter().map(String::as_str))
+            .find(|marker| system.contains(marker) || first_user.contains(marker));
+        if let Some(marker) = marker {
+            return Some(format!("prompt contains '{}'", marker));
+        }
+        if original_model.to_lowercase().contains("haiku") {
+            return Some(format!("'{}' with max_tokens {}", original_model, request.max_tokens));
+        }
+        None
+    }
+
     /// Extract subagent model from system prompt tag
     /// Checks for <CCM-SUBAGENT-MODEL>model-name</CCM-SUBAGENT-MODEL> in system[1].text
     /// and removes the tag after extraction
@@ -227,6 +418,23 @@ impl Router {
     }
 }
 
+fn system_text(request: &AnthropicRequest) -> String {
+    match &request.system {
+        Some(SystemPrompt::Text(text)) => text.clone(),
+        Some(SystemPrompt::Blocks(blocks)) => blocks.iter().map(|b| b.text.as_str()).collect::<Vec<_>>().join("\n"),
+        None => String::new(),
+    }
+}
+
+fn role_route_type(role: Role) -> RouteType {
+    match role {
+        Role::Main => RouteType::Default,
+        Role::Plan => RouteType::Think,
+        Role::Subagent => RouteType::Subagent,
+        Role::Background => RouteType::Background,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -243,9 +451,26 @@ mod tests {
                 websearch: Some("websearch.model".to_string()),
                 auto_map_regex: None,   // Use default Claude pattern
                 background_regex: None, // Use default claude-haiku pattern
+                completion: None,
+                background_detection: Default::default(),
+                roles: Default::default(),
             },
             providers: vec![],
             models: vec![],
+            tool_result_truncation: Default::default(),
+            blob_store: Default::default(),
+            alerting: Default::default(),
+            usage_report: Default::default(),
+            database: Default::default(),
+            shared_state: Default::default(),
+            semantic_cache: Default::default(),
+            templates: vec![],
+            guardrails: Default::default(),
+            billing: Default::default(),
+            trace_export: vec![],
+            archive: Default::default(),
+            organizations: Default::default(),
+            judge: Default::default(),
         }
     }
 
@@ -266,6 +491,11 @@ mod tests {
             metadata: None,
             system: None,
             tools: None,
+            betas: Vec::new(),
+            tool_choice: None,
+            anthropic_version: None,
+            idempotency_key: None,
+            verbosity: None,
         }
     }
 
@@ -342,6 +572,7 @@ mod tests {
                 "type": "object",
                 "properties": {}
             })),
+            strict: None,
         }]);
 
         let decision = router.route(&mut request).unwrap();
@@ -365,6 +596,7 @@ mod tests {
             name: None,
             description: None,
             input_schema: None,
+            strict: None,
         }]);
 
         let decision = router.route(&mut request).unwrap();
@@ -414,4 +646,79 @@ mod tests {
         assert_eq!(decision.route_type, RouteType::Default);
         assert_eq!(decision.model_name, "glm-4.6"); // Uses original model name (no auto-mapping)
     }
+
+    #[test]
+    fn test_explain_records_rules() {
+        let router = Router::new(create_test_config());
+        let mut request = create_simple_request("Hello");
+        request.model = "claude-3-5-haiku-20241022".to_string();
+
+        let (decision, checks) = router.explain(&mut request, None);
+        assert_eq!(decision.unwrap().route_type, RouteType::Background);
+        let rules: Vec<_> = checks.iter().map(|c| (c.rule, c.matched)).collect();
+        assert_eq!(
+            rules,
+            [("auto_map", true), ("websearch", false), ("subagent", false), ("think", false), ("background", true)]
+        );
+    }
+
+    #[test]
+    fn test_role_models() {
+        let mut config = create_test_config();
+        config.router.roles.main = Some("main.model".to_string());
+        config.router.roles.subagent = Some("subagent.model".to_string());
+        let router = Router::new(config);
+
+        let mut request = create_simple_request("Find the config loader");
+        request.system = Some(SystemPrompt::Text(
+            "You are an agent for Claude Code, Anthropic's official CLI for Claude.".to_string(),
+        ));
+        let decision = router.route(&mut request.clone()).unwrap();
+        assert_eq!((decision.model_name.as_str(), decision.route_type), ("subagent.model", RouteType::Subagent));
+
+        request.system = Some(SystemPrompt::Text("You are an interactive CLI tool that helps users".to_string()));
+        assert_eq!(router.route(&mut request.clone()).unwrap().model_name, "main.model");
+
+        // The header wins over detection; plan falls back to router.think
+        let decision = router.route_as(&mut request.clone(), Some(Role::Plan)).unwrap();
+        assert_eq!((decision.model_name.as_str(), decision.route_type), ("think.model", RouteType::Think));
+        assert_eq!("Background".parse::<Role>(), Ok(Role::Background));
+
+        // Requests from other clients keep the regular rules
+        request.system = None;
+        assert_eq!(router.route(&mut request).unwrap().model_name, "default.model");
+    }
+
+    #[test]
+    fn test_utility_call_detection() {
+        let mut config = create_test_config();
+        config.router.background_detection.enabled = true;
+        config.router.background_detection.model = Some("cheap.model".to_string());
+        let router = Router::new(config);
+
+        // A title request through a non-Haiku small model
+        let mut request = create_simple_request("Fix the login bug");
+        request.model = "glm-4.5-air".to_string();
+        request.max_tokens = 512;
+        request.system = Some(SystemPrompt::Text(
+            "Analyze if this message indicates a new conversation topic. If it does, extract a 2-3 word title."
+                .to_string(),
+        ));
+        let decision = router.route(&mut request.clone()).unwrap();
+        assert_eq!(decision.route_type, RouteType::Background);
+        assert_eq!(decision.model_name, "cheap.model");
+
+        // The main conversation asks for more output and carries tools
+        request.max_tokens = 32_000;
+        assert_eq!(router.route(&mut request.clone()).unwrap().model_name, "glm-4.5-air");
+        request.max_tokens = 512;
+        request.tools = Some(vec![crate::models::Tool {
+            r#type: None,
+            name: Some("Read".to_string()),
+            description: None,
+            input_schema: None,
+            strict: None,
+        }]);
+        assert_eq!(router.route(&mut request).unwrap().model_name, "glm-4.5-air");
+    }
 }
@@ -0,0 +1,52 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+
+use super::limits::error_response;
+use super::AppState;
+
+/// Require an OIDC bearer token on admin routes when `[server.oidc]` is set
+///
+/// The token is read from `Authorization: Bearer` or, behind oauth2-proxy,
+/// `X-Forwarded-Access-Token`. On success the verified subject replaces any
+/// client-supplied `X-Forwarded-User`, so audit entries can't be spoofed.
+pub async fn require_admin(State(state): State<Arc<AppState>>, mut request: Request<Body>, next: Next) -> Response {
+    let Some(verifier) = &state.oidc else {
+        return next.run(request).await;
+    };
+
+    let headers = request.headers();
+    let token = headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-forwarded-access-token").and_then(|v| v.to_str().ok()));
+    let Some(token) = token else {
+        return unauthorized("Missing bearer token".to_string());
+    };
+
+    match verifier.verify(token).await {
+        Ok(claims) => {
+            let headers = request.headers_mut();
+            headers.remove("x-forwarded-email");
+            match HeaderValue::from_str(&claims.sub) {
+                Ok(subject) => headers.insert("x-forwarded-user", subject),
+                Err(_) => headers.remove("x-forwarded-user"),
+            };
+            next.run(request).await
+        }
+        Err(e) => {
+            tracing::warn!("🔒 Rejected admin token: {}", e);
+            unauthorized(format!("Invalid admin token: {}", e))
+        }
+    }
+}
+
+fn unauthorized(message: String) -> Response {
+    error_response(StatusCode::UNAUTHORIZED, "authentication_error", message)
+}
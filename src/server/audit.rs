@@ -0,0 +1,35 @@
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::sync::Arc;
+
+use crate::storage::audit::AuditQuery;
+
+use super::limits::error_response;
+use super::AppState;
+
+/// Who is making an admin request
+///
+/// Uses the identity set by an authenticating reverse proxy, or the token
+/// subject when `[server.oidc]` is set (see `admin_auth::require_admin`).
+pub fn actor(headers: &HeaderMap) -> String {
+    ["x-forwarded-user", "x-forwarded-email"]
+        .iter()
+        .find_map(|name| headers.get(*name).and_then(|v| v.to_str().ok()))
+        .filter(|v| !v.is_empty())
+        .unwrap_or("admin")
+        .to_string()
+}
+
+/// GET /admin/audit - recorded admin actions, newest first
+///
+/// Query: `action` (exact, or a prefix ending in `.`), `actor`, `since` (RFC 3339), `limit` (default 100)
+pub async fn list_audit(State(state): State<Arc<AppState>>, Query(query): Query<AuditQuery>) -> Response {
+    match state.audit_log.query(&query) {
+        Ok(entries) => Json(entries).into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, "api_error", e.to_string()),
+    }
+}
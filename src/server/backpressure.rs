@@ -0,0 +1,181 @@
+use axum::{extract::State, Json};
+use bytes::Bytes;
+use futures::stream::{Stream, StreamExt};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use crate::providers::error::ProviderError;
+
+use super::AppState;
+
+/// Buffering of client-facing streams across all requests
+#[derive(Debug, Default)]
+pub struct StreamMetrics {
+    active: AtomicUsize,
+    buffered_bytes: AtomicUsize,
+    high_water_bytes: AtomicUsize,
+    upstream_pauses: AtomicU64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StreamMetricsSnapshot {
+    pub active_streams: usize,
+    /// Bytes read from upstream but not yet taken by clients
+    pub buffered_bytes: usize,
+    /// Highest `buffered_bytes` since startup
+    pub high_water_bytes: usize,
+    /// Times upstream reads were paused because a client fell behind
+    pub upstream_pauses: u64,
+}
+
+impl StreamMetrics {
+    pub fn snapshot(&self) -> StreamMetricsSnapshot {
+        StreamMetricsSnapshot {
+            active_streams: self.active.load(Ordering::Relaxed),
+            buffered_bytes: self.buffered_bytes.load(Ordering::Relaxed),
+            high_water_bytes: self.high_water_bytes.load(Ordering::Relaxed),
+            upstream_pauses: self.upstream_pauses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn add(&self, bytes: usize) {
+        let buffered = self.buffered_bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.high_water_bytes.fetch_max(buffered, Ordering::Relaxed);
+    }
+
+    fn sub(&self, bytes: usize) {
+        self.buffered_bytes.fetch_sub(bytes, Ordering::Relaxed);
+    }
+}
+
+/// Bytes queued for one stream; whichever side finishes last releases the rest
+struct Queued {
+    bytes: AtomicUsize,
+    metrics: Arc<StreamMetrics>,
+}
+
+impl Queued {
+    fn release(&self) {
+        self.metrics.sub(self.bytes.swap(0, Ordering::Relaxed));
+    }
+}
+
+/// Receiving half; releases whatever the client never read
+struct Receiver {
+    rx: mpsc::Receiver<Result<Bytes, ProviderError>>,
+    queued: Arc<Queued>,
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        self.queued.release();
+        self.queued.metrics.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Relay an upstream stream through a channel of at most `capacity` chunks
+///
+/// Upstream is read by a separate task that waits whenever the channel is full, so
+/// a slow client pauses the upstream connection (via TCP flow control) instead of
+/// piling chunks up in memory. Dropping the client stream drops the upstream stream
+/// right away, even while it is waiting for the next chunk.
+pub fn relay<S>(
+    mut stream: S,
+    capacity: usize,
+    metrics: Arc<StreamMetrics>,
+) -> impl Stream<Item = Result<Bytes, ProviderError>> + Send
+where
+    S: Stream<Item = Result<Bytes, ProviderError>> + Send + Unpin + 'static,
+{
+    let (tx, rx) = mpsc::channel(capacity.max(1));
+    metrics.active.fetch_add(1, Ordering::Relaxed);
+    let queued = Arc::new(Queued {
+        bytes: AtomicUsize::new(0),
+        metrics,
+    });
+
+    let sender_queued = queued.clone();
+    tokio::spawn(async move {
+        loop {
+            let item = tokio::select! {
+                // Client went away (or the request was cancelled) while upstream was idle
+                _ = tx.closed() => break,
+                item = stream.next() => match item {
+                    Some(item) => item,
+                    None => break,
+                },
+            };
+            let len = item.as_ref().map_or(0, |b| b.len());
+            sender_queued.bytes.fetch_add(len, Ordering::Relaxed);
+            sender_queued.metrics.add(len);
+            if tx.capacity() == 0 {
+                sender_queued.metrics.upstream_pauses.fetch_add(1, Ordering::Relaxed);
+            }
+            if tx.send(item).await.is_err() {
+                // Client went away
+                sender_queued.release();
+                break;
+            }
+        }
+    });
+
+    futures::stream::unfold(Receiver { rx, queued }, |mut receiver| async move {
+        let item = receiver.rx.recv().await?;
+        if let Ok(bytes) = &item {
+            receiver.queued.bytes.fetch_sub(bytes.len(), Ordering::Relaxed);
+            receiver.queued.metrics.sub(bytes.len());
+        }
+        Some((item, receiver))
+    })
+}
+
+/// GET /admin/streams - client stream buffering
+pub async fn stream_metrics(State(state): State<Arc<AppState>>) -> Json<StreamMetricsSnapshot> {
+    Json(state.stream_metrics.snapshot())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_relay_bounds_buffering() {
+        let metrics = Arc::new(StreamMetrics::default());
+        let chunks: Vec<Result<Bytes, ProviderError>> = (0..10).map(|_| Ok(Bytes::from_static(b"0123456789"))).collect();
+        let mut stream = Box::pin(relay(futures::stream::iter(chunks), 2, metrics.clone()));
+
+        // Nothing read yet: upstream stops once the channel is full
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        assert!(metrics.snapshot().buffered_bytes <= 30);
+        assert!(metrics.snapshot().upstream_pauses > 0);
+
+        let mut total = 0;
+        while let Some(chunk) = stream.next().await {
+            total += chunk.unwrap().len();
+        }
+        assert_eq!(total, 100);
+        drop(stream);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!((snapshot.active_streams, snapshot.buffered_bytes), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_relay_drops_idle_upstream_on_disconnect() {
+        let upstream = Arc::new(());
+        let held = upstream.clone();
+        let stream = futures::stream::pending::<Result<Bytes, ProviderError>>().map(move |item| {
+            let _ = &held;
+            item
+        });
+        let client = relay(stream, 2, Arc::new(StreamMetrics::default()));
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        assert_eq!(Arc::strong_count(&upstream), 2);
+
+        drop(client);
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        assert_eq!(Arc::strong_count(&upstream), 1);
+    }
+}
@@ -0,0 +1,226 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Duration;
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::auth::api_keys::{check_config_keys, Client, KeyCheck, PrivacySettings};
+use crate::storage::AuditEntry;
+
+use super::limits::error_response;
+use super::{audit, client_api_key, AppState};
+
+/// Require a valid client key on API routes once any key is configured
+///
+/// Accepted keys are `server.api_key`, unexpired `[[server.api_keys]]` entries and
+/// keys minted through `/admin/keys`.
+pub async fn require_api_key(State(state): State<Arc<AppState>>, request: Request<Body>, next: Next) -> Response {
+    let active = state.active();
+    let server = &active.config.server;
+    if server.api_key.is_none() && server.api_keys.is_empty() && state.api_keys.is_empty() {
+        return next.run(request).await;
+    }
+
+    let Some(key) = client_api_key(request.headers()) else {
+        return unauthorized("Missing API key (x-api-key or Authorization: Bearer)");
+    };
+    if server.api_key.as_deref() == Some(key) {
+        return next.run(request).await;
+    }
+    let check = match check_config_keys(&server.api_keys, key) {
+        KeyCheck::Unknown => state.api_keys.check(key),
+        check => check,
+    };
+    match check {
+        KeyCheck::Valid(client) => {
+            tracing::debug!("🔑 Request from client '{}'", client.name);
+            next.run(request).await
+        }
+        KeyCheck::Expired => unauthorized("API key expired; rotate to the new key"),
+        KeyCheck::Unknown => unauthorized("Invalid API key"),
+    }
+}
+
+/// Client of the request's key, for attributing usage and applying its privacy settings
+pub fn client(state: &AppState, headers: &HeaderMap) -> Option<Client> {
+    let key = client_api_key(headers)?;
+    let check = match check_config_keys(&state.active().config.server.api_keys, key) {
+        KeyCheck::Unknown => state.api_keys.check(key),
+        check => check,
+    };
+    match check {
+        KeyCheck::Valid(client) => Some(client),
+        _ => None,
+    }
+}
+
+/// Require an admin credential on `/admin/keys*`
+///
+/// Without it, any client turned away from `/v1` could mint itself a key on the
+/// same port. With `[server.oidc]` the admin token was already checked; otherwise
+/// only `server.api_key` may manage keys, and without either the routes are refused.
+pub async fn require_key_admin(State(state): State<Arc<AppState>>, request: Request<Body>, next: Next) -> Response {
+    let master = state.active().config.server.api_key.clone();
+    match key_admin_rejection(state.oidc.is_some(), master.as_deref(), client_api_key(request.headers())) {
+        None => next.run(request).await,
+        Some((StatusCode::FORBIDDEN, message)) => {
+            error_response(StatusCode::FORBIDDEN, "permission_error", message.to_string())
+        }
+        Some((_, message)) => unauthorized(message),
+    }
+}
+
+/// Why a key management request is refused, if it is
+fn key_admin_rejection(oidc: bool, master: Option<&str>, key: Option<&str>) -> Option<(StatusCode, &'static str)> {
+    if oidc {
+        return None;
+    }
+    match master {
+        None => Some((
+            StatusCode::FORBIDDEN,
+            "Key management requires [server.oidc] or server.api_key",
+        )),
+        Some(master) if key == Some(master) => None,
+        Some(_) => Some((StatusCode::UNAUTHORIZED, "Key management requires server.api_key")),
+    }
+}
+
+fn unauthorized(message: &str) -> Response {
+    error_response(StatusCode::UNAUTHORIZED, "authentication_error", message.to_string())
+}
+
+/// GET /admin/keys - client keys (never the key values)
+pub async fn list_keys(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    let config_keys: Vec<_> = state
+        .active()
+        .config
+        .server
+        .api_keys
+        .iter()
+        .map(|k| {
+            serde_json::json!({
+                "client": k.client,
+                "expires_at": k.expires_at,
+                "privacy": k.privacy,
+                "source": "config",
+            })
+        })
+        .collect();
+    Json(serde_json::json!({
+        "config": config_keys,
+        "minted": state.api_keys.list(),
+    }))
+}
+
+/// Default time rotated keys keep working
+const DEFAULT_GRACE_SECS: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Deserialize)]
+pub struct MintRequest {
+    pub client: String,
+    /// Lifetime of the new key (default: no expiry)
+    #[serde(default)]
+    pub expires_in_secs: Option<i64>,
+    /// Expire the client's other keys after the rotation grace period
+    #[serde(default)]
+    pub rotate: bool,
+    /// Grace period for rotated keys (default: one day)
+    #[serde(default)]
+    pub grace_secs: Option<i64>,
+    /// `log_bodies`, `store_sessions` and `metrics` for requests with this key
+    #[serde(flatten)]
+    pub privacy: PrivacySettings,
+}
+
+/// POST /admin/keys - mint a client key; the key is only returned here
+pub async fn mint_key(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<MintRequest>,
+) -> Response {
+    let grace = request
+        .rotate
+        .then(|| Duration::seconds(request.grace_secs.unwrap_or(DEFAULT_GRACE_SECS)));
+    match state
+        .api_keys
+        .mint(
+            &request.client,
+            request.privacy.clone(),
+            request.expires_in_secs.map(Duration::seconds),
+            grace,
+        )
+    {
+        Ok((key, record)) => {
+            info!("🔑 Minted API key {} for client '{}'", record.id, record.client);
+            let action = if request.rotate { "keys.rotate" } else { "keys.mint" };
+            state.audit_log.record(
+                AuditEntry::new(audit::actor(&headers), action)
+                    .target(record.id.clone())
+                    .diff(&serde_json::Value::Null, &serde_json::to_value(&record).unwrap_or_default()),
+            );
+            Json(serde_json::json!({ "key": key, "record": record })).into_response()
+        }
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, "api_error", e.to_string()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevokeRequest {
+    pub id: String,
+    /// Keep the key working for this long (default: revoke immediately)
+    #[serde(default)]
+    pub grace_secs: i64,
+}
+
+/// POST /admin/keys/revoke - expire a minted key, optionally after a grace period
+pub async fn revoke_key(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<RevokeRequest>,
+) -> Response {
+    match state.api_keys.revoke(&request.id, Duration::seconds(request.grace_secs)) {
+        Ok(true) => {
+            info!("🔑 Revoked API key {} (grace {}s)", request.id, request.grace_secs);
+            state.audit_log.record(
+                AuditEntry::new(audit::actor(&headers), "keys.revoke").target(request.id.clone()),
+            );
+            Json(serde_json::json!({ "revoked": request.id })).into_response()
+        }
+        Ok(false) => error_response(
+            StatusCode::NOT_FOUND,
+            "not_found_error",
+            format!("No API key with id '{}'", request.id),
+        ),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, "api_error", e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_admin_rejection() {
+        // Open admin API: key management is refused outright
+        assert_eq!(key_admin_rejection(false, None, None).unwrap().0, StatusCode::FORBIDDEN);
+        assert_eq!(key_admin_rejection(false, None, Some("sk-client")).unwrap().0, StatusCode::FORBIDDEN);
+
+        // Only the server key may mint; client keys may not
+        assert_eq!(
+            key_admin_rejection(false, Some("sk-master"), Some("sk-client")).unwrap().0,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(key_admin_rejection(false, Some("sk-master"), None).unwrap().0, StatusCode::UNAUTHORIZED);
+        assert!(key_admin_rejection(false, Some("sk-master"), Some("sk-master")).is_none());
+
+        // Checked by the OIDC layer
+        assert!(key_admin_rejection(true, None, None).is_none());
+    }
+}
@@ -0,0 +1,234 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+use super::limits::error_response;
+use super::request_log::RequestLogEntry;
+use super::AppState;
+
+/// Texts longer than this many lines are summarized without a line diff
+const MAX_DIFF_LINES: usize = 2000;
+
+#[derive(Debug, Deserialize)]
+pub struct CompareQuery {
+    pub a: String,
+    pub b: String,
+}
+
+/// One side of a comparison
+#[derive(Debug, Serialize)]
+pub struct ResponseSummary {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actual_model: Option<String>,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+    /// Characters of text output
+    pub text_chars: usize,
+    pub tool_calls: Vec<ToolCall>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolCall {
+    pub name: String,
+    pub input: Value,
+}
+
+/// A run of lines in a text diff
+#[derive(Debug, PartialEq, Serialize)]
+pub struct DiffHunk {
+    /// `equal`, `delete` (only in a) or `insert` (only in b)
+    pub op: &'static str,
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Comparison {
+    pub a: ResponseSummary,
+    pub b: ResponseSummary,
+    /// Both requests sent the same system prompt and messages
+    pub same_prompt: bool,
+    pub same_tool_calls: bool,
+    /// Line diff of the text output (absent for very long texts)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_diff: Option<Vec<DiffHunk>>,
+}
+
+/// GET /admin/logs/compare?a=<id>&b=<id> - diff two logged responses
+///
+/// Both requests need their bodies kept (`[server] replay_buffer`) and a
+/// non-streaming response, such as a replay and its original.
+pub async fn compare(State(state): State<Arc<AppState>>, Query(query): Query<CompareQuery>) -> Response {
+    let mut entries = Vec::new();
+    for id in [&query.a, &query.b] {
+        let Some(entry) = state.request_log.get(id) else {
+            return error_response(StatusCode::NOT_FOUND, "not_found_error", format!("No logged request '{}'", id));
+        };
+        if entry.response_body.is_none() {
+            return error_response(
+                StatusCode::CONFLICT,
+                "invalid_request_error",
+                format!("The response of '{}' was not kept (streamed, or [server] replay_buffer is off)", id),
+            );
+        }
+        entries.push(entry);
+    }
+    Json(comparison(&entries[0], &entries[1])).into_response()
+}
+
+fn comparison(a: &RequestLogEntry, b: &RequestLogEntry) -> Comparison {
+    let prompt = |entry: &RequestLogEntry| {
+        entry
+            .request_body
+            .as_ref()
+            .map(|body| (body.get("system").cloned(), body.get("messages").cloned()))
+    };
+    let same_prompt = prompt(a).is_some() && prompt(a) == prompt(b);
+
+    let (text_a, text_b) = (text(a), text(b));
+    let short = text_a.lines().count() <= MAX_DIFF_LINES && text_b.lines().count() <= MAX_DIFF_LINES;
+    let text_diff = short.then(|| line_diff(&text_a, &text_b));
+    let (a, b) = (summary(a, &text_a), summary(b, &text_b));
+
+    Comparison {
+        same_prompt,
+        same_tool_calls: a.tool_calls == b.tool_calls,
+        text_diff,
+        a,
+        b,
+    }
+}
+
+fn content(entry: &RequestLogEntry) -> &[Value] {
+    entry
+        .response_body
+        .as_ref()
+        .and_then(|body| body["content"].as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+/// Text blocks of a response, joined
+fn text(entry: &RequestLogEntry) -> String {
+    content(entry)
+        .iter()
+        .filter(|block| block["type"] == "text")
+        .filter_map(|block| block["text"].as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn summary(entry: &RequestLogEntry, text: &str) -> ResponseSummary {
+    let tool_calls = content(entry)
+        .iter()
+        .filter(|block| block["type"] == "tool_use")
+        .map(|block| ToolCall {
+            name: block["name"].as_str().unwrap_or_default().to_string(),
+            input: block["input"].clone(),
+        })
+        .collect();
+    ResponseSummary {
+        id: entry.id.clone(),
+        provider: entry.provider.clone(),
+        actual_model: entry.actual_model.clone(),
+        latency_ms: entry.latency_ms,
+        input_tokens: entry.input_tokens,
+        output_tokens: entry.output_tokens,
+        cost_usd: entry.cost_usd,
+        stop_reason: entry
+            .response_body
+            .as_ref()
+            .and_then(|body| body["stop_reason"].as_str())
+            .map(str::to_string),
+        text_chars: text.chars().count(),
+        tool_calls,
+    }
+}
+
+/// Line diff (longest common subsequence), as runs of equal, deleted and inserted lines
+fn line_diff(a: &str, b: &str) -> Vec<DiffHunk> {
+    let (a, b): (Vec<&str>, Vec<&str>) = (a.lines().collect(), b.lines().collect());
+    // lcs[i][j]: common subsequence length of a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut hunks: Vec<DiffHunk> = Vec::new();
+    let mut push = |op: &'static str, line: &str| match hunks.last_mut() {
+        Some(hunk) if hunk.op == op => hunk.lines.push(line.to_string()),
+        _ => hunks.push(DiffHunk { op, lines: vec![line.to_string()] }),
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            push("equal", a[i]);
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            push("delete", a[i]);
+            i += 1;
+        } else {
+            push("insert", b[j]);
+            j += 1;
+        }
+    }
+    hunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(provider: &str, content: Value) -> RequestLogEntry {
+        let request = serde_json::json!({ "model": "sonnet", "messages": [{ "role": "user", "content": "hi" }] });
+        let mut entry = RequestLogEntry::new("/v1/messages", &request);
+        entry.provider = Some(provider.to_string());
+        entry.request_body = Some(request);
+        entry.response_body = Some(serde_json::json!({ "content": content, "stop_reason": "end_turn" }));
+        entry
+    }
+
+    #[test]
+    fn test_comparison() {
+        let a = entry("anthropic", serde_json::json!([{ "type": "text", "text": "one\ntwo\nthree" }]));
+        let b = entry(
+            "gemini",
+            serde_json::json!([
+                { "type": "text", "text": "one\n2\nthree" },
+                { "type": "tool_use", "id": "t1", "name": "Read", "input": { "path": "a.rs" } },
+            ]),
+        );
+
+        let comparison = comparison(&a, &b);
+        assert!(comparison.same_prompt);
+        assert!(!comparison.same_tool_calls);
+        assert_eq!(comparison.a.text_chars, 13);
+        assert_eq!(comparison.b.tool_calls[0].name, "Read");
+        assert_eq!(comparison.b.stop_reason.as_deref(), Some("end_turn"));
+
+        let ops: Vec<(&str, usize)> = comparison.text_diff.unwrap().iter().map(|h| (h.op, h.lines.len())).collect();
+        assert_eq!(ops, vec![("equal", 1), ("delete", 1), ("insert", 1), ("equal", 1)]);
+    }
+}
@@ -0,0 +1,413 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use regex::Regex;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use tracing::info;
+
+use crate::cli::{AppConfig, ModelConfig};
+use crate::providers::ProviderRegistry;
+use crate::router::Router;
+use crate::storage::AuditEntry;
+
+use super::model_map::{self, ModelOverrides};
+use super::{audit, AppState};
+
+/// Configuration in effect, together with the router and providers built from it
+pub struct ActiveConfig {
+    pub config: AppConfig,
+    pub router: Router,
+    pub provider_registry: Arc<ProviderRegistry>,
+    /// Alias overrides from `model_map.txt`
+    pub model_overrides: ModelOverrides,
+}
+
+impl ActiveConfig {
+    /// Model config of an alias, with its `model_map.txt` override applied
+    pub fn model(&self, name: &str) -> Option<Cow<'_, ModelConfig>> {
+        model_map::resolve(&self.config.models, &self.model_overrides, name)
+    }
+}
+
+/// A changed scalar setting
+#[derive(Debug, Serialize, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// Routing-relevant differences between two configurations
+#[derive(Debug, Default, Serialize)]
+pub struct ConfigDiff {
+    pub router: Vec<FieldChange>,
+    pub models_added: Vec<String>,
+    pub models_removed: Vec<String>,
+    pub models_changed: Vec<String>,
+    pub providers_added: Vec<String>,
+    pub providers_removed: Vec<String>,
+    pub providers_changed: Vec<String>,
+    /// Sections that differ but are only read at startup
+    pub restart_required: Vec<String>,
+}
+
+impl ConfigDiff {
+    pub fn between(old: &AppConfig, new: &AppConfig) -> Self {
+        let mut diff = Self::default();
+
+        let (a, b) = (&old.router, &new.router);
+        let fields = [
+            ("default", Some(&a.default), Some(&b.default)),
+            ("background", a.background.as_ref(), b.background.as_ref()),
+            ("think", a.think.as_ref(), b.think.as_ref()),
+            ("websearch", a.websearch.as_ref(), b.websearch.as_ref()),
+            ("completion", a.completion.as_ref(), b.completion.as_ref()),
+            ("auto_map_regex", a.auto_map_regex.as_ref(), b.auto_map_regex.as_ref()),
+            ("background_regex", a.background_regex.as_ref(), b.background_regex.as_ref()),
+        ];
+        for (field, from, to) in fields {
+            if from != to {
+                diff.router.push(FieldChange {
+                    field: field.to_string(),
+                    from: from.cloned(),
+                    to: to.cloned(),
+                });
+            }
+        }
+        // Tables are compared, and reported, as JSON
+        let tables = [
+            ("roles", section(&a.roles), section(&b.roles)),
+            ("background_detection", section(&a.background_detection), section(&b.background_detection)),
+        ];
+        for (field, from, to) in tables {
+            if from != to {
+                diff.router.push(FieldChange {
+                    field: field.to_string(),
+                    from: from.map(|v| v.to_string()),
+                    to: to.map(|v| v.to_string()),
+                });
+            }
+        }
+
+        let old_models = by_name(old.models.iter().map(|m| (m.name.clone(), serde_json::to_value(m))));
+        let new_models = by_name(new.models.iter().map(|m| (m.name.clone(), serde_json::to_value(m))));
+        (diff.models_added, diff.models_removed, diff.models_changed) = compare(&old_models, &new_models);
+
+        let old_providers = by_name(old.providers.iter().map(|p| (p.name.clone(), serde_json::to_value(p))));
+        let new_providers = by_name(new.providers.iter().map(|p| (p.name.clone(), serde_json::to_value(p))));
+        (diff.providers_added, diff.providers_removed, diff.providers_changed) =
+            compare(&old_providers, &new_providers);
+
+        let sections = [
+            ("server", section(&old.server) != section(&new.server)),
+            ("blob_store", section(&old.blob_store) != section(&new.blob_store)),
+            ("alerting", section(&old.alerting) != section(&new.alerting)),
+            ("usage_report", section(&old.usage_report) != section(&new.usage_report)),
+            ("database", section(&old.database) != section(&new.database)),
+            ("shared_state", section(&old.shared_state) != section(&new.shared_state)),
+            ("semantic_cache", section(&old.semantic_cache) != section(&new.semantic_cache)),
+            ("billing", section(&old.billing) != section(&new.billing)),
+            ("trace_export", section(&old.trace_export) != section(&new.trace_export)),
+            ("archive", section(&old.archive) != section(&new.archive)),
+            ("judge", section(&old.judge) != section(&new.judge)),
+        ];
+        diff.restart_required = sections
+            .into_iter()
+            .filter(|(_, changed)| *changed)
+            .map(|(name, _)| name.to_string())
+            .collect();
+
+        diff
+    }
+}
+
+fn section<T: Serialize>(value: &T) -> Option<serde_json::Value> {
+    serde_json::to_value(value).ok()
+}
+
+fn by_name(
+    items: impl Iterator<Item = (String, serde_json::Result<serde_json::Value>)>,
+) -> BTreeMap<String, Option<serde_json::Value>> {
+    items.map(|(name, value)| (name, value.ok())).collect()
+}
+
+/// (added, removed, changed) names
+fn compare(
+    old: &BTreeMap<String, Option<serde_json::Value>>,
+    new: &BTreeMap<String, Option<serde_json::Value>>,
+) -> (Vec<String>, Vec<String>, Vec<String>) {
+    let added = new.keys().filter(|k| !old.contains_key(*k)).cloned().collect();
+    let removed = old.keys().filter(|k| !new.contains_key(*k)).cloned().collect();
+    let changed = new
+        .iter()
+        .filter(|(k, v)| old.get(*k).is_some_and(|old| old != *v))
+        .map(|(k, _)| k.clone())
+        .collect();
+    (added, removed, changed)
+}
+
+/// Result of validating a candidate configuration
+#[derive(Debug, Default, Serialize)]
+pub struct ValidationReport {
+    pub valid: bool,
+    pub applied: bool,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<ConfigDiff>,
+}
+
+/// Parse and check a candidate config; returns it with the provider registry when valid
+fn validate(
+    state: &AppState,
+    content: &str,
+) -> (ValidationReport, Option<(AppConfig, ProviderRegistry)>) {
+    let mut report = ValidationReport::default();
+
+    let config = match AppConfig::from_toml_str(content) {
+        Ok(config) => config,
+        Err(e) => {
+            report.errors.push(format!("Invalid config: {:#}", e));
+            return (report, None);
+        }
+    };
+
+    report.errors = check_config(&config);
+    report.warnings = routing_warnings(&config);
+
+    let registry = match ProviderRegistry::from_configs(&config.providers, &config.server.timeouts, Some(state.token_store.clone())) {
+        Ok(registry) => Some(registry),
+        Err(e) => {
+            report.errors.push(e.to_string());
+            None
+        }
+    };
+
+    let diff = ConfigDiff::between(&state.active().config, &config);
+    for section in &diff.restart_required {
+        report
+            .warnings
+            .push(format!("Changes to [{}] take effect after a restart", section));
+    }
+    report.diff = Some(diff);
+    report.valid = report.errors.is_empty();
+
+    match registry {
+        Some(registry) if report.valid => (report, Some((config, registry))),
+        _ => (report, None),
+    }
+}
+
+/// Structural errors that would break routing
+fn check_config(config: &AppConfig) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    for (field, pattern) in [
+        ("auto_map_regex", &config.router.auto_map_regex),
+        ("background_regex", &config.router.background_regex),
+    ] {
+        if let Some(pattern) = pattern.as_deref().filter(|p| !p.is_empty()) {
+            if let Err(e) = Regex::new(pattern) {
+                errors.push(format!("router.{} is not a valid regex: {}", field, e));
+            }
+        }
+    }
+
+    let mut provider_names = HashSet::new();
+    for provider in &config.providers {
+        if !provider_names.insert(provider.name.as_str()) {
+            errors.push(format!("Duplicate provider '{}'", provider.name));
+        }
+    }
+
+    let enabled: HashSet<&str> = config
+        .providers
+        .iter()
+        .filter(|p| p.is_enabled())
+        .map(|p| p.name.as_str())
+        .collect();
+
+    let mut model_names = HashSet::new();
+    for model in &config.models {
+        if !model_names.insert(model.name.as_str()) {
+            errors.push(format!("Duplicate model '{}'", model.name));
+        }
+        if model.mappings.is_empty() {
+            errors.push(format!("Model '{}' has no mappings", model.name));
+        }
+        for mapping in &model.mappings {
+            if !enabled.contains(mapping.provider.as_str()) {
+                errors.push(format!(
+                    "Model '{}' maps to unknown or disabled provider '{}'",
+                    model.name, mapping.provider
+                ));
+            }
+        }
+    }
+
+    errors
+}
+
+/// Router targets that aren't configured models still work if a provider serves them directly
+fn routing_warnings(config: &AppConfig) -> Vec<String> {
+    let router = &config.router;
+    [
+        ("default", Some(&router.default)),
+        ("background", router.background.as_ref()),
+        ("think", router.think.as_ref()),
+        ("websearch", router.websearch.as_ref()),
+        ("completion", router.completion.as_ref()),
+    ]
+    .into_iter()
+    .filter_map(|(field, model)| Some((field, model?)))
+    .filter(|(_, model)| !config.models.iter().any(|m| &m.name == *model))
+    .map(|(field, model)| format!("router.{} '{}' is not a configured model", field, model))
+    .collect()
+}
+
+fn report_response(report: ValidationReport) -> Response {
+    let status = if report.valid {
+        StatusCode::OK
+    } else {
+        StatusCode::UNPROCESSABLE_ENTITY
+    };
+    (status, Json(report)).into_response()
+}
+
+/// POST /admin/config/validate - check a candidate TOML config and diff it against the active one
+pub async fn validate_config(State(state): State<Arc<AppState>>, body: String) -> Response {
+    let (report, _) = validate(&state, &body);
+    report_response(report)
+}
+
+/// POST /admin/config/reload - validate and apply a TOML config
+///
+/// With a body, the candidate is written to the config file and applied; with an
+/// empty body, the config file is re-read from disk.
+pub async fn reload_config(State(state): State<Arc<AppState>>, headers: HeaderMap, body: String) -> Response {
+    let from_body = !body.trim().is_empty();
+    let content = if from_body {
+        body
+    } else {
+        match std::fs::read_to_string(&state.config_path) {
+            Ok(content) => content,
+            Err(e) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read config: {}", e))
+                    .into_response()
+            }
+        }
+    };
+
+    let (mut report, candidate) = validate(&state, &content);
+    let Some((config, registry)) = candidate else {
+        return report_response(report);
+    };
+
+    if from_body {
+        if let Err(e) = write_atomically(&state.config_path, &content) {
+            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write config: {}", e))
+                .into_response();
+        }
+    }
+
+    let audit = AuditEntry::new(audit::actor(&headers), "config.reload").diff(
+        &serde_json::to_value(&state.active().config).unwrap_or_default(),
+        &serde_json::to_value(&config).unwrap_or_default(),
+    );
+    let active = Arc::new(ActiveConfig {
+        router: Router::new(config.clone()),
+        provider_registry: Arc::new(registry),
+        model_overrides: model_map::load(&model_map::path(&state.config_path), &config),
+        config,
+    });
+    *state.active.write().unwrap_or_else(|e| e.into_inner()) = active;
+    state.audit_log.record(audit);
+    report.applied = true;
+
+    info!("🔄 Configuration reloaded ({} models, {} providers)",
+        state.active().config.models.len(),
+        state.active().provider_registry.list_providers().len()
+    );
+    report_response(report)
+}
+
+/// Write via a temporary file so readers never see a partial config
+fn write_atomically(path: &std::path::Path, content: &str) -> std::io::Result<()> {
+    let tmp = path.with_extension("toml.tmp");
+    std::fs::write(&tmp, content)?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = r#"
+[router]
+default = "sonnet"
+
+[[providers]]
+name = "zai"
+provider_type = "z.ai"
+api_key = "key"
+models = []
+
+[[models]]
+name = "sonnet"
+[[models.mappings]]
+priority = 1
+provider = "zai"
+actual_model = "glm-4.6"
+"#;
+
+    #[test]
+    fn test_diff_reports_routing_changes() {
+        let old = AppConfig::from_toml_str(BASE).unwrap();
+        let new = AppConfig::from_toml_str(
+            &BASE
+                .replace("default = \"sonnet\"", "default = \"sonnet\"\nthink = \"sonnet\"")
+                .replace("glm-4.6", "glm-4.7"),
+        )
+        .unwrap();
+
+        let diff = ConfigDiff::between(&old, &new);
+        assert_eq!(
+            diff.router,
+            vec![FieldChange {
+                field: "think".to_string(),
+                from: None,
+                to: Some("sonnet".to_string()),
+            }]
+        );
+        assert_eq!(diff.models_changed, vec!["sonnet"]);
+        assert!(diff.providers_changed.is_empty());
+        assert!(diff.restart_required.is_empty());
+
+        let new = AppConfig::from_toml_str(&BASE.replace(
+            "default = \"sonnet\"",
+            "default = \"sonnet\"\n[router.roles]\nsubagent = \"sonnet\"\n[router.background_detection]\nenabled = true",
+        ))
+        .unwrap();
+        let diff = ConfigDiff::between(&old, &new);
+        let fields: Vec<_> = diff.router.iter().map(|c| c.field.as_str()).collect();
+        assert_eq!(fields, ["roles", "background_detection"]);
+        assert_eq!(diff.router[0].from.as_deref(), Some("{}"));
+        assert_eq!(diff.router[0].to.as_deref(), Some(r#"{"subagent":"sonnet"}"#));
+    }
+
+    #[test]
+    fn test_check_config_rejects_unknown_provider() {
+        let config = AppConfig::from_toml_str(&BASE.replace("provider = \"zai\"", "provider = \"nope\"")).unwrap();
+        let errors = check_config(&config);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("'nope'"));
+
+        assert!(check_config(&AppConfig::from_toml_str(BASE).unwrap()).is_empty());
+    }
+}
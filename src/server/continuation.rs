@@ -0,0 +1,450 @@
+use bytes::Bytes;
+use futures::stream::{Stream, StreamExt};
+use std::pin::Pin;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::cli::Continuation;
+use crate::models::{AnthropicRequest, ContentBlock, Message, MessageContent};
+use crate::providers::error::ProviderError;
+use crate::providers::streaming::{parse_sse_events, LineBuffer, SseEvent};
+use crate::providers::{AnthropicProvider, ProviderResponse};
+
+type ByteStream = Pin<Box<dyn Stream<Item = Result<Bytes, ProviderError>> + Send>>;
+
+/// Output budget of a request whose `max_tokens` exceeds the model's limit
+///
+/// `prepare` caps the request at the limit; the difference is produced by
+/// continuation rounds that prefill the partial answer and ask for more.
+#[derive(Debug, Clone)]
+pub struct Budget {
+    /// Tokens the client asked for
+    requested: u32,
+    /// Model output limit per round
+    per_round: u32,
+    max_rounds: u32,
+}
+
+impl Budget {
+    /// Cap `max_tokens` at the model's limit (None when no continuation is needed)
+    pub fn prepare(request: &mut AnthropicRequest, continuation: Option<&Continuation>) -> Option<Self> {
+        let continuation = continuation?;
+        if request.max_tokens <= continuation.max_output_tokens {
+            return None;
+        }
+        let budget = Self {
+            requested: request.max_tokens,
+            per_round: continuation.max_output_tokens,
+            max_rounds: continuation.max_rounds,
+        };
+        request.max_tokens = continuation.max_output_tokens;
+        Some(budget)
+    }
+
+    /// Request for the next round, or None when the budget is spent
+    fn next_request(&self, base: &AnthropicRequest, round: u32, produced: u32, text: &str) -> Option<AnthropicRequest> {
+        let remaining = self.requested.saturating_sub(produced);
+        if round >= self.max_rounds || remaining == 0 || text.trim_end().is_empty() {
+            return None;
+        }
+        let mut request = base.clone();
+        request.max_tokens = remaining.min(self.per_round);
+        // Each round is a new request upstream
+        request.idempotency_key = base.idempotency_key.as_ref().map(|key| format!("{}-{}", key, round + 1));
+        // A prefilled assistant turn can't be combined with extended thinking,
+        // and must not end in whitespace
+        request.thinking = None;
+        request.messages.push(Message {
+            role: "assistant".to_string(),
+            content: MessageContent::Blocks(vec![ContentBlock::Text {
+                text: text.trim_end().to_string(),
+            }]),
+        });
+        Some(request)
+    }
+}
+
+/// Text of a continuation that follows `prefix` (whitespace trimmed from the
+/// prefill is not repeated)
+fn join_text(prefix: &str, continuation: &str) -> String {
+    if prefix.ends_with(char::is_whitespace) {
+        continuation.trim_start().to_string()
+    } else {
+        continuation.to_string()
+    }
+}
+
+fn text_of(content: &[ContentBlock]) -> String {
+    content
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::Text { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Send a request, continuing while it stops at `max_tokens` within the budget,
+/// and stitch the rounds into one response
+pub async fn send_message(
+    provider: &dyn AnthropicProvider,
+    request: AnthropicRequest,
+    budget: &Budget,
+) -> Result<ProviderResponse, ProviderError> {
+    let mut response = provider.send_message(request.clone()).await?;
+    let mut round = 0;
+    while response.stop_reason.as_deref() == Some("max_tokens")
+        && matches!(response.content.last(), Some(ContentBlock::Text { .. }))
+    {
+        let text = text_of(&response.content);
+        let Some(next) = budget.next_request(&request, round, response.usage.output_tokens, &text) else {
+            break;
+        };
+        round += 1;
+        info!("➡️  Output hit max_tokens; continuation round {}", round);
+        let more = match provider.send_message(next).await {
+            Ok(more) => more,
+            Err(e) => {
+                warn!("Continuation round {} failed, returning partial output: {}", round, e);
+                break;
+            }
+        };
+
+        let mut blocks = more.content.into_iter();
+        if let Some(ContentBlock::Text { text: tail }) = blocks.next() {
+            let tail = join_text(&text, &tail);
+            if let Some(ContentBlock::Text { text }) = response.content.last_mut() {
+                text.push_str(&tail);
+            }
+        }
+        response.content.extend(blocks);
+        response.usage.input_tokens += more.usage.input_tokens;
+        response.usage.output_tokens += more.usage.output_tokens;
+        response.stop_reason = more.stop_reason;
+        response.stop_sequence = more.stop_sequence;
+    }
+    Ok(response)
+}
+
+/// Continue a stream that stops at `max_tokens`: the next round's events are
+/// spliced in (its `message_start` dropped, block indices shifted, its first
+/// text block merged into the open one) so the client sees a single message
+pub fn stream(
+    first: ByteStream,
+    provider: Arc<Box<dyn AnthropicProvider>>,
+    request: AnthropicRequest,
+    budget: Budget,
+) -> ByteStream {
+    let stitcher = Stitcher {
+        provider,
+        request,
+        budget,
+        round: 0,
+        produced: 0,
+        text: String::new(),
+        offset: 0,
+        open_text: None,
+        held_stop: None,
+        continuing: false,
+        trim_leading: false,
+    };
+    Box::pin(futures::stream::unfold(
+        (Some(first), LineBuffer::default(), stitcher),
+        |(upstream, mut buffer, mut stitcher)| async move {
+            let mut upstream = upstream?;
+            loop {
+                match upstream.next().await {
+                    Some(Ok(bytes)) => {
+                        buffer.push(&bytes);
+                        let mut output = String::new();
+                        while let Some(event) = buffer.event() {
+                            output.push_str(&stitcher.event(&event));
+                        }
+                        if !output.is_empty() {
+                            return Some((Ok(Bytes::from(output)), (Some(upstream), buffer, stitcher)));
+                        }
+                    }
+                    Some(Err(e)) => return Some((Err(e), (Some(upstream), buffer, stitcher))),
+                    None if stitcher.continuing => {
+                        buffer = LineBuffer::default();
+                        match stitcher.next_round().await {
+                            Ok(next) => upstream = next,
+                            Err(finish) => return Some((Ok(Bytes::from(finish)), (None, buffer, stitcher))),
+                        }
+                    }
+                    None => {
+                        if buffer.is_empty() {
+                            return None;
+                        }
+                        return Some((Ok(Bytes::from(buffer.rest())), (None, buffer, stitcher)));
+                    }
+                }
+            }
+        },
+    ))
+}
+
+/// State of a stitched stream across rounds
+struct Stitcher {
+    provider: Arc<Box<dyn AnthropicProvider>>,
+    request: AnthropicRequest,
+    budget: Budget,
+    round: u32,
+    /// Output tokens of completed rounds
+    produced: u32,
+    /// All text streamed so far (the next round's prefill)
+    text: String,
+    /// Added to upstream block indices of the current round
+    offset: u64,
+    /// Client index of the last text block, while it may still be continued
+    open_text: Option<u64>,
+    /// `content_block_stop` of the open text block, held until the stop reason is known
+    held_stop: Option<String>,
+    /// The current round stopped at `max_tokens` and another one follows
+    continuing: bool,
+    /// Drop leading whitespace of the next text (it was trimmed from the prefill)
+    trim_leading: bool,
+}
+
+impl Stitcher {
+    /// Rewrite one SSE event of the current round
+    fn event(&mut self, raw: &str) -> String {
+        let Some(sse) = parse_sse_events(raw).into_iter().next() else {
+            return raw.to_string();
+        };
+        let Ok(mut data) = serde_json::from_str::<serde_json::Value>(&sse.data) else {
+            return raw.to_string();
+        };
+        let kind = data["type"].as_str().unwrap_or_default().to_string();
+        let upstream_index = data["index"].as_u64();
+        let index = upstream_index.map(|i| i + self.offset);
+        if let Some(index) = index {
+            data["index"] = index.into();
+        }
+        let emit = |data: &serde_json::Value| {
+            SseEvent {
+                event: sse.event.clone(),
+                data: data.to_string(),
+            }
+            .to_sse_string()
+        };
+
+        match kind.as_str() {
+            "message_start" if self.round > 0 => String::new(),
+            "message_stop" if self.continuing => String::new(),
+            "content_block_start" => {
+                let is_text = data["content_block"]["type"] == "text";
+                if self.round > 0 && upstream_index == Some(0) && is_text && self.held_stop.is_some() {
+                    // Continues the open text block
+                    return String::new();
+                }
+                let mut output = self.held_stop.take().unwrap_or_default();
+                self.open_text = if is_text { index } else { None };
+                output.push_str(&emit(&data));
+                output
+            }
+            "content_block_delta" => {
+                let mut output = String::new();
+                if index != self.open_text {
+                    output.push_str(&self.held_stop.take().unwrap_or_default());
+                }
+                if let Some(text) = data["delta"]["text"].as_str() {
+                    let text = if self.trim_leading { text.trim_start() } else { text }.to_string();
+                    if text.is_empty() {
+                        return output;
+                    }
+                    self.trim_leading = false;
+                    self.text.push_str(&text);
+                    data["delta"]["text"] = text.into();
+                }
+                output.push_str(&emit(&data));
+                output
+            }
+            "content_block_stop" if index.is_some() && index == self.open_text => {
+                self.held_stop = Some(emit(&data));
+                String::new()
+            }
+            "message_delta" => {
+                let output_tokens = data["usage"]["output_tokens"].as_u64().unwrap_or(0) as u32;
+                let at_limit = data["delta"]["stop_reason"] == "max_tokens" && self.held_stop.is_some();
+                let produced = self.produced + output_tokens;
+                if at_limit && self.budget.next_request(&self.request, self.round, produced, &self.text).is_some() {
+                    self.produced = produced;
+                    self.continuing = true;
+                    return String::new();
+                }
+                if let Some(usage) = data.get_mut("usage") {
+                    usage["output_tokens"] = produced.into();
+                }
+                let mut output = self.held_stop.take().unwrap_or_default();
+                output.push_str(&emit(&data));
+                output
+            }
+            _ => {
+                let mut output = self.held_stop.take().unwrap_or_default();
+                output.push_str(&emit(&data));
+                output
+            }
+        }
+    }
+
+    /// Start the next round; on failure, the events that end the message
+    async fn next_round(&mut self) -> Result<ByteStream, String> {
+        self.continuing = false;
+        let next = self
+            .budget
+            .next_request(&self.request, self.round, self.produced, &self.text)
+            .expect("checked before continuing");
+        self.round += 1;
+        info!("➡️  Stream hit max_tokens; continuation round {}", self.round);
+        match self.provider.send_message_stream(next).await {
+            Ok(stream) => {
+                self.offset = self.open_text.unwrap_or(0);
+                self.trim_leading = self.text.ends_with(char::is_whitespace);
+                Ok(stream)
+            }
+            Err(e) => {
+                warn!("Continuation round {} failed, ending with partial output: {}", self.round, e);
+                let mut output = self.held_stop.take().unwrap_or_default();
+                for (event, data) in [
+                    (
+                        "message_delta",
+                        serde_json::json!({
+                            "type": "message_delta",
+                            "delta": { "stop_reason": "max_tokens", "stop_sequence": null },
+                            "usage": { "output_tokens": self.produced },
+                        }),
+                    ),
+                    ("message_stop", serde_json::json!({ "type": "message_stop" })),
+                ] {
+                    output.push_str(
+                        &SseEvent {
+                            event: Some(event.to_string()),
+                            data: data.to_string(),
+                        }
+                        .to_sse_string(),
+                    );
+                }
+                Err(output)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use crate::models::{CountTokensRequest, CountTokensResponse};
+    use crate::providers::Usage;
+
+    /// Answers the first call with "Hello " (max_tokens), later calls with "world."
+    struct TwoPart;
+
+    #[async_trait]
+    impl AnthropicProvider for TwoPart {
+        async fn send_message(&self, request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
+            let first = request.messages.len() == 1;
+            // Each round is its own request upstream
+            let key = if first { "req_1" } else { "req_1-1" };
+            assert_eq!(request.idempotency_key.as_deref(), Some(key));
+            Ok(ProviderResponse {
+                id: "msg_1".to_string(),
+                r#type: "message".to_string(),
+                role: "assistant".to_string(),
+                content: vec![ContentBlock::Text {
+                    text: if first { "Hello " } else { " world." }.to_string(),
+                }],
+                model: request.model,
+                stop_reason: Some(if first { "max_tokens" } else { "end_turn" }.to_string()),
+                stop_sequence: None,
+                usage: Usage { input_tokens: 10, output_tokens: 4, ..Default::default() },
+            })
+        }
+
+        async fn send_message_stream(
+            &self,
+            request: AnthropicRequest,
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<Bytes, ProviderError>> + Send>>, ProviderError> {
+            assert_eq!(request.messages.len(), 2);
+            assert_eq!(request.max_tokens, 4);
+            assert_eq!(request.idempotency_key.as_deref(), Some("req_1-1"));
+            let events = [
+                r#"{"type":"message_start","message":{}}"#,
+                r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+                r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":" world."}}"#,
+                r#"{"type":"content_block_stop","index":0}"#,
+                r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":2}}"#,
+                r#"{"type":"message_stop"}"#,
+            ];
+            Ok(sse(&events))
+        }
+
+        async fn count_tokens(&self, _request: CountTokensRequest) -> Result<CountTokensResponse, ProviderError> {
+            unimplemented!()
+        }
+
+        fn supports_model(&self, _model: &str) -> bool {
+            true
+        }
+    }
+
+    fn sse(events: &[&str]) -> ByteStream {
+        let text: String = events
+            .iter()
+            .map(|data| {
+                let kind = serde_json::from_str::<serde_json::Value>(data).unwrap()["type"].as_str().unwrap().to_string();
+                SseEvent { event: Some(kind), data: data.to_string() }.to_sse_string()
+            })
+            .collect();
+        Box::pin(futures::stream::iter(vec![Ok(Bytes::from(text))]))
+    }
+
+    fn request() -> AnthropicRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "m", "max_tokens": 12,
+            "messages": [{ "role": "user", "content": "hi" }],
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_continuation() {
+        let continuation = Continuation { max_output_tokens: 8, max_rounds: 2 };
+        let mut request = request();
+        request.idempotency_key = Some("req_1".to_string());
+        let budget = Budget::prepare(&mut request, Some(&continuation)).unwrap();
+        assert_eq!(request.max_tokens, 8);
+
+        let response = send_message(&TwoPart, request.clone(), &budget).await.unwrap();
+        assert!(matches!(&response.content[..], [ContentBlock::Text { text }] if text == "Hello world."));
+        assert_eq!(response.stop_reason.as_deref(), Some("end_turn"));
+        assert_eq!(response.usage.output_tokens, 8);
+
+        let first = sse(&[
+            r#"{"type":"message_start","message":{}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hello "}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"max_tokens"},"usage":{"output_tokens":8}}"#,
+            r#"{"type":"message_stop"}"#,
+        ]);
+        let output: Vec<_> = stream(first, Arc::new(Box::new(TwoPart)), request, budget).collect().await;
+        let text: String = output.into_iter().map(|r| String::from_utf8(r.unwrap().to_vec()).unwrap()).collect();
+        let kinds: Vec<_> = parse_sse_events(&text).into_iter().map(|e| e.event.unwrap()).collect();
+        assert_eq!(
+            kinds,
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop"
+            ]
+        );
+        assert!(text.contains(r#""text":"world.""#));
+        assert!(text.contains(r#""output_tokens":10"#));
+    }
+}
@@ -0,0 +1,234 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::models::AnthropicRequest;
+use crate::router::RuleCheck;
+
+use super::config_reload::ActiveConfig;
+use super::limits::error_response;
+use super::AppState;
+
+/// Request header asking for the routing explanation of a live request
+pub const EXPLAIN_HEADER: &str = "x-ccm-explain";
+/// Response header carrying it (compact JSON)
+const EXPLANATION_HEADER: &str = "x-ccm-route-explanation";
+
+/// Why a request goes where it goes
+#[derive(Debug, Serialize)]
+pub struct RouteExplanation {
+    /// Model named in the request
+    pub requested_model: String,
+    /// Router rules in evaluation order
+    pub rules: Vec<RuleCheck>,
+    /// Alias (`[[models]]` entry) or model the router chose
+    pub alias: String,
+    pub route_type: String,
+    /// Mappings of the alias in the order they are tried
+    pub providers: Vec<ProviderCheck>,
+    /// First provider that will be tried (None when nothing can serve the request)
+    pub target: Option<Target>,
+    /// Provider that answered (live requests only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub served_by: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProviderCheck {
+    pub provider: String,
+    pub model: String,
+    pub priority: u32,
+    /// "selected", "fallback" or "excluded"
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Target {
+    pub provider: String,
+    pub model: String,
+}
+
+/// Evaluate routing for a request body without sending it
+pub fn explain(active: &ActiveConfig, headers: &HeaderMap, request_json: &serde_json::Value) -> Result<RouteExplanation, String> {
+    let mut request: AnthropicRequest =
+        serde_json::from_value(request_json.clone()).map_err(|e| format!("Invalid request format: {}", e))?;
+    let requested_model = request.model.clone();
+    let (decision, rules) = active.router.explain(&mut request, super::client_role(headers));
+    let decision = decision.map_err(|e| e.to_string())?;
+
+    let forced_provider = headers
+        .get("x-provider")
+        .and_then(|v| v.to_str().ok())
+        .filter(|s| !s.is_empty());
+    let mut providers = Vec::new();
+    let mut target = None;
+
+    match active.model(&decision.model_name) {
+        Some(model_config) => {
+            let mut mappings = model_config.mappings.clone();
+            mappings.sort_by_key(|m| m.priority);
+            for mapping in mappings {
+                let configured = active.config.providers.iter().find(|p| p.name == mapping.provider);
+                let reason = match forced_provider {
+                    Some(forced) if forced != mapping.provider => {
+                        Some(format!("X-Provider header forces '{}'", forced))
+                    }
+                    _ if active.provider_registry.get_provider(&mapping.provider).is_some() => None,
+                    _ if configured.is_some_and(|p| !p.is_enabled()) => Some("provider is disabled".to_string()),
+                    _ if configured.is_some() => Some("provider failed to initialize".to_string()),
+                    _ => Some("no provider with this name".to_string()),
+                };
+                let status = match (&reason, &target) {
+                    (Some(_), _) => "excluded",
+                    (None, None) => {
+                        target = Some(Target {
+                            provider: mapping.provider.clone(),
+                            model: mapping.actual_model.clone(),
+                        });
+                        "selected"
+                    }
+                    (None, Some(_)) => "fallback",
+                };
+                providers.push(ProviderCheck {
+                    provider: mapping.provider,
+                    model: mapping.actual_model,
+                    priority: mapping.priority,
+                    status,
+                    reason,
+                });
+            }
+        }
+        None => {
+            // Direct lookup by model name (no [[models]] entry)
+            let direct = active
+                .config
+                .providers
+                .iter()
+                .find(|p| p.models.contains(&decision.model_name))
+                .filter(|_| active.provider_registry.get_provider_for_model(&decision.model_name).is_ok());
+            if let Some(provider) = direct {
+                providers.push(ProviderCheck {
+                    provider: provider.name.clone(),
+                    model: decision.model_name.clone(),
+                    priority: 0,
+                    status: "selected",
+                    reason: Some(format!("no [[models]] entry; '{}' lists the model", provider.name)),
+                });
+                target = Some(Target {
+                    provider: provider.name.clone(),
+                    model: decision.model_name.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(RouteExplanation {
+        requested_model,
+        rules,
+        alias: decision.model_name,
+        route_type: decision.route_type.to_string(),
+        providers,
+        target,
+        served_by: None,
+    })
+}
+
+/// Whether a live request asked for its routing explanation
+pub fn requested(headers: &HeaderMap) -> bool {
+    headers
+        .get(EXPLAIN_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| matches!(v, "1" | "true"))
+}
+
+/// Attach an explanation to a live response
+pub fn attach(response: &mut Response, explanation: &RouteExplanation) {
+    let json = serde_json::to_string(explanation).unwrap_or_default();
+    if let Ok(value) = HeaderValue::from_str(&json) {
+        response.headers_mut().insert(EXPLANATION_HEADER, value);
+    }
+}
+
+/// POST /admin/route/explain - routing decision for a request body, without sending it
+///
+/// Honors `X-Provider` like `/v1/messages` does.
+pub async fn explain_route(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request_json): Json<serde_json::Value>,
+) -> Response {
+    match explain(&state.active(), &headers, &request_json) {
+        Ok(explanation) => Json(explanation).into_response(),
+        Err(e) => error_response(StatusCode::BAD_REQUEST, "invalid_request_error", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::AppConfig;
+    use crate::providers::ProviderRegistry;
+    use crate::router::Router;
+
+    #[test]
+    fn test_explain() {
+        let config: AppConfig = toml::from_str(
+            r#"
+[server]
+[router]
+default = "fast"
+
+[[providers]]
+name = "zai"
+provider_type = "z.ai"
+api_key = "k"
+models = []
+
+[[providers]]
+name = "openrouter"
+provider_type = "openrouter"
+api_key = "k"
+enabled = false
+models = []
+
+[[models]]
+name = "fast"
+
+[[models.mappings]]
+provider = "openrouter"
+actual_model = "z-ai/glm-4.6"
+priority = 1
+
+[[models.mappings]]
+provider = "zai"
+actual_model = "glm-4.6"
+priority = 2
+"#,
+        )
+        .unwrap();
+        let active = ActiveConfig {
+            router: Router::new(config.clone()),
+            provider_registry: Arc::new(ProviderRegistry::from_configs(&config.providers, &config.server.timeouts, None).unwrap()),
+            model_overrides: Default::default(),
+            config,
+        };
+        let body = serde_json::json!({
+            "model": "claude-sonnet-4-5", "max_tokens": 10,
+            "messages": [{ "role": "user", "content": "hi" }],
+        });
+
+        let explanation = explain(&active, &HeaderMap::new(), &body).unwrap();
+        assert_eq!(explanation.alias, "fast");
+        assert_eq!(explanation.providers[0].status, "excluded");
+        assert_eq!(explanation.providers[0].reason.as_deref(), Some("provider is disabled"));
+        assert_eq!(explanation.providers[1].status, "selected");
+        assert_eq!(explanation.target.unwrap().provider, "zai");
+    }
+}
@@ -0,0 +1,267 @@
+//! gRPC ingress (`proto/ccm.proto`)
+//!
+//! Each call is dispatched in-process to the HTTP handler of the same API, so
+//! client auth, limits, routing and logging behave exactly as over HTTP.
+
+use axum::body::Body;
+use axum::http::{HeaderName, HeaderValue, Request, StatusCode};
+use axum::Router;
+use futures::stream::{Stream, StreamExt};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tonic::codegen::{http, BoxFuture, Service, StdError};
+use tonic::server::{Grpc, NamedService, ServerStreamingService, UnaryService};
+use tonic::Status;
+use tracing::{error, info};
+
+use crate::providers::streaming::{parse_sse_events, LineBuffer};
+
+/// Largest response body accepted from the HTTP handlers
+const MAX_RESPONSE_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MessageRequest {
+    /// Request body (JSON)
+    #[prost(bytes = "vec", tag = "1")]
+    pub body: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MessageResponse {
+    /// Response body (JSON)
+    #[prost(bytes = "vec", tag = "1")]
+    pub body: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StreamEvent {
+    #[prost(string, tag = "1")]
+    pub event: String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub data: Vec<u8>,
+}
+
+/// Serve the `ccm.v1.Messages` service until the process exits
+pub async fn serve(addr: SocketAddr, app: Router) {
+    info!("🚀 gRPC listening on {}", addr);
+    let service = MessagesService { app };
+    if let Err(e) = tonic::transport::Server::builder().add_service(service).serve(addr).await {
+        error!("gRPC server error: {}", e);
+    }
+}
+
+/// `ccm.v1.Messages`, backed by the HTTP router
+#[derive(Clone)]
+struct MessagesService {
+    app: Router,
+}
+
+impl NamedService for MessagesService {
+    const NAME: &'static str = "ccm.v1.Messages";
+}
+
+/// Run a request through the HTTP router
+async fn dispatch(
+    mut app: Router,
+    path: &str,
+    request: tonic::Request<MessageRequest>,
+    stream: bool,
+) -> Result<Body, Status> {
+    let (metadata, _, message) = request.into_parts();
+    let mut body: serde_json::Value = serde_json::from_slice(&message.body)
+        .map_err(|e| Status::invalid_argument(format!("Request body is not JSON: {}", e)))?;
+    if stream {
+        body["stream"] = serde_json::Value::Bool(true);
+    }
+
+    let mut http_request = Request::post(path)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .map_err(|e| Status::internal(e.to_string()))?;
+    // Client keys and routing headers travel as metadata
+    for (name, value) in metadata.into_headers() {
+        if let Some(name) = name.filter(|name| !name.as_str().starts_with("grpc-") && name != "content-type") {
+            http_request.headers_mut().insert(name, value);
+        }
+    }
+
+    let response = app.call(http_request).await.unwrap_or_else(|e: Infallible| match e {});
+    let (parts, body) = response.into_parts();
+    if parts.status.is_success() {
+        return Ok(body);
+    }
+    let message = axum::body::to_bytes(body, MAX_RESPONSE_BYTES).await.unwrap_or_default();
+    Err(Status::new(code(parts.status), String::from_utf8_lossy(&message)))
+}
+
+/// gRPC status code of an HTTP error
+fn code(status: StatusCode) -> tonic::Code {
+    match status.as_u16() {
+        400 | 413 | 422 => tonic::Code::InvalidArgument,
+        401 => tonic::Code::Unauthenticated,
+        403 => tonic::Code::PermissionDenied,
+        404 => tonic::Code::NotFound,
+        429 => tonic::Code::ResourceExhausted,
+        503 => tonic::Code::Unavailable,
+        504 => tonic::Code::DeadlineExceeded,
+        _ => tonic::Code::Internal,
+    }
+}
+
+struct Unary {
+    app: Router,
+    path: &'static str,
+}
+
+impl UnaryService<MessageRequest> for Unary {
+    type Response = MessageResponse;
+    type Future = BoxFuture<tonic::Response<MessageResponse>, Status>;
+
+    fn call(&mut self, request: tonic::Request<MessageRequest>) -> Self::Future {
+        let (app, path) = (self.app.clone(), self.path);
+        Box::pin(async move {
+            let body = dispatch(app, path, request, false).await?;
+            let body = axum::body::to_bytes(body, MAX_RESPONSE_BYTES)
+                .await
+                .map_err(|e| Status::internal(e.to_string()))?;
+            Ok(tonic::Response::new(MessageResponse { body: body.to_vec() }))
+        })
+    }
+}
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<StreamEvent, Status>> + Send>>;
+
+struct Streaming {
+    app: Router,
+}
+
+impl ServerStreamingService<MessageRequest> for Streaming {
+    type Response = StreamEvent;
+    type ResponseStream = EventStream;
+    type Future = BoxFuture<tonic::Response<EventStream>, Status>;
+
+    fn call(&mut self, request: tonic::Request<MessageRequest>) -> Self::Future {
+        let app = self.app.clone();
+        Box::pin(async move {
+            let body = dispatch(app, "/v1/messages", request, true).await?;
+            Ok(tonic::Response::new(sse_events(body.into_data_stream())))
+        })
+    }
+}
+
+/// Split an SSE byte stream into events
+fn sse_events<S, E>(stream: S) -> EventStream
+where
+    S: Stream<Item = Result<bytes::Bytes, E>> + Send + 'static,
+    E: std::fmt::Display,
+{
+    let events = futures::stream::unfold((Box::pin(stream), LineBuffer::default()), |(mut stream, mut buffer)| async move {
+        loop {
+            if let Some(raw) = buffer.event() {
+                let events: Vec<Result<StreamEvent, Status>> = parse_sse_events(&raw)
+                    .into_iter()
+                    .map(|e| StreamEvent {
+                        event: e.event.unwrap_or_default(),
+                        data: e.data.into_bytes(),
+                    })
+                    .map(Ok)
+                    .collect();
+                return Some((futures::stream::iter(events), (stream, buffer)));
+            }
+            match stream.next().await {
+                Some(Ok(bytes)) => buffer.push(&bytes),
+                Some(Err(e)) => {
+                    let error = vec![Err(Status::unavailable(e.to_string()))];
+                    return Some((futures::stream::iter(error), (stream, LineBuffer::default())));
+                }
+                None => return None,
+            }
+        }
+    });
+    Box::pin(events.flatten())
+}
+
+impl<B> Service<http::Request<B>> for MessagesService
+where
+    B: tonic::codegen::Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let app = self.app.clone();
+        match request.uri().path() {
+            "/ccm.v1.Messages/Send" => Box::pin(async move {
+                let service = Unary { app, path: "/v1/messages" };
+                Ok(Grpc::new(tonic::codec::ProstCodec::default()).unary(service, request).await)
+            }),
+            "/ccm.v1.Messages/CountTokens" => Box::pin(async move {
+                let service = Unary { app, path: "/v1/messages/count_tokens" };
+                Ok(Grpc::new(tonic::codec::ProstCodec::default()).unary(service, request).await)
+            }),
+            "/ccm.v1.Messages/Stream" => Box::pin(async move {
+                Ok(Grpc::new(tonic::codec::ProstCodec::default()).server_streaming(Streaming { app }, request).await)
+            }),
+            _ => Box::pin(async move {
+                let mut response = http::Response::new(tonic::body::empty_body());
+                let headers = response.headers_mut();
+                headers.insert(HeaderName::from_static("grpc-status"), HeaderValue::from(tonic::Code::Unimplemented as i32));
+                headers.insert(http::header::CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+                Ok(response)
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sse_events() {
+        let chunks: Vec<Result<bytes::Bytes, Infallible>> = vec![
+            Ok(bytes::Bytes::from("event: message_start\ndata: {\"a\":1}\n\nevent: ping\n")),
+            Ok(bytes::Bytes::from("data: {}\n\n")),
+        ];
+        let events: Vec<StreamEvent> = sse_events(futures::stream::iter(chunks))
+            .map(|e| e.unwrap())
+            .collect()
+            .await;
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, "message_start");
+        assert_eq!(events[0].data, b"{\"a\":1}");
+        assert_eq!(events[1].event, "ping");
+        assert_eq!(code(StatusCode::TOO_MANY_REQUESTS), tonic::Code::ResourceExhausted);
+    }
+
+    #[tokio::test]
+    async fn test_dispatch() {
+        let app = Router::new().route(
+            "/v1/messages",
+            axum::routing::post(|headers: axum::http::HeaderMap, body: String| async move {
+                match headers.get("x-api-key") {
+                    Some(key) => Ok(format!("{} {}", key.to_str().unwrap(), body)),
+                    None => Err(StatusCode::UNAUTHORIZED),
+                }
+            }),
+        );
+        let message = MessageRequest { body: br#"{"model":"m"}"#.to_vec() };
+
+        let mut request = tonic::Request::new(message.clone());
+        request.metadata_mut().insert("x-api-key", "k".parse().unwrap());
+        let body = dispatch(app.clone(), "/v1/messages", request, true).await.unwrap();
+        let body = axum::body::to_bytes(body, 1024).await.unwrap();
+        assert_eq!(body, r#"k {"model":"m","stream":true}"#);
+
+        let status = dispatch(app, "/v1/messages", tonic::Request::new(message), false).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    }
+}
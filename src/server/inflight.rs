@@ -0,0 +1,249 @@
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::watch;
+use tracing::info;
+
+use crate::providers::error::ProviderError;
+use crate::providers::streaming::SseEvent;
+use crate::storage::AuditEntry;
+
+use super::limits::error_response;
+use super::{audit, AppState};
+
+/// Message of requests cancelled through the admin API
+pub const CANCELLED: &str = "Request cancelled by an administrator";
+
+/// Requests currently being handled, with a way to cancel them
+#[derive(Debug, Default)]
+pub struct InflightRequests {
+    requests: Mutex<HashMap<String, Inflight>>,
+}
+
+#[derive(Debug)]
+struct Inflight {
+    info: InflightInfo,
+    started: Instant,
+    cancel: watch::Sender<bool>,
+}
+
+/// A running request, as listed by `GET /admin/requests`
+#[derive(Debug, Clone, Serialize)]
+pub struct InflightInfo {
+    /// Request log id
+    pub id: String,
+    pub model: String,
+    /// Provider currently serving (or being tried for) the request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Client key name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+    pub stream: bool,
+    pub started_at: DateTime<Utc>,
+    pub elapsed_ms: u64,
+}
+
+impl InflightRequests {
+    /// Track a request until the returned registration (and every clone of it) is dropped
+    pub fn register(self: &Arc<Self>, id: &str, model: &str, client: Option<String>, stream: bool) -> Arc<Registration> {
+        let (cancel, cancelled) = watch::channel(false);
+        let info = InflightInfo {
+            id: id.to_string(),
+            model: model.to_string(),
+            provider: None,
+            client,
+            stream,
+            started_at: Utc::now(),
+            elapsed_ms: 0,
+        };
+        self.requests.lock().unwrap().insert(
+            id.to_string(),
+            Inflight {
+                info,
+                started: Instant::now(),
+                cancel,
+            },
+        );
+        Arc::new(Registration {
+            id: id.to_string(),
+            requests: self.clone(),
+            cancelled,
+        })
+    }
+
+    /// Running requests, longest-running first
+    pub fn list(&self) -> Vec<InflightInfo> {
+        let mut list: Vec<InflightInfo> = self
+            .requests
+            .lock()
+            .unwrap()
+            .values()
+            .map(|inflight| InflightInfo {
+                elapsed_ms: inflight.started.elapsed().as_millis() as u64,
+                ..inflight.info.clone()
+            })
+            .collect();
+        list.sort_by_key(|info| std::cmp::Reverse(info.elapsed_ms));
+        list
+    }
+
+    /// Cancel one request; false when it isn't running
+    pub fn cancel(&self, id: &str) -> bool {
+        self.cancel_where(|info| info.id == id) > 0
+    }
+
+    /// Cancel every request to a provider, returning how many were running
+    pub fn cancel_provider(&self, provider: &str) -> usize {
+        self.cancel_where(|info| info.provider.as_deref() == Some(provider))
+    }
+
+    fn cancel_where(&self, matches: impl Fn(&InflightInfo) -> bool) -> usize {
+        let requests = self.requests.lock().unwrap();
+        let mut cancelled = 0;
+        for inflight in requests.values().filter(|inflight| matches(&inflight.info)) {
+            inflight.cancel.send_replace(true);
+            cancelled += 1;
+        }
+        cancelled
+    }
+}
+
+/// Handle of a tracked request
+#[derive(Debug)]
+pub struct Registration {
+    id: String,
+    requests: Arc<InflightRequests>,
+    cancelled: watch::Receiver<bool>,
+}
+
+impl Registration {
+    pub fn set_provider(&self, provider: &str) {
+        if let Some(inflight) = self.requests.requests.lock().unwrap().get_mut(&self.id) {
+            inflight.info.provider = Some(provider.to_string());
+        }
+    }
+
+    /// Resolves when the request is cancelled
+    pub async fn cancelled(&self) {
+        let mut cancelled = self.cancelled.clone();
+        // The sender lives as long as the registration
+        let _ = cancelled.wait_for(|cancelled| *cancelled).await;
+    }
+
+    /// End a client stream early, with an error event, when the request is cancelled
+    ///
+    /// The stream keeps the request registered until it finishes.
+    pub fn guard_stream<S>(self: Arc<Self>, stream: S) -> impl Stream<Item = Result<Bytes, ProviderError>> + Send
+    where
+        S: Stream<Item = Result<Bytes, ProviderError>> + Send + Unpin + 'static,
+    {
+        futures::stream::unfold(Some((stream, self)), |state| async move {
+            let (mut stream, registration) = state?;
+            tokio::select! {
+                item = stream.next() => item.map(|item| (item, Some((stream, registration)))),
+                _ = registration.cancelled() => {
+                    info!("🛑 Cancelled stream {}", registration.id);
+                    let event = SseEvent {
+                        event: Some("error".to_string()),
+                        data: serde_json::json!({
+                            "type": "error",
+                            "error": { "type": "api_error", "message": CANCELLED },
+                        })
+                        .to_string(),
+                    };
+                    Some((Ok(Bytes::from(event.to_sse_string())), None))
+                }
+            }
+        })
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.requests.requests.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// GET /admin/requests - requests currently in flight
+pub async fn list_requests(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "requests": state.inflight.list() }))
+}
+
+/// POST /admin/requests/:id/cancel - cancel one request
+pub async fn cancel_request(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    if !state.inflight.cancel(&id) {
+        return error_response(
+            StatusCode::NOT_FOUND,
+            "not_found_error",
+            format!("No request '{}' in flight", id),
+        );
+    }
+    info!("🛑 Cancelling request {}", id);
+    state
+        .audit_log
+        .record(AuditEntry::new(audit::actor(&headers), "requests.cancel").target(id.clone()));
+    Json(serde_json::json!({ "cancelled": [id] })).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CancelProviderRequest {
+    pub provider: String,
+}
+
+/// POST /admin/requests/cancel - cancel every request to a provider
+pub async fn cancel_provider(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<CancelProviderRequest>,
+) -> Response {
+    let cancelled = state.inflight.cancel_provider(&request.provider);
+    info!("🛑 Cancelling {} requests to {}", cancelled, request.provider);
+    if cancelled > 0 {
+        state.audit_log.record(
+            AuditEntry::new(audit::actor(&headers), "requests.cancel_provider").target(request.provider.clone()),
+        );
+    }
+    Json(serde_json::json!({ "provider": request.provider, "cancelled": cancelled })).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancel_stream() {
+        let requests = Arc::new(InflightRequests::default());
+        let registration = requests.register("req_1", "sonnet", None, true);
+        registration.set_provider("zai");
+        assert_eq!(requests.list()[0].provider.as_deref(), Some("zai"));
+
+        let upstream = futures::stream::iter(vec![Ok(Bytes::from("data: a\n\n"))]).chain(futures::stream::pending());
+        let mut stream = Box::pin(registration.guard_stream(Box::pin(upstream)));
+        assert_eq!(stream.next().await.unwrap().unwrap(), Bytes::from("data: a\n\n"));
+
+        assert_eq!(requests.cancel_provider("other"), 0);
+        assert_eq!(requests.cancel_provider("zai"), 1);
+        let error = stream.next().await.unwrap().unwrap();
+        assert!(String::from_utf8_lossy(&error).contains(CANCELLED));
+        assert!(stream.next().await.is_none());
+
+        // The request is unregistered once its stream is gone
+        drop(stream);
+        assert!(requests.list().is_empty());
+    }
+}
@@ -0,0 +1,268 @@
+//! Quality scoring of sampled responses by a judge model
+//!
+//! A share of completed requests is queued with its response; a background
+//! worker asks the configured judge model to grade each one and stores the
+//! grades per provider and model.
+
+use bytes::Bytes;
+use futures::stream::{Stream, StreamExt};
+use serde_json::Value;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tracing::{debug, info, warn};
+
+use crate::cli::JudgeConfig;
+use crate::models::{AnthropicRequest, Message, MessageContent, SystemPrompt};
+use crate::providers::error::ProviderError;
+use crate::providers::streaming::collect_response;
+use crate::providers::ProviderResponse;
+use crate::storage::{QualityScore, ScoreStore};
+
+use super::config_reload::ActiveConfig;
+
+/// Responses waiting for a grade; more are dropped
+const QUEUE_CAPACITY: usize = 100;
+
+/// Transcript characters shown to the judge (the most recent are kept)
+const MAX_TRANSCRIPT_CHARS: usize = 24_000;
+
+const JUDGE_SYSTEM: &str = "You grade replies of an AI coding assistant. Read the conversation and the \
+assistant's reply, then rate the reply from 1 (worst) to 10 (best) for helpfulness (does it move the \
+user's task forward) and correctness (is it accurate and free of mistakes). Answer with JSON only: \
+{\"helpfulness\": <1-10>, \"correctness\": <1-10>, \"rationale\": \"<one sentence>\"}";
+
+type ByteStream = Pin<Box<dyn Stream<Item = Result<Bytes, ProviderError>> + Send>>;
+
+/// A response to grade
+#[derive(Debug)]
+pub struct JudgeJob {
+    pub request_id: String,
+    pub provider: String,
+    pub model: String,
+    /// Client request (Anthropic Messages API)
+    pub request: Value,
+}
+
+/// Samples requests and queues them for the grading worker
+pub struct Judge {
+    sample_rate: f64,
+    sender: mpsc::Sender<(JudgeJob, ProviderResponse)>,
+}
+
+impl Judge {
+    /// Start the grading worker (None when disabled)
+    pub fn spawn(config: &JudgeConfig, active: Arc<RwLock<Arc<ActiveConfig>>>, store: ScoreStore) -> Option<Arc<Self>> {
+        if !config.enabled {
+            return None;
+        }
+        let (sender, mut receiver) = mpsc::channel::<(JudgeJob, ProviderResponse)>(QUEUE_CAPACITY);
+        let alias = config.model.clone();
+        info!("⚖️  Grading {:.1}% of responses with {}", config.sample_rate * 100.0, alias);
+        tokio::spawn(async move {
+            while let Some((job, response)) = receiver.recv().await {
+                let active = active.read().unwrap_or_else(|e| e.into_inner()).clone();
+                match grade(&active, &alias, &job, &response).await {
+                    Ok(score) => {
+                        debug!(
+                            "⚖️  {} via {}/{}: helpfulness {}, correctness {}",
+                            job.request_id, job.provider, job.model, score.helpfulness, score.correctness
+                        );
+                        if let Err(e) = store.record(&score).await {
+                            warn!("Failed to store quality score: {}", e);
+                        }
+                    }
+                    Err(e) => warn!("Failed to grade {}: {}", job.request_id, e),
+                }
+            }
+        });
+        Some(Arc::new(Self {
+            sample_rate: config.sample_rate.clamp(0.0, 1.0),
+            sender,
+        }))
+    }
+
+    /// Whether to grade the next request
+    pub fn sample(&self) -> bool {
+        rand::random::<f64>() < self.sample_rate
+    }
+
+    /// Queue a response for grading
+    pub fn submit(&self, job: JudgeJob, response: ProviderResponse) {
+        if let Err(mpsc::error::TrySendError::Full((job, _))) = self.sender.try_send((job, response)) {
+            debug!("Judge queue is full, not grading {}", job.request_id);
+        }
+    }
+
+    /// Pass a stream through unchanged, queueing the collected response once it completes
+    pub fn watch_stream(self: &Arc<Self>, stream: ByteStream, job: JudgeJob) -> ByteStream {
+        let (tx, rx) = mpsc::unbounded_channel::<Bytes>();
+        let judge = self.clone();
+        tokio::spawn(async move {
+            // Streams the client abandoned end without a stop reason
+            match collect_response(UnboundedReceiverStream::new(rx).map(Ok)).await {
+                Ok(response) if response.stop_reason.is_some() => judge.submit(job, response),
+                _ => debug!("Stream of {} did not complete, not grading it", job.request_id),
+            }
+        });
+        Box::pin(stream.inspect(move |chunk| {
+            if let Ok(bytes) = chunk {
+                let _ = tx.send(bytes.clone());
+            }
+        }))
+    }
+}
+
+/// Ask the judge model for a grade, trying its mappings in priority order
+async fn grade(
+    active: &ActiveConfig,
+    alias: &str,
+    job: &JudgeJob,
+    response: &ProviderResponse,
+) -> Result<QualityScore, String> {
+    let model = active
+        .model(alias)
+        .ok_or_else(|| format!("judge model '{}' is not a configured model", alias))?;
+    let mut mappings = model.mappings.clone();
+    mappings.sort_by_key(|m| m.priority);
+
+    let prompt = grading_prompt(&job.request, response);
+    let mut last_error = format!("judge model '{}' has no mappings", alias);
+    for mapping in mappings {
+        let Some(provider) = active.provider_registry.get_provider(&mapping.provider) else {
+            continue;
+        };
+        let request = AnthropicRequest {
+            model: mapping.actual_model.clone(),
+            messages: vec![Message {
+                role: "user".to_string(),
+                content: MessageContent::Text(prompt.clone()),
+            }],
+            max_tokens: 512,
+            thinking: None,
+            temperature: Some(0.0),
+            top_p: None,
+            top_k: None,
+            stop_sequences: None,
+            stream: None,
+            metadata: None,
+            system: Some(SystemPrompt::Text(JUDGE_SYSTEM.to_string())),
+            tools: None,
+            betas: Vec::new(),
+            tool_choice: None,
+            anthropic_version: None,
+            idempotency_key: None,
+            verbosity: None,
+        };
+        match provider.send_message(request).await {
+            Ok(verdict) => {
+                let verdict = serde_json::to_value(&verdict).unwrap_or_default();
+                let text = content_text(&verdict["content"]);
+                let (helpfulness, correctness, rationale) =
+                    parse_grade(&text).ok_or_else(|| format!("unreadable verdict: {}", text))?;
+                return Ok(QualityScore {
+                    request_id: job.request_id.clone(),
+                    provider: job.provider.clone(),
+                    model: job.model.clone(),
+                    helpfulness,
+                    correctness,
+                    rationale,
+                });
+            }
+            Err(e) => last_error = e.to_string(),
+        }
+    }
+    Err(last_error)
+}
+
+/// Conversation and reply as plain text for the judge
+fn grading_prompt(request: &Value, response: &ProviderResponse) -> String {
+    let mut transcript = String::new();
+    if let Some(system) = request.get("system") {
+        transcript.push_str(&format!("[system]\n{}\n\n", content_text(system)));
+    }
+    for message in request["messages"].as_array().into_iter().flatten() {
+        let role = message["role"].as_str().unwrap_or("user");
+        transcript.push_str(&format!("[{}]\n{}\n\n", role, content_text(&message["content"])));
+    }
+    if transcript.len() > MAX_TRANSCRIPT_CHARS {
+        let mut start = transcript.len() - MAX_TRANSCRIPT_CHARS;
+        while !transcript.is_char_boundary(start) {
+            start += 1;
+        }
+        transcript = format!("[... earlier conversation omitted ...]\n{}", &transcript[start..]);
+    }
+
+    let reply = serde_json::to_value(response).unwrap_or_default();
+    format!(
+        "<conversation>\n{}</conversation>\n\n<reply>\n{}\n</reply>",
+        transcript,
+        content_text(&reply["content"])
+    )
+}
+
+/// Text of a message content (string or blocks), with tool use summarized
+fn content_text(content: &Value) -> String {
+    let Some(blocks) = content.as_array() else {
+        return content.as_str().unwrap_or_default().to_string();
+    };
+    blocks
+        .iter()
+        .filter_map(|block| match block["type"].as_str()? {
+            "text" => block["text"].as_str().map(str::to_string),
+            "tool_use" => Some(format!("(calls tool {} with {})", block["name"].as_str()?, block["input"])),
+            "tool_result" => Some(format!("(tool result: {})", content_text(&block["content"]))),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// (helpfulness, correctness, rationale) from the judge's JSON answer
+fn parse_grade(text: &str) -> Option<(u8, u8, Option<String>)> {
+    let json: Value = serde_json::from_str(&text[text.find('{')?..=text.rfind('}')?]).ok()?;
+    let grade = |field: &str| json[field].as_f64().map(|g| g.round().clamp(1.0, 10.0) as u8);
+    Some((
+        grade("helpfulness")?,
+        grade("correctness")?,
+        json["rationale"].as_str().map(str::to_string),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_grading() {
+        assert_eq!(
+            parse_grade("Sure.\n```json\n{\"helpfulness\": 8, \"correctness\": 11.2, \"rationale\": \"ok\"}\n```"),
+            Some((8, 10, Some("ok".to_string())))
+        );
+        assert_eq!(parse_grade("{\"helpfulness\": 8}"), None);
+        assert_eq!(parse_grade("no verdict"), None);
+
+        let request = json!({
+            "system": "Be brief",
+            "messages": [
+                { "role": "user", "content": "Read main.rs" },
+                { "role": "assistant", "content": [{ "type": "tool_use", "id": "t1", "name": "Read", "input": { "path": "main.rs" } }] },
+                { "role": "user", "content": [{ "type": "tool_result", "tool_use_id": "t1", "content": "fn main() {}" }] },
+            ],
+        });
+        let response: ProviderResponse = serde_json::from_value(json!({
+            "id": "msg_1", "type": "message", "role": "assistant", "model": "glm-4.6",
+            "content": [{ "type": "text", "text": "It is empty." }],
+            "stop_reason": "end_turn", "stop_sequence": null,
+            "usage": { "input_tokens": 10, "output_tokens": 4 },
+        }))
+        .unwrap();
+        let prompt = grading_prompt(&request, &response);
+        assert!(prompt.starts_with("<conversation>\n[system]\nBe brief\n\n[user]\nRead main.rs"));
+        assert!(prompt.contains("(calls tool Read with {\"path\":\"main.rs\"})"));
+        assert!(prompt.contains("(tool result: fn main() {})"));
+        assert!(prompt.ends_with("<reply>\nIt is empty.\n</reply>"));
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::models::{AnthropicRequest, Message, MessageContent};
+
+use super::config_reload::ActiveConfig;
+
+/// How often providers are checked for a due ping
+const TICK: Duration = Duration::from_secs(15);
+
+/// Ping providers with `keep_warm` configured so the first real request
+/// doesn't pay for a model load or a serverless cold start
+///
+/// The active configuration is read on every tick, so reloads take effect
+/// without a restart.
+pub fn spawn(active: Arc<RwLock<Arc<ActiveConfig>>>) {
+    tokio::spawn(async move {
+        let mut last_ping: HashMap<String, Instant> = HashMap::new();
+        let mut ticker = tokio::time::interval(TICK);
+        loop {
+            ticker.tick().await;
+            let active = active.read().unwrap_or_else(|e| e.into_inner()).clone();
+
+            for provider in active.config.providers.iter().filter(|p| p.is_enabled()) {
+                let Some(keep_warm) = &provider.keep_warm else {
+                    continue;
+                };
+                let interval = Duration::from_secs(keep_warm.interval_secs.max(1));
+                if last_ping.get(&provider.name).is_some_and(|t| t.elapsed() < interval) {
+                    continue;
+                }
+                last_ping.insert(provider.name.clone(), Instant::now());
+
+                let Some(model) = keep_warm.model.clone().or_else(|| provider.models.first().cloned()) else {
+                    warn!("keep_warm for provider '{}' needs a model", provider.name);
+                    continue;
+                };
+                let Some(handle) = active.provider_registry.get_provider(&provider.name) else {
+                    continue;
+                };
+
+                let name = provider.name.clone();
+                tokio::spawn(async move {
+                    let started = Instant::now();
+                    match handle.send_message(ping_request(model.clone())).await {
+                        Ok(_) => debug!(
+                            "🔥 Keep-warm ping to {}/{} took {}ms",
+                            name,
+                            model,
+                            started.elapsed().as_millis()
+                        ),
+                        Err(e) => warn!("Keep-warm ping to {}/{} failed: {}", name, model, e),
+                    }
+                });
+            }
+        }
+    });
+}
+
+/// Smallest request that makes the backend load the model
+fn ping_request(model: String) -> AnthropicRequest {
+    AnthropicRequest {
+        model,
+        messages: vec![Message {
+            role: "user".to_string(),
+            content: MessageContent::Text("ping".to_string()),
+        }],
+        max_tokens: 1,
+        thinking: None,
+        temperature: None,
+        top_p: None,
+        top_k: None,
+        stop_sequences: None,
+        stream: None,
+        metadata: None,
+        system: None,
+        tools: None,
+        betas: Vec::new(),
+        tool_choice: None,
+        anthropic_version: None,
+        idempotency_key: None,
+        verbosity: None,
+    }
+}
@@ -0,0 +1,161 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::cli::LimitsConfig;
+use crate::models::{AnthropicRequest, ContentBlock, ImageSource, MessageContent, ToolResultBlock, ToolResultContent};
+
+use super::{AppError, AppState};
+
+/// Request bodies currently held in memory across all requests
+#[derive(Debug, Default)]
+pub struct InflightBodies(AtomicUsize);
+
+/// Releases a body reservation when the request is done
+struct Reservation<'a> {
+    inflight: &'a InflightBodies,
+    bytes: usize,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.inflight.0.fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+pub(super) fn error_response(status: StatusCode, error_type: &str, message: String) -> Response {
+    let body = Json(serde_json::json!({
+        "type": "error",
+        "error": { "type": error_type, "message": message },
+    }));
+    (status, body).into_response()
+}
+
+/// Reject oversized bodies up front and cap the memory held by concurrent bodies
+///
+/// Bodies without a Content-Length are counted at the maximum request size.
+pub async fn guard(State(state): State<Arc<AppState>>, request: Request<Body>, next: Next) -> Response {
+    let limits = state.active().config.server.limits.clone();
+    let declared = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+
+    if let Some(length) = declared.filter(|l| *l > limits.max_request_bytes) {
+        return too_large(length, limits.max_request_bytes);
+    }
+
+    let bytes = declared.unwrap_or(limits.max_request_bytes);
+    let held = state.inflight_bodies.0.fetch_add(bytes, Ordering::Relaxed) + bytes;
+    let _reservation = Reservation {
+        inflight: &state.inflight_bodies,
+        bytes,
+    };
+    // A lone request is always let through
+    if held > limits.max_inflight_bytes && held != bytes {
+        return error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "overloaded_error",
+            "Too many large requests in progress, retry shortly".to_string(),
+        );
+    }
+
+    let response = next.run(request).await;
+
+    // Body limit rejections from extractors are plain text; make them API errors
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE
+        && response.headers().get(header::CONTENT_TYPE).is_none_or(|t| t != "application/json")
+    {
+        return too_large(bytes, limits.max_request_bytes);
+    }
+    response
+}
+
+pub(super) fn too_large(length: usize, max: usize) -> Response {
+    error_response(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        "request_too_large",
+        format!(
+            "Request body is {} but the limit is {} (server.limits.max_request_bytes)",
+            format_bytes(length),
+            format_bytes(max)
+        ),
+    )
+}
+
+fn format_bytes(bytes: usize) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{} KiB", bytes.div_ceil(1024))
+    }
+}
+
+/// Decoded size of a base64 image
+fn image_bytes(source: &ImageSource) -> usize {
+    source.data.as_ref().map_or(0, |data| data.len() / 4 * 3)
+}
+
+/// Reject requests carrying an image larger than the configured limit
+pub fn check_images(request: &AnthropicRequest, limits: &LimitsConfig) -> Result<(), AppError> {
+    let mut sources = Vec::new();
+    for message in &request.messages {
+        let MessageContent::Blocks(blocks) = &message.content else {
+            continue;
+        };
+        for block in blocks {
+            match block {
+                ContentBlock::Image { source } => sources.push(source),
+                ContentBlock::ToolResult {
+                    content: ToolResultContent::Blocks(blocks),
+                    ..
+                } => sources.extend(blocks.iter().filter_map(|b| match b {
+                    ToolResultBlock::Image { source } => Some(source),
+                    _ => None,
+                })),
+                _ => {}
+            }
+        }
+    }
+
+    match sources.into_iter().map(image_bytes).max() {
+        Some(largest) if largest > limits.max_image_bytes => Err(AppError::PayloadTooLarge(format!(
+            "Image is {} but the limit is {} (server.limits.max_image_bytes)",
+            format_bytes(largest),
+            format_bytes(limits.max_image_bytes)
+        ))),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_check_images() {
+        let request: AnthropicRequest = serde_json::from_value(json!({
+            "model": "m",
+            "max_tokens": 16,
+            "messages": [{ "role": "user", "content": [
+                { "type": "image", "source": { "type": "base64", "media_type": "image/png", "data": "A".repeat(4000) } },
+            ]}],
+        }))
+        .unwrap();
+
+        let mut limits = LimitsConfig::default();
+        assert!(check_images(&request, &limits).is_ok());
+
+        limits.max_image_bytes = 2000;
+        assert!(matches!(check_images(&request, &limits), Err(AppError::PayloadTooLarge(_))));
+    }
+}
@@ -1,51 +1,202 @@
+mod admin_auth;
+mod audit;
+mod backpressure;
+mod client_auth;
+mod compare;
+mod config_reload;
+mod continuation;
+mod explain;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod inflight;
+mod judge;
+mod keep_warm;
+mod limits;
+mod model_map;
+mod model_stats;
 mod openai_compat;
+mod organizations;
 mod oauth_handlers;
+mod pacing;
+mod payload_stats;
+mod refusal;
+mod replay;
+mod request_log;
+mod sessions;
+mod stats;
+mod stdio;
+mod streamed_json;
+mod telemetry;
+mod validate;
 
 use crate::cli::AppConfig;
-use crate::models::AnthropicRequest;
+use crate::models::{parse_anthropic_version, parse_betas, AnthropicRequest, Role, RouteDecision, RouteType, FINE_GRAINED_TOOL_STREAMING};
 use crate::router::Router;
-use crate::providers::ProviderRegistry;
-use crate::auth::TokenStore;
+use crate::providers::{FimRequest, ProviderRegistry, ProviderResponse};
+use crate::providers::streaming::{coalesce_tool_input, first_token, map_sse_lines};
+use crate::providers::vision::VisionFormula;
+use crate::providers::http;
+use crate::transform::templates::TemplateRequest;
+use crate::transform::{self, fences, handoff};
+use crate::auth::api_keys::{MetricsMode, PrivacySettings};
+use crate::auth::{ApiKeyStore, OidcVerifier, TokenStore};
+use crate::storage::{kv, AuditEntry, AuditLog, BlobStore, RequestArchive, Database, HealthStore, KvStore, ScoreStore, SemanticCache, UsageStore};
+use crate::providers::error::ProviderError;
+use crate::alerting::Alerter;
+use crate::billing::{BillingHook, UsageEvent};
+use crate::traces::TraceExporter;
+use backpressure::{relay, StreamMetrics};
+use inflight::{InflightRequests, Registration};
+use judge::{Judge, JudgeJob};
+use model_stats::ModelStats;
+use payload_stats::PayloadStats;
+use streamed_json::StreamedJson;
+use sessions::{SessionLimits, SessionPins};
+use limits::InflightBodies;
+use config_reload::ActiveConfig;
+use request_log::{RequestLog, RequestLogEntry};
 use axum::{
-    extract::State,
-    http::{HeaderMap, StatusCode},
+    extract::{DefaultBodyLimit, RawQuery, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
     response::{
         Html, IntoResponse, Response, sse::{Event, Sse},
     },
-    routing::{get, post},
+    routing::{any, get, post},
     Form, Json, Router as AxumRouter,
 };
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use tower_http::compression::CompressionLayer;
+use tower_http::decompression::RequestDecompressionLayer;
 use tokio::net::TcpListener;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use futures::stream::StreamExt;
 
+/// Inflate gzip/br request bodies and, when `enabled`, compress responses
+/// for clients that accept it. Event streams are left uncompressed.
+fn compression<S: Clone + Send + Sync + 'static>(app: AxumRouter<S>, enabled: bool) -> AxumRouter<S> {
+    // Added after the body limits so they apply to the inflated size
+    let app = app.layer(RequestDecompressionLayer::new());
+    if enabled {
+        app.layer(CompressionLayer::new())
+    } else {
+        app
+    }
+}
+
 /// Application state shared across handlers
 #[derive(Clone)]
 pub struct AppState {
-    pub config: AppConfig,
-    pub router: Router,
-    pub provider_registry: Arc<ProviderRegistry>,
+    /// Config, router and providers in effect (swapped atomically on reload)
+    pub active: Arc<RwLock<Arc<ActiveConfig>>>,
     pub token_store: TokenStore,
+    /// Client keys minted through the admin API
+    pub api_keys: ApiKeyStore,
+    /// Append-only record of admin mutations
+    pub audit_log: AuditLog,
+    /// Admin token verification (None when `[server.oidc]` is not set)
+    pub oidc: Option<Arc<OidcVerifier>>,
     pub config_path: std::path::PathBuf,
+    /// Blob store for externalizing oversized payloads in logs (None when disabled)
+    pub blob_store: Option<BlobStore>,
+    /// Live feed and recent history of handled requests
+    pub request_log: RequestLog,
+    /// Webhook alerts on provider/auth failures
+    pub alerter: Alerter,
+    /// Request history (None when the database is disabled)
+    pub usage_store: Option<UsageStore>,
+    /// Provider health history (None when the database is disabled)
+    pub health_store: Option<HealthStore>,
+    /// Judge grades of sampled responses (None when the database is disabled)
+    pub score_store: Option<ScoreStore>,
+    /// Grades sampled responses (`[judge]`)
+    pub judge: Option<Arc<Judge>>,
+    /// State shared between replicas (in-memory unless Redis is configured)
+    pub shared: Arc<dyn KvStore>,
+    /// Buffering of client-facing streams
+    pub stream_metrics: Arc<StreamMetrics>,
+    /// TTFT and throughput of streamed responses per provider model
+    pub model_stats: Arc<ModelStats>,
+    /// Request and response sizes per provider
+    pub payload_stats: Arc<PayloadStats>,
+    /// Request bodies held in memory
+    pub inflight_bodies: Arc<InflightBodies>,
+    /// Responses for near-duplicate requests (None when disabled)
+    pub semantic_cache: Option<Arc<SemanticCache>>,
+    /// Usage events for an external billing system (None when disabled)
+    pub billing: Option<BillingHook>,
+    /// Langfuse/LangSmith trace export (None when not configured)
+    pub trace_exporter: Option<Arc<TraceExporter>>,
+    /// Hourly request log files uploaded to object storage (None when disabled)
+    pub archive: Option<RequestArchive>,
+    /// Requests currently being handled
+    pub inflight: Arc<InflightRequests>,
+    /// Conversations pinned to a provider
+    pub session_pins: Arc<SessionPins>,
+    /// Requests running per conversation
+    pub session_limits: Arc<SessionLimits>,
+}
+
+impl AppState {
+    /// Snapshot of the active configuration; handlers keep it for the whole request
+    pub fn active(&self) -> Arc<ActiveConfig> {
+        self.active.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Record a successful provider attempt
+    pub async fn record_provider_success(&self, provider: &str) {
+        self.alerter.record_success(provider).await;
+        if let Some(store) = &self.health_store {
+            if let Err(e) = store.record(provider, None).await {
+                error!("Failed to record provider health: {}", e);
+            }
+        }
+    }
+
+    /// Record a failed provider attempt
+    pub async fn record_provider_failure(&self, provider: &str, err: &ProviderError) {
+        self.alerter.record_failure(provider, err).await;
+        if let Some(store) = &self.health_store {
+            if let Err(e) = store.record(provider, Some((&err.to_string(), err.failure_kind().as_str()))).await {
+                error!("Failed to record provider health: {}", e);
+            }
+        }
+    }
 }
 
 /// Start the HTTP server
-pub async fn start_server(config: AppConfig, config_path: std::path::PathBuf) -> anyhow::Result<()> {
+pub async fn start_server(config: AppConfig, config_path: std::path::PathBuf, stdio: bool) -> anyhow::Result<()> {
     let router = Router::new(config.clone());
 
+    let shared = kv::connect(&config.shared_state)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to initialize shared state: {}", e))?;
+
     // Initialize OAuth token store FIRST (needed by provider registry)
-    let token_store = TokenStore::default()
+    let mut token_store = TokenStore::default()
         .map_err(|e| anyhow::anyhow!("Failed to initialize token store: {}", e))?;
 
+    if config.shared_state.share_oauth_tokens {
+        token_store = token_store.with_shared(shared.clone());
+        if let Err(e) = token_store.sync_shared().await {
+            error!("Failed to sync shared OAuth tokens: {}", e);
+        }
+        token_store.spawn_shared_sync(std::time::Duration::from_secs(30));
+        info!("🔐 Sharing OAuth tokens through the {:?} state backend", config.shared_state.backend);
+    }
+
     let existing_tokens = token_store.list_providers();
     if !existing_tokens.is_empty() {
         info!("🔐 Loaded {} OAuth tokens from storage", existing_tokens.len());
     }
 
+    let api_keys = ApiKeyStore::default()
+        .map_err(|e| anyhow::anyhow!("Failed to initialize API key store: {}", e))?;
+    let audit_log = AuditLog::default()
+        .map_err(|e| anyhow::anyhow!("Failed to initialize audit log: {}", e))?;
+
     // Initialize provider registry from config (with token store)
     let provider_registry = Arc::new(
-        ProviderRegistry::from_configs(&config.providers, Some(token_store.clone()))
+        ProviderRegistry::from_configs(&config.providers, &config.server.timeouts, Some(token_store.clone()))
             .map_err(|e| anyhow::anyhow!("Failed to initialize provider registry: {}", e))?
     );
 
@@ -54,21 +205,112 @@ pub async fn start_server(config: AppConfig, config_path: std::path::PathBuf) ->
         provider_registry.list_models().len()
     );
 
-    let state = Arc::new(AppState {
+    let blob_store = BlobStore::from_config(&config.blob_store)
+        .map_err(|e| anyhow::anyhow!("Failed to initialize blob store: {}", e))?;
+
+    let archive = RequestArchive::spawn(&config.archive)
+        .map_err(|e| anyhow::anyhow!("Failed to initialize request log archive: {}", e))?;
+
+    let alerter = Alerter::new(config.alerting.clone(), shared.clone());
+    alerter.spawn_token_watcher(token_store.clone());
+    alerter.spawn_listener();
+
+    // Usage reports and quality scores need the database even if it wasn't enabled explicitly
+    let mut database_config = config.database.clone();
+    database_config.enabled |= config.usage_report.enabled || config.judge.enabled;
+    let database = Database::from_config(&database_config)
+        .map_err(|e| anyhow::anyhow!("Failed to open database: {}", e))?;
+
+    let usage_store = database.clone().map(UsageStore::new);
+    let health_store = database.clone().map(HealthStore::new);
+    let score_store = database.map(ScoreStore::new);
+
+    if let Some(store) = &usage_store {
+        // Usage used to be stored as JSON-lines files; fold them into the database
+        if let Some(home) = dirs::home_dir() {
+            let legacy_dir = home.join(".claude-code-mux").join("usage");
+            if let Err(e) = store.import_jsonl_dir(&legacy_dir).await {
+                error!("Failed to import legacy usage files: {}", e);
+            }
+        }
+        if config.usage_report.enabled {
+            crate::reports::spawn_daily_report(config.usage_report.clone(), store.clone());
+        }
+    }
+
+    let model_map_path = model_map::path(&config_path);
+    let active = Arc::new(RwLock::new(Arc::new(ActiveConfig {
         config: config.clone(),
         router,
         provider_registry,
+        model_overrides: model_map::load(&model_map_path, &config),
+    })));
+    let judge = score_store
+        .clone()
+        .and_then(|store| Judge::spawn(&config.judge, active.clone(), store));
+    let state = Arc::new(AppState {
+        active,
         token_store,
+        api_keys,
+        audit_log,
+        oidc: config.server.oidc.clone().map(|oidc| Arc::new(OidcVerifier::new(oidc))),
         config_path,
+        blob_store: blob_store.clone(),
+        request_log: RequestLog::with_replay_buffer(config.server.replay_buffer).with_blob_store(blob_store),
+        alerter,
+        usage_store,
+        health_store,
+        score_store,
+        judge,
+        shared,
+        stream_metrics: Arc::new(StreamMetrics::default()),
+        model_stats: Arc::new(ModelStats::default()),
+        payload_stats: Arc::new(PayloadStats::default()),
+        inflight_bodies: Arc::new(InflightBodies::default()),
+        semantic_cache: SemanticCache::from_config(&config.semantic_cache).map(Arc::new),
+        billing: BillingHook::spawn(&config.billing),
+        trace_exporter: TraceExporter::from_config(&config.trace_export).map(Arc::new),
+        archive,
+        inflight: Arc::new(InflightRequests::default()),
+        session_pins: Arc::new(SessionPins::default()),
+        session_limits: Arc::new(SessionLimits::default()),
     });
 
-    // Build router
-    let app = AxumRouter::new()
-        .route("/", get(serve_admin))
+    keep_warm::spawn(state.active.clone());
+    model_map::spawn_watcher(model_map_path, state.active.clone());
+
+    // Client API (requires a key once any is configured)
+    let mut api = AxumRouter::new()
         .route("/v1/messages", post(handle_messages))
         .route("/v1/messages/count_tokens", post(handle_count_tokens))
+        .route("/v1/messages/validate", post(validate::validate_messages))
         .route("/v1/chat/completions", post(handle_openai_chat_completions))
-        .route("/health", get(health_check))
+        .route("/v1/completions", post(handle_openai_completions))
+        .route("/v1/templates", get(list_templates))
+        .route("/v1/stats/models", get(model_stats::model_stats))
+        .route("/v1/stats/payloads", get(payload_stats::payload_stats))
+        .route("/v1/templates/:name/messages", post(handle_template_messages));
+    // Claude Code's event logging and feature flag calls
+    let telemetry = &config.server.telemetry;
+    if telemetry.mode != crate::cli::TelemetryMode::Off {
+        let paths = telemetry::PATHS.iter().copied().chain(telemetry.paths.iter().map(String::as_str));
+        for path in paths {
+            api = api.route(path, any(telemetry::handle));
+        }
+    }
+    let api = api
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), client_auth::require_api_key));
+
+    // Client API keys (an admin credential is required even without OIDC)
+    let keys = AxumRouter::new()
+        .route("/admin/keys", get(client_auth::list_keys).post(client_auth::mint_key))
+        .route("/admin/keys/revoke", post(client_auth::revoke_key))
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), client_auth::require_key_admin));
+
+    // Admin API and dashboard (require an OIDC token when configured)
+    let admin = AxumRouter::new()
+        .merge(keys)
+        .route("/", get(serve_admin))
         .route("/api/models", get(get_models))
         .route("/api/providers", get(get_providers))
         .route("/api/models-config", get(get_models_config))
@@ -77,19 +319,68 @@ pub async fn start_server(config: AppConfig, config_path: std::path::PathBuf) ->
         .route("/api/config/json", get(get_config_json))
         .route("/api/config/json", post(update_config_json))
         .route("/api/restart", post(restart_server))
+        .route("/api/blobs/:hash", get(get_blob))
+        // Request log tailing
+        .route("/admin/logs", get(request_log::list_logs))
+        .route("/admin/logs/stream", get(request_log::stream_logs))
+        .route("/admin/logs/compare", get(compare::compare))
+        .route("/v1/logs/:id/replay", post(replay::replay))
+        // Persistent stats (require the database)
+        .route("/admin/health", get(stats::provider_health))
+        .route("/admin/failures", get(stats::provider_failures))
+        .route("/admin/scores", get(stats::quality_scores))
+        .route("/admin/usage", get(stats::usage))
+        .route("/admin/usage/live", get(stats::shared_usage))
+        .route("/admin/streams", get(backpressure::stream_metrics))
+        .route("/admin/audit", get(audit::list_audit))
+        .route("/admin/route/explain", post(explain::explain_route))
+        // Running requests and the kill switch
+        .route("/admin/requests", get(inflight::list_requests))
+        .route("/admin/requests/cancel", post(inflight::cancel_provider))
+        .route("/admin/requests/:id/cancel", post(inflight::cancel_request))
+        // Conversations pinned to a provider
+        .route("/admin/sessions", get(sessions::list_pins))
+        .route("/admin/sessions/:id/pin", post(sessions::pin_session))
+        .route("/admin/sessions/:id/unpin", post(sessions::unpin_session))
+        // Config management
+        .route("/admin/config/validate", post(config_reload::validate_config))
+        .route("/admin/config/reload", post(config_reload::reload_config))
+        // Anthropic Admin API passthrough for usage dashboards
+        .route("/v1/organizations/*path", any(organizations::passthrough))
         // OAuth endpoints
         .route("/api/oauth/authorize", post(oauth_handlers::oauth_authorize))
         .route("/api/oauth/exchange", post(oauth_handlers::oauth_exchange))
-        .route("/api/oauth/callback", get(oauth_handlers::oauth_callback))
-        .route("/auth/callback", get(oauth_handlers::oauth_callback))  // OpenAI Codex uses this path
         .route("/api/oauth/tokens", get(oauth_handlers::oauth_list_tokens))
         .route("/api/oauth/tokens/delete", post(oauth_handlers::oauth_delete_token))
-        .route("/api/oauth/tokens/refresh", post(oauth_handlers::oauth_refresh_token));
+        .route("/api/oauth/tokens/refresh", post(oauth_handlers::oauth_refresh_token))
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), admin_auth::require_admin));
+
+    // Build router
+    let app = AxumRouter::new()
+        .merge(api)
+        .merge(admin)
+        .route("/health", get(health_check))
+        .route("/metrics", get(model_stats::metrics))
+        // OAuth provider redirects carry no admin token
+        .route("/api/oauth/callback", get(oauth_handlers::oauth_callback))
+        .route("/auth/callback", get(oauth_handlers::oauth_callback));  // OpenAI Codex uses this path
+
+    // Size limits are read at startup
+    let app = app
+        .layer(axum::middleware::from_fn_with_state(state.clone(), limits::guard))
+        .layer(DefaultBodyLimit::max(config.server.limits.max_request_bytes));
+
+    let app = compression(app, config.server.compression);
 
     // Clone state before moving it
     let oauth_state = state.clone();
     let app = app.with_state(state);
 
+    // Editor plugins talk to a subprocess over stdin/stdout, no ports are bound
+    if stdio {
+        return stdio::serve(app).await;
+    }
+
     // Bind to main address
     let addr = format!("{}:{}", config.server.host, config.server.port);
     let listener = TcpListener::bind(&addr).await?;
@@ -119,6 +410,19 @@ pub async fn start_server(config: AppConfig, config_path: std::path::PathBuf) ->
         }
     });
 
+    // gRPC ingress dispatches to the same routes
+    if let Some(grpc_port) = config.server.grpc_port {
+        #[cfg(feature = "grpc")]
+        match format!("{}:{}", config.server.host, grpc_port).parse() {
+            Ok(grpc_addr) => {
+                tokio::spawn(grpc::serve(grpc_addr, app.clone()));
+            }
+            Err(e) => error!("⚠️  Invalid gRPC address: {}", e),
+        }
+        #[cfg(not(feature = "grpc"))]
+        warn!("⚠️  grpc_port {} is set, but this build has no gRPC support (build with --features grpc)", grpc_port);
+    }
+
     // Start main server
     axum::serve(listener, app).await?;
 
@@ -146,16 +450,17 @@ async fn get_models(State(_state): State<Arc<AppState>>) -> Result<Json<serde_js
 
 /// Get current routing configuration
 async fn get_config(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let active = state.active();
     Json(serde_json::json!({
         "server": {
-            "host": state.config.server.host,
-            "port": state.config.server.port,
+            "host": active.config.server.host,
+            "port": active.config.server.port,
         },
         "router": {
-            "default": state.config.router.default,
-            "background": state.config.router.background,
-            "think": state.config.router.think,
-            "websearch": state.config.router.websearch,
+            "default": active.config.router.default,
+            "background": active.config.router.background,
+            "think": active.config.router.think,
+            "websearch": active.config.router.websearch,
         }
     }))
 }
@@ -172,6 +477,7 @@ struct ConfigUpdate {
 
 async fn update_config(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Form(update): Form<ConfigUpdate>,
 ) -> Result<Html<String>, AppError> {
     // Read current config
@@ -181,6 +487,7 @@ async fn update_config(
 
     let mut config: toml::Value = toml::from_str(&config_str)
         .map_err(|e| AppError::ParseError(format!("Failed to parse config: {}", e)))?;
+    let before = config.clone();
 
     // Update router section
     if let Some(router) = config.get_mut("router").and_then(|v| v.as_table_mut()) {
@@ -207,35 +514,45 @@ async fn update_config(
         .map_err(|e| AppError::ParseError(format!("Failed to write config: {}", e)))?;
 
     info!("✅ Configuration updated successfully");
+    record_config_change(&state, &headers, &before, &config);
 
     Ok(Html("<div class='px-4 py-3 rounded-xl bg-primary/20 border border-primary/50 text-foreground text-sm'>✅ Configuration saved successfully! Please restart the server to apply changes.</div>".to_string()))
 }
 
+/// Audit a config file edit made through the admin UI
+fn record_config_change(state: &AppState, headers: &HeaderMap, before: &toml::Value, after: &toml::Value) {
+    let to_json = |v: &toml::Value| serde_json::to_value(v).unwrap_or_default();
+    state
+        .audit_log
+        .record(AuditEntry::new(audit::actor(headers), "config.update").diff(&to_json(before), &to_json(after)));
+}
+
 /// Get providers configuration
 async fn get_providers(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    Json(state.config.providers.clone())
+    Json(state.active().config.providers.clone())
 }
 
 /// Get models configuration
 async fn get_models_config(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    Json(state.config.models.clone())
+    Json(state.active().config.models.clone())
 }
 
 /// Get full configuration as JSON (for admin UI)
 async fn get_config_json(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let active = state.active();
     Json(serde_json::json!({
         "server": {
-            "host": state.config.server.host,
-            "port": state.config.server.port,
+            "host": active.config.server.host,
+            "port": active.config.server.port,
         },
         "router": {
-            "default": state.config.router.default,
-            "background": state.config.router.background,
-            "think": state.config.router.think,
-            "websearch": state.config.router.websearch,
+            "default": active.config.router.default,
+            "background": active.config.router.background,
+            "think": active.config.router.think,
+            "websearch": active.config.router.websearch,
         },
-        "providers": state.config.providers,
-        "models": state.config.models,
+        "providers": active.config.providers,
+        "models": active.config.models,
     }))
 }
 
@@ -260,6 +577,7 @@ fn remove_null_values(value: &mut serde_json::Value) {
 /// Update configuration via JSON (for admin UI)
 async fn update_config_json(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Json(mut new_config): Json<serde_json::Value>,
 ) -> Result<Json<serde_json::Value>, AppError> {
     // Remove null values (TOML doesn't support null)
@@ -274,6 +592,7 @@ async fn update_config_json(
 
     let mut config: toml::Value = toml::from_str(&config_str)
         .map_err(|e| AppError::ParseError(format!("Failed to parse config: {}", e)))?;
+    let before = config.clone();
 
     // Update providers section
     if let Some(providers) = new_config.get("providers") {
@@ -341,6 +660,7 @@ async fn update_config_json(
         .map_err(|e| AppError::ParseError(format!("Failed to write config: {}", e)))?;
 
     info!("✅ Configuration updated successfully via admin UI");
+    record_config_change(&state, &headers, &before, &config);
 
     Ok(Json(serde_json::json!({
         "status": "success",
@@ -348,11 +668,27 @@ async fn update_config_json(
     })))
 }
 
+/// Fetch an externalized payload from the blob store
+async fn get_blob(
+    State(state): State<Arc<AppState>>,
+    axum::extract::Path(hash): axum::extract::Path<String>,
+) -> Response {
+    let Some(store) = &state.blob_store else {
+        return (StatusCode::NOT_FOUND, "Blob store is disabled").into_response();
+    };
+    match store.get(&hash) {
+        Ok(Some(bytes)) => bytes.into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Blob not found").into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
 /// Restart server automatically using shell script
-async fn restart_server(State(state): State<Arc<AppState>>) -> Response {
+async fn restart_server(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
     info!("🔄 Server restart requested via UI");
+    state.audit_log.record(AuditEntry::new(audit::actor(&headers), "server.restart"));
 
-    let port = state.config.server.port;
+    let port = state.active().config.server.port;
 
     // Create a shell script to handle restart
     match create_and_execute_restart_script(port) {
@@ -463,8 +799,18 @@ async fn handle_openai_chat_completions(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
     Json(openai_request): Json<openai_compat::OpenAIRequest>,
+) -> Result<Response, AppError> {
+    crate::providers::pool::scope(openai_chat_completions(state, headers, openai_request)).await
+}
+
+async fn openai_chat_completions(
+    state: Arc<AppState>,
+    headers: HeaderMap,
+    openai_request: openai_compat::OpenAIRequest,
 ) -> Result<Response, AppError> {
     let model = openai_request.model.clone();
+    let legacy_functions = openai_request.uses_legacy_functions();
+    let include_usage = openai_request.stream_options.as_ref().is_some_and(|o| o.include_usage);
     info!("Received OpenAI-compatible request for model: {}", model);
 
     // 1. Transform OpenAI request to Anthropic format
@@ -474,18 +820,149 @@ async fn handle_openai_chat_completions(
     info!("Transformed OpenAI request to Anthropic format");
 
     // 2. Route the request (may modify system prompt to remove CCM-SUBAGENT-MODEL tag)
-    let decision = state
+    let active = state.active();
+    let decision = active
         .router
         .route(&mut anthropic_request)
         .map_err(|e| AppError::RoutingError(e.to_string()))?;
 
+    let anthropic_response = match send_openai_compat(&state, &active, &headers, anthropic_request, decision).await? {
+        CompatResponse::Complete(response) => response,
+        CompatResponse::Stream(stream) => {
+            let mut translator = openai_compat::ChunkTranslator::new(model, include_usage, legacy_functions);
+            let events = stream.flat_map(move |result| {
+                let events: Vec<Result<Event, ProviderError>> = match result {
+                    Ok(bytes) => translator.push(&bytes).into_iter().map(|data| Ok(Event::default().data(data))).collect(),
+                    Err(e) => vec![Err(e)],
+                };
+                futures::stream::iter(events)
+            });
+            return Ok(Sse::new(events).into_response());
+        }
+    };
+
+    // Transform Anthropic response to OpenAI format
+    Ok(Json(openai_compat::transform_anthropic_to_openai(anthropic_response, model, legacy_functions)).into_response())
+}
+
+/// Handle legacy /v1/completions requests (OpenAI-compatible endpoint)
+async fn handle_openai_completions(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(completion_request): Json<openai_compat::CompletionRequest>,
+) -> Result<Response, AppError> {
+    crate::providers::pool::scope(openai_completions(state, headers, completion_request)).await
+}
+
+async fn openai_completions(
+    state: Arc<AppState>,
+    headers: HeaderMap,
+    completion_request: openai_compat::CompletionRequest,
+) -> Result<Response, AppError> {
+    let model = completion_request.model.clone();
+    info!("Received OpenAI-compatible completion request for model: {}", model);
+
+    let prompt = completion_request
+        .prompt_text()
+        .map_err(AppError::ParseError)?;
+    let echo = completion_request.echo.unwrap_or(false);
+    let suffix = completion_request.suffix.clone().unwrap_or_default();
+    let mut anthropic_request = openai_compat::transform_completion_to_anthropic(completion_request, &prompt);
+
+    let active = state.active();
+    let decision = active
+        .router
+        .route_completion(&mut anthropic_request)
+        .map_err(|e| AppError::RoutingError(e.to_string()))?;
+    info!("🎯 Routed to: {} ({})", decision.model_name, decision.route_type);
+    set_lane(&headers, &decision);
+
+    // Mappings with a FIM format go to the provider's completions endpoint directly
+    let forced_provider = headers.get("x-provider").and_then(|v| v.to_str().ok());
+    if let Some(model_config) = active.model(&decision.model_name) {
+        let mut fim_mappings: Vec<_> = model_config
+            .mappings
+            .iter()
+            .filter(|m| m.fim.is_some() && forced_provider.is_none_or(|p| p == m.provider))
+            .collect();
+        fim_mappings.sort_by_key(|m| m.priority);
+
+        for mapping in fim_mappings {
+            let Some(provider) = active.provider_registry.get_provider(&mapping.provider) else {
+                continue;
+            };
+            let template = mapping.fim.expect("filtered on fim");
+            let (fim_prompt, fim_suffix) = template.render(&prompt, &suffix);
+            let stop = template.stop(anthropic_request.stop_sequences.clone().unwrap_or_default());
+
+            let fim_request = FimRequest {
+                model: mapping.actual_model.clone(),
+                prompt: fim_prompt,
+                suffix: fim_suffix,
+                max_tokens: anthropic_request.max_tokens,
+                temperature: anthropic_request.temperature,
+                top_p: anthropic_request.top_p,
+                stop,
+            };
+            match provider.complete_fim(fim_request).await {
+                Ok(response) => {
+                    info!("✅ FIM completion succeeded with provider: {}", mapping.provider);
+                    state.record_provider_success(&mapping.provider).await;
+                    return Ok(Json(openai_compat::fim_to_completion(response, model, &prompt, echo)).into_response());
+                }
+                Err(e) => {
+                    info!("⚠️ Provider {} FIM completion failed: {}, trying next fallback", mapping.provider, e);
+                    state.record_provider_failure(&mapping.provider, &e).await;
+                }
+            }
+        }
+    }
+
+    // Otherwise emulate with a chat request and assistant prefill
+    let anthropic_response = send_openai_compat(&state, &active, &headers, anthropic_request, decision)
+        .await?
+        .collect()
+        .await?;
+
+    Ok(Json(openai_compat::transform_anthropic_to_completion(anthropic_response, model, &prompt, echo))
+        .into_response())
+}
+
+/// Upstream answer to an OpenAI-compatible request
+enum CompatResponse {
+    Complete(ProviderResponse),
+    /// Anthropic SSE, when the client asked to stream
+    Stream(std::pin::Pin<Box<dyn futures::Stream<Item = Result<bytes::Bytes, ProviderError>> + Send>>),
+}
+
+impl CompatResponse {
+    /// The complete response, reading a stream to its end
+    async fn collect(self) -> Result<ProviderResponse, AppError> {
+        match self {
+            CompatResponse::Complete(response) => Ok(response),
+            CompatResponse::Stream(stream) => crate::providers::streaming::collect_response(stream)
+                .await
+                .map_err(|e| AppError::ProviderError(e.to_string())),
+        }
+    }
+}
+
+/// Send a routed OpenAI-compatible request, with mapping fallback
+async fn send_openai_compat(
+    state: &Arc<AppState>,
+    active: &ActiveConfig,
+    headers: &HeaderMap,
+    mut anthropic_request: AnthropicRequest,
+    decision: RouteDecision,
+) -> Result<CompatResponse, AppError> {
     info!(
         "🎯 Routed to: {} ({})",
         decision.model_name, decision.route_type
     );
+    set_lane(headers, &decision);
 
     // 3. Try model mappings with fallback (1:N mapping)
-    if let Some(model_config) = state.config.models.iter().find(|m| m.name == decision.model_name) {
+    if let Some(model_config) = active.model(&decision.model_name) {
         info!("📋 Found {} provider mappings for model: {}", model_config.mappings.len(), decision.model_name);
 
         // Check for X-Provider header to override priority
@@ -516,6 +993,9 @@ async fn handle_openai_chat_completions(
             sorted_mappings.sort_by_key(|m| m.priority);
         }
 
+        // One key for every attempt, so a provider that already ran the request can tell
+        let idempotency_key = idempotency_key(headers, &format!("req_{}", request_log::uuid_like()));
+
         // Try each mapping in priority order (or just the forced one)
         for (idx, mapping) in sorted_mappings.iter().enumerate() {
             info!(
@@ -527,33 +1007,69 @@ async fn handle_openai_chat_completions(
             );
 
             // Try to get provider from registry
-            if let Some(provider) = state.provider_registry.get_provider(&mapping.provider) {
+            if let Some(provider) = active.provider_registry.get_provider(&mapping.provider) {
                 // Update model to actual model name
                 anthropic_request.model = mapping.actual_model.clone();
 
-                // Check if streaming is requested
-                let is_streaming = anthropic_request.stream == Some(true);
-
-                if is_streaming {
-                    // Streaming not fully implemented for OpenAI format yet
-                    info!("⚠️ Streaming requested but not fully supported for OpenAI format, falling back to non-streaming");
+                let mut request = anthropic_request.clone();
+                if active.config.providers.iter().any(|p| p.name == mapping.provider && p.idempotency_keys) {
+                    request.idempotency_key = Some(idempotency_key.clone());
+                }
+                let transform::Prepared { renames: tool_renames, optional } = match transform::prepare(
+                    &active.config,
+                    Some(&model_config),
+                    mapping,
+                    provider.as_ref().as_ref(),
+                    &mut request,
+                ) {
+                    Ok(prepared) => prepared,
+                    Err(reason) => {
+                        info!("⚠️ Provider {} skipped: {}, trying next fallback", mapping.provider, reason);
+                        continue;
+                    }
+                };
+
+                // Streams are translated to OpenAI chunks by the caller
+                if anthropic_request.stream == Some(true) {
+                    match provider.send_message_stream(request).await {
+                        Ok(mut stream) => {
+                            info!("✅ Streaming request succeeded with provider: {}", mapping.provider);
+                            state.record_provider_success(&mapping.provider).await;
+                            if !optional.is_empty() {
+                                stream = Box::pin(coalesce_tool_input(stream));
+                                stream = Box::pin(optional.strip_stream(stream));
+                            }
+                            if !tool_renames.is_empty() {
+                                stream = Box::pin(map_sse_lines(stream, move |line| {
+                                    tool_renames.restore_sse_line(line)
+                                }));
+                            }
+                            return Ok(CompatResponse::Stream(stream));
+                        }
+                        Err(e) => {
+                            info!("⚠️ Provider {} streaming failed: {}, trying next fallback", mapping.provider, e);
+                            state.record_provider_failure(&mapping.provider, &e).await;
+                            continue;
+                        }
+                    }
                 }
 
-                // Non-streaming request
-                match provider.send_message(anthropic_request.clone()).await {
-                    Ok(anthropic_response) => {
+                // Non-streaming request, continued past the model's output limit if configured
+                let sent = match continuation::Budget::prepare(&mut request, mapping.continuation.as_ref()) {
+                    Some(budget) => continuation::send_message(provider.as_ref().as_ref(), request, &budget).await,
+                    None => provider.send_message(request).await,
+                };
+                match sent {
+                    Ok(mut anthropic_response) => {
                         info!("✅ Request succeeded with provider: {}", mapping.provider);
-
-                        // Transform Anthropic response to OpenAI format
-                        let openai_response = openai_compat::transform_anthropic_to_openai(
-                            anthropic_response,
-                            model.clone(),
-                        );
-
-                        return Ok(Json(openai_response).into_response());
+                        state.record_provider_success(&mapping.provider).await;
+                        optional.strip_response(&mut anthropic_response);
+                        tool_renames.restore_response(&mut anthropic_response);
+                        return Ok(CompatResponse::Complete(anthropic_response));
                     }
                     Err(e) => {
                         info!("⚠️ Provider {} failed: {}, trying next fallback", mapping.provider, e);
+                        state.record_provider_failure(&mapping.provider, &e).await;
                         continue;
                     }
                 }
@@ -571,24 +1087,18 @@ async fn handle_openai_chat_completions(
         )));
     } else {
         // No model mapping found, try direct provider registry lookup (backward compatibility)
-        if let Ok(provider) = state.provider_registry.get_provider_for_model(&decision.model_name) {
+        if let Ok(provider) = active.provider_registry.get_provider_for_model(&decision.model_name) {
             info!("📦 Using provider from registry (direct lookup): {}", decision.model_name);
 
             // Update model to routed model
             anthropic_request.model = decision.model_name.clone();
 
             // Call provider
-            let anthropic_response = provider.send_message(anthropic_request)
+            anthropic_request.stream = None;
+            return provider.send_message(anthropic_request)
                 .await
-                .map_err(|e| AppError::ProviderError(e.to_string()))?;
-
-            // Transform to OpenAI format
-            let openai_response = openai_compat::transform_anthropic_to_openai(
-                anthropic_response,
-                model,
-            );
-
-            return Ok(Json(openai_response).into_response());
+                .map(CompatResponse::Complete)
+                .map_err(|e| AppError::ProviderError(e.to_string()));
         }
 
         error!("❌ No model mapping or provider found for model: {}", decision.model_name);
@@ -602,18 +1112,284 @@ async fn handle_openai_chat_completions(
 /// Handle /v1/messages requests (both streaming and non-streaming)
 async fn handle_messages(
     State(state): State<Arc<AppState>>,
+    RawQuery(query): RawQuery,
+    mut headers: HeaderMap,
+    StreamedJson(request_json): StreamedJson,
+) -> Result<Response, AppError> {
+    // Betas listed in the query string are treated like `anthropic-beta` headers
+    for beta in query_betas(query.as_deref()) {
+        if let Ok(value) = HeaderValue::from_str(&beta) {
+            headers.append("anthropic-beta", value);
+        }
+    }
+    run_messages(&state, &headers, "/v1/messages", request_json, None).await
+}
+
+/// Beta flags from `?beta=` / `?betas=` values
+///
+/// `?beta=true` (as sent by the Anthropic SDKs) only marks the beta endpoint.
+fn query_betas(query: Option<&str>) -> Vec<String> {
+    url::form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+        .filter(|(key, value)| (key == "beta" || key == "betas") && value != "true" && value != "false")
+        .map(|(_, value)| value.into_owned())
+        .collect()
+}
+
+/// Handle /v1/templates/:name/messages: render a configured prompt template and
+/// route the result like a /v1/messages request
+async fn handle_template_messages(
+    State(state): State<Arc<AppState>>,
+    axum::extract::Path(name): axum::extract::Path<String>,
     headers: HeaderMap,
-    Json(request_json): Json<serde_json::Value>,
+    Json(request): Json<TemplateRequest>,
+) -> Result<Response, AppError> {
+    let active = state.active();
+    let template = active
+        .config
+        .templates
+        .iter()
+        .find(|t| t.name == name)
+        .ok_or_else(|| AppError::RoutingError(format!("Unknown template '{}'", name)))?;
+    let request_json = template
+        .render(request, &active.config.router.default)
+        .map_err(AppError::RoutingError)?;
+
+    let endpoint = format!("/v1/templates/{}/messages", name);
+    run_messages(&state, &headers, &endpoint, request_json, None).await
+}
+
+/// GET /v1/templates - configured templates and their variables
+async fn list_templates(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    let templates: Vec<_> = state
+        .active()
+        .config
+        .templates
+        .iter()
+        .map(|t| serde_json::json!({ "name": t.name, "model": t.model, "variables": t.variables() }))
+        .collect();
+    Json(serde_json::json!({ "templates": templates }))
+}
+
+/// Key the client authenticated with (`x-api-key` or `Authorization: Bearer`)
+fn client_api_key(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get("x-api-key")
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| {
+            headers
+                .get("authorization")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix("Bearer "))
+        })
+}
+
+/// Idempotency key for the upstream calls of a request: the client's own
+/// `Idempotency-Key` if it sent one, else `request_id`
+fn idempotency_key(headers: &HeaderMap, request_id: &str) -> String {
+    headers
+        .get(http::IDEMPOTENCY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+        .unwrap_or(request_id)
+        .to_string()
+}
+
+/// Background work yields upstream connection slots to the interactive session
+fn set_lane(headers: &HeaderMap, decision: &RouteDecision) {
+    let background = headers
+        .get("x-ccm-priority")
+        .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"background"));
+    if background || decision.route_type == RouteType::Background {
+        crate::providers::pool::set_lane(crate::providers::pool::Lane::Background);
+    }
+}
+
+/// Role the client gave the request (`x-ccm-role: main|plan|subagent|background`)
+fn client_role(headers: &HeaderMap) -> Option<Role> {
+    headers
+        .get("x-ccm-role")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+}
+
+/// Route a Messages API request and record it in the request log and usage store
+async fn run_messages(
+    state: &Arc<AppState>,
+    headers: &HeaderMap,
+    endpoint: &str,
+    request_json: serde_json::Value,
+    replay_of: Option<String>,
+) -> Result<Response, AppError> {
+    let client = client_auth::client(state, headers);
+    let tenant = client.as_ref().map(|c| c.name.clone());
+    let mut privacy = client.map(|c| c.privacy).unwrap_or_default();
+    // Requests outside the body sample are logged like a `log_bodies = false` key's
+    let logging = state.active().config.server.logging.clone();
+    privacy.log_bodies &= logging.sample();
+    let exporter = state
+        .trace_exporter
+        .as_ref()
+        .filter(|e| privacy.metrics == MetricsMode::Full && e.wants(tenant.as_deref()));
+    let mut log_entry = RequestLogEntry::new(endpoint, &request_json);
+    log_entry.replay_of = replay_of;
+    let archive_bodies = privacy.log_bodies && state.archive.as_ref().is_some_and(|a| a.include_bodies());
+    let keep_bodies = state.request_log.has_subscribers() || state.request_log.keeps_bodies();
+    if privacy.log_bodies && (keep_bodies || exporter.is_some() || archive_bodies) {
+        log_entry.request_body = Some(request_json.clone());
+    }
+
+    // Routing explanation for live requests that ask for it
+    let explanation = explain::requested(headers)
+        .then(|| explain::explain(&state.active(), headers, &request_json).ok())
+        .flatten();
+
+    let registration = state.inflight.register(&log_entry.id, &log_entry.model, tenant.clone(), log_entry.stream);
+    let limit = state.active().config.server.limits.max_requests_per_session;
+    let session_id = sessions::session_id(&request_json).map(str::to_string);
+    let handled = async {
+        // Held until the response body (stream included) is done
+        let slot = state.session_limits.acquire(session_id.as_deref(), limit).await;
+        let response = handle_messages_inner(state, headers, &privacy, request_json, &mut log_entry, &registration).await?;
+        Ok(match slot {
+            Some(slot) => response.map(|body| {
+                axum::body::Body::from_stream(body.into_data_stream().map(move |chunk| {
+                    let _ = &slot;
+                    chunk
+                }))
+            }),
+            None => response,
+        })
+    };
+    let (mut result, losses) = transform::losses::collect(crate::providers::pool::scope(async {
+        tokio::select! {
+            result = handled => result,
+            _ = registration.cancelled() => Err(AppError::Cancelled(inflight::CANCELLED.to_string())),
+        }
+    }))
+    .await;
+    if let (false, Ok(response)) = (losses.is_empty(), result.as_mut()) {
+        if state.active().config.server.transform_warnings_header {
+            if let Ok(value) = HeaderValue::from_str(&transform::losses::summary(&losses)) {
+                response.headers_mut().insert(transform::losses::HEADER, value);
+            }
+        }
+    }
+    log_entry.transform_warnings = losses;
+    if let (Some(mut explanation), Ok(response)) = (explanation, result.as_mut()) {
+        explanation.served_by = log_entry.provider.clone();
+        explain::attach(response, &explanation);
+    }
+    if let (Some(_), Ok(response)) = (&log_entry.replay_of, result.as_mut()) {
+        if let Ok(value) = HeaderValue::from_str(&log_entry.id) {
+            response.headers_mut().insert(replay::REQUEST_ID_HEADER, value);
+        }
+    }
+
+    log_entry.finish(&result);
+    log_entry.redact(&logging.redact);
+    if privacy.metrics == MetricsMode::HeadersOnly {
+        log_entry.strip_details();
+    }
+    let exporter = exporter.cloned();
+    match result.as_mut() {
+        // Usage of a stream is known once the client has read it (or gone away)
+        Ok(response) if log_entry.streaming() => {
+            let record = RecordOnDrop(Some((state.clone(), log_entry, tenant, exporter, archive_bodies)));
+            let body = std::mem::take(response.body_mut());
+            *response.body_mut() = axum::body::Body::from_stream(body.into_data_stream().map(move |chunk| {
+                let _ = &record;
+                chunk
+            }));
+        }
+        _ => record_request(state, log_entry, tenant, exporter, archive_bodies).await,
+    }
+    result
+}
+
+type PendingRecord = (Arc<AppState>, RequestLogEntry, Option<String>, Option<Arc<TraceExporter>>, bool);
+
+/// Records a streamed request when its response body is dropped
+struct RecordOnDrop(Option<PendingRecord>);
+
+impl Drop for RecordOnDrop {
+    fn drop(&mut self) {
+        if let Some((state, mut log_entry, tenant, exporter, archive_bodies)) = self.0.take() {
+            tokio::spawn(async move {
+                log_entry.settle_stream();
+                record_request(&state, log_entry, tenant, exporter, archive_bodies).await;
+            });
+        }
+    }
+}
+
+/// Write a finished request to the usage store, exporters, archive and request log
+async fn record_request(
+    state: &Arc<AppState>,
+    mut log_entry: RequestLogEntry,
+    tenant: Option<String>,
+    exporter: Option<Arc<TraceExporter>>,
+    archive_bodies: bool,
+) {
+    let usage = log_entry.to_usage_record();
+    if let Some(store) = &state.usage_store {
+        if let Err(e) = store.append(&usage).await {
+            error!("Failed to record usage: {}", e);
+        }
+    }
+    if let Some(exporter) = exporter {
+        exporter.export(log_entry.to_trace(tenant.clone()));
+    }
+    if let Some(billing) = &state.billing {
+        billing.record(UsageEvent::new(&usage, tenant));
+    }
+    if let Some(archive) = &state.archive {
+        if archive_bodies {
+            // Archived files keep references to blobs rather than inline images and documents
+            if let Some(store) = &state.blob_store {
+                log_entry.externalize(store);
+            }
+            archive.append(&log_entry);
+        } else {
+            archive.append(&log_entry.summary());
+        }
+    }
+    stats::record_shared_usage(state.shared.as_ref(), &log_entry).await;
+    state.request_log.record(log_entry);
+}
+
+async fn handle_messages_inner(
+    state: &Arc<AppState>,
+    headers: &HeaderMap,
+    privacy: &PrivacySettings,
+    request_json: serde_json::Value,
+    log_entry: &mut RequestLogEntry,
+    inflight: &Arc<Registration>,
 ) -> Result<Response, AppError> {
+    let active = state.active();
     let model = request_json
         .get("model")
         .and_then(|m| m.as_str())
         .unwrap_or("unknown");
     info!("Received request for model: {}", model);
 
-    // DEBUG: Log request body for debugging
-    if let Ok(json_str) = serde_json::to_string_pretty(&request_json) {
-        tracing::debug!("📥 Incoming request body:\n{}", json_str);
+    // Sampled for grading by the judge model
+    let judged = state
+        .judge
+        .as_ref()
+        .filter(|judge| privacy.log_bodies && judge.sample())
+        .map(|judge| (judge.clone(), request_json.clone()));
+
+    // DEBUG: Log request body for debugging (large payloads replaced by blob references)
+    if privacy.log_bodies && tracing::enabled!(tracing::Level::DEBUG) {
+        let mut logged = request_json.clone();
+        request_log::redact(&mut logged, &active.config.server.logging.redact);
+        let logged = match &state.blob_store {
+            Some(store) => store.externalize(&logged),
+            None => logged,
+        };
+        if let Ok(json_str) = serde_json::to_string_pretty(&logged) {
+            tracing::debug!("📥 Incoming request body:\n{}", json_str);
+        }
     }
 
     // 1. Parse request for routing decision (mutable for tag extraction)
@@ -623,19 +1399,87 @@ async fn handle_messages(
             AppError::ParseError(format!("Invalid request format: {}", e))
         })?;
 
+    limits::check_images(&request_for_routing, &active.config.server.limits)?;
+
+    // Beta flags travel in headers; Bedrock- and Vertex-style bodies carry them as `anthropic_beta`
+    let body_betas = match request_json.get("anthropic_beta") {
+        Some(serde_json::Value::Array(betas)) => betas.iter().filter_map(|b| b.as_str()).collect(),
+        Some(serde_json::Value::String(betas)) => vec![betas.as_str()],
+        _ => Vec::new(),
+    };
+    let betas = parse_betas(
+        headers
+            .get_all("anthropic-beta")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .chain(body_betas),
+    );
+    // An unrecognized version is ignored rather than rejected; upstream requests use the pinned one
+    let anthropic_version = headers
+        .get("anthropic-version")
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| request_json.get("anthropic_version").and_then(|v| v.as_str()))
+        .and_then(|version| {
+            let parsed = parse_anthropic_version(version);
+            if parsed.is_none() {
+                tracing::debug!("Ignoring unrecognized anthropic-version {:?}", version);
+            }
+            parsed
+        });
+
     // 2. Route the request (may modify system prompt to remove CCM-SUBAGENT-MODEL tag)
-    let decision = state
+    let decision = active
         .router
-        .route(&mut request_for_routing)
+        .route_as(&mut request_for_routing, client_role(headers))
         .map_err(|e| AppError::RoutingError(e.to_string()))?;
 
     info!(
         "🎯 Routed to: {} ({})",
         decision.model_name, decision.route_type
     );
+    log_entry.routed_model = Some(decision.model_name.clone());
+    log_entry.route_type = Some(decision.route_type.to_string());
+
+    set_lane(headers, &decision);
+
+    // Loop and turn limits for agent traffic
+    active
+        .config
+        .guardrails
+        .limits_for(client_api_key(headers))
+        .apply(&mut request_for_routing)
+        .map_err(AppError::GuardrailTriggered)?;
+
+    // Opted-in models may be answered from the semantic cache (non-streaming only),
+    // with answers shared only within one tenant or client key
+    let mut cache_key = None;
+    let cache_client = client_auth::client(state, headers)
+        .map(|c| c.name)
+        .or_else(|| client_api_key(headers).map(crate::auth::api_keys::hash_key));
+    let cache = state.semantic_cache.as_ref().filter(|c| {
+        cache_client.is_some()
+            && privacy.store_sessions
+            && c.applies_to(&decision.model_name)
+            && request_for_routing.stream != Some(true)
+    });
+    if let (Some(cache), Some(client)) = (cache, &cache_client) {
+        match cache.key(&request_for_routing, &decision.model_name, client).await {
+            Ok(key) => {
+                if let Some(mut response) = cache.lookup(&key) {
+                    info!("🧠 Served from semantic cache: {}", decision.model_name);
+                    response.model = model.to_string();
+                    log_entry.provider = Some("semantic_cache".to_string());
+                    log_entry.record_response(&response, log_entry.request_body.is_some());
+                    return Ok(Json(response).into_response());
+                }
+                cache_key = Some(key);
+            }
+            Err(e) => warn!("Semantic cache skipped: {:#}", e),
+        }
+    }
 
     // 3. Try model mappings with fallback (1:N mapping)
-    if let Some(model_config) = state.config.models.iter().find(|m| m.name == decision.model_name) {
+    if let Some(model_config) = active.model(&decision.model_name) {
         info!("📋 Found {} provider mappings for model: {}", model_config.mappings.len(), decision.model_name);
 
         // Check for X-Provider header to override priority
@@ -649,6 +1493,25 @@ async fn handle_messages(
             info!("🎯 Using forced provider from X-Provider header: {}", provider_name);
         }
 
+        // A conversation pinned through the admin API stays on its provider
+        let pinned_mappings = log_entry
+            .session_id
+            .as_deref()
+            .and_then(|id| state.session_pins.get(id))
+            .map(|pin| {
+                let mappings = pin.mappings(&model_config.mappings);
+                if mappings.is_empty() {
+                    warn!(
+                        "📌 Session pinned to {}, which has no mapping for '{}'; routing normally",
+                        pin.provider, decision.model_name
+                    );
+                } else {
+                    info!("📌 Using provider pinned for the session: {}", pin.provider);
+                }
+                mappings
+            })
+            .filter(|mappings| !mappings.is_empty());
+
         // Sort mappings by priority (or filter by forced provider)
         let mut sorted_mappings = model_config.mappings.clone();
 
@@ -661,11 +1524,23 @@ async fn handle_messages(
                     provider_name, decision.model_name
                 )));
             }
+        } else if let Some(pinned) = pinned_mappings {
+            sorted_mappings = pinned;
         } else {
             // Use priority ordering
             sorted_mappings.sort_by_key(|m| m.priority);
         }
 
+        // Only one SLO miss is rerouted; later mappings get unlimited time
+        let mut slo_rerouted = false;
+        // Likewise, a refusal is retried on the next mapping once
+        let refusal_check = model_config.refusal_retry.as_ref().map(refusal::RefusalCheck::new);
+        let mut refusal_retried = false;
+        let received_bytes = serde_json::to_vec(&request_json).map_or(0, |body| body.len());
+        // One key for every attempt, so a provider that already ran the request can tell
+        let idempotency_key = idempotency_key(headers, &log_entry.id);
+        let tenant = client_auth::client(state, headers).map(|client| client.name);
+
         // Try each mapping in priority order (or just the forced one)
         for (idx, mapping) in sorted_mappings.iter().enumerate() {
             info!(
@@ -677,8 +1552,14 @@ async fn handle_messages(
             );
 
             // Try to get provider from registry
-            if let Some(provider) = state.provider_registry.get_provider(&mapping.provider) {
+            if let Some(provider) = active.provider_registry.get_provider(&mapping.provider) {
+                // Only the warnings of the provider that answers are reported
+                transform::losses::reset();
+
                 // Trust the model mapping configuration - no need to validate
+                inflight.set_provider(&mapping.provider);
+                log_entry.provider = Some(mapping.provider.clone());
+                log_entry.actual_model = Some(mapping.actual_model.clone());
 
                 // Parse request as Anthropic format
                 let mut anthropic_request: AnthropicRequest = serde_json::from_value(request_json.clone())
@@ -692,6 +1573,54 @@ async fn handle_messages(
 
                 // Update system if modified during routing
                 anthropic_request.system = request_for_routing.system.clone();
+                anthropic_request.betas = betas.clone();
+                anthropic_request.anthropic_version = anthropic_version.clone();
+                let provider_config = active.config.providers.iter().find(|p| p.name == mapping.provider);
+                if provider_config.is_some_and(|p| p.idempotency_keys) {
+                    anthropic_request.idempotency_key = Some(idempotency_key.clone());
+                }
+                // After session pinning, which reads the client's user_id
+                if let Some(tagging) = provider_config.and_then(|p| p.user_id.as_ref()) {
+                    tagging.apply(&mut anthropic_request, tenant.as_deref(), client_api_key(headers));
+                }
+
+                // Fine-grained tool streaming is passed through where supported, emulated elsewhere
+                let coalesce_tools = anthropic_request.has_beta(FINE_GRAINED_TOOL_STREAMING)
+                    && !provider.supports_beta(FINE_GRAINED_TOOL_STREAMING);
+
+                // Tool policy, history normalization, truncation and output defaults
+                let transform::Prepared { renames: tool_renames, optional } = match transform::prepare(
+                    &active.config,
+                    Some(&model_config),
+                    mapping,
+                    provider.as_ref().as_ref(),
+                    &mut anthropic_request,
+                ) {
+                    Ok(prepared) => prepared,
+                    Err(reason) => {
+                        info!("⚠️ Provider {} skipped: {}, trying next fallback", mapping.provider, reason);
+                        continue;
+                    }
+                };
+
+                let check_refusal = refusal_check
+                    .as_ref()
+                    .filter(|_| !refusal_retried && idx + 1 < sorted_mappings.len());
+                let repair_fences = active
+                    .config
+                    .providers
+                    .iter()
+                    .find(|p| p.name == mapping.provider)
+                    .is_some_and(|p| p.repair_code_fences);
+
+                // Cap max_tokens at the model's limit and continue past it
+                let budget = continuation::Budget::prepare(&mut anthropic_request, mapping.continuation.as_ref());
+                let continued = budget.map(|budget| (budget, anthropic_request.clone()));
+
+                let payload_warnings = &active.config.server.payload_warnings;
+                state
+                    .payload_stats
+                    .record_request(&mapping.provider, received_bytes, &anthropic_request, payload_warnings);
 
                 // Check if streaming is requested
                 let is_streaming = anthropic_request.stream == Some(true);
@@ -700,9 +1629,111 @@ async fn handle_messages(
                     // Streaming request
                     info!("🌊 Streaming request to provider: {}", mapping.provider);
 
-                    match provider.send_message_stream(anthropic_request).await {
-                        Ok(stream) => {
+                    let sent = std::time::Instant::now();
+                    let slo = model_config
+                        .ttft_slo_ms
+                        .filter(|_| !slo_rerouted && idx + 1 < sorted_mappings.len())
+                        .map(std::time::Duration::from_millis);
+                    let started = match slo {
+                        Some(slo) => {
+                            let started = async {
+                                Ok(first_token(provider.send_message_stream(anthropic_request).await?).await)
+                            };
+                            match tokio::time::timeout(slo, started).await {
+                                Ok(result) => result,
+                                Err(_) => {
+                                    warn!(
+                                        "⏱️ Provider {} missed the {}ms time-to-first-token SLO, rerouting",
+                                        mapping.provider,
+                                        slo.as_millis()
+                                    );
+                                    slo_rerouted = true;
+                                    continue;
+                                }
+                            }
+                        }
+                        None => provider.send_message_stream(anthropic_request).await,
+                    };
+
+                    let started = match (started, check_refusal) {
+                        (Ok(stream), Some(check)) => match check.check_stream(stream).await {
+                            (true, _) => {
+                                warn!("🙅 Provider {} refused the request, retrying on the next mapping", mapping.provider);
+                                refusal_retried = true;
+                                continue;
+                            }
+                            (false, stream) => Ok(stream),
+                        },
+                        (started, _) => started,
+                    };
+
+                    match started {
+                        Ok(mut stream) => {
                             info!("✅ Streaming request started with provider: {}", mapping.provider);
+                            state.record_provider_success(&mapping.provider).await;
+
+                            // Splice in continuation rounds when the output limit is hit
+                            if let Some((budget, request)) = continued {
+                                stream = continuation::stream(stream, provider.clone(), request, budget);
+                            }
+
+                            if repair_fences {
+                                stream = Box::pin(fences::repair_stream(stream));
+                            }
+
+                            // Buffer tool input so the client gets it as one well-formed delta
+                            // (needed as well to clean up the input of strict tools)
+                            if coalesce_tools || !optional.is_empty() {
+                                stream = Box::pin(coalesce_tool_input(stream));
+                            }
+                            if !optional.is_empty() {
+                                stream = Box::pin(optional.strip_stream(stream));
+                            }
+
+                            // Restore original tool names in tool_use events
+                            if !tool_renames.is_empty() {
+                                stream = Box::pin(map_sse_lines(stream, move |line| {
+                                    tool_renames.restore_sse_line(line)
+                                }));
+                            }
+
+                            if let Some((judge, request)) = &judged {
+                                let job = JudgeJob {
+                                    request_id: log_entry.id.clone(),
+                                    provider: mapping.provider.clone(),
+                                    model: mapping.actual_model.clone(),
+                                    request: request.clone(),
+                                };
+                                stream = judge.watch_stream(stream, job);
+                            }
+
+                            stream = model_stats::observe(
+                                stream,
+                                state.model_stats.clone(),
+                                log_entry.watch_stream(mapping),
+                                &mapping.provider,
+                                &mapping.actual_model,
+                                sent,
+                            );
+                            stream = payload_stats::observe(
+                                stream,
+                                state.payload_stats.clone(),
+                                &mapping.provider,
+                                payload_warnings.clone(),
+                            );
+
+                            if let Some(rate) = model_config.max_output_tokens_per_sec {
+                                stream = pacing::pace(stream, rate);
+                            }
+
+                            // Bound read-ahead so a slow client pauses upstream
+                            let stream = relay(
+                                stream,
+                                active.config.server.stream_buffer_chunks,
+                                state.stream_metrics.clone(),
+                            );
+                            // Stays listed in /admin/requests (and cancellable) until the stream ends
+                            let stream = inflight.clone().guard_stream(Box::pin(stream));
 
                             // Convert byte stream to SSE response
                             // The provider returns raw bytes (SSE format), we pass them through
@@ -721,20 +1752,79 @@ async fn handle_messages(
                         }
                         Err(e) => {
                             info!("⚠️ Provider {} streaming failed: {}, trying next fallback", mapping.provider, e);
+                            state.record_provider_failure(&mapping.provider, &e).await;
                             continue;
                         }
                     }
                 } else {
                     // Non-streaming request (original behavior)
-                    match provider.send_message(anthropic_request).await {
+                    // Fallback for usage without prompt tokens, so priced requests
+                    // with images aren't counted as nearly free
+                    let estimated_input = mapping.input_cost_per_mtok.is_some().then(|| {
+                        let provider_type = active
+                            .config
+                            .providers
+                            .iter()
+                            .find(|p| p.name == mapping.provider)
+                            .map_or("openai", |p| p.provider_type.as_str());
+                        VisionFormula::for_provider(provider_type).estimate_input_tokens(&anthropic_request)
+                    });
+                    let sent = match &continued {
+                        Some((budget, _)) => continuation::send_message(provider.as_ref().as_ref(), anthropic_request, budget).await,
+                        None => provider.send_message(anthropic_request).await,
+                    };
+                    match sent {
+                        Ok(response) if check_refusal.is_some_and(|check| check.is_refusal(&response)) => {
+                            warn!("🙅 Provider {} refused the request, retrying on the next mapping", mapping.provider);
+                            refusal_retried = true;
+                            continue;
+                        }
                         Ok(mut response) => {
                             // Restore original model name in response
                             response.model = original_model;
+                            optional.strip_response(&mut response);
+                            tool_renames.restore_response(&mut response);
+                            if repair_fences {
+                                fences::repair_response(&mut response);
+                            }
                             info!("✅ Request succeeded with provider: {}, response model: {}", mapping.provider, response.model);
+                            state.record_provider_success(&mapping.provider).await;
+                            log_entry.record_response(&response, log_entry.request_body.is_some());
+                            state.payload_stats.record_response(
+                                &mapping.provider,
+                                serde_json::to_vec(&response).map_or(0, |body| body.len()),
+                                payload_warnings,
+                            );
+                            let mut input_tokens = response.usage.input_tokens;
+                            if let Some(estimated) = estimated_input.filter(|_| {
+                                input_tokens == 0 && response.usage.cache_read_input_tokens.is_none()
+                            }) {
+                                tracing::debug!("Provider reported no prompt tokens, estimating {}", estimated);
+                                input_tokens = estimated;
+                                log_entry.input_tokens = Some(estimated);
+                            }
+                            log_entry.cost_usd = mapping.cost_usd(
+                                input_tokens,
+                                response.usage.cache_read_input_tokens.unwrap_or(0),
+                                response.usage.output_tokens,
+                            );
+                            if let (Some(cache), Some(key)) = (cache, cache_key.take()) {
+                                cache.insert(key, &response);
+                            }
+                            if let Some((judge, request)) = &judged {
+                                let job = JudgeJob {
+                                    request_id: log_entry.id.clone(),
+                                    provider: mapping.provider.clone(),
+                                    model: mapping.actual_model.clone(),
+                                    request: request.clone(),
+                                };
+                                judge.submit(job, response.clone());
+                            }
                             return Ok(Json(response).into_response());
                         }
                         Err(e) => {
                             info!("⚠️ Provider {} failed: {}, trying next fallback", mapping.provider, e);
+                            state.record_provider_failure(&mapping.provider, &e).await;
                             continue;
                         }
                     }
@@ -753,8 +1843,9 @@ async fn handle_messages(
         )));
     } else {
         // No model mapping found, try direct provider registry lookup (backward compatibility)
-        if let Ok(provider) = state.provider_registry.get_provider_for_model(&decision.model_name) {
+        if let Ok(provider) = active.provider_registry.get_provider_for_model(&decision.model_name) {
             info!("📦 Using provider from registry (direct lookup): {}", decision.model_name);
+            log_entry.actual_model = Some(decision.model_name.clone());
 
             // Parse request as Anthropic format
             let mut anthropic_request: AnthropicRequest = serde_json::from_value(request_json.clone())
@@ -768,6 +1859,14 @@ async fn handle_messages(
 
             // Update system if modified during routing
             anthropic_request.system = request_for_routing.system.clone();
+            anthropic_request.betas = betas;
+            anthropic_request.anthropic_version = anthropic_version;
+
+            let provider_config = active.config.providers.iter().find(|p| p.models.contains(&decision.model_name));
+            handoff::normalize(&mut anthropic_request, provider_config);
+
+            // Truncate oversized tool results
+            active.config.tool_result_truncation.apply(&mut anthropic_request);
 
             // Call provider
             let mut provider_response = provider.send_message(anthropic_request)
@@ -776,6 +1875,7 @@ async fn handle_messages(
 
             // Restore original model name in response
             provider_response.model = original_model;
+            log_entry.record_response(&provider_response, log_entry.request_body.is_some());
 
             // Return provider response
             return Ok(Json(provider_response).into_response());
@@ -792,8 +1892,9 @@ async fn handle_messages(
 /// Handle /v1/messages/count_tokens requests
 async fn handle_count_tokens(
     State(state): State<Arc<AppState>>,
-    Json(request_json): Json<serde_json::Value>,
+    StreamedJson(request_json): StreamedJson,
 ) -> Result<Response, AppError> {
+    let active = state.active();
     let model = request_json.get("model").and_then(|m| m.as_str()).unwrap_or("unknown");
     info!("Received count_tokens request for model: {}", model);
 
@@ -809,6 +1910,11 @@ async fn handle_count_tokens(
         max_tokens: 1024, // Dummy value for routing
         system: count_request.system.clone(),
         tools: count_request.tools.clone(),
+        betas: Vec::new(),
+        tool_choice: None,
+        anthropic_version: None,
+        idempotency_key: None,
+        verbosity: None,
         thinking: None,
         temperature: None,
         top_p: None,
@@ -817,7 +1923,7 @@ async fn handle_count_tokens(
         stream: None,
         metadata: None,
     };
-    let decision = state
+    let decision = active
         .router
         .route(&mut routing_request)
         .map_err(|e| AppError::RoutingError(e.to_string()))?;
@@ -828,7 +1934,7 @@ async fn handle_count_tokens(
     );
 
     // 3. Try model mappings with fallback (1:N mapping)
-    if let Some(model_config) = state.config.models.iter().find(|m| m.name == decision.model_name) {
+    if let Some(model_config) = active.model(&decision.model_name) {
         info!("📋 Found {} provider mappings for token counting: {}", model_config.mappings.len(), decision.model_name);
 
         // Sort mappings by priority
@@ -846,7 +1952,7 @@ async fn handle_count_tokens(
             );
 
             // Try to get provider from registry
-            if let Some(provider) = state.provider_registry.get_provider(&mapping.provider) {
+            if let Some(provider) = active.provider_registry.get_provider(&mapping.provider) {
                 // Trust the model mapping configuration - no need to validate
 
                 // Update model to actual model name
@@ -878,7 +1984,7 @@ async fn handle_count_tokens(
         )));
     } else {
         // No model mapping found, try direct provider registry lookup (backward compatibility)
-        if let Ok(provider) = state.provider_registry.get_provider_for_model(&decision.model_name) {
+        if let Ok(provider) = active.provider_registry.get_provider_for_model(&decision.model_name) {
             info!("📦 Using provider from registry (direct lookup) for token counting: {}", decision.model_name);
 
             // Update model to routed model
@@ -908,6 +2014,9 @@ pub enum AppError {
     RoutingError(String),
     ParseError(String),
     ProviderError(String),
+    PayloadTooLarge(String),
+    GuardrailTriggered(String),
+    Cancelled(String),
 }
 
 impl IntoResponse for AppError {
@@ -916,6 +2025,9 @@ impl IntoResponse for AppError {
             AppError::RoutingError(msg) => (StatusCode::BAD_REQUEST, msg),
             AppError::ParseError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
             AppError::ProviderError(msg) => (StatusCode::BAD_GATEWAY, msg),
+            AppError::PayloadTooLarge(msg) => (StatusCode::PAYLOAD_TOO_LARGE, msg),
+            AppError::GuardrailTriggered(msg) => (StatusCode::BAD_REQUEST, msg),
+            AppError::Cancelled(msg) => (StatusCode::SERVICE_UNAVAILABLE, msg),
         };
 
         let body = Json(serde_json::json!({
@@ -935,8 +2047,243 @@ impl std::fmt::Display for AppError {
             AppError::RoutingError(msg) => write!(f, "Routing error: {}", msg),
             AppError::ParseError(msg) => write!(f, "Parse error: {}", msg),
             AppError::ProviderError(msg) => write!(f, "Provider error: {}", msg),
+            AppError::PayloadTooLarge(msg) => write!(f, "Payload too large: {}", msg),
+            AppError::GuardrailTriggered(msg) => write!(f, "{}", msg),
+            AppError::Cancelled(msg) => write!(f, "{}", msg),
         }
     }
 }
 
 impl std::error::Error for AppError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ContentBlock, CountTokensRequest, CountTokensResponse};
+    use crate::providers::pool::{HostGate, HostLimited, Lane};
+    use crate::providers::{AnthropicProvider, FimResponse, Usage};
+    use crate::storage::kv::MemoryKv;
+    use async_trait::async_trait;
+    use std::time::Duration;
+
+    struct Echo;
+
+    #[async_trait]
+    impl AnthropicProvider for Echo {
+        async fn send_message(&self, request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
+            Ok(ProviderResponse {
+                id: "msg_1".to_string(),
+                r#type: "message".to_string(),
+                role: "assistant".to_string(),
+                content: vec![ContentBlock::Text { text: "ok".to_string() }],
+                model: request.model,
+                stop_reason: Some("end_turn".to_string()),
+                stop_sequence: None,
+                usage: Usage::default(),
+            })
+        }
+
+        async fn send_message_stream(
+            &self,
+            _request: AnthropicRequest,
+        ) -> Result<std::pin::Pin<Box<dyn futures::Stream<Item = Result<bytes::Bytes, ProviderError>> + Send>>, ProviderError>
+        {
+            let events = [
+                ("message_start", r#"{"type":"message_start","message":{"usage":{"input_tokens":1000,"output_tokens":1}}}"#),
+                ("content_block_start", r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#),
+                ("content_block_delta", r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"ok"}}"#),
+                ("content_block_stop", r#"{"type":"content_block_stop","index":0}"#),
+                ("message_delta", r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":500}}"#),
+                ("message_stop", r#"{"type":"message_stop"}"#),
+            ];
+            let chunks: Vec<_> = events
+                .iter()
+                .map(|(event, data)| Ok(bytes::Bytes::from(format!("event: {}\ndata: {}\n\n", event, data))))
+                .collect();
+            Ok(Box::pin(futures::stream::iter(chunks)))
+        }
+
+        async fn count_tokens(&self, _request: CountTokensRequest) -> Result<CountTokensResponse, ProviderError> {
+            unimplemented!()
+        }
+
+        fn supports_model(&self, _model: &str) -> bool {
+            true
+        }
+
+        async fn complete_fim(&self, _request: FimRequest) -> Result<FimResponse, ProviderError> {
+            Ok(FimResponse {
+                id: "cmpl_1".to_string(),
+                text: "ok".to_string(),
+                finish_reason: Some("stop".to_string()),
+                usage: Usage::default(),
+            })
+        }
+    }
+
+    fn state(dir: &std::path::Path, gate: Arc<HostGate>) -> Arc<AppState> {
+        let config: AppConfig = toml::from_str(
+            r#"
+[server]
+[router]
+default = "chat"
+completion = "fim"
+
+[[models]]
+name = "chat"
+[[models.mappings]]
+provider = "mock"
+actual_model = "m"
+priority = 1
+input_cost_per_mtok = 1.0
+output_cost_per_mtok = 2.0
+
+[[models]]
+name = "fim"
+[[models.mappings]]
+provider = "mock"
+actual_model = "m"
+priority = 1
+fim = "native"
+"#,
+        )
+        .unwrap();
+        let mut registry = ProviderRegistry::new();
+        registry.insert("mock", Box::new(HostLimited::new(Box::new(Echo), gate)));
+        let shared: Arc<dyn KvStore> = Arc::new(MemoryKv::default());
+        Arc::new(AppState {
+            active: Arc::new(RwLock::new(Arc::new(ActiveConfig {
+                router: Router::new(config.clone()),
+                provider_registry: Arc::new(registry),
+                model_overrides: Default::default(),
+                config: config.clone(),
+            }))),
+            token_store: TokenStore::new(dir.join("tokens.json")).unwrap(),
+            api_keys: ApiKeyStore::new(dir.join("keys.json")).unwrap(),
+            audit_log: AuditLog::new(dir.join("audit.jsonl")),
+            oidc: None,
+            config_path: dir.join("config.toml"),
+            blob_store: None,
+            request_log: RequestLog::new(),
+            alerter: Alerter::new(config.alerting.clone(), shared.clone()),
+            usage_store: None,
+            health_store: None,
+            score_store: None,
+            judge: None,
+            shared,
+            stream_metrics: Arc::new(StreamMetrics::default()),
+            model_stats: Arc::new(ModelStats::default()),
+            payload_stats: Arc::new(PayloadStats::default()),
+            inflight_bodies: Arc::new(InflightBodies::default()),
+            semantic_cache: None,
+            billing: None,
+            trace_exporter: None,
+            archive: None,
+            inflight: Arc::new(InflightRequests::default()),
+            session_pins: Arc::new(SessionPins::default()),
+            session_limits: Arc::new(SessionLimits::default()),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_streamed_usage_is_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = state(dir.path(), Arc::new(HostGate::new(2, 1)));
+        let request = serde_json::json!({
+            "model": "claude-sonnet-4-5", "max_tokens": 1000, "stream": true,
+            "messages": [{ "role": "user", "content": "hi" }],
+        });
+        let response = run_messages(&state, &HeaderMap::new(), "/v1/messages", request, None).await.unwrap();
+        // Nothing is recorded until the client has read the stream
+        assert!(state.request_log.recent().is_empty());
+        axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        let mut recent = Vec::new();
+        for _ in 0..100 {
+            recent = state.request_log.recent();
+            if !recent.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(recent.len(), 1);
+        assert_eq!((recent[0].input_tokens, recent[0].output_tokens), (Some(1000), Some(500)));
+        assert_eq!(recent[0].cost_usd, Some(0.002));
+    }
+
+    #[tokio::test]
+    async fn test_openai_compat_lane() {
+        let dir = tempfile::tempdir().unwrap();
+        let gate = Arc::new(HostGate::new(2, 1));
+        let state = state(dir.path(), gate.clone());
+        // Background traffic holds every slot it may take
+        let _background = gate.acquire(Lane::Background).await;
+
+        let chat = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4", "messages": [{ "role": "user", "content": "hi" }],
+        }))
+        .unwrap();
+        let response = tokio::time::timeout(
+            Duration::from_secs(1),
+            handle_openai_chat_completions(State(state.clone()), HeaderMap::new(), Json(chat)),
+        )
+        .await
+        .expect("chat completions waited in the background lane")
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let completion = serde_json::from_value(serde_json::json!({ "model": "gpt-4", "prompt": "fn main" })).unwrap();
+        let response = tokio::time::timeout(
+            Duration::from_secs(1),
+            handle_openai_completions(State(state.clone()), HeaderMap::new(), Json(completion)),
+        )
+        .await
+        .expect("FIM completion waited in the background lane")
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Requests marked as background still queue behind it
+        let chat = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4", "messages": [{ "role": "user", "content": "hi" }],
+        }))
+        .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ccm-priority", HeaderValue::from_static("background"));
+        let blocked = tokio::time::timeout(
+            Duration::from_millis(50),
+            handle_openai_chat_completions(State(state), headers, Json(chat)),
+        )
+        .await;
+        assert!(blocked.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_compression_layers() {
+        use axum::body::Body;
+        use axum::http::{header, Request};
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+        use tower::Service;
+
+        let router = || {
+            AxumRouter::new().route("/", post(|body: String| async move { body.repeat(8) }))
+        };
+        let request = || {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(b"hello compressed world ").unwrap();
+            Request::post("/")
+                .header(header::CONTENT_ENCODING, "gzip")
+                .header(header::ACCEPT_ENCODING, "gzip")
+                .body(Body::from(encoder.finish().unwrap()))
+                .unwrap()
+        };
+
+        let response = compression(router(), true).call(request()).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+
+        let response = compression(router(), false).call(request()).await.unwrap();
+        assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "hello compressed world ".repeat(8));
+    }
+}
@@ -0,0 +1,216 @@
+//! Alias overrides from `model_map.txt`, layered over the TOML mappings
+//!
+//! Each line is `alias=provider/model` (or `alias=provider` to keep the
+//! alias's own mapping for that provider); `#` starts a comment. The file sits
+//! next to the config file and is re-read whenever it changes, so scripts can
+//! flip an alias's backend with a one-line write.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+use crate::cli::{AppConfig, ModelConfig, ModelMapping};
+
+use super::config_reload::ActiveConfig;
+
+const FILE_NAME: &str = "model_map.txt";
+
+/// How often the file is checked for changes
+const POLL: Duration = Duration::from_secs(2);
+
+/// Where an alias is sent instead of its TOML mappings
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelOverride {
+    pub provider: String,
+    /// Model to request (default: the alias's mapping for the provider)
+    pub model: Option<String>,
+}
+
+/// Overrides by alias
+pub type ModelOverrides = BTreeMap<String, ModelOverride>;
+
+/// `model_map.txt` next to the config file
+pub fn path(config_path: &Path) -> PathBuf {
+    config_path.with_file_name(FILE_NAME)
+}
+
+/// Parse override lines, skipping (and logging) malformed ones
+pub fn parse(content: &str) -> ModelOverrides {
+    let mut overrides = ModelOverrides::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some((alias, target)) = line.split_once('=') else {
+            warn!("{} line {}: expected alias=provider/model", FILE_NAME, number + 1);
+            continue;
+        };
+        // Model names may contain slashes themselves (e.g. OpenRouter's)
+        let (provider, model) = match target.trim().split_once('/') {
+            Some((provider, model)) => (provider.trim(), Some(model.trim().to_string())),
+            None => (target.trim(), None),
+        };
+        if alias.trim().is_empty() || provider.is_empty() || model.as_deref() == Some("") {
+            warn!("{} line {}: expected alias=provider/model", FILE_NAME, number + 1);
+            continue;
+        }
+        overrides.insert(
+            alias.trim().to_string(),
+            ModelOverride {
+                provider: provider.to_string(),
+                model,
+            },
+        );
+    }
+    overrides
+}
+
+/// Read the overrides file, dropping overrides the config can't serve
+///
+/// A missing file means no overrides.
+pub fn load(path: &Path, config: &AppConfig) -> ModelOverrides {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return ModelOverrides::new(),
+        Err(e) => {
+            warn!("Failed to read {}: {}", path.display(), e);
+            return ModelOverrides::new();
+        }
+    };
+    let mut overrides = parse(&content);
+    overrides.retain(|alias, o| {
+        if !config.providers.iter().any(|p| p.name == o.provider && p.is_enabled()) {
+            warn!("{}: '{}' maps to unknown or disabled provider '{}'", FILE_NAME, alias, o.provider);
+            return false;
+        }
+        let mapped = config
+            .models
+            .iter()
+            .any(|m| &m.name == alias && m.mappings.iter().any(|m| m.provider == o.provider));
+        if o.model.is_none() && !mapped {
+            warn!("{}: '{}' has no mapping to '{}', give a model", FILE_NAME, alias, o.provider);
+            return false;
+        }
+        true
+    });
+    overrides
+}
+
+/// An alias's model config with its override applied
+///
+/// An override replaces the alias's mappings with a single one to the
+/// override's provider, keeping that mapping's costs and policies when the
+/// alias already has one. Aliases that only exist in the file are created.
+pub fn resolve<'a>(models: &'a [ModelConfig], overrides: &ModelOverrides, name: &str) -> Option<Cow<'a, ModelConfig>> {
+    let model = models.iter().find(|m| m.name == name);
+    let Some(o) = overrides.get(name) else {
+        return model.map(Cow::Borrowed);
+    };
+
+    let mut mappings: Vec<&ModelMapping> = model
+        .map(|m| m.mappings.iter().filter(|m| m.provider == o.provider).collect())
+        .unwrap_or_default();
+    mappings.sort_by_key(|m| m.priority);
+    let mut mapping = match (mappings.first(), &o.model) {
+        (Some(mapping), _) => (*mapping).clone(),
+        (None, Some(actual_model)) => ModelMapping {
+            priority: 1,
+            provider: o.provider.clone(),
+            actual_model: actual_model.clone(),
+            tool_policy: None,
+            input_cost_per_mtok: None,
+            output_cost_per_mtok: None,
+            cached_input_cost_per_mtok: None,
+            fim: None,
+            continuation: None,
+        },
+        (None, None) => return model.map(Cow::Borrowed),
+    };
+    if let Some(actual_model) = &o.model {
+        mapping.actual_model = actual_model.clone();
+    }
+
+    let mut config = model.cloned().unwrap_or_else(|| ModelConfig {
+        name: name.to_string(),
+        mappings: Vec::new(),
+        ttft_slo_ms: None,
+        verbosity: None,
+        max_tokens: None,
+        refusal_retry: None,
+        max_output_tokens_per_sec: None,
+        sampling: None,
+    });
+    config.mappings = vec![mapping];
+    Some(Cow::Owned(config))
+}
+
+/// Re-read the overrides file when it changes and swap them into the active config
+pub fn spawn_watcher(path: PathBuf, active: Arc<RwLock<Arc<ActiveConfig>>>) {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    tokio::spawn(async move {
+        let mut last: Option<SystemTime> = modified(&path);
+        let mut ticker = tokio::time::interval(POLL);
+        loop {
+            ticker.tick().await;
+            let modified = modified(&path);
+            if modified == last {
+                continue;
+            }
+            last = modified;
+
+            let current = active.read().unwrap_or_else(|e| e.into_inner()).clone();
+            let overrides = load(&path, &current.config);
+            if overrides == current.model_overrides {
+                continue;
+            }
+            info!("🗺️  {} changed: {} alias overrides", FILE_NAME, overrides.len());
+            let mut active = active.write().unwrap_or_else(|e| e.into_inner());
+            *active = Arc::new(ActiveConfig {
+                config: active.config.clone(),
+                router: active.router.clone(),
+                provider_registry: active.provider_registry.clone(),
+                model_overrides: overrides,
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_overrides() {
+        let overrides = parse(
+            "# flipped by scripts\nsonnet = openrouter/qwen/qwen3-coder\nhaiku=zai  # keep glm\nbroken\nopus=/x\n",
+        );
+        assert_eq!(overrides.len(), 2);
+        assert_eq!(overrides["sonnet"].model.as_deref(), Some("qwen/qwen3-coder"));
+        assert_eq!(overrides["haiku"], ModelOverride { provider: "zai".to_string(), model: None });
+
+        let models: Vec<ModelConfig> = serde_json::from_value(serde_json::json!([{
+            "name": "haiku",
+            "max_tokens": 4096,
+            "mappings": [
+                { "priority": 1, "provider": "anthropic", "actual_model": "claude-haiku-4-5" },
+                { "priority": 2, "provider": "zai", "actual_model": "glm-4.5-air", "input_cost_per_mtok": 0.2 },
+            ],
+        }]))
+        .unwrap();
+
+        let haiku = resolve(&models, &overrides, "haiku").unwrap();
+        assert_eq!(haiku.mappings.len(), 1);
+        assert_eq!(haiku.mappings[0].actual_model, "glm-4.5-air");
+        assert_eq!(haiku.mappings[0].input_cost_per_mtok, Some(0.2));
+        assert_eq!(haiku.max_tokens, Some(4096));
+
+        let sonnet = resolve(&models, &overrides, "sonnet").unwrap();
+        assert_eq!(sonnet.mappings[0].provider, "openrouter");
+        assert!(resolve(&models, &overrides, "opus").is_none());
+        assert!(matches!(resolve(&models, &ModelOverrides::new(), "haiku"), Some(Cow::Borrowed(_))));
+    }
+}
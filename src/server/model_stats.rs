@@ -0,0 +1,336 @@
+//! Time to first token and generation throughput per provider model
+//!
+//! Measured on streamed responses, where total latency mostly reflects output
+//! length. Recent samples are kept in memory and summarized as percentiles.
+
+use axum::{
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use bytes::Bytes;
+use futures::stream::{Stream, StreamExt};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::providers::error::ProviderError;
+use crate::providers::streaming::{parse_sse_events, LineBuffer};
+
+use super::request_log::StreamUsage;
+use super::AppState;
+
+/// Samples kept per provider model
+const WINDOW: usize = 1000;
+
+const QUANTILES: [f64; 3] = [0.5, 0.9, 0.99];
+
+type ByteStream = Pin<Box<dyn Stream<Item = Result<Bytes, ProviderError>> + Send>>;
+
+#[derive(Debug, Default)]
+struct Samples {
+    ttft_ms: VecDeque<f64>,
+    tokens_per_sec: VecDeque<f64>,
+}
+
+fn push(samples: &mut VecDeque<f64>, value: f64) {
+    if samples.len() == WINDOW {
+        samples.pop_front();
+    }
+    samples.push_back(value);
+}
+
+/// Recent stream timings by (provider, model)
+#[derive(Debug, Default)]
+pub struct ModelStats {
+    samples: Mutex<BTreeMap<(String, String), Samples>>,
+}
+
+/// p50/p90/p99 of a metric
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+}
+
+impl Percentiles {
+    fn of(samples: &VecDeque<f64>) -> Option<Self> {
+        let mut sorted: Vec<f64> = samples.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let [p50, p90, p99] = QUANTILES.map(|q| quantile(&sorted, q));
+        Some(Self {
+            p50: p50?,
+            p90: p90?,
+            p99: p99?,
+        })
+    }
+}
+
+/// Nearest-rank quantile of sorted samples
+fn quantile(sorted: &[f64], q: f64) -> Option<f64> {
+    let rank = ((q * sorted.len() as f64).ceil() as usize).max(1);
+    sorted.get(rank - 1).copied()
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModelStatsEntry {
+    pub provider: String,
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttft_ms: Option<Percentiles>,
+    /// Output tokens per second after the first token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokens_per_sec: Option<Percentiles>,
+    pub samples: usize,
+}
+
+impl ModelStats {
+    fn record(&self, provider: &str, model: &str, update: impl FnOnce(&mut Samples)) {
+        let mut samples = self.samples.lock().unwrap();
+        update(
+            samples
+                .entry((provider.to_string(), model.to_string()))
+                .or_default(),
+        );
+    }
+
+    pub fn record_ttft(&self, provider: &str, model: &str, ttft: Duration) {
+        self.record(provider, model, |s| {
+            push(&mut s.ttft_ms, ttft.as_secs_f64() * 1000.0)
+        });
+    }
+
+    pub fn record_throughput(
+        &self,
+        provider: &str,
+        model: &str,
+        output_tokens: u64,
+        generating: Duration,
+    ) {
+        if output_tokens == 0 || generating.is_zero() {
+            return;
+        }
+        let rate = output_tokens as f64 / generating.as_secs_f64();
+        self.record(provider, model, |s| push(&mut s.tokens_per_sec, rate));
+    }
+
+    pub fn snapshot(&self) -> Vec<ModelStatsEntry> {
+        self.samples
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((provider, model), samples)| ModelStatsEntry {
+                provider: provider.clone(),
+                model: model.clone(),
+                ttft_ms: Percentiles::of(&samples.ttft_ms),
+                tokens_per_sec: Percentiles::of(&samples.tokens_per_sec),
+                samples: samples.ttft_ms.len(),
+            })
+            .collect()
+    }
+
+    /// Prometheus text exposition of the percentiles
+    pub fn prometheus(&self) -> String {
+        let snapshot = self.snapshot();
+        let mut out = String::new();
+        write_summary(
+            &mut out,
+            "ccm_ttft_seconds",
+            "Time to first token of streamed responses",
+            snapshot
+                .iter()
+                .filter_map(|e| Some((e, e.ttft_ms.as_ref()?))),
+            0.001,
+        );
+        write_summary(
+            &mut out,
+            "ccm_tokens_per_second",
+            "Output tokens per second after the first token",
+            snapshot
+                .iter()
+                .filter_map(|e| Some((e, e.tokens_per_sec.as_ref()?))),
+            1.0,
+        );
+        out
+    }
+}
+
+/// One summary metric, with values multiplied by `scale`
+fn write_summary<'a>(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    entries: impl Iterator<Item = (&'a ModelStatsEntry, &'a Percentiles)>,
+    scale: f64,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} summary", name);
+    for (entry, p) in entries {
+        let labels = format!(
+            "provider=\"{}\",model=\"{}\"",
+            escape(&entry.provider),
+            escape(&entry.model)
+        );
+        for (q, value) in QUANTILES.iter().zip([p.p50, p.p90, p.p99]) {
+            let _ = writeln!(
+                out,
+                "{}{{{},quantile=\"{}\"}} {}",
+                name,
+                labels,
+                q,
+                value * scale
+            );
+        }
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Pass a stream through, timing its first content delta and the output rate
+///
+/// `sent` is when the request went upstream.
+pub fn observe(
+    stream: ByteStream,
+    stats: Arc<ModelStats>,
+    usage: Arc<StreamUsage>,
+    provider: &str,
+    model: &str,
+    sent: Instant,
+) -> ByteStream {
+    struct Observed {
+        stream: ByteStream,
+        stats: Arc<ModelStats>,
+        usage: Arc<StreamUsage>,
+        provider: String,
+        model: String,
+        sent: Instant,
+        first_token: Option<Instant>,
+        output_tokens: u64,
+        buffer: LineBuffer,
+    }
+
+    let observed = Observed {
+        stream,
+        stats,
+        usage,
+        provider: provider.to_string(),
+        model: model.to_string(),
+        sent,
+        first_token: None,
+        output_tokens: 0,
+        buffer: LineBuffer::default(),
+    };
+    Box::pin(futures::stream::unfold(observed, |mut o| async move {
+        let Some(item) = o.stream.next().await else {
+            if let Some(first_token) = o.first_token {
+                o.stats.record_throughput(
+                    &o.provider,
+                    &o.model,
+                    o.output_tokens,
+                    first_token.elapsed(),
+                );
+            }
+            return None;
+        };
+        if let Ok(bytes) = &item {
+            o.buffer.push(bytes);
+            while let Some(raw) = o.buffer.event() {
+                for event in parse_sse_events(&raw) {
+                    match event.event.as_deref() {
+                        Some("content_block_delta") if o.first_token.is_none() => {
+                            o.first_token = Some(Instant::now());
+                            o.stats.record_ttft(&o.provider, &o.model, o.sent.elapsed());
+                        }
+                        Some("message_start") => {
+                            let data = serde_json::from_str(&event.data).unwrap_or_default();
+                            o.usage.observe("message_start", &data);
+                        }
+                        Some("message_delta") => {
+                            let data: serde_json::Value =
+                                serde_json::from_str(&event.data).unwrap_or_default();
+                            if let Some(tokens) = data
+                                .pointer("/usage/output_tokens")
+                                .and_then(|t| t.as_u64())
+                            {
+                                o.output_tokens = tokens;
+                            }
+                            o.usage.observe("message_delta", &data);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Some((item, o))
+    }))
+}
+
+/// GET /v1/stats/models - TTFT and throughput percentiles per provider model
+pub async fn model_stats(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "models": state.model_stats.snapshot() }))
+}
+
+/// GET /metrics - Prometheus metrics
+pub async fn metrics(State(state): State<Arc<AppState>>) -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.model_stats.prometheus() + &state.payload_stats.prometheus(),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_observe_stream() {
+        let chunks: Vec<Result<Bytes, ProviderError>> = vec![
+            Ok(Bytes::from(
+                "event: message_start\ndata: {\"message\":{\"usage\":{\"input_tokens\":7}}}\n\nevent: content_block_delta\n",
+            )),
+            Ok(Bytes::from("data: {\"delta\":{\"text\":\"hi\"}}\n\n")),
+            Ok(Bytes::from(
+                "event: message_delta\ndata: {\"usage\":{\"output_tokens\":42}}\n\nevent: message_stop\ndata: {}\n\n",
+            )),
+        ];
+        let stats = Arc::new(ModelStats::default());
+        let usage = Arc::new(StreamUsage::default());
+        let stream = observe(
+            Box::pin(futures::stream::iter(chunks)),
+            stats.clone(),
+            usage.clone(),
+            "zai",
+            "glm-4.6",
+            Instant::now() - Duration::from_millis(250),
+        );
+        assert_eq!(stream.collect::<Vec<_>>().await.len(), 3);
+        assert_eq!((usage.get().input_tokens, usage.get().output_tokens), (7, 42));
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.len(), 1);
+        assert!(snapshot[0].ttft_ms.as_ref().unwrap().p50 >= 250.0);
+        assert!(snapshot[0].tokens_per_sec.is_some());
+        assert!(stats
+            .prometheus()
+            .contains("ccm_ttft_seconds{provider=\"zai\",model=\"glm-4.6\",quantile=\"0.5\"}"));
+
+        let samples: VecDeque<f64> = (1..=100).map(f64::from).collect();
+        assert_eq!(
+            Percentiles::of(&samples),
+            Some(Percentiles {
+                p50: 50.0,
+                p90: 90.0,
+                p99: 99.0
+            })
+        );
+        assert_eq!(Percentiles::of(&VecDeque::new()), None);
+    }
+}
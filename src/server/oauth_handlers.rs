@@ -1,6 +1,6 @@
 use axum::{
     extract::{Query, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::{Html, IntoResponse},
     Json,
 };
@@ -8,8 +8,9 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 use crate::auth::{OAuthClient, OAuthConfig, TokenStore};
+use crate::storage::AuditEntry;
 
-use super::AppState;
+use super::{audit, AppState};
 
 /// Request to start OAuth authorization flow
 #[derive(Debug, Deserialize)]
@@ -108,6 +109,7 @@ pub async fn oauth_authorize(
 /// Exchange authorization code for tokens
 pub async fn oauth_exchange(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Json(req): Json<OAuthExchangeRequest>,
 ) -> Result<Json<OAuthExchangeResponse>, (StatusCode, String)> {
     tracing::info!("📥 OAuth exchange request: provider_id={}, oauth_type={:?}",
@@ -165,6 +167,11 @@ pub async fn oauth_exchange(
                         StatusCode::INTERNAL_SERVER_ERROR,
                         format!("Failed to save token with project_id: {}", e)
                     ))?;
+                state.token_store.publish_overwrite(&token).await
+                    .map_err(|e| (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        format!("Failed to share token with project_id: {}", e)
+                    ))?;
             }
             Err(e) => {
                 tracing::warn!("⚠️ No project ID available: {}", e);
@@ -175,6 +182,10 @@ pub async fn oauth_exchange(
         }
     }
 
+    state.audit_log.record(
+        AuditEntry::new(audit::actor(&headers), "oauth.import").target(req.provider_id.clone()),
+    );
+
     Ok(Json(OAuthExchangeResponse {
         success: true,
         message: "OAuth authentication successful! Token saved.".to_string(),
@@ -210,6 +221,7 @@ pub struct DeleteTokenRequest {
 
 pub async fn oauth_delete_token(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Json(req): Json<DeleteTokenRequest>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     state.token_store
@@ -218,6 +230,16 @@ pub async fn oauth_delete_token(
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("Failed to delete token: {}", e)
         ))?;
+    state.token_store
+        .unpublish(&req.provider_id)
+        .await
+        .map_err(|e| (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to delete shared token: {}", e)
+        ))?;
+    state.audit_log.record(
+        AuditEntry::new(audit::actor(&headers), "oauth.delete").target(req.provider_id.clone()),
+    );
 
     Ok(Json(serde_json::json!({
         "success": true,
@@ -228,19 +250,11 @@ pub async fn oauth_delete_token(
 /// Refresh a token manually (for testing/debugging)
 pub async fn oauth_refresh_token(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Json(req): Json<DeleteTokenRequest>,
 ) -> Result<Json<OAuthExchangeResponse>, (StatusCode, String)> {
     // Determine OAuth config based on provider_id
-    let config = if req.provider_id.to_lowercase().contains("openai") ||
-                     req.provider_id.to_lowercase().contains("codex") ||
-                     req.provider_id.to_lowercase().contains("chatgpt") {
-        OAuthConfig::openai_codex()
-    } else if req.provider_id.to_lowercase().contains("gemini") ||
-              req.provider_id.to_lowercase().contains("google") {
-        OAuthConfig::gemini()
-    } else {
-        OAuthConfig::anthropic()
-    };
+    let config = OAuthConfig::for_provider_id(&req.provider_id);
 
     let oauth_client = OAuthClient::new(config, state.token_store.clone());
 
@@ -251,6 +265,9 @@ pub async fn oauth_refresh_token(
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("Failed to refresh token: {}", e)
         ))?;
+    state.audit_log.record(
+        AuditEntry::new(audit::actor(&headers), "oauth.refresh").target(req.provider_id.clone()),
+    );
 
     Ok(Json(OAuthExchangeResponse {
         success: true,
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
-use crate::models::{AnthropicRequest, MessageContent, ContentBlock, SystemPrompt};
-use crate::providers::ProviderResponse;
+use crate::models::{AnthropicRequest, MessageContent, ContentBlock, SystemPrompt, Tool, ToolResultContent, Verbosity};
+use crate::providers::streaming::{parse_sse_events, LineBuffer};
+use crate::providers::{FimResponse, ProviderResponse};
 
 /// OpenAI Chat Completions request format
 #[derive(Debug, Deserialize)]
@@ -9,6 +10,11 @@ pub struct OpenAIRequest {
     pub messages: Vec<OpenAIMessage>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<u32>,
+    /// Newer name of `max_tokens` (takes precedence)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_completion_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verbosity: Option<Verbosity>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -21,6 +27,14 @@ pub struct OpenAIRequest {
     pub tools: Option<Vec<serde_json::Value>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_choice: Option<serde_json::Value>,
+    #[serde(default)]
+    pub stream_options: Option<StreamOptions>,
+    /// Deprecated form of `tools` (older LangChain versions and plugins)
+    #[serde(default)]
+    pub functions: Option<Vec<serde_json::Value>>,
+    /// Deprecated form of `tool_choice`
+    #[serde(default)]
+    pub function_call: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -30,6 +44,100 @@ pub struct OpenAIMessage {
     pub content: Option<OpenAIContent>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    /// Calls made by an assistant message
+    #[serde(default)]
+    pub tool_calls: Option<Vec<OpenAIToolCall>>,
+    /// Call a `tool` message answers
+    #[serde(default)]
+    pub tool_call_id: Option<String>,
+    /// Deprecated form of `tool_calls` (a single call)
+    #[serde(default)]
+    pub function_call: Option<OpenAIFunctionCall>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OpenAIToolCall {
+    pub id: String,
+    #[serde(rename = "type", default = "function_type")]
+    pub r#type: String,
+    pub function: OpenAIFunctionCall,
+}
+
+fn function_type() -> String {
+    "function".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OpenAIFunctionCall {
+    pub name: String,
+    /// JSON-encoded arguments
+    #[serde(default)]
+    pub arguments: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct StreamOptions {
+    /// Send a final chunk with the usage of the whole response
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
+impl OpenAIRequest {
+    /// Whether the client uses the deprecated `functions` API (and expects
+    /// `function_call` in the response)
+    pub fn uses_legacy_functions(&self) -> bool {
+        self.functions.is_some() && self.tools.is_none()
+    }
+
+    /// Rewrite deprecated `functions`/`function_call` fields and `function`
+    /// messages into their tools equivalents
+    fn normalize_legacy_functions(&mut self) {
+        if let Some(functions) = self.functions.take() {
+            if self.tools.is_none() {
+                self.tools = Some(
+                    functions
+                        .into_iter()
+                        .map(|function| serde_json::json!({ "type": "function", "function": function }))
+                        .collect(),
+                );
+            }
+        }
+        if let Some(function_call) = self.function_call.take() {
+            if self.tool_choice.is_none() {
+                self.tool_choice = Some(match function_call {
+                    serde_json::Value::Object(call) => serde_json::json!({
+                        "type": "function",
+                        "function": { "name": call.get("name").cloned().unwrap_or_default() },
+                    }),
+                    mode => mode,
+                });
+            }
+        }
+
+        // Legacy calls have no ids: number them and answer them in order
+        let mut unanswered: Vec<(String, String)> = Vec::new();
+        for (index, message) in self.messages.iter_mut().enumerate() {
+            if let Some(call) = message.function_call.take() {
+                let id = format!("call_{}", index);
+                unanswered.push((call.name.clone(), id.clone()));
+                message.tool_calls.get_or_insert_with(Vec::new).push(OpenAIToolCall {
+                    id,
+                    r#type: function_type(),
+                    function: call,
+                });
+            }
+            if message.role == "function" {
+                let name = message.name.take().unwrap_or_default();
+                let position = unanswered.iter().position(|(called, _)| *called == name);
+                let id = match position {
+                    Some(position) => unanswered.remove(position).1,
+                    None => format!("call_{}", index),
+                };
+                message.role = "tool".to_string();
+                message.tool_call_id = Some(id);
+            }
+        }
+    }
 }
 
 /// Content can be string or array of content parts
@@ -48,6 +156,9 @@ pub enum OpenAIContentPart {
     Text { text: String },
     #[serde(rename = "image_url")]
     ImageUrl { image_url: OpenAIImageUrl },
+    /// Part types without an Anthropic equivalent (ignored)
+    #[serde(other)]
+    Unsupported,
 }
 
 /// Image URL object
@@ -79,6 +190,11 @@ pub struct OpenAIChoice {
 pub struct OpenAIResponseMessage {
     pub role: String,
     pub content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<OpenAIToolCall>>,
+    /// The first call, for clients of the deprecated `functions` API
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function_call: Option<OpenAIFunctionCall>,
 }
 
 #[derive(Debug, Serialize)]
@@ -89,15 +205,17 @@ pub struct OpenAIUsage {
 }
 
 /// Transform OpenAI request to Anthropic format
-pub fn transform_openai_to_anthropic(openai_req: OpenAIRequest) -> Result<AnthropicRequest, String> {
-    let mut messages = Vec::new();
+pub fn transform_openai_to_anthropic(mut openai_req: OpenAIRequest) -> Result<AnthropicRequest, String> {
+    openai_req.normalize_legacy_functions();
+    let mut messages: Vec<crate::models::Message> = Vec::new();
     let mut system_prompt: Option<SystemPrompt> = None;
 
     // Process messages
     for msg in openai_req.messages {
         match msg.role.as_str() {
-            "system" => {
-                // Extract system message
+            // Newer OpenAI models call the system role "developer"
+            "system" | "developer" => {
+                // Extract system message; several system messages are concatenated
                 if let Some(content) = msg.content {
                     let text = match content {
                         OpenAIContent::String(s) => s,
@@ -114,6 +232,13 @@ pub fn transform_openai_to_anthropic(openai_req: OpenAIRequest) -> Result<Anthro
                                 .join("\n")
                         }
                     };
+                    let text = match (system_prompt.take(), msg.name) {
+                        (Some(SystemPrompt::Text(existing)), _) if text.is_empty() => existing,
+                        (Some(SystemPrompt::Text(existing)), name) => {
+                            format!("{}\n\n{}", existing, with_name(name.as_deref(), &text))
+                        }
+                        (_, name) => with_name(name.as_deref(), &text),
+                    };
                     system_prompt = Some(SystemPrompt::Text(text));
                 }
             }
@@ -172,6 +297,7 @@ pub fn transform_openai_to_anthropic(openai_req: OpenAIRequest) -> Result<Anthro
                                                 })
                                             }
                                         }
+                                        OpenAIContentPart::Unsupported => None,
                                     }
                                 })
                                 .collect();
@@ -187,22 +313,92 @@ pub fn transform_openai_to_anthropic(openai_req: OpenAIRequest) -> Result<Anthro
                     MessageContent::Text(String::new())
                 };
 
+                // Multi-party chats identify speakers via `name`
+                let content = match (msg.name.as_deref(), content) {
+                    (Some(name), MessageContent::Text(text)) => {
+                        MessageContent::Text(with_name(Some(name), &text))
+                    }
+                    (Some(name), MessageContent::Blocks(mut blocks)) => {
+                        blocks.insert(0, ContentBlock::Text { text: format!("{}:", name) });
+                        MessageContent::Blocks(blocks)
+                    }
+                    (None, content) => content,
+                };
+
+                // Assistant tool calls become tool_use blocks
+                let calls = msg.tool_calls.unwrap_or_default();
+                let content = if calls.is_empty() {
+                    content
+                } else {
+                    let mut blocks = match content {
+                        MessageContent::Text(text) if text.is_empty() => Vec::new(),
+                        MessageContent::Text(text) => vec![ContentBlock::Text { text }],
+                        MessageContent::Blocks(blocks) => blocks,
+                    };
+                    for call in calls {
+                        let input = serde_json::from_str(&call.function.arguments)
+                            .map_err(|e| format!("Invalid arguments for {}: {}", call.function.name, e))?;
+                        blocks.push(ContentBlock::ToolUse {
+                            id: call.id,
+                            name: call.function.name,
+                            input,
+                        });
+                    }
+                    MessageContent::Blocks(blocks)
+                };
+
                 messages.push(crate::models::Message {
                     role: msg.role,
                     content,
                 });
             }
+            "tool" => {
+                let text = match msg.content {
+                    Some(OpenAIContent::String(text)) => text,
+                    Some(OpenAIContent::Parts(parts)) => parts
+                        .iter()
+                        .filter_map(|p| match p {
+                            OpenAIContentPart::Text { text } => Some(text.as_str()),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                    None => String::new(),
+                };
+                let result = ContentBlock::ToolResult {
+                    tool_use_id: msg.tool_call_id.ok_or("tool message without tool_call_id")?,
+                    content: ToolResultContent::Text(text),
+                };
+                // Results of parallel calls go back in one user turn
+                match messages.last_mut() {
+                    Some(last) if last.role == "user" && is_tool_results(&last.content) => {
+                        if let MessageContent::Blocks(blocks) = &mut last.content {
+                            blocks.push(result);
+                        }
+                    }
+                    _ => messages.push(crate::models::Message {
+                        role: "user".to_string(),
+                        content: MessageContent::Blocks(vec![result]),
+                    }),
+                }
+            }
             _ => {
-                // Skip other roles (tool, function, etc.)
+                // Skip other roles
                 tracing::warn!("Skipping unsupported message role: {}", msg.role);
             }
         }
     }
 
+    // Anthropic requests can't forbid tool use; leave the tools out instead
+    let tools = match openai_req.tool_choice.as_ref().and_then(|c| c.as_str()) {
+        Some("none") => None,
+        _ => openai_req.tools.map(|tools| tools.iter().filter_map(function_tool).collect()),
+    };
+
     Ok(AnthropicRequest {
         model: openai_req.model,
         messages,
-        max_tokens: openai_req.max_tokens.unwrap_or(4096),
+        max_tokens: openai_req.max_completion_tokens.or(openai_req.max_tokens).unwrap_or(4096),
         thinking: None,
         temperature: openai_req.temperature,
         top_p: openai_req.top_p,
@@ -211,14 +407,252 @@ pub fn transform_openai_to_anthropic(openai_req: OpenAIRequest) -> Result<Anthro
         stream: openai_req.stream,
         metadata: None,
         system: system_prompt,
-        tools: None, // TODO: Transform tools if needed
+        tools,
+        betas: Vec::new(),
+        tool_choice: None,
+        anthropic_version: None,
+        idempotency_key: None,
+        verbosity: openai_req.verbosity,
+    })
+}
+
+/// Anthropic tool for an OpenAI `{"type": "function", "function": {...}}` tool
+fn function_tool(tool: &serde_json::Value) -> Option<Tool> {
+    let function = tool.get("function")?;
+    Some(Tool {
+        r#type: None,
+        name: Some(function.get("name")?.as_str()?.to_string()),
+        description: function.get("description").and_then(|d| d.as_str()).map(str::to_string),
+        input_schema: Some(
+            function
+                .get("parameters")
+                .cloned()
+                .unwrap_or_else(|| serde_json::json!({ "type": "object", "properties": {} })),
+        ),
+        strict: function.get("strict").and_then(|s| s.as_bool()),
     })
 }
 
+fn is_tool_results(content: &MessageContent) -> bool {
+    matches!(content, MessageContent::Blocks(blocks)
+        if blocks.iter().all(|b| matches!(b, ContentBlock::ToolResult { .. })))
+}
+
+/// Legacy OpenAI Completions request format
+#[derive(Debug, Deserialize)]
+pub struct CompletionRequest {
+    pub model: String,
+    pub prompt: serde_json::Value,
+    #[serde(default)]
+    pub suffix: Option<String>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    /// Single string or list of stop sequences
+    #[serde(default)]
+    pub stop: Option<serde_json::Value>,
+    /// Include the prompt in the returned text
+    #[serde(default)]
+    pub echo: Option<bool>,
+}
+
+impl CompletionRequest {
+    /// The prompt as text (batched and token-array prompts aren't supported)
+    pub fn prompt_text(&self) -> Result<String, String> {
+        match &self.prompt {
+            serde_json::Value::String(prompt) => Ok(prompt.clone()),
+            serde_json::Value::Array(items) if items.len() == 1 => items[0]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| "Token array prompts are not supported".to_string()),
+            serde_json::Value::Array(_) => Err("Batched prompts are not supported".to_string()),
+            _ => Err("prompt must be a string".to_string()),
+        }
+    }
+}
+
+/// Legacy OpenAI Completions response format
+#[derive(Debug, Serialize)]
+pub struct CompletionResponse {
+    pub id: String,
+    pub object: String,
+    pub created: u64,
+    pub model: String,
+    pub choices: Vec<CompletionChoice>,
+    pub usage: OpenAIUsage,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CompletionChoice {
+    pub text: String,
+    pub index: u32,
+    pub logprobs: Option<serde_json::Value>,
+    pub finish_reason: Option<String>,
+}
+
+const COMPLETION_SYSTEM_PROMPT: &str =
+    "You are a text completion engine. Continue the text exactly where it stops. Output only the continuation.";
+
+const INFILL_SYSTEM_PROMPT: &str = "You are a code completion engine. The user message contains the text that \
+follows the insertion point. Continue the text exactly where it stops so that it connects to that following text. \
+Output only the missing text and never repeat the following text.";
+
+/// Transform a completion request into a single-turn Anthropic request
+///
+/// The prompt becomes an assistant prefill so the model continues it directly;
+/// a suffix is given in the user turn.
+pub fn transform_completion_to_anthropic(req: CompletionRequest, prompt: &str) -> AnthropicRequest {
+    let (system, user) = match req.suffix.as_deref().filter(|s| !s.is_empty()) {
+        Some(suffix) => (INFILL_SYSTEM_PROMPT, suffix.to_string()),
+        None => (COMPLETION_SYSTEM_PROMPT, "Continue the text.".to_string()),
+    };
+
+    let mut messages = vec![crate::models::Message {
+        role: "user".to_string(),
+        content: MessageContent::Text(user),
+    }];
+    // Prefill must not end in whitespace
+    let prefill = prompt.trim_end();
+    if !prefill.is_empty() {
+        messages.push(crate::models::Message {
+            role: "assistant".to_string(),
+            content: MessageContent::Text(prefill.to_string()),
+        });
+    }
+
+    let stop_sequences = match req.stop {
+        Some(serde_json::Value::String(stop)) => Some(vec![stop]),
+        Some(serde_json::Value::Array(stops)) => Some(
+            stops
+                .into_iter()
+                .filter_map(|s| s.as_str().map(str::to_string))
+                .collect(),
+        ),
+        _ => None,
+    };
+
+    AnthropicRequest {
+        model: req.model,
+        messages,
+        // OpenAI's legacy default is 16 tokens
+        max_tokens: req.max_tokens.unwrap_or(16),
+        thinking: None,
+        temperature: req.temperature,
+        top_p: req.top_p,
+        top_k: None,
+        stop_sequences,
+        stream: None,
+        metadata: None,
+        system: Some(SystemPrompt::Text(system.to_string())),
+        tools: None,
+        betas: Vec::new(),
+        tool_choice: None,
+        anthropic_version: None,
+        idempotency_key: None,
+        verbosity: None,
+    }
+}
+
+/// Transform an Anthropic response to a legacy completion
+pub fn transform_anthropic_to_completion(
+    anthropic_resp: ProviderResponse,
+    model: String,
+    prompt: &str,
+    echo: bool,
+) -> CompletionResponse {
+    let mut text: String = anthropic_resp.content.iter()
+        .filter_map(|block| match block {
+            ContentBlock::Text { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    // Whitespace trimmed from the prefill may be repeated at the start of the output
+    let trimmed = &prompt[prompt.trim_end().len()..];
+    if !trimmed.is_empty() {
+        if let Some(rest) = text.strip_prefix(trimmed) {
+            text = rest.to_string();
+        }
+    }
+    if echo {
+        text = format!("{}{}", prompt, text);
+    }
+
+    let finish_reason = anthropic_resp.stop_reason.as_deref().map(|reason| {
+        match reason {
+            "max_tokens" => "length",
+            _ => "stop",
+        }
+        .to_string()
+    });
+
+    CompletionResponse {
+        id: anthropic_resp.id,
+        object: "text_completion".to_string(),
+        created: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        model,
+        choices: vec![CompletionChoice {
+            text,
+            index: 0,
+            logprobs: None,
+            finish_reason,
+        }],
+        usage: OpenAIUsage {
+            prompt_tokens: anthropic_resp.usage.input_tokens,
+            completion_tokens: anthropic_resp.usage.output_tokens,
+            total_tokens: anthropic_resp.usage.input_tokens + anthropic_resp.usage.output_tokens,
+        },
+    }
+}
+
+/// Transform a provider FIM completion to a legacy completion
+pub fn fim_to_completion(fim: FimResponse, model: String, prompt: &str, echo: bool) -> CompletionResponse {
+    let text = if echo {
+        format!("{}{}", prompt, fim.text)
+    } else {
+        fim.text
+    };
+    CompletionResponse {
+        id: fim.id,
+        object: "text_completion".to_string(),
+        created: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        model,
+        choices: vec![CompletionChoice {
+            text,
+            index: 0,
+            logprobs: None,
+            finish_reason: fim.finish_reason,
+        }],
+        usage: OpenAIUsage {
+            prompt_tokens: fim.usage.input_tokens,
+            completion_tokens: fim.usage.output_tokens,
+            total_tokens: fim.usage.input_tokens + fim.usage.output_tokens,
+        },
+    }
+}
+
+/// Prefix text with the message author's `name`, if any
+fn with_name(name: Option<&str>, text: &str) -> String {
+    match name.filter(|n| !n.is_empty()) {
+        Some(name) => format!("{}: {}", name, text),
+        None => text.to_string(),
+    }
+}
+
 /// Transform Anthropic response to OpenAI format
 pub fn transform_anthropic_to_openai(
     anthropic_resp: ProviderResponse,
     model: String,
+    legacy_functions: bool,
 ) -> OpenAIResponse {
     // Extract text content from content blocks
     let content = anthropic_resp.content.iter()
@@ -237,30 +671,41 @@ pub fn transform_anthropic_to_openai(
         Some(content)
     };
 
-    // Map finish_reason
-    let finish_reason = anthropic_resp.stop_reason.as_ref().map(|reason| {
-        match reason.as_str() {
-            "end_turn" => "stop",
-            "max_tokens" => "length",
-            "stop_sequence" => "stop",
-            _ => "stop",
-        }
-        .to_string()
-    });
+    let tool_calls: Vec<OpenAIToolCall> = anthropic_resp
+        .content
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::ToolUse { id, name, input } => Some(OpenAIToolCall {
+                id: id.clone(),
+                r#type: function_type(),
+                function: OpenAIFunctionCall {
+                    name: name.clone(),
+                    arguments: input.to_string(),
+                },
+            }),
+            _ => None,
+        })
+        .collect();
+
+    let finish_reason = anthropic_resp
+        .stop_reason
+        .as_deref()
+        .map(|reason| finish_reason(reason, legacy_functions).to_string());
 
     OpenAIResponse {
         id: anthropic_resp.id,
         object: "chat.completion".to_string(),
-        created: std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs(),
+        created: unix_now(),
         model,
         choices: vec![OpenAIChoice {
             index: 0,
             message: OpenAIResponseMessage {
                 role: anthropic_resp.role,
                 content,
+                function_call: legacy_functions
+                    .then(|| tool_calls.first().map(|call| call.function.clone()))
+                    .flatten(),
+                tool_calls: (!legacy_functions && !tool_calls.is_empty()).then_some(tool_calls),
             },
             finish_reason,
         }],
@@ -271,3 +716,309 @@ pub fn transform_anthropic_to_openai(
         },
     }
 }
+
+/// OpenAI finish_reason for an Anthropic stop_reason
+fn finish_reason(stop_reason: &str, legacy_functions: bool) -> &'static str {
+    match stop_reason {
+        "end_turn" => "stop",
+        "max_tokens" => "length",
+        "stop_sequence" => "stop",
+        "tool_use" if legacy_functions => "function_call",
+        "tool_use" => "tool_calls",
+        _ => "stop",
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Translates an Anthropic SSE stream into `chat.completion.chunk` payloads
+///
+/// [`push`](Self::push) returns the `data:` payloads for the client, ending
+/// with `[DONE]`. With `include_usage`, the usage of the whole response comes
+/// after the last choice chunk, in a chunk without choices, as OpenAI sends it.
+pub struct ChunkTranslator {
+    id: String,
+    model: String,
+    created: u64,
+    include_usage: bool,
+    legacy_functions: bool,
+    /// (input, output) tokens from the upstream usage events
+    usage: (u32, u32),
+    /// Anthropic content block index and OpenAI tool call index of each tool call
+    tool_calls: Vec<(u64, usize)>,
+    buffer: LineBuffer,
+}
+
+impl ChunkTranslator {
+    pub fn new(model: String, include_usage: bool, legacy_functions: bool) -> Self {
+        Self {
+            id: String::new(),
+            model,
+            created: unix_now(),
+            include_usage,
+            legacy_functions,
+            usage: (0, 0),
+            tool_calls: Vec::new(),
+            buffer: LineBuffer::default(),
+        }
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.push(bytes);
+        let mut out = Vec::new();
+        while let Some(raw) = self.buffer.event() {
+            for event in parse_sse_events(&raw) {
+                let data: serde_json::Value = serde_json::from_str(&event.data).unwrap_or_default();
+                self.translate(event.event.as_deref().unwrap_or_default(), &data, &mut out);
+            }
+        }
+        out
+    }
+
+    fn translate(&mut self, event: &str, data: &serde_json::Value, out: &mut Vec<String>) {
+        let tokens = |usage: &serde_json::Value, field: &str| usage[field].as_u64().map(|t| t as u32);
+        match event {
+            "message_start" => {
+                self.id = data["message"]["id"].as_str().unwrap_or_default().to_string();
+                self.usage.0 = tokens(&data["message"]["usage"], "input_tokens").unwrap_or(0);
+                out.push(self.chunk(serde_json::json!({ "role": "assistant", "content": "" }), None));
+            }
+            "content_block_start" if data["content_block"]["type"] == "tool_use" => {
+                let index = self.tool_calls.len();
+                self.tool_calls.push((data["index"].as_u64().unwrap_or(0), index));
+                let (id, name) = (&data["content_block"]["id"], &data["content_block"]["name"]);
+                let delta = if self.legacy_functions {
+                    serde_json::json!({ "function_call": { "name": name, "arguments": "" } })
+                } else {
+                    serde_json::json!({ "tool_calls": [{
+                        "index": index, "id": id, "type": "function",
+                        "function": { "name": name, "arguments": "" },
+                    }] })
+                };
+                out.push(self.chunk(delta, None));
+            }
+            "content_block_delta" => {
+                let delta = &data["delta"];
+                match delta["type"].as_str() {
+                    Some("text_delta") => {
+                        out.push(self.chunk(serde_json::json!({ "content": delta["text"] }), None));
+                    }
+                    Some("input_json_delta") => {
+                        let block = data["index"].as_u64().unwrap_or(0);
+                        let Some(&(_, index)) = self.tool_calls.iter().find(|(b, _)| *b == block) else {
+                            return;
+                        };
+                        let arguments = &delta["partial_json"];
+                        let delta = if self.legacy_functions {
+                            serde_json::json!({ "function_call": { "arguments": arguments } })
+                        } else {
+                            serde_json::json!({ "tool_calls": [{ "index": index, "function": { "arguments": arguments } }] })
+                        };
+                        out.push(self.chunk(delta, None));
+                    }
+                    _ => {}
+                }
+            }
+            "message_delta" => {
+                if let Some(input) = tokens(&data["usage"], "input_tokens") {
+                    self.usage.0 = input;
+                }
+                if let Some(output) = tokens(&data["usage"], "output_tokens") {
+                    self.usage.1 = output;
+                }
+                if let Some(reason) = data["delta"]["stop_reason"].as_str() {
+                    let reason = finish_reason(reason, self.legacy_functions);
+                    out.push(self.chunk(serde_json::json!({}), Some(reason)));
+                }
+            }
+            "message_stop" => {
+                if self.include_usage {
+                    let (prompt, completion) = self.usage;
+                    let usage = serde_json::json!({
+                        "prompt_tokens": prompt,
+                        "completion_tokens": completion,
+                        "total_tokens": prompt + completion,
+                    });
+                    out.push(self.envelope(serde_json::json!([]), usage));
+                }
+                out.push("[DONE]".to_string());
+            }
+            "error" => out.push(serde_json::json!({ "error": data["error"] }).to_string()),
+            _ => {}
+        }
+    }
+
+    fn chunk(&self, delta: serde_json::Value, finish_reason: Option<&str>) -> String {
+        let choices = serde_json::json!([{ "index": 0, "delta": delta, "finish_reason": finish_reason }]);
+        self.envelope(choices, serde_json::Value::Null)
+    }
+
+    fn envelope(&self, choices: serde_json::Value, usage: serde_json::Value) -> String {
+        let mut chunk = serde_json::json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": choices,
+        });
+        // With include_usage every chunk has a usage field, null until the last
+        if self.include_usage {
+            chunk["usage"] = usage;
+        }
+        chunk.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_developer_role_names_and_system_parts() {
+        let request: OpenAIRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [
+                { "role": "system", "content": [
+                    { "type": "text", "text": "Be brief." },
+                    { "type": "input_audio", "input_audio": {} }
+                ]},
+                { "role": "developer", "content": "Answer in French." },
+                { "role": "user", "name": "alice", "content": "Hi" }
+            ]
+        }))
+        .unwrap();
+
+        let anthropic = transform_openai_to_anthropic(request).unwrap();
+        match anthropic.system {
+            Some(SystemPrompt::Text(text)) => assert_eq!(text, "Be brief.\n\nAnswer in French."),
+            other => panic!("unexpected system prompt: {:?}", other),
+        }
+        assert_eq!(anthropic.messages.len(), 1);
+        match &anthropic.messages[0].content {
+            MessageContent::Text(text) => assert_eq!(text, "alice: Hi"),
+            other => panic!("unexpected content: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_legacy_functions_become_tools() {
+        let request: OpenAIRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o",
+            "functions": [{ "name": "get_weather", "parameters": { "type": "object", "properties": { "city": { "type": "string" } } } }],
+            "function_call": "auto",
+            "messages": [
+                { "role": "user", "content": "Weather in Paris?" },
+                { "role": "assistant", "content": null, "function_call": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" } },
+                { "role": "function", "name": "get_weather", "content": "18C, sunny" }
+            ]
+        }))
+        .unwrap();
+        assert!(request.uses_legacy_functions());
+
+        let anthropic = transform_openai_to_anthropic(request).unwrap();
+        let tools = anthropic.tools.unwrap();
+        assert_eq!(tools[0].name.as_deref(), Some("get_weather"));
+        assert_eq!(tools[0].input_schema.as_ref().unwrap()["properties"]["city"]["type"], "string");
+        assert_eq!(anthropic.messages.len(), 3);
+        let (call_id, result_id) = match (&anthropic.messages[1].content, &anthropic.messages[2].content) {
+            (MessageContent::Blocks(call), MessageContent::Blocks(result)) => match (&call[0], &result[0]) {
+                (ContentBlock::ToolUse { id, input, .. }, ContentBlock::ToolResult { tool_use_id, .. }) => {
+                    assert_eq!(input["city"], "Paris");
+                    (id.clone(), tool_use_id.clone())
+                }
+                other => panic!("unexpected blocks: {:?}", other),
+            },
+            other => panic!("unexpected content: {:?}", other),
+        };
+        assert_eq!(call_id, result_id);
+        assert_eq!(anthropic.messages[2].role, "user");
+
+        let response: ProviderResponse = serde_json::from_value(serde_json::json!({
+            "id": "msg_1", "type": "message", "role": "assistant", "model": "claude",
+            "content": [{ "type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": { "city": "Lyon" } }],
+            "stop_reason": "tool_use", "stop_sequence": null,
+            "usage": { "input_tokens": 10, "output_tokens": 5 }
+        }))
+        .unwrap();
+        let legacy = serde_json::to_value(transform_anthropic_to_openai(response.clone(), "gpt-4o".into(), true)).unwrap();
+        assert_eq!(legacy["choices"][0]["finish_reason"], "function_call");
+        assert_eq!(legacy["choices"][0]["message"]["function_call"]["arguments"], "{\"city\":\"Lyon\"}");
+        assert!(legacy["choices"][0]["message"].get("tool_calls").is_none());
+        let current = serde_json::to_value(transform_anthropic_to_openai(response, "gpt-4o".into(), false)).unwrap();
+        assert_eq!(current["choices"][0]["message"]["tool_calls"][0]["id"], "toolu_1");
+    }
+
+    #[test]
+    fn test_stream_chunks_with_usage() {
+        let upstream = concat!(
+            "event: message_start\ndata: {\"message\":{\"id\":\"msg_1\",\"usage\":{\"input_tokens\":12}}}\n\n",
+            "event: content_block_delta\ndata: {\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n",
+            "event: content_block_start\ndata: {\"index\":1,\"content_block\":{\"type\":\"tool_use\",\"id\":\"toolu_1\",\"name\":\"Read\"}}\n\n",
+            "event: content_block_delta\ndata: {\"index\":1,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{}\"}}\n\n",
+            "event: message_delta\ndata: {\"delta\":{\"stop_reason\":\"tool_use\"},\"usage\":{\"output_tokens\":7}}\n\n",
+            "event: message_stop\ndata: {}\n\n",
+        );
+        let mut translator = ChunkTranslator::new("gpt-4o".to_string(), true, false);
+        // Split mid-event, as network reads do
+        let (a, b) = upstream.split_at(50);
+        let mut payloads = translator.push(a.as_bytes());
+        payloads.extend(translator.push(b.as_bytes()));
+
+        assert_eq!(payloads.last().unwrap(), "[DONE]");
+        let chunks: Vec<serde_json::Value> =
+            payloads[..payloads.len() - 1].iter().map(|p| serde_json::from_str(p).unwrap()).collect();
+        assert_eq!(chunks.len(), 6);
+        assert_eq!(chunks[0]["id"], "msg_1");
+        assert_eq!(chunks[0]["usage"], serde_json::Value::Null);
+        assert_eq!(chunks[1]["choices"][0]["delta"]["content"], "Hi");
+        assert_eq!(chunks[2]["choices"][0]["delta"]["tool_calls"][0]["id"], "toolu_1");
+        assert_eq!(chunks[3]["choices"][0]["delta"]["tool_calls"][0]["index"], 0);
+        assert_eq!(chunks[4]["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(chunks[5]["choices"], serde_json::json!([]));
+        assert_eq!(chunks[5]["usage"]["total_tokens"], 19);
+
+        let mut translator = ChunkTranslator::new("gpt-4o".to_string(), false, false);
+        let payloads = translator.push(upstream.as_bytes());
+        assert!(payloads.iter().all(|p| !p.contains("usage")));
+    }
+
+    #[test]
+    fn test_completion_with_suffix_uses_prefill() {
+        let request: CompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "deepseek-coder",
+            "prompt": "fn add(a: i32, b: i32) -> i32 {\n    ",
+            "suffix": "\n}",
+            "stop": "\n\n",
+            "echo": true
+        }))
+        .unwrap();
+        let prompt = request.prompt_text().unwrap();
+
+        let anthropic = transform_completion_to_anthropic(request, &prompt);
+        assert_eq!(anthropic.max_tokens, 16);
+        assert_eq!(anthropic.stop_sequences, Some(vec!["\n\n".to_string()]));
+        match &anthropic.messages[1].content {
+            MessageContent::Text(text) => assert_eq!(text, "fn add(a: i32, b: i32) -> i32 {"),
+            other => panic!("unexpected content: {:?}", other),
+        }
+
+        let response = ProviderResponse {
+            id: "msg_1".to_string(),
+            r#type: "message".to_string(),
+            role: "assistant".to_string(),
+            content: vec![ContentBlock::Text { text: "\n    a + b".to_string() }],
+            model: "deepseek-coder".to_string(),
+            stop_reason: Some("end_turn".to_string()),
+            stop_sequence: None,
+            usage: crate::providers::Usage { input_tokens: 10, output_tokens: 3, ..Default::default() },
+        };
+        let completion = transform_anthropic_to_completion(response, "deepseek-coder".to_string(), &prompt, true);
+        assert_eq!(completion.choices[0].text, "fn add(a: i32, b: i32) -> i32 {\n    a + b");
+        assert_eq!(completion.choices[0].finish_reason.as_deref(), Some("stop"));
+    }
+}
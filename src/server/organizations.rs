@@ -0,0 +1,279 @@
+use axum::{
+    body::Bytes,
+    extract::{Path, RawQuery, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+use crate::cli::OrganizationsConfig;
+use crate::providers::{http, AuthType, ProviderConfig};
+use crate::storage::UsageRecord;
+
+use super::limits::error_response;
+use super::AppState;
+
+const DEFAULT_ANTHROPIC_VERSION: &str = "2023-06-01";
+/// Marks the entries the mux adds to Anthropic's reports
+const SOURCE: &str = "claude-code-mux";
+
+/// Admin API reports that mux usage is merged into
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Report {
+    Usage,
+    Cost,
+}
+
+impl Report {
+    fn from_path(path: &str) -> Option<Self> {
+        match path.trim_end_matches('/') {
+            "usage_report/messages" => Some(Self::Usage),
+            "cost_report" => Some(Self::Cost),
+            _ => None,
+        }
+    }
+}
+
+/// The Anthropic provider that serves the Admin API
+fn admin_provider<'a>(config: &OrganizationsConfig, providers: &'a [ProviderConfig]) -> Option<&'a ProviderConfig> {
+    match &config.provider {
+        Some(name) => providers.iter().find(|p| &p.name == name),
+        None => providers
+            .iter()
+            .find(|p| p.is_enabled() && p.provider_type == "anthropic" && p.auth_type == AuthType::ApiKey),
+    }
+}
+
+/// ANY /v1/organizations/*path - Anthropic Admin API passthrough
+///
+/// Usage and cost reports get the mux's own traffic to other providers added,
+/// so dashboards see all spend in one place.
+pub async fn passthrough(
+    State(state): State<Arc<AppState>>,
+    method: Method,
+    Path(path): Path<String>,
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let active = state.active();
+    let config = &active.config.organizations;
+    let Some(provider) = admin_provider(config, &active.config.providers) else {
+        return error_response(
+            StatusCode::NOT_FOUND,
+            "not_found_error",
+            "No Anthropic provider is configured for the Admin API".to_string(),
+        );
+    };
+    let Some(admin_key) = config
+        .admin_key
+        .clone()
+        .or_else(|| headers.get("x-api-key").and_then(|v| v.to_str().ok()).map(str::to_string))
+    else {
+        return error_response(
+            StatusCode::UNAUTHORIZED,
+            "authentication_error",
+            "An Anthropic Admin API key is required (x-api-key or [organizations] admin_key)".to_string(),
+        );
+    };
+
+    let base_url = provider.base_url.as_deref().unwrap_or("https://api.anthropic.com");
+    let mut url = format!("{}/v1/organizations/{}", base_url.trim_end_matches('/'), path);
+    if let Some(query) = &query {
+        url = format!("{}?{}", url, query);
+    }
+    debug!("🏢 Admin API passthrough: {} {}", method, url);
+
+    let version = headers
+        .get("anthropic-version")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or(DEFAULT_ANTHROPIC_VERSION);
+    let mut request = http::client(provider, &active.config.server.timeouts)
+        .request(method, &url)
+        .header("x-api-key", admin_key)
+        .header("anthropic-version", version);
+    if !body.is_empty() {
+        request = request.header(header::CONTENT_TYPE, "application/json").body(body);
+    }
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => return error_response(StatusCode::BAD_GATEWAY, "api_error", format!("Admin API request failed: {}", e)),
+    };
+    let status = StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    let bytes = match response.bytes().await {
+        Ok(bytes) => bytes,
+        Err(e) => return error_response(StatusCode::BAD_GATEWAY, "api_error", format!("Admin API request failed: {}", e)),
+    };
+
+    let merge = config.merge_usage && status.is_success();
+    let report = Report::from_path(&path).filter(|_| merge);
+    let (Some(report), Some(store)) = (report, &state.usage_store) else {
+        return (status, [(header::CONTENT_TYPE, "application/json")], bytes).into_response();
+    };
+    let Ok(mut body) = serde_json::from_slice::<Value>(&bytes) else {
+        return (status, [(header::CONTENT_TYPE, "application/json")], bytes).into_response();
+    };
+    let records = match report_span(&body) {
+        Some((start, end)) => store.load_range(start, end).await.unwrap_or_else(|e| {
+            warn!("Failed to load usage for the Admin API report: {}", e);
+            Vec::new()
+        }),
+        None => Vec::new(),
+    };
+    merge_report(&mut body, report, &provider.name, &records);
+    (status, axum::Json(body)).into_response()
+}
+
+/// Start and end of a report time bucket
+fn bucket_bounds(bucket: &Value) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let bound = |field: &str| {
+        bucket[field]
+            .as_str()
+            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+            .map(|t| t.with_timezone(&Utc))
+    };
+    Some((bound("starting_at")?, bound("ending_at")?))
+}
+
+/// Time covered by all buckets of a report
+fn report_span(body: &Value) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    body["data"]
+        .as_array()?
+        .iter()
+        .filter_map(bucket_bounds)
+        .reduce(|(start, end), (s, e)| (start.min(s), end.max(e)))
+}
+
+/// Add mux usage to each time bucket of a usage or cost report
+///
+/// Requests served by the Admin API's own provider are left out, Anthropic
+/// already counts them.
+fn merge_report(
+    body: &mut Value,
+    report: Report,
+    admin_provider: &str,
+    records: &[UsageRecord],
+) {
+    let Some(buckets) = body["data"].as_array_mut() else {
+        return;
+    };
+    for bucket in buckets {
+        let Some((start, end)) = bucket_bounds(bucket) else {
+            continue;
+        };
+        let records: Vec<UsageRecord> = records
+            .iter()
+            .filter(|r| r.timestamp >= start && r.timestamp < end)
+            .filter(|r| r.provider.as_deref() != Some(admin_provider))
+            .cloned()
+            .collect();
+        let results = mux_results(report, &records);
+        if let Some(existing) = bucket["results"].as_array_mut() {
+            existing.extend(results);
+        } else {
+            bucket["results"] = Value::Array(results);
+        }
+    }
+}
+
+/// Report entries for mux requests, one per provider and model
+fn mux_results(report: Report, records: &[UsageRecord]) -> Vec<Value> {
+    // (provider, model) -> (input tokens, output tokens, cost)
+    let mut totals: BTreeMap<(String, String), (u64, u64, f64)> = BTreeMap::new();
+    for record in records {
+        let provider = record.provider.clone().unwrap_or_else(|| "unknown".to_string());
+        let model = record.actual_model.clone().unwrap_or_else(|| record.model.clone());
+        let total = totals.entry((provider, model)).or_default();
+        total.0 += record.input_tokens as u64;
+        total.1 += record.output_tokens as u64;
+        total.2 += record.cost_usd.unwrap_or(0.0);
+    }
+
+    totals
+        .into_iter()
+        .filter(|(_, (_, _, cost))| report == Report::Usage || *cost > 0.0)
+        .map(|((provider, model), (input, output, cost))| match report {
+            Report::Usage => json!({
+                "uncached_input_tokens": input,
+                "cache_creation": { "ephemeral_1h_input_tokens": 0, "ephemeral_5m_input_tokens": 0 },
+                "cache_read_input_tokens": 0,
+                "output_tokens": output,
+                "server_tool_use": { "web_search_requests": 0 },
+                "api_key_id": null,
+                "workspace_id": null,
+                "model": model,
+                "service_tier": null,
+                "context_window": null,
+                "source": SOURCE,
+                "provider": provider,
+            }),
+            // Amounts are in cents, as decimal strings
+            Report::Cost => json!({
+                "currency": "USD",
+                "amount": format!("{:.6}", cost * 100.0),
+                "workspace_id": null,
+                "description": format!("{} via {}", model, provider),
+                "cost_type": "tokens",
+                "context_window": null,
+                "model": model,
+                "service_tier": null,
+                "token_type": null,
+                "source": SOURCE,
+                "provider": provider,
+            }),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(provider: &str, model: &str, cost: Option<f64>) -> UsageRecord {
+        serde_json::from_value(json!({
+            "timestamp": "2025-06-01T10:00:00Z",
+            "model": model,
+            "provider": provider,
+            "success": true,
+            "latency_ms": 100,
+            "input_tokens": 1000,
+            "output_tokens": 200,
+            "cost_usd": cost,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_merge_usage_report() {
+        let mut body = json!({
+            "data": [{
+                "starting_at": "2025-06-01T00:00:00Z",
+                "ending_at": "2025-06-02T00:00:00Z",
+                "results": [{ "model": "claude-sonnet-4-5", "uncached_input_tokens": 5 }],
+            }],
+            "has_more": false,
+            "next_page": null,
+        });
+        let records = vec![
+            record("anthropic", "claude-sonnet-4-5", Some(0.5)),
+            record("deepseek", "deepseek-chat", Some(0.01)),
+            record("deepseek", "deepseek-chat", None),
+        ];
+        merge_report(&mut body, Report::Usage, "anthropic", &records);
+
+        let results = body["data"][0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1]["model"], "deepseek-chat");
+        assert_eq!(results[1]["uncached_input_tokens"], 2000);
+        assert_eq!(results[1]["source"], SOURCE);
+
+        let costs = mux_results(Report::Cost, &records[1..]);
+        assert_eq!(costs[0]["amount"], "1.000000");
+        assert_eq!(Report::from_path("cost_report"), Some(Report::Cost));
+        assert_eq!(Report::from_path("users"), None);
+    }
+}
@@ -0,0 +1,170 @@
+//! Output pacing for streamed responses
+//!
+//! Very fast providers can deliver hundreds of tokens per second, which some
+//! terminal tools render badly and which is hard to follow in a recording.
+//! With `max_output_tokens_per_sec` on an alias, content deltas are released
+//! at that rate instead; large deltas are split so the text still flows.
+
+use bytes::Bytes;
+use futures::stream::{Stream, StreamExt};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::providers::error::ProviderError;
+use crate::providers::streaming::{parse_sse_events, LineBuffer, SseEvent};
+
+type ByteStream = Pin<Box<dyn Stream<Item = Result<Bytes, ProviderError>> + Send>>;
+
+/// Rough characters per token, for estimating delta sizes
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Updates per second the client gets at most
+const UPDATES_PER_SEC: f64 = 20.0;
+
+/// Release content deltas at no more than `tokens_per_sec`
+///
+/// Other events pass through as soon as the deltas before them are out.
+pub fn pace(stream: ByteStream, tokens_per_sec: f64) -> ByteStream {
+    struct Paced {
+        stream: ByteStream,
+        buffer: LineBuffer,
+        /// Events waiting to go out, with their token cost
+        queue: VecDeque<(Bytes, f64)>,
+        /// When the next delta may be sent
+        next: Option<Instant>,
+        done: bool,
+    }
+
+    let rate = tokens_per_sec.max(1.0);
+    // Deltas are split into pieces of about this many characters
+    let piece_chars =
+        ((rate / UPDATES_PER_SEC).ceil() as usize * CHARS_PER_TOKEN).max(CHARS_PER_TOKEN);
+
+    let paced = Paced {
+        stream,
+        buffer: LineBuffer::default(),
+        queue: VecDeque::new(),
+        next: None,
+        done: false,
+    };
+    Box::pin(futures::stream::unfold(paced, move |mut p| async move {
+        loop {
+            if let Some((bytes, cost)) = p.queue.pop_front() {
+                if cost > 0.0 {
+                    // A stalled upstream doesn't earn a burst afterwards
+                    let now = Instant::now();
+                    let at = p.next.filter(|next| *next > now).unwrap_or(now);
+                    tokio::time::sleep_until(at).await;
+                    p.next = Some(at + Duration::from_secs_f64(cost / rate));
+                }
+                return Some((Ok(bytes), p));
+            }
+            if p.done {
+                return None;
+            }
+            match p.stream.next().await {
+                Some(Ok(bytes)) => {
+                    p.buffer.push(&bytes);
+                    while let Some(raw) = p.buffer.event() {
+                        p.queue.extend(split_event(&raw, piece_chars));
+                    }
+                }
+                Some(Err(e)) => return Some((Err(e), p)),
+                None => {
+                    p.done = true;
+                    if !p.buffer.is_empty() {
+                        p.queue.push_back((Bytes::from(p.buffer.rest()), 0.0));
+                    }
+                }
+            }
+        }
+    }))
+}
+
+/// An event as one or more chunks with their token cost
+fn split_event(raw: &str, piece_chars: usize) -> Vec<(Bytes, f64)> {
+    let events = parse_sse_events(raw);
+    let [event] = events.as_slice() else {
+        return vec![(Bytes::from(raw.to_string()), 0.0)];
+    };
+    if event.event.as_deref() != Some("content_block_delta") {
+        return vec![(Bytes::from(raw.to_string()), 0.0)];
+    }
+    let Ok(data) = serde_json::from_str::<serde_json::Value>(&event.data) else {
+        return vec![(Bytes::from(raw.to_string()), 0.0)];
+    };
+    let field = match data["delta"]["type"].as_str() {
+        Some("text_delta") => "text",
+        Some("thinking_delta") => "thinking",
+        Some("input_json_delta") => "partial_json",
+        _ => return vec![(Bytes::from(raw.to_string()), 0.0)],
+    };
+    let text = data["delta"][field].as_str().unwrap_or_default();
+
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() <= piece_chars {
+        return vec![(Bytes::from(raw.to_string()), tokens(chars.len()))];
+    }
+    chars
+        .chunks(piece_chars)
+        .map(|piece| {
+            let mut data = data.clone();
+            data["delta"][field] = piece.iter().collect::<String>().into();
+            let event = SseEvent {
+                event: event.event.clone(),
+                data: data.to_string(),
+            };
+            (Bytes::from(event.to_sse_string()), tokens(piece.len()))
+        })
+        .collect()
+}
+
+fn tokens(chars: usize) -> f64 {
+    (chars as f64 / CHARS_PER_TOKEN as f64).max(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pace_deltas() {
+        let text = "x".repeat(400);
+        let chunks: Vec<Result<Bytes, ProviderError>> = vec![
+            Ok(Bytes::from("event: message_start\ndata: {}\n\n")),
+            Ok(Bytes::from(format!(
+                "event: content_block_delta\ndata: {}\n\n",
+                serde_json::json!({ "index": 0, "delta": { "type": "text_delta", "text": text } })
+            ))),
+            Ok(Bytes::from("event: message_stop\ndata: {}\n\n")),
+        ];
+        let started = Instant::now();
+        // 100 tokens at 200 tokens/sec, in pieces of 10 tokens
+        let out: Vec<Bytes> = pace(Box::pin(futures::stream::iter(chunks)), 200.0)
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(out.len(), 12);
+        let elapsed = started.elapsed();
+        // The first piece goes out right away
+        assert!(
+            elapsed >= Duration::from_millis(450) && elapsed < Duration::from_millis(500),
+            "{:?}",
+            elapsed
+        );
+
+        let text: String = out[1..11]
+            .iter()
+            .flat_map(|chunk| parse_sse_events(&String::from_utf8_lossy(chunk)))
+            .map(|event| {
+                serde_json::from_str::<serde_json::Value>(&event.data).unwrap()["delta"]["text"]
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(text.len(), 400);
+    }
+}
@@ -0,0 +1,256 @@
+//! Request and response sizes per provider
+//!
+//! Long agent sessions grow mostly through tool results, and every byte is
+//! paid for on each turn. Totals per provider show where the volume goes and
+//! how much the request transforms (truncation, image conversion, tool
+//! filtering) take off; single payloads over the configured thresholds are
+//! logged as they happen.
+
+use axum::{extract::State, Json};
+use bytes::Bytes;
+use futures::stream::{Stream, StreamExt};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+use crate::cli::PayloadWarnings;
+use crate::models::{AnthropicRequest, ContentBlock, MessageContent};
+use crate::providers::error::ProviderError;
+
+use super::AppState;
+
+type ByteStream = Pin<Box<dyn Stream<Item = Result<Bytes, ProviderError>> + Send>>;
+
+/// Metric name, help text and value of a Prometheus counter
+type Counter = (&'static str, &'static str, fn(&PayloadTotals) -> u64);
+
+/// Byte totals for one provider
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PayloadTotals {
+    pub requests: u64,
+    /// Client request bodies as received
+    pub received_bytes: u64,
+    /// Request bodies after the transforms, in Anthropic format
+    pub sent_bytes: u64,
+    /// Part of `sent_bytes` in tool results
+    pub tool_result_bytes: u64,
+    pub response_bytes: u64,
+    pub largest_request_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PayloadStatsEntry {
+    pub provider: String,
+    #[serde(flatten)]
+    pub totals: PayloadTotals,
+    /// `sent_bytes / received_bytes`; below 1 when the transforms shrank requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression_ratio: Option<f64>,
+}
+
+/// Payload totals by provider
+#[derive(Debug, Default)]
+pub struct PayloadStats {
+    totals: Mutex<BTreeMap<String, PayloadTotals>>,
+}
+
+impl PayloadStats {
+    fn update(&self, provider: &str, update: impl FnOnce(&mut PayloadTotals)) {
+        let mut totals = self.totals.lock().unwrap();
+        update(totals.entry(provider.to_string()).or_default());
+    }
+
+    /// Count a request going to `provider`; `received` is the client body size
+    pub fn record_request(
+        &self,
+        provider: &str,
+        received: usize,
+        request: &AnthropicRequest,
+        warnings: &PayloadWarnings,
+    ) {
+        let sent = serde_json::to_vec(request).map_or(0, |body| body.len());
+        let tool_results = tool_result_bytes(request);
+        self.update(provider, |t| {
+            t.requests += 1;
+            t.received_bytes += received as u64;
+            t.sent_bytes += sent as u64;
+            t.tool_result_bytes += tool_results as u64;
+            t.largest_request_bytes = t.largest_request_bytes.max(sent as u64);
+        });
+        if let Some(limit) = warnings.request_bytes.filter(|limit| sent > *limit) {
+            warn!(
+                "📦 Request to {} is {} bytes ({} in tool results), over the {}-byte warning threshold",
+                provider, sent, tool_results, limit
+            );
+        }
+    }
+
+    /// Count a response from `provider`
+    pub fn record_response(&self, provider: &str, bytes: usize, warnings: &PayloadWarnings) {
+        self.update(provider, |t| t.response_bytes += bytes as u64);
+        if let Some(limit) = warnings.response_bytes.filter(|limit| bytes > *limit) {
+            warn!(
+                "📦 Response from {} is {} bytes, over the {}-byte warning threshold",
+                provider, bytes, limit
+            );
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<PayloadStatsEntry> {
+        self.totals
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(provider, totals)| PayloadStatsEntry {
+                provider: provider.clone(),
+                totals: totals.clone(),
+                compression_ratio: (totals.received_bytes > 0)
+                    .then(|| totals.sent_bytes as f64 / totals.received_bytes as f64),
+            })
+            .collect()
+    }
+
+    /// Prometheus counters of the byte totals
+    pub fn prometheus(&self) -> String {
+        let snapshot = self.snapshot();
+        let mut out = String::new();
+        let counters: [Counter; 4] = [
+            (
+                "ccm_client_request_bytes_total",
+                "Client request bodies as received",
+                |t| t.received_bytes,
+            ),
+            (
+                "ccm_provider_request_bytes_total",
+                "Request bodies sent to the provider, after transforms",
+                |t| t.sent_bytes,
+            ),
+            (
+                "ccm_provider_tool_result_bytes_total",
+                "Tool result content in requests sent to the provider",
+                |t| t.tool_result_bytes,
+            ),
+            (
+                "ccm_provider_response_bytes_total",
+                "Response bodies returned by the provider",
+                |t| t.response_bytes,
+            ),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for entry in &snapshot {
+                let _ = writeln!(
+                    out,
+                    "{}{{provider=\"{}\"}} {}",
+                    name,
+                    entry.provider.replace('\\', "\\\\").replace('"', "\\\""),
+                    value(&entry.totals)
+                );
+            }
+        }
+        out
+    }
+}
+
+/// Bytes of tool result content in a request
+fn tool_result_bytes(request: &AnthropicRequest) -> usize {
+    request
+        .messages
+        .iter()
+        .filter_map(|message| match &message.content {
+            MessageContent::Blocks(blocks) => Some(blocks),
+            MessageContent::Text(_) => None,
+        })
+        .flatten()
+        .map(|block| match block {
+            ContentBlock::ToolResult { content, .. } => content.to_string().len(),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Pass a stream through, counting its bytes as the response size at the end
+pub fn observe(
+    stream: ByteStream,
+    stats: Arc<PayloadStats>,
+    provider: &str,
+    warnings: PayloadWarnings,
+) -> ByteStream {
+    struct Observed {
+        stream: ByteStream,
+        stats: Arc<PayloadStats>,
+        provider: String,
+        warnings: PayloadWarnings,
+        bytes: usize,
+    }
+
+    let observed = Observed {
+        stream,
+        stats,
+        provider: provider.to_string(),
+        warnings,
+        bytes: 0,
+    };
+    Box::pin(futures::stream::unfold(observed, |mut o| async move {
+        let Some(item) = o.stream.next().await else {
+            o.stats.record_response(&o.provider, o.bytes, &o.warnings);
+            return None;
+        };
+        o.bytes += item.as_ref().map_or(0, |chunk| chunk.len());
+        Some((item, o))
+    }))
+}
+
+/// GET /v1/stats/payloads - request and response sizes per provider
+pub async fn payload_stats(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "providers": state.payload_stats.snapshot() }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_payload_totals() {
+        let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "m",
+            "max_tokens": 16,
+            "messages": [{ "role": "user", "content": [
+                { "type": "tool_result", "tool_use_id": "t1", "content": "x".repeat(100) },
+            ] }],
+        }))
+        .unwrap();
+        let stats = Arc::new(PayloadStats::default());
+        let warnings = PayloadWarnings {
+            request_bytes: Some(10),
+            response_bytes: None,
+        };
+        stats.record_request("zai", 1000, &request, &warnings);
+
+        let chunks: Vec<Result<Bytes, ProviderError>> = vec![
+            Ok(Bytes::from("event: a\n\n")),
+            Ok(Bytes::from("event: b\n\n")),
+        ];
+        let stream = observe(
+            Box::pin(futures::stream::iter(chunks)),
+            stats.clone(),
+            "zai",
+            warnings,
+        );
+        assert_eq!(stream.collect::<Vec<_>>().await.len(), 2);
+
+        let snapshot = stats.snapshot();
+        let totals = &snapshot[0].totals;
+        assert_eq!((totals.requests, totals.received_bytes), (1, 1000));
+        assert_eq!(totals.tool_result_bytes, 100);
+        assert_eq!(totals.response_bytes, 20);
+        assert!(snapshot[0].compression_ratio.unwrap() < 1.0);
+        assert!(stats
+            .prometheus()
+            .contains("ccm_provider_response_bytes_total{provider=\"zai\"} 20"));
+    }
+}
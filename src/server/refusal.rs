@@ -0,0 +1,128 @@
+use bytes::Bytes;
+use futures::stream::{Stream, StreamExt};
+use regex::Regex;
+use std::pin::Pin;
+use tracing::warn;
+
+use crate::cli::RefusalRetry;
+use crate::models::ContentBlock;
+use crate::providers::error::ProviderError;
+use crate::providers::streaming::{parse_sse_events, LineBuffer};
+use crate::providers::ProviderResponse;
+
+type ByteStream = Pin<Box<dyn Stream<Item = Result<Bytes, ProviderError>> + Send>>;
+
+/// Compiled refusal patterns of an alias
+pub struct RefusalCheck {
+    patterns: Vec<Regex>,
+    scan_chars: usize,
+}
+
+impl RefusalCheck {
+    pub fn new(config: &RefusalRetry) -> Self {
+        let patterns = config
+            .patterns
+            .iter()
+            .filter_map(|pattern| match Regex::new(&format!("(?i){}", pattern)) {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    warn!("Ignoring invalid refusal pattern {:?}: {}", pattern, e);
+                    None
+                }
+            })
+            .collect();
+        Self {
+            patterns,
+            scan_chars: config.scan_chars,
+        }
+    }
+
+    fn matches(&self, stop_reason: Option<&str>, text: &str) -> bool {
+        let text = text.trim_start();
+        let end = text.char_indices().nth(self.scan_chars).map_or(text.len(), |(i, _)| i);
+        stop_reason == Some("refusal") || self.patterns.iter().any(|p| p.is_match(&text[..end]))
+    }
+
+    /// Whether a response is a refusal
+    pub fn is_refusal(&self, response: &ProviderResponse) -> bool {
+        let text = match response.content.first() {
+            Some(ContentBlock::Text { text }) => text.as_str(),
+            _ => "",
+        };
+        self.matches(response.stop_reason.as_deref(), text)
+    }
+
+    /// Read the start of a stream to see whether it is a refusal, returning a
+    /// stream that replays what was read
+    ///
+    /// Reading stops once enough text has arrived, at the first non-text block
+    /// or at the stop reason.
+    pub async fn check_stream(&self, stream: ByteStream) -> (bool, ByteStream) {
+        let mut stream = stream.fuse();
+        let mut head = Vec::new();
+        let mut buffer = LineBuffer::default();
+        let mut text = String::new();
+        let mut stop_reason = None;
+        'read: while let Some(item) = stream.next().await {
+            let Ok(bytes) = &item else {
+                head.push(item);
+                break;
+            };
+            buffer.push(bytes);
+            head.push(item);
+            while let Some(event) = buffer.event() {
+                let Some(data) = parse_sse_events(&event)
+                    .into_iter()
+                    .next()
+                    .and_then(|e| serde_json::from_str::<serde_json::Value>(&e.data).ok())
+                else {
+                    continue;
+                };
+                match data["type"].as_str().unwrap_or_default() {
+                    "content_block_start" if data["content_block"]["type"] != "text" => break 'read,
+                    "content_block_delta" => text.push_str(data["delta"]["text"].as_str().unwrap_or_default()),
+                    "message_delta" => {
+                        stop_reason = data["delta"]["stop_reason"].as_str().map(str::to_string);
+                        break 'read;
+                    }
+                    _ => {}
+                }
+                if text.chars().count() >= self.scan_chars {
+                    break 'read;
+                }
+            }
+        }
+        let refused = self.matches(stop_reason.as_deref(), &text);
+        (refused, Box::pin(futures::stream::iter(head).chain(stream)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check() -> RefusalCheck {
+        RefusalCheck::new(&toml::from_str::<RefusalRetry>("").unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_refusal_detection() {
+        let check = check();
+        assert!(check.matches(None, "I'm sorry, but I can't help with that request."));
+        assert!(check.matches(None, "\nI cannot assist with creating malware."));
+        assert!(check.matches(Some("refusal"), ""));
+        assert!(!check.matches(None, "Here's the fix. I can't reproduce the crash, but this should help."));
+
+        let events = [
+            r#"{"type":"message_start","message":{}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Sorry, I can't"}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":" help with that."}}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":9}}"#,
+        ];
+        let chunks: Vec<_> = events.iter().map(|e| Ok(Bytes::from(format!("data: {}\n\n", e)))).collect();
+        let (refused, replay) = check.check_stream(Box::pin(futures::stream::iter(chunks))).await;
+        assert!(refused);
+        assert_eq!(replay.collect::<Vec<_>>().await.len(), events.len());
+    }
+}
@@ -0,0 +1,151 @@
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::info;
+
+use super::limits::error_response;
+use super::AppState;
+
+/// Response header naming the log entry of a replayed request
+pub const REQUEST_ID_HEADER: &str = "x-ccm-request-id";
+
+/// Largest replayed response read back for the reply
+const MAX_RESPONSE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Client headers that shape a request and are carried into replays
+const FORWARDED_HEADERS: &[&str] = &["anthropic-beta", "anthropic-version"];
+
+/// What to change when replaying a request
+#[derive(Debug, Default, Deserialize)]
+pub struct ReplayOverrides {
+    /// Model (alias or provider model) to request instead
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    /// Provider to send the request to (as with an `X-Provider` header)
+    #[serde(default)]
+    pub provider: Option<String>,
+}
+
+impl ReplayOverrides {
+    /// The original request with the overrides applied, always non-streaming
+    /// so the response body can be logged
+    fn apply(&self, mut request: serde_json::Value) -> serde_json::Value {
+        if let Some(model) = &self.model {
+            request["model"] = model.clone().into();
+        }
+        if let Some(temperature) = self.temperature {
+            request["temperature"] = temperature.into();
+        }
+        request["stream"] = false.into();
+        request
+    }
+}
+
+/// POST /v1/logs/:id/replay - re-run a logged request, optionally on another model or provider
+///
+/// The replay is logged like any request, with `replay_of` pointing at the
+/// original, so the two can be compared.
+pub async fn replay(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    overrides: Option<Json<ReplayOverrides>>,
+) -> Response {
+    let Some(original) = state.request_log.get(&id) else {
+        return error_response(StatusCode::NOT_FOUND, "not_found_error", format!("No logged request '{}'", id));
+    };
+    let Some(request) = original.request_body else {
+        return error_response(
+            StatusCode::CONFLICT,
+            "invalid_request_error",
+            format!("The body of '{}' was not kept (set [server] replay_buffer)", id),
+        );
+    };
+    // Kept bodies reference large payloads in the blob store
+    let request = match &state.blob_store {
+        Some(store) => store.resolve(&request),
+        None => request,
+    };
+    let overrides = overrides.map(|Json(o)| o).unwrap_or_default();
+
+    let mut replay_headers = HeaderMap::new();
+    for name in FORWARDED_HEADERS {
+        if let Some(value) = headers.get(*name) {
+            replay_headers.insert(*name, value.clone());
+        }
+    }
+    if let Some(provider) = &overrides.provider {
+        match HeaderValue::from_str(provider) {
+            Ok(value) => {
+                replay_headers.insert("x-provider", value);
+            }
+            Err(_) => {
+                return error_response(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_request_error",
+                    format!("Invalid provider '{}'", provider),
+                )
+            }
+        }
+    }
+
+    info!("🔁 Replaying {} (model: {:?}, provider: {:?})", id, overrides.model, overrides.provider);
+    let response = match super::run_messages(
+        &state,
+        &replay_headers,
+        &original.endpoint,
+        overrides.apply(request),
+        Some(id.clone()),
+    )
+    .await
+    {
+        Ok(response) => response,
+        Err(e) => return e.into_response(),
+    };
+
+    let (parts, body) = response.into_parts();
+    let replay_id = parts.headers.get(REQUEST_ID_HEADER).and_then(|v| v.to_str().ok()).map(str::to_string);
+    let body = axum::body::to_bytes(body, MAX_RESPONSE_BYTES).await.unwrap_or_default();
+    let body: serde_json::Value = serde_json::from_slice(&body)
+        .unwrap_or_else(|_| serde_json::Value::String(String::from_utf8_lossy(&body).into_owned()));
+    let reply = serde_json::json!({
+        "id": replay_id,
+        "replay_of": id,
+        "response": body,
+    });
+    (parts.status, Json(reply)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_overrides() {
+        let request = serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "stream": true,
+            "temperature": 1.0,
+            "messages": [{ "role": "user", "content": "hi" }],
+        });
+
+        let overrides: ReplayOverrides =
+            serde_json::from_value(serde_json::json!({ "model": "gemini-2.5-pro", "temperature": 0.2 })).unwrap();
+        let replayed = overrides.apply(request.clone());
+        assert_eq!(replayed["model"], "gemini-2.5-pro");
+        assert_eq!(replayed["temperature"], 0.2);
+        assert_eq!(replayed["stream"], false);
+        assert_eq!(replayed["messages"], request["messages"]);
+
+        let replayed = ReplayOverrides::default().apply(request.clone());
+        assert_eq!(replayed["model"], request["model"]);
+        assert_eq!(replayed["temperature"], 1.0);
+    }
+}
@@ -0,0 +1,554 @@
+use axum::{
+    extract::{Query, State},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures::stream::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+
+use super::{AppError, AppState};
+use crate::cli::{ModelMapping, Redaction};
+use crate::providers::{ProviderResponse, Usage};
+use crate::storage::{BlobStore, UsageRecord};
+use crate::traces::Trace;
+use crate::transform::losses::Loss;
+
+/// Number of recent entries kept in memory for `GET /admin/logs`
+const RECENT_CAPACITY: usize = 200;
+
+/// Summary of a completed request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestLogEntry {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub endpoint: String,
+    /// Model requested by the client
+    pub model: String,
+    /// Model alias selected by the router
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub routed_model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route_type: Option<String>,
+    /// Provider that served (or last attempted) the request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actual_model: Option<String>,
+    /// Conversation id (from Claude Code's `metadata.user_id`), for pinning
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    pub stream: bool,
+    pub success: bool,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_tokens: Option<u32>,
+    /// Estimated cost in USD (when the mapping has pricing)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Full request body (only captured while someone is listening)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_body: Option<serde_json::Value>,
+    /// Full response body for non-streaming requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_body: Option<serde_json::Value>,
+    /// Content the request transformations dropped or degraded
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transform_warnings: Vec<Loss>,
+    /// Logged request this one replays
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay_of: Option<String>,
+    #[serde(skip)]
+    started: Option<Instant>,
+    /// Usage of a streamed response and its pricing, settled when the stream ends
+    #[serde(skip)]
+    stream_usage: Option<(Arc<StreamUsage>, ModelMapping)>,
+}
+
+/// Token counts of a streamed response, filled in as its events pass by
+#[derive(Debug, Default)]
+pub struct StreamUsage(Mutex<Usage>);
+
+impl StreamUsage {
+    /// Take the usage reported by a `message_start` or `message_delta` event
+    pub fn observe(&self, event: &str, data: &serde_json::Value) {
+        let usage = match event {
+            "message_start" => &data["message"]["usage"],
+            "message_delta" => &data["usage"],
+            _ => return,
+        };
+        let count = |field: &str| usage.get(field).and_then(|v| v.as_u64()).map(|v| v as u32);
+        let mut tally = self.0.lock().unwrap();
+        if let Some(tokens) = count("input_tokens").filter(|t| *t > 0) {
+            tally.input_tokens = tokens;
+        }
+        if let Some(tokens) = count("output_tokens") {
+            tally.output_tokens = tally.output_tokens.max(tokens);
+        }
+        if let Some(tokens) = count("cache_read_input_tokens") {
+            tally.cache_read_input_tokens = Some(tokens);
+        }
+        if let Some(tokens) = count("cache_creation_input_tokens") {
+            tally.cache_creation_input_tokens = Some(tokens);
+        }
+    }
+
+    pub fn get(&self) -> Usage {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl RequestLogEntry {
+    pub fn new(endpoint: &str, request_json: &serde_json::Value) -> Self {
+        Self {
+            id: format!("req_{}", uuid_like()),
+            timestamp: Utc::now(),
+            endpoint: endpoint.to_string(),
+            model: request_json
+                .get("model")
+                .and_then(|m| m.as_str())
+                .unwrap_or("unknown")
+                .to_string(),
+            routed_model: None,
+            route_type: None,
+            provider: None,
+            actual_model: None,
+            session_id: super::sessions::session_id(request_json).map(str::to_string),
+            stream: request_json.get("stream").and_then(|s| s.as_bool()).unwrap_or(false),
+            success: false,
+            latency_ms: 0,
+            input_tokens: None,
+            output_tokens: None,
+            cost_usd: None,
+            error: None,
+            request_body: None,
+            response_body: None,
+            transform_warnings: Vec::new(),
+            replay_of: None,
+            started: Some(Instant::now()),
+            stream_usage: None,
+        }
+    }
+
+    /// Record the final outcome of the request
+    pub fn finish<T>(&mut self, result: &Result<T, AppError>) {
+        self.latency_ms = self
+            .started
+            .map(|s| s.elapsed().as_millis() as u64)
+            .unwrap_or_default();
+        match result {
+            Ok(_) => self.success = true,
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+
+    /// Record usage (and optionally the full body) of a non-streaming response
+    pub fn record_response(&mut self, response: &ProviderResponse, capture_body: bool) {
+        self.input_tokens = Some(response.usage.input_tokens);
+        self.output_tokens = Some(response.usage.output_tokens);
+        if capture_body {
+            self.response_body = serde_json::to_value(response).ok();
+        }
+    }
+
+    /// Count the usage of a streamed response from `mapping` as its events pass by
+    pub fn watch_stream(&mut self, mapping: &ModelMapping) -> Arc<StreamUsage> {
+        let usage = Arc::new(StreamUsage::default());
+        self.stream_usage = Some((usage.clone(), mapping.clone()));
+        usage
+    }
+
+    /// Whether the usage of a streamed response is still to be settled
+    pub fn streaming(&self) -> bool {
+        self.stream_usage.is_some()
+    }
+
+    /// Record the usage and cost of a streamed response once it has ended
+    pub fn settle_stream(&mut self) {
+        let Some((usage, mapping)) = self.stream_usage.take() else {
+            return;
+        };
+        let usage = usage.get();
+        let cached = usage.cache_read_input_tokens.unwrap_or(0);
+        self.input_tokens = Some(usage.input_tokens);
+        self.output_tokens = Some(usage.output_tokens);
+        self.cost_usd = mapping.cost_usd(usage.input_tokens, cached, usage.output_tokens);
+    }
+
+    /// Drop everything but timing, status and token counts (`metrics = "headers_only"`)
+    pub fn strip_details(&mut self) {
+        self.model = "[redacted]".to_string();
+        self.routed_model = None;
+        self.route_type = None;
+        self.provider = None;
+        self.actual_model = None;
+        self.session_id = None;
+        self.error = None;
+        self.request_body = None;
+        self.response_body = None;
+        self.transform_warnings.clear();
+    }
+
+    /// Replace the configured parts of the bodies with size notes
+    pub fn redact(&mut self, redactions: &[Redaction]) {
+        for body in [&mut self.request_body, &mut self.response_body].into_iter().flatten() {
+            redact(body, redactions);
+        }
+    }
+
+    /// Replace large payloads in the bodies with blob store references
+    pub fn externalize(&mut self, store: &BlobStore) {
+        for body in [&mut self.request_body, &mut self.response_body].into_iter().flatten() {
+            *body = store.externalize(body);
+        }
+    }
+
+    /// Usage record persisted to the usage store
+    pub fn to_usage_record(&self) -> UsageRecord {
+        UsageRecord {
+            id: self.id.clone(),
+            timestamp: self.timestamp,
+            endpoint: self.endpoint.clone(),
+            model: self.model.clone(),
+            routed_model: self.routed_model.clone(),
+            route_type: self.route_type.clone(),
+            provider: self.provider.clone(),
+            actual_model: self.actual_model.clone(),
+            stream: self.stream,
+            success: self.success,
+            latency_ms: self.latency_ms,
+            input_tokens: self.input_tokens.unwrap_or(0),
+            output_tokens: self.output_tokens.unwrap_or(0),
+            cost_usd: self.cost_usd,
+            error: self.error.clone(),
+        }
+    }
+
+    /// Trace for observability exporters
+    pub fn to_trace(&self, tenant: Option<String>) -> Trace {
+        Trace {
+            id: self.id.clone(),
+            tenant,
+            endpoint: self.endpoint.clone(),
+            model: self.model.clone(),
+            provider: self.provider.clone(),
+            actual_model: self.actual_model.clone(),
+            start: self.timestamp,
+            end: self.timestamp + chrono::Duration::milliseconds(self.latency_ms as i64),
+            input: self.request_body.clone(),
+            output: self.response_body.clone(),
+            input_tokens: self.input_tokens,
+            output_tokens: self.output_tokens,
+            cost_usd: self.cost_usd,
+            error: self.error.clone(),
+        }
+    }
+
+    /// Copy without request/response bodies
+    pub fn summary(&self) -> Self {
+        Self {
+            request_body: None,
+            response_body: None,
+            ..self.clone()
+        }
+    }
+}
+
+/// Replace the configured parts of a Messages body with size notes
+pub fn redact(body: &mut serde_json::Value, redactions: &[Redaction]) {
+    for redaction in redactions {
+        match redaction {
+            Redaction::Images => redact_sources(body),
+            Redaction::System => {
+                if let Some(system) = body.get_mut("system") {
+                    let chars = match &*system {
+                        serde_json::Value::String(text) => text.len(),
+                        blocks => blocks
+                            .as_array()
+                            .into_iter()
+                            .flatten()
+                            .filter_map(|b| b["text"].as_str())
+                            .map(str::len)
+                            .sum(),
+                    };
+                    *system = format!("[redacted: {} chars]", chars).into();
+                }
+            }
+            Redaction::ToolResults => {
+                let messages = body.get_mut("messages").and_then(|m| m.as_array_mut());
+                for message in messages.into_iter().flatten() {
+                    let blocks = message.get_mut("content").and_then(|c| c.as_array_mut());
+                    for block in blocks.into_iter().flatten() {
+                        if block["type"] != "tool_result" {
+                            continue;
+                        }
+                        if let Some(content) = block.get_mut("content") {
+                            let chars = content.to_string().len();
+                            *content = format!("[redacted: {} chars]", chars).into();
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Base64 `data` of every image/document source, at any depth
+fn redact_sources(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(object) => {
+            if object.get("type").and_then(|t| t.as_str()) == Some("base64") {
+                if let Some(data) = object.get_mut("data") {
+                    let bytes = data.as_str().map_or(0, |d| d.len() / 4 * 3);
+                    *data = format!("[redacted: {} bytes]", bytes).into();
+                }
+            }
+            object.values_mut().for_each(redact_sources);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_sources),
+        _ => {}
+    }
+}
+
+/// Short random id (hex) for request ids
+pub(super) fn uuid_like() -> String {
+    use rand::Rng;
+    let bytes: [u8; 12] = rand::thread_rng().gen();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// In-memory request log: broadcasts live entries and keeps the most recent ones
+#[derive(Clone)]
+pub struct RequestLog {
+    sender: broadcast::Sender<Arc<RequestLogEntry>>,
+    recent: Arc<Mutex<VecDeque<RequestLogEntry>>>,
+    /// Recent entries with their bodies (`[server] replay_buffer`)
+    kept: Arc<Mutex<VecDeque<Arc<RequestLogEntry>>>>,
+    kept_capacity: usize,
+    /// Large payloads in kept and broadcast bodies are replaced by references
+    blob_store: Option<BlobStore>,
+}
+
+impl RequestLog {
+    pub fn new() -> Self {
+        Self::with_replay_buffer(0)
+    }
+
+    /// Also keep the bodies of the last `capacity` requests for replay
+    pub fn with_replay_buffer(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(256);
+        Self {
+            sender,
+            recent: Arc::new(Mutex::new(VecDeque::with_capacity(RECENT_CAPACITY))),
+            kept: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            kept_capacity: capacity,
+            blob_store: None,
+        }
+    }
+
+    /// Externalize large payloads before entries are kept or broadcast
+    pub fn with_blob_store(mut self, blob_store: Option<BlobStore>) -> Self {
+        self.blob_store = blob_store;
+        self
+    }
+
+    /// Whether full bodies should be captured (someone is tailing the log)
+    pub fn has_subscribers(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+
+    /// Whether full bodies should be captured for replay
+    pub fn keeps_bodies(&self) -> bool {
+        self.kept_capacity > 0
+    }
+
+    pub fn record(&self, mut entry: RequestLogEntry) {
+        if let Some(store) = &self.blob_store {
+            entry.externalize(store);
+        }
+        let entry = Arc::new(entry);
+        {
+            let mut recent = self.recent.lock().unwrap();
+            if recent.len() == RECENT_CAPACITY {
+                recent.pop_front();
+            }
+            // Bodies are only kept for live subscribers, not in the recent buffer
+            recent.push_back(entry.summary());
+        }
+        if self.keeps_bodies() && entry.request_body.is_some() {
+            let mut kept = self.kept.lock().unwrap();
+            if kept.len() == self.kept_capacity {
+                kept.pop_front();
+            }
+            kept.push_back(entry.clone());
+        }
+        // Err means no subscribers, which is fine
+        let _ = self.sender.send(entry);
+    }
+
+    pub fn recent(&self) -> Vec<RequestLogEntry> {
+        self.recent.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// A recent entry, with its bodies when they were kept
+    pub fn get(&self, id: &str) -> Option<RequestLogEntry> {
+        if let Some(entry) = self.kept.lock().unwrap().iter().rev().find(|e| e.id == id) {
+            return Some((**entry).clone());
+        }
+        self.recent.lock().unwrap().iter().rev().find(|e| e.id == id).cloned()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<RequestLogEntry>> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for RequestLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogStreamQuery {
+    /// Include full request/response bodies
+    #[serde(default)]
+    pub bodies: bool,
+}
+
+/// GET /admin/logs - recent request summaries
+pub async fn list_logs(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(state.request_log.recent())
+}
+
+/// GET /admin/logs/stream - live SSE feed of request summaries
+pub async fn stream_logs(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<LogStreamQuery>,
+) -> Sse<impl Stream<Item = Result<Event, std::convert::Infallible>>> {
+    let stream = BroadcastStream::new(state.request_log.subscribe()).filter_map(move |entry| {
+        let event = match entry {
+            Ok(entry) => {
+                let entry = if query.bodies { (*entry).clone() } else { entry.summary() };
+                Event::default().event("request").json_data(&entry).ok()
+            }
+            Err(BroadcastStreamRecvError::Lagged(skipped)) => Some(
+                Event::default()
+                    .event("lagged")
+                    .data(format!("{{\"skipped\":{}}}", skipped)),
+            ),
+        };
+        futures::future::ready(event.map(Ok))
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_strips_bodies_and_subscribers_get_them() {
+        let log = RequestLog::new();
+        let mut rx = log.subscribe();
+        assert!(log.has_subscribers());
+
+        let request = serde_json::json!({"model": "claude-sonnet", "stream": true});
+        let mut entry = RequestLogEntry::new("/v1/messages", &request);
+        entry.request_body = Some(request.clone());
+        entry.finish::<()>(&Ok(()));
+        log.record(entry);
+
+        let recent = log.recent();
+        assert_eq!(recent.len(), 1);
+        assert!(recent[0].success && recent[0].stream);
+        assert!(recent[0].request_body.is_none());
+
+        let live = rx.try_recv().unwrap();
+        assert_eq!(live.request_body.as_ref(), Some(&request));
+        assert!(log.get(&live.id).unwrap().request_body.is_none());
+    }
+
+    #[test]
+    fn test_replay_buffer_keeps_bodies() {
+        let log = RequestLog::with_replay_buffer(1);
+        let request = serde_json::json!({"model": "claude-sonnet"});
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let mut entry = RequestLogEntry::new("/v1/messages", &request);
+            entry.request_body = Some(request.clone());
+            ids.push(entry.id.clone());
+            log.record(entry);
+        }
+
+        assert_eq!(log.get(&ids[1]).unwrap().request_body.as_ref(), Some(&request));
+        // Evicted from the replay buffer, still in the recent summaries
+        assert!(log.get(&ids[0]).unwrap().request_body.is_none());
+        assert!(log.get("req_missing").is_none());
+    }
+
+    #[test]
+    fn test_kept_bodies_are_externalized() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let store = BlobStore::new(temp_dir.path().to_path_buf(), 8).unwrap();
+        let log = RequestLog::with_replay_buffer(1).with_blob_store(Some(store.clone()));
+        let mut rx = log.subscribe();
+
+        let request = serde_json::json!({"model": "claude-sonnet", "messages": [{ "role": "user", "content": [
+            { "type": "image", "source": { "type": "base64", "media_type": "image/png", "data": "AAAAAAAAAAAA" } },
+        ] }]});
+        let mut entry = RequestLogEntry::new("/v1/messages", &request);
+        entry.request_body = Some(request.clone());
+        let id = entry.id.clone();
+        log.record(entry);
+
+        let kept = log.get(&id).unwrap().request_body.unwrap();
+        let data = kept["messages"][0]["content"][0]["source"]["data"].as_str().unwrap();
+        assert!(data.starts_with(crate::storage::blobs::BLOB_REF_PREFIX));
+        assert_eq!(rx.try_recv().unwrap().request_body.as_ref(), Some(&kept));
+        // Replays get the original payload back
+        assert_eq!(store.resolve(&kept), request);
+    }
+
+    #[test]
+    fn test_redact_bodies() {
+        let mut body = serde_json::json!({
+            "model": "claude-sonnet",
+            "system": [{ "type": "text", "text": "secret" }],
+            "messages": [{ "role": "user", "content": [
+                { "type": "tool_result", "tool_use_id": "t1", "content": [
+                    { "type": "image", "source": { "type": "base64", "media_type": "image/png", "data": "AAAABBBB" } },
+                ] },
+                { "type": "image", "source": { "type": "base64", "media_type": "image/png", "data": "AAAABBBB" } },
+                { "type": "text", "text": "hi" },
+            ] }],
+        });
+        redact(&mut body, &[Redaction::Images, Redaction::System]);
+        assert_eq!(body["system"], "[redacted: 6 chars]");
+        assert_eq!(body["messages"][0]["content"][1]["source"]["data"], "[redacted: 6 bytes]");
+        assert_eq!(
+            body["messages"][0]["content"][0]["content"][0]["source"]["data"],
+            "[redacted: 6 bytes]"
+        );
+        assert_eq!(body["messages"][0]["content"][2]["text"], "hi");
+
+        redact(&mut body, &[Redaction::ToolResults]);
+        assert!(body["messages"][0]["content"][0]["content"]
+            .as_str()
+            .unwrap()
+            .starts_with("[redacted: "));
+    }
+}
@@ -0,0 +1,247 @@
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, info};
+
+use crate::cli::ModelMapping;
+use crate::storage::AuditEntry;
+
+use super::limits::error_response;
+use super::{audit, AppState};
+
+/// Pins of conversations that have been quiet this long are dropped
+const PIN_IDLE_TTL: Duration = Duration::from_secs(24 * 3600);
+
+/// Session part of Claude Code's `metadata.user_id` (`user_..._session_<id>`)
+pub fn session_id(request_json: &serde_json::Value) -> Option<&str> {
+    let user_id = request_json.pointer("/metadata/user_id")?.as_str()?;
+    user_id.split_once("_session_").map(|(_, session)| session).filter(|s| !s.is_empty())
+}
+
+/// Conversations pinned to a provider through the admin API
+#[derive(Debug, Default)]
+pub struct SessionPins {
+    pins: Mutex<HashMap<String, SessionPin>>,
+}
+
+/// A pinned conversation, as listed by `GET /admin/sessions`
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionPin {
+    pub session_id: String,
+    pub provider: String,
+    /// Model to request from the provider (default: the alias's mapping for it)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    pub pinned_at: DateTime<Utc>,
+    #[serde(skip)]
+    last_used: Instant,
+}
+
+impl SessionPin {
+    /// Mappings to try for an alias: the alias's own mappings to the pinned
+    /// provider, or one built from the pinned model
+    ///
+    /// Empty when the pin can't serve the alias (the provider has no mapping
+    /// and no model was given).
+    pub fn mappings(&self, alias_mappings: &[ModelMapping]) -> Vec<ModelMapping> {
+        let mut mappings: Vec<ModelMapping> = alias_mappings
+            .iter()
+            .filter(|m| m.provider == self.provider)
+            .cloned()
+            .collect();
+        mappings.sort_by_key(|m| m.priority);
+        let Some(model) = &self.model else {
+            return mappings;
+        };
+        let mut mapping = mappings.into_iter().next().unwrap_or_else(|| ModelMapping {
+            priority: 0,
+            provider: self.provider.clone(),
+            actual_model: model.clone(),
+            tool_policy: None,
+            input_cost_per_mtok: None,
+            output_cost_per_mtok: None,
+            cached_input_cost_per_mtok: None,
+            fim: None,
+            continuation: None,
+        });
+        mapping.actual_model = model.clone();
+        vec![mapping]
+    }
+}
+
+impl SessionPins {
+    pub fn pin(&self, session_id: &str, provider: &str, model: Option<String>) -> SessionPin {
+        let pin = SessionPin {
+            session_id: session_id.to_string(),
+            provider: provider.to_string(),
+            model,
+            pinned_at: Utc::now(),
+            last_used: Instant::now(),
+        };
+        self.pins.lock().unwrap().insert(session_id.to_string(), pin.clone());
+        pin
+    }
+
+    /// Remove a pin; false when the session wasn't pinned
+    pub fn unpin(&self, session_id: &str) -> bool {
+        self.pins.lock().unwrap().remove(session_id).is_some()
+    }
+
+    /// The pin of a session, keeping it alive
+    pub fn get(&self, session_id: &str) -> Option<SessionPin> {
+        let mut pins = self.pins.lock().unwrap();
+        pins.retain(|_, pin| pin.last_used.elapsed() < PIN_IDLE_TTL);
+        let pin = pins.get_mut(session_id)?;
+        pin.last_used = Instant::now();
+        Some(pin.clone())
+    }
+
+    pub fn list(&self) -> Vec<SessionPin> {
+        let mut pins = self.pins.lock().unwrap();
+        pins.retain(|_, pin| pin.last_used.elapsed() < PIN_IDLE_TTL);
+        let mut list: Vec<SessionPin> = pins.values().cloned().collect();
+        list.sort_by_key(|pin| pin.pinned_at);
+        list
+    }
+}
+
+/// Caps simultaneous requests per conversation, so one agent swarm can't
+/// take a provider's whole rate limit (`[server.limits] max_requests_per_session`)
+#[derive(Debug, Default)]
+pub struct SessionLimits {
+    gates: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl SessionLimits {
+    /// Wait (in arrival order) for a slot of the session
+    ///
+    /// None when there's no limit or the request names no session. A changed
+    /// limit applies once the session has no requests running.
+    pub async fn acquire(&self, session_id: Option<&str>, limit: Option<usize>) -> Option<OwnedSemaphorePermit> {
+        let (session_id, limit) = (session_id?, limit?);
+        let gate = {
+            let mut gates = self.gates.lock().unwrap();
+            // Gates nobody holds or waits on are dropped
+            gates.retain(|_, gate| Arc::strong_count(gate) > 1);
+            gates
+                .entry(session_id.to_string())
+                .or_insert_with(|| Arc::new(Semaphore::new(limit.max(1))))
+                .clone()
+        };
+        if gate.available_permits() == 0 {
+            debug!("⏳ Session {} has {} requests running, queueing", session_id, limit);
+        }
+        gate.acquire_owned().await.ok()
+    }
+}
+
+/// GET /admin/sessions - pinned conversations
+pub async fn list_pins(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "pins": state.session_pins.list() }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PinRequest {
+    pub provider: String,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// POST /admin/sessions/:id/pin - send the rest of a conversation to one provider
+pub async fn pin_session(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(request): Json<PinRequest>,
+) -> Response {
+    if state.active().provider_registry.get_provider(&request.provider).is_none() {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            format!("Unknown provider '{}'", request.provider),
+        );
+    }
+    info!("📌 Pinning session {} to {}", id, request.provider);
+    let pin = state.session_pins.pin(&id, &request.provider, request.model);
+    state.audit_log.record(
+        AuditEntry::new(audit::actor(&headers), "sessions.pin")
+            .target(id)
+            .diff(&serde_json::json!({}), &serde_json::json!({ "provider": pin.provider, "model": pin.model })),
+    );
+    Json(pin).into_response()
+}
+
+/// POST /admin/sessions/:id/unpin - return a conversation to normal routing
+pub async fn unpin_session(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    if !state.session_pins.unpin(&id) {
+        return error_response(
+            StatusCode::NOT_FOUND,
+            "not_found_error",
+            format!("Session '{}' is not pinned", id),
+        );
+    }
+    info!("📌 Unpinned session {}", id);
+    state
+        .audit_log
+        .record(AuditEntry::new(audit::actor(&headers), "sessions.unpin").target(id.clone()));
+    Json(serde_json::json!({ "unpinned": id })).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_pin() {
+        let request = serde_json::json!({ "metadata": { "user_id": "user_abc_account_123_session_9f2e" } });
+        assert_eq!(session_id(&request), Some("9f2e"));
+
+        let pins = SessionPins::default();
+        pins.pin("9f2e", "zai", None);
+        assert!(pins.get("other").is_none());
+        let pin = pins.get("9f2e").unwrap();
+
+        let mappings: Vec<ModelMapping> = serde_json::from_value(serde_json::json!([
+            { "priority": 1, "provider": "anthropic", "actual_model": "claude-sonnet-4-5" },
+            { "priority": 2, "provider": "zai", "actual_model": "glm-4.6" },
+        ]))
+        .unwrap();
+        assert_eq!(pin.mappings(&mappings)[0].actual_model, "glm-4.6");
+
+        let pin = pins.pin("9f2e", "openrouter", Some("qwen/qwen3-coder".to_string()));
+        assert_eq!(pin.mappings(&mappings)[0].provider, "openrouter");
+        assert!(pins.unpin("9f2e"));
+        assert!(pins.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_session_limits() {
+        let limits = SessionLimits::default();
+        assert!(limits.acquire(Some("a"), None).await.is_none());
+        assert!(limits.acquire(None, Some(2)).await.is_none());
+
+        let first = limits.acquire(Some("a"), Some(2)).await;
+        let _second = limits.acquire(Some("a"), Some(2)).await;
+        let queued = tokio::time::timeout(Duration::from_millis(20), limits.acquire(Some("a"), Some(2))).await;
+        assert!(queued.is_err());
+        // Other conversations aren't held up
+        assert!(limits.acquire(Some("b"), Some(2)).await.is_some());
+
+        drop(first);
+        let third = tokio::time::timeout(Duration::from_millis(20), limits.acquire(Some("a"), Some(2))).await;
+        assert!(third.unwrap().is_some());
+    }
+}
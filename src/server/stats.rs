@@ -0,0 +1,151 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{NaiveDate, Utc};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::reports::UsageReport;
+use crate::storage::KvStore;
+
+use super::request_log::RequestLogEntry;
+use super::AppState;
+
+/// Shared usage counters are kept for a week
+const SHARED_USAGE_TTL: std::time::Duration = std::time::Duration::from_secs(8 * 24 * 3600);
+
+#[derive(Debug, Deserialize)]
+pub struct HealthQuery {
+    /// Look-back window in hours (default 24)
+    #[serde(default = "default_hours")]
+    pub hours: i64,
+}
+
+fn default_hours() -> i64 {
+    24
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    /// UTC day (YYYY-MM-DD), defaults to today
+    pub date: Option<NaiveDate>,
+}
+
+fn database_disabled() -> Response {
+    (StatusCode::NOT_FOUND, "Database is disabled").into_response()
+}
+
+/// GET /admin/health - provider success/failure counts from the health history
+pub async fn provider_health(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<HealthQuery>,
+) -> Response {
+    let Some(store) = &state.health_store else {
+        return database_disabled();
+    };
+    let since = Utc::now() - chrono::Duration::hours(query.hours);
+    match store.summary(since).await {
+        Ok(summary) => Json(summary).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// GET /admin/failures - provider failures by kind, overall and per hour
+pub async fn provider_failures(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<HealthQuery>,
+) -> Response {
+    let Some(store) = &state.health_store else {
+        return database_disabled();
+    };
+    let since = Utc::now() - chrono::Duration::hours(query.hours);
+    match store.failures(since).await {
+        Ok(failures) => Json(failures).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// GET /admin/scores - average judge grades per provider and model
+pub async fn quality_scores(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<HealthQuery>,
+) -> Response {
+    let Some(store) = &state.score_store else {
+        return database_disabled();
+    };
+    let since = Utc::now() - chrono::Duration::hours(query.hours);
+    match store.summary(since).await {
+        Ok(summary) => Json(summary).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// GET /admin/usage - aggregated usage for one day
+pub async fn usage(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<UsageQuery>,
+) -> Response {
+    let Some(store) = &state.usage_store else {
+        return database_disabled();
+    };
+    let date = query.date.unwrap_or_else(|| Utc::now().date_naive());
+    match store.load_day(date).await {
+        Ok(records) => Json(UsageReport::from_records(date, &records)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+fn shared_usage_key(date: NaiveDate) -> String {
+    format!("usage:{}", date.format("%Y-%m-%d"))
+}
+
+/// Add a finished request to the per-day usage counters shared by all replicas
+pub async fn record_shared_usage(kv: &dyn KvStore, entry: &RequestLogEntry) {
+    let key = shared_usage_key(entry.timestamp.date_naive());
+    let provider = entry.provider.as_deref().unwrap_or("unknown");
+    let counters = [
+        ("requests", 1),
+        ("errors", i64::from(!entry.success)),
+        ("input_tokens", entry.input_tokens.unwrap_or(0) as i64),
+        ("output_tokens", entry.output_tokens.unwrap_or(0) as i64),
+    ];
+    for (name, by) in counters {
+        if by == 0 {
+            continue;
+        }
+        let field = format!("{}:{}", provider, name);
+        if let Err(e) = kv.hincr(&key, &field, by, Some(SHARED_USAGE_TTL)).await {
+            tracing::warn!("Failed to update shared usage counters: {}", e);
+            return;
+        }
+    }
+}
+
+/// GET /admin/usage/live - per-provider counters aggregated across replicas
+pub async fn shared_usage(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<UsageQuery>,
+) -> Response {
+    let date = query.date.unwrap_or_else(|| Utc::now().date_naive());
+    let counters = match state.shared.hgetall(&shared_usage_key(date)).await {
+        Ok(counters) => counters,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    // "provider:counter" fields -> { provider: { counter: value } }
+    let mut providers: BTreeMap<String, BTreeMap<String, i64>> = BTreeMap::new();
+    for (field, value) in counters {
+        if let Some((provider, counter)) = field.rsplit_once(':') {
+            providers
+                .entry(provider.to_string())
+                .or_default()
+                .insert(counter.to_string(), value);
+        }
+    }
+
+    Json(serde_json::json!({ "date": date, "providers": providers })).into_response()
+}
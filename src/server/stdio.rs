@@ -0,0 +1,219 @@
+//! Newline-delimited JSON over stdin/stdout (`ccm start --stdio`)
+//!
+//! Editor plugins spawn the mux as a subprocess instead of talking to a port.
+//! Each request line is dispatched in-process to the HTTP routes, so client
+//! auth, limits, routing and logging behave exactly as over HTTP.
+
+use axum::body::Body;
+use axum::http::{header, Method, Request, Response};
+use axum::Router;
+use futures::stream::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tower::Service;
+use tracing::info;
+
+use crate::providers::streaming::{parse_sse_events, LineBuffer};
+
+/// Largest response body accepted from the HTTP handlers
+const MAX_RESPONSE_BYTES: usize = 64 * 1024 * 1024;
+
+/// One request line
+#[derive(Debug, Deserialize)]
+struct Envelope {
+    /// Echoed on every line answering this request
+    #[serde(default)]
+    id: Value,
+    #[serde(default = "default_method")]
+    method: String,
+    #[serde(default = "default_path")]
+    path: String,
+    /// Client key, anthropic-version, X-Provider and other HTTP headers
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    /// Request body (e.g. an Anthropic Messages request)
+    #[serde(default)]
+    body: Value,
+}
+
+fn default_method() -> String {
+    "POST".to_string()
+}
+
+fn default_path() -> String {
+    "/v1/messages".to_string()
+}
+
+/// Answer request lines from stdin until it closes
+///
+/// Requests run concurrently; their replies are interleaved on stdout and
+/// matched up by `id`.
+pub async fn serve(app: Router) -> anyhow::Result<()> {
+    info!("🚀 Serving newline-delimited JSON on stdin/stdout");
+    let (tx, mut rx) = mpsc::unbounded_channel::<Value>();
+    let writer = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(line) = rx.recv().await {
+            let mut bytes = serde_json::to_vec(&line)?;
+            bytes.push(b'\n');
+            stdout.write_all(&bytes).await?;
+            stdout.flush().await?;
+        }
+        Ok::<_, std::io::Error>(())
+    });
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (app, tx) = (app.clone(), tx.clone());
+        tokio::spawn(async move { handle_line(app, &line, &tx).await });
+    }
+
+    // The writer finishes once every running request has replied
+    drop(tx);
+    writer.await??;
+    Ok(())
+}
+
+/// Answer one request line
+///
+/// Plain responses are a single `{"id", "status", "body"}` line. Streams are
+/// one `{"id", "event", "data"}` line per SSE event, then
+/// `{"id", "status", "done": true}`.
+async fn handle_line(app: Router, line: &str, tx: &UnboundedSender<Value>) {
+    let envelope: Envelope = match serde_json::from_str(line) {
+        Ok(envelope) => envelope,
+        Err(e) => {
+            let body = error_body("invalid_request_error", format!("Request line is not a valid envelope: {}", e));
+            let _ = tx.send(json!({ "id": null, "status": 400, "body": body }));
+            return;
+        }
+    };
+    let id = envelope.id.clone();
+    let response = match dispatch(app, envelope).await {
+        Ok(response) => response,
+        Err(message) => {
+            let body = error_body("invalid_request_error", message);
+            let _ = tx.send(json!({ "id": id, "status": 400, "body": body }));
+            return;
+        }
+    };
+
+    let (parts, body) = response.into_parts();
+    let status = parts.status.as_u16();
+    let is_stream = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    if !is_stream {
+        let bytes = axum::body::to_bytes(body, MAX_RESPONSE_BYTES).await.unwrap_or_default();
+        let body = serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+        let _ = tx.send(json!({ "id": id, "status": status, "body": body }));
+        return;
+    }
+
+    let mut stream = body.into_data_stream();
+    let mut buffer = LineBuffer::default();
+    while let Some(chunk) = stream.next().await {
+        match chunk {
+            Ok(bytes) => buffer.push(&bytes),
+            Err(e) => {
+                let data = error_body("api_error", format!("Stream interrupted: {}", e));
+                let _ = tx.send(json!({ "id": id, "event": "error", "data": data }));
+                break;
+            }
+        }
+        while let Some(raw) = buffer.event() {
+            for event in parse_sse_events(&raw) {
+                let data = serde_json::from_str(&event.data).unwrap_or(Value::String(event.data));
+                let _ = tx.send(json!({ "id": id, "event": event.event, "data": data }));
+            }
+        }
+    }
+    let _ = tx.send(json!({ "id": id, "status": status, "done": true }));
+}
+
+/// Run an envelope through the HTTP router
+async fn dispatch(mut app: Router, envelope: Envelope) -> Result<Response<Body>, String> {
+    let method = Method::from_bytes(envelope.method.to_uppercase().as_bytes())
+        .map_err(|_| format!("Invalid method '{}'", envelope.method))?;
+    let mut request = Request::builder().method(method).uri(&envelope.path);
+    for (name, value) in &envelope.headers {
+        request = request.header(name.as_str(), value.as_str());
+    }
+    let request = if envelope.body.is_null() {
+        request.body(Body::empty())
+    } else {
+        request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(envelope.body.to_string()))
+    }
+    .map_err(|e| e.to_string())?;
+
+    Ok(app.call(request).await.unwrap_or_else(|e: Infallible| match e {}))
+}
+
+fn error_body(error_type: &str, message: String) -> Value {
+    json!({ "type": "error", "error": { "type": error_type, "message": message } })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn replies(app: Router, line: &str) -> Vec<Value> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        handle_line(app, line, &tx).await;
+        drop(tx);
+        let mut replies = Vec::new();
+        while let Some(reply) = rx.recv().await {
+            replies.push(reply);
+        }
+        replies
+    }
+
+    #[tokio::test]
+    async fn test_handle_line() {
+        let app = Router::new()
+            .route(
+                "/v1/messages",
+                axum::routing::post(|headers: axum::http::HeaderMap, body: String| async move {
+                    match headers.get("x-api-key") {
+                        Some(_) => Ok(([(header::CONTENT_TYPE, "application/json")], body)),
+                        None => Err(axum::http::StatusCode::UNAUTHORIZED),
+                    }
+                }),
+            )
+            .route(
+                "/stream",
+                axum::routing::post(|| async {
+                    (
+                        [(header::CONTENT_TYPE, "text/event-stream")],
+                        "event: message_start\ndata: {\"a\":1}\n\nevent: message_stop\ndata: {}\n\n",
+                    )
+                }),
+            );
+
+        let reply = replies(app.clone(), r#"{"id":7,"headers":{"x-api-key":"k"},"body":{"model":"m"}}"#).await;
+        assert_eq!(reply, vec![json!({ "id": 7, "status": 200, "body": { "model": "m" } })]);
+
+        let reply = replies(app.clone(), r#"{"id":"a","body":{}}"#).await;
+        assert_eq!(reply[0]["status"], 401);
+
+        let reply = replies(app.clone(), r#"{"id":"s","path":"/stream","body":{"stream":true}}"#).await;
+        assert_eq!(reply.len(), 3);
+        assert_eq!(reply[0], json!({ "id": "s", "event": "message_start", "data": { "a": 1 } }));
+        assert_eq!(reply[2], json!({ "id": "s", "status": 200, "done": true }));
+
+        let reply = replies(app, "not json").await;
+        assert_eq!(reply[0]["body"]["error"]["type"], "invalid_request_error");
+    }
+}
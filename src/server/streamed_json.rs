@@ -0,0 +1,132 @@
+//! JSON request bodies parsed while they upload
+//!
+//! axum's `Json` buffers the whole body before parsing it, so a large prompt
+//! is held twice (raw bytes and parsed value) and nothing happens until the
+//! last byte arrives. Here chunks are handed to a blocking parser as they come
+//! in: malformed JSON is rejected without waiting for the rest of the upload,
+//! and the raw body is never held in full.
+
+use axum::{
+    async_trait,
+    body::Body,
+    extract::{FromRequest, Request},
+    http::{header, StatusCode},
+    response::Response,
+};
+use bytes::{Buf, Bytes};
+use futures::StreamExt;
+use serde_json::Value;
+use std::io::Read;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use super::limits::{error_response, too_large};
+use super::AppState;
+
+/// Chunks buffered between the connection and the parser
+const CHANNEL_CHUNKS: usize = 16;
+
+/// A JSON request body, parsed incrementally
+pub struct StreamedJson(pub Value);
+
+#[async_trait]
+impl FromRequest<Arc<AppState>> for StreamedJson {
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        let is_json = request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|t| t.starts_with("application/json"));
+        if !is_json {
+            return Err(error_response(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "invalid_request_error",
+                "Expected request with `Content-Type: application/json`".to_string(),
+            ));
+        }
+        let max_bytes = state.active().config.server.limits.max_request_bytes;
+        parse(request.into_body(), max_bytes).await.map(StreamedJson)
+    }
+}
+
+/// Parse a body as it arrives, stopping early on malformed or oversized input
+async fn parse(body: Body, max_bytes: usize) -> Result<Value, Response> {
+    let (tx, rx) = mpsc::channel::<Bytes>(CHANNEL_CHUNKS);
+    let parser = tokio::task::spawn_blocking(move || {
+        serde_json::from_reader::<_, Value>(ChunkReader {
+            rx,
+            chunk: Bytes::new(),
+        })
+    });
+
+    let mut received = 0usize;
+    let mut frames = body.into_data_stream();
+    while let Some(frame) = frames.next().await {
+        let chunk = frame.map_err(|e| invalid(format!("Failed to read request body: {}", e)))?;
+        received += chunk.len();
+        if received > max_bytes {
+            return Err(too_large(received, max_bytes));
+        }
+        // The parser hung up: the JSON is complete or malformed
+        if tx.send(chunk).await.is_err() {
+            break;
+        }
+    }
+    drop(tx);
+
+    match parser.await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(invalid(format!("Invalid JSON body: {}", e))),
+        Err(e) => Err(invalid(format!("Failed to parse request body: {}", e))),
+    }
+}
+
+fn invalid(message: String) -> Response {
+    error_response(StatusCode::BAD_REQUEST, "invalid_request_error", message)
+}
+
+/// Blocking reader over body chunks sent from the async side
+struct ChunkReader {
+    rx: mpsc::Receiver<Bytes>,
+    chunk: Bytes,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while !self.chunk.has_remaining() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => self.chunk = chunk,
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len());
+        self.chunk.copy_to_slice(&mut buf[..n]);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunked(parts: &[&'static str]) -> Body {
+        let chunks: Vec<Result<Bytes, std::io::Error>> = parts.iter().map(|p| Ok(Bytes::from(*p))).collect();
+        Body::from_stream(futures::stream::iter(chunks))
+    }
+
+    #[tokio::test]
+    async fn test_parse_chunked_body() {
+        let value = parse(chunked(&["{\"model\": \"son", "net\", \"messages\"", ": []}"]), 1024)
+            .await
+            .unwrap();
+        assert_eq!(value["model"], "sonnet");
+
+        let err = parse(chunked(&["{\"model\": ", "]"]), 1024).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+
+        let err = parse(chunked(&["{\"model\": \"", "sonnet\"}"]), 16).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
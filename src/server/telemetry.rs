@@ -0,0 +1,155 @@
+//! Claude Code's auxiliary endpoints
+//!
+//! Besides the Messages API, Claude Code posts event logs and metrics to its
+//! base URL and asks it for feature settings. Without a route these 404,
+//! which fills the logs and makes the client retry. They're answered with an
+//! empty success, or forwarded to Anthropic when there's an OAuth login.
+
+use axum::{
+    body::Bytes,
+    extract::{RawQuery, State},
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::debug;
+
+use crate::cli::TelemetryMode;
+use crate::providers::{http, AuthType};
+
+use super::AppState;
+
+/// Paths Claude Code calls on its base URL
+pub const PATHS: &[&str] = &[
+    "/api/event_logging/batch",
+    "/api/claude_code/metrics",
+    "/api/claude_code/organizations/metrics_enabled",
+    "/api/hello",
+];
+
+const ANTHROPIC_URL: &str = "https://api.anthropic.com";
+
+/// Proxied calls give up quickly; the client doesn't wait on them
+const PROXY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Benign reply for a telemetry path
+fn stub(path: &str, body: &[u8]) -> Value {
+    match path {
+        "/api/event_logging/batch" => {
+            let events = serde_json::from_slice::<Value>(body)
+                .ok()
+                .and_then(|body| body["events"].as_array().map(Vec::len))
+                .unwrap_or(0);
+            json!({ "accepted_count": events, "rejected_count": 0 })
+        }
+        "/api/claude_code/organizations/metrics_enabled" => {
+            json!({ "metrics_logging_enabled": false })
+        }
+        _ => json!({}),
+    }
+}
+
+/// ANY on the telemetry paths
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    method: Method,
+    uri: Uri,
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let path = uri.path();
+    if state.active().config.server.telemetry.mode == TelemetryMode::Proxy {
+        match proxy(
+            &state,
+            method,
+            path,
+            query.as_deref(),
+            &headers,
+            body.clone(),
+        )
+        .await
+        {
+            Ok(response) => return response,
+            Err(reason) => debug!("📡 Stubbing {} ({})", path, reason),
+        }
+    }
+    (StatusCode::OK, Json(stub(path, &body))).into_response()
+}
+
+/// Forward a call to Anthropic with the first Anthropic OAuth login
+async fn proxy(
+    state: &AppState,
+    method: Method,
+    path: &str,
+    query: Option<&str>,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Result<Response, String> {
+    let active = state.active();
+    let provider = active
+        .config
+        .providers
+        .iter()
+        .find(|p| {
+            p.is_enabled() && p.provider_type == "anthropic" && p.auth_type == AuthType::OAuth
+        })
+        .ok_or("no Anthropic OAuth provider")?;
+    let token = provider
+        .oauth_provider
+        .as_deref()
+        .and_then(|id| state.token_store.get(id))
+        .filter(|token| !token.is_expired())
+        .ok_or("no valid OAuth token")?;
+
+    let mut url = format!("{}{}", ANTHROPIC_URL, path);
+    if let Some(query) = query {
+        url = format!("{}?{}", url, query);
+    }
+    let mut request = http::client(provider, &active.config.server.timeouts)
+        .request(method, &url)
+        .bearer_auth(token.access_token)
+        .header("anthropic-beta", "oauth-2025-04-20")
+        .timeout(PROXY_TIMEOUT);
+    // Bodies arrive already inflated, so Content-Encoding isn't passed on
+    for name in [header::CONTENT_TYPE, header::USER_AGENT] {
+        if let Some(value) = headers.get(&name) {
+            request = request.header(name, value);
+        }
+    }
+    let response = request.body(body).send().await.map_err(|e| e.to_string())?;
+    let status =
+        StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    if status.is_server_error() {
+        return Err(format!("upstream answered {}", status));
+    }
+    let content_type = response.headers().get(header::CONTENT_TYPE).cloned();
+    let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+    let mut response = (status, bytes).into_response();
+    if let Some(content_type) = content_type {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, content_type);
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stub_replies() {
+        let batch = br#"{"events": [{"event_type": "a"}, {"event_type": "b"}]}"#;
+        assert_eq!(stub("/api/event_logging/batch", batch)["accepted_count"], 2);
+        assert_eq!(stub("/api/event_logging/batch", b"")["accepted_count"], 0);
+        assert_eq!(
+            stub("/api/claude_code/organizations/metrics_enabled", b"")["metrics_logging_enabled"],
+            false
+        );
+        assert_eq!(stub("/api/claude_code/settings", b""), json!({}));
+    }
+}
@@ -0,0 +1,243 @@
+//! Pre-flight request checks
+//!
+//! `POST /v1/messages/validate` takes a `/v1/messages` body and runs the
+//! checks and transforms that happen before a provider is called: parsing,
+//! image limits, routing, guardrails, and each mapping's request preparation.
+//! The report says whether the request would go out, where, with which lossy
+//! transforms, at roughly how many tokens and what cost. Pipelines built
+//! against the mux can check their prompts in CI without spending tokens.
+
+use axum::{extract::State, http::HeaderMap, Json};
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::models::AnthropicRequest;
+use crate::providers::vision::VisionFormula;
+use crate::transform::{self, losses::Loss};
+
+use super::config_reload::ActiveConfig;
+use super::explain::{self, RouteExplanation};
+use super::{client_api_key, client_role, limits, AppError, AppState};
+
+/// Outcome of the pre-flight checks
+#[derive(Debug, Serialize)]
+pub struct ValidationReport {
+    /// Whether `/v1/messages` would send the request to a provider
+    pub valid: bool,
+    /// Problems that would fail the request
+    pub errors: Vec<String>,
+    /// Routing decision (absent when the request can't be parsed or routed)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route: Option<RouteExplanation>,
+    /// Mappings that would be tried, in order
+    pub providers: Vec<ProviderReport>,
+}
+
+/// The request as one mapping would receive it
+#[derive(Debug, Serialize)]
+pub struct ProviderReport {
+    pub provider: String,
+    pub model: String,
+    /// "ok" or "rejected"
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Lossy transforms applied for this provider
+    pub warnings: Vec<Loss>,
+    /// Estimated prompt tokens after the transforms
+    pub input_tokens: u32,
+    /// Cost of the prompt plus a full `max_tokens` answer (None without pricing)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_cost_usd: Option<f64>,
+}
+
+impl ValidationReport {
+    fn failed(error: String, route: Option<RouteExplanation>) -> Self {
+        Self {
+            valid: false,
+            errors: vec![error],
+            route,
+            providers: Vec::new(),
+        }
+    }
+}
+
+/// Run the pre-flight checks on a request body
+pub async fn validate(active: &ActiveConfig, headers: &HeaderMap, request_json: &serde_json::Value) -> ValidationReport {
+    let mut request: AnthropicRequest = match serde_json::from_value(request_json.clone()) {
+        Ok(request) => request,
+        Err(e) => return ValidationReport::failed(format!("Invalid request format: {}", e), None),
+    };
+    if let Err(e) = limits::check_images(&request, &active.config.server.limits) {
+        return ValidationReport::failed(error_message(e), None);
+    }
+
+    let route = match explain::explain(active, headers, request_json) {
+        Ok(route) => route,
+        Err(e) => return ValidationReport::failed(e, None),
+    };
+    // Routing may strip tags from the system prompt; the mappings get the routed request
+    if let Err(e) = active.router.route_as(&mut request, client_role(headers)) {
+        return ValidationReport::failed(e.to_string(), Some(route));
+    }
+    if let Err(e) = active
+        .config
+        .guardrails
+        .limits_for(client_api_key(headers))
+        .apply(&mut request)
+    {
+        return ValidationReport::failed(e, Some(route));
+    }
+
+    let mut providers = Vec::new();
+    let model_config = active.model(&route.alias);
+    let tried = route.providers.iter().filter(|check| check.status != "excluded");
+    for check in tried {
+        let mapping = model_config
+            .as_ref()
+            .and_then(|m| m.mappings.iter().find(|m| m.provider == check.provider && m.actual_model == check.model));
+        let (Some(mapping), Some(provider)) = (mapping, active.provider_registry.get_provider(&check.provider)) else {
+            continue;
+        };
+        let provider_type = active
+            .config
+            .providers
+            .iter()
+            .find(|p| p.name == check.provider)
+            .map_or("", |p| p.provider_type.as_str());
+
+        let mut prepared = request.clone();
+        prepared.model = mapping.actual_model.clone();
+        let (prepared_result, warnings) = transform::losses::collect(async {
+            transform::prepare(
+                &active.config,
+                model_config.as_deref(),
+                mapping,
+                provider.as_ref().as_ref(),
+                &mut prepared,
+            )
+        })
+        .await;
+        let input_tokens = VisionFormula::for_provider(provider_type).estimate_input_tokens(&prepared);
+        providers.push(ProviderReport {
+            provider: check.provider.clone(),
+            model: check.model.clone(),
+            status: if prepared_result.is_ok() { "ok" } else { "rejected" },
+            reason: prepared_result.err(),
+            warnings,
+            input_tokens,
+            max_cost_usd: mapping.cost_usd(input_tokens, 0, prepared.max_tokens),
+        });
+    }
+
+    let mut errors = Vec::new();
+    if route.target.is_none() {
+        errors.push(format!("No available provider for model '{}'", route.alias));
+    } else if !providers.is_empty() && providers.iter().all(|p| p.status == "rejected") {
+        errors.push("Every provider rejected the request".to_string());
+    }
+    ValidationReport {
+        valid: errors.is_empty(),
+        errors,
+        route: Some(route),
+        providers,
+    }
+}
+
+fn error_message(error: AppError) -> String {
+    match error {
+        AppError::RoutingError(msg)
+        | AppError::ParseError(msg)
+        | AppError::ProviderError(msg)
+        | AppError::PayloadTooLarge(msg)
+        | AppError::GuardrailTriggered(msg)
+        | AppError::Cancelled(msg) => msg,
+    }
+}
+
+/// POST /v1/messages/validate - pre-flight report for a request, without sending it
+pub async fn validate_messages(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request_json): Json<serde_json::Value>,
+) -> Json<ValidationReport> {
+    Json(validate(&state.active(), &headers, &request_json).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::AppConfig;
+    use crate::providers::ProviderRegistry;
+    use crate::router::Router;
+
+    #[tokio::test]
+    async fn test_validate() {
+        let config: AppConfig = toml::from_str(
+            r#"
+[server]
+[router]
+default = "fast"
+
+[[providers]]
+name = "zai"
+provider_type = "z.ai"
+api_key = "k"
+models = []
+
+[providers.unsupported_content]
+action = "error"
+
+[[providers]]
+name = "openai"
+provider_type = "openai"
+api_key = "k"
+models = []
+
+[[models]]
+name = "fast"
+
+[[models.mappings]]
+provider = "zai"
+actual_model = "glm-4.6"
+priority = 1
+
+[[models.mappings]]
+provider = "openai"
+actual_model = "gpt-4.1"
+priority = 2
+input_cost_per_mtok = 2.0
+output_cost_per_mtok = 8.0
+"#,
+        )
+        .unwrap();
+        let active = ActiveConfig {
+            router: Router::new(config.clone()),
+            provider_registry: Arc::new(ProviderRegistry::from_configs(&config.providers, &config.server.timeouts, None).unwrap()),
+            model_overrides: Default::default(),
+            config,
+        };
+        let body = serde_json::json!({
+            "model": "claude-sonnet-4-5", "max_tokens": 1000,
+            "messages": [{ "role": "user", "content": [
+                { "type": "document", "source": { "type": "url", "url": "https://example.com/spec.pdf" } },
+                { "type": "text", "text": "x".repeat(4000) },
+            ] }],
+        });
+
+        let report = validate(&active, &HeaderMap::new(), &body).await;
+        assert!(report.valid, "{:?}", report.errors);
+        assert_eq!(report.route.unwrap().alias, "fast");
+        assert_eq!(report.providers[0].status, "rejected");
+        assert!(report.providers[0].reason.as_ref().unwrap().contains("spec.pdf"));
+        let openai = &report.providers[1];
+        assert_eq!(openai.status, "ok");
+        assert_eq!(openai.warnings.len(), 1);
+        assert!(openai.input_tokens >= 1000);
+        assert!(openai.max_cost_usd.unwrap() > 0.01);
+
+        let report = validate(&active, &HeaderMap::new(), &serde_json::json!({ "model": "m" })).await;
+        assert!(!report.valid);
+        assert!(report.errors[0].starts_with("Invalid request format"));
+    }
+}
@@ -0,0 +1,455 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration as ChronoDuration, NaiveDateTime, Utc};
+use flate2::{write::GzEncoder, Compression};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Request log archival configuration
+///
+/// Completed requests are appended to hourly JSON-lines files. Files from past
+/// hours are gzipped and uploaded to an S3-compatible bucket, then removed
+/// locally. GCS works through its S3-compatible XML API with HMAC keys.
+///
+/// Example:
+/// ```toml
+/// [archive]
+/// enabled = true
+/// provider = "s3"
+/// bucket = "ccm-logs"
+/// region = "eu-west-1"
+/// access_key_id = "$AWS_ACCESS_KEY_ID"
+/// secret_access_key = "$AWS_SECRET_ACCESS_KEY"
+/// retention_days = 90
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub provider: ArchiveProvider,
+    #[serde(default)]
+    pub bucket: String,
+    /// Object key prefix (use one per replica when several share a bucket)
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    /// Endpoint URL (default: AWS for `s3`, storage.googleapis.com for `gcs`); set for MinIO, R2, ...
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    /// Signing region (default: us-east-1 for `s3`, auto for `gcs`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    /// Access key id (supports `$ENV_VAR`)
+    #[serde(default)]
+    pub access_key_id: String,
+    /// Secret access key (supports `$ENV_VAR`)
+    #[serde(default)]
+    pub secret_access_key: String,
+    /// Local directory for the hourly files (default: ~/.claude-code-mux/request_logs)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Include request and response bodies (transcripts), not just summaries
+    #[serde(default)]
+    pub include_bodies: bool,
+    /// How often to look for finished files to upload
+    #[serde(default = "default_upload_interval_secs")]
+    pub upload_interval_secs: u64,
+    /// Local files that could not be uploaded are deleted after this many days
+    #[serde(default = "default_keep_local_days")]
+    pub keep_local_days: u32,
+    /// Delete archived objects older than this many days (default: keep forever)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention_days: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveProvider {
+    #[default]
+    S3,
+    Gcs,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            provider: ArchiveProvider::default(),
+            bucket: String::new(),
+            prefix: default_prefix(),
+            endpoint: None,
+            region: None,
+            access_key_id: String::new(),
+            secret_access_key: String::new(),
+            path: None,
+            include_bodies: false,
+            upload_interval_secs: default_upload_interval_secs(),
+            keep_local_days: default_keep_local_days(),
+            retention_days: None,
+        }
+    }
+}
+
+fn default_prefix() -> String {
+    "ccm/request-logs".to_string()
+}
+
+fn default_upload_interval_secs() -> u64 {
+    600
+}
+
+fn default_keep_local_days() -> u32 {
+    7
+}
+
+impl ArchiveConfig {
+    /// Replace `$ENV_VAR` credentials with their values
+    pub fn resolve_env_vars(&mut self) {
+        for field in [&mut self.access_key_id, &mut self.secret_access_key] {
+            if let Some(var) = field.strip_prefix('$') {
+                *field = std::env::var(var).unwrap_or_default();
+            }
+        }
+    }
+}
+
+/// Hourly request log files and their upload job
+#[derive(Debug, Clone)]
+pub struct RequestArchive {
+    dir: PathBuf,
+    include_bodies: bool,
+    write_lock: Arc<Mutex<()>>,
+}
+
+impl RequestArchive {
+    /// Start archiving (None when disabled or misconfigured)
+    pub fn spawn(config: &ArchiveConfig) -> Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        if config.bucket.is_empty() || config.access_key_id.is_empty() || config.secret_access_key.is_empty() {
+            bail!("archive needs bucket, access_key_id and secret_access_key");
+        }
+        let dir = match &config.path {
+            Some(path) => path.clone(),
+            None => dirs::home_dir()
+                .context("Failed to get home directory")?
+                .join(".claude-code-mux")
+                .join("request_logs"),
+        };
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+        let client = ObjectStore::new(config);
+        let job_config = config.clone();
+        let job_dir = dir.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(job_config.upload_interval_secs.max(60)));
+            loop {
+                interval.tick().await;
+                upload_finished(&client, &job_config, &job_dir).await;
+                if let Some(days) = job_config.retention_days {
+                    if let Err(e) = client.expire(&job_config.prefix, days).await {
+                        warn!("Archive retention pass failed: {:#}", e);
+                    }
+                }
+            }
+        });
+
+        info!("🗄️  Archiving request logs to {}/{}", config.bucket, config.prefix);
+        Ok(Some(Self {
+            dir,
+            include_bodies: config.include_bodies,
+            write_lock: Arc::new(Mutex::new(())),
+        }))
+    }
+
+    /// Whether request and response bodies should be captured for the archive
+    pub fn include_bodies(&self) -> bool {
+        self.include_bodies
+    }
+
+    /// Append a completed request to the current hour's file
+    pub fn append<T: Serialize>(&self, entry: &T) {
+        let result = (|| -> Result<()> {
+            let line = serde_json::to_string(entry)?;
+            let path = self.dir.join(format!("{}.jsonl", Utc::now().format(HOUR_FORMAT)));
+            let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", line)?;
+            Ok(())
+        })();
+        if let Err(e) = result {
+            warn!("Failed to write request archive: {}", e);
+        }
+    }
+}
+
+/// Hourly file stem, e.g. `2026-10-16T14`
+const HOUR_FORMAT: &str = "%Y-%m-%dT%H";
+
+/// Start of the hour a file (or object) name covers
+fn hour_of(name: &str) -> Option<DateTime<Utc>> {
+    let stem = name.get(..13)?;
+    NaiveDateTime::parse_from_str(&format!("{}:00", stem), "%Y-%m-%dT%H:%M")
+        .ok()
+        .map(|t| t.and_utc())
+}
+
+/// `prefix/YYYY/MM/DD/<hour>.jsonl.gz`
+fn object_key(prefix: &str, file_name: &str) -> Option<String> {
+    let hour = hour_of(file_name)?;
+    Some(format!(
+        "{}/{}/{}.gz",
+        prefix.trim_matches('/'),
+        hour.format("%Y/%m/%d"),
+        file_name
+    ))
+}
+
+/// Upload files of past hours; delete them once uploaded (or when too old)
+async fn upload_finished(client: &ObjectStore, config: &ArchiveConfig, dir: &Path) {
+    let current = format!("{}.jsonl", Utc::now().format(HOUR_FORMAT));
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name == current || !name.ends_with(".jsonl") {
+            continue;
+        }
+        let Some(key) = object_key(&config.prefix, &name) else {
+            continue;
+        };
+        let path = entry.path();
+        let uploaded = async {
+            let body = gzip(&fs::read(&path)?)?;
+            client.put(&key, body).await
+        };
+        match uploaded.await {
+            Ok(()) => {
+                info!("🗄️  Archived {}", key);
+                if let Err(e) = fs::remove_file(&path) {
+                    warn!("Failed to remove archived file {}: {}", path.display(), e);
+                }
+            }
+            Err(e) => {
+                warn!("Failed to archive {}: {:#}", name, e);
+                let too_old = hour_of(&name)
+                    .is_some_and(|h| Utc::now() - h > ChronoDuration::days(config.keep_local_days as i64));
+                if too_old {
+                    warn!("Dropping {} after {} days without a successful upload", name, config.keep_local_days);
+                    let _ = fs::remove_file(&path);
+                }
+            }
+        }
+    }
+}
+
+fn gzip(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+/// Minimal S3 API client (path-style URLs, SigV4)
+struct ObjectStore {
+    client: reqwest::Client,
+    endpoint: String,
+    region: String,
+    bucket: String,
+    access_key_id: String,
+    secret_access_key: String,
+}
+
+impl ObjectStore {
+    fn new(config: &ArchiveConfig) -> Self {
+        let (endpoint, region) = match config.provider {
+            ArchiveProvider::S3 => {
+                let region = config.region.clone().unwrap_or_else(|| "us-east-1".to_string());
+                (format!("https://s3.{}.amazonaws.com", region), region)
+            }
+            ArchiveProvider::Gcs => (
+                "https://storage.googleapis.com".to_string(),
+                config.region.clone().unwrap_or_else(|| "auto".to_string()),
+            ),
+        };
+        Self {
+            client: reqwest::Client::new(),
+            endpoint: config.endpoint.clone().unwrap_or(endpoint).trim_end_matches('/').to_string(),
+            region,
+            bucket: config.bucket.clone(),
+            access_key_id: config.access_key_id.clone(),
+            secret_access_key: config.secret_access_key.clone(),
+        }
+    }
+
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<()> {
+        self.send(reqwest::Method::PUT, key, &[], body).await.map(|_| ())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.send(reqwest::Method::DELETE, key, &[], Vec::new()).await.map(|_| ())
+    }
+
+    /// Delete objects under `prefix` whose hour is older than `days`
+    async fn expire(&self, prefix: &str, days: u32) -> Result<()> {
+        let cutoff = Utc::now() - ChronoDuration::days(days as i64);
+        let list_prefix = format!("{}/", prefix.trim_matches('/'));
+        let key_re = regex::Regex::new(r"<Key>([^<]+)</Key>").expect("valid regex");
+        let token_re = regex::Regex::new(r"<NextContinuationToken>([^<]+)</NextContinuationToken>").expect("valid regex");
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2".to_string()), ("prefix", list_prefix.clone())];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token.clone()));
+            }
+            let listing = self.send(reqwest::Method::GET, "", &query, Vec::new()).await?;
+            for caps in key_re.captures_iter(&listing) {
+                let key = &caps[1];
+                let name = key.rsplit('/').next().unwrap_or(key);
+                if hour_of(name).is_some_and(|h| h < cutoff) {
+                    self.delete(key).await?;
+                    info!("🗄️  Expired archived {}", key);
+                }
+            }
+            token = token_re.captures(&listing).map(|c| c[1].to_string());
+            if token.is_none() {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn send(&self, method: reqwest::Method, key: &str, query: &[(&str, String)], body: Vec<u8>) -> Result<String> {
+        let path = if key.is_empty() {
+            format!("/{}", self.bucket)
+        } else {
+            format!("/{}/{}", self.bucket, key)
+        };
+        let url = url::Url::parse(&format!("{}{}", self.endpoint, uri_encode(&path, false)))?;
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+
+        let mut query: Vec<(String, String)> =
+            query.iter().map(|(k, v)| (uri_encode(k, true), uri_encode(v, true))).collect();
+        query.sort();
+        let canonical_query = query.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join("&");
+
+        let now = Utc::now();
+        let payload_hash = hex(&Sha256::digest(&body));
+        let authorization = self.authorization(
+            method.as_str(),
+            url.path(),
+            &canonical_query,
+            &host,
+            &payload_hash,
+            now,
+        );
+
+        let full_url = if canonical_query.is_empty() {
+            url.to_string()
+        } else {
+            format!("{}?{}", url, canonical_query)
+        };
+        let response = self
+            .client
+            .request(method.clone(), &full_url)
+            .header("x-amz-date", now.format("%Y%m%dT%H%M%SZ").to_string())
+            .header("x-amz-content-sha256", payload_hash)
+            .header("authorization", authorization)
+            .body(body)
+            .send()
+            .await
+            .with_context(|| format!("{} {} failed", method, path))?;
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        if !status.is_success() {
+            bail!("{} {} returned {}: {}", method, path, status, text.chars().take(300).collect::<String>());
+        }
+        Ok(text)
+    }
+
+    /// SigV4 `Authorization` header
+    fn authorization(
+        &self,
+        method: &str,
+        canonical_uri: &str,
+        canonical_query: &str,
+        host: &str,
+        payload_hash: &str,
+        now: DateTime<Utc>,
+    ) -> String {
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method, canonical_uri, canonical_query, host, payload_hash, amz_date, signed_headers, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let key = signing_key(&self.secret_access_key, &date, &self.region, "s3");
+        let signature = hex(hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &key), string_to_sign.as_bytes()).as_ref());
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id, scope, signed_headers, signature
+        )
+    }
+}
+
+fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let sign = |key: &[u8], data: &str| hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes()).as_ref().to_vec();
+    let key = sign(format!("AWS4{}", secret).as_bytes(), date);
+    let key = sign(&key, region);
+    let key = sign(&key, service);
+    sign(&key, "aws4_request")
+}
+
+/// SigV4 URI encoding (unreserved characters kept; `/` kept unless `encode_slash`)
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(byte as char),
+            b'/' if !encode_slash => out.push('/'),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_and_signing() {
+        // Example from the AWS SigV4 documentation
+        let key = signing_key("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20120215", "us-east-1", "iam");
+        assert_eq!(hex(&key), "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d");
+
+        assert_eq!(
+            object_key("/ccm/logs/", "2026-10-16T14.jsonl").as_deref(),
+            Some("ccm/logs/2026/10/16/2026-10-16T14.jsonl.gz")
+        );
+        assert!(object_key("ccm", "notes.jsonl").is_none());
+        assert_eq!(hour_of("2026-10-16T14.jsonl.gz").unwrap().to_rfc3339(), "2026-10-16T14:00:00+00:00");
+        assert_eq!(uri_encode("a b/c=", false), "a%20b/c%3D");
+    }
+}
@@ -0,0 +1,259 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Placeholder for secret values in recorded diffs
+const REDACTED: &str = "[redacted]";
+
+/// One admin mutation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    /// Who made the change
+    pub actor: String,
+    /// What was done, e.g. `config.update` or `keys.mint`
+    pub action: String,
+    /// Object the action applied to (provider id, key id, ...)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Changed values; secrets are redacted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<Change>,
+}
+
+/// A value that changed; `None` means absent on that side
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+    /// Dotted path, with `[name]` for named array entries (e.g. `providers[openrouter].api_key`)
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<Value>,
+}
+
+impl AuditEntry {
+    pub fn new(actor: impl Into<String>, action: &str) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            actor: actor.into(),
+            action: action.to_string(),
+            target: None,
+            changes: Vec::new(),
+        }
+    }
+
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Record the difference between two JSON documents
+    pub fn diff(mut self, before: &Value, after: &Value) -> Self {
+        diff_into("", before, after, &mut self.changes);
+        self
+    }
+}
+
+/// Filters for `AuditLog::query`
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    /// Action, or action prefix ending in `.` (e.g. `keys.`)
+    pub action: Option<String>,
+    pub actor: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+/// Append-only log of admin actions, one JSON entry per line
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    file_path: PathBuf,
+    write_lock: Arc<Mutex<()>>,
+}
+
+impl AuditLog {
+    pub fn new(file_path: PathBuf) -> Self {
+        Self {
+            file_path,
+            write_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// ~/.claude-code-mux/audit.jsonl
+    pub fn default_path() -> Result<PathBuf> {
+        let home = dirs::home_dir().context("Failed to get home directory")?;
+        let config_dir = home.join(".claude-code-mux");
+        fs::create_dir_all(&config_dir).context("Failed to create config directory")?;
+        Ok(config_dir.join("audit.jsonl"))
+    }
+
+    pub fn default() -> Result<Self> {
+        Ok(Self::new(Self::default_path()?))
+    }
+
+    /// Append an entry; failures are logged, never returned to the admin client
+    pub fn record(&self, entry: AuditEntry) {
+        tracing::info!("📝 Audit: {} {} by {}", entry.action, entry.target.as_deref().unwrap_or(""), entry.actor);
+        if let Err(e) = self.append(&entry) {
+            tracing::error!("Failed to write audit log: {}", e);
+        }
+    }
+
+    fn append(&self, entry: &AuditEntry) -> Result<()> {
+        let line = serde_json::to_string(entry)?;
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file_path)
+            .context("Failed to open audit log")?;
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+
+    /// Matching entries, newest first
+    pub fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>> {
+        let file = match fs::File::open(&self.file_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).context("Failed to open audit log"),
+        };
+        let mut entries: Vec<AuditEntry> = BufReader::new(file)
+            .lines()
+            .map_while(|line| line.ok())
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .filter(|e: &AuditEntry| query.matches(e))
+            .collect();
+        entries.reverse();
+        entries.truncate(query.limit.unwrap_or(100));
+        Ok(entries)
+    }
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        let action = self.action.as_deref().is_none_or(|a| match a.strip_suffix('.') {
+            Some(prefix) => entry.action.starts_with(prefix),
+            None => entry.action == a,
+        });
+        action
+            && self.actor.as_deref().is_none_or(|a| entry.actor == a)
+            && self.since.is_none_or(|t| entry.timestamp >= t)
+    }
+}
+
+fn diff_into(path: &str, before: &Value, after: &Value, changes: &mut Vec<Change>) {
+    if before == after {
+        return;
+    }
+    if is_secret(path) {
+        changes.push(Change {
+            path: path.to_string(),
+            before: (!before.is_null()).then(|| REDACTED.into()),
+            after: (!after.is_null()).then(|| REDACTED.into()),
+        });
+        return;
+    }
+    match (before, after) {
+        (Value::Object(a), Value::Object(b)) => {
+            for (key, value) in a {
+                diff_into(&join(path, key), value, b.get(key).unwrap_or(&Value::Null), changes);
+            }
+            for (key, value) in b.iter().filter(|(k, _)| !a.contains_key(*k)) {
+                diff_into(&join(path, key), &Value::Null, value, changes);
+            }
+        }
+        (Value::Array(a), Value::Array(b)) if named(a) && named(b) => {
+            let name = |v: &Value| v["name"].as_str().unwrap_or_default().to_string();
+            for item in a {
+                let other = b.iter().find(|o| name(o) == name(item)).unwrap_or(&Value::Null);
+                diff_into(&format!("{}[{}]", path, name(item)), item, other, changes);
+            }
+            for item in b.iter().filter(|o| !a.iter().any(|i| name(i) == name(o))) {
+                diff_into(&format!("{}[{}]", path, name(item)), &Value::Null, item, changes);
+            }
+        }
+        _ => changes.push(Change {
+            path: path.to_string(),
+            before: (!before.is_null()).then(|| redact(before)),
+            after: (!after.is_null()).then(|| redact(after)),
+        }),
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Arrays of tables with a `name` (providers, models) are diffed entry by entry
+fn named(items: &[Value]) -> bool {
+    items.iter().all(|v| v.get("name").is_some_and(Value::is_string))
+}
+
+fn is_secret(path: &str) -> bool {
+    let key = path.rsplit('.').next().unwrap_or(path).to_ascii_lowercase();
+    key.ends_with("key") || key.ends_with("token") || key.contains("secret") || key.contains("password")
+}
+
+/// Copy of a value with secret fields replaced
+fn redact(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| {
+                    let v = if is_secret(k) { REDACTED.into() } else { redact(v) };
+                    (k.clone(), v)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact).collect()),
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_redacts_secrets() {
+        let before = json!({
+            "router": { "default": "sonnet" },
+            "providers": [{ "name": "zai", "api_key": "old", "enabled": true }],
+        });
+        let after = json!({
+            "router": { "default": "opus" },
+            "providers": [
+                { "name": "zai", "api_key": "new", "enabled": true },
+                { "name": "openrouter", "api_key": "sk-or" },
+            ],
+        });
+        let entry = AuditEntry::new("admin", "config.update").diff(&before, &after);
+        let paths: Vec<&str> = entry.changes.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, ["providers[zai].api_key", "providers[openrouter]", "router.default"]);
+        assert_eq!(entry.changes[0].after, Some(json!("[redacted]")));
+        assert_eq!(entry.changes[1].after, Some(json!({ "name": "openrouter", "api_key": "[redacted]" })));
+        assert_eq!(entry.changes[2].before, Some(json!("sonnet")));
+
+        let path = std::env::temp_dir().join(format!("ccm-audit-{}.jsonl", std::process::id()));
+        let log = AuditLog::new(path.clone());
+        log.record(entry);
+        log.record(AuditEntry::new("ops", "keys.mint").target("abc"));
+        let keys = log.query(&AuditQuery { action: Some("keys.".to_string()), ..Default::default() }).unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].actor, "ops");
+        assert_eq!(log.query(&AuditQuery::default()).unwrap()[0].action, "keys.mint");
+        let _ = fs::remove_file(path);
+    }
+}
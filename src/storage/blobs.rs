@@ -0,0 +1,212 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+
+/// Prefix used for blob references embedded in logs
+pub const BLOB_REF_PREFIX: &str = "ccm-blob:sha256:";
+
+/// Blob store configuration
+///
+/// Example:
+/// ```toml
+/// [blob_store]
+/// enabled = true
+/// min_bytes = 16384
+/// # path = "/var/lib/ccm/blobs"   # defaults to ~/.claude-code-mux/blobs
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlobStoreConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Payloads at least this large are externalized
+    #[serde(default = "default_min_bytes")]
+    pub min_bytes: usize,
+}
+
+impl Default for BlobStoreConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: None,
+            min_bytes: default_min_bytes(),
+        }
+    }
+}
+
+fn default_min_bytes() -> usize {
+    16 * 1024
+}
+
+/// Content-addressable store for oversized payloads (images, documents)
+///
+/// Payloads are stored once under their SHA-256 hash and replaced with a short
+/// `ccm-blob:sha256:<hash>` reference wherever requests are logged or persisted.
+/// Requests sent upstream are never modified.
+#[derive(Debug, Clone)]
+pub struct BlobStore {
+    root: PathBuf,
+    min_bytes: usize,
+}
+
+impl BlobStore {
+    pub fn new(root: PathBuf, min_bytes: usize) -> Result<Self> {
+        fs::create_dir_all(&root)
+            .with_context(|| format!("Failed to create blob directory: {}", root.display()))?;
+        Ok(Self { root, min_bytes })
+    }
+
+    /// Build a blob store from config (None when disabled)
+    pub fn from_config(config: &BlobStoreConfig) -> Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let root = match &config.path {
+            Some(path) => path.clone(),
+            None => Self::default_path()?,
+        };
+        Self::new(root, config.min_bytes).map(Some)
+    }
+
+    /// ~/.claude-code-mux/blobs
+    pub fn default_path() -> Result<PathBuf> {
+        let home = dirs::home_dir().context("Failed to get home directory")?;
+        Ok(home.join(".claude-code-mux").join("blobs"))
+    }
+
+    fn blob_path(&self, hash: &str) -> PathBuf {
+        // Shard by first two hex chars to keep directories small
+        self.root.join(&hash[..2]).join(hash)
+    }
+
+    /// Store bytes and return their hash (no-op if already stored)
+    pub fn put(&self, bytes: &[u8]) -> Result<String> {
+        let hash = format!("{:x}", Sha256::digest(bytes));
+        let path = self.blob_path(&hash);
+        if !path.exists() {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, bytes)
+                .with_context(|| format!("Failed to write blob: {}", path.display()))?;
+        }
+        Ok(hash)
+    }
+
+    /// Load a blob by hash
+    pub fn get(&self, hash: &str) -> Result<Option<Vec<u8>>> {
+        if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Ok(None);
+        }
+        let path = self.blob_path(hash);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read(&path)?))
+    }
+
+    /// Return a copy of `value` with large `data` payloads replaced by blob references
+    pub fn externalize(&self, value: &serde_json::Value) -> serde_json::Value {
+        let mut value = value.clone();
+        self.externalize_in_place(&mut value);
+        value
+    }
+
+    /// Return a copy of `value` with blob references replaced by their payloads
+    ///
+    /// References to missing blobs are left in place.
+    pub fn resolve(&self, value: &serde_json::Value) -> serde_json::Value {
+        let mut value = value.clone();
+        self.resolve_in_place(&mut value);
+        value
+    }
+
+    fn resolve_in_place(&self, value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::String(s) => {
+                let Some(hash) = s.strip_prefix(BLOB_REF_PREFIX) else {
+                    return;
+                };
+                match self.get(hash).map(|blob| blob.map(String::from_utf8)) {
+                    Ok(Some(Ok(data))) => *s = data,
+                    Ok(_) => tracing::warn!("Blob {} is missing", hash),
+                    Err(e) => tracing::warn!("Failed to read blob: {}", e),
+                }
+            }
+            serde_json::Value::Object(map) => map.values_mut().for_each(|v| self.resolve_in_place(v)),
+            serde_json::Value::Array(arr) => arr.iter_mut().for_each(|v| self.resolve_in_place(v)),
+            _ => {}
+        }
+    }
+
+    fn externalize_in_place(&self, value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, v) in map.iter_mut() {
+                    match v {
+                        serde_json::Value::String(s) if key == "data" && s.len() >= self.min_bytes => {
+                            match self.put(s.as_bytes()) {
+                                Ok(hash) => *s = format!("{}{}", BLOB_REF_PREFIX, hash),
+                                Err(e) => tracing::warn!("Failed to store blob: {}", e),
+                            }
+                        }
+                        _ => self.externalize_in_place(v),
+                    }
+                }
+            }
+            serde_json::Value::Array(arr) => {
+                for item in arr.iter_mut() {
+                    self.externalize_in_place(item);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_put_get_dedup() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = BlobStore::new(temp_dir.path().to_path_buf(), 4).unwrap();
+
+        let hash = store.put(b"hello").unwrap();
+        assert_eq!(hash, store.put(b"hello").unwrap());
+        assert_eq!(store.get(&hash).unwrap().unwrap(), b"hello");
+        assert!(store.get("../../etc/passwd").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_externalize_large_data() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = BlobStore::new(temp_dir.path().to_path_buf(), 8).unwrap();
+
+        let request = serde_json::json!({
+            "messages": [{
+                "role": "user",
+                "content": [
+                    {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "AAAAAAAAAAAA"}},
+                    {"type": "text", "text": "small", "data": "tiny"}
+                ]
+            }]
+        });
+
+        let logged = store.externalize(&request);
+        let data = logged["messages"][0]["content"][0]["source"]["data"].as_str().unwrap();
+        assert!(data.starts_with(BLOB_REF_PREFIX));
+        assert_eq!(logged["messages"][0]["content"][1]["data"], "tiny");
+
+        // Original is untouched
+        assert_eq!(request["messages"][0]["content"][0]["source"]["data"], "AAAAAAAAAAAA");
+
+        let hash = data.strip_prefix(BLOB_REF_PREFIX).unwrap();
+        assert_eq!(store.get(hash).unwrap().unwrap(), b"AAAAAAAAAAAA");
+    }
+}
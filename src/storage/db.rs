@@ -0,0 +1,174 @@
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+/// Schema migrations, applied in order. `PRAGMA user_version` records how many
+/// have run, so existing entries must never be edited - append new ones instead.
+const MIGRATIONS: &[&str] = &[
+    // 1: request history (usage stats + request log summaries)
+    "CREATE TABLE requests (
+        id            TEXT PRIMARY KEY,
+        timestamp     TEXT NOT NULL,
+        endpoint      TEXT NOT NULL,
+        model         TEXT NOT NULL,
+        routed_model  TEXT,
+        route_type    TEXT,
+        provider      TEXT,
+        actual_model  TEXT,
+        stream        INTEGER NOT NULL DEFAULT 0,
+        success       INTEGER NOT NULL,
+        latency_ms    INTEGER NOT NULL,
+        input_tokens  INTEGER NOT NULL DEFAULT 0,
+        output_tokens INTEGER NOT NULL DEFAULT 0,
+        cost_usd      REAL,
+        error         TEXT
+    );
+    CREATE INDEX idx_requests_timestamp ON requests(timestamp);
+    CREATE INDEX idx_requests_provider ON requests(provider, timestamp);",
+    // 2: per-attempt provider health history
+    "CREATE TABLE provider_health (
+        id         INTEGER PRIMARY KEY AUTOINCREMENT,
+        provider   TEXT NOT NULL,
+        timestamp  TEXT NOT NULL,
+        success    INTEGER NOT NULL,
+        error      TEXT
+    );
+    CREATE INDEX idx_provider_health ON provider_health(provider, timestamp);",
+    // 3: judge model grades of sampled responses
+    "CREATE TABLE quality_scores (
+        id           INTEGER PRIMARY KEY AUTOINCREMENT,
+        request_id   TEXT NOT NULL,
+        timestamp    TEXT NOT NULL,
+        provider     TEXT NOT NULL,
+        model        TEXT NOT NULL,
+        helpfulness  INTEGER NOT NULL,
+        correctness  INTEGER NOT NULL,
+        rationale    TEXT
+    );
+    CREATE INDEX idx_quality_scores ON quality_scores(provider, timestamp);",
+    // 4: failure taxonomy of provider attempts
+    "ALTER TABLE provider_health ADD COLUMN failure_kind TEXT;",
+];
+
+/// Embedded database configuration
+///
+/// Example:
+/// ```toml
+/// [database]
+/// enabled = true
+/// # path = "/var/lib/ccm/ccm.db"   # defaults to ~/.claude-code-mux/ccm.db
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DatabaseConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+}
+
+/// Single embedded SQLite database holding all persistent server state
+#[derive(Clone)]
+pub struct Database {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Database {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open database: {}", path.display()))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        Self::from_connection(conn)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut conn: Connection) -> Result<Self> {
+        migrate(&mut conn)?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Open the configured database (None when disabled)
+    pub fn from_config(config: &DatabaseConfig) -> Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let path = match &config.path {
+            Some(path) => path.clone(),
+            None => Self::default_path()?,
+        };
+        Self::open(&path).map(Some)
+    }
+
+    /// ~/.claude-code-mux/ccm.db
+    pub fn default_path() -> Result<PathBuf> {
+        let home = dirs::home_dir().context("Failed to get home directory")?;
+        Ok(home.join(".claude-code-mux").join("ccm.db"))
+    }
+
+    /// Run queries on the blocking thread pool
+    ///
+    /// SQLite calls block (and wait on the connection lock), so they never run
+    /// on an async worker thread.
+    pub async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || f(&conn.lock().unwrap_or_else(|e| e.into_inner())))
+            .await
+            .context("Database task failed")?
+    }
+}
+
+/// Apply pending migrations inside a transaction
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version >= MIGRATIONS.len() {
+        return Ok(());
+    }
+
+    let tx = conn.transaction()?;
+    for (idx, sql) in MIGRATIONS.iter().enumerate().skip(version) {
+        tx.execute_batch(sql)
+            .with_context(|| format!("Database migration {} failed", idx + 1))?;
+    }
+    tx.pragma_update(None, "user_version", MIGRATIONS.len())?;
+    tx.commit()?;
+
+    info!("🗄️  Database migrated from version {} to {}", version, MIGRATIONS.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_migrations_are_idempotent() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("ccm.db");
+
+        Database::open(&path).unwrap();
+        let db = Database::open(&path).unwrap();
+
+        let version: usize = db
+            .run(|conn| Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?))
+            .await
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+    }
+}
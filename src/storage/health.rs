@@ -0,0 +1,175 @@
+use anyhow::Result;
+use chrono::{DateTime, DurationRound, Utc};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use super::db::Database;
+
+/// Success/failure counts for one provider over a time window
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderHealthSummary {
+    pub provider: String,
+    pub successes: u64,
+    pub failures: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_failure_at: Option<DateTime<Utc>>,
+}
+
+/// Failed attempts of one provider by failure kind, overall and per hour
+#[derive(Debug, Clone, Serialize)]
+pub struct FailureBreakdown {
+    pub provider: String,
+    pub failures: u64,
+    pub by_kind: BTreeMap<String, u64>,
+    pub hourly: Vec<FailureBucket>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailureBucket {
+    /// Start of the hour
+    pub hour: DateTime<Utc>,
+    pub by_kind: BTreeMap<String, u64>,
+}
+
+/// Kind recorded for failures from before the taxonomy existed
+const UNCLASSIFIED: &str = "unclassified";
+
+/// Per-attempt provider health history stored in the `provider_health` table
+#[derive(Clone)]
+pub struct HealthStore {
+    db: Database,
+}
+
+impl HealthStore {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// Record an attempt; failures carry their error and failure kind
+    pub async fn record(&self, provider: &str, failure: Option<(&str, &str)>) -> Result<()> {
+        let provider = provider.to_string();
+        let success = failure.is_none();
+        let (error, kind) = failure.map(|(e, k)| (e.to_string(), k.to_string())).unzip();
+        self.db
+            .run(move |conn| {
+                conn.execute(
+                    "INSERT INTO provider_health (provider, timestamp, success, error, failure_kind)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![provider, Utc::now(), success, error, kind],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    /// Health summary per provider since `since`
+    pub async fn summary(&self, since: DateTime<Utc>) -> Result<Vec<ProviderHealthSummary>> {
+        self.db.run(move |conn| summary(conn, since)).await
+    }
+
+    /// Failures per provider and kind since `since`
+    pub async fn failures(&self, since: DateTime<Utc>) -> Result<Vec<FailureBreakdown>> {
+        self.db.run(move |conn| failures(conn, since)).await
+    }
+}
+
+fn summary(conn: &Connection, since: DateTime<Utc>) -> Result<Vec<ProviderHealthSummary>> {
+    let mut stmt = conn.prepare(
+        "SELECT provider,
+                SUM(success),
+                SUM(1 - success),
+                (SELECT error FROM provider_health h2
+                  WHERE h2.provider = h.provider AND h2.success = 0
+                  ORDER BY timestamp DESC LIMIT 1),
+                MAX(CASE WHEN success = 0 THEN timestamp END)
+           FROM provider_health h
+          WHERE timestamp >= ?1
+          GROUP BY provider
+          ORDER BY provider",
+    )?;
+    let rows = stmt
+        .query_map(params![since], |row| {
+            Ok(ProviderHealthSummary {
+                provider: row.get(0)?,
+                successes: row.get(1)?,
+                failures: row.get(2)?,
+                last_error: row.get(3)?,
+                last_failure_at: row.get(4)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+fn failures(conn: &Connection, since: DateTime<Utc>) -> Result<Vec<FailureBreakdown>> {
+    let mut stmt = conn.prepare(
+        "SELECT provider, timestamp, failure_kind
+           FROM provider_health
+          WHERE success = 0 AND timestamp >= ?1
+          ORDER BY provider, timestamp",
+    )?;
+    let rows = stmt
+        .query_map(params![since], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, DateTime<Utc>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut breakdowns: Vec<FailureBreakdown> = Vec::new();
+    for (provider, timestamp, kind) in rows {
+        let kind = kind.unwrap_or_else(|| UNCLASSIFIED.to_string());
+        let hour = timestamp.duration_trunc(chrono::Duration::hours(1))?;
+        if breakdowns.last().map(|b| &b.provider) != Some(&provider) {
+            breakdowns.push(FailureBreakdown {
+                provider,
+                failures: 0,
+                by_kind: BTreeMap::new(),
+                hourly: Vec::new(),
+            });
+        }
+        let breakdown = breakdowns.last_mut().unwrap();
+        breakdown.failures += 1;
+        *breakdown.by_kind.entry(kind.clone()).or_default() += 1;
+        if breakdown.hourly.last().map(|b| b.hour) != Some(hour) {
+            breakdown.hourly.push(FailureBucket {
+                hour,
+                by_kind: BTreeMap::new(),
+            });
+        }
+        *breakdown.hourly.last_mut().unwrap().by_kind.entry(kind).or_default() += 1;
+    }
+    Ok(breakdowns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_summary_counts_attempts() {
+        let store = HealthStore::new(Database::open_in_memory().unwrap());
+        store.record("zai", None).await.unwrap();
+        store.record("zai", Some(("timeout", "timeout"))).await.unwrap();
+        store.record("openrouter", None).await.unwrap();
+        store.record("openrouter", Some(("401 - bad key", "auth"))).await.unwrap();
+        store.record("openrouter", Some(("403 - revoked", "auth"))).await.unwrap();
+
+        let summary = store.summary(Utc::now() - chrono::Duration::hours(1)).await.unwrap();
+        assert_eq!(summary.len(), 2);
+        let zai = summary.iter().find(|s| s.provider == "zai").unwrap();
+        assert_eq!((zai.successes, zai.failures), (1, 1));
+        assert_eq!(zai.last_error.as_deref(), Some("timeout"));
+
+        let failures = store.failures(Utc::now() - chrono::Duration::hours(1)).await.unwrap();
+        assert_eq!(failures[0].provider, "openrouter");
+        assert_eq!(failures[0].by_kind["auth"], 2);
+        assert_eq!(failures[0].hourly.last().unwrap().by_kind["auth"], 2);
+        assert_eq!(failures[1].failures, 1);
+    }
+}
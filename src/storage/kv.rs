@@ -0,0 +1,293 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use dashmap::DashMap;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Shared state backend configuration
+///
+/// With the default in-memory backend every instance keeps its own counters.
+/// Point all replicas at the same Redis to share failure counters, alert
+/// cooldowns and usage aggregates (and, with `share_oauth_tokens`, OAuth tokens).
+///
+/// Example:
+/// ```toml
+/// [shared_state]
+/// backend = "redis"
+/// url = "redis://127.0.0.1:6379/0"
+/// key_prefix = "ccm:"
+/// share_oauth_tokens = true
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedStateConfig {
+    #[serde(default)]
+    pub backend: KvBackend,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default = "default_key_prefix")]
+    pub key_prefix: String,
+    /// Store OAuth tokens in the backend and coordinate refreshes between replicas
+    #[serde(default)]
+    pub share_oauth_tokens: bool,
+}
+
+impl Default for SharedStateConfig {
+    fn default() -> Self {
+        Self {
+            backend: KvBackend::default(),
+            url: None,
+            key_prefix: default_key_prefix(),
+            share_oauth_tokens: false,
+        }
+    }
+}
+
+fn default_key_prefix() -> String {
+    "ccm:".to_string()
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum KvBackend {
+    #[default]
+    Memory,
+    Redis,
+}
+
+/// Minimal key-value interface for state shared between mux instances
+#[async_trait]
+pub trait KvStore: Send + Sync {
+    /// Set only if the key does not exist; returns whether it was set
+    async fn set_nx(&self, key: &str, value: &str, ttl: Duration) -> Result<bool>;
+
+    async fn del(&self, key: &str) -> Result<()>;
+
+    /// Increment a field of a hash, (re)setting the hash expiry when `ttl` is given
+    async fn hincr(&self, key: &str, field: &str, by: i64, ttl: Option<Duration>) -> Result<i64>;
+
+    async fn hgetall(&self, key: &str) -> Result<HashMap<String, i64>>;
+
+    /// All fields of a string-valued hash
+    async fn hgetall_raw(&self, key: &str) -> Result<HashMap<String, String>>;
+
+    /// Compare-and-set a hash field: writes `new` (or deletes the field when None)
+    /// only if the current value equals `expected` (None = field absent)
+    async fn hcas(&self, key: &str, field: &str, expected: Option<&str>, new: Option<&str>) -> Result<bool>;
+}
+
+/// Build the configured backend
+pub async fn connect(config: &SharedStateConfig) -> Result<Arc<dyn KvStore>> {
+    match config.backend {
+        KvBackend::Memory => Ok(Arc::new(MemoryKv::default())),
+        KvBackend::Redis => {
+            let url = config
+                .url
+                .as_deref()
+                .context("shared_state.url is required for the redis backend")?;
+            Ok(Arc::new(RedisKv::connect(url, &config.key_prefix).await?))
+        }
+    }
+}
+
+/// Process-local backend (default)
+#[derive(Default)]
+pub struct MemoryKv {
+    flags: DashMap<String, Instant>,
+    hashes: DashMap<String, (HashMap<String, i64>, Option<Instant>)>,
+    raw_hashes: DashMap<String, HashMap<String, String>>,
+}
+
+fn expired(expires: Option<Instant>) -> bool {
+    expires.is_some_and(|expires| expires <= Instant::now())
+}
+
+#[async_trait]
+impl KvStore for MemoryKv {
+    async fn set_nx(&self, key: &str, _value: &str, ttl: Duration) -> Result<bool> {
+        let mut inserted = false;
+        self.flags
+            .entry(key.to_string())
+            .and_modify(|expires| {
+                // Replace an expired flag
+                if expired(Some(*expires)) {
+                    *expires = Instant::now() + ttl;
+                    inserted = true;
+                }
+            })
+            .or_insert_with(|| {
+                inserted = true;
+                Instant::now() + ttl
+            });
+        Ok(inserted)
+    }
+
+    async fn del(&self, key: &str) -> Result<()> {
+        self.flags.remove(key);
+        self.hashes.remove(key);
+        self.raw_hashes.remove(key);
+        Ok(())
+    }
+
+    async fn hincr(&self, key: &str, field: &str, by: i64, ttl: Option<Duration>) -> Result<i64> {
+        let mut entry = self.hashes.entry(key.to_string()).or_default();
+        if expired(entry.1) {
+            entry.0.clear();
+        }
+        let value = entry.0.entry(field.to_string()).or_insert(0);
+        *value += by;
+        let value = *value;
+        if let Some(ttl) = ttl {
+            entry.1 = Some(Instant::now() + ttl);
+        }
+        Ok(value)
+    }
+
+    async fn hgetall(&self, key: &str) -> Result<HashMap<String, i64>> {
+        Ok(match self.hashes.get(key) {
+            Some(entry) if !expired(entry.1) => entry.0.clone(),
+            _ => HashMap::new(),
+        })
+    }
+
+    async fn hgetall_raw(&self, key: &str) -> Result<HashMap<String, String>> {
+        Ok(self.raw_hashes.get(key).map(|h| h.clone()).unwrap_or_default())
+    }
+
+    async fn hcas(&self, key: &str, field: &str, expected: Option<&str>, new: Option<&str>) -> Result<bool> {
+        let mut hash = self.raw_hashes.entry(key.to_string()).or_default();
+        if hash.get(field).map(String::as_str) != expected {
+            return Ok(false);
+        }
+        match new {
+            Some(value) => hash.insert(field.to_string(), value.to_string()),
+            None => hash.remove(field),
+        };
+        Ok(true)
+    }
+}
+
+/// Atomic compare-and-set of a hash field ('' stands for "absent")
+const HCAS_SCRIPT: &str = r"
+local current = redis.call('HGET', KEYS[1], ARGV[1])
+if current == false then current = '' end
+if current ~= ARGV[2] then return 0 end
+if ARGV[3] == '' then
+    redis.call('HDEL', KEYS[1], ARGV[1])
+else
+    redis.call('HSET', KEYS[1], ARGV[1], ARGV[3])
+end
+return 1
+";
+
+/// Redis backend shared by all replicas
+pub struct RedisKv {
+    conn: ConnectionManager,
+    prefix: String,
+}
+
+impl RedisKv {
+    pub async fn connect(url: &str, prefix: &str) -> Result<Self> {
+        let client = redis::Client::open(url).context("Invalid Redis URL")?;
+        let conn = ConnectionManager::new(client)
+            .await
+            .with_context(|| format!("Failed to connect to Redis at {}", url))?;
+        Ok(Self {
+            conn,
+            prefix: prefix.to_string(),
+        })
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+#[async_trait]
+impl KvStore for RedisKv {
+    async fn set_nx(&self, key: &str, value: &str, ttl: Duration) -> Result<bool> {
+        let result: Option<String> = redis::cmd("SET")
+            .arg(self.key(key))
+            .arg(value)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl.as_millis() as u64)
+            .query_async(&mut self.conn.clone())
+            .await?;
+        Ok(result.is_some())
+    }
+
+    async fn del(&self, key: &str) -> Result<()> {
+        self.conn.clone().del::<_, ()>(self.key(key)).await?;
+        Ok(())
+    }
+
+    async fn hincr(&self, key: &str, field: &str, by: i64, ttl: Option<Duration>) -> Result<i64> {
+        let key = self.key(key);
+        let mut pipe = redis::pipe();
+        pipe.atomic().hincr(&key, field, by);
+        if let Some(ttl) = ttl {
+            pipe.pexpire(&key, ttl.as_millis() as i64).ignore();
+        }
+        let (value,): (i64,) = pipe.query_async(&mut self.conn.clone()).await?;
+        Ok(value)
+    }
+
+    async fn hgetall(&self, key: &str) -> Result<HashMap<String, i64>> {
+        Ok(self.conn.clone().hgetall(self.key(key)).await?)
+    }
+
+    async fn hgetall_raw(&self, key: &str) -> Result<HashMap<String, String>> {
+        Ok(self.conn.clone().hgetall(self.key(key)).await?)
+    }
+
+    async fn hcas(&self, key: &str, field: &str, expected: Option<&str>, new: Option<&str>) -> Result<bool> {
+        let swapped: i32 = redis::Script::new(HCAS_SCRIPT)
+            .key(self.key(key))
+            .arg(field)
+            .arg(expected.unwrap_or(""))
+            .arg(new.unwrap_or(""))
+            .invoke_async(&mut self.conn.clone())
+            .await?;
+        Ok(swapped == 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_hash_counters() {
+        let kv = MemoryKv::default();
+        kv.hincr("h", "x", 1, None).await.unwrap();
+        kv.hincr("h", "x", 1, None).await.unwrap();
+        kv.hincr("h", "y", 4, None).await.unwrap();
+        let hash = kv.hgetall("h").await.unwrap();
+        assert_eq!((hash["x"], hash["y"]), (2, 4));
+    }
+
+    #[tokio::test]
+    async fn test_memory_set_nx_and_expiry() {
+        let kv = MemoryKv::default();
+        assert!(kv.set_nx("lock", "1", Duration::from_millis(20)).await.unwrap());
+        assert!(!kv.set_nx("lock", "1", Duration::from_millis(20)).await.unwrap());
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(kv.set_nx("lock", "1", Duration::from_millis(20)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_memory_hcas() {
+        let kv = MemoryKv::default();
+        assert!(kv.hcas("t", "a", None, Some("v1")).await.unwrap());
+        assert!(!kv.hcas("t", "a", None, Some("v2")).await.unwrap());
+        assert!(kv.hcas("t", "a", Some("v1"), Some("v2")).await.unwrap());
+        assert_eq!(kv.hgetall_raw("t").await.unwrap()["a"], "v2");
+        assert!(kv.hcas("t", "a", Some("v2"), None).await.unwrap());
+        assert!(kv.hgetall_raw("t").await.unwrap().is_empty());
+    }
+}
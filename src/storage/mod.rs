@@ -0,0 +1,26 @@
+//! Local persistence used by the server (blobs, logs, ...)
+//!
+//! Structured state (usage, request history, provider health, quality scores)
+//! lives in a single SQLite database (`db`); large opaque payloads go to the
+//! blob store. State that must be shared between replicas goes through the
+//! `kv` backend.
+
+pub mod archive;
+pub mod audit;
+pub mod blobs;
+pub mod db;
+pub mod health;
+pub mod kv;
+pub mod scores;
+pub mod semantic_cache;
+pub mod usage;
+
+pub use archive::{ArchiveConfig, RequestArchive};
+pub use audit::{AuditEntry, AuditLog};
+pub use blobs::{BlobStore, BlobStoreConfig};
+pub use db::{Database, DatabaseConfig};
+pub use health::HealthStore;
+pub use kv::{KvStore, SharedStateConfig};
+pub use scores::{QualityScore, ScoreStore};
+pub use semantic_cache::{SemanticCache, SemanticCacheConfig};
+pub use usage::{UsageRecord, UsageStore, BENCH_ENDPOINT};
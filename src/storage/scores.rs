@@ -0,0 +1,118 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use serde::Serialize;
+
+use super::db::Database;
+
+/// A judge model's grades for one response (1-10)
+#[derive(Debug, Clone, Serialize)]
+pub struct QualityScore {
+    pub request_id: String,
+    pub provider: String,
+    pub model: String,
+    pub helpfulness: u8,
+    pub correctness: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rationale: Option<String>,
+}
+
+/// Average grades for one provider and model over a time window
+#[derive(Debug, Clone, Serialize)]
+pub struct QualitySummary {
+    pub provider: String,
+    pub model: String,
+    pub samples: u64,
+    pub helpfulness: f64,
+    pub correctness: f64,
+}
+
+/// Judge grades stored in the `quality_scores` table
+#[derive(Clone)]
+pub struct ScoreStore {
+    db: Database,
+}
+
+impl ScoreStore {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    pub async fn record(&self, score: &QualityScore) -> Result<()> {
+        let score = score.clone();
+        self.db
+            .run(move |conn| {
+                conn.execute(
+                    "INSERT INTO quality_scores (request_id, timestamp, provider, model, helpfulness, correctness, rationale)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        score.request_id,
+                        Utc::now(),
+                        score.provider,
+                        score.model,
+                        score.helpfulness,
+                        score.correctness,
+                        score.rationale,
+                    ],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    /// Average grades per provider and model since `since`
+    pub async fn summary(&self, since: DateTime<Utc>) -> Result<Vec<QualitySummary>> {
+        self.db
+            .run(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT provider, model, COUNT(*), AVG(helpfulness), AVG(correctness)
+                       FROM quality_scores
+                      WHERE timestamp >= ?1
+                      GROUP BY provider, model
+                      ORDER BY provider, model",
+                )?;
+                let rows = stmt
+                    .query_map(params![since], |row| {
+                        Ok(QualitySummary {
+                            provider: row.get(0)?,
+                            model: row.get(1)?,
+                            samples: row.get(2)?,
+                            helpfulness: row.get(3)?,
+                            correctness: row.get(4)?,
+                        })
+                    })?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                Ok(rows)
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_summary_averages_scores() {
+        let store = ScoreStore::new(Database::open_in_memory().unwrap());
+        for (helpfulness, correctness) in [(8, 9), (6, 5)] {
+            store
+                .record(&QualityScore {
+                    request_id: "req_1".to_string(),
+                    provider: "zai".to_string(),
+                    model: "glm-4.6".to_string(),
+                    helpfulness,
+                    correctness,
+                    rationale: None,
+                })
+                .await
+                .unwrap();
+        }
+
+        let summary = store.summary(Utc::now() - chrono::Duration::hours(1)).await.unwrap();
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].samples, 2);
+        assert_eq!(summary[0].helpfulness, 7.0);
+        assert_eq!(summary[0].correctness, 7.0);
+    }
+}
@@ -0,0 +1,319 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::models::{AnthropicRequest, ContentBlock, MessageContent};
+use crate::providers::ProviderResponse;
+
+/// Longest prompt text sent to the embeddings endpoint (the end of the conversation is kept)
+const MAX_EMBED_CHARS: usize = 8000;
+
+/// Semantic response cache configuration
+///
+/// Serves a stored response when a new non-streaming request to an opted-in model
+/// is close enough (cosine similarity of prompt embeddings) to an earlier one from
+/// the same client and user with the same system prompt and tools. Requests
+/// without a client key are never cached.
+///
+/// Example:
+/// ```toml
+/// [semantic_cache]
+/// enabled = true
+/// models = ["background"]
+/// similarity_threshold = 0.97
+///
+/// [semantic_cache.embeddings]
+/// base_url = "http://localhost:11434/v1"
+/// model = "nomic-embed-text"
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SemanticCacheConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Model names (from [[models]]) whose responses may be cached; nothing is cached by default
+    #[serde(default)]
+    pub models: Vec<String>,
+    #[serde(default = "default_similarity_threshold")]
+    pub similarity_threshold: f32,
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embeddings: Option<EmbeddingsConfig>,
+}
+
+/// OpenAI-compatible embeddings endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingsConfig {
+    /// Base URL; `/embeddings` is appended
+    pub base_url: String,
+    pub model: String,
+    /// API key (supports `$ENV_VAR`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+}
+
+impl Default for SemanticCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            models: Vec::new(),
+            similarity_threshold: default_similarity_threshold(),
+            ttl_secs: default_ttl_secs(),
+            max_entries: default_max_entries(),
+            embeddings: None,
+        }
+    }
+}
+
+fn default_similarity_threshold() -> f32 {
+    0.97
+}
+
+fn default_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_max_entries() -> usize {
+    1000
+}
+
+/// Where a request may be looked up and stored
+pub struct CacheKey {
+    /// Hash of everything that must match exactly (user, model, system prompt, tools)
+    scope: u64,
+    /// Normalized prompt embedding
+    embedding: Vec<f32>,
+}
+
+struct Entry {
+    scope: u64,
+    embedding: Vec<f32>,
+    response: ProviderResponse,
+    stored: Instant,
+}
+
+pub struct SemanticCache {
+    config: SemanticCacheConfig,
+    embeddings: EmbeddingsConfig,
+    client: reqwest::Client,
+    entries: Mutex<VecDeque<Entry>>,
+}
+
+impl SemanticCache {
+    /// None when the cache is disabled or has no embeddings endpoint
+    pub fn from_config(config: &SemanticCacheConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        let Some(embeddings) = config.embeddings.clone() else {
+            tracing::warn!("semantic_cache is enabled but [semantic_cache.embeddings] is missing; cache disabled");
+            return None;
+        };
+        Some(Self {
+            config: config.clone(),
+            embeddings,
+            client: reqwest::Client::new(),
+            entries: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    /// Whether requests routed to this model opted in
+    pub fn applies_to(&self, model: &str) -> bool {
+        self.config.models.iter().any(|m| m == model)
+    }
+
+    /// Embed the prompt of a request routed to `model`
+    ///
+    /// `client` identifies who authenticated (tenant or key hash); responses are
+    /// only shared within it.
+    pub async fn key(&self, request: &AnthropicRequest, model: &str, client: &str) -> Result<CacheKey> {
+        let embedding = self.embed(&prompt_text(request)).await?;
+        Ok(CacheKey {
+            scope: scope(request, model, client),
+            embedding: normalize(embedding),
+        })
+    }
+
+    pub fn lookup(&self, key: &CacheKey) -> Option<ProviderResponse> {
+        let ttl = Duration::from_secs(self.config.ttl_secs);
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|e| e.stored.elapsed() < ttl);
+
+        entries
+            .iter()
+            .filter(|e| e.scope == key.scope)
+            .map(|e| (dot(&e.embedding, &key.embedding), e))
+            .filter(|(similarity, _)| *similarity >= self.config.similarity_threshold)
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(similarity, e)| {
+                tracing::debug!("🧠 Semantic cache hit (similarity {:.3})", similarity);
+                e.response.clone()
+            })
+    }
+
+    /// Store a response; only complete text answers are cached
+    pub fn insert(&self, key: CacheKey, response: &ProviderResponse) {
+        let complete = response.stop_reason.as_deref() == Some("end_turn")
+            && response.content.iter().all(|b| matches!(b, ContentBlock::Text { .. }));
+        if !complete {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.push_back(Entry {
+            scope: key.scope,
+            embedding: key.embedding,
+            response: response.clone(),
+            stored: Instant::now(),
+        });
+        while entries.len() > self.config.max_entries {
+            entries.pop_front();
+        }
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let url = format!("{}/embeddings", self.embeddings.base_url.trim_end_matches('/'));
+        let mut request = self.client.post(&url).json(&serde_json::json!({
+            "model": self.embeddings.model,
+            "input": text,
+        }));
+        if let Some(key) = &self.embeddings.api_key {
+            request = request.bearer_auth(key);
+        }
+        let body: serde_json::Value = request
+            .send()
+            .await
+            .with_context(|| format!("Embeddings request to {} failed", url))?
+            .error_for_status()?
+            .json()
+            .await?;
+        serde_json::from_value(body["data"][0]["embedding"].clone()).context("Embeddings response has no data[0].embedding")
+    }
+}
+
+/// User that sent the request: Claude Code's `metadata.user_id` without the session part
+fn user(request: &AnthropicRequest) -> &str {
+    let user_id = request
+        .metadata
+        .as_ref()
+        .and_then(|m| m.get("user_id"))
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+    user_id.split("_session_").next().unwrap_or_default()
+}
+
+fn scope(request: &AnthropicRequest, model: &str, client: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    client.hash(&mut hasher);
+    user(request).hash(&mut hasher);
+    model.hash(&mut hasher);
+    serde_json::to_string(&request.system).unwrap_or_default().hash(&mut hasher);
+    serde_json::to_string(&request.tools).unwrap_or_default().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Conversation text that gets embedded
+fn prompt_text(request: &AnthropicRequest) -> String {
+    let mut text = String::new();
+    for message in &request.messages {
+        text.push_str(&message.role);
+        text.push_str(": ");
+        match &message.content {
+            MessageContent::Text(t) => text.push_str(t),
+            MessageContent::Blocks(blocks) => {
+                for block in blocks {
+                    if let ContentBlock::Text { text: t } = block {
+                        text.push_str(t);
+                    }
+                }
+            }
+        }
+        text.push('\n');
+    }
+    match text.char_indices().nth_back(MAX_EMBED_CHARS - 1) {
+        Some((start, _)) => text[start..].to_string(),
+        None => text,
+    }
+}
+
+fn normalize(mut v: Vec<f32>) -> Vec<f32> {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+    v
+}
+
+/// Cosine similarity of normalized vectors
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::Usage;
+
+    fn response(text: &str) -> ProviderResponse {
+        ProviderResponse {
+            id: "msg_1".to_string(),
+            r#type: "message".to_string(),
+            role: "assistant".to_string(),
+            content: vec![ContentBlock::Text { text: text.to_string() }],
+            model: "m".to_string(),
+            stop_reason: Some("end_turn".to_string()),
+            stop_sequence: None,
+            usage: Usage {
+                input_tokens: 0,
+                output_tokens: 0,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_lookup_by_similarity_within_scope() {
+        let cache = SemanticCache::from_config(&SemanticCacheConfig {
+            enabled: true,
+            models: vec!["background".to_string()],
+            similarity_threshold: 0.95,
+            embeddings: Some(EmbeddingsConfig {
+                base_url: "http://localhost".to_string(),
+                model: "e".to_string(),
+                api_key: None,
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+        let key = |scope, embedding: Vec<f32>| CacheKey {
+            scope,
+            embedding: normalize(embedding),
+        };
+
+        cache.insert(key(1, vec![1.0, 0.0, 0.0]), &response("cached"));
+        let hit = cache.lookup(&key(1, vec![0.99, 0.05, 0.0])).unwrap();
+        assert!(matches!(&hit.content[0], ContentBlock::Text { text } if text == "cached"));
+
+        assert!(cache.lookup(&key(1, vec![0.5, 0.5, 0.0])).is_none());
+        assert!(cache.lookup(&key(2, vec![1.0, 0.0, 0.0])).is_none());
+    }
+
+    #[test]
+    fn test_scope_separates_clients() {
+        let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "m", "max_tokens": 10,
+            "messages": [{ "role": "user", "content": "hi" }],
+        }))
+        .unwrap();
+        assert_eq!(scope(&request, "background", "team-a"), scope(&request, "background", "team-a"));
+        assert_ne!(scope(&request, "background", "team-a"), scope(&request, "background", "team-b"));
+    }
+}
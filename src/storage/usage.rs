@@ -0,0 +1,239 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+use super::db::Database;
+
+/// Endpoint recorded for `ccm bench` runs, which usage reports leave out
+pub const BENCH_ENDPOINT: &str = "bench";
+
+/// One completed request, as persisted for usage statistics
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageRecord {
+    #[serde(default)]
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub endpoint: String,
+    /// Model requested by the client
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub routed_model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actual_model: Option<String>,
+    #[serde(default)]
+    pub stream: bool,
+    pub success: bool,
+    pub latency_ms: u64,
+    #[serde(default)]
+    pub input_tokens: u32,
+    #[serde(default)]
+    pub output_tokens: u32,
+    /// Estimated cost in USD (None when the mapping has no pricing)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl UsageRecord {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get("id")?,
+            timestamp: row.get("timestamp")?,
+            endpoint: row.get("endpoint")?,
+            model: row.get("model")?,
+            routed_model: row.get("routed_model")?,
+            route_type: row.get("route_type")?,
+            provider: row.get("provider")?,
+            actual_model: row.get("actual_model")?,
+            stream: row.get("stream")?,
+            success: row.get("success")?,
+            latency_ms: row.get("latency_ms")?,
+            input_tokens: row.get("input_tokens")?,
+            output_tokens: row.get("output_tokens")?,
+            cost_usd: row.get("cost_usd")?,
+            error: row.get("error")?,
+        })
+    }
+}
+
+/// Request history stored in the `requests` table
+#[derive(Clone)]
+pub struct UsageStore {
+    db: Database,
+}
+
+impl UsageStore {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    pub async fn append(&self, record: &UsageRecord) -> Result<()> {
+        let record = record.clone();
+        self.db.run(move |conn| append(conn, &record)).await
+    }
+
+    /// All records for a given UTC day
+    pub async fn load_day(&self, day: NaiveDate) -> Result<Vec<UsageRecord>> {
+        let start = day.and_hms_opt(0, 0, 0).expect("valid time").and_utc();
+        let end = start + chrono::Duration::days(1);
+        self.load_range(start, end).await
+    }
+
+    /// Records of client traffic (not benchmarks) between `start` and `end`
+    pub async fn load_range(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<UsageRecord>> {
+        self.db
+            .run(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT * FROM requests
+                      WHERE timestamp >= ?1 AND timestamp < ?2 AND endpoint != ?3
+                      ORDER BY timestamp",
+                )?;
+                let records = stmt
+                    .query_map(params![start, end, BENCH_ENDPOINT], UsageRecord::from_row)?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                Ok(records)
+            })
+            .await
+    }
+
+    /// Import legacy per-day JSON-lines usage files, renaming them once imported
+    pub async fn import_jsonl_dir(&self, dir: &Path) -> Result<usize> {
+        let dir = dir.to_path_buf();
+        self.db.run(move |conn| import_jsonl_dir(conn, &dir)).await
+    }
+}
+
+fn append(conn: &Connection, record: &UsageRecord) -> Result<()> {
+    let id = if record.id.is_empty() {
+        format!("{}-{}", record.timestamp.timestamp_nanos_opt().unwrap_or_default(), record.model)
+    } else {
+        record.id.clone()
+    };
+    conn.execute(
+        "INSERT OR IGNORE INTO requests (
+            id, timestamp, endpoint, model, routed_model, route_type, provider, actual_model,
+            stream, success, latency_ms, input_tokens, output_tokens, cost_usd, error
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+        params![
+            id,
+            record.timestamp,
+            record.endpoint,
+            record.model,
+            record.routed_model,
+            record.route_type,
+            record.provider,
+            record.actual_model,
+            record.stream,
+            record.success,
+            record.latency_ms,
+            record.input_tokens,
+            record.output_tokens,
+            record.cost_usd,
+            record.error,
+        ],
+    )?;
+    Ok(())
+}
+
+fn import_jsonl_dir(conn: &Connection, dir: &Path) -> Result<usize> {
+    if !dir.is_dir() {
+        return Ok(0);
+    }
+
+    let mut imported = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("jsonl") {
+            continue;
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read usage file: {}", path.display()))?;
+        for line in content.lines() {
+            if let Ok(record) = serde_json::from_str::<UsageRecord>(line) {
+                append(conn, &record)?;
+                imported += 1;
+            }
+        }
+        fs::rename(&path, path.with_extension("jsonl.imported"))?;
+    }
+
+    if imported > 0 {
+        info!("🗄️  Imported {} usage records from {}", imported, dir.display());
+    }
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn record() -> UsageRecord {
+        UsageRecord {
+            id: String::new(),
+            timestamp: Utc::now(),
+            endpoint: "/v1/messages".to_string(),
+            model: "claude-sonnet".to_string(),
+            routed_model: Some("default".to_string()),
+            route_type: Some("default".to_string()),
+            provider: Some("zai".to_string()),
+            actual_model: Some("glm-4.6".to_string()),
+            stream: false,
+            success: true,
+            latency_ms: 120,
+            input_tokens: 10,
+            output_tokens: 20,
+            cost_usd: None,
+            error: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_append_and_load_day() {
+        let store = UsageStore::new(Database::open_in_memory().unwrap());
+
+        let mut first = record();
+        first.id = "req_1".to_string();
+        let mut second = record();
+        second.id = "req_2".to_string();
+        let mut bench = record();
+        bench.id = "bench_1".to_string();
+        bench.endpoint = BENCH_ENDPOINT.to_string();
+        store.append(&first).await.unwrap();
+        store.append(&second).await.unwrap();
+        store.append(&bench).await.unwrap();
+
+        let records = store.load_day(first.timestamp.date_naive()).await.unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].output_tokens, 20);
+    }
+
+    #[tokio::test]
+    async fn test_imports_legacy_jsonl() {
+        let temp_dir = TempDir::new().unwrap();
+        let legacy = temp_dir.path().join("2025-01-01.jsonl");
+        // Legacy records had no id/endpoint fields
+        fs::write(
+            &legacy,
+            r#"{"timestamp":"2025-01-01T10:00:00Z","model":"m","success":true,"latency_ms":5,"input_tokens":1,"output_tokens":2}"#,
+        )
+        .unwrap();
+
+        let store = UsageStore::new(Database::open_in_memory().unwrap());
+        assert_eq!(store.import_jsonl_dir(temp_dir.path()).await.unwrap(), 1);
+        assert!(!legacy.exists());
+
+        let day = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        assert_eq!(store.load_day(day).await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,309 @@
+//! Export of request/response traces to LLM observability platforms
+//! (Langfuse, LangSmith)
+//!
+//! Each exporter can be limited to some tenants (client names of API keys),
+//! so teams can send their traffic to their own project.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// One trace exporter
+///
+/// Example:
+/// ```toml
+/// [[trace_export]]
+/// backend = "langfuse"
+/// public_key = "$LANGFUSE_PUBLIC_KEY"
+/// secret_key = "$LANGFUSE_SECRET_KEY"
+///
+/// [[trace_export]]
+/// backend = "langsmith"
+/// api_key = "$LANGSMITH_API_KEY"
+/// project = "ci-agents"
+/// tenants = ["ci"]
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceExportConfig {
+    pub backend: TraceBackend,
+    /// Only export requests from these clients (default: all requests)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tenants: Vec<String>,
+    /// API URL (default: the backend's cloud endpoint)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    /// Langfuse public key (supports `$ENV_VAR`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+    /// Langfuse secret key (supports `$ENV_VAR`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_key: Option<String>,
+    /// LangSmith API key (supports `$ENV_VAR`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// LangSmith project (default: "default")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceBackend {
+    Langfuse,
+    Langsmith,
+}
+
+impl TraceExportConfig {
+    /// Replace `$ENV_VAR` credentials with their values (unset variables clear the field)
+    pub fn resolve_env_vars(&mut self) {
+        for field in [&mut self.public_key, &mut self.secret_key, &mut self.api_key] {
+            if let Some(var) = field.as_deref().and_then(|v| v.strip_prefix('$')) {
+                *field = std::env::var(var).ok();
+            }
+        }
+    }
+
+    fn applies_to(&self, tenant: Option<&str>) -> bool {
+        self.tenants.is_empty() || tenant.is_some_and(|t| self.tenants.iter().any(|x| x == t))
+    }
+
+    fn has_credentials(&self) -> bool {
+        match self.backend {
+            TraceBackend::Langfuse => self.public_key.is_some() && self.secret_key.is_some(),
+            TraceBackend::Langsmith => self.api_key.is_some(),
+        }
+    }
+}
+
+/// A completed request as seen by the exporters
+#[derive(Debug, Clone)]
+pub struct Trace {
+    pub id: String,
+    pub tenant: Option<String>,
+    pub endpoint: String,
+    /// Model requested by the client
+    pub model: String,
+    pub provider: Option<String>,
+    /// Upstream model that served the request
+    pub actual_model: Option<String>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Request body (messages, tools, ...)
+    pub input: Option<Value>,
+    /// Response body (text and tool calls); not captured for streams
+    pub output: Option<Value>,
+    pub input_tokens: Option<u32>,
+    pub output_tokens: Option<u32>,
+    pub cost_usd: Option<f64>,
+    pub error: Option<String>,
+}
+
+/// Sends traces to the configured exporters in the background
+pub struct TraceExporter {
+    exporters: Vec<TraceExportConfig>,
+    client: reqwest::Client,
+}
+
+impl TraceExporter {
+    /// None when no usable exporter is configured
+    pub fn from_config(configs: &[TraceExportConfig]) -> Option<Self> {
+        let exporters: Vec<_> = configs
+            .iter()
+            .filter(|c| {
+                let usable = c.has_credentials();
+                if !usable {
+                    warn!("{:?} trace export is missing credentials; skipped", c.backend);
+                }
+                usable
+            })
+            .cloned()
+            .collect();
+        if exporters.is_empty() {
+            return None;
+        }
+        Some(Self {
+            exporters,
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Whether requests from this tenant are exported (bodies must then be captured)
+    pub fn wants(&self, tenant: Option<&str>) -> bool {
+        self.exporters.iter().any(|e| e.applies_to(tenant))
+    }
+
+    pub fn export(&self, trace: Trace) {
+        for exporter in self.exporters.iter().filter(|e| e.applies_to(trace.tenant.as_deref())) {
+            let request = match exporter.backend {
+                TraceBackend::Langfuse => {
+                    let base = exporter.base_url.as_deref().unwrap_or("https://cloud.langfuse.com");
+                    self.client
+                        .post(format!("{}/api/public/ingestion", base.trim_end_matches('/')))
+                        .basic_auth(exporter.public_key.as_deref().unwrap_or_default(), exporter.secret_key.as_deref())
+                        .json(&langfuse_batch(&trace))
+                }
+                TraceBackend::Langsmith => {
+                    let base = exporter.base_url.as_deref().unwrap_or("https://api.smith.langchain.com");
+                    self.client
+                        .post(format!("{}/runs", base.trim_end_matches('/')))
+                        .header("x-api-key", exporter.api_key.as_deref().unwrap_or_default())
+                        .json(&langsmith_run(&trace, exporter.project.as_deref().unwrap_or("default")))
+                }
+            };
+            let backend = exporter.backend;
+            let id = trace.id.clone();
+            tokio::spawn(async move {
+                match request.send().await {
+                    Ok(resp) if resp.status().is_success() => debug!("📈 Exported trace {} to {:?}", id, backend),
+                    Ok(resp) => warn!("{:?} trace export returned {}", backend, resp.status()),
+                    Err(e) => warn!("Failed to export trace to {:?}: {}", backend, e),
+                }
+            });
+        }
+    }
+}
+
+/// Langfuse ingestion batch: a trace with one generation
+fn langfuse_batch(trace: &Trace) -> Value {
+    let now = Utc::now().to_rfc3339();
+    let generation_id = format!("{}-generation", trace.id);
+    json!({
+        "batch": [
+            {
+                "id": format!("{}-trace-event", trace.id),
+                "type": "trace-create",
+                "timestamp": now,
+                "body": {
+                    "id": trace.id,
+                    "name": trace.endpoint,
+                    "timestamp": trace.start.to_rfc3339(),
+                    "userId": trace.tenant,
+                    "input": trace.input,
+                    "output": trace.output,
+                    "tags": ["claude-code-mux"],
+                },
+            },
+            {
+                "id": format!("{}-generation-event", trace.id),
+                "type": "generation-create",
+                "timestamp": now,
+                "body": {
+                    "id": generation_id,
+                    "traceId": trace.id,
+                    "name": trace.model,
+                    "startTime": trace.start.to_rfc3339(),
+                    "endTime": trace.end.to_rfc3339(),
+                    "model": trace.actual_model.as_deref().unwrap_or(&trace.model),
+                    "input": trace.input,
+                    "output": trace.output,
+                    "usage": {
+                        "input": trace.input_tokens,
+                        "output": trace.output_tokens,
+                        "unit": "TOKENS",
+                        "totalCost": trace.cost_usd,
+                    },
+                    "metadata": { "provider": trace.provider },
+                    "level": if trace.error.is_some() { "ERROR" } else { "DEFAULT" },
+                    "statusMessage": trace.error,
+                },
+            },
+        ]
+    })
+}
+
+/// LangSmith LLM run
+fn langsmith_run(trace: &Trace, project: &str) -> Value {
+    json!({
+        "id": uuid_from(&trace.id),
+        "name": trace.model,
+        "run_type": "llm",
+        "session_name": project,
+        "start_time": trace.start.to_rfc3339(),
+        "end_time": trace.end.to_rfc3339(),
+        "inputs": trace.input.clone().unwrap_or_default(),
+        "outputs": trace.output.clone().unwrap_or_default(),
+        "error": trace.error,
+        "extra": {
+            "metadata": {
+                "request_id": trace.id,
+                "tenant": trace.tenant,
+                "endpoint": trace.endpoint,
+                "provider": trace.provider,
+                "ls_model_name": trace.actual_model.as_deref().unwrap_or(&trace.model),
+                "usage_metadata": {
+                    "input_tokens": trace.input_tokens,
+                    "output_tokens": trace.output_tokens,
+                },
+                "cost_usd": trace.cost_usd,
+            },
+        },
+        "tags": ["claude-code-mux"],
+    })
+}
+
+/// LangSmith requires UUID run ids; derive one from the request id
+fn uuid_from(id: &str) -> String {
+    use sha2::{Digest, Sha256};
+    let mut bytes: [u8; 16] = Sha256::digest(id.as_bytes())[..16].try_into().expect("16 bytes");
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace() -> Trace {
+        Trace {
+            id: "req_1".to_string(),
+            tenant: Some("ci".to_string()),
+            endpoint: "/v1/messages".to_string(),
+            model: "claude-sonnet".to_string(),
+            provider: Some("openrouter".to_string()),
+            actual_model: Some("anthropic/claude-sonnet-4".to_string()),
+            start: Utc::now(),
+            end: Utc::now(),
+            input: Some(json!({ "messages": [{ "role": "user", "content": "hi" }] })),
+            output: Some(json!({ "content": [{ "type": "tool_use", "name": "Bash", "input": {} }] })),
+            input_tokens: Some(12),
+            output_tokens: Some(3),
+            cost_usd: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_payloads() {
+        let batch = langfuse_batch(&trace());
+        let generation = &batch["batch"][1]["body"];
+        assert_eq!(generation["traceId"], "req_1");
+        assert_eq!(generation["model"], "anthropic/claude-sonnet-4");
+        assert_eq!(generation["usage"]["input"], 12);
+        assert_eq!(generation["output"]["content"][0]["name"], "Bash");
+
+        let run = langsmith_run(&trace(), "agents");
+        assert_eq!(run["session_name"], "agents");
+        assert_eq!(run["run_type"], "llm");
+        let id = run["id"].as_str().unwrap();
+        assert_eq!(id.len(), 36);
+        assert_eq!(&id[14..15], "4");
+        assert_eq!(id, uuid_from("req_1"));
+    }
+
+    #[test]
+    fn test_tenant_filter() {
+        let config: TraceExportConfig =
+            toml::from_str("backend = \"langsmith\"\napi_key = \"k\"\ntenants = [\"ci\"]").unwrap();
+        let exporter = TraceExporter::from_config(&[config]).unwrap();
+        assert!(exporter.wants(Some("ci")));
+        assert!(!exporter.wants(Some("web")));
+        assert!(!exporter.wants(None));
+    }
+}
@@ -0,0 +1,278 @@
+use bytes::Bytes;
+use futures::stream::Stream;
+use std::collections::HashMap;
+use tracing::info;
+
+use crate::models::ContentBlock;
+use crate::providers::streaming::{parse_sse_events, LineBuffer, SseEvent};
+use crate::providers::ProviderResponse;
+
+/// Repairs broken markdown code fences in model output, line by line
+///
+/// Weaker models glue fences to surrounding text (`Here it is:```rust`, `}```)
+/// or stop without closing a fence. Text is fed in as it arrives; only complete
+/// lines are returned, the rest is held until more text or `finish`.
+#[derive(Debug)]
+pub struct FenceRepair {
+    /// Marker of the open fence (e.g. "```" or "~~~~")
+    open: Option<String>,
+    /// Incomplete last line
+    pending: String,
+    /// Whether the returned text ends at a line start
+    at_line_start: bool,
+    /// Number of repairs made
+    repairs: usize,
+}
+
+impl Default for FenceRepair {
+    fn default() -> Self {
+        Self {
+            open: None,
+            pending: String::new(),
+            at_line_start: true,
+            repairs: 0,
+        }
+    }
+}
+
+impl FenceRepair {
+    /// Add text, returning the repaired complete lines
+    pub fn push(&mut self, text: &str) -> String {
+        self.pending.push_str(text);
+        let Some(end) = self.pending.rfind('\n') else {
+            return String::new();
+        };
+        let complete: String = self.pending.drain(..=end).collect();
+        let output: String = complete.split_inclusive('\n').map(|line| self.line(line)).collect();
+        self.at_line_start = true;
+        output
+    }
+
+    /// The rest of the text, closing a fence that is still open
+    pub fn finish(&mut self) -> String {
+        let mut output = String::new();
+        if !self.pending.is_empty() {
+            let line = std::mem::take(&mut self.pending);
+            output = self.line(&line);
+            self.at_line_start = output.ends_with('\n');
+        }
+        if let Some(marker) = self.open.take() {
+            if !self.at_line_start {
+                output.push('\n');
+            }
+            output.push_str(&marker);
+            self.repairs += 1;
+        }
+        output
+    }
+
+    pub fn repairs(&self) -> usize {
+        self.repairs
+    }
+
+    fn line(&mut self, line: &str) -> String {
+        let (body, newline) = match line.strip_suffix('\n') {
+            Some(body) => (body, "\n"),
+            None => (line, ""),
+        };
+        match &self.open {
+            None => {
+                if let Some(marker) = opening_fence(body.trim_start()) {
+                    self.open = Some(marker);
+                    return line.to_string();
+                }
+                // Fence glued to the end of a prose line
+                if let Some(pos) = body.find("```") {
+                    let (before, fence) = body.split_at(pos);
+                    if !before.trim().is_empty() && !before.contains('`') {
+                        if let Some(marker) = opening_fence(fence) {
+                            self.open = Some(marker);
+                            self.repairs += 1;
+                            return format!("{}\n{}{}", before.trim_end(), fence, newline);
+                        }
+                    }
+                }
+                line.to_string()
+            }
+            Some(marker) => {
+                let trimmed = body.trim();
+                let fence_char = marker.chars().next().unwrap_or('`');
+                if trimmed.len() >= marker.len() && trimmed.chars().all(|c| c == fence_char) {
+                    self.open = None;
+                    return line.to_string();
+                }
+                // Closing fence glued to the end of a code line
+                if let Some(code) = body.trim_end().strip_suffix(marker.as_str()) {
+                    if !code.trim().is_empty() && !code.contains(fence_char) {
+                        let marker = self.open.take().unwrap_or_default();
+                        self.repairs += 1;
+                        return format!("{}\n{}{}", code, marker, newline);
+                    }
+                }
+                line.to_string()
+            }
+        }
+    }
+}
+
+/// Marker of a fence opening line (three or more backticks or tildes and an optional language)
+fn opening_fence(line: &str) -> Option<String> {
+    let fence_char = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let marker: String = line.chars().take_while(|c| *c == fence_char).collect();
+    let info = line[marker.len()..].trim();
+    let valid_info = info
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '+' | '.' | '#'));
+    (marker.len() >= 3 && valid_info).then_some(marker)
+}
+
+/// Repair the code fences in the text blocks of a response
+pub fn repair_response(response: &mut ProviderResponse) {
+    let mut repairs = 0;
+    for block in &mut response.content {
+        if let ContentBlock::Text { text } = block {
+            let mut repair = FenceRepair::default();
+            let mut repaired = repair.push(text);
+            repaired.push_str(&repair.finish());
+            if repair.repairs() > 0 {
+                repairs += repair.repairs();
+                *text = repaired;
+            }
+        }
+    }
+    if repairs > 0 {
+        info!("🩹 Repaired {} code fences", repairs);
+    }
+}
+
+/// Repair the code fences of text blocks in an Anthropic SSE stream
+///
+/// Text deltas are released a line at a time; a fence left open is closed
+/// just before the block's `content_block_stop`.
+pub fn repair_stream<S, E>(stream: S) -> impl Stream<Item = Result<Bytes, E>> + Send
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + Unpin + 'static,
+    E: Send + 'static,
+{
+    use futures::StreamExt;
+
+    futures::stream::unfold(
+        (stream, LineBuffer::default(), HashMap::new(), false),
+        |(mut stream, mut buffer, mut blocks, done)| async move {
+            if done {
+                return None;
+            }
+            loop {
+                match stream.next().await {
+                    Some(Ok(bytes)) => {
+                        buffer.push(&bytes);
+                        let mut output = String::new();
+                        while let Some(event) = buffer.event() {
+                            output.push_str(&repair_event(&event, &mut blocks));
+                        }
+                        if output.is_empty() {
+                            continue;
+                        }
+                        return Some((Ok(Bytes::from(output)), (stream, buffer, blocks, false)));
+                    }
+                    Some(Err(e)) => return Some((Err(e), (stream, buffer, blocks, false))),
+                    None => {
+                        if buffer.is_empty() {
+                            return None;
+                        }
+                        return Some((Ok(Bytes::from(buffer.rest())), (stream, buffer, blocks, true)));
+                    }
+                }
+            }
+        },
+    )
+}
+
+fn text_delta(index: u64, text: String) -> String {
+    SseEvent {
+        event: Some("content_block_delta".to_string()),
+        data: serde_json::json!({
+            "type": "content_block_delta",
+            "index": index,
+            "delta": { "type": "text_delta", "text": text },
+        })
+        .to_string(),
+    }
+    .to_sse_string()
+}
+
+/// Rewrite one SSE event for `repair_stream`
+fn repair_event(event: &str, blocks: &mut HashMap<u64, FenceRepair>) -> String {
+    let Some(data) = parse_sse_events(event)
+        .into_iter()
+        .next()
+        .and_then(|e| serde_json::from_str::<serde_json::Value>(&e.data).ok())
+    else {
+        return event.to_string();
+    };
+    let index = data["index"].as_u64().unwrap_or(0);
+
+    match data["type"].as_str() {
+        Some("content_block_start") if data["content_block"]["type"] == "text" => {
+            blocks.insert(index, FenceRepair::default());
+            event.to_string()
+        }
+        Some("content_block_delta") if data["delta"]["type"] == "text_delta" => match blocks.get_mut(&index) {
+            Some(repair) => {
+                let text = repair.push(data["delta"]["text"].as_str().unwrap_or_default());
+                if text.is_empty() {
+                    String::new()
+                } else {
+                    text_delta(index, text)
+                }
+            }
+            None => event.to_string(),
+        },
+        Some("content_block_stop") => match blocks.remove(&index) {
+            Some(mut repair) => {
+                let tail = repair.finish();
+                if repair.repairs() > 0 {
+                    info!("🩹 Repaired {} code fences", repair.repairs());
+                }
+                if tail.is_empty() {
+                    event.to_string()
+                } else {
+                    format!("{}{}", text_delta(index, tail), event)
+                }
+            }
+            None => event.to_string(),
+        },
+        _ => event.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repair(text: &str) -> String {
+        let mut repair = FenceRepair::default();
+        // Fed in small pieces, as a stream would be
+        let mut output: String = text
+            .as_bytes()
+            .chunks(5)
+            .map(|chunk| repair.push(std::str::from_utf8(chunk).unwrap()))
+            .collect();
+        output.push_str(&repair.finish());
+        output
+    }
+
+    #[test]
+    fn test_repair_fences() {
+        let intact = "Fix:\n\n```rust\nfn main() {}\n```\n\nDone.";
+        assert_eq!(repair(intact), intact);
+
+        assert_eq!(repair("Here it is:```rust\nfn main() {}\n```"), "Here it is:\n```rust\nfn main() {}\n```");
+        assert_eq!(repair("```rust\nfn main() {}```\nDone."), "```rust\nfn main() {}\n```\nDone.");
+        assert_eq!(repair("```py\nprint(1)\n"), "```py\nprint(1)\n```");
+        assert_eq!(repair("```py\nprint(1)"), "```py\nprint(1)\n```");
+        // Inline code and longer fences are left alone
+        assert_eq!(repair("Use `x``` here"), "Use `x``` here");
+        assert_eq!(repair("````md\n```\ninner\n```\n````"), "````md\n```\ninner\n```\n````");
+    }
+}
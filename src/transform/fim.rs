@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+
+/// Most stop sequences OpenAI-compatible completions endpoints accept
+const MAX_STOP_SEQUENCES: usize = 4;
+
+/// How a fill-in-the-middle request is sent to a completions endpoint
+///
+/// Example (model mapping):
+/// ```toml
+/// [[models.mappings]]
+/// priority = 1
+/// provider = "local-llama"
+/// actual_model = "qwen2.5-coder-7b"
+/// fim = "qwen"
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FimTemplate {
+    /// The API takes `prompt` + `suffix` itself (DeepSeek /beta, Codestral /v1/fim)
+    Native,
+    /// `<fim_prefix>…<fim_suffix>…<fim_middle>` (StarCoder, StarCoder2)
+    Starcoder,
+    /// `<|fim_prefix|>…<|fim_suffix|>…<|fim_middle|>` (Qwen2.5-Coder)
+    Qwen,
+    /// `<｜fim▁begin｜>…<｜fim▁hole｜>…<｜fim▁end｜>` (DeepSeek-Coder on local servers)
+    Deepseek,
+    /// `[SUFFIX]…[PREFIX]…` (Codestral on local servers)
+    Codestral,
+}
+
+impl FimTemplate {
+    /// Build the completions `prompt` (and `suffix`, for native FIM APIs)
+    pub fn render(self, prefix: &str, suffix: &str) -> (String, Option<String>) {
+        let prompt = match self {
+            FimTemplate::Native => return (prefix.to_string(), Some(suffix.to_string())),
+            FimTemplate::Starcoder => format!("<fim_prefix>{}<fim_suffix>{}<fim_middle>", prefix, suffix),
+            FimTemplate::Qwen => format!("<|fim_prefix|>{}<|fim_suffix|>{}<|fim_middle|>", prefix, suffix),
+            FimTemplate::Deepseek => format!("<｜fim▁begin｜>{}<｜fim▁hole｜>{}<｜fim▁end｜>", prefix, suffix),
+            FimTemplate::Codestral => format!("[SUFFIX]{}[PREFIX]{}", suffix, prefix),
+        };
+        (prompt, None)
+    }
+
+    /// Special tokens that end the middle section for raw templates
+    pub fn stop_tokens(self) -> &'static [&'static str] {
+        match self {
+            FimTemplate::Native => &[],
+            FimTemplate::Starcoder => &["<|endoftext|>", "<fim_prefix>", "<fim_suffix>", "<fim_middle>"],
+            FimTemplate::Qwen => &["<|endoftext|>", "<|fim_prefix|>", "<|fim_suffix|>", "<|fim_pad|>"],
+            FimTemplate::Deepseek => &["<｜end▁of▁sentence｜>", "<｜fim▁begin｜>"],
+            FimTemplate::Codestral => &["</s>", "[PREFIX]", "[SUFFIX]"],
+        }
+    }
+
+    /// Stop sequences to send: the client's first, then the template's, at most
+    /// [`MAX_STOP_SEQUENCES`] (the server still stops at end of sequence)
+    pub fn stop(self, client: Vec<String>) -> Vec<String> {
+        let mut stop = client;
+        for token in self.stop_tokens() {
+            if !stop.iter().any(|s| s == token) {
+                stop.push(token.to_string());
+            }
+        }
+        stop.truncate(MAX_STOP_SEQUENCES);
+        stop
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_templates() {
+        assert_eq!(
+            FimTemplate::Starcoder.render("a", "b"),
+            ("<fim_prefix>a<fim_suffix>b<fim_middle>".to_string(), None)
+        );
+        assert_eq!(
+            FimTemplate::Codestral.render("a", "b"),
+            ("[SUFFIX]b[PREFIX]a".to_string(), None)
+        );
+        assert_eq!(
+            FimTemplate::Native.render("a", "b"),
+            ("a".to_string(), Some("b".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_stop_sequences_are_capped() {
+        let stop = FimTemplate::Qwen.stop(vec!["\n\n".to_string()]);
+        assert_eq!(stop, ["\n\n", "<|endoftext|>", "<|fim_prefix|>", "<|fim_suffix|>"]);
+
+        let client: Vec<String> = (0..6).map(|i| i.to_string()).collect();
+        assert_eq!(FimTemplate::Native.stop(client).len(), 4);
+        assert_eq!(FimTemplate::Codestral.stop(Vec::new()), ["</s>", "[PREFIX]", "[SUFFIX]"]);
+    }
+}
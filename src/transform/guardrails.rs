@@ -0,0 +1,191 @@
+use crate::models::{AnthropicRequest, ContentBlock, MessageContent, SystemBlock, SystemPrompt};
+use serde::{Deserialize, Serialize};
+
+/// Limits for autonomous agent traffic
+///
+/// Requests carry the whole conversation, so both checks look at the history
+/// of the request itself; no session state is kept.
+///
+/// Example:
+/// ```toml
+/// [guardrails]
+/// max_repeated_tool_calls = 5
+/// max_turns = 200
+/// action = "warn"
+///
+/// [[guardrails.keys]]
+/// api_key = "$CI_AGENT_KEY"
+/// max_repeated_tool_calls = 3
+/// action = "refuse"
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GuardrailsConfig {
+    #[serde(flatten)]
+    pub limits: GuardrailLimits,
+    /// Overrides for requests authenticated with a specific key
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keys: Vec<KeyGuardrails>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GuardrailLimits {
+    /// Identical tool calls (same name and input) in a row before acting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_repeated_tool_calls: Option<usize>,
+    /// Assistant turns per conversation before acting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_turns: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<GuardrailAction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyGuardrails {
+    /// Key sent in `x-api-key` or `Authorization: Bearer` (supports `$ENV_VAR`)
+    pub api_key: String,
+    #[serde(flatten)]
+    pub limits: GuardrailLimits,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GuardrailAction {
+    /// Tell the model in the system prompt and let the request through
+    #[default]
+    Warn,
+    /// Reject the request
+    Refuse,
+}
+
+impl GuardrailsConfig {
+    /// Limits for a request's key: key overrides on top of the global limits
+    pub fn limits_for(&self, api_key: Option<&str>) -> GuardrailLimits {
+        let mut limits = self.limits.clone();
+        if let Some(key) = api_key.and_then(|k| self.keys.iter().find(|g| g.api_key == k)) {
+            limits.max_repeated_tool_calls = key.limits.max_repeated_tool_calls.or(limits.max_repeated_tool_calls);
+            limits.max_turns = key.limits.max_turns.or(limits.max_turns);
+            limits.action = key.limits.action.or(limits.action);
+        }
+        limits
+    }
+}
+
+impl GuardrailLimits {
+    /// Apply the limits; returns the refusal message when the request must be rejected
+    pub fn apply(&self, request: &mut AnthropicRequest) -> Result<(), String> {
+        let Some(problem) = self.check(request) else {
+            return Ok(());
+        };
+        match self.action.unwrap_or_default() {
+            GuardrailAction::Refuse => Err(format!("Guardrail triggered: {}", problem)),
+            GuardrailAction::Warn => {
+                tracing::warn!("🛑 Guardrail warning: {}", problem);
+                append_system(
+                    request,
+                    format!(
+                        "Warning from the API gateway: {}. Stop repeating the same action; \
+                         try a different approach or ask the user for guidance.",
+                        problem
+                    ),
+                );
+                Ok(())
+            }
+        }
+    }
+
+    fn check(&self, request: &AnthropicRequest) -> Option<String> {
+        if let Some(max) = self.max_repeated_tool_calls.filter(|m| *m > 0) {
+            if let Some((name, count)) = trailing_repeats(request).filter(|(_, count)| *count >= max) {
+                return Some(format!("tool '{}' was called {} times in a row with identical input", name, count));
+            }
+        }
+        if let Some(max) = self.max_turns.filter(|m| *m > 0) {
+            let turns = request.messages.iter().filter(|m| m.role == "assistant").count();
+            if turns >= max {
+                return Some(format!("the conversation reached {} assistant turns (limit {})", turns, max));
+            }
+        }
+        None
+    }
+}
+
+/// The most recent tool call and how many identical calls end the history
+fn trailing_repeats(request: &AnthropicRequest) -> Option<(String, usize)> {
+    let mut calls = request
+        .messages
+        .iter()
+        .rev()
+        .filter(|m| m.role == "assistant")
+        .filter_map(|m| match &m.content {
+            MessageContent::Blocks(blocks) => Some(blocks),
+            MessageContent::Text(_) => None,
+        })
+        .flat_map(|blocks| blocks.iter().rev())
+        .filter_map(|b| match b {
+            ContentBlock::ToolUse { name, input, .. } => Some((name, input)),
+            _ => None,
+        });
+
+    let last = calls.next()?;
+    let count = 1 + calls.take_while(|call| *call == last).count();
+    Some((last.0.clone(), count))
+}
+
+pub(super) fn append_system(request: &mut AnthropicRequest, text: String) {
+    request.system = Some(match request.system.take() {
+        None => SystemPrompt::Text(text),
+        Some(SystemPrompt::Text(system)) => SystemPrompt::Text(format!("{}\n\n{}", system, text)),
+        Some(SystemPrompt::Blocks(mut blocks)) => {
+            blocks.push(SystemBlock {
+                r#type: "text".to_string(),
+                text,
+                cache_control: None,
+            });
+            SystemPrompt::Blocks(blocks)
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn looping_request(repeats: usize) -> AnthropicRequest {
+        let mut messages = Vec::new();
+        for i in 0..repeats {
+            messages.push(json!({ "role": "assistant", "content": [
+                { "type": "tool_use", "id": format!("toolu_{}", i), "name": "Bash", "input": { "command": "cargo test" } },
+            ]}));
+            messages.push(json!({ "role": "user", "content": [
+                { "type": "tool_result", "tool_use_id": format!("toolu_{}", i), "content": "1 failed" },
+            ]}));
+        }
+        serde_json::from_value(json!({ "model": "m", "max_tokens": 16, "messages": messages })).unwrap()
+    }
+
+    #[test]
+    fn test_repeated_tool_calls() {
+        let config: GuardrailsConfig = toml::from_str(
+            r#"
+max_repeated_tool_calls = 3
+
+[[keys]]
+api_key = "ci"
+action = "refuse"
+"#,
+        )
+        .unwrap();
+
+        let mut request = looping_request(2);
+        assert!(config.limits_for(None).apply(&mut request).is_ok());
+        assert!(request.system.is_none());
+
+        let mut request = looping_request(3);
+        assert!(config.limits_for(None).apply(&mut request).is_ok());
+        assert!(matches!(&request.system, Some(SystemPrompt::Text(t)) if t.contains("'Bash' was called 3 times")));
+
+        let mut request = looping_request(3);
+        assert!(config.limits_for(Some("ci")).apply(&mut request).is_err());
+    }
+}
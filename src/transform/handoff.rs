@@ -0,0 +1,343 @@
+use crate::models::{AnthropicRequest, ContentBlock, Message, MessageContent, SystemPrompt};
+use crate::providers::ProviderConfig;
+use crate::transform::thinking::ThinkingHistory;
+use std::collections::{HashMap, VecDeque};
+use tracing::debug;
+
+/// Most cache breakpoints Anthropic accepts in one request
+const MAX_CACHE_BREAKPOINTS: usize = 4;
+
+/// Rewrite provider-specific artifacts in the conversation history so a transcript
+/// started on one backend is accepted by another (e.g. after failover):
+/// - tool_use ids are made valid (`[A-Za-z0-9_-]+`) and kept paired with their tool_result
+/// - thinking blocks follow the provider's `thinking_history` policy
+/// - cache_control breakpoints are removed for backends without prompt caching and
+///   capped for Anthropic
+/// - parallel tool calls are split into one call per turn when the provider sets
+///   `parallel_tool_calls = false`
+/// - web search calls and results become text where Anthropic can't take them
+pub fn normalize(request: &mut AnthropicRequest, provider: Option<&ProviderConfig>) {
+    normalize_tool_ids(request);
+    flatten_web_search(request, provider.is_some_and(|p| p.provider_type == "anthropic"));
+
+    if provider.and_then(|p| p.parallel_tool_calls) == Some(false) {
+        serialize_tool_calls(request);
+    }
+
+    let thinking = provider.map_or(ThinkingHistory::Redact, |p| p.thinking_history());
+    thinking.apply(request);
+
+    let caches = provider.is_some_and(|p| p.provider_type == "anthropic");
+    normalize_cache_control(request, if caches { MAX_CACHE_BREAKPOINTS } else { 0 });
+}
+
+/// Whether an id is accepted by every backend
+fn is_valid_tool_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn sanitize_tool_id(id: &str) -> String {
+    id.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect()
+}
+
+/// Fix tool ids other backends produced (missing or with unsupported characters)
+fn normalize_tool_ids(request: &mut AnthropicRequest) {
+    let mut renamed: HashMap<String, String> = HashMap::new();
+    // Empty ids are paired with empty tool_result ids in order
+    let mut unnamed: VecDeque<String> = VecDeque::new();
+    let mut generated = 0;
+
+    for message in &mut request.messages {
+        let MessageContent::Blocks(blocks) = &mut message.content else {
+            continue;
+        };
+        for block in blocks {
+            match block {
+                ContentBlock::ToolUse { id, .. } if !is_valid_tool_id(id) => {
+                    let new_id = if id.is_empty() {
+                        generated += 1;
+                        let new_id = format!("toolu_ccm_{}", generated);
+                        unnamed.push_back(new_id.clone());
+                        new_id
+                    } else {
+                        let new_id = sanitize_tool_id(id);
+                        renamed.insert(id.clone(), new_id.clone());
+                        new_id
+                    };
+                    *id = new_id;
+                }
+                ContentBlock::ToolResult { tool_use_id, .. } if !is_valid_tool_id(tool_use_id) => {
+                    let new_id = if tool_use_id.is_empty() {
+                        unnamed.pop_front()
+                    } else {
+                        renamed.get(tool_use_id.as_str()).cloned()
+                    };
+                    *tool_use_id = new_id.unwrap_or_else(|| sanitize_tool_id(tool_use_id));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if !renamed.is_empty() || generated > 0 {
+        debug!("🔧 Normalized {} tool ids for handoff", renamed.len() + generated);
+    }
+}
+
+/// Split assistant turns with several tool_use blocks into one turn per call,
+/// each followed by a user turn with its tool_result
+fn serialize_tool_calls(request: &mut AnthropicRequest) {
+    let messages = std::mem::take(&mut request.messages);
+    let mut iter = messages.into_iter().peekable();
+
+    while let Some(message) = iter.next() {
+        let calls = match &message.content {
+            MessageContent::Blocks(blocks) if message.role == "assistant" => blocks
+                .iter()
+                .filter(|b| matches!(b, ContentBlock::ToolUse { .. }))
+                .count(),
+            _ => 0,
+        };
+        let results_follow = iter.peek().is_some_and(|next| next.role == "user");
+        if calls < 2 || !results_follow {
+            request.messages.push(message);
+            continue;
+        }
+
+        let (MessageContent::Blocks(blocks), Some(next)) = (message.content, iter.next()) else {
+            unreachable!("checked above");
+        };
+        let mut user_blocks = match next.content {
+            MessageContent::Blocks(blocks) => blocks,
+            MessageContent::Text(text) => vec![ContentBlock::Text { text }],
+        };
+
+        // Text/thinking stays with the first call
+        let (tool_uses, mut preamble): (Vec<_>, Vec<_>) = blocks
+            .into_iter()
+            .partition(|b| matches!(b, ContentBlock::ToolUse { .. }));
+        let last = tool_uses.len() - 1;
+        for (i, tool_use) in tool_uses.into_iter().enumerate() {
+            let ContentBlock::ToolUse { id, .. } = &tool_use else {
+                unreachable!("partitioned on tool_use");
+            };
+            let mut results: Vec<ContentBlock> = Vec::new();
+            if let Some(pos) = user_blocks
+                .iter()
+                .position(|b| matches!(b, ContentBlock::ToolResult { tool_use_id, .. } if tool_use_id == id))
+            {
+                results.push(user_blocks.remove(pos));
+            }
+            // Anything else in the user turn follows the last result
+            if i == last {
+                results.append(&mut user_blocks);
+            }
+
+            let mut assistant = std::mem::take(&mut preamble);
+            assistant.push(tool_use);
+            request.messages.push(Message {
+                role: "assistant".to_string(),
+                content: MessageContent::Blocks(assistant),
+            });
+            request.messages.push(Message {
+                role: "user".to_string(),
+                content: MessageContent::Blocks(results),
+            });
+        }
+    }
+}
+
+/// Replace web search calls and results with a text list of the sources found
+///
+/// Only Anthropic runs the `web_search` server tool, and it only takes results
+/// it produced itself (with `encrypted_content`). Other searches, e.g. Gemini's
+/// grounding, are kept as text so the model still sees what was found.
+fn flatten_web_search(request: &mut AnthropicRequest, native: bool) {
+    let foreign = |content: &serde_json::Value| {
+        content.as_array().is_some_and(|results| {
+            results
+                .iter()
+                .any(|r| r["encrypted_content"].as_str().unwrap_or_default().is_empty())
+        })
+    };
+    let flattened: HashMap<String, Option<String>> = request
+        .messages
+        .iter()
+        .filter_map(|message| match &message.content {
+            MessageContent::Blocks(blocks) => Some(blocks),
+            MessageContent::Text(_) => None,
+        })
+        .flatten()
+        .filter_map(|block| match block {
+            ContentBlock::WebSearchToolResult { tool_use_id, content } if !native || foreign(content) => {
+                Some(tool_use_id.clone())
+            }
+            _ => None,
+        })
+        .map(|id| (id, None))
+        .collect();
+    if flattened.is_empty() {
+        return;
+    }
+
+    let mut queries = flattened;
+    for message in &mut request.messages {
+        let MessageContent::Blocks(blocks) = &mut message.content else {
+            continue;
+        };
+        blocks.retain_mut(|block| match block {
+            ContentBlock::ServerToolUse { id, input, .. } if queries.contains_key(id.as_str()) => {
+                queries.insert(id.clone(), input["query"].as_str().map(str::to_string));
+                false
+            }
+            ContentBlock::WebSearchToolResult { tool_use_id, content } if queries.contains_key(tool_use_id.as_str()) => {
+                let query = queries.get(tool_use_id.as_str()).cloned().flatten();
+                let text = web_search_text(query.as_deref(), content);
+                *block = ContentBlock::Text { text };
+                true
+            }
+            _ => true,
+        });
+    }
+    debug!("Flattened {} web search results into text", queries.len());
+}
+
+/// `Web search for "...":` followed by one line per source (or the error)
+fn web_search_text(query: Option<&str>, content: &serde_json::Value) -> String {
+    let mut text = match query {
+        Some(query) => format!("Web search for \"{}\":", query),
+        None => "Web search:".to_string(),
+    };
+    match content.as_array() {
+        Some(results) => {
+            for result in results {
+                let url = result["url"].as_str().unwrap_or_default();
+                match result["title"].as_str().filter(|title| !title.is_empty()) {
+                    Some(title) => text.push_str(&format!("\n- {} ({})", title, url)),
+                    None => text.push_str(&format!("\n- {}", url)),
+                }
+            }
+            if results.is_empty() {
+                text.push_str(" no results");
+            }
+        }
+        None => text.push_str(&format!(
+            " failed ({})",
+            content["error_code"].as_str().unwrap_or("unknown error")
+        )),
+    }
+    text
+}
+
+/// Keep at most `max` cache breakpoints (the last ones, closest to the new turn)
+fn normalize_cache_control(request: &mut AnthropicRequest, max: usize) {
+    let Some(SystemPrompt::Blocks(blocks)) = &mut request.system else {
+        return;
+    };
+    let mut kept = 0;
+    for block in blocks.iter_mut().rev() {
+        if block.cache_control.is_some() {
+            if kept < max {
+                kept += 1;
+            } else {
+                block.cache_control = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tool_ids_stay_paired() {
+        let mut request: AnthropicRequest = serde_json::from_value(json!({
+            "model": "m",
+            "max_tokens": 16,
+            "messages": [
+                { "role": "assistant", "content": [
+                    { "type": "tool_use", "id": "call:1/a", "name": "Read", "input": {} },
+                    { "type": "tool_use", "id": "", "name": "Bash", "input": {} },
+                ]},
+                { "role": "user", "content": [
+                    { "type": "tool_result", "tool_use_id": "call:1/a", "content": "x" },
+                    { "type": "tool_result", "tool_use_id": "", "content": "y" },
+                ]},
+            ],
+        }))
+        .unwrap();
+        normalize(&mut request, None);
+
+        let ids = |message: &Message| -> Vec<String> {
+            let MessageContent::Blocks(blocks) = &message.content else {
+                panic!("expected blocks");
+            };
+            blocks
+                .iter()
+                .map(|b| match b {
+                    ContentBlock::ToolUse { id, .. } => id.clone(),
+                    ContentBlock::ToolResult { tool_use_id, .. } => tool_use_id.clone(),
+                    _ => panic!("unexpected block"),
+                })
+                .collect()
+        };
+        assert_eq!(ids(&request.messages[0]), vec!["call_1_a", "toolu_ccm_1"]);
+        assert_eq!(ids(&request.messages[1]), ids(&request.messages[0]));
+
+        serialize_tool_calls(&mut request);
+        let turns: Vec<_> = request.messages.iter().map(|m| (m.role.as_str(), ids(m))).collect();
+        assert_eq!(
+            turns,
+            vec![
+                ("assistant", vec!["call_1_a".to_string()]),
+                ("user", vec!["call_1_a".to_string()]),
+                ("assistant", vec!["toolu_ccm_1".to_string()]),
+                ("user", vec!["toolu_ccm_1".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_web_search_flattened() {
+        let request = |encrypted: &str| -> AnthropicRequest {
+            serde_json::from_value(json!({
+                "model": "m",
+                "max_tokens": 16,
+                "messages": [
+                    { "role": "user", "content": "News?" },
+                    { "role": "assistant", "content": [
+                        { "type": "server_tool_use", "id": "srvtoolu_1", "name": "web_search", "input": { "query": "rust news" } },
+                        { "type": "web_search_tool_result", "tool_use_id": "srvtoolu_1", "content": [
+                            { "type": "web_search_result", "url": "https://blog.rust-lang.org", "title": "Rust Blog", "encrypted_content": encrypted },
+                        ] },
+                        { "type": "text", "text": "Rust 2.0 is out" },
+                    ] },
+                ],
+            }))
+            .unwrap()
+        };
+        let blocks = |request: &AnthropicRequest| match &request.messages[1].content {
+            MessageContent::Blocks(blocks) => blocks.clone(),
+            MessageContent::Text(_) => panic!("expected blocks"),
+        };
+
+        // Anthropic keeps its own results
+        let mut native = request("Eq...");
+        flatten_web_search(&mut native, true);
+        assert_eq!(blocks(&native).len(), 3);
+
+        // Results from another backend, or for another backend, become text
+        for (encrypted, native) in [("", true), ("Eq...", false)] {
+            let mut flattened = request(encrypted);
+            flatten_web_search(&mut flattened, native);
+            let blocks = blocks(&flattened);
+            assert_eq!(blocks.len(), 2);
+            assert!(matches!(&blocks[0], ContentBlock::Text { text }
+                if text == "Web search for \"rust news\":\n- Rust Blog (https://blog.rust-lang.org)"));
+        }
+    }
+}
@@ -0,0 +1,281 @@
+//! Re-encoding of images a provider can't take as sent
+//!
+//! Clients paste whatever the OS produced (WebP screenshots, GIFs, BMPs,
+//! huge retina captures). Providers that only accept PNG/JPEG, or cap the
+//! image size, get a converted and downscaled copy instead of an error.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::{DynamicImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use tracing::debug;
+
+use super::losses::{self, LossKind};
+use crate::models::{
+    AnthropicRequest, ContentBlock, ImageSource, MessageContent, ToolResultBlock, ToolResultContent,
+};
+
+/// Image formats and size a provider accepts
+///
+/// ```toml
+/// [providers.images]
+/// formats = ["png", "jpeg"]   # others are converted (default: png, jpeg, gif, webp)
+/// max_dimension = 2048        # longer side in pixels; larger images are downscaled
+/// max_bytes = 5242880         # larger images are downscaled until they fit
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ImageSettings {
+    #[serde(default = "default_formats")]
+    pub formats: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_dimension: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<usize>,
+}
+
+fn default_formats() -> Vec<String> {
+    ["png", "jpeg", "gif", "webp"].map(String::from).to_vec()
+}
+
+/// Tries at shrinking an image under `max_bytes`
+const MAX_SHRINK_STEPS: usize = 4;
+
+const JPEG_QUALITY: u8 = 85;
+
+impl ImageSettings {
+    /// Convert and downscale the request's base64 images as needed
+    pub fn apply(&self, request: &mut AnthropicRequest) {
+        for message in &mut request.messages {
+            let MessageContent::Blocks(blocks) = &mut message.content else {
+                continue;
+            };
+            for block in blocks {
+                match block {
+                    ContentBlock::Image { source } => self.fit(source),
+                    ContentBlock::ToolResult {
+                        content: ToolResultContent::Blocks(blocks),
+                        ..
+                    } => {
+                        for block in blocks {
+                            if let ToolResultBlock::Image { source } = block {
+                                self.fit(source);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn accepts(&self, format: &str) -> bool {
+        self.formats.iter().any(|f| normalize(f) == format)
+    }
+
+    /// Re-encode one image if its format or size is off limits
+    fn fit(&self, source: &mut ImageSource) {
+        let (Some(media_type), Some(data)) = (&source.media_type, &source.data) else {
+            return;
+        };
+        let format = normalize(media_type);
+        let too_large = self.max_bytes.is_some_and(|max| data.len() / 4 * 3 > max);
+        if self.accepts(&format) && self.max_dimension.is_none() && !too_large {
+            return;
+        }
+
+        let decoded = STANDARD
+            .decode(data)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| image::load_from_memory(&bytes).map_err(|e| e.to_string()));
+        let image = match decoded {
+            Ok(image) => image,
+            Err(e) => {
+                if !self.accepts(&format) {
+                    losses::record(
+                        LossKind::ImageConverted,
+                        format!(
+                            "{} image can't be converted ({}), sent as is",
+                            media_type, e
+                        ),
+                    );
+                }
+                return;
+            }
+        };
+        let oversized = self
+            .max_dimension
+            .is_some_and(|max| image.width().max(image.height()) > max);
+        if self.accepts(&format) && !oversized && !too_large {
+            return;
+        }
+
+        let Some(target) = self.target(&format, &image) else {
+            losses::record(
+                LossKind::ImageConverted,
+                format!(
+                    "{} image: the provider accepts neither PNG nor JPEG, sent as is",
+                    media_type
+                ),
+            );
+            return;
+        };
+        let mut image = match self.max_dimension {
+            Some(max) if oversized => image.resize(max, max, image::imageops::FilterType::Triangle),
+            _ => image,
+        };
+        let mut encoded = encode(&image, target);
+        for _ in 0..MAX_SHRINK_STEPS {
+            match (&encoded, self.max_bytes) {
+                (Some(bytes), Some(max)) if bytes.len() > max => {
+                    image = image.resize(
+                        image.width() / 2,
+                        image.height() / 2,
+                        image::imageops::FilterType::Triangle,
+                    );
+                    encoded = encode(&image, target);
+                }
+                _ => break,
+            }
+        }
+        let Some(bytes) = encoded else {
+            return;
+        };
+
+        let converted = match target {
+            ImageFormat::Png => "image/png",
+            _ => "image/jpeg",
+        }
+        .to_string();
+        let detail = format!(
+            "{} image sent as {} ({}x{}, {} bytes)",
+            media_type,
+            converted,
+            image.width(),
+            image.height(),
+            bytes.len()
+        );
+        debug!("🖼️  {}", detail);
+        losses::record(LossKind::ImageConverted, detail);
+        source.media_type = Some(converted);
+        source.data = Some(STANDARD.encode(bytes));
+    }
+
+    /// PNG for images with transparency or already PNG, JPEG otherwise,
+    /// whichever the provider accepts
+    fn target(&self, format: &str, image: &DynamicImage) -> Option<ImageFormat> {
+        let lossless = format == "png" || image.color().has_alpha();
+        let preferred = if lossless {
+            ["png", "jpeg"]
+        } else {
+            ["jpeg", "png"]
+        };
+        preferred.into_iter().find(|f| self.accepts(f)).map(|f| {
+            if f == "png" {
+                ImageFormat::Png
+            } else {
+                ImageFormat::Jpeg
+            }
+        })
+    }
+}
+
+/// `image/jpg`, `JPG`, `jpeg` -> `jpeg`
+fn normalize(format: &str) -> String {
+    let format = format.trim().to_ascii_lowercase();
+    let format = format.strip_prefix("image/").unwrap_or(&format);
+    match format {
+        "jpg" => "jpeg".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> Option<Vec<u8>> {
+    let mut out = Cursor::new(Vec::new());
+    let result =
+        match format {
+            ImageFormat::Jpeg => image.to_rgb8().write_with_encoder(
+                image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY),
+            ),
+            _ => image.write_to(&mut out, format),
+        };
+    match result {
+        Ok(()) => Some(out.into_inner()),
+        Err(e) => {
+            debug!("Failed to encode image as {:?}: {}", format, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn image_request(
+        media_type: &str,
+        image: &DynamicImage,
+        format: ImageFormat,
+    ) -> AnthropicRequest {
+        let mut bytes = Cursor::new(Vec::new());
+        image.write_to(&mut bytes, format).unwrap();
+        serde_json::from_value(serde_json::json!({
+            "model": "m",
+            "max_tokens": 16,
+            "messages": [{ "role": "user", "content": [
+                { "type": "image", "source": { "type": "base64", "media_type": media_type, "data": STANDARD.encode(bytes.into_inner()) } },
+            ] }],
+        }))
+        .unwrap()
+    }
+
+    fn source(request: &AnthropicRequest) -> &ImageSource {
+        let MessageContent::Blocks(blocks) = &request.messages[0].content else {
+            panic!()
+        };
+        let ContentBlock::Image { source } = &blocks[0] else {
+            panic!()
+        };
+        source
+    }
+
+    #[test]
+    fn test_convert_and_downscale() {
+        let photo = DynamicImage::ImageRgb8(RgbImage::from_pixel(400, 200, Rgb([200, 30, 30])));
+        let settings: ImageSettings =
+            toml::from_str("formats = [\"png\", \"jpg\"]\nmax_dimension = 100").unwrap();
+
+        // WebP becomes JPEG, downscaled to fit
+        let mut request = image_request("image/webp", &photo, ImageFormat::WebP);
+        settings.apply(&mut request);
+        let converted = source(&request);
+        assert_eq!(converted.media_type.as_deref(), Some("image/jpeg"));
+        let decoded =
+            image::load_from_memory(&STANDARD.decode(converted.data.as_ref().unwrap()).unwrap())
+                .unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (100, 50));
+
+        // Accepted and small enough: untouched
+        let small = DynamicImage::ImageRgb8(RgbImage::from_pixel(50, 50, Rgb([0, 0, 0])));
+        let mut request = image_request("image/png", &small, ImageFormat::Png);
+        let before = source(&request).data.clone();
+        settings.apply(&mut request);
+        assert_eq!(source(&request).data, before);
+
+        // Not decodable here: sent as is
+        let mut request = image_request("image/heic", &small, ImageFormat::Png);
+        source_mut(&mut request).data = Some(STANDARD.encode(b"not an image"));
+        settings.apply(&mut request);
+        assert_eq!(source(&request).media_type.as_deref(), Some("image/heic"));
+    }
+
+    fn source_mut(request: &mut AnthropicRequest) -> &mut ImageSource {
+        let MessageContent::Blocks(blocks) = &mut request.messages[0].content else {
+            panic!()
+        };
+        let ContentBlock::Image { source } = &mut blocks[0] else {
+            panic!()
+        };
+        source
+    }
+}
@@ -0,0 +1,123 @@
+//! Warnings about content a transformation dropped or degraded
+//!
+//! Transformations and provider translations call [`record`] when the upstream
+//! request can't carry something the client sent. The server collects the
+//! warnings of a request with [`collect`] and attaches them to its log entry,
+//! so behavior differences between providers don't go unnoticed.
+
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::future::Future;
+use tracing::debug;
+
+/// Response header listing the kinds of loss (when enabled in `[server]`)
+pub const HEADER: &str = "x-ccm-transform-warnings";
+
+tokio::task_local! {
+    static LOSSES: RefCell<Vec<Loss>>;
+}
+
+/// What kind of content was lost
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LossKind {
+    /// Tools removed by the tool policy
+    ToolsFiltered,
+    /// Thinking blocks removed from history
+    ThinkingDropped,
+    /// Thinking block signatures removed
+    ThinkingRedacted,
+    /// Thinking blocks sent as plain text
+    ThinkingFlattened,
+    /// Tool results shortened
+    ToolResultTruncated,
+    /// Content blocks the provider can't represent
+    ContentDropped,
+    /// Request parameters the provider doesn't support
+    FieldDropped,
+    /// Images converted or downscaled for the provider
+    ImageConverted,
+}
+
+impl LossKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LossKind::ToolsFiltered => "tools_filtered",
+            LossKind::ThinkingDropped => "thinking_dropped",
+            LossKind::ThinkingRedacted => "thinking_redacted",
+            LossKind::ThinkingFlattened => "thinking_flattened",
+            LossKind::ToolResultTruncated => "tool_result_truncated",
+            LossKind::ContentDropped => "content_dropped",
+            LossKind::FieldDropped => "field_dropped",
+            LossKind::ImageConverted => "image_converted",
+        }
+    }
+}
+
+/// One lossy transformation of a request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Loss {
+    pub kind: LossKind,
+    pub detail: String,
+}
+
+/// Note a lossy transformation of the current request
+///
+/// Does nothing outside of [`collect`] (e.g. in `ccm transform test`).
+pub fn record(kind: LossKind, detail: impl Into<String>) {
+    let loss = Loss {
+        kind,
+        detail: detail.into(),
+    };
+    debug!("⚠️  Lossy transform ({}): {}", kind.as_str(), loss.detail);
+    let _ = LOSSES.try_with(|losses| losses.borrow_mut().push(loss));
+}
+
+/// Forget the warnings recorded so far (before trying the next provider)
+pub fn reset() {
+    let _ = LOSSES.try_with(|losses| losses.borrow_mut().clear());
+}
+
+/// Run a request, returning the warnings recorded while it ran
+pub async fn collect<F: Future>(future: F) -> (F::Output, Vec<Loss>) {
+    LOSSES
+        .scope(RefCell::new(Vec::new()), async {
+            let output = future.await;
+            (output, LOSSES.with(|losses| losses.take()))
+        })
+        .await
+}
+
+/// Header value listing the kinds of loss, e.g. `thinking_flattened, tools_filtered`
+pub fn summary(losses: &[Loss]) -> String {
+    let mut kinds: Vec<&str> = Vec::new();
+    for loss in losses {
+        if !kinds.contains(&loss.kind.as_str()) {
+            kinds.push(loss.kind.as_str());
+        }
+    }
+    kinds.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_collect() {
+        // Outside of a request, recording is a no-op
+        record(LossKind::FieldDropped, "top_k");
+
+        let ((), losses) = collect(async {
+            record(LossKind::ContentDropped, "image on the first attempt");
+            reset();
+            tokio::task::yield_now().await;
+            record(LossKind::ThinkingFlattened, "2 thinking blocks");
+            record(LossKind::ToolsFiltered, "kept 3/5 tools");
+            record(LossKind::ThinkingFlattened, "1 thinking block");
+        })
+        .await;
+        assert_eq!(losses.len(), 3);
+        assert_eq!(summary(&losses), "thinking_flattened, tools_filtered");
+    }
+}
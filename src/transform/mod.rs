@@ -0,0 +1,96 @@
+//! Request/response transformations applied by the server before dispatching
+//! to a provider (and after receiving its response).
+//!
+//! Provider modules only translate between wire formats; anything that is
+//! driven by user configuration (filtering, truncation, ...) lives here.
+
+pub mod fences;
+pub mod fim;
+pub mod guardrails;
+pub mod handoff;
+pub mod images;
+pub mod losses;
+pub mod sampling;
+pub mod strict;
+pub mod templates;
+pub mod thinking;
+pub mod tools;
+pub mod truncation;
+pub mod unsupported;
+pub mod user_id;
+pub mod verbosity;
+
+use crate::cli::{AppConfig, ModelConfig, ModelMapping};
+use crate::models::AnthropicRequest;
+use crate::providers::AnthropicProvider;
+use strict::OptionalFields;
+use tools::{ToolPolicy, ToolRenames};
+
+/// What to undo in the response to a prepared request
+#[derive(Debug, Clone, Default)]
+pub struct Prepared {
+    /// Tool names to restore
+    pub renames: ToolRenames,
+    /// Nulls to remove from the tool input of strict tools
+    pub optional: OptionalFields,
+}
+
+/// Apply the configured transformations for one mapping, in dispatch order
+///
+/// Returns what to undo in the response, or why the provider can't take the
+/// request.
+pub fn prepare(
+    config: &AppConfig,
+    model_config: Option<&ModelConfig>,
+    mapping: &ModelMapping,
+    provider: &dyn AnthropicProvider,
+    request: &mut AnthropicRequest,
+) -> Result<Prepared, String> {
+    // Tool filtering/renaming for this provider
+    let renames = tool_policy_for(config, mapping)
+        .map(|policy| policy.apply(request))
+        .unwrap_or_default();
+
+    // Make history from other backends acceptable to this one
+    let provider_config = config.providers.iter().find(|p| p.name == mapping.provider);
+    handoff::normalize(request, provider_config);
+    let optional = match provider_config {
+        Some(p) if p.strict_tools => strict::apply(request),
+        _ => OptionalFields::default(),
+    };
+    provider_config
+        .and_then(|p| p.unsupported_content.clone())
+        .unwrap_or_default()
+        .apply(request, provider.supports_documents())?;
+    if let Some(images) = provider_config.and_then(|p| p.images.as_ref()) {
+        images.apply(request);
+    }
+
+    // Truncate oversized tool results
+    config.tool_result_truncation.apply(request);
+
+    // Alias sampling defaults and overrides
+    if let Some(sampling) = model_config.and_then(|m| m.sampling.as_ref()) {
+        sampling.apply(request);
+    }
+
+    // Alias output defaults, verbosity as the provider understands it
+    verbosity::apply(
+        request,
+        model_config.and_then(|m| m.verbosity),
+        model_config.and_then(|m| m.max_tokens),
+        provider.supports_verbosity(),
+    );
+    Ok(Prepared { renames, optional })
+}
+
+/// Resolve the tool policy for a mapping (mapping-level overrides provider-level)
+fn tool_policy_for<'a>(config: &'a AppConfig, mapping: &'a ModelMapping) -> Option<&'a ToolPolicy> {
+    mapping.tool_policy.as_ref().or_else(|| {
+        config
+            .providers
+            .iter()
+            .find(|p| p.name == mapping.provider)
+            .and_then(|p| p.tool_policy.as_ref())
+    })
+}
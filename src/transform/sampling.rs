@@ -0,0 +1,123 @@
+//! Per-model sampling parameters
+//!
+//! Some models reject parameters clients send by habit: reasoning models that
+//! only take `temperature = 1`, APIs without `top_k`, or backends that refuse
+//! `temperature` and `top_p` together. An alias can fill in defaults, force
+//! values and strip parameters before the request goes out.
+
+use serde::{Deserialize, Serialize};
+
+use super::losses::{self, LossKind};
+use crate::models::AnthropicRequest;
+
+/// Sampling parameter values
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct SamplingValues {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+}
+
+/// Sampling defaults and overrides for an alias
+///
+/// ```toml
+/// [models.sampling]
+/// defaults = { temperature = 0.3 }   # when the client sends none
+/// force = { temperature = 1.0 }      # whatever the client sends
+/// strip = ["top_p", "top_k"]         # never sent
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Sampling {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub defaults: Option<SamplingValues>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub force: Option<SamplingValues>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub strip: Vec<String>,
+}
+
+impl Sampling {
+    /// Fill in defaults, then apply forced values, then strip
+    pub fn apply(&self, request: &mut AnthropicRequest) {
+        let defaults = self.defaults.unwrap_or_default();
+        request.temperature = request.temperature.or(defaults.temperature);
+        request.top_p = request.top_p.or(defaults.top_p);
+        request.top_k = request.top_k.or(defaults.top_k);
+
+        if let Some(force) = &self.force {
+            overwrite("temperature", &mut request.temperature, force.temperature);
+            overwrite("top_p", &mut request.top_p, force.top_p);
+            overwrite("top_k", &mut request.top_k, force.top_k);
+        }
+
+        for name in &self.strip {
+            let stripped = match name.as_str() {
+                "temperature" => request.temperature.take().map(|v| v.to_string()),
+                "top_p" => request.top_p.take().map(|v| v.to_string()),
+                "top_k" => request.top_k.take().map(|v| v.to_string()),
+                other => {
+                    tracing::warn!("Unknown sampling parameter in strip: {}", other);
+                    None
+                }
+            };
+            if let Some(value) = stripped {
+                losses::record(
+                    LossKind::FieldDropped,
+                    format!("{} = {} (stripped for this model)", name, value),
+                );
+            }
+        }
+    }
+}
+
+/// Replace a client value with the forced one, noting the change
+fn overwrite<T: Copy + PartialEq + std::fmt::Display>(
+    name: &str,
+    value: &mut Option<T>,
+    forced: Option<T>,
+) {
+    let Some(forced) = forced else {
+        return;
+    };
+    if let Some(sent) = value.filter(|sent| *sent != forced) {
+        losses::record(
+            LossKind::FieldDropped,
+            format!("{} = {} (forced to {} for this model)", name, sent, forced),
+        );
+    }
+    *value = Some(forced);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampling_overrides() {
+        let sampling: Sampling = toml::from_str(
+            "defaults = { temperature = 0.3, top_k = 40 }\nforce = { top_p = 1.0 }\nstrip = [\"top_k\"]",
+        )
+        .unwrap();
+        let mut request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "m", "max_tokens": 16, "top_p": 0.9,
+            "messages": [{ "role": "user", "content": "hi" }],
+        }))
+        .unwrap();
+        sampling.apply(&mut request);
+        assert_eq!(request.temperature, Some(0.3));
+        assert_eq!(request.top_p, Some(1.0));
+        assert_eq!(request.top_k, None);
+
+        // Client values win over defaults
+        let mut request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "m", "max_tokens": 16, "temperature": 0.8,
+            "messages": [{ "role": "user", "content": "hi" }],
+        }))
+        .unwrap();
+        sampling.apply(&mut request);
+        assert_eq!(request.temperature, Some(0.8));
+    }
+}
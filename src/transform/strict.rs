@@ -0,0 +1,381 @@
+use crate::models::{AnthropicRequest, ContentBlock};
+use crate::providers::streaming::{parse_sse_events, LineBuffer};
+use crate::providers::ProviderResponse;
+use bytes::Bytes;
+use futures::stream::Stream;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use tracing::debug;
+
+/// Optional properties strict mode made nullable, per tool
+///
+/// The model fills them with `null` when it means to leave them out, which
+/// clients validating against the original schema reject, so those nulls are
+/// removed from the tool input again.
+#[derive(Debug, Clone, Default)]
+pub struct OptionalFields(HashMap<String, Fields>);
+
+/// Nullable properties of one object schema, and of the objects nested in it
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Fields {
+    nullable: HashSet<String>,
+    nested: HashMap<String, Fields>,
+}
+
+impl Fields {
+    fn strip(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                map.retain(|name, v| !(v.is_null() && self.nullable.contains(name)));
+                for (name, v) in map.iter_mut() {
+                    if let Some(nested) = self.nested.get(name) {
+                        nested.strip(v);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.strip(item)),
+            _ => {}
+        }
+    }
+}
+
+impl OptionalFields {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Remove the nulls of omitted optional arguments from a tool call's input
+    pub fn strip(&self, tool: &str, input: &mut Value) {
+        if let Some(fields) = self.0.get(tool) {
+            fields.strip(input);
+        }
+    }
+
+    /// Remove them from the tool calls of a non-streaming response
+    pub fn strip_response(&self, response: &mut ProviderResponse) {
+        for block in &mut response.content {
+            if let ContentBlock::ToolUse { name, input, .. } = block {
+                self.strip(name, input);
+            }
+        }
+    }
+
+    /// Remove them from a stream whose tool input arrives as one
+    /// `input_json_delta` per block (see `coalesce_tool_input`)
+    pub fn strip_stream<S, E>(self, stream: S) -> impl Stream<Item = Result<Bytes, E>> + Send
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + Unpin + 'static,
+        E: Send + 'static,
+    {
+        use futures::StreamExt;
+
+        // Tool name of each open tool_use block
+        let tools: HashMap<u64, String> = HashMap::new();
+        futures::stream::unfold(
+            (stream, LineBuffer::default(), tools, self, false),
+            |(mut stream, mut buffer, mut tools, fields, done)| async move {
+                if done {
+                    return None;
+                }
+                loop {
+                    match stream.next().await {
+                        Some(Ok(bytes)) => {
+                            buffer.push(&bytes);
+                            let mut output = String::new();
+                            while let Some(event) = buffer.event() {
+                                output.push_str(&fields.strip_event(event, &mut tools));
+                            }
+                            if output.is_empty() {
+                                continue;
+                            }
+                            return Some((Ok(Bytes::from(output)), (stream, buffer, tools, fields, false)));
+                        }
+                        Some(Err(e)) => return Some((Err(e), (stream, buffer, tools, fields, false))),
+                        None => {
+                            if buffer.is_empty() {
+                                return None;
+                            }
+                            return Some((Ok(Bytes::from(buffer.rest())), (stream, buffer, tools, fields, true)));
+                        }
+                    }
+                }
+            },
+        )
+    }
+
+    fn strip_event(&self, event: String, tools: &mut HashMap<u64, String>) -> String {
+        let Some(sse) = parse_sse_events(&event).into_iter().next() else {
+            return event;
+        };
+        let Ok(mut data) = serde_json::from_str::<Value>(&sse.data) else {
+            return event;
+        };
+        let index = data["index"].as_u64().unwrap_or(0);
+        match data["type"].as_str() {
+            Some("content_block_start") if data["content_block"]["type"] == "tool_use" => {
+                if let Some(name) = data["content_block"]["name"].as_str() {
+                    tools.insert(index, name.to_string());
+                }
+                event
+            }
+            Some("content_block_delta") if data["delta"]["type"] == "input_json_delta" => {
+                let (Some(tool), Some(Ok(mut input))) = (
+                    tools.get(&index),
+                    data["delta"]["partial_json"].as_str().map(serde_json::from_str::<Value>),
+                ) else {
+                    return event;
+                };
+                self.strip(tool, &mut input);
+                data["delta"]["partial_json"] = input.to_string().into();
+                crate::providers::streaming::SseEvent { event: sse.event, data: data.to_string() }.to_sse_string()
+            }
+            Some("content_block_stop") => {
+                tools.remove(&index);
+                event
+            }
+            _ => event,
+        }
+    }
+}
+
+/// Mark tools `strict` and rewrite their schemas to meet OpenAI strict-mode rules
+///
+/// Tools whose schema can't be expressed in strict mode (free-form objects) are
+/// left as they are. Returns the optional properties that were made nullable.
+pub fn apply(request: &mut AnthropicRequest) -> OptionalFields {
+    let mut optional = OptionalFields::default();
+    let Some(tools) = &mut request.tools else {
+        return optional;
+    };
+    for tool in tools.iter_mut().filter(|t| t.r#type.is_none()) {
+        let Some(schema) = &tool.input_schema else {
+            continue;
+        };
+        let mut strict_schema = schema.clone();
+        let mut fields = Fields::default();
+        if make_strict(&mut strict_schema, &mut fields) {
+            tool.input_schema = Some(strict_schema);
+            tool.strict = Some(true);
+            if let Some(name) = tool.name.clone().filter(|_| fields != Fields::default()) {
+                optional.0.insert(name, fields);
+            }
+        } else {
+            debug!("Tool {:?} can't use strict mode, sending it as is", tool.name);
+        }
+    }
+    optional
+}
+
+/// Rewrite a schema in place; returns false if it can't be made strict
+///
+/// Strict mode requires `additionalProperties: false` and every property listed
+/// in `required` on each object; optional properties become nullable instead
+/// and are noted in `fields`.
+fn make_strict(schema: &mut Value, fields: &mut Fields) -> bool {
+    let Some(obj) = schema.as_object_mut() else {
+        return true;
+    };
+
+    // Keywords strict mode rejects
+    for keyword in ["default", "minLength", "maxLength", "minItems", "maxItems", "format"] {
+        obj.remove(keyword);
+    }
+
+    let is_object = obj.get("type").is_some_and(|t| t == "object") || obj.contains_key("properties");
+    if is_object {
+        match obj.get("additionalProperties") {
+            None | Some(Value::Bool(false)) => {}
+            // Free-form maps have no strict equivalent
+            Some(_) if !obj.contains_key("properties") => return false,
+            Some(_) => {}
+        }
+        obj.insert("additionalProperties".to_string(), Value::Bool(false));
+
+        let required: Vec<String> = obj
+            .get("required")
+            .and_then(|r| r.as_array())
+            .map(|r| r.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+            .unwrap_or_default();
+        let Some(properties) = obj
+            .entry("properties")
+            .or_insert_with(|| Value::Object(Map::new()))
+            .as_object_mut()
+        else {
+            return false;
+        };
+        for (name, property) in properties.iter_mut() {
+            let mut nested = Fields::default();
+            if !make_strict(property, &mut nested) {
+                return false;
+            }
+            if nested != Fields::default() {
+                fields.nested.insert(name.clone(), nested);
+            }
+            if !required.contains(name) {
+                make_nullable(property);
+                fields.nullable.insert(name.clone());
+            }
+        }
+        let all: Vec<Value> = properties.keys().cloned().map(Value::String).collect();
+        obj.insert("required".to_string(), Value::Array(all));
+    }
+
+    // Array items and the variants of a union describe the same value
+    if let Some(items) = obj.get_mut("items") {
+        if !make_strict(items, fields) {
+            return false;
+        }
+    }
+    for keyword in ["anyOf", "allOf", "oneOf"] {
+        if let Some(Value::Array(variants)) = obj.get_mut(keyword) {
+            if !variants.iter_mut().all(|variant| make_strict(variant, fields)) {
+                return false;
+            }
+        }
+    }
+    // Referenced definitions aren't followed when stripping nulls
+    for keyword in ["$defs", "definitions"] {
+        if let Some(Value::Object(defs)) = obj.get_mut(keyword) {
+            if !defs.values_mut().all(|def| make_strict(def, &mut Fields::default())) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Allow null for an optional property
+fn make_nullable(property: &mut Value) {
+    let Some(obj) = property.as_object_mut() else {
+        return;
+    };
+    match obj.get_mut("type") {
+        Some(Value::String(t)) if t != "null" => {
+            let t = std::mem::take(t);
+            obj.insert("type".to_string(), serde_json::json!([t, "null"]));
+        }
+        Some(Value::Array(types)) if !types.iter().any(|t| t == "null") => {
+            types.push(Value::String("null".to_string()));
+        }
+        Some(_) => {}
+        None => {
+            // anyOf/$ref: add a null branch
+            let schema = std::mem::take(obj);
+            obj.insert(
+                "anyOf".to_string(),
+                serde_json::json!([Value::Object(schema), { "type": "null" }]),
+            );
+        }
+    }
+    if let Some(Value::Array(values)) = obj.get_mut("enum") {
+        if !values.contains(&Value::Null) {
+            values.push(Value::Null);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_make_strict() {
+        let mut schema = json!({
+            "type": "object",
+            "properties": {
+                "file_path": { "type": "string" },
+                "limit": { "type": "number", "default": 100 },
+                "options": {
+                    "type": "object",
+                    "properties": { "mode": { "type": "string", "enum": ["a", "b"] } },
+                },
+            },
+            "required": ["file_path"],
+        });
+        assert!(make_strict(&mut schema, &mut Fields::default()));
+
+        assert_eq!(schema["additionalProperties"], false);
+        assert_eq!(schema["required"], json!(["file_path", "limit", "options"]));
+        assert_eq!(schema["properties"]["file_path"]["type"], "string");
+        assert_eq!(schema["properties"]["limit"], json!({ "type": ["number", "null"] }));
+        assert_eq!(schema["properties"]["options"]["type"], json!(["object", "null"]));
+        assert_eq!(schema["properties"]["options"]["required"], json!(["mode"]));
+        assert_eq!(
+            schema["properties"]["options"]["properties"]["mode"]["enum"],
+            json!(["a", "b", null])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_omitted_optional_arguments_round_trip() {
+        use futures::StreamExt;
+
+        let mut request: AnthropicRequest = serde_json::from_value(json!({
+            "model": "m", "max_tokens": 10,
+            "messages": [{ "role": "user", "content": "hi" }],
+            "tools": [{ "name": "Read", "input_schema": {
+                "type": "object",
+                "properties": {
+                    "file_path": { "type": "string" },
+                    "limit": { "type": "number" },
+                    "ranges": { "type": "array", "items": {
+                        "type": "object",
+                        "properties": { "start": { "type": "number" }, "end": { "type": "number" } },
+                        "required": ["start"],
+                    } },
+                },
+                "required": ["file_path"],
+            } }],
+        }))
+        .unwrap();
+        let optional = apply(&mut request);
+        assert_eq!(request.tools.as_ref().unwrap()[0].strict, Some(true));
+
+        // As the model answers under strict mode
+        let sent = json!({ "file_path": "a.rs", "limit": null, "ranges": [{ "start": 1, "end": null }] });
+        let expected = json!({ "file_path": "a.rs", "ranges": [{ "start": 1 }] });
+        let mut response: ProviderResponse = serde_json::from_value(json!({
+            "id": "msg_1", "type": "message", "role": "assistant", "model": "m",
+            "content": [{ "type": "tool_use", "id": "toolu_1", "name": "Read", "input": sent }],
+            "stop_reason": "tool_use", "stop_sequence": null,
+            "usage": { "input_tokens": 1, "output_tokens": 1 },
+        }))
+        .unwrap();
+        optional.strip_response(&mut response);
+        let ContentBlock::ToolUse { input, .. } = &response.content[0] else { panic!() };
+        assert_eq!(input, &expected);
+
+        let events = [
+            json!({ "type": "content_block_start", "index": 0,
+                "content_block": { "type": "tool_use", "id": "toolu_1", "name": "Read", "input": {} } }),
+            json!({ "type": "content_block_delta", "index": 0,
+                "delta": { "type": "input_json_delta", "partial_json": sent.to_string() } }),
+            json!({ "type": "content_block_stop", "index": 0 }),
+        ];
+        let chunks: Vec<Result<Bytes, ()>> = events
+            .iter()
+            .map(|e| Ok(Bytes::from(format!("event: {}\ndata: {}\n\n", e["type"].as_str().unwrap(), e))))
+            .collect();
+        let output: Vec<_> = optional.strip_stream(futures::stream::iter(chunks)).collect().await;
+        let output: String = output.into_iter().map(|c| String::from_utf8(c.unwrap().to_vec()).unwrap()).collect();
+        let delta = parse_sse_events(&output)
+            .into_iter()
+            .map(|e| serde_json::from_str::<Value>(&e.data).unwrap())
+            .find(|e| e["type"] == "content_block_delta")
+            .unwrap();
+        let input: Value = serde_json::from_str(delta["delta"]["partial_json"].as_str().unwrap()).unwrap();
+        assert_eq!(input, expected);
+    }
+
+    #[test]
+    fn test_free_form_object_is_not_strict() {
+        let mut schema = json!({
+            "type": "object",
+            "properties": {
+                "env": { "type": "object", "additionalProperties": { "type": "string" } },
+            },
+        });
+        assert!(!make_strict(&mut schema, &mut Fields::default()));
+    }
+}
@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Named prompt served at `/v1/templates/{name}/messages`
+///
+/// `{{variable}}` placeholders in `system` and `prompt` are filled from the
+/// request's `variables`, falling back to `defaults`.
+///
+/// Example:
+/// ```toml
+/// [[templates]]
+/// name = "release-notes"
+/// model = "default"
+/// system = "You write release notes for {{product}}."
+/// prompt = "Summarize these commits:\n{{commits}}"
+/// defaults = { product = "claude-code-mux" }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub name: String,
+    /// Model to request (default: the request's `model`, else the router default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    pub prompt: String,
+    #[serde(default = "default_max_tokens")]
+    pub max_tokens: u32,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub defaults: BTreeMap<String, String>,
+}
+
+fn default_max_tokens() -> u32 {
+    4096
+}
+
+/// Body of a template request
+#[derive(Debug, Default, Deserialize)]
+pub struct TemplateRequest {
+    /// Values for the template's placeholders (non-strings are inserted as JSON)
+    #[serde(default)]
+    pub variables: BTreeMap<String, serde_json::Value>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub stream: Option<bool>,
+    /// Follow-up turns appended after the rendered prompt
+    #[serde(default)]
+    pub messages: Vec<serde_json::Value>,
+}
+
+impl PromptTemplate {
+    /// Placeholder names used by the template
+    pub fn variables(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+        for text in self.system.iter().chain(std::iter::once(&self.prompt)) {
+            let mut rest = text.as_str();
+            while let Some((name, after)) = next_placeholder(rest) {
+                names.insert(name.to_string());
+                rest = after;
+            }
+        }
+        names
+    }
+
+    /// Render into a Messages API request body
+    pub fn render(&self, request: TemplateRequest, default_model: &str) -> Result<serde_json::Value, String> {
+        let mut values: BTreeMap<String, String> = self.defaults.clone();
+        for (name, value) in request.variables {
+            let value = match value {
+                serde_json::Value::String(s) => s,
+                other => other.to_string(),
+            };
+            values.insert(name, value);
+        }
+
+        let missing: Vec<String> = self.variables().into_iter().filter(|v| !values.contains_key(v)).collect();
+        if !missing.is_empty() {
+            return Err(format!(
+                "Template '{}' is missing variables: {}",
+                self.name,
+                missing.join(", ")
+            ));
+        }
+
+        let model = request
+            .model
+            .or_else(|| self.model.clone())
+            .unwrap_or_else(|| default_model.to_string());
+        let mut messages = vec![serde_json::json!({
+            "role": "user",
+            "content": substitute(&self.prompt, &values),
+        })];
+        messages.extend(request.messages);
+
+        let mut body = serde_json::json!({
+            "model": model,
+            "max_tokens": request.max_tokens.unwrap_or(self.max_tokens),
+            "messages": messages,
+        });
+        if let Some(system) = &self.system {
+            body["system"] = substitute(system, &values).into();
+        }
+        if let Some(stream) = request.stream {
+            body["stream"] = stream.into();
+        }
+        Ok(body)
+    }
+}
+
+/// `(name, rest)` of the first `{{name}}` in `text`
+fn next_placeholder(text: &str) -> Option<(&str, &str)> {
+    let start = text.find("{{")? + 2;
+    let end = start + text[start..].find("}}")?;
+    Some((text[start..end].trim(), &text[end + 2..]))
+}
+
+fn substitute(text: &str, values: &BTreeMap<String, String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some((name, after)) = next_placeholder(rest) {
+        let start = rest.find("{{").expect("placeholder found");
+        out.push_str(&rest[..start]);
+        out.push_str(values.get(name).map_or("", String::as_str));
+        rest = after;
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render() {
+        let template: PromptTemplate = toml::from_str(
+            r#"
+name = "notes"
+system = "You write release notes for {{ product }}."
+prompt = "Summarize:\n{{commits}}"
+defaults = { product = "ccm" }
+"#,
+        )
+        .unwrap();
+
+        let request: TemplateRequest = serde_json::from_value(json!({ "variables": { "commits": "fix a\nfix b" } })).unwrap();
+        let body = template.render(request, "default").unwrap();
+        assert_eq!(body["model"], "default");
+        assert_eq!(body["max_tokens"], 4096);
+        assert_eq!(body["system"], "You write release notes for ccm.");
+        assert_eq!(body["messages"][0]["content"], "Summarize:\nfix a\nfix b");
+
+        let error = template.render(TemplateRequest::default(), "default").unwrap_err();
+        assert!(error.contains("commits"));
+    }
+}
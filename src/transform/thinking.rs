@@ -0,0 +1,147 @@
+use crate::models::{AnthropicRequest, ContentBlock, MessageContent};
+use super::losses::{self, LossKind};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// What to do with thinking blocks in the conversation history sent to a provider
+///
+/// Anthropic signs thinking blocks and requires them to be echoed back verbatim,
+/// but other backends can't verify those signatures (and produce unsigned ones).
+///
+/// Example:
+/// ```toml
+/// [[providers]]
+/// name = "zai"
+/// thinking_history = "drop"
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThinkingHistory {
+    /// Forward signed thinking and redacted_thinking blocks as is; unsigned
+    /// thinking (produced by another backend) can't be verified and is dropped
+    Keep,
+    /// Keep the readable thinking text but remove signatures and the opaque
+    /// redacted_thinking blocks
+    Redact,
+    /// Remove all thinking from the history
+    Drop,
+}
+
+impl ThinkingHistory {
+    /// Default policy: Anthropic's own API verifies signatures, others can't
+    pub fn default_for(provider_type: &str) -> Self {
+        match provider_type {
+            "anthropic" => ThinkingHistory::Keep,
+            _ => ThinkingHistory::Redact,
+        }
+    }
+
+    /// Rewrite thinking blocks in the request history
+    pub fn apply(self, request: &mut AnthropicRequest) {
+        let mut removed = 0;
+        let mut redacted = 0;
+        for message in &mut request.messages {
+            let MessageContent::Blocks(blocks) = &mut message.content else {
+                continue;
+            };
+            let before = blocks.len();
+            blocks.retain_mut(|block| match block {
+                ContentBlock::Thinking { signature, .. } => match self {
+                    ThinkingHistory::Keep => !signature.is_empty(),
+                    ThinkingHistory::Redact => {
+                        if !signature.is_empty() {
+                            signature.clear();
+                            redacted += 1;
+                        }
+                        true
+                    }
+                    ThinkingHistory::Drop => false,
+                },
+                ContentBlock::RedactedThinking { .. } => self == ThinkingHistory::Keep,
+                _ => true,
+            });
+            removed += before - blocks.len();
+        }
+        if removed > 0 {
+            debug!("🧠 Removed {} thinking blocks from history ({:?})", removed, self);
+            losses::record(LossKind::ThinkingDropped, format!("{} thinking blocks removed from history", removed));
+        }
+        if redacted > 0 {
+            losses::record(LossKind::ThinkingRedacted, format!("{} thinking signatures removed", redacted));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Message;
+
+    fn request() -> AnthropicRequest {
+        let blocks = vec![
+            ContentBlock::Thinking {
+                thinking: "signed".to_string(),
+                signature: "sig".to_string(),
+            },
+            ContentBlock::Thinking {
+                thinking: "unsigned".to_string(),
+                signature: String::new(),
+            },
+            ContentBlock::RedactedThinking {
+                data: "opaque".to_string(),
+            },
+            ContentBlock::Text {
+                text: "answer".to_string(),
+            },
+        ];
+        AnthropicRequest {
+            model: "test".to_string(),
+            messages: vec![Message {
+                role: "assistant".to_string(),
+                content: MessageContent::Blocks(blocks),
+            }],
+            max_tokens: 1024,
+            thinking: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
+            stop_sequences: None,
+            stream: None,
+            metadata: None,
+            system: None,
+            tools: None,
+            betas: Vec::new(),
+            tool_choice: None,
+            anthropic_version: None,
+            idempotency_key: None,
+            verbosity: None,
+        }
+    }
+
+    fn block_types(request: &AnthropicRequest) -> Vec<String> {
+        let MessageContent::Blocks(blocks) = &request.messages[0].content else {
+            panic!("expected blocks");
+        };
+        blocks
+            .iter()
+            .map(|b| serde_json::to_value(b).unwrap()["type"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_policies() {
+        let mut keep = request();
+        ThinkingHistory::Keep.apply(&mut keep);
+        assert_eq!(block_types(&keep), vec!["thinking", "redacted_thinking", "text"]);
+
+        let mut redact = request();
+        ThinkingHistory::Redact.apply(&mut redact);
+        assert_eq!(block_types(&redact), vec!["thinking", "thinking", "text"]);
+        let json = serde_json::to_value(&redact.messages[0].content).unwrap();
+        assert!(json[0].get("signature").is_none());
+
+        let mut drop = request();
+        ThinkingHistory::Drop.apply(&mut drop);
+        assert_eq!(block_types(&drop), vec!["text"]);
+    }
+}
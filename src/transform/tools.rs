@@ -0,0 +1,292 @@
+use crate::models::{AnthropicRequest, ContentBlock, MessageContent, Tool, ToolChoice};
+use crate::providers::ProviderResponse;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::debug;
+
+use super::losses::{self, LossKind};
+
+/// Per-provider (or per-mapping) tool policy
+///
+/// Example:
+/// ```toml
+/// [providers.tool_policy]
+/// deny = ["web_search*", "NotebookEdit"]
+/// max_tools = 16
+///
+/// [providers.tool_policy.rename]
+/// Bash = "run_shell"
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ToolPolicy {
+    /// Only keep tools matching one of these patterns (empty = keep all)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+    /// Drop tools matching one of these patterns
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+    /// Rename tools before sending upstream (original name -> upstream name)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub rename: HashMap<String, String>,
+    /// Maximum number of tools to send; least-recently-used tools are dropped first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tools: Option<usize>,
+}
+
+/// Reverse rename table (upstream name -> original name) produced by [`ToolPolicy::apply`]
+#[derive(Debug, Clone, Default)]
+pub struct ToolRenames(HashMap<String, String>);
+
+impl ToolRenames {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Restore original tool names in a non-streaming response
+    pub fn restore_response(&self, response: &mut ProviderResponse) {
+        if self.is_empty() {
+            return;
+        }
+        for block in &mut response.content {
+            if let ContentBlock::ToolUse { name, .. } = block {
+                if let Some(original) = self.0.get(name) {
+                    *name = original.clone();
+                }
+            }
+        }
+    }
+
+    /// Restore original tool names in a single SSE line (content_block_start events)
+    pub fn restore_sse_line(&self, line: &str) -> String {
+        if self.is_empty() || !line.contains("\"tool_use\"") {
+            return line.to_string();
+        }
+        let mut line = line.to_string();
+        for (upstream, original) in &self.0 {
+            line = line.replace(
+                &format!("\"name\":\"{}\"", upstream),
+                &format!("\"name\":\"{}\"", original),
+            );
+        }
+        line
+    }
+}
+
+/// Match a tool name/type against a pattern (exact, or prefix with trailing `*`)
+fn matches_pattern(pattern: &str, value: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => value.starts_with(prefix),
+        None => pattern == value,
+    }
+}
+
+fn tool_matches(patterns: &[String], tool: &Tool) -> bool {
+    patterns.iter().any(|p| {
+        tool.name.as_deref().is_some_and(|n| matches_pattern(p, n))
+            || tool.r#type.as_deref().is_some_and(|t| matches_pattern(p, t))
+    })
+}
+
+impl ToolPolicy {
+    /// Apply the policy to a request in place
+    /// Returns the reverse rename table needed to restore names in the response
+    pub fn apply(&self, request: &mut AnthropicRequest) -> ToolRenames {
+        let Some(tools) = request.tools.take() else {
+            return ToolRenames::default();
+        };
+        let original_count = tools.len();
+
+        let mut kept: Vec<Tool> = tools
+            .into_iter()
+            .filter(|tool| self.allow.is_empty() || tool_matches(&self.allow, tool))
+            .filter(|tool| !tool_matches(&self.deny, tool))
+            .collect();
+
+        if let Some(max_tools) = self.max_tools {
+            if kept.len() > max_tools {
+                let recency = tool_recency(request);
+                // Rank: recently used tools first (most recent wins), then unused in original order
+                let mut ranked: Vec<usize> = (0..kept.len()).collect();
+                ranked.sort_by_key(|&idx| {
+                    kept[idx]
+                        .name
+                        .as_ref()
+                        .and_then(|n| recency.get(n))
+                        .copied()
+                        .unwrap_or(usize::MAX)
+                });
+                let mut keep_idx: Vec<usize> = ranked.into_iter().take(max_tools).collect();
+                keep_idx.sort_unstable();
+                kept = kept
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(idx, _)| keep_idx.contains(idx))
+                    .map(|(_, tool)| tool)
+                    .collect();
+            }
+        }
+
+        if kept.len() != original_count {
+            debug!("🧰 Tool policy kept {}/{} tools", kept.len(), original_count);
+            losses::record(LossKind::ToolsFiltered, format!("tool policy kept {}/{} tools", kept.len(), original_count));
+        }
+
+        let mut renames = HashMap::new();
+        if !self.rename.is_empty() {
+            for tool in &mut kept {
+                if let Some(new_name) = tool.name.as_ref().and_then(|n| self.rename.get(n)) {
+                    renames.insert(new_name.clone(), tool.name.replace(new_name.clone()).unwrap_or_default());
+                }
+            }
+            // Keep tool_use blocks in the history consistent with the renamed definitions
+            for msg in &mut request.messages {
+                if let MessageContent::Blocks(blocks) = &mut msg.content {
+                    for block in blocks {
+                        if let ContentBlock::ToolUse { name, .. } = block {
+                            if let Some(new_name) = self.rename.get(name) {
+                                *name = new_name.clone();
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // A forced tool follows its rename, or is no longer forced once filtered out
+        if let Some(ToolChoice::Tool { name, .. }) = &mut request.tool_choice {
+            if let Some(new_name) = self.rename.get(name) {
+                *name = new_name.clone();
+            }
+            if !kept.iter().any(|tool| tool.name.as_ref() == Some(name)) {
+                losses::record(LossKind::FieldDropped, format!("tool_choice {} (tool filtered out)", name));
+                request.tool_choice = None;
+            }
+        }
+
+        request.tools = if kept.is_empty() { None } else { Some(kept) };
+        ToolRenames(renames)
+    }
+}
+
+/// Map tool name -> recency rank (0 = used most recently) from tool_use blocks in history
+fn tool_recency(request: &AnthropicRequest) -> HashMap<String, usize> {
+    let mut recency = HashMap::new();
+    for msg in request.messages.iter().rev() {
+        if let MessageContent::Blocks(blocks) = &msg.content {
+            for block in blocks.iter().rev() {
+                if let ContentBlock::ToolUse { name, .. } = block {
+                    let rank = recency.len();
+                    recency.entry(name.clone()).or_insert(rank);
+                }
+            }
+        }
+    }
+    recency
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Message;
+
+    fn tool(name: &str, r#type: Option<&str>) -> Tool {
+        Tool {
+            r#type: r#type.map(|t| t.to_string()),
+            name: Some(name.to_string()),
+            description: None,
+            input_schema: None,
+            strict: None,
+        }
+    }
+
+    fn request(tools: Vec<Tool>, messages: Vec<Message>) -> AnthropicRequest {
+        AnthropicRequest {
+            model: "test".to_string(),
+            messages,
+            max_tokens: 1024,
+            thinking: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
+            stop_sequences: None,
+            stream: None,
+            metadata: None,
+            system: None,
+            tools: Some(tools),
+            betas: Vec::new(),
+            tool_choice: None,
+            anthropic_version: None,
+            idempotency_key: None,
+            verbosity: None,
+        }
+    }
+
+    fn tool_names(request: &AnthropicRequest) -> Vec<String> {
+        request
+            .tools
+            .as_ref()
+            .map(|t| t.iter().filter_map(|t| t.name.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_deny_by_type_prefix() {
+        let policy = ToolPolicy {
+            deny: vec!["web_search*".to_string()],
+            ..Default::default()
+        };
+        let mut req = request(
+            vec![tool("Bash", None), tool("web_search", Some("web_search_20250305"))],
+            vec![],
+        );
+        policy.apply(&mut req);
+        assert_eq!(tool_names(&req), vec!["Bash"]);
+    }
+
+    #[test]
+    fn test_allow_list_removes_all() {
+        let policy = ToolPolicy {
+            allow: vec!["Read".to_string()],
+            ..Default::default()
+        };
+        let mut req = request(vec![tool("Bash", None)], vec![]);
+        policy.apply(&mut req);
+        assert!(req.tools.is_none());
+    }
+
+    #[test]
+    fn test_max_tools_keeps_recently_used() {
+        let policy = ToolPolicy {
+            max_tools: Some(2),
+            ..Default::default()
+        };
+        let history = vec![Message {
+            role: "assistant".to_string(),
+            content: MessageContent::Blocks(vec![ContentBlock::ToolUse {
+                id: "toolu_1".to_string(),
+                name: "Grep".to_string(),
+                input: serde_json::json!({}),
+            }]),
+        }];
+        let mut req = request(
+            vec![tool("Bash", None), tool("Read", None), tool("Grep", None)],
+            history,
+        );
+        policy.apply(&mut req);
+        assert_eq!(tool_names(&req), vec!["Bash", "Grep"]);
+    }
+
+    #[test]
+    fn test_rename_round_trip() {
+        let policy = ToolPolicy {
+            rename: HashMap::from([("Bash".to_string(), "run_shell".to_string())]),
+            ..Default::default()
+        };
+        let mut req = request(vec![tool("Bash", None)], vec![]);
+        let renames = policy.apply(&mut req);
+        assert_eq!(tool_names(&req), vec!["run_shell"]);
+
+        let line = r#"data: {"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_1","name":"run_shell","input":{}}}"#;
+        assert!(renames.restore_sse_line(line).contains("\"name\":\"Bash\""));
+    }
+}
@@ -0,0 +1,161 @@
+use crate::models::{AnthropicRequest, ContentBlock, MessageContent, ToolResultBlock, ToolResultContent};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::losses::{self, LossKind};
+
+/// Truncation policy for oversized tool_result blocks
+///
+/// Example:
+/// ```toml
+/// [tool_result_truncation]
+/// enabled = true
+/// max_bytes = 50000
+/// head_bytes = 20000
+/// tail_bytes = 10000
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TruncationConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Tool results larger than this are truncated
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: usize,
+    /// Bytes kept from the start of the result
+    #[serde(default = "default_head_bytes")]
+    pub head_bytes: usize,
+    /// Bytes kept from the end of the result
+    #[serde(default = "default_tail_bytes")]
+    pub tail_bytes: usize,
+}
+
+impl Default for TruncationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_bytes: default_max_bytes(),
+            head_bytes: default_head_bytes(),
+            tail_bytes: default_tail_bytes(),
+        }
+    }
+}
+
+fn default_max_bytes() -> usize {
+    50_000
+}
+
+fn default_head_bytes() -> usize {
+    20_000
+}
+
+fn default_tail_bytes() -> usize {
+    10_000
+}
+
+impl TruncationConfig {
+    /// Truncate oversized tool results in place
+    /// Returns the number of bytes removed
+    pub fn apply(&self, request: &mut AnthropicRequest) -> usize {
+        if !self.enabled {
+            return 0;
+        }
+
+        let mut removed = 0;
+        for msg in &mut request.messages {
+            let MessageContent::Blocks(blocks) = &mut msg.content else {
+                continue;
+            };
+            for block in blocks {
+                let ContentBlock::ToolResult { content, .. } = block else {
+                    continue;
+                };
+                match content {
+                    ToolResultContent::Text(text) => removed += self.truncate(text),
+                    ToolResultContent::Blocks(blocks) => {
+                        for block in blocks {
+                            if let ToolResultBlock::Text { text } = block {
+                                removed += self.truncate(text);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if removed > 0 {
+            info!("✂️  Truncated {} bytes of tool results", removed);
+            losses::record(LossKind::ToolResultTruncated, format!("{} bytes of tool results truncated", removed));
+        }
+        removed
+    }
+
+    /// Keep head and tail of `text`, replacing the middle with a marker
+    fn truncate(&self, text: &mut String) -> usize {
+        if text.len() <= self.max_bytes || self.head_bytes + self.tail_bytes >= text.len() {
+            return 0;
+        }
+
+        let head_end = floor_char_boundary(text, self.head_bytes);
+        let tail_start = ceil_char_boundary(text, text.len() - self.tail_bytes);
+        let removed = tail_start - head_end;
+
+        let marker = format!(
+            "\n\n[... {} bytes truncated by claude-code-mux ...]\n\n",
+            removed
+        );
+        text.replace_range(head_end..tail_start, &marker);
+        removed
+    }
+}
+
+fn floor_char_boundary(text: &str, mut idx: usize) -> usize {
+    while !text.is_char_boundary(idx) {
+        idx -= 1;
+    }
+    idx
+}
+
+fn ceil_char_boundary(text: &str, mut idx: usize) -> usize {
+    while !text.is_char_boundary(idx) {
+        idx += 1;
+    }
+    idx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(max_bytes: usize, head_bytes: usize, tail_bytes: usize) -> TruncationConfig {
+        TruncationConfig {
+            enabled: true,
+            max_bytes,
+            head_bytes,
+            tail_bytes,
+        }
+    }
+
+    #[test]
+    fn test_small_results_untouched() {
+        let mut text = "short".to_string();
+        assert_eq!(config(100, 10, 10).truncate(&mut text), 0);
+        assert_eq!(text, "short");
+    }
+
+    #[test]
+    fn test_keeps_head_and_tail() {
+        let mut text = format!("{}{}{}", "a".repeat(10), "b".repeat(100), "c".repeat(10));
+        let removed = config(50, 10, 10).truncate(&mut text);
+        assert_eq!(removed, 100);
+        assert!(text.starts_with("aaaaaaaaaa\n\n[... 100 bytes truncated"));
+        assert!(text.ends_with("cccccccccc"));
+    }
+
+    #[test]
+    fn test_respects_char_boundaries() {
+        let mut text = "é".repeat(100);
+        config(50, 11, 11).truncate(&mut text);
+        assert!(text.starts_with(&"é".repeat(5)));
+        assert!(text.ends_with(&"é".repeat(5)));
+    }
+}
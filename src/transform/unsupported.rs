@@ -0,0 +1,271 @@
+//! Content blocks a provider can't take
+//!
+//! Documents only exist in Anthropic's API, and text-only models can't take
+//! images. Dropping such blocks silently leaves the model answering about an
+//! attachment it never saw, so by default they're replaced with a short note.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+use super::losses::{self, LossKind};
+use crate::models::{
+    AnthropicRequest, ContentBlock, MessageContent, ToolResultBlock, ToolResultContent,
+};
+
+/// What to do with a block the provider can't take
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnsupportedAction {
+    /// Leave it out
+    Skip,
+    /// Replace it with the placeholder text
+    #[default]
+    Placeholder,
+    /// Refuse the request (the next mapping is tried)
+    Error,
+}
+
+/// Handling of content blocks the provider can't take
+///
+/// ```toml
+/// [providers.unsupported_content]
+/// action = "placeholder"    # skip, placeholder (default) or error
+/// placeholder = "[attachment omitted: {name}{details}]"
+/// blocks = ["image"]        # also unsupported by this model (text-only)
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UnsupportedContent {
+    #[serde(default)]
+    pub action: UnsupportedAction,
+    /// `{name}` is the title or block type, `{details}` e.g. `, 14 pages`
+    #[serde(default = "default_placeholder")]
+    pub placeholder: String,
+    /// Block types to treat as unsupported besides those the API lacks
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocks: Vec<String>,
+}
+
+impl Default for UnsupportedContent {
+    fn default() -> Self {
+        Self {
+            action: UnsupportedAction::default(),
+            placeholder: default_placeholder(),
+            blocks: Vec::new(),
+        }
+    }
+}
+
+fn default_placeholder() -> String {
+    "[attachment omitted: {name}{details}]".to_string()
+}
+
+impl UnsupportedContent {
+    /// Skip, replace or refuse the blocks the provider can't take
+    ///
+    /// `documents` is whether the provider's API takes document blocks.
+    pub fn apply(&self, request: &mut AnthropicRequest, documents: bool) -> Result<(), String> {
+        let images = !self.blocks.iter().any(|b| b == "image");
+        let documents = documents && !self.blocks.iter().any(|b| b == "document");
+        if images && documents {
+            return Ok(());
+        }
+
+        let mut omitted = Vec::new();
+        for message in &mut request.messages {
+            let MessageContent::Blocks(blocks) = &mut message.content else {
+                continue;
+            };
+            let mut kept = Vec::with_capacity(blocks.len());
+            for mut block in std::mem::take(blocks) {
+                let description = match &block {
+                    ContentBlock::Document { source, title, .. } if !documents => {
+                        Some(describe_document(source, title.as_deref()))
+                    }
+                    ContentBlock::Image { source } if !images => Some((
+                        "image".to_string(),
+                        source.media_type.clone().unwrap_or_default(),
+                    )),
+                    _ => None,
+                };
+                if let (
+                    false,
+                    ContentBlock::ToolResult {
+                        content: ToolResultContent::Blocks(results),
+                        ..
+                    },
+                ) = (images, &mut block)
+                {
+                    results.retain_mut(|result| {
+                        let ToolResultBlock::Image { source } = result else {
+                            return true;
+                        };
+                        let text =
+                            self.render("image", source.media_type.as_deref().unwrap_or_default());
+                        omitted.push(text.clone());
+                        if self.action != UnsupportedAction::Placeholder {
+                            return false;
+                        }
+                        *result = ToolResultBlock::Text { text };
+                        true
+                    });
+                }
+                match description {
+                    Some((name, details)) => {
+                        let text = self.render(&name, &details);
+                        omitted.push(text.clone());
+                        if self.action == UnsupportedAction::Placeholder {
+                            kept.push(ContentBlock::Text { text });
+                        }
+                    }
+                    None => kept.push(block),
+                }
+            }
+            *blocks = kept;
+        }
+
+        if omitted.is_empty() {
+            return Ok(());
+        }
+        match self.action {
+            UnsupportedAction::Error => {
+                Err(format!("the provider can't take {}", omitted.join(", ")))
+            }
+            action => {
+                let verb = match action {
+                    UnsupportedAction::Skip => "skipped",
+                    _ => "replaced with a placeholder",
+                };
+                losses::record(
+                    LossKind::ContentDropped,
+                    format!("{} {}", omitted.join(", "), verb),
+                );
+                Ok(())
+            }
+        }
+    }
+
+    fn render(&self, name: &str, details: &str) -> String {
+        let details = if details.is_empty() {
+            String::new()
+        } else {
+            format!(", {}", details)
+        };
+        self.placeholder
+            .replace("{name}", name)
+            .replace("{details}", &details)
+    }
+}
+
+/// Name and details of a document block
+fn describe_document(source: &serde_json::Value, title: Option<&str>) -> (String, String) {
+    let name = title
+        .map(str::to_string)
+        .or_else(|| {
+            source["url"]
+                .as_str()
+                .and_then(|url| url.rsplit('/').next())
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| "document".to_string());
+    let pages = source["data"]
+        .as_str()
+        .filter(|_| source["media_type"] == "application/pdf")
+        .and_then(|data| STANDARD.decode(data).ok())
+        .map(|pdf| pdf_pages(&pdf))
+        .filter(|pages| *pages > 0);
+    let details = match (pages, source["media_type"].as_str()) {
+        (Some(1), _) => "1 page".to_string(),
+        (Some(pages), _) => format!("{} pages", pages),
+        (None, Some(media_type)) => media_type.to_string(),
+        (None, None) => String::new(),
+    };
+    (name, details)
+}
+
+/// Page objects in a PDF (`/Type /Page`, not `/Pages`)
+///
+/// Pages inside compressed object streams aren't seen; 0 means unknown.
+fn pdf_pages(pdf: &[u8]) -> usize {
+    let mut pages = 0;
+    for marker in [&b"/Type /Page"[..], b"/Type/Page"] {
+        pages += pdf
+            .windows(marker.len() + 1)
+            .filter(|w| w.starts_with(marker) && !w[marker.len()].is_ascii_alphanumeric())
+            .count();
+    }
+    pages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(content: serde_json::Value) -> AnthropicRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "m",
+            "max_tokens": 16,
+            "messages": [{ "role": "user", "content": content }],
+        }))
+        .unwrap()
+    }
+
+    fn texts(request: &AnthropicRequest) -> Vec<String> {
+        let MessageContent::Blocks(blocks) = &request.messages[0].content else {
+            panic!()
+        };
+        blocks
+            .iter()
+            .map(|block| match block {
+                ContentBlock::Text { text } => text.clone(),
+                other => format!("{:?}", other)
+                    .split_whitespace()
+                    .next()
+                    .unwrap()
+                    .to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_unsupported_blocks() {
+        let pdf = STANDARD.encode(b"%PDF-1.4 /Type /Pages /Count 2 /Type /Page x /Type/Page\n");
+        let content = serde_json::json!([
+            { "type": "document", "title": "report.pdf", "source": { "type": "base64", "media_type": "application/pdf", "data": pdf } },
+            { "type": "image", "source": { "type": "base64", "media_type": "image/png", "data": "AAAA" } },
+            { "type": "text", "text": "Summarize" },
+        ]);
+
+        // Placeholder for documents; images pass
+        let mut placeholder = request(content.clone());
+        UnsupportedContent::default()
+            .apply(&mut placeholder, false)
+            .unwrap();
+        assert_eq!(
+            texts(&placeholder),
+            [
+                "[attachment omitted: report.pdf, 2 pages]",
+                "Image",
+                "Summarize"
+            ]
+        );
+
+        // Native documents: untouched
+        let mut native = request(content.clone());
+        UnsupportedContent::default()
+            .apply(&mut native, true)
+            .unwrap();
+        assert_eq!(texts(&native), ["Document", "Image", "Summarize"]);
+
+        // Text-only model, skipping
+        let settings: UnsupportedContent =
+            toml::from_str("action = \"skip\"\nblocks = [\"image\"]").unwrap();
+        let mut skipped = request(content.clone());
+        settings.apply(&mut skipped, false).unwrap();
+        assert_eq!(texts(&skipped), ["Summarize"]);
+
+        let settings: UnsupportedContent = toml::from_str("action = \"error\"").unwrap();
+        let err = settings.apply(&mut request(content), false).unwrap_err();
+        assert!(err.contains("report.pdf"), "{}", err);
+    }
+}
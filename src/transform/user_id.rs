@@ -0,0 +1,96 @@
+//! Anthropic `metadata.user_id` from the mux client
+//!
+//! Anthropic attributes usage by `metadata.user_id`, but behind the mux every
+//! request carries whatever the client put there (Claude Code sends its own
+//! account and session ids). A provider can send the mux tenant instead, so
+//! usage in the Anthropic console lines up with mux clients.
+
+use serde::{Deserialize, Serialize};
+
+use crate::auth::api_keys::hash_key;
+use crate::models::AnthropicRequest;
+
+/// What identifies the client in `metadata.user_id`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserIdSource {
+    /// Client name of the key the request came with
+    #[default]
+    Tenant,
+    /// The key itself, always hashed
+    Key,
+}
+
+/// `metadata.user_id` tagging for a provider
+///
+/// ```toml
+/// [providers.user_id]
+/// source = "tenant"    # tenant (default) or key
+/// hash = true          # send a SHA-256 of the tenant name
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct UserIdTagging {
+    #[serde(default)]
+    pub source: UserIdSource,
+    #[serde(default)]
+    pub hash: bool,
+}
+
+impl UserIdTagging {
+    /// Replace the client's `metadata.user_id`
+    ///
+    /// Requests without a known tenant or key keep the client's value.
+    pub fn apply(&self, request: &mut AnthropicRequest, tenant: Option<&str>, key: Option<&str>) {
+        let user_id = match self.source {
+            UserIdSource::Tenant if self.hash => tenant.map(hash_key),
+            UserIdSource::Tenant => tenant.map(str::to_string),
+            UserIdSource::Key => key.map(hash_key),
+        };
+        let Some(user_id) = user_id else {
+            return;
+        };
+        request
+            .metadata
+            .get_or_insert_with(Default::default)
+            .insert("user_id".to_string(), user_id.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_id_tagging() {
+        let request = || -> AnthropicRequest {
+            serde_json::from_value(serde_json::json!({
+                "model": "m",
+                "max_tokens": 16,
+                "metadata": { "user_id": "user_abc_account_1_session_2" },
+                "messages": [{ "role": "user", "content": "hi" }],
+            }))
+            .unwrap()
+        };
+        let user_id = |r: &AnthropicRequest| r.metadata.as_ref().unwrap()["user_id"].clone();
+
+        let mut tenant = request();
+        UserIdTagging::default().apply(&mut tenant, Some("team-a"), Some("sk-1"));
+        assert_eq!(user_id(&tenant), "team-a");
+
+        let hashed: UserIdTagging = toml::from_str("hash = true").unwrap();
+        let mut request_hashed = request();
+        hashed.apply(&mut request_hashed, Some("team-a"), None);
+        assert_eq!(user_id(&request_hashed), hash_key("team-a"));
+
+        // The key is never sent as is
+        let key: UserIdTagging = toml::from_str("source = \"key\"").unwrap();
+        let mut keyed = request();
+        key.apply(&mut keyed, Some("team-a"), Some("sk-1"));
+        assert_eq!(user_id(&keyed), hash_key("sk-1"));
+
+        // Unauthenticated: the client's value stays
+        let mut anonymous = request();
+        UserIdTagging::default().apply(&mut anonymous, None, None);
+        assert_eq!(user_id(&anonymous), "user_abc_account_1_session_2");
+    }
+}
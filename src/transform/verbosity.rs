@@ -0,0 +1,51 @@
+use crate::models::{AnthropicRequest, Verbosity};
+
+use super::guardrails::append_system;
+
+/// Apply an alias's output defaults and express verbosity for the provider
+///
+/// `default` fills in a verbosity the client didn't ask for, and `max_tokens`
+/// caps the client's value. Providers without a native verbosity parameter
+/// get an instruction in the system prompt instead.
+pub fn apply(request: &mut AnthropicRequest, default: Option<Verbosity>, max_tokens: Option<u32>, native: bool) {
+    if let Some(cap) = max_tokens {
+        request.max_tokens = request.max_tokens.min(cap);
+    }
+    request.verbosity = request.verbosity.or(default);
+    if native {
+        return;
+    }
+    let instruction = match request.verbosity {
+        Some(Verbosity::Low) => "Keep answers short: no preamble, no recap, no repetition.",
+        Some(Verbosity::High) => "Give thorough, detailed answers.",
+        Some(Verbosity::Medium) | None => return,
+    };
+    append_system(request, instruction.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::SystemPrompt;
+
+    #[test]
+    fn test_apply() {
+        let mut request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "m", "max_tokens": 32000, "system": "You are helpful.",
+            "messages": [{ "role": "user", "content": "hi" }],
+        }))
+        .unwrap();
+
+        let mut native = request.clone();
+        apply(&mut native, Some(Verbosity::Low), Some(4096), true);
+        assert_eq!(native.max_tokens, 4096);
+        assert_eq!(native.verbosity, Some(Verbosity::Low));
+        assert!(matches!(&native.system, Some(SystemPrompt::Text(s)) if s == "You are helpful."));
+
+        request.verbosity = Some(Verbosity::High);
+        apply(&mut request, Some(Verbosity::Low), None, false);
+        assert_eq!(request.max_tokens, 32000);
+        assert!(matches!(&request.system, Some(SystemPrompt::Text(s)) if s.ends_with("detailed answers.")));
+        assert!(serde_json::to_value(&request).unwrap().get("verbosity").is_none());
+    }
+}